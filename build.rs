@@ -1,6 +1,43 @@
+// messages that carry a credential (session id, MAC, second factor code,
+// state bundle, ticket) or a proof. their Debug is written in src/proto.rs
+// and redacts those fields, so logging a request doesn't log the secret
+#[cfg(feature = "proto")]
+const REDACTED: &[&str] = &[
+    ".zkp_auth.AuthenticationAnswerRequest",
+    ".zkp_auth.AuthenticationAnswerResponse",
+    ".zkp_auth.ValidateSessionRequest",
+    ".zkp_auth.RefreshSessionRequest",
+    ".zkp_auth.RefreshSessionResponse",
+    ".zkp_auth.LogoutRequest",
+    ".zkp_auth.GetSessionInfoRequest",
+    ".zkp_auth.ExportStateResponse",
+    ".zkp_auth.ImportStateRequest",
+    ".zkp_auth.DecoyHit",
+    ".zkp_auth.RecoverAccountRequest",
+    ".zkp_auth.ListDevicesRequest",
+    ".zkp_auth.RevokeDeviceRequest",
+    ".zkp_auth.MigrateGroupRequest",
+    ".zkp_auth.IssueTicketsRequest",
+    ".zkp_auth.EvaluatedTicket",
+    ".zkp_auth.RedeemTicketRequest",
+    ".zkp_auth.v2.AuthenticationAnswerRequest",
+    ".zkp_auth.v2.AuthenticationAnswerResponse",
+    ".zkp_auth.v2.Session",
+    ".zkp_auth.v2.ValidateSessionRequest",
+    ".zkp_auth.v2.RefreshSessionRequest",
+    ".zkp_auth.v2.LogoutRequest",
+    ".zkp_auth.v2.GetSessionInfoRequest",
+];
+
 fn main() {
+    // generated into OUT_DIR and only needed for the proto feature, so
+    // builds without it don't need protoc (or the code generator)
+    #[cfg(feature = "proto")]
     tonic_prost_build::configure()
-        .out_dir("src/") // specify the generated code's location
-        .compile_protos(&["proto/zkp_auth.proto"], &["proto/"])
+        .skip_debug(REDACTED)
+        .compile_protos(
+            &["proto/zkp_auth.proto", "proto/zkp_auth_v2.proto"],
+            &["proto/"],
+        )
         .unwrap();
 }
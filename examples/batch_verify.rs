@@ -0,0 +1,58 @@
+// a batch of statements and their proofs verified at once; the index of the
+// first proof that doesn't verify comes back
+mod common;
+
+use common::check;
+use zkp_chaum_pedersen::groups;
+use zkp_chaum_pedersen::keys::KeyPair;
+use zkp_chaum_pedersen::statement::Statement;
+
+fn main() {
+    let zkp = groups::default_group();
+    let keys: Vec<KeyPair> = (0..4).map(|_| KeyPair::generate(&zkp)).collect();
+    let statements: Vec<Statement> = keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| {
+            Statement::builder()
+                .group(zkp.clone())
+                .public_key(&key.public)
+                .application(b"batch-verify")
+                .context(format!("ballot {}", i).as_bytes())
+                .build()
+                .expect("a complete statement")
+        })
+        .collect();
+    let proofs: Vec<_> = statements
+        .iter()
+        .zip(&keys)
+        .map(|(statement, key)| statement.prove(key.x.expose()).expect("the right secret"))
+        .collect();
+
+    let batch: Vec<_> = statements.iter().zip(&proofs).collect();
+    check(
+        "every proof of the batch verifies",
+        Statement::verify_batch(&batch) == Ok(()),
+    );
+
+    // the proofs of the last two statements swapped
+    let mut swapped = batch.clone();
+    swapped[2].1 = &proofs[3];
+    swapped[3].1 = &proofs[2];
+    check(
+        "a swapped proof is found at its index",
+        Statement::verify_batch(&swapped) == Err(2),
+    );
+
+    // a statement survives JSON, and its proof still verifies against it
+    let json = serde_json::to_string(&statements[0]).expect("statement to JSON");
+    let parsed: Statement = serde_json::from_str(&json).expect("statement from JSON");
+    check(
+        "a statement read back from JSON verifies its proof",
+        parsed.verify(&proofs[0]),
+    );
+    check(
+        "a statement can't be proven with another secret",
+        statements[0].prove(keys[1].x.expose()).is_err(),
+    );
+}
@@ -0,0 +1,10 @@
+// the examples double as smoke tests of the public API: every check prints
+// what it found, and one that doesn't hold ends the run with exit status 1
+pub fn check(what: &str, holds: bool) {
+    if holds {
+        println!("✅ {}", what);
+    } else {
+        eprintln!("❌ {}", what);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,48 @@
+// Chaum-Pedersen on elliptic curves: an interactive round in Ristretto and,
+// with the bls12-381 feature, a non-interactive proof in G1 of BLS12-381
+mod common;
+
+use common::check;
+use num_bigint::BigUint;
+use zkp_chaum_pedersen::group::ChaumPedersenGroup;
+use zkp_chaum_pedersen::ristretto::RistrettoGroup;
+
+// commit, challenge, respond and check, with the secret the verifier
+// expects or another
+fn round<G: ChaumPedersenGroup>(group: &G, x: &BigUint, claimed: &BigUint) -> bool {
+    let (y1, y2) = group.commit(x);
+    let k = group.random_nonce();
+    let (r1, r2) = group.commit(&k);
+    let c = group.random_challenge();
+    let s = group.respond(&k, &c, claimed);
+    group.check(&r1, &r2, &y1, &y2, &c, &s)
+}
+
+fn main() {
+    let ristretto = RistrettoGroup::default();
+    let x = ristretto.random_nonce();
+    check("Ristretto round verifies", round(&ristretto, &x, &x));
+    check(
+        "Ristretto round with the wrong secret is rejected",
+        !round(&ristretto, &x, &(&x + 1u32)),
+    );
+
+    #[cfg(feature = "bls12-381")]
+    {
+        use zkp_chaum_pedersen::bls::Bls12G1Group;
+
+        let bls = Bls12G1Group::default();
+        let x = bls.random_nonce();
+        check("BLS12-381 round verifies", round(&bls, &x, &x));
+        let (y1, y2) = bls.commit(&x);
+        let proof = bls.prove_non_interactive(&x, b"curve backend");
+        check(
+            "BLS12-381 non-interactive proof verifies",
+            bls.verify_non_interactive(&y1, &y2, &proof, b"curve backend"),
+        );
+        check(
+            "BLS12-381 proof under another context is rejected",
+            !bls.verify_non_interactive(&y1, &y2, &proof, b"other"),
+        );
+    }
+}
@@ -0,0 +1,42 @@
+// an honest prover against an honest verifier, interactive and
+// non-interactive: every proof made with the right secret verifies
+mod common;
+
+use common::check;
+use zkp_chaum_pedersen::keys::KeyPair;
+use zkp_chaum_pedersen::{groups, ZKP};
+
+fn main() {
+    let zkp = groups::default_group();
+    let key = KeyPair::generate(&zkp);
+    let (y1, y2) = (&key.public.y1, &key.public.y2);
+    println!(
+        "=== {} ({} bits of security) ===",
+        groups::DEFAULT,
+        zkp.security_bits()
+    );
+
+    // interactive: commit, the verifier's challenge, the answer
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let r1 = ZKP::exponentiate(&zkp.g, &k, &zkp.p);
+    let r2 = ZKP::exponentiate(&zkp.h, &k, &zkp.p);
+    let c = ZKP::generate_random_number_below(&zkp.q);
+    let s = zkp.solve(&k, &c, key.x.expose());
+    check(
+        "interactive round verifies",
+        zkp.verify(&r1, &r2, y1, y2, &c, &s),
+    );
+
+    // non-interactive: the challenge is the hash of the commitments and the
+    // context
+    let proof = zkp.prove_non_interactive(key.x.expose(), b"honest run");
+    check(
+        "non-interactive proof verifies",
+        zkp.verify_non_interactive(y1, y2, &proof, b"honest run"),
+    );
+    check(
+        "proof fits the group's size",
+        proof.size_bytes() <= 2 * zkp.scalar_len(),
+    );
+    println!("proof: {:?} ({} bytes)", proof, proof.size_bytes());
+}
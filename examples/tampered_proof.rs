@@ -0,0 +1,69 @@
+// a proof changed in any way after it was made, or checked against the wrong
+// statement, doesn't verify
+mod common;
+
+use common::check;
+use num_bigint::BigUint;
+use zkp_chaum_pedersen::keys::KeyPair;
+use zkp_chaum_pedersen::{groups, Proof};
+
+fn main() {
+    let zkp = groups::default_group();
+    let key = KeyPair::generate(&zkp);
+    let (y1, y2) = (&key.public.y1, &key.public.y2);
+    let proof = zkp.prove_non_interactive(key.x.expose(), b"payment 42");
+    check(
+        "the untouched proof verifies",
+        zkp.verify_non_interactive(y1, y2, &proof, b"payment 42"),
+    );
+
+    let one = BigUint::from(1u32);
+    let tampered = [
+        (
+            "s changed",
+            Proof {
+                s: (&proof.s + &one) % &zkp.q,
+                ..proof.clone()
+            },
+        ),
+        (
+            "c changed",
+            Proof {
+                c: (&proof.c + &one) % &zkp.q,
+                ..proof.clone()
+            },
+        ),
+        (
+            "s set to zero",
+            Proof {
+                s: BigUint::from(0u32),
+                ..proof.clone()
+            },
+        ),
+    ];
+    for (what, proof) in &tampered {
+        check(
+            &format!("proof with {} is rejected", what),
+            !zkp.verify_non_interactive(y1, y2, proof, b"payment 42"),
+        );
+    }
+
+    check(
+        "proof under another context is rejected",
+        !zkp.verify_non_interactive(y1, y2, &proof, b"payment 43"),
+    );
+    check(
+        "proof under another application id is rejected",
+        !zkp.verify_non_interactive_in(b"other-app", y1, y2, &proof, b"payment 42"),
+    );
+    let other = KeyPair::generate(&zkp);
+    check(
+        "proof against another public key is rejected",
+        !zkp.verify_non_interactive(&other.public.y1, &other.public.y2, &proof, b"payment 42"),
+    );
+    let stronger = groups::by_name(groups::RFC5114_2048_256).expect("named group");
+    check(
+        "proof in another group is rejected",
+        !stronger.verify_non_interactive(y1, y2, &proof, b"payment 42"),
+    );
+}
@@ -1,5 +1,12 @@
+// the zero-value bypass (see Known Vulnerabilities in the README): the raw
+// verification equations hold for y1 = y2 = r1 = r2 = s = 0 and any c > 0,
+// so the inputs have to be checked before they get there
+mod common;
+
+use common::check;
 use num_bigint::BigUint;
-use zkp_chaum_pedersen::ZKP;
+use zkp_chaum_pedersen::statement::{Statement, StatementError};
+use zkp_chaum_pedersen::{Proof, ZKP};
 
 fn main() {
     // ZKP setup (using small values for easier testing)
@@ -11,51 +18,49 @@ fn main() {
     };
 
     // Test case: all values are 0 except c (which is generated randomly)
-    let r1 = BigUint::from(0u32);
-    let r2 = BigUint::from(0u32);
-    let y1 = BigUint::from(0u32);
-    let y2 = BigUint::from(0u32);
+    let zero = BigUint::from(0u32);
     let c = BigUint::from(4u32); // non-zero challenge
-    let s = BigUint::from(0u32);
-
     println!("=== Testing with zero values ===");
-    println!(
-        "r1={}, r2={}, y1={}, y2={}, c={}, s={}",
-        r1, r2, y1, y2, c, s
-    );
+    println!("r1=0, r2=0, y1=0, y2=0, c={}, s=0", c);
 
-    // Calculate what the verification should check:
     // cond1: r1 == (g^s * y1^c) mod p
     // cond2: r2 == (h^s * y2^c) mod p
-
-    let g_s = zkp.g.modpow(&s, &zkp.p);
-    let y1_c = y1.modpow(&c, &zkp.p);
-    let h_s = zkp.h.modpow(&s, &zkp.p);
-    let y2_c = y2.modpow(&c, &zkp.p);
-
-    println!("g^s mod p = {}", g_s);
-    println!("y1^c mod p = {}", y1_c);
-    println!("h^s mod p = {}", h_s);
-    println!("y2^c mod p = {}", y2_c);
-
-    let cond1_calc = (g_s.clone() * y1_c.clone()).modpow(&BigUint::from(1u32), &zkp.p);
-    let cond2_calc = (h_s.clone() * y2_c.clone()).modpow(&BigUint::from(1u32), &zkp.p);
-
+    let cond1_calc = ZKP::mod_mul(
+        &zkp.g.modpow(&zero, &zkp.p),
+        &zero.modpow(&c, &zkp.p),
+        &zkp.p,
+    );
+    let cond2_calc = ZKP::mod_mul(
+        &zkp.h.modpow(&zero, &zkp.p),
+        &zero.modpow(&c, &zkp.p),
+        &zkp.p,
+    );
     println!("(g^s * y1^c) mod p = {}", cond1_calc);
     println!("(h^s * y2^c) mod p = {}", cond2_calc);
-
-    let cond1 = r1 == cond1_calc;
-    let cond2 = r2 == cond2_calc;
-
-    println!(
-        "cond1: r1 == (g^s * y1^c) mod p => {} == {} = {}",
-        r1, cond1_calc, cond1
+    check(
+        "the raw equations hold for zeros (the bypass)",
+        cond1_calc == zero && cond2_calc == zero,
     );
-    println!(
-        "cond2: r2 == (h^s * y2^c) mod p => {} == {} = {}",
-        r2, cond2_calc, cond2
+    check(
+        "ZKP::verify takes the zeros as they are",
+        zkp.verify(&zero, &zero, &zero, &zero, &c, &zero),
     );
 
-    let result = zkp.verify(&r1, &r2, &y1, &y2, &c, &s);
-    println!("Final verification result: {}", result);
+    // the checked paths refuse them
+    let statement = Statement::builder()
+        .group(zkp.clone())
+        .public(zero.clone(), zero.clone())
+        .build();
+    check(
+        "a Statement with y1 = 0 is refused",
+        statement.unwrap_err() == StatementError::OutOfRange("y1"),
+    );
+    let proof = Proof {
+        c: c.clone(),
+        s: zero.clone(),
+    };
+    check(
+        "a non-interactive proof for y1 = y2 = 0 doesn't verify",
+        !zkp.verify_non_interactive(&zero, &zero, &proof, b""),
+    );
 }
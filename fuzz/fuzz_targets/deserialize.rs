@@ -0,0 +1,49 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use prost::Message;
+use zkp_chaum_pedersen::kat::KatFile;
+use zkp_chaum_pedersen::offline::{ChallengeFile, ProofFile};
+use zkp_chaum_pedersen::proto::*;
+use zkp_chaum_pedersen::ZKP;
+
+// everything a peer or a file can hand us: the offline challenge and proof
+// files, known-answer files and every request message of the service
+fuzz_target!(|data: &[u8]| {
+    let (g, h, p, q) = ZKP::get_constants();
+    let zkp = ZKP { p, q, g, h };
+
+    if let Ok(challenge) = serde_json::from_slice::<ChallengeFile>(data) {
+        let _ = challenge.context();
+    }
+    if let Ok(proof) = serde_json::from_slice::<ProofFile>(data) {
+        // registered with the proof's own key, so the proof gets verified
+        let challenge = ChallengeFile {
+            user: proof.user.clone(),
+            nonce: proof.nonce.clone(),
+            y1: Some(proof.y1.clone()),
+            y2: Some(proof.y2.clone()),
+            salt: None,
+            kdf: None,
+            purpose: None,
+            max_lifetime_seconds: None,
+            group: None,
+        };
+        let _ = proof.verify(&zkp, b"", &challenge);
+    }
+    if let Ok(file) = serde_json::from_slice::<KatFile>(data) {
+        // the file picks its own group, keep it to sizes a real one has
+        if let Ok(group) = file.zkp()
+            && group.p.bits() <= 2048
+        {
+            let _ = file.check();
+        }
+    }
+
+    let _ = RegisterRequest::decode(data);
+    let _ = AuthenticationChallengeRequest::decode(data);
+    let _ = AuthenticationAnswerRequest::decode(data);
+    let _ = ValidateSessionRequest::decode(data);
+    let _ = RefreshSessionRequest::decode(data);
+    let _ = LogoutRequest::decode(data);
+});
@@ -0,0 +1,28 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use num_bigint::BigUint;
+use zkp_chaum_pedersen::{Proof, ZKP};
+
+// splits the input into length-prefixed r1, r2, y1, y2, c, s and runs both
+// verifiers on them. values of any length must be rejected quickly, never panic
+fuzz_target!(|data: &[u8]| {
+    let mut rest = data;
+    let mut next = || {
+        let (&len, tail) = rest.split_first()?;
+        let len = (len as usize * 2).min(tail.len());
+        let (value, tail) = tail.split_at(len);
+        rest = tail;
+        Some(BigUint::from_bytes_be(value))
+    };
+    let (Some(r1), Some(r2), Some(y1), Some(y2), Some(c), Some(s)) =
+        (next(), next(), next(), next(), next(), next())
+    else {
+        return;
+    };
+
+    let (g, h, p, q) = ZKP::get_constants();
+    let zkp = ZKP { p, q, g, h };
+    let _ = zkp.verify(&r1, &r2, &y1, &y2, &c, &s);
+    let _ = zkp.verify_non_interactive(&y1, &y2, &Proof { c, s }, rest);
+});
@@ -0,0 +1,201 @@
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::str::FromStr;
+use tokio::net::TcpStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::server::TcpIncoming;
+
+// CIDR allow and deny lists, checked on the peer address of each TCP
+// connection as it is accepted. a rejected peer is disconnected before the TLS
+// handshake, so it never gets the server to do any crypto work
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessList {
+    // empty allows every peer that isn't denied
+    pub allow: Vec<Cidr>,
+    // checked first, a denied peer is rejected even if it's also allowed
+    pub deny: Vec<Cidr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    Denied(Cidr),
+    NotAllowed,
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejection::Denied(cidr) => write!(f, "denied by {}", cidr),
+            Rejection::NotAllowed => write!(f, "not in the allow list"),
+        }
+    }
+}
+
+fn bits(addr: IpAddr) -> (u128, u8) {
+    match addr {
+        IpAddr::V4(addr) => (u32::from(addr) as u128, 32),
+        IpAddr::V6(addr) => (u128::from(addr), 128),
+    }
+}
+
+// the top `prefix` bits of a `width` bit address
+fn mask(prefix: u8, width: u8) -> u128 {
+    match prefix {
+        0 => 0,
+        prefix => (u128::MAX >> (128 - width)) & !((1u128 << (width - prefix)) - 1),
+    }
+}
+
+impl Cidr {
+    // IPv4 peers reaching a dual-stack socket show up as ::ffff:a.b.c.d and
+    // match IPv4 networks
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let (network, width) = bits(self.network);
+        let (addr, addr_width) = bits(addr.to_canonical());
+        width == addr_width && addr & mask(self.prefix, width) == network
+    }
+}
+
+// `10.0.0.0/8`, `2001:db8::/32`, or a single address
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (network, prefix) = match value.split_once('/') {
+            Some((network, prefix)) => (network, Some(prefix)),
+            None => (value, None),
+        };
+        let network: IpAddr = network
+            .parse()
+            .map_err(|_| format!("{} is not an IP address", network))?;
+        let (bits, width) = bits(network);
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= width)
+                .ok_or_else(|| format!("{} is not a prefix length up to {}", prefix, width))?,
+            None => width,
+        };
+        // a typo like 10.0.0.1/8 would allow or deny more than it says
+        if bits & !mask(prefix, width) != 0 {
+            return Err(format!(
+                "{} has bits set past the /{} prefix",
+                value, prefix
+            ));
+        }
+        Ok(Cidr { network, prefix })
+    }
+}
+
+impl Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl AccessList {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn check(&self, addr: IpAddr) -> Result<(), Rejection> {
+        if let Some(cidr) = self.deny.iter().find(|cidr| cidr.contains(addr)) {
+            return Err(Rejection::Denied(*cidr));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|cidr| cidr.contains(addr)) {
+            return Err(Rejection::NotAllowed);
+        }
+        Ok(())
+    }
+
+    // the accepted connections of peers the lists let through, the others are
+    // dropped (closing them) and logged
+    pub fn filter_incoming(
+        self,
+        incoming: TcpIncoming,
+    ) -> impl Stream<Item = std::io::Result<TcpStream>> {
+        incoming.filter(move |stream| {
+            let Ok(stream) = stream else {
+                return true;
+            };
+            let Ok(peer) = stream.peer_addr() else {
+                return false;
+            };
+            match self.check(peer.ip()) {
+                Ok(()) => true,
+                Err(rejection) => {
+                    println!("🚫 Rejected connection from {}: {}", peer, rejection);
+                    false
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr(value: &str) -> Cidr {
+        value.parse().unwrap()
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr() {
+        assert!(cidr("10.0.0.0/8").contains(ip("10.255.0.1")));
+        assert!(!cidr("10.0.0.0/8").contains(ip("11.0.0.1")));
+        assert!(cidr("0.0.0.0/0").contains(ip("192.0.2.1")));
+        assert!(cidr("192.0.2.7").contains(ip("192.0.2.7")));
+        assert!(!cidr("192.0.2.7").contains(ip("192.0.2.8")));
+        assert!(cidr("2001:db8::/32").contains(ip("2001:db8:1::1")));
+        assert!(!cidr("2001:db8::/32").contains(ip("2001:db9::1")));
+        // IPv4 networks don't match IPv6 peers, except IPv4-mapped ones
+        assert!(!cidr("0.0.0.0/0").contains(ip("::1")));
+        assert!(cidr("10.0.0.0/8").contains(ip("::ffff:10.1.2.3")));
+        assert_eq!(cidr("10.0.0.0/8").to_string(), "10.0.0.0/8");
+
+        for bad in [
+            "10.0.0.1/8",
+            "10.0.0.0/33",
+            "10.0.0/8",
+            "2001:db8::1/32",
+            "any",
+        ] {
+            assert!(bad.parse::<Cidr>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_deny_wins_over_allow() {
+        let list = AccessList {
+            allow: vec![cidr("10.0.0.0/8")],
+            deny: vec![cidr("10.6.0.0/16")],
+        };
+        assert_eq!(list.check(ip("10.1.0.1")), Ok(()));
+        assert_eq!(
+            list.check(ip("10.6.0.1")),
+            Err(Rejection::Denied(cidr("10.6.0.0/16")))
+        );
+        assert_eq!(list.check(ip("192.0.2.1")), Err(Rejection::NotAllowed));
+
+        // without an allow list only the denied are rejected
+        let list = AccessList {
+            deny: vec![cidr("192.0.2.0/24")],
+            ..AccessList::default()
+        };
+        assert_eq!(list.check(ip("198.51.100.1")), Ok(()));
+        assert!(list.check(ip("192.0.2.1")).is_err());
+        assert!(AccessList::default().check(ip("192.0.2.1")).is_ok());
+    }
+}
@@ -0,0 +1,162 @@
+use crate::workers::WorkerPool;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// sheds the calls that can wait when the verification workers fall behind, so
+// the logins already under way and the services checking sessions keep a
+// bounded latency through a spike. how loaded the server is follows the p99
+// of recent verifications (queue wait included) against a target, and how
+// full the workers' queue is. when busy, registrations are turned away;
+// when overloaded, new logins too. answers to challenges already issued and
+// ValidateSession are never turned away here: a session check does no
+// verification math, and an answer is the end of a login the server has
+// already paid for
+#[derive(Debug)]
+pub struct Admission {
+    // the p99 verification latency to stay under
+    pub target: Duration,
+    shed: [AtomicU64; 2],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Load {
+    Normal,
+    // p99 over half the target, or the queue half full
+    Busy,
+    // p99 over the target, or the queue three quarters full
+    Overloaded,
+}
+
+impl Display for Load {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Load::Normal => write!(f, "normal"),
+            Load::Busy => write!(f, "busy"),
+            Load::Overloaded => write!(f, "overloaded"),
+        }
+    }
+}
+
+// the calls admission control can turn away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Call {
+    Register,
+    Login,
+}
+
+impl Call {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Call::Register => "register",
+            Call::Login => "login",
+        }
+    }
+
+    // the lightest load the call is turned away at
+    fn shed_at(&self) -> Load {
+        match self {
+            Call::Register => Load::Busy,
+            Call::Login => Load::Overloaded,
+        }
+    }
+}
+
+const CALLS: [Call; 2] = [Call::Register, Call::Login];
+
+impl Admission {
+    pub fn new(target: Duration) -> Self {
+        Admission {
+            target,
+            shed: Default::default(),
+        }
+    }
+
+    pub fn classify(&self, p99: Duration, queued: usize, capacity: usize) -> Load {
+        let capacity = capacity.max(1);
+        if p99 > self.target || queued * 4 >= capacity * 3 {
+            Load::Overloaded
+        } else if p99 * 2 > self.target || queued * 2 >= capacity {
+            Load::Busy
+        } else {
+            Load::Normal
+        }
+    }
+
+    pub fn load(&self, pool: &WorkerPool, now: Instant) -> Load {
+        self.classify(
+            pool.latencies().p99(now).unwrap_or_default(),
+            pool.queued(),
+            pool.capacity(),
+        )
+    }
+
+    // Err with the load the call was turned away at
+    pub fn admit(&self, pool: &WorkerPool, call: Call, now: Instant) -> Result<(), Load> {
+        let load = self.load(pool, now);
+        if load < call.shed_at() {
+            return Ok(());
+        }
+        self.shed[call as usize].fetch_add(1, Ordering::Relaxed);
+        Err(load)
+    }
+
+    pub fn shed(&self, call: Call) -> u64 {
+        self.shed[call as usize].load(Ordering::Relaxed)
+    }
+
+    // the load as 0 (normal) to 2 (overloaded) and the calls turned away, in
+    // the Prometheus text format
+    pub fn metrics(&self, pool: &WorkerPool, now: Instant) -> String {
+        let mut out = String::new();
+        out += &format!(
+            "# TYPE zkp_admission_load gauge\nzkp_admission_load {}\n",
+            self.load(pool, now) as u8
+        );
+        out += "# TYPE zkp_admission_shed_total counter\n";
+        for call in CALLS {
+            out += &format!(
+                "zkp_admission_shed_total{{call=\"{}\"}} {}\n",
+                call.as_str(),
+                self.shed(call)
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calls_are_shed_by_load() {
+        let admission = Admission::new(Duration::from_millis(100));
+        let ms = Duration::from_millis;
+        assert_eq!(admission.classify(ms(40), 0, 64), Load::Normal);
+        assert_eq!(admission.classify(ms(60), 0, 64), Load::Busy);
+        assert_eq!(admission.classify(ms(10), 32, 64), Load::Busy);
+        assert_eq!(admission.classify(ms(101), 0, 64), Load::Overloaded);
+        assert_eq!(admission.classify(ms(10), 48, 64), Load::Overloaded);
+
+        let pool = WorkerPool::new(1, 64);
+        let now = Instant::now();
+        assert_eq!(admission.admit(&pool, Call::Register, now), Ok(()));
+        pool.latencies().record(now, ms(60));
+        assert_eq!(admission.admit(&pool, Call::Register, now), Err(Load::Busy));
+        assert_eq!(admission.admit(&pool, Call::Login, now), Ok(()));
+        pool.latencies().record(now, ms(500));
+        pool.latencies().record(now, ms(500));
+        assert_eq!(
+            admission.admit(&pool, Call::Login, now),
+            Err(Load::Overloaded)
+        );
+        assert_eq!(
+            (admission.shed(Call::Register), admission.shed(Call::Login)),
+            (1, 1)
+        );
+        let metrics = admission.metrics(&pool, now);
+        assert!(metrics.contains("zkp_admission_load 2\n"));
+        assert!(metrics.contains("zkp_admission_shed_total{call=\"login\"} 1\n"));
+    }
+}
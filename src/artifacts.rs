@@ -0,0 +1,314 @@
+use crate::secret::SecretBigUint;
+use crate::{Proof, ZKP};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::io::Write;
+use std::path::Path;
+
+// the files zkp-tool reads and writes: group parameters, key pairs, public
+// keys and proofs, as JSON or CBOR. numbers are big-endian hex in both, and
+// every file says what it holds in its "kind", so one reader takes any of them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Artifact {
+    Params(GroupParams),
+    SecretKey(SecretKey),
+    PublicKey(PublicValues),
+    Proof(ContextProof),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupParams {
+    // the named group these are, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub p: String,
+    pub q: String,
+    pub g: String,
+    pub h: String,
+}
+
+// x with its public values, so a key file is enough to prove
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretKey {
+    pub x: String,
+    pub y1: String,
+    pub y2: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicValues {
+    pub y1: String,
+    pub y2: String,
+}
+
+// a non-interactive proof over a context. the context itself isn't kept, only
+// its SHA-256, so a verifier given other bytes can say so
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextProof {
+    pub c: String,
+    pub s: String,
+    pub context_sha256: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Cbor,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArtifactError {
+    Malformed(String),
+    InvalidHex(&'static str),
+    WrongKind {
+        expected: &'static str,
+        found: &'static str,
+    },
+}
+
+impl Display for ArtifactError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArtifactError::Malformed(e) => write!(f, "not a JSON or CBOR artifact: {}", e),
+            ArtifactError::InvalidHex(field) => write!(f, "{} is not valid hex", field),
+            ArtifactError::WrongKind { expected, found } => {
+                write!(f, "expected a {} file, found a {} file", expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArtifactError {}
+
+// the key is only shown by the fields that are public
+impl std::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretKey")
+            .field("y1", &self.y1)
+            .field("y2", &self.y2)
+            .finish_non_exhaustive()
+    }
+}
+
+fn to_hex(n: &BigUint) -> String {
+    hex::encode(n.to_bytes_be())
+}
+
+fn from_hex(field: &'static str, value: &str) -> Result<BigUint, ArtifactError> {
+    hex::decode(value)
+        .map(|bytes| BigUint::from_bytes_be(&bytes))
+        .map_err(|_| ArtifactError::InvalidHex(field))
+}
+
+impl Artifact {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Artifact::Params(_) => "params",
+            Artifact::SecretKey(_) => "secret-key",
+            Artifact::PublicKey(_) => "public-key",
+            Artifact::Proof(_) => "proof",
+        }
+    }
+
+    pub fn encode(&self, format: Format) -> Vec<u8> {
+        match format {
+            Format::Json => {
+                let mut json = serde_json::to_vec_pretty(self).expect("artifacts serialize");
+                json.push(b'\n');
+                json
+            }
+            Format::Cbor => {
+                let mut cbor = Vec::new();
+                ciborium::into_writer(self, &mut cbor).expect("artifacts serialize");
+                cbor
+            }
+        }
+    }
+
+    // JSON when it starts with '{', CBOR otherwise
+    pub fn decode(bytes: &[u8]) -> Result<Artifact, ArtifactError> {
+        let json = bytes.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+        if json {
+            serde_json::from_slice(bytes).map_err(|e| ArtifactError::Malformed(e.to_string()))
+        } else {
+            ciborium::from_reader(bytes).map_err(|e| ArtifactError::Malformed(e.to_string()))
+        }
+    }
+
+    pub fn read(path: &Path) -> std::io::Result<Artifact> {
+        Artifact::decode(&std::fs::read(path)?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    // a secret key is only readable by its owner
+    pub fn write(&self, path: &Path, format: Format) -> std::io::Result<()> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        if let Artifact::SecretKey(_) = self {
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        }
+        options.open(path)?.write_all(&self.encode(format))
+    }
+
+    fn wrong_kind(&self, expected: &'static str) -> ArtifactError {
+        ArtifactError::WrongKind {
+            expected,
+            found: self.kind(),
+        }
+    }
+
+    pub fn into_params(self) -> Result<GroupParams, ArtifactError> {
+        match self {
+            Artifact::Params(params) => Ok(params),
+            other => Err(other.wrong_kind("params")),
+        }
+    }
+
+    pub fn into_secret_key(self) -> Result<SecretKey, ArtifactError> {
+        match self {
+            Artifact::SecretKey(key) => Ok(key),
+            other => Err(other.wrong_kind("secret-key")),
+        }
+    }
+
+    // the public values of a public key file, or of a key file
+    pub fn into_public_values(self) -> Result<PublicValues, ArtifactError> {
+        match self {
+            Artifact::PublicKey(public) => Ok(public),
+            Artifact::SecretKey(key) => Ok(key.public()),
+            other => Err(other.wrong_kind("public-key")),
+        }
+    }
+
+    pub fn into_proof(self) -> Result<ContextProof, ArtifactError> {
+        match self {
+            Artifact::Proof(proof) => Ok(proof),
+            other => Err(other.wrong_kind("proof")),
+        }
+    }
+}
+
+impl GroupParams {
+    pub fn new(name: Option<&str>, zkp: &ZKP) -> Self {
+        GroupParams {
+            name: name.map(str::to_string),
+            p: to_hex(&zkp.p),
+            q: to_hex(&zkp.q),
+            g: to_hex(&zkp.g),
+            h: to_hex(&zkp.h),
+        }
+    }
+
+    pub fn zkp(&self) -> Result<ZKP, ArtifactError> {
+        Ok(ZKP {
+            p: from_hex("p", &self.p)?,
+            q: from_hex("q", &self.q)?,
+            g: from_hex("g", &self.g)?,
+            h: from_hex("h", &self.h)?,
+        })
+    }
+
+    // groups::fingerprint of the parameters, the same however the hex is padded
+    pub fn fingerprint(&self) -> Result<[u8; 32], ArtifactError> {
+        self.zkp().map(|zkp| crate::groups::fingerprint(&zkp))
+    }
+
+    // ZKP::security_bits of the parameters
+    pub fn security_bits(&self) -> Result<u64, ArtifactError> {
+        self.zkp().map(|zkp| zkp.security_bits())
+    }
+}
+
+impl SecretKey {
+    pub fn new(zkp: &ZKP, x: &BigUint) -> Self {
+        SecretKey {
+            x: to_hex(x),
+            y1: to_hex(&ZKP::exponentiate(&zkp.g, x, &zkp.p)),
+            y2: to_hex(&ZKP::exponentiate(&zkp.h, x, &zkp.p)),
+        }
+    }
+
+    pub fn x(&self) -> Result<SecretBigUint, ArtifactError> {
+        from_hex("x", &self.x).map(SecretBigUint::new)
+    }
+
+    pub fn public(&self) -> PublicValues {
+        PublicValues {
+            y1: self.y1.clone(),
+            y2: self.y2.clone(),
+        }
+    }
+}
+
+impl PublicValues {
+    pub fn values(&self) -> Result<(BigUint, BigUint), ArtifactError> {
+        Ok((from_hex("y1", &self.y1)?, from_hex("y2", &self.y2)?))
+    }
+}
+
+impl ContextProof {
+    pub fn new(proof: &Proof, context_sha256: [u8; 32]) -> Self {
+        ContextProof {
+            c: to_hex(&proof.c),
+            s: to_hex(&proof.s),
+            context_sha256: hex::encode(context_sha256),
+        }
+    }
+
+    pub fn proof(&self) -> Result<Proof, ArtifactError> {
+        Ok(Proof {
+            c: from_hex("c", &self.c)?,
+            s: from_hex("s", &self.s)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groups;
+
+    #[test]
+    fn test_artifacts_round_trip() {
+        let zkp = groups::default_group();
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let key = SecretKey::new(&zkp, &x);
+        let proof = zkp.prove_non_interactive(&x, b"release.tar");
+        let artifacts = [
+            Artifact::Params(GroupParams::new(Some(groups::DEFAULT), &zkp)),
+            Artifact::SecretKey(key.clone()),
+            Artifact::PublicKey(key.public()),
+            Artifact::Proof(ContextProof::new(&proof, [7; 32])),
+        ];
+        for artifact in artifacts {
+            for format in [Format::Json, Format::Cbor] {
+                let decoded = Artifact::decode(&artifact.encode(format)).unwrap();
+                assert_eq!(decoded, artifact);
+            }
+        }
+
+        let read = Artifact::SecretKey(key.clone());
+        assert_eq!(read.into_public_values().unwrap(), key.public());
+        assert_eq!(
+            Artifact::PublicKey(key.public()).into_secret_key(),
+            Err(ArtifactError::WrongKind {
+                expected: "secret-key",
+                found: "public-key"
+            })
+        );
+        assert_eq!(key.x().unwrap().expose(), &x);
+        assert!(!format!("{:?}", key).contains(&key.x));
+        assert!(Artifact::decode(b"{\"kind\": \"ballot\"}").is_err());
+
+        let params = GroupParams::new(None, &zkp);
+        let padded = GroupParams {
+            p: format!("00{}", params.p),
+            ..params.clone()
+        };
+        assert_eq!(params.fingerprint(), Ok(groups::fingerprint(&zkp)));
+        assert_eq!(padded.fingerprint(), params.fingerprint());
+    }
+}
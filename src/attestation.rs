@@ -0,0 +1,157 @@
+use crate::capabilities;
+use crate::groups;
+use crate::service::AuthImpl;
+use crate::transcript::Transcript;
+
+// the crypto configuration a server is running, logged at startup and served
+// on /metrics, so an operator can check a deployment runs the intended one:
+// the fingerprint of every group it serves (groups::fingerprint, what clients
+// pin with --params-fingerprint), the login and VerifyProof flavors and group
+// backends of the build, and the SHA-256 of its TLS certificate. digest folds
+// them into one value to compare across replicas
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attestation {
+    pub groups: Vec<(&'static str, [u8; 32])>,
+    pub login_flavors: Vec<&'static str>,
+    pub proof_flavors: Vec<&'static str>,
+    // see capabilities::Capabilities::backends
+    pub backends: Vec<&'static str>,
+    // of the DER certificate the server presents, as `openssl x509
+    // -fingerprint -sha256` shows it. None without TLS
+    pub tls_certificate: Option<[u8; 32]>,
+}
+
+// every login takes one of these, see CreateAuthenticationChallenge
+const LOGIN_FLAVORS: &[&str] = &["interactive", "nonce-commitment", "non-interactive"];
+// VerifyProof's ProofFlavor
+const PROOF_FLAVORS: &[&str] = &["chaum-pedersen", "schnorr"];
+
+impl Attestation {
+    pub fn of(auth: &AuthImpl) -> Self {
+        Attestation {
+            groups: groups::NAMES
+                .iter()
+                .filter_map(|name| Some((*name, groups::fingerprint(&*auth.groups.get(name)?))))
+                .collect(),
+            login_flavors: LOGIN_FLAVORS.to_vec(),
+            proof_flavors: PROOF_FLAVORS.to_vec(),
+            backends: capabilities::capabilities().backends,
+            tls_certificate: auth
+                .channel_binding
+                .as_deref()
+                .and_then(|binding| binding.try_into().ok()),
+        }
+    }
+
+    pub fn digest(&self) -> [u8; 32] {
+        let mut transcript = Transcript::new(b"zkp-chaum-pedersen/attestation");
+        for (name, fingerprint) in &self.groups {
+            transcript.append(b"group", name.as_bytes());
+            transcript.append(b"fingerprint", fingerprint);
+        }
+        for flavor in &self.login_flavors {
+            transcript.append(b"login-flavor", flavor.as_bytes());
+        }
+        for flavor in &self.proof_flavors {
+            transcript.append(b"proof-flavor", flavor.as_bytes());
+        }
+        for backend in &self.backends {
+            transcript.append(b"backend", backend.as_bytes());
+        }
+        transcript.append(
+            b"tls-certificate",
+            self.tls_certificate.as_ref().map_or(&[][..], |c| &c[..]),
+        );
+        transcript.finalize()
+    }
+
+    // one line, for the log and for grep
+    pub fn line(&self) -> String {
+        let groups: Vec<_> = self
+            .groups
+            .iter()
+            .map(|(name, fingerprint)| format!("{}={}", name, hex::encode(fingerprint)))
+            .collect();
+        format!(
+            "digest={} groups={} login-flavors={} proof-flavors={} backends={} tls-certificate={}",
+            hex::encode(self.digest()),
+            groups.join(","),
+            self.login_flavors.join(","),
+            self.proof_flavors.join(","),
+            self.backends.join(","),
+            self.tls_certificate
+                .map_or_else(|| "none".to_string(), hex::encode)
+        )
+    }
+
+    // info gauges, always 1, whose labels carry the values, in the Prometheus
+    // text format
+    pub fn metrics(&self) -> String {
+        let mut out = String::new();
+        out += "# TYPE zkp_attestation_info gauge\n";
+        out += &format!(
+            "zkp_attestation_info{{digest=\"{}\",tls_certificate=\"{}\"}} 1\n",
+            hex::encode(self.digest()),
+            self.tls_certificate.map(hex::encode).unwrap_or_default()
+        );
+        out += "# TYPE zkp_group_info gauge\n";
+        for (name, fingerprint) in &self.groups {
+            out += &format!(
+                "zkp_group_info{{group=\"{}\",fingerprint=\"{}\"}} 1\n",
+                name,
+                hex::encode(fingerprint)
+            );
+        }
+        out += "# TYPE zkp_flavor_info gauge\n";
+        for (kind, flavors) in [
+            ("login", &self.login_flavors),
+            ("proof", &self.proof_flavors),
+        ] {
+            for flavor in flavors {
+                out += &format!(
+                    "zkp_flavor_info{{kind=\"{}\",flavor=\"{}\"}} 1\n",
+                    kind, flavor
+                );
+            }
+        }
+        out += "# TYPE zkp_backend_info gauge\n";
+        for backend in &self.backends {
+            out += &format!("zkp_backend_info{{backend=\"{}\"}} 1\n", backend);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attestation_follows_the_configuration() {
+        let plain = Attestation::of(&AuthImpl::default());
+        assert_eq!(plain.groups.len(), groups::NAMES.len());
+        assert_eq!(
+            plain.groups[0],
+            (
+                groups::NAMES[0],
+                groups::fingerprint(&groups::by_name(groups::NAMES[0]).unwrap())
+            )
+        );
+        assert!(plain.line().ends_with(" tls-certificate=none"));
+        assert!(plain
+            .metrics()
+            .contains("zkp_flavor_info{kind=\"login\",flavor=\"non-interactive\"} 1\n"));
+
+        let tls = Attestation::of(&AuthImpl {
+            channel_binding: Some(vec![7; 32]),
+            ..AuthImpl::default()
+        });
+        assert_eq!(tls.tls_certificate, Some([7; 32]));
+        assert_ne!(tls.digest(), plain.digest());
+        assert!(tls.metrics().contains(&format!(
+            "zkp_attestation_info{{digest=\"{}\",tls_certificate=\"{}\"}} 1\n",
+            hex::encode(tls.digest()),
+            hex::encode([7; 32])
+        )));
+    }
+}
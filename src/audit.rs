@@ -0,0 +1,612 @@
+use crate::clock::Clock;
+use crate::state::AuditEvent;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::ffi::OsString;
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// where the audit events go besides the server's log and the copy kept for
+// ListAuditEvents. a deployment picks any number of sinks with --audit-sink;
+// each gets every event as one JSON object, see Record. a sink that fails
+// loses the event for its destination only, the others and the kept copy
+// still have it
+pub trait AuditSink: Debug + Send + Sync {
+    fn write(&self, event: &AuditEvent) -> io::Result<()>;
+
+    // the destination, as --audit-sink names it
+    fn name(&self) -> String;
+
+    // whether the destination can be written to, asked once at startup so a
+    // sink that can't is found then rather than by the first event
+    fn check(&self) -> io::Result<()>;
+}
+
+// how long a network sink waits to connect, send or hear back
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(2);
+
+// an event as the sinks write it
+#[derive(Debug, Serialize)]
+pub struct Record<'a> {
+    pub kind: &'static str,
+    pub user: &'a str,
+    pub request_id: &'a str,
+    // unix seconds
+    pub at: u64,
+    // empty when unknown
+    pub remote_addr: String,
+    pub user_agent: &'a str,
+    pub device_id: &'a str,
+}
+
+impl<'a> Record<'a> {
+    pub fn of(event: &'a AuditEvent) -> Self {
+        Record {
+            kind: event.kind.as_str(),
+            user: &event.user_name,
+            request_id: &event.request_id,
+            at: event
+                .at
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            remote_addr: event
+                .client
+                .remote_addr
+                .map(|addr| addr.to_string())
+                .unwrap_or_default(),
+            user_agent: &event.client.user_agent,
+            device_id: &event.client.device_id,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("audit records serialize")
+    }
+}
+
+// when a FileSink starts a new file: once the next event would take the file
+// past max_bytes, or comes max_age or more after the file was started, the
+// file becomes <path>.1 (<path>.1.gz when compressed), the previous <path>.1
+// becomes <path>.2 and so on, and the one past <path>.<keep> is deleted. a
+// file already there when the sink opens it counts as started then
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rotation {
+    pub max_bytes: u64,
+    // by size only when None
+    pub max_age: Option<Duration>,
+    pub keep: usize,
+    // gzip the files rotated out, on the write that rotates them
+    pub compress: bool,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Rotation {
+            max_bytes: 10 * 1024 * 1024,
+            max_age: None,
+            keep: 5,
+            compress: false,
+        }
+    }
+}
+
+// JSON lines appended to a file, rotated by size and age
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    rotation: Rotation,
+    // what the age of a file is measured by
+    clock: Box<dyn Clock>,
+    // opened by the first write, and again after a rotation or an error
+    current: Mutex<Option<Current>>,
+}
+
+#[derive(Debug)]
+struct Current {
+    file: File,
+    started: SystemTime,
+}
+
+fn ignore_missing(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<PathBuf>, rotation: Rotation, clock: Box<dyn Clock>) -> Self {
+        FileSink {
+            path: path.into(),
+            rotation,
+            clock,
+            current: Mutex::new(None),
+        }
+    }
+
+    // <path>.<n>, or <path>.<n>.gz
+    fn rotated(&self, n: usize, gzip: bool) -> PathBuf {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(format!(".{}", n));
+        if gzip {
+            path.push(".gz");
+        }
+        path.into()
+    }
+
+    fn open(&self) -> io::Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+    }
+
+    // files rotated out before compression was turned on or off move up
+    // under the names they have
+    fn rotate(&self) -> io::Result<()> {
+        let keep = self.rotation.keep;
+        if keep == 0 {
+            return std::fs::remove_file(&self.path);
+        }
+        for gzip in [false, true] {
+            ignore_missing(std::fs::remove_file(self.rotated(keep, gzip)))?;
+            for n in (1..keep).rev() {
+                ignore_missing(std::fs::rename(
+                    self.rotated(n, gzip),
+                    self.rotated(n + 1, gzip),
+                ))?;
+            }
+        }
+        if !self.rotation.compress {
+            return std::fs::rename(&self.path, self.rotated(1, false));
+        }
+        // written next to itself and renamed, so there is never half a .gz
+        let gzipped = self.rotated(1, true);
+        let temporary = gzipped.with_extension("tmp");
+        let mut encoder = GzEncoder::new(File::create(&temporary)?, Compression::default());
+        io::copy(&mut File::open(&self.path)?, &mut encoder)?;
+        encoder.finish()?;
+        std::fs::rename(&temporary, &gzipped)?;
+        std::fs::remove_file(&self.path)
+    }
+}
+
+impl AuditSink for FileSink {
+    fn write(&self, event: &AuditEvent) -> io::Result<()> {
+        let line = Record::of(event).to_json() + "\n";
+        let now = self.clock.now();
+        let mut current = self.current.lock().unwrap();
+        let mut open = match current.take() {
+            Some(open) => open,
+            None => Current {
+                file: self.open()?,
+                started: now,
+            },
+        };
+        let len = open.file.metadata()?.len();
+        let full = len + line.len() as u64 > self.rotation.max_bytes;
+        let old = self
+            .rotation
+            .max_age
+            .is_some_and(|max_age| now.duration_since(open.started).unwrap_or_default() >= max_age);
+        if len > 0 && (full || old) {
+            drop(open);
+            self.rotate()?;
+            open = Current {
+                file: self.open()?,
+                started: now,
+            };
+        }
+        open.file.write_all(line.as_bytes())?;
+        *current = Some(open);
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        format!("file:{}", self.path.display())
+    }
+
+    // opens the file, which the first event then writes to
+    fn check(&self) -> io::Result<()> {
+        let mut current = self.current.lock().unwrap();
+        if current.is_none() {
+            *current = Some(Current {
+                file: self.open()?,
+                started: self.clock.now(),
+            });
+        }
+        Ok(())
+    }
+}
+
+// where a SyslogSink sends to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyslogTarget {
+    // a syslog daemon's UDP port, host:port
+    Udp(String),
+    // a local socket such as /dev/log
+    #[cfg(unix)]
+    Local(PathBuf),
+}
+
+// RFC 5424 messages, facility authpriv and severity notice, with the JSON
+// record as the message. the daemon adds the time and host
+#[derive(Debug)]
+pub struct SyslogSink {
+    target: SyslogTarget,
+}
+
+// authpriv (10) * 8 + notice (5)
+const SYSLOG_PRIORITY: u8 = 85;
+
+impl SyslogSink {
+    pub fn new(target: SyslogTarget) -> Self {
+        SyslogSink { target }
+    }
+
+    fn message(event: &AuditEvent) -> String {
+        format!(
+            "<{}>1 - - zkp-chaum-pedersen {} audit - {}",
+            SYSLOG_PRIORITY,
+            std::process::id(),
+            Record::of(event).to_json()
+        )
+    }
+}
+
+impl AuditSink for SyslogSink {
+    fn write(&self, event: &AuditEvent) -> io::Result<()> {
+        let message = SyslogSink::message(event);
+        match &self.target {
+            SyslogTarget::Udp(address) => {
+                let to = resolve(address)?;
+                let from: SocketAddr = if to.is_ipv4() {
+                    ([0, 0, 0, 0], 0).into()
+                } else {
+                    ([0u16; 8], 0).into()
+                };
+                UdpSocket::bind(from)?.send_to(message.as_bytes(), to)?;
+            }
+            #[cfg(unix)]
+            SyslogTarget::Local(path) => {
+                std::os::unix::net::UnixDatagram::unbound()?.send_to(message.as_bytes(), path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        match &self.target {
+            SyslogTarget::Udp(address) => format!("syslog:{}", address),
+            #[cfg(unix)]
+            SyslogTarget::Local(path) => format!("syslog:{}", path.display()),
+        }
+    }
+
+    // datagrams are sent unanswered, so all there is to check is that the
+    // address resolves or the socket exists
+    fn check(&self) -> io::Result<()> {
+        match &self.target {
+            SyslogTarget::Udp(address) => resolve(address).map(drop),
+            #[cfg(unix)]
+            SyslogTarget::Local(path) => std::fs::metadata(path).map(drop),
+        }
+    }
+}
+
+fn resolve(address: &str) -> io::Result<SocketAddr> {
+    address.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} has no address", address),
+        )
+    })
+}
+
+// published to a NATS subject over the server's text protocol. each event
+// is followed by a PING, and counts as sent once the server's PONG is back,
+// so an event the connection loses is reported rather than dropped
+// silently. the connection is made by the first write and again after one
+// fails
+#[derive(Debug)]
+pub struct NatsSink {
+    // host:port
+    address: String,
+    subject: String,
+    connection: Mutex<Option<BufReader<TcpStream>>>,
+}
+
+impl NatsSink {
+    pub fn new(address: impl Into<String>, subject: impl Into<String>) -> Self {
+        NatsSink {
+            address: address.into(),
+            subject: subject.into(),
+            connection: Mutex::new(None),
+        }
+    }
+
+    fn connect(&self) -> io::Result<BufReader<TcpStream>> {
+        let stream = TcpStream::connect_timeout(&resolve(&self.address)?, NETWORK_TIMEOUT)?;
+        stream.set_read_timeout(Some(NETWORK_TIMEOUT))?;
+        stream.set_write_timeout(Some(NETWORK_TIMEOUT))?;
+        let mut connection = BufReader::new(stream);
+        // the server's INFO comes first
+        let info = read_line(&mut connection)?;
+        if !info.starts_with("INFO") {
+            return Err(protocol_error(&info));
+        }
+        connection
+            .get_mut()
+            .write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false}\r\n")?;
+        Ok(connection)
+    }
+
+    fn publish(&self, connection: &mut BufReader<TcpStream>, payload: &str) -> io::Result<()> {
+        let message = format!(
+            "PUB {} {}\r\n{}\r\nPING\r\n",
+            self.subject,
+            payload.len(),
+            payload
+        );
+        connection.get_mut().write_all(message.as_bytes())?;
+        loop {
+            let line = read_line(connection)?;
+            match line.as_str() {
+                "PONG" => return Ok(()),
+                "PING" => connection.get_mut().write_all(b"PONG\r\n")?,
+                line if line.starts_with("INFO") || line == "+OK" => {}
+                line => return Err(protocol_error(line)),
+            }
+        }
+    }
+}
+
+fn read_line(connection: &mut BufReader<TcpStream>) -> io::Result<String> {
+    let mut line = String::new();
+    if connection.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(line.trim_end().to_string())
+}
+
+fn protocol_error(line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("NATS server said {:?}", line),
+    )
+}
+
+impl AuditSink for NatsSink {
+    fn write(&self, event: &AuditEvent) -> io::Result<()> {
+        let payload = Record::of(event).to_json();
+        let mut connection = self.connection.lock().unwrap();
+        let open = match connection.as_mut() {
+            Some(open) => open,
+            None => connection.insert(self.connect()?),
+        };
+        let sent = self.publish(open, &payload);
+        if sent.is_err() {
+            *connection = None;
+        }
+        sent
+    }
+
+    fn name(&self) -> String {
+        format!("nats://{}/{}", self.address, self.subject)
+    }
+
+    // connects, and keeps the connection for the first event
+    fn check(&self) -> io::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_none() {
+            *connection = Some(self.connect()?);
+        }
+        Ok(())
+    }
+}
+
+// a sink from its --audit-sink form:
+//   file:/var/log/zkp/audit.jsonl      JSON lines, rotated as the rotation says
+//   syslog:127.0.0.1:514               RFC 5424 over UDP
+//   syslog:/dev/log                    RFC 5424 to a local socket (unix)
+//   nats://127.0.0.1:4222/zkp.audit    published to the subject
+pub fn open(spec: &str, rotation: Rotation) -> Result<Box<dyn AuditSink>, String> {
+    if let Some(path) = spec.strip_prefix("file:") {
+        if path.is_empty() {
+            return Err("file: needs a path".to_string());
+        }
+        return Ok(Box::new(FileSink::new(path, rotation, Box::default())));
+    }
+    if let Some(target) = spec.strip_prefix("syslog:") {
+        #[cfg(unix)]
+        if target.starts_with('/') {
+            return Ok(Box::new(SyslogSink::new(SyslogTarget::Local(
+                target.into(),
+            ))));
+        }
+        if !target.contains(':') {
+            return Err("syslog: needs host:port or a socket path".to_string());
+        }
+        return Ok(Box::new(SyslogSink::new(SyslogTarget::Udp(
+            target.to_string(),
+        ))));
+    }
+    if let Some(rest) = spec.strip_prefix("nats://") {
+        return match rest.split_once('/') {
+            Some((address, subject)) if !address.is_empty() && !subject.is_empty() => {
+                if subject.contains(char::is_whitespace) {
+                    return Err("a NATS subject has no whitespace".to_string());
+                }
+                Ok(Box::new(NatsSink::new(address, subject)))
+            }
+            _ => Err("nats:// needs host:port/subject".to_string()),
+        };
+    }
+    Err(format!(
+        "{:?} is none of file:PATH, syslog:HOST:PORT, syslog:/PATH or nats://HOST:PORT/SUBJECT",
+        spec
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::state::{AuditKind, ClientInfo};
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    fn event(user_name: &str) -> AuditEvent {
+        AuditEvent {
+            kind: AuditKind::AccountRecovered,
+            user_name: user_name.to_string(),
+            request_id: "req-1".to_string(),
+            at: UNIX_EPOCH + Duration::from_secs(1000),
+            client: ClientInfo {
+                remote_addr: Some(([127, 0, 0, 1], 4000).into()),
+                ..ClientInfo::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_file_sink_rotates_by_size() {
+        let path = std::env::temp_dir().join(format!("audit-{}.jsonl", std::process::id()));
+        let line = Record::of(&event("alice")).to_json().len() as u64 + 1;
+        let sink = FileSink::new(
+            &path,
+            Rotation {
+                max_bytes: 2 * line,
+                keep: 2,
+                ..Rotation::default()
+            },
+            Box::default(),
+        );
+        for _ in 0..7 {
+            sink.write(&event("alice")).unwrap();
+        }
+        // 7 events, 2 to a file: the current file has 1, .1 and .2 have 2
+        // each and the 2 before them are gone
+        let lines = |path: &PathBuf| std::fs::read_to_string(path).unwrap().lines().count();
+        assert_eq!(lines(&path), 1);
+        assert_eq!(lines(&sink.rotated(1, false)), 2);
+        assert_eq!(lines(&sink.rotated(2, false)), 2);
+        assert!(!sink.rotated(3, false).exists());
+        let record: serde_json::Value =
+            serde_json::from_str(std::fs::read_to_string(&path).unwrap().trim()).unwrap();
+        assert_eq!(record["kind"], "account-recovered");
+        assert_eq!(record["at"], 1000);
+        assert_eq!(record["remote_addr"], "127.0.0.1:4000");
+        for path in [path.clone(), sink.rotated(1, false), sink.rotated(2, false)] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_file_sink_rotates_by_age_and_compresses() {
+        let path = std::env::temp_dir().join(format!("audit-gz-{}.jsonl", std::process::id()));
+        let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000));
+        let sink = FileSink::new(
+            &path,
+            Rotation {
+                max_age: Some(Duration::from_secs(3600)),
+                keep: 1,
+                compress: true,
+                ..Rotation::default()
+            },
+            Box::new(clock.clone()),
+        );
+        sink.write(&event("alice")).unwrap();
+        clock.advance(Duration::from_secs(3599));
+        sink.write(&event("bob")).unwrap();
+        clock.advance(Duration::from_secs(1));
+        sink.write(&event("carol")).unwrap();
+
+        let mut rotated = String::new();
+        GzDecoder::new(File::open(sink.rotated(1, true)).unwrap())
+            .read_to_string(&mut rotated)
+            .unwrap();
+        assert_eq!(rotated.lines().count(), 2);
+        assert!(!sink.rotated(1, false).exists());
+        let current = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(current.trim(), Record::of(&event("carol")).to_json());
+
+        // keep: 1, so the next rotation drops the first file
+        clock.advance(Duration::from_secs(3600));
+        sink.write(&event("dave")).unwrap();
+        let mut rotated = String::new();
+        GzDecoder::new(File::open(sink.rotated(1, true)).unwrap())
+            .read_to_string(&mut rotated)
+            .unwrap();
+        assert_eq!(rotated, current);
+        assert!(!sink.rotated(2, true).exists());
+        for path in [path.clone(), sink.rotated(1, true)] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_network_sinks_deliver_events() {
+        let daemon = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let syslog = open(
+            &format!("syslog:{}", daemon.local_addr().unwrap()),
+            Rotation::default(),
+        )
+        .unwrap();
+        syslog.write(&event("alice")).unwrap();
+        let mut message = [0u8; 1024];
+        let len = daemon.recv(&mut message).unwrap();
+        let message = std::str::from_utf8(&message[..len]).unwrap();
+        assert!(message.starts_with("<85>1 - - zkp-chaum-pedersen "));
+        assert!(message.ends_with(&Record::of(&event("alice")).to_json()));
+
+        // a NATS server that answers every PING, and hangs up after the
+        // first event so the second has to reconnect
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut published = Vec::new();
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut connection = BufReader::new(stream);
+                connection.get_mut().write_all(b"INFO {}\r\n").unwrap();
+                loop {
+                    let line = read_line(&mut connection).unwrap();
+                    if line.starts_with("PUB") {
+                        published.push((line, read_line(&mut connection).unwrap()));
+                    } else if line == "PING" {
+                        connection.get_mut().write_all(b"PONG\r\n").unwrap();
+                        break;
+                    }
+                }
+            }
+            published
+        });
+        let nats = open(
+            &format!("nats://{}/zkp.audit", address),
+            Rotation::default(),
+        )
+        .unwrap();
+        nats.write(&event("alice")).unwrap();
+        // the first write fails on the closed connection, the next reconnects
+        while nats.write(&event("bob")).is_err() {}
+        let published = server.join().unwrap();
+        let payload = Record::of(&event("bob")).to_json();
+        assert_eq!(published.len(), 2);
+        assert_eq!(
+            published[1],
+            (format!("PUB zkp.audit {}", payload.len()), payload)
+        );
+
+        assert!(open("kafka://127.0.0.1:9092/audit", Rotation::default()).is_err());
+        assert!(open("nats://127.0.0.1:4222", Rotation::default()).is_err());
+    }
+}
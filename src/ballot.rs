@@ -0,0 +1,282 @@
+use crate::elgamal::{self, Ciphertext};
+use crate::montgomery::Montgomery;
+use crate::rng;
+use crate::secret::wipe_biguint;
+use crate::ZKP;
+use num_bigint::BigUint;
+
+// ballot validity: (a, b) encrypts 0 or 1 under y, without revealing which.
+// OR of two Chaum-Pedersen statements on the bases (g, y) (Cramer–Damgård–
+// Schoenmakers): branch j claims log_g a = log_y (b / g ** j). the prover
+// answers the true branch, simulates the other with a chosen (c_j, s_j), and
+// c0 + c1 = H(...) mod q keeps it from simulating both
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BallotProof {
+    // (t_a, t_b) per branch
+    pub commitments: [(BigUint, BigUint); 2],
+    pub challenges: [BigUint; 2],
+    pub responses: [BigUint; 2],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ballot {
+    pub ciphertext: Ciphertext,
+    pub proof: BallotProof,
+}
+
+// [b, b / g mod p], g ** -1 = g ** (q - 1) as g has order q
+fn branch_values(zkp: &ZKP, b: &BigUint) -> [BigUint; 2] {
+    let g_inverse = ZKP::exponentiate(&zkp.g, &(&zkp.q - 1u32), &zkp.p);
+    [b.clone(), (b * g_inverse) % &zkp.p]
+}
+
+// c = H(g, h, p, q, y, a, b, t_a0, t_b0, t_a1, t_b1, context) mod q
+fn challenge(
+    zkp: &ZKP,
+    y: &BigUint,
+    ciphertext: &Ciphertext,
+    commitments: &[(BigUint, BigUint); 2],
+    context: &[u8],
+) -> BigUint {
+    let mut transcript = zkp.transcript(b"zkp-chaum-pedersen/ballot");
+    transcript.append_biguint(b"y", y);
+    transcript.append_biguint(b"a", &ciphertext.a);
+    transcript.append_biguint(b"b", &ciphertext.b);
+    for (t_a, t_b) in commitments {
+        transcript.append_biguint(b"t_a", t_a);
+        transcript.append_biguint(b"t_b", t_b);
+    }
+    transcript.append(b"context", context);
+    transcript.challenge(&zkp.q)
+}
+
+// proof for a ciphertext made with encrypt(zkp, y, vote, r)
+pub fn prove(
+    zkp: &ZKP,
+    y: &BigUint,
+    ciphertext: &Ciphertext,
+    vote: bool,
+    r: &BigUint,
+    context: &[u8],
+) -> BallotProof {
+    let bases = elgamal::key_bases(zkp, y);
+    let values = branch_values(zkp, &ciphertext.b);
+    let real = vote as usize;
+    let fake = 1 - real;
+
+    // simulated branch: t_a = g ** s * a ** c ; t_b = y ** s * (b / g ** j) ** c
+    let fake_c = ZKP::generate_random_number_below(&zkp.q);
+    let fake_s = ZKP::generate_random_number_below(&zkp.q);
+    let fake_commitment = (
+        (ZKP::exponentiate(&zkp.g, &fake_s, &zkp.p)
+            * ZKP::exponentiate(&ciphertext.a, &fake_c, &zkp.p))
+            % &zkp.p,
+        (ZKP::exponentiate(y, &fake_s, &zkp.p) * ZKP::exponentiate(&values[fake], &fake_c, &zkp.p))
+            % &zkp.p,
+    );
+
+    // real branch: t_a = g ** k ; t_b = y ** k
+    let mut k = ZKP::generate_random_number_below(&zkp.q);
+    let real_commitment = (
+        ZKP::exponentiate(&bases.g, &k, &zkp.p),
+        ZKP::exponentiate(&bases.h, &k, &zkp.p),
+    );
+
+    let commitments = if vote {
+        [fake_commitment, real_commitment]
+    } else {
+        [real_commitment, fake_commitment]
+    };
+    let c = challenge(zkp, y, ciphertext, &commitments, context);
+
+    // c_real = c - c_fake mod q
+    let real_c = (c + &zkp.q - &fake_c) % &zkp.q;
+    let real_s = bases.solve(&k, &real_c, r);
+    wipe_biguint(&mut k);
+
+    let (challenges, responses) = if vote {
+        ([fake_c, real_c], [fake_s, real_s])
+    } else {
+        ([real_c, fake_c], [real_s, fake_s])
+    };
+    BallotProof {
+        commitments,
+        challenges,
+        responses,
+    }
+}
+
+// encrypts the vote with a fresh r and proves it is 0 or 1
+pub fn cast(zkp: &ZKP, y: &BigUint, vote: bool, context: &[u8]) -> Ballot {
+    let mut r = ZKP::generate_random_number_below(&zkp.q);
+    let ciphertext = elgamal::encrypt(zkp, y, &BigUint::from(vote as u32), &r);
+    let proof = prove(zkp, y, &ciphertext, vote, &r, context);
+    wipe_biguint(&mut r);
+    Ballot { ciphertext, proof }
+}
+
+// the checks that don't need an exponentiation: every number in range and
+// c0 + c1 = H(...) mod q
+fn well_formed(zkp: &ZKP, y: &BigUint, ballot: &Ballot, context: &[u8]) -> bool {
+    let proof = &ballot.proof;
+    let zero = BigUint::from(0u32);
+    let commitments_in_range = proof
+        .commitments
+        .iter()
+        .all(|(t_a, t_b)| *t_a != zero && *t_b != zero && *t_a < zkp.p && *t_b < zkp.p);
+    let scalars_in_range = proof
+        .challenges
+        .iter()
+        .chain(&proof.responses)
+        .all(|n| *n < zkp.q);
+    commitments_in_range
+        && scalars_in_range
+        && (&proof.challenges[0] + &proof.challenges[1]) % &zkp.q
+            == challenge(zkp, y, &ballot.ciphertext, &proof.commitments, context)
+}
+
+fn ciphertext_in_subgroup(zkp: &ZKP, ciphertext: &Ciphertext) -> bool {
+    elgamal::in_subgroup(zkp, &ciphertext.a) && elgamal::in_subgroup(zkp, &ciphertext.b)
+}
+
+pub fn verify(zkp: &ZKP, y: &BigUint, ballot: &Ballot, context: &[u8]) -> bool {
+    if !elgamal::in_subgroup(zkp, y)
+        || !ciphertext_in_subgroup(zkp, &ballot.ciphertext)
+        || !well_formed(zkp, y, ballot, context)
+    {
+        return false;
+    }
+    let bases = elgamal::key_bases(zkp, y);
+    let values = branch_values(zkp, &ballot.ciphertext.b);
+    let proof = &ballot.proof;
+    (0..2).all(|j| {
+        let (t_a, t_b) = &proof.commitments[j];
+        bases.verify(
+            t_a,
+            t_b,
+            &ballot.ciphertext.a,
+            &values[j],
+            &proof.challenges[j],
+            &proof.responses[j],
+        )
+    })
+}
+
+// all ballots at once (small-exponent batch test): every verification
+// equation is raised to a random 64-bit weight and the products are compared,
+// so g and y are exponentiated once for the whole batch and a and b once per
+// ballot. accepts an invalid ballot with probability about 2 ** -64.
+// a, b and y must lie in the order-q subgroup for this to hold, so they are
+// still checked one by one. every product and exponentiation of the batch
+// shares one reduction context for p
+pub fn verify_batch(zkp: &ZKP, y: &BigUint, ballots: &[Ballot], context: &[u8]) -> bool {
+    if !elgamal::in_subgroup(zkp, y) {
+        return false;
+    }
+    let Some(montgomery) = Montgomery::shared(&zkp.p) else {
+        return false;
+    };
+    let mut left = BigUint::from(1u32);
+    let mut right = BigUint::from(1u32);
+    // exponents of g and y, accumulated mod q
+    let mut g_exponent = BigUint::from(0u32);
+    let mut y_exponent = BigUint::from(0u32);
+
+    for ballot in ballots {
+        if !ciphertext_in_subgroup(zkp, &ballot.ciphertext) || !well_formed(zkp, y, ballot, context)
+        {
+            return false;
+        }
+        let proof = &ballot.proof;
+        let mut a_exponent = BigUint::from(0u32);
+        let mut b_exponent = BigUint::from(0u32);
+        for j in 0..2 {
+            let (t_a, t_b) = &proof.commitments[j];
+            let (c, s) = (&proof.challenges[j], &proof.responses[j]);
+            let (u, v) = rng::with_rng(|rng| (rng.next_u64(), rng.next_u64()));
+            let (u, v) = (BigUint::from(u), BigUint::from(v));
+
+            // t_a ** u = g ** (u * s) * a ** (u * c)
+            // t_b ** v = y ** (v * s) * b ** (v * c) * g ** (-j * v * c)
+            left = montgomery.mod_mul(&left, &montgomery.pow_product(t_a, &u, t_b, &v));
+            g_exponent += &u * s;
+            y_exponent += &v * s;
+            a_exponent += &u * c;
+            b_exponent += &v * c;
+            if j == 1 {
+                let vc = (&v * c) % &zkp.q;
+                g_exponent += &zkp.q - vc;
+            }
+        }
+        let (a, b) = (&ballot.ciphertext.a, &ballot.ciphertext.b);
+        let (a_exponent, b_exponent) = (a_exponent % &zkp.q, b_exponent % &zkp.q);
+        right = montgomery.mod_mul(
+            &right,
+            &montgomery.pow_product(a, &a_exponent, b, &b_exponent),
+        );
+        g_exponent %= &zkp.q;
+        y_exponent %= &zkp.q;
+    }
+    right = montgomery.mod_mul(
+        &right,
+        &montgomery.pow_product(&zkp.g, &g_exponent, y, &y_exponent),
+    );
+    left == right
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (ZKP, BigUint, BigUint) {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let y = elgamal::public_key(&zkp, &x);
+        (zkp, x, y)
+    }
+
+    #[test]
+    fn test_ballot_proofs() {
+        let (zkp, x, y) = setup();
+        for vote in [false, true] {
+            let ballot = cast(&zkp, &y, vote, b"election 1");
+            assert!(verify(&zkp, &y, &ballot, b"election 1"));
+            assert!(!verify(&zkp, &y, &ballot, b"election 2"));
+            assert_eq!(
+                elgamal::decrypt(&zkp, &x, &ballot.ciphertext, 1),
+                Some(vote as u64)
+            );
+        }
+
+        // an encryption of 2 can't be proven, whichever branch is claimed
+        let r = ZKP::generate_random_number_below(&zkp.q);
+        let ciphertext = elgamal::encrypt(&zkp, &y, &BigUint::from(2u32), &r);
+        for vote in [false, true] {
+            let proof = prove(&zkp, &y, &ciphertext, vote, &r, b"election 1");
+            let ballot = Ballot {
+                ciphertext: ciphertext.clone(),
+                proof,
+            };
+            assert!(!verify(&zkp, &y, &ballot, b"election 1"));
+            assert!(!verify_batch(&zkp, &y, &[ballot], b"election 1"));
+        }
+    }
+
+    #[test]
+    fn test_batch_verification() {
+        let (zkp, _, y) = setup();
+        let mut ballots: Vec<Ballot> = [true, false, false, true, true]
+            .iter()
+            .map(|vote| cast(&zkp, &y, *vote, b"election 1"))
+            .collect();
+        assert!(verify_batch(&zkp, &y, &ballots, b"election 1"));
+        assert!(verify_batch(&zkp, &y, &[], b"election 1"));
+
+        // a response changed in one ballot fails the whole batch
+        let s = &ballots[3].proof.responses[0];
+        ballots[3].proof.responses[0] = (s + 1u32) % &zkp.q;
+        assert!(!verify(&zkp, &y, &ballots[3], b"election 1"));
+        assert!(!verify_batch(&zkp, &y, &ballots, b"election 1"));
+    }
+}
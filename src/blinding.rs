@@ -0,0 +1,171 @@
+use crate::keys::PublicKey;
+use crate::secret::wipe_biguint;
+use crate::transcript::Transcript;
+use crate::ZKP;
+use num_bigint::BigUint;
+use std::fmt::Debug;
+use zeroize::Zeroize;
+
+// blinded login: the prover picks a secret t and proves knowledge of x on the
+// bases G = g ** t, H = h ** t for the blinded key Y1 = y1 ** t, Y2 = y2 ** t.
+// t only travels sealed to the server, so an observer can't check a transcript
+// against a registered (y1, y2) and logins of one user can't be linked
+pub fn blinded_bases(zkp: &ZKP, t: &BigUint) -> ZKP {
+    ZKP {
+        p: zkp.p.clone(),
+        q: zkp.q.clone(),
+        g: ZKP::exponentiate(&zkp.g, t, &zkp.p),
+        h: ZKP::exponentiate(&zkp.h, t, &zkp.p),
+    }
+}
+
+// Y1 = y1 ** t mod p ; Y2 = y2 ** t mod p
+pub fn blinded_key(zkp: &ZKP, y1: &BigUint, y2: &BigUint, t: &BigUint) -> (BigUint, BigUint) {
+    (
+        ZKP::exponentiate(y1, t, &zkp.p),
+        ZKP::exponentiate(y2, t, &zkp.p),
+    )
+}
+
+// symmetric key shared with the server through its public key y1_server:
+// client U = g ** u, K = y1_server ** u ; server K = U ** x_server
+pub struct SealKey([u8; 32]);
+
+impl SealKey {
+    fn from_shared(u_public: &BigUint, shared: &BigUint) -> SealKey {
+        let mut transcript = Transcript::new(b"zkp-chaum-pedersen/seal-key");
+        transcript.append_biguint(b"U", u_public);
+        transcript.append_biguint(b"K", shared);
+        SealKey(transcript.finalize())
+    }
+
+    // returns (U, key)
+    pub fn to_server(zkp: &ZKP, server: &PublicKey) -> (BigUint, SealKey) {
+        let mut u = ZKP::generate_random_number_below(&zkp.q);
+        let u_public = ZKP::exponentiate(&zkp.g, &u, &zkp.p);
+        let mut shared = ZKP::exponentiate(&server.y1, &u, &zkp.p);
+        let key = SealKey::from_shared(&u_public, &shared);
+        wipe_biguint(&mut u);
+        wipe_biguint(&mut shared);
+        (u_public, key)
+    }
+
+    // None unless U is a non-trivial element of the order-q subgroup
+    pub fn from_client(zkp: &ZKP, x_server: &BigUint, u_public: &BigUint) -> Option<SealKey> {
+        let one = BigUint::from(1u32);
+        if *u_public <= one
+            || *u_public >= zkp.p
+            || ZKP::exponentiate(u_public, &zkp.q, &zkp.p) != one
+        {
+            return None;
+        }
+        let mut shared = ZKP::exponentiate(u_public, x_server, &zkp.p);
+        let key = SealKey::from_shared(u_public, &shared);
+        wipe_biguint(&mut shared);
+        Some(key)
+    }
+
+    fn mask_block(&self, label: &[u8], counter: u64) -> [u8; 32] {
+        let mut transcript = Transcript::new(b"zkp-chaum-pedersen/seal");
+        transcript.append(b"key", &self.0);
+        transcript.append(b"label", label);
+        transcript.append(b"counter", &counter.to_be_bytes());
+        transcript.finalize()
+    }
+
+    // data XOR H(key, label, 0) || H(key, label, 1) || ..., seals and opens alike.
+    // every label is used for one message per key
+    pub fn xor(&self, label: &[u8], data: &[u8]) -> Vec<u8> {
+        data.chunks(32)
+            .enumerate()
+            .flat_map(|(i, chunk)| {
+                let block = self.mask_block(label, i as u64);
+                chunk
+                    .iter()
+                    .zip(block)
+                    .map(|(byte, mask)| byte ^ mask)
+                    .collect::<Vec<u8>>()
+            })
+            .collect()
+    }
+
+    // t + H(key, "t") mod q
+    pub fn seal_scalar(&self, q: &BigUint, t: &BigUint) -> BigUint {
+        (t + self.scalar_mask(q)) % q
+    }
+
+    // sealed - H(key, "t") mod q
+    pub fn open_scalar(&self, q: &BigUint, sealed: &BigUint) -> BigUint {
+        (sealed % q + q - self.scalar_mask(q)) % q
+    }
+
+    fn scalar_mask(&self, q: &BigUint) -> BigUint {
+        let mut transcript = Transcript::new(b"zkp-chaum-pedersen/seal-scalar");
+        transcript.append(b"key", &self.0);
+        transcript.challenge(q)
+    }
+}
+
+impl Drop for SealKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Debug for SealKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SealKey(<redacted>)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::KeyPair;
+
+    #[test]
+    fn test_blinded_proof_verifies_only_on_blinded_key() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let y1 = ZKP::exponentiate(&zkp.g, &x, &zkp.p);
+        let y2 = ZKP::exponentiate(&zkp.h, &x, &zkp.p);
+        let t = ZKP::generate_random_number_below(&zkp.q);
+
+        let bases = blinded_bases(&zkp, &t);
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        let r1 = ZKP::exponentiate(&bases.g, &k, &zkp.p);
+        let r2 = ZKP::exponentiate(&bases.h, &k, &zkp.p);
+        let c = ZKP::generate_random_number_below(&zkp.q);
+        let s = zkp.solve(&k, &c, &x);
+
+        let (blinded_y1, blinded_y2) = blinded_key(&zkp, &y1, &y2, &t);
+        assert!(bases.verify(&r1, &r2, &blinded_y1, &blinded_y2, &c, &s));
+        // an observer who knows (y1, y2) but not t can't check the transcript
+        assert!(!zkp.verify(&r1, &r2, &y1, &y2, &c, &s));
+    }
+
+    #[test]
+    fn test_seal_round_trip() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+        let server = KeyPair::generate(&zkp);
+
+        let (u_public, client_key) = SealKey::to_server(&zkp, &server.public);
+        let server_key = SealKey::from_client(&zkp, server.x.expose(), &u_public).unwrap();
+
+        let sealed = client_key.xor(b"user", "a user name longer than one block".as_bytes());
+        assert_ne!(sealed, b"a user name longer than one block");
+        assert_eq!(
+            server_key.xor(b"user", &sealed),
+            b"a user name longer than one block"
+        );
+
+        let t = ZKP::generate_random_number_below(&zkp.q);
+        let sealed_t = client_key.seal_scalar(&zkp.q, &t);
+        assert_eq!(server_key.open_scalar(&zkp.q, &sealed_t), t);
+
+        assert!(SealKey::from_client(&zkp, server.x.expose(), &BigUint::from(1u32)).is_none());
+    }
+}
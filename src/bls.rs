@@ -0,0 +1,253 @@
+use crate::group::ChaumPedersenGroup;
+use crate::transcript::Transcript;
+use crate::{secret, Proof, ZKP};
+use bls12_381::{G1Affine, G1Projective, Scalar};
+use num_bigint::BigUint;
+use sha2::{Digest, Sha512};
+
+// Chaum-Pedersen in G1 of BLS12-381, the curve of BLS signatures, so a
+// project that already signs there proves discrete log equality on the same
+// points instead of bringing a second group. written multiplicatively like
+// the rest of the crate, g ** e is the point e * G. elements travel as the
+// big-endian number of their 48-byte compressed encoding (the one the
+// signature libraries use) and scalars as numbers below r
+
+// h = the first point SHA-512(H_SEED || counter) maps to, so nobody knows
+// log_g h
+pub const H_SEED: &[u8] = b"zkp-chaum-pedersen/bls12-381/g1/h";
+
+#[derive(Debug, Clone)]
+pub struct Bls12G1Group {
+    pub g: G1Projective,
+    pub h: G1Projective,
+}
+
+impl Default for Bls12G1Group {
+    fn default() -> Self {
+        Bls12G1Group {
+            g: G1Projective::generator(),
+            h: hash_to_g1(H_SEED),
+        }
+    }
+}
+
+// try-and-increment: the digest as the x coordinate of a compressed point
+// until one is on the curve, then into the prime order subgroup
+fn hash_to_g1(seed: &[u8]) -> G1Projective {
+    let mut counter = 0u32;
+    loop {
+        let digest = Sha512::new()
+            .chain_update(seed)
+            .chain_update(counter.to_be_bytes())
+            .finalize();
+        let mut bytes = [0u8; 48];
+        bytes.copy_from_slice(&digest[..48]);
+        // compressed, not the point at infinity, the sign bit from the digest
+        bytes[0] = (bytes[0] & 0x3f) | 0x80;
+        let point = Option::<G1Affine>::from(G1Affine::from_compressed_unchecked(&bytes))
+            .map(|point| G1Projective::from(point).clear_cofactor());
+        if let Some(point) = point.filter(|point| !bool::from(point.is_identity())) {
+            return point;
+        }
+        counter += 1;
+    }
+}
+
+impl Bls12G1Group {
+    // bases of the caller's choosing, e.g. the generator its signatures use
+    // and a point hashed from its own domain. None for the identity or g = h,
+    // where a proof says nothing
+    pub fn new(g: G1Projective, h: G1Projective) -> Option<Self> {
+        if bool::from(g.is_identity()) || bool::from(h.is_identity()) || g == h {
+            return None;
+        }
+        Some(Bls12G1Group { g, h })
+    }
+
+    // r = 0x73eda753299d7d483339d80809a1d80553bda402fffe5bfeffffffff00000001,
+    // the order of G1
+    pub fn order() -> BigUint {
+        BigUint::parse_bytes(
+            b"73eda753299d7d483339d80809a1d80553bda402fffe5bfeffffffff00000001",
+            16,
+        )
+        .expect("the order is hex")
+    }
+
+    pub fn element(point: &G1Projective) -> BigUint {
+        BigUint::from_bytes_be(&G1Affine::from(point).to_compressed())
+    }
+
+    // None for a number that isn't the encoding of a point of G1
+    pub fn point(element: &BigUint) -> Option<G1Projective> {
+        let bytes: [u8; 48] = element.to_bytes_be().try_into().ok()?;
+        Option::<G1Affine>::from(G1Affine::from_compressed(&bytes)).map(G1Projective::from)
+    }
+
+    // None at or above r, where two numbers would be one scalar
+    fn scalar(n: &BigUint) -> Option<Scalar> {
+        let mut bytes = [0u8; 32];
+        let encoded = n.to_bytes_le();
+        bytes.get_mut(..encoded.len())?.copy_from_slice(&encoded);
+        Scalar::from_bytes(&bytes).into()
+    }
+
+    // any number, reduced mod r
+    fn reduced(n: &BigUint) -> Scalar {
+        let reduced = n % Self::order();
+        Self::scalar(&reduced).expect("reduced below r")
+    }
+
+    // c = H(g, h, y1, y2, r1, r2, context) mod r
+    fn challenge(
+        &self,
+        (y1, y2): (&BigUint, &BigUint),
+        (r1, r2): (&BigUint, &BigUint),
+        context: &[u8],
+    ) -> BigUint {
+        let mut transcript = Transcript::new(b"zkp-chaum-pedersen/bls12-381/g1");
+        transcript.append_biguint(b"g", &Self::element(&self.g));
+        transcript.append_biguint(b"h", &Self::element(&self.h));
+        transcript.append_biguint(b"y1", y1);
+        transcript.append_biguint(b"y2", y2);
+        transcript.append_biguint(b"r1", r1);
+        transcript.append_biguint(b"r2", r2);
+        transcript.append(b"context", context);
+        transcript.challenge(&Self::order())
+    }
+
+    // the non-interactive proof of ZKP::prove_non_interactive, in G1: a
+    // crate::Proof whose c and s are scalars below r
+    pub fn prove_non_interactive(&self, x: &BigUint, context: &[u8]) -> Proof {
+        let (y1, y2) = self.commit(x);
+        let mut k = self.random_nonce();
+        let (r1, r2) = self.commit(&k);
+        let c = self.challenge((&y1, &y2), (&r1, &r2), context);
+        let s = self.respond(&k, &c, x);
+        secret::wipe_biguint(&mut k);
+        Proof { c, s }
+    }
+
+    // r1 = g ** s * y1 ** c and r2 = h ** s * y2 ** c rebuilt from the proof,
+    // under the checks of ChaumPedersenGroup::check
+    pub fn verify_non_interactive(
+        &self,
+        y1: &BigUint,
+        y2: &BigUint,
+        proof: &Proof,
+        context: &[u8],
+    ) -> bool {
+        let (Some(y1_point), Some(y2_point)) = (Self::point(y1), Self::point(y2)) else {
+            return false;
+        };
+        let (Some(c), Some(s)) = (Self::scalar(&proof.c), Self::scalar(&proof.s)) else {
+            return false;
+        };
+        if bool::from(y1_point.is_identity()) || bool::from(y2_point.is_identity()) {
+            return false;
+        }
+        let r1 = Self::element(&(self.g * s + y1_point * c));
+        let r2 = Self::element(&(self.h * s + y2_point * c));
+        self.challenge((y1, y2), (&r1, &r2), context) == proof.c
+    }
+}
+
+impl ChaumPedersenGroup for Bls12G1Group {
+    fn commit(&self, e: &BigUint) -> (BigUint, BigUint) {
+        let e = Self::reduced(e);
+        (Self::element(&(self.g * e)), Self::element(&(self.h * e)))
+    }
+
+    // s = k - c * x mod r
+    fn respond(&self, k: &BigUint, c: &BigUint, x: &BigUint) -> BigUint {
+        let s = Self::reduced(k) - Self::reduced(c) * Self::reduced(x);
+        BigUint::from_bytes_le(&s.to_bytes())
+    }
+
+    // r1 = g ** s * y1 ** c ; r2 = h ** s * y2 ** c
+    fn check(
+        &self,
+        r1: &BigUint,
+        r2: &BigUint,
+        y1: &BigUint,
+        y2: &BigUint,
+        c: &BigUint,
+        s: &BigUint,
+    ) -> bool {
+        let points = (
+            Self::point(r1),
+            Self::point(r2),
+            Self::point(y1),
+            Self::point(y2),
+        );
+        let (Some(r1), Some(r2), Some(y1), Some(y2)) = points else {
+            return false;
+        };
+        let (Some(c), Some(s)) = (Self::scalar(c), Self::scalar(s)) else {
+            return false;
+        };
+        // the identity as y makes y ** c vanish, which would let anyone answer
+        if bool::from(y1.is_identity()) || bool::from(y2.is_identity()) {
+            return false;
+        }
+        r1 == self.g * s + y1 * c && r2 == self.h * s + y2 * c
+    }
+
+    fn random_nonce(&self) -> BigUint {
+        ZKP::generate_random_number_below(&Self::order())
+    }
+
+    fn random_challenge(&self) -> BigUint {
+        ZKP::generate_random_number_below(&Self::order())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::tests::run_round;
+
+    #[test]
+    fn test_bls12_381_round() {
+        let group = Bls12G1Group::default();
+        assert_ne!(group.g, group.h);
+        assert!(bool::from(G1Affine::from(group.h).is_torsion_free()));
+        let x = group.random_nonce();
+        assert!(run_round(&group, &x, &x));
+        assert!(!run_round(&group, &x, &(&x + 1u32)));
+
+        // the identity as the public key can't be answered for
+        let zero = BigUint::from(0u32);
+        let (r1, r2) = group.commit(&zero);
+        let c = group.random_challenge();
+        assert!(!group.check(&r1, &r2, &r1, &r2, &c, &zero));
+
+        // nor a scalar at or above r, or a number that isn't a point
+        let (y1, y2) = group.commit(&x);
+        let k = group.random_nonce();
+        let (r1, r2) = group.commit(&k);
+        let s = group.respond(&k, &c, &x);
+        assert!(group.check(&r1, &r2, &y1, &y2, &c, &s));
+        let unreduced = &s + Bls12G1Group::order();
+        assert!(!group.check(&r1, &r2, &y1, &y2, &c, &unreduced));
+        assert!(Bls12G1Group::point(&(BigUint::from(1u32) << 383u32)).is_none());
+    }
+
+    #[test]
+    fn test_non_interactive_proof_on_the_callers_bases() {
+        // the generator of the caller's signatures and a base of its own
+        let h = hash_to_g1(b"my-protocol/h");
+        let group = Bls12G1Group::new(G1Projective::generator(), h).unwrap();
+        let x = group.random_nonce();
+        let (y1, y2) = group.commit(&x);
+        let proof = group.prove_non_interactive(&x, b"nonce");
+        assert!(group.verify_non_interactive(&y1, &y2, &proof, b"nonce"));
+        assert!(!group.verify_non_interactive(&y1, &y2, &proof, b"other"));
+        assert!(!Bls12G1Group::default().verify_non_interactive(&y1, &y2, &proof, b"nonce"));
+
+        let other = group.commit(&(&x + 1u32));
+        assert!(!group.verify_non_interactive(&y1, &other.1, &proof, b"nonce"));
+        assert!(Bls12G1Group::new(h, h).is_none());
+        assert!(Bls12G1Group::new(G1Projective::identity(), h).is_none());
+    }
+}
@@ -0,0 +1,364 @@
+use crate::grants::Grant;
+use crate::keys::{KeyPair, PublicKey};
+use crate::secret::Redacted;
+use crate::session_key::SessionKey;
+use crate::snapshot::UserRecord;
+use crate::state::{ClientInfo, Session, SessionStore, UserInfo, UserStore};
+use crate::transcript::Transcript;
+use crate::{Proof, ZKP};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// registrations and live sessions of one server, for backups and for moving
+// them to another instance (blue/green cutovers). signed with the exporting
+// server's key: the signature is a non-interactive proof of knowledge of x
+// over the digest of everything else in the bundle. session keys travel in
+// the clear, a bundle is as secret as the server state it came from
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bundle {
+    pub version: u32,
+    pub exported_at: u64,
+    pub users: Vec<UserRecord>,
+    pub sessions: Vec<SessionRecord>,
+    #[serde(default)]
+    pub signature: Option<BundleSignature>,
+}
+
+// times are unix seconds, keys big-endian hex, empty when there is none
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub session_id: String,
+    pub user: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub realm: String,
+    pub key: String,
+    pub remote_addr: String,
+    pub user_agent: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub device_id: String,
+    // left out when empty, so bundles from before grants still verify
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub claims: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleSignature {
+    // public key of the exporting server
+    pub y1: String,
+    pub y2: String,
+    pub c: String,
+    pub s: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleError {
+    UnsupportedVersion(u32),
+    Unsigned,
+    // signed by a key the importing server doesn't trust
+    UntrustedSigner,
+    BadSignature,
+    InvalidRecord(String),
+}
+
+impl Display for BundleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BundleError::UnsupportedVersion(version) => write!(
+                f,
+                "bundle version {} is not supported, only {}",
+                version, VERSION
+            ),
+            BundleError::Unsigned => write!(f, "bundle is not signed"),
+            BundleError::UntrustedSigner => write!(f, "bundle is signed by an untrusted key"),
+            BundleError::BadSignature => write!(f, "bundle signature does not verify"),
+            BundleError::InvalidRecord(reason) => write!(f, "invalid record: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn from_unix_seconds(seconds: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+fn from_hex(value: &str) -> Result<Vec<u8>, BundleError> {
+    hex::decode(value).map_err(|e| BundleError::InvalidRecord(e.to_string()))
+}
+
+// the session id and the session key are shown by their fingerprints
+impl std::fmt::Debug for SessionRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionRecord")
+            .field("session_id", &Redacted(&self.session_id))
+            .field("user", &self.user)
+            .field("created_at", &self.created_at)
+            .field("expires_at", &self.expires_at)
+            .field("realm", &self.realm)
+            .field("key", &Redacted(&self.key))
+            .field("remote_addr", &self.remote_addr)
+            .field("user_agent", &self.user_agent)
+            .field("device_id", &self.device_id)
+            .field("scopes", &self.scopes)
+            .field("claims", &self.claims)
+            .finish()
+    }
+}
+
+impl SessionRecord {
+    fn from_session(session_id: &str, session: &Session) -> Self {
+        SessionRecord {
+            session_id: session_id.to_string(),
+            user: session.user_name.clone(),
+            created_at: unix_seconds(session.created_at),
+            expires_at: unix_seconds(session.expires_at),
+            realm: session.realm.clone(),
+            key: session
+                .key
+                .as_ref()
+                .map(|key| hex::encode(key.to_bytes()))
+                .unwrap_or_default(),
+            remote_addr: session
+                .client
+                .remote_addr
+                .map(|addr| addr.to_string())
+                .unwrap_or_default(),
+            user_agent: session.client.user_agent.clone(),
+            device_id: session.client.device_id.clone(),
+            scopes: session.grant.scopes.clone(),
+            claims: session.grant.claims.clone(),
+        }
+    }
+
+    fn to_session(&self) -> Result<Session, BundleError> {
+        let key = match self.key.as_str() {
+            "" => None,
+            key => {
+                let key: [u8; 32] = from_hex(key)?.try_into().map_err(|_| {
+                    BundleError::InvalidRecord(format!(
+                        "session key of {} is not 32 bytes",
+                        self.user
+                    ))
+                })?;
+                Some(SessionKey::from_bytes(key))
+            }
+        };
+        let remote_addr = match self.remote_addr.as_str() {
+            "" => None,
+            addr => Some(
+                addr.parse()
+                    .map_err(|_| BundleError::InvalidRecord(format!("address {}", addr)))?,
+            ),
+        };
+        Ok(Session {
+            user_name: self.user.clone(),
+            created_at: from_unix_seconds(self.created_at),
+            expires_at: from_unix_seconds(self.expires_at),
+            key,
+            realm: self.realm.clone(),
+            client: ClientInfo {
+                remote_addr,
+                user_agent: self.user_agent.clone(),
+                device_id: self.device_id.clone(),
+            },
+            grant: Grant {
+                scopes: self.scopes.clone(),
+                claims: self.claims.clone(),
+            },
+            // a session moved to another instance starts its tickets over
+            tickets: 0,
+        })
+    }
+}
+
+impl Bundle {
+    // the sessions live at now
+    pub fn export(users: &UserStore, sessions: &SessionStore, now: SystemTime) -> Self {
+        Bundle {
+            version: VERSION,
+            exported_at: unix_seconds(now),
+            users: users.all().iter().map(UserRecord::from_user).collect(),
+            sessions: sessions.export(now, SessionRecord::from_session),
+            signature: None,
+        }
+    }
+
+    // H(bundle without its signature)
+    fn digest(&self) -> [u8; 32] {
+        let unsigned = Bundle {
+            signature: None,
+            ..self.clone()
+        };
+        let mut transcript = Transcript::new(b"zkp-chaum-pedersen/bundle");
+        transcript.append(
+            b"bundle",
+            &serde_json::to_vec(&unsigned).expect("bundle serializes"),
+        );
+        transcript.finalize()
+    }
+
+    pub fn sign(&mut self, zkp: &ZKP, key: &KeyPair) {
+        let proof = zkp.prove_non_interactive(key.x.expose(), &self.digest());
+        self.signature = Some(BundleSignature {
+            y1: hex::encode(key.public.y1.to_bytes_be()),
+            y2: hex::encode(key.public.y2.to_bytes_be()),
+            c: hex::encode(proof.c.to_bytes_be()),
+            s: hex::encode(proof.s.to_bytes_be()),
+        });
+    }
+
+    // the signer must be one of the trusted keys
+    pub fn verify(&self, zkp: &ZKP, trusted: &[&PublicKey]) -> Result<(), BundleError> {
+        if self.version != VERSION {
+            return Err(BundleError::UnsupportedVersion(self.version));
+        }
+        let signature = self.signature.as_ref().ok_or(BundleError::Unsigned)?;
+        let signer = PublicKey {
+            y1: BigUint::from_bytes_be(&from_hex(&signature.y1)?),
+            y2: BigUint::from_bytes_be(&from_hex(&signature.y2)?),
+        };
+        if !trusted.iter().any(|key| **key == signer) {
+            return Err(BundleError::UntrustedSigner);
+        }
+        let proof = Proof {
+            c: BigUint::from_bytes_be(&from_hex(&signature.c)?),
+            s: BigUint::from_bytes_be(&from_hex(&signature.s)?),
+        };
+        if !zkp.verify_non_interactive(&signer.y1, &signer.y2, &proof, &self.digest()) {
+            return Err(BundleError::BadSignature);
+        }
+        Ok(())
+    }
+
+    // every record is checked before the stores see any of them. returns the
+    // number of users and sessions imported, sessions expired by now are left
+    // out
+    pub fn import(
+        &self,
+        users: &UserStore,
+        sessions: &SessionStore,
+        now: SystemTime,
+    ) -> Result<(usize, usize), BundleError> {
+        let imported_users: Vec<UserInfo> = self
+            .users
+            .iter()
+            .map(|record| {
+                record
+                    .to_user()
+                    .map_err(|e| BundleError::InvalidRecord(e.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        let mut imported_sessions = Vec::new();
+        for record in &self.sessions {
+            let session = record.to_session()?;
+            if session.expires_at > now {
+                imported_sessions.push((record.session_id.clone(), session));
+            }
+        }
+
+        let counts = (imported_users.len(), imported_sessions.len());
+        for user in imported_users {
+            users.restore(user);
+        }
+        for (session_id, session) in imported_sessions {
+            sessions.insert(session_id, session);
+        }
+        Ok(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groups;
+
+    #[test]
+    fn test_signed_bundle_round_trip() {
+        let zkp = groups::default_group();
+        let (users, sessions) = (UserStore::default(), SessionStore::default());
+        users.insert(UserInfo {
+            user_name: "alice".to_string(),
+            y1: BigUint::from(2u32),
+            y2: BigUint::from(3u32),
+            salt: Vec::new(),
+            group: groups::DEFAULT.to_string(),
+            kdf: Default::default(),
+            ..UserInfo::default()
+        });
+        let now = SystemTime::now();
+        let session = |expires_at| Session {
+            user_name: "alice".to_string(),
+            created_at: now,
+            expires_at,
+            key: Some(SessionKey::from_bytes([7; 32])),
+            realm: "staff".to_string(),
+            client: ClientInfo::default(),
+            grant: Grant {
+                scopes: vec!["read".to_string()],
+                claims: BTreeMap::from([("team".to_string(), "ops".to_string())]),
+            },
+            tickets: 0,
+        };
+        sessions.insert("live".to_string(), session(now + Duration::from_secs(60)));
+        sessions.insert("expired".to_string(), session(now - Duration::from_secs(1)));
+
+        let key = KeyPair::generate(&zkp);
+        let mut bundle = Bundle::export(&users, &sessions, now);
+        assert_eq!(bundle.sessions.len(), 1);
+        assert_eq!(
+            bundle.verify(&zkp, &[&key.public]),
+            Err(BundleError::Unsigned)
+        );
+        bundle.sign(&zkp, &key);
+        bundle.verify(&zkp, &[&key.public]).unwrap();
+
+        // through JSON to another instance
+        let json = serde_json::to_string(&bundle).unwrap();
+        let bundle: Bundle = serde_json::from_str(&json).unwrap();
+        let (other_users, other_sessions) = (UserStore::default(), SessionStore::default());
+        assert_eq!(
+            bundle.import(&other_users, &other_sessions, now).unwrap(),
+            (1, 1)
+        );
+        assert_eq!(other_users.get("alice").unwrap().y1, BigUint::from(2u32));
+        other_sessions
+            .with_session("live", now, |session| {
+                assert_eq!(session.realm, "staff");
+                assert_eq!(session.grant.scopes, ["read"]);
+                assert!(session
+                    .key
+                    .as_ref()
+                    .unwrap()
+                    .verify_mac(b"m", &SessionKey::from_bytes([7; 32]).mac(b"m")));
+                Ok(())
+            })
+            .unwrap();
+
+        // another signer, or a changed bundle
+        let other = KeyPair::generate(&zkp);
+        assert_eq!(
+            bundle.verify(&zkp, &[&other.public]),
+            Err(BundleError::UntrustedSigner)
+        );
+        let mut tampered = bundle.clone();
+        tampered.users[0].y1 = "04".to_string();
+        assert_eq!(
+            tampered.verify(&zkp, &[&key.public]),
+            Err(BundleError::BadSignature)
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use crate::groups;
+
+// what this build of the crate can do, read at runtime, so an orchestration
+// layer decides by policy (no group under 112 bits of security, no proving
+// on a verifier-only gateway) rather than by what it was compiled against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    // cargo features the crate was built with
+    pub features: Vec<&'static str>,
+    // groups proofs can be made or verified in: "modp" always, the others
+    // with their features
+    pub backends: Vec<&'static str>,
+    // false in a verifier-only build, which can only check proofs
+    pub proving: bool,
+    pub groups: Vec<GroupCapability>,
+}
+
+// a named group, see groups::NAMES
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupCapability {
+    pub name: &'static str,
+    pub p_bits: u64,
+    pub q_bits: u64,
+    // ZKP::security_bits
+    pub security_bits: u64,
+    // the largest Proof::size_bytes in the group, c and s below q
+    pub max_proof_bytes: usize,
+}
+
+pub fn capabilities() -> Capabilities {
+    let features = [
+        ("proto", cfg!(feature = "proto")),
+        ("prover", cfg!(feature = "prover")),
+        ("server", cfg!(feature = "server")),
+        ("verifier-only", cfg!(feature = "verifier-only")),
+        ("unknown-order", cfg!(feature = "unknown-order")),
+        ("ristretto", cfg!(feature = "ristretto")),
+        ("bls12-381", cfg!(feature = "bls12-381")),
+        ("profiling", cfg!(feature = "profiling")),
+    ];
+    let backends = [
+        ("modp", true),
+        ("unknown-order", cfg!(feature = "unknown-order")),
+        ("ristretto", cfg!(feature = "ristretto")),
+        ("bls12-381", cfg!(feature = "bls12-381")),
+    ];
+    let enabled = |list: &[(&'static str, bool)]| {
+        list.iter()
+            .filter(|(_, on)| *on)
+            .map(|(name, _)| *name)
+            .collect()
+    };
+    Capabilities {
+        features: enabled(&features),
+        backends: enabled(&backends),
+        proving: cfg!(feature = "prover"),
+        groups: groups::NAMES
+            .iter()
+            .filter_map(|name| {
+                let zkp = groups::by_name(name)?;
+                Some(GroupCapability {
+                    name,
+                    p_bits: zkp.p.bits(),
+                    q_bits: zkp.q.bits(),
+                    security_bits: zkp.security_bits(),
+                    max_proof_bytes: 2 * zkp.scalar_len(),
+                })
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_of_this_build() {
+        let capabilities = capabilities();
+        assert!(capabilities.backends.contains(&"modp"));
+        assert_eq!(
+            capabilities.features.contains(&"prover"),
+            capabilities.proving
+        );
+        assert_eq!(capabilities.groups.len(), groups::NAMES.len());
+
+        // the policy of refusing groups under 2048 bits
+        let strong: Vec<_> = capabilities
+            .groups
+            .iter()
+            .filter(|group| group.p_bits >= 2048)
+            .map(|group| group.name)
+            .collect();
+        assert_eq!(strong, [groups::RFC5114_2048_256]);
+        let rfc5114_2048 = &capabilities.groups[1];
+        assert_eq!(rfc5114_2048.security_bits, 112);
+        assert_eq!(rfc5114_2048.max_proof_bytes, 64);
+    }
+}
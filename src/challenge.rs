@@ -0,0 +1,139 @@
+use crate::ZKP;
+use num_bigint::BigUint;
+use std::fmt::Debug;
+use zeroize::Zeroizing;
+
+// where the server's challenge c comes from. the server asks its source once
+// per challenge request, after the auth_id is drawn, so a source can bind c to
+// the attempt. whatever the source, c must be unpredictable to the prover
+// before it has sent (r1, r2) or its nonce commitment
+pub trait ChallengeSource: Debug + Send + Sync {
+    fn challenge(&self, zkp: &ZKP, request: &ChallengeRequest<'_>) -> BigUint;
+}
+
+// what the server knows when it picks c
+#[derive(Debug, Clone, Copy)]
+pub struct ChallengeRequest<'a> {
+    // the deployment's application id, empty without one
+    pub application: &'a [u8],
+    pub user: &'a str,
+    pub auth_id: &'a str,
+    // empty for the nonce-commitment and non-interactive variants
+    pub r1: &'a [u8],
+    pub r2: &'a [u8],
+    pub nonce_commitment: &'a [u8],
+}
+
+// c drawn uniformly below q, the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UniformRandom;
+
+impl ChallengeSource for UniformRandom {
+    fn challenge(&self, zkp: &ZKP, _request: &ChallengeRequest<'_>) -> BigUint {
+        ZKP::generate_random_number_below(&zkp.q)
+    }
+}
+
+// c = H(key, g, h, p, q, application, user, auth_id, r1, r2, nonce commitment) mod q
+// bound to everything the prover sent and to the auth_id, so the challenge of
+// an attempt can be recomputed (and audited) by whoever holds the key. the
+// key keeps it unpredictable to the prover
+pub struct HashBound {
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl HashBound {
+    pub fn new(key: [u8; 32]) -> Self {
+        HashBound {
+            key: Zeroizing::new(key),
+        }
+    }
+
+    pub fn generate() -> Self {
+        let mut key = [0u8; 32];
+        crate::rng::with_rng(|rng| rng.fill_bytes(&mut key));
+        HashBound::new(key)
+    }
+}
+
+impl Debug for HashBound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HashBound").finish_non_exhaustive()
+    }
+}
+
+impl ChallengeSource for HashBound {
+    fn challenge(&self, zkp: &ZKP, request: &ChallengeRequest<'_>) -> BigUint {
+        let mut transcript = zkp.application_transcript(
+            b"zkp-chaum-pedersen/hash-bound-challenge",
+            request.application,
+        );
+        transcript.append(b"key", &self.key[..]);
+        transcript.append(b"user", request.user.as_bytes());
+        transcript.append(b"auth_id", request.auth_id.as_bytes());
+        transcript.append(b"r1", request.r1);
+        transcript.append(b"r2", request.r2);
+        transcript.append(b"nonce_commitment", request.nonce_commitment);
+        transcript.challenge(&zkp.q)
+    }
+}
+
+// the same c for every login, for tests that need to know it in advance.
+// a prover that knows c can answer without knowing x, never serve this
+#[derive(Debug, Clone)]
+pub struct Fixed(pub BigUint);
+
+impl ChallengeSource for Fixed {
+    fn challenge(&self, zkp: &ZKP, _request: &ChallengeRequest<'_>) -> BigUint {
+        &self.0 % &zkp.q
+    }
+}
+
+// HSMs and other external sources implement ChallengeSource themselves
+impl Default for Box<dyn ChallengeSource> {
+    fn default() -> Self {
+        Box::new(UniformRandom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request<'a>(auth_id: &'a str, r1: &'a [u8]) -> ChallengeRequest<'a> {
+        ChallengeRequest {
+            application: &[],
+            user: "alice",
+            auth_id,
+            r1,
+            r2: &[2],
+            nonce_commitment: &[],
+        }
+    }
+
+    #[test]
+    fn test_hash_bound_challenges() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+        let source = HashBound::new([7; 32]);
+
+        let c = source.challenge(&zkp, &request("first", &[1]));
+        assert!(c < zkp.q);
+        // recomputable from the attempt
+        assert_eq!(c, source.challenge(&zkp, &request("first", &[1])));
+        // a new attempt with the same commitments gets a new challenge
+        assert_ne!(c, source.challenge(&zkp, &request("second", &[1])));
+        assert_ne!(c, source.challenge(&zkp, &request("first", &[3])));
+        // and another key another one
+        let other = HashBound::new([8; 32]);
+        assert_ne!(c, other.challenge(&zkp, &request("first", &[1])));
+        // and another deployment another one
+        let elsewhere = ChallengeRequest {
+            application: b"other-app",
+            ..request("first", &[1])
+        };
+        assert_ne!(c, source.challenge(&zkp, &elsewhere));
+
+        assert_eq!(format!("{:?}", source), "HashBound { .. }");
+    }
+}
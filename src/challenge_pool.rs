@@ -0,0 +1,226 @@
+use crate::challenge::{ChallengeRequest, ChallengeSource};
+use crate::state::IdLength;
+use crate::ZKP;
+use num_bigint::BigUint;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+// challenges and auth_ids drawn ahead of time by a background task, so a
+// burst of CreateAuthenticationChallenge calls takes them off a queue instead
+// of all drawing from the generator at once. a challenge waits in memory
+// until it's handed out, as unpredictable to the prover as one drawn on the
+// spot. once a queue is down to half, the task tops it up again; while it's
+// empty the call draws its own and counts a miss
+#[derive(Debug)]
+pub struct ChallengePool {
+    depth: usize,
+    id_length: IdLength,
+    // one queue per group, c is drawn below its q
+    challenges: Vec<Queue<BigUint>>,
+    auth_ids: Queue<String>,
+    low: Notify,
+}
+
+#[derive(Debug)]
+struct Queue<T> {
+    // the group's name, or "" for the auth_ids
+    group: String,
+    q: BigUint,
+    items: Mutex<VecDeque<T>>,
+    // calls that found the queue empty
+    misses: AtomicU64,
+}
+
+impl<T> Queue<T> {
+    fn new(group: &str, q: BigUint) -> Self {
+        Queue {
+            group: group.to_string(),
+            q,
+            items: Mutex::new(VecDeque::new()),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+
+    // the next item and how many are left behind it, None when empty
+    fn take(&self) -> (Option<T>, usize) {
+        let mut items = self.items.lock().unwrap();
+        let item = items.pop_front();
+        if item.is_none() {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        (item, items.len())
+    }
+
+    // draws outside the lock, so takers don't wait on the generator
+    fn fill(&self, depth: usize, draw: impl Fn(&BigUint) -> T) -> usize {
+        let missing = depth.saturating_sub(self.len());
+        let drawn: Vec<T> = (0..missing).map(|_| draw(&self.q)).collect();
+        let mut items = self.items.lock().unwrap();
+        let room = depth.saturating_sub(items.len());
+        items.extend(drawn.into_iter().take(room));
+        missing.min(room)
+    }
+}
+
+impl ChallengePool {
+    // up to `depth` challenges for each of `groups` (name and q) and up to
+    // `depth` auth_ids of id_length, empty until the first fill
+    pub fn new<'a>(
+        depth: usize,
+        id_length: IdLength,
+        groups: impl IntoIterator<Item = (&'a str, &'a ZKP)>,
+    ) -> Self {
+        ChallengePool {
+            depth,
+            id_length,
+            challenges: groups
+                .into_iter()
+                .map(|(name, zkp)| Queue::new(name, zkp.q.clone()))
+                .collect(),
+            auth_ids: Queue::new("", BigUint::default()),
+            low: Notify::new(),
+        }
+    }
+
+    fn taken(&self, left: usize) {
+        if left < self.depth / 2 {
+            self.low.notify_one();
+        }
+    }
+
+    // c uniformly below q, drawn on the spot for a group the pool doesn't hold
+    pub fn challenge(&self, q: &BigUint) -> BigUint {
+        let Some(queue) = self.challenges.iter().find(|queue| &queue.q == q) else {
+            return ZKP::generate_random_number_below(q);
+        };
+        let (c, left) = queue.take();
+        self.taken(left);
+        c.unwrap_or_else(|| ZKP::generate_random_number_below(q))
+    }
+
+    pub fn auth_id(&self) -> String {
+        let (auth_id, left) = self.auth_ids.take();
+        self.taken(left);
+        auth_id.unwrap_or_else(|| self.id_length.generate())
+    }
+
+    // tops every queue up to the depth, returns how many were drawn
+    pub fn fill(&self) -> usize {
+        let challenges: usize = self
+            .challenges
+            .iter()
+            .map(|queue| queue.fill(self.depth, ZKP::generate_random_number_below))
+            .sum();
+        challenges
+            + self
+                .auth_ids
+                .fill(self.depth, |_| self.id_length.generate())
+    }
+
+    // fills the pool, then again each time a queue runs low, until the
+    // runtime stops. the drawing runs on the blocking threads
+    pub async fn keep_filled(self: Arc<Self>) {
+        loop {
+            let pool = self.clone();
+            if tokio::task::spawn_blocking(move || pool.fill())
+                .await
+                .is_err()
+            {
+                return;
+            }
+            self.low.notified().await;
+        }
+    }
+
+    // challenges waiting, by group
+    pub fn challenges(&self) -> Vec<(&str, usize)> {
+        self.challenges
+            .iter()
+            .map(|queue| (queue.group.as_str(), queue.len()))
+            .collect()
+    }
+
+    pub fn auth_ids(&self) -> usize {
+        self.auth_ids.len()
+    }
+
+    // the depth of each queue and its misses, in the Prometheus text format
+    pub fn metrics(&self) -> String {
+        let mut out = String::new();
+        out += "# TYPE zkp_challenge_pool_depth gauge\n";
+        for queue in &self.challenges {
+            out += &format!(
+                "zkp_challenge_pool_depth{{kind=\"challenge\",group=\"{}\"}} {}\n",
+                queue.group,
+                queue.len()
+            );
+        }
+        out += &format!(
+            "zkp_challenge_pool_depth{{kind=\"auth_id\"}} {}\n",
+            self.auth_ids.len()
+        );
+        out += "# TYPE zkp_challenge_pool_misses_total counter\n";
+        for queue in &self.challenges {
+            out += &format!(
+                "zkp_challenge_pool_misses_total{{kind=\"challenge\",group=\"{}\"}} {}\n",
+                queue.group,
+                queue.misses.load(Ordering::Relaxed)
+            );
+        }
+        out += &format!(
+            "zkp_challenge_pool_misses_total{{kind=\"auth_id\"}} {}\n",
+            self.auth_ids.misses.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
+
+// UniformRandom, with c taken from a pool
+#[derive(Debug, Clone)]
+pub struct Pooled(pub Arc<ChallengePool>);
+
+impl ChallengeSource for Pooled {
+    fn challenge(&self, zkp: &ZKP, _request: &ChallengeRequest<'_>) -> BigUint {
+        self.0.challenge(&zkp.q)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groups;
+    use crate::state::ID_BYTES;
+
+    #[test]
+    fn test_pool_is_drained_and_refilled() {
+        let zkp = groups::by_name(groups::NAMES[0]).unwrap();
+        let pool = ChallengePool::new(4, IdLength::new(ID_BYTES).unwrap(), [("modp", &zkp)]);
+        assert_eq!(pool.fill(), 8);
+        assert_eq!(pool.fill(), 0);
+        assert_eq!(pool.challenges(), [("modp", 4)]);
+        assert_eq!(pool.auth_ids(), 4);
+
+        let taken: Vec<_> = (0..6).map(|_| pool.challenge(&zkp.q)).collect();
+        assert!(taken.iter().all(|c| c < &zkp.q));
+        assert_ne!(taken[0], taken[1]);
+        let auth_id = pool.auth_id();
+        assert_eq!(auth_id.len(), IdLength::default().generate().len());
+        assert_eq!(pool.challenges(), [("modp", 0)]);
+        assert_eq!(pool.auth_ids(), 3);
+        let metrics = pool.metrics();
+        assert!(metrics.contains("zkp_challenge_pool_depth{kind=\"auth_id\"} 3\n"));
+        assert!(metrics
+            .contains("zkp_challenge_pool_misses_total{kind=\"challenge\",group=\"modp\"} 2\n"));
+
+        // a group the pool doesn't hold is drawn for on the spot
+        let (_, _, p, _) = ZKP::get_constants();
+        assert!(pool.challenge(&p) < p);
+        assert_eq!(pool.fill(), 5);
+    }
+}
@@ -1,9 +1,374 @@
-use std::io::stdin;
-include!("./zkp_auth.rs");
-use auth_client::AuthClient;
-use num_bigint::BigUint;
+use clap::{Parser, Subcommand, ValueEnum};
+use hyper_util::rt::TokioIo;
+use std::future::Future;
+use std::io::{stdin, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tonic::{Request, Status};
+use tracing::{debug, error, info, info_span, warn, Instrument, Level};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use zeroize::Zeroizing;
+use zkp_chaum_pedersen::failover::{Failover, Server};
+use zkp_chaum_pedersen::groups;
+use zkp_chaum_pedersen::keys::{KeyPair, PublicKey};
+use zkp_chaum_pedersen::known_params::{KnownParameters, OnChange, Trust};
+use zkp_chaum_pedersen::login::{
+    answer_challenge, check_known_parameters, check_parameters, create_challenge, issue_tickets,
+    migrate_group, receipt_of, recover_account, redeem_ticket, register_on_device,
+    register_with_recovery, AuthError, ChannelBinding, LoginOptions,
+};
+use zkp_chaum_pedersen::offline::{ChallengeFile, ProofFile};
+use zkp_chaum_pedersen::proof_metadata::ProofMetadata;
+use zkp_chaum_pedersen::proto::*;
+use zkp_chaum_pedersen::proxy::ProxyConfig;
+use zkp_chaum_pedersen::receipt::{Receipt, Receipts};
+use zkp_chaum_pedersen::request_id;
+use zkp_chaum_pedersen::rng;
+use zkp_chaum_pedersen::secret::redact;
+use zkp_chaum_pedersen::session_key::SessionKey;
+use zkp_chaum_pedersen::tickets::Ticket;
+use zkp_chaum_pedersen::tls;
+use zkp_chaum_pedersen::username::UsernamePolicy;
+use zkp_chaum_pedersen::verifier;
 use zkp_chaum_pedersen::ZKP;
 
+#[derive(Parser, Debug)]
+#[command(about = "Chaum-Pedersen ZKP authentication client")]
+struct Args {
+    /// server address; several (repeated or comma separated) are failed over
+    /// between in order, taking the first that passes its gRPC health check
+    #[arg(long, value_delimiter = ',', default_value = "http://127.0.0.1:50051")]
+    server: Vec<String>,
+
+    /// the --server instances share their state, so an answer may go to
+    /// another one than the instance that issued the challenge
+    #[arg(long)]
+    servers_share_state: bool,
+
+    /// interval between HTTP/2 keepalive pings (seconds)
+    #[arg(long, default_value_t = 30)]
+    keepalive_interval: u64,
+
+    /// time to wait for a keepalive ping to be acknowledged (seconds)
+    #[arg(long, default_value_t = 10)]
+    keepalive_timeout: u64,
+
+    /// timeout for connecting and for each individual rpc (seconds)
+    #[arg(long, default_value_t = 10)]
+    timeout: u64,
+
+    /// deadline for the whole operation, covering every rpc (seconds)
+    #[arg(long)]
+    deadline: Option<u64>,
+
+    /// route the connection through a proxy (http://host:port or socks5://host:port),
+    /// defaults to HTTPS_PROXY / ALL_PROXY
+    #[arg(long)]
+    proxy: Option<String>,
+
+    /// public key file of the server (`<key-file>.pub.json`); when set the server
+    /// must prove its identity on every login
+    #[arg(long)]
+    server_public_key: Option<PathBuf>,
+
+    /// connect with TLS, trusting the PEM certificates in this file; logins are
+    /// bound to the TLS connection so they can't be relayed to the server
+    #[arg(long)]
+    tls_ca: Option<PathBuf>,
+
+    /// PEM client certificate chain to present, for a server that requires
+    /// one (`server --tls-client-ca`)
+    #[arg(long, requires_all = ["tls_ca", "tls_client_key"])]
+    tls_client_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-client-cert
+    #[arg(long, requires = "tls_client_cert")]
+    tls_client_key: Option<PathBuf>,
+
+    /// register with a salted verifier (x = KDF(user, password, salt)) instead
+    /// of the raw password, logins pick the salt and KDF parameters up from the server
+    #[arg(long)]
+    salted: bool,
+
+    /// KDF a salted verifier is derived with
+    #[arg(long, value_enum, default_value_t = KdfKind::Pbkdf2, requires = "salted")]
+    kdf: KdfKind,
+
+    /// blind every login with a fresh random factor sealed to the server key, so
+    /// logins of one user can't be linked by a network observer
+    #[arg(long, requires = "server_public_key")]
+    blinded: bool,
+
+    /// send only a hash commitment to (r1, r2) with the challenge request and
+    /// reveal them with the answer, so the server's challenge can't depend on them
+    #[arg(long)]
+    commit_nonce: bool,
+
+    /// answer with a Fiat–Shamir proof bound to the auth id (and the TLS
+    /// connection) instead of the server's challenge
+    #[arg(long, conflicts_with = "commit_nonce")]
+    non_interactive: bool,
+
+    /// application id of the server's deployment (its --app-id), mixed into
+    /// the challenges the login answers
+    #[arg(long, default_value = "")]
+    app_id: String,
+
+    /// hex SHA-256 fingerprint of the group parameters the server must have
+    /// (zkp-tool inspect and the server's startup output print it); checked
+    /// right after connecting
+    #[arg(long)]
+    params_fingerprint: Option<String>,
+
+    /// file of the parameter fingerprints of the servers this client has
+    /// talked to: the first connection records the server's, later ones must
+    /// match it (see --on-params-change)
+    #[arg(long, conflicts_with = "params_fingerprint")]
+    known_params: Option<PathBuf>,
+
+    /// what to do when a server's parameters changed since they were recorded
+    /// in --known-params
+    #[arg(long, value_enum, default_value_t = ParamsChange::Refuse, requires = "known_params")]
+    on_params_change: ParamsChange,
+
+    /// file of the registration receipts the servers signed: a registration
+    /// records its receipt, and a login in the shell presents it so a server
+    /// that lost or replaced the registration says so
+    #[arg(long)]
+    receipts: Option<PathBuf>,
+
+    /// send user names as typed instead of case folded, for a server run with
+    /// --username-case-sensitive
+    #[arg(long)]
+    username_case_sensitive: bool,
+
+    /// code for the server's second factor (e.g. the current TOTP code),
+    /// sent with the answer
+    #[arg(long, default_value = "")]
+    second_factor: String,
+
+    /// ID this device is known by on the server, sent with registrations and
+    /// logins so they show up in the user's device list (shell: `devices`)
+    #[arg(long)]
+    device_id: Option<String>,
+
+    /// name shown for the device in the device list
+    #[arg(long, default_value = "")]
+    device_name: String,
+
+    /// group to register and log in under (rfc5114-1024-160 or rfc5114-2048-256);
+    /// a user always logs in under the group it registered with
+    #[arg(long, default_value = groups::DEFAULT)]
+    group: String,
+
+    /// mask auth ids and session ids in the output (--redact=false prints them in full)
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    redact: bool,
+
+    /// log more: -v adds debug events, -vv trace events of the client and the
+    /// libraries under it
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// write log events to stderr as text lines or as JSON objects, one per line
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// file with one "username password" pair per line; every pair is
+    /// registered and logged in over the same connection
+    #[arg(long)]
+    batch: Option<PathBuf>,
+
+    /// write a new recovery key pair to this file (which must not exist yet)
+    /// and register its public half with the user; `recover` sets a new
+    /// password with it once the old one is lost
+    #[arg(long)]
+    recovery_key_file: Option<PathBuf>,
+
+    /// seed every random draw (nonces, challenges, ids) so runs are reproducible;
+    /// for tests only, a seeded run has no secrets
+    #[arg(long)]
+    seed: Option<u64>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum KdfKind {
+    /// PBKDF2-HMAC-SHA256, 100000 iterations
+    Pbkdf2,
+    /// Argon2id, 64 MiB, 3 passes, 4 lanes
+    Argon2id,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LogFormat {
+    /// `LEVEL message field=value ...`
+    Text,
+    /// `{"level": ..., "message": ..., "field": value, ...}`
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ParamsChange {
+    /// refuse the server until its entry is removed from the file
+    Refuse,
+    /// warn and record the new fingerprint
+    Warn,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// write a non-interactive proof for a challenge file, without contacting the server
+    Prove {
+        /// challenge file issued by the verifier
+        #[arg(long)]
+        challenge_file: PathBuf,
+
+        /// where to write the proof
+        #[arg(long)]
+        out: PathBuf,
+
+        /// seconds the proof stays valid, stamped into it with the challenge's
+        /// purpose; defaults to the challenge's max_lifetime_seconds, and no
+        /// expiry without either
+        #[arg(long)]
+        valid_for: Option<u64>,
+    },
+    /// interactive shell to register, login and manage the session over one connection
+    Shell,
+    /// write the server's users and live sessions to a signed bundle (admin only)
+    Export {
+        /// where to write the bundle, it holds session keys
+        #[arg(long)]
+        out: PathBuf,
+
+        /// file whose first line is an admin token of the server
+        #[arg(long)]
+        admin_token_file: PathBuf,
+    },
+    /// load a bundle written by `export` into the server (admin only)
+    Import {
+        /// bundle to import, signed by the server or one of its trusted bundle keys
+        #[arg(long)]
+        bundle: PathBuf,
+
+        /// file whose first line is an admin token of the server
+        #[arg(long)]
+        admin_token_file: PathBuf,
+    },
+    /// mark a user name as a decoy account, whose logins are reported (admin only)
+    MarkDecoy {
+        /// user to mark, it doesn't have to be registered yet
+        #[arg(long)]
+        user: String,
+
+        /// clear the mark instead
+        #[arg(long)]
+        clear: bool,
+
+        /// file whose first line is an admin token of the server
+        #[arg(long)]
+        admin_token_file: PathBuf,
+    },
+    /// delete a user, who can't log in from then on and is erased after the
+    /// server's retention, or restore one before that (admin only)
+    DeleteUser {
+        #[arg(long)]
+        user: String,
+
+        /// restore the user instead
+        #[arg(long)]
+        restore: bool,
+
+        /// file whose first line is an admin token of the server
+        #[arg(long)]
+        admin_token_file: PathBuf,
+    },
+    /// put the server in drain mode for a rolling deploy, or lift it (admin only)
+    Drain {
+        /// accept registrations and logins again
+        #[arg(long)]
+        resume: bool,
+
+        /// file whose first line is an admin token of the server
+        #[arg(long)]
+        admin_token_file: PathBuf,
+    },
+    /// reread the server's settings and grants files, as SIGHUP does (admin only)
+    ReloadSettings {
+        /// file whose first line is an admin token of the server
+        #[arg(long)]
+        admin_token_file: PathBuf,
+    },
+    /// list the logins to decoy accounts (admin only)
+    DecoyHits {
+        /// file whose first line is an admin token of the server
+        #[arg(long)]
+        admin_token_file: PathBuf,
+    },
+    /// set a new password for a user with the recovery key written at
+    /// registration (prompts for the user and the new password)
+    Recover {
+        /// file --recovery-key-file wrote the key pair to
+        #[arg(long)]
+        recovery_key_file: PathBuf,
+    },
+    /// list the account recovery audit events (admin only)
+    AuditEvents {
+        /// file whose first line is an admin token of the server
+        #[arg(long)]
+        admin_token_file: PathBuf,
+    },
+}
+
+const SHELL_HELP: &str = "Commands:
+  register <user>   register a user (prompts for the password)
+  login <user>      authenticate and keep the session
+  validate          check the current session
+  info              show where and when the current session was created
+  refresh           replace the current session ID with a new one
+  logout            end the current session
+  devices           list the devices of the current session's user
+  revoke <device>   revoke a device and end its sessions
+  migrate <user> <group>
+                    move a user's verifier to a stronger group (prompts for
+                    the password) and keep the session
+  tickets <n>       have n anonymous tickets issued to the current session
+  redeem            spend one of the tickets, without the session
+  help              show this message
+  quit              leave the shell";
+
+// events of the client and the crate at info (debug with -v), other crates'
+// (tonic, h2) only from warn unless -vv. prompts and the shell's help stay on
+// stdout, the events go to stderr
+fn init_logging(verbose: u8, format: LogFormat) {
+    let (ours, others) = match verbose {
+        0 => (Level::INFO, Level::WARN),
+        1 => (Level::DEBUG, Level::WARN),
+        _ => (Level::TRACE, Level::TRACE),
+    };
+    let filter = Targets::new()
+        .with_target("client", ours)
+        .with_target("zkp_chaum_pedersen", ours)
+        .with_default(others);
+    let events = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_target(false);
+    let registry = tracing_subscriber::registry().with(filter);
+    match format {
+        LogFormat::Text => registry.with(events.without_time()).init(),
+        LogFormat::Json => registry.with(events.json().flatten_event(true)).init(),
+    }
+}
+
 fn read_input(prompt: &str) -> Result<String, std::io::Error> {
     println!("{}", prompt);
     let mut buf = String::new();
@@ -11,125 +376,1296 @@ fn read_input(prompt: &str) -> Result<String, std::io::Error> {
     Ok(buf.trim().to_string())
 }
 
+// like read_input, but every copy of the password is zeroized when dropped
+fn read_secret(prompt: &str) -> Result<Zeroizing<String>, std::io::Error> {
+    println!("{}", prompt);
+    let mut buf = Zeroizing::new(String::new());
+    stdin().read_line(&mut buf)?;
+    Ok(Zeroizing::new(buf.trim().to_string()))
+}
+
+// auth ids and session ids are bearer secrets
+fn display_token(token: &str, redact_output: bool) -> String {
+    if redact_output {
+        redact(token)
+    } else {
+        token.to_string()
+    }
+}
+
+// plain or TLS stream handed to tonic by the connector
+trait Io: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Io for T {}
+
+// one channel per server is shared by every rpc (and every login in batch
+// mode), HTTP/2 keepalive pings stop idle connections from being dropped. a
+// lazy channel connects on its first rpc, so a server that is down doesn't
+// stop the client from failing over to the next
+async fn connect(
+    args: &Args,
+    address: &str,
+    lazy: bool,
+) -> Result<Server, Box<dyn std::error::Error>> {
+    let identity = args
+        .tls_client_cert
+        .as_deref()
+        .zip(args.tls_client_key.as_deref());
+    let tls_config = args
+        .tls_ca
+        .as_deref()
+        .map(|ca| tls::client_config(ca, identity))
+        .transpose()?;
+    // with TLS the connector does the handshake itself so the certificate the
+    // server presents can be read, tonic is handed a plain http:// endpoint
+    let server = match &tls_config {
+        Some(_) => {
+            let uri: Uri = address.parse()?;
+            let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+                Some("http") => 80,
+                _ => 443,
+            });
+            format!("http://{}:{}", uri.host().unwrap_or_default(), port)
+        }
+        None => address.to_string(),
+    };
+
+    let keepalive_interval = Duration::from_secs(args.keepalive_interval);
+    let endpoint = Endpoint::from_shared(server)?
+        .http2_keep_alive_interval(keepalive_interval)
+        .keep_alive_timeout(Duration::from_secs(args.keepalive_timeout))
+        .keep_alive_while_idle(true)
+        .tcp_keepalive(Some(keepalive_interval))
+        .connect_timeout(Duration::from_secs(args.timeout))
+        .timeout(Duration::from_secs(args.timeout));
+
+    let target_host = endpoint.uri().host().unwrap_or_default().to_string();
+    let proxy = match &args.proxy {
+        Some(url) => Some(ProxyConfig::parse(url)?),
+        None => ProxyConfig::from_env(&target_host).transpose()?,
+    };
+
+    let channel_binding = ChannelBinding::default();
+    let channel = if proxy.is_none() && tls_config.is_none() {
+        match lazy {
+            true => endpoint.connect_lazy(),
+            false => endpoint.connect().await?,
+        }
+    } else {
+        if let Some(proxy) = &proxy {
+            info!(%proxy, "connecting through proxy");
+        }
+        let binding = channel_binding.clone();
+        let connector = tower::service_fn(move |uri: Uri| {
+            let (proxy, tls_config, binding) = (proxy.clone(), tls_config.clone(), binding.clone());
+            async move {
+                let host = uri.host().unwrap_or_default();
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+                    Some("https") => 443,
+                    _ => 80,
+                });
+                let stream = match &proxy {
+                    Some(proxy) => proxy.connect(host, port).await?,
+                    None => TcpStream::connect((host, port)).await?,
+                };
+                let io: Box<dyn Io> = match tls_config {
+                    Some(config) => {
+                        let (stream, channel_binding) = tls::connect(stream, host, config).await?;
+                        *binding.lock().unwrap() = Some(channel_binding);
+                        Box::new(stream)
+                    }
+                    None => Box::new(stream),
+                };
+                Ok::<_, std::io::Error>(TokioIo::new(io))
+            }
+        });
+        match lazy {
+            true => endpoint.connect_with_connector_lazy(connector),
+            false => endpoint.connect_with_connector(connector).await?,
+        }
+    };
+    debug!(server = address, lazy, "channel opened");
+    Ok(Server {
+        address: address.to_string(),
+        channel,
+        channel_binding,
+    })
+}
+
+// fails with DeadlineExceeded once the overall --deadline has passed
+async fn within_deadline<T>(
+    deadline: Option<Instant>,
+    operation: impl Future<Output = Result<T, Status>>,
+) -> Result<T, Status> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, operation)
+            .await
+            .unwrap_or_else(|_| Err(Status::deadline_exceeded("operation deadline exceeded"))),
+        None => operation.await,
+    }
+}
+
+// the proof is made in the challenge's group, or --group when it names none,
+// under --app-id
+fn run_prove(args: &Args, challenge_file: &Path, out: &Path, valid_for: Option<u64>) {
+    let challenge: ChallengeFile = match std::fs::read_to_string(challenge_file)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| serde_json::from_str(&contents).map_err(|e| e.to_string()))
+    {
+        Ok(challenge) => challenge,
+        Err(e) => {
+            error!(file = %challenge_file.display(), error = %e, "failed to read challenge file");
+            std::process::exit(1);
+        }
+    };
+
+    let group = challenge.group.as_deref().unwrap_or(&args.group);
+    let Some(zkp) = groups::by_name(group) else {
+        error!(group, groups = ?groups::NAMES, "unknown group");
+        std::process::exit(1);
+    };
+    let application = args.app_id.as_bytes();
+
+    let password_input =
+        match read_secret(&format!("Please enter password for {}:", challenge.user)) {
+            Ok(input) => input,
+            Err(e) => {
+                error!(error = %e, "failed to fetch password");
+                std::process::exit(1);
+            }
+        };
+    let salt = match challenge.salt.as_deref().map(hex::decode).transpose() {
+        Ok(salt) => salt.unwrap_or_default(),
+        Err(e) => {
+            error!(error = %e, "invalid salt in challenge file");
+            std::process::exit(1);
+        }
+    };
+    let kdf = challenge.kdf.unwrap_or_default();
+    if let Err(e) = verifier::KdfPolicy::permissive().check(&kdf) {
+        error!(error = %e, "invalid KDF parameters in challenge file");
+        std::process::exit(1);
+    }
+    let password = verifier::login_secret(&zkp, &challenge.user, &password_input, &salt, &kdf);
+    drop(password_input);
+
+    let proof = match valid_for {
+        Some(seconds) => {
+            let metadata = ProofMetadata::new(
+                challenge.purpose.as_deref().unwrap_or_default(),
+                unix_now(),
+                Duration::from_secs(seconds),
+            );
+            ProofFile::prove_stamped(
+                &zkp,
+                application,
+                &challenge,
+                password.expose(),
+                Some(metadata),
+            )
+        }
+        None => ProofFile::prove(&zkp, application, &challenge, password.expose()),
+    };
+    let json = serde_json::to_string_pretty(&proof).expect("proof file serializes");
+    if let Err(e) = std::fs::write(out, json) {
+        error!(file = %out.display(), error = %e, "failed to write proof");
+        std::process::exit(1);
+    }
+    let expires_at = proof.metadata.as_ref().map(|metadata| metadata.expires_at);
+    info!(file = %out.display(), expires_at, "proof written");
+}
+
+// the receipt of a registration, recorded in --receipts when given. a
+// receipt that isn't for the registration sent is logged and dropped
+fn keep_receipt(
+    receipts: &mut Option<Receipts>,
+    server: &str,
+    response: &RegisterResponse,
+    zkp: &ZKP,
+    user: &str,
+    server_key: Option<&PublicKey>,
+) -> Option<Receipt> {
+    let receipt = match receipt_of(response, zkp, user, server_key) {
+        Ok(Some(receipt)) => receipt,
+        Ok(None) => return None,
+        Err(e) => {
+            error!(
+                user,
+                error = e.message(),
+                "refusing the registration receipt"
+            );
+            return None;
+        }
+    };
+    info!(
+        user,
+        registered_at = receipt.registered_at,
+        "registration receipted"
+    );
+    if let Some(receipts) = receipts {
+        receipts.insert(server, receipt.clone());
+        if let Err(e) = receipts.save() {
+            error!(file = %receipts.path().display(), error = %e, "failed to save receipts");
+        }
+    }
+    Some(receipt)
+}
+
+// a failed rpc, with the server's code and message and the request ID to find
+// it in the server log
+fn rpc_failed(action: &str, status: &Status) {
+    error!(
+        code = ?status.code(),
+        error = status.message(),
+        request_id = request_id::of(status.metadata()),
+        "{} failed",
+        action
+    );
+}
+
+// a failed login, also with its cause (see AuthError) and what to do about it
+fn login_failed(action: &str, status: Status) {
+    let error = AuthError::from(status);
+    let (cause, advice) = match &error {
+        AuthError::WrongCredentials(_) => ("wrong_credentials", "check the user name and password"),
+        AuthError::ChallengeExpired(_) => ("challenge_expired", "log in again"),
+        AuthError::LockedOut { .. } => ("locked_out", "wait for the lock to run out"),
+        AuthError::ServerUnavailable(_) => {
+            ("server_unavailable", "retry later or on another instance")
+        }
+        AuthError::ParameterMismatch(_) => (
+            "parameter_mismatch",
+            "check the group the client is configured with",
+        ),
+        AuthError::RegistrationLost(_) => (
+            "registration_lost",
+            "register again and ask the operator what happened to the registration",
+        ),
+        AuthError::Other(_) => ("other", ""),
+    };
+    let retry_after = match &error {
+        AuthError::LockedOut { retry_after, .. } => retry_after.map(|after| after.as_secs()),
+        _ => None,
+    };
+    let status = error.status();
+    error!(
+        cause,
+        advice,
+        retry_after,
+        code = ?status.code(),
+        error = status.message(),
+        request_id = request_id::of(status.metadata()),
+        "{} failed",
+        action
+    );
+}
+
+// a request carrying `authorization: Bearer <token>`, the token from the file
+fn admin_request<T>(message: T, admin_token_file: &Path) -> Request<T> {
+    let token = match std::fs::read_to_string(admin_token_file) {
+        Ok(contents) => contents
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+        Err(e) => {
+            error!(file = %admin_token_file.display(), error = %e, "failed to read admin token");
+            std::process::exit(1);
+        }
+    };
+    let mut request = Request::new(message);
+    match format!("Bearer {}", token).parse() {
+        Ok(value) => {
+            request.metadata_mut().insert("authorization", value);
+        }
+        Err(_) => {
+            error!("the admin token is not a valid header value");
+            std::process::exit(1);
+        }
+    }
+    request
+}
+
+async fn run_export(client: &mut AuthClient<Channel>, out: &Path, admin_token_file: &Path) {
+    let request = admin_request(ExportStateRequest {}, admin_token_file);
+    let bundle = match client.export_state(request).await {
+        Ok(response) => response.into_inner().bundle,
+        Err(e) => {
+            rpc_failed("export", &e);
+            std::process::exit(1);
+        }
+    };
+    // session keys are in the bundle, so it's as private as a key file
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    if let Err(e) = options
+        .open(out)
+        .and_then(|mut file| file.write_all(bundle.as_bytes()))
+    {
+        error!(file = %out.display(), error = %e, "failed to write bundle");
+        std::process::exit(1);
+    }
+    info!(file = %out.display(), "bundle written");
+}
+
+async fn run_import(client: &mut AuthClient<Channel>, bundle: &Path, admin_token_file: &Path) {
+    let contents = match std::fs::read_to_string(bundle) {
+        Ok(contents) => contents,
+        Err(e) => {
+            error!(file = %bundle.display(), error = %e, "failed to read bundle");
+            std::process::exit(1);
+        }
+    };
+    let request = admin_request(ImportStateRequest { bundle: contents }, admin_token_file);
+    match client.import_state(request).await {
+        Ok(response) => {
+            let response = response.into_inner();
+            info!(
+                users = response.users,
+                sessions = response.sessions,
+                "bundle imported"
+            );
+        }
+        Err(e) => {
+            rpc_failed("import", &e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run_mark_decoy(
+    client: &mut AuthClient<Channel>,
+    user: &str,
+    decoy: bool,
+    admin_token_file: &Path,
+) {
+    let request = admin_request(
+        MarkDecoyRequest {
+            user: user.to_string(),
+            decoy,
+        },
+        admin_token_file,
+    );
+    match client.mark_decoy(request).await {
+        Ok(_) => info!(user, decoy, "decoy mark set"),
+        Err(e) => {
+            rpc_failed("marking the decoy account", &e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run_delete_user(
+    client: &mut AuthClient<Channel>,
+    user: &str,
+    restore: bool,
+    admin_token_file: &Path,
+) {
+    let request = admin_request(
+        DeleteUserRequest {
+            user: user.to_string(),
+            restore,
+        },
+        admin_token_file,
+    );
+    match client.delete_user(request).await {
+        Ok(_) if restore => info!(user, "user restored"),
+        Ok(response) => {
+            let response = response.into_inner();
+            info!(
+                user,
+                sessions_ended = response.sessions_ended,
+                erase_at = response.erase_at,
+                "user deleted"
+            );
+        }
+        Err(e) => {
+            rpc_failed("deleting the user", &e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run_drain(client: &mut AuthClient<Channel>, draining: bool, admin_token_file: &Path) {
+    let request = admin_request(SetDrainRequest { draining }, admin_token_file);
+    match client.set_drain(request).await {
+        Ok(_) => info!(draining, "drain mode set"),
+        Err(e) => {
+            rpc_failed("setting drain mode", &e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run_reload_settings(client: &mut AuthClient<Channel>, admin_token_file: &Path) {
+    let request = admin_request(ReloadSettingsRequest {}, admin_token_file);
+    match client.reload_settings(request).await {
+        Ok(response) => {
+            let settings = response.into_inner();
+            info!(
+                session_ttl_seconds = settings.session_ttl_seconds,
+                max_sessions_per_user = settings.max_sessions_per_user,
+                session_limit_policy = settings.session_limit_policy,
+                grant_realms = settings.grant_realms,
+                grant_users = settings.grant_users,
+                "settings reloaded"
+            );
+        }
+        Err(e) => {
+            rpc_failed("reloading settings", &e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run_decoy_hits(client: &mut AuthClient<Channel>, admin_token_file: &Path) {
+    let request = admin_request(ListDecoyHitsRequest {}, admin_token_file);
+    let hits = match client.list_decoy_hits(request).await {
+        Ok(response) => response.into_inner().hits,
+        Err(e) => {
+            rpc_failed("listing decoy logins", &e);
+            std::process::exit(1);
+        }
+    };
+    info!(hits = hits.len(), "decoy logins listed");
+    for hit in hits {
+        let client = hit.client.unwrap_or_default();
+        warn!(
+            user = hit.user,
+            at = hit.at,
+            remote_addr = client.remote_addr,
+            user_agent = client.user_agent,
+            session_id = hit.session_id,
+            "decoy login"
+        );
+    }
+}
+
+async fn run_audit_events(client: &mut AuthClient<Channel>, admin_token_file: &Path) {
+    let request = admin_request(ListAuditEventsRequest {}, admin_token_file);
+    let events = match client.list_audit_events(request).await {
+        Ok(response) => response.into_inner().events,
+        Err(e) => {
+            rpc_failed("listing audit events", &e);
+            std::process::exit(1);
+        }
+    };
+    info!(events = events.len(), "audit events listed");
+    for event in events {
+        let client = event.client.unwrap_or_default();
+        info!(
+            kind = event.kind,
+            user = event.user,
+            at = event.at,
+            remote_addr = client.remote_addr,
+            user_agent = client.user_agent,
+            request_id = event.request_id,
+            "audit event"
+        );
+    }
+}
+
+async fn run_recover(
+    client: &mut AuthClient<Channel>,
+    zkp: &ZKP,
+    recovery_key_file: &Path,
+    options: &LoginOptions<'_>,
+    usernames: &UsernamePolicy,
+) {
+    let recovery_key = match KeyPair::load(zkp, recovery_key_file) {
+        Ok(key) => key,
+        Err(e) => {
+            error!(file = %recovery_key_file.display(), error = %e, "failed to read recovery key");
+            std::process::exit(1);
+        }
+    };
+    let user = match read_input("Please enter username:") {
+        Ok(name) => usernames.canonical(&name),
+        Err(e) => {
+            error!(error = %e, "failed to fetch username");
+            std::process::exit(1);
+        }
+    };
+    let password = match read_secret("Please enter the new password:") {
+        Ok(input) => input,
+        Err(e) => {
+            error!(error = %e, "failed to fetch password");
+            std::process::exit(1);
+        }
+    };
+    match recover_account(
+        client,
+        zkp,
+        &user,
+        &password,
+        salted_kdf(options),
+        &recovery_key,
+        options.application,
+    )
+    .await
+    {
+        Ok(response) => info!(
+            user,
+            sessions_ended = response.into_inner().sessions_ended,
+            "account recovered"
+        ),
+        Err(e) => {
+            rpc_failed("recovering the account", &e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn salted_kdf<'a>(options: &'a LoginOptions<'_>) -> Option<&'a verifier::KdfParams> {
+    options.salted.then_some(&options.kdf)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn seconds_until(expires_at: u64) -> u64 {
+    expires_at.saturating_sub(unix_now())
+}
+
+fn seconds_since(created_at: u64) -> u64 {
+    unix_now().saturating_sub(created_at)
+}
+
+async fn run_shell(
+    client: &mut AuthClient<Channel>,
+    server: &str,
+    zkp: &ZKP,
+    options: &LoginOptions<'_>,
+    usernames: &UsernamePolicy,
+    receipts: &mut Option<Receipts>,
+    redact_output: bool,
+) {
+    println!("{}", SHELL_HELP);
+
+    // (user, session_id, session key) of the last successful login
+    let mut session: Option<(String, String, Option<SessionKey>)> = None;
+    // anonymous tickets not spent yet, they outlive the session
+    let mut tickets: Vec<Ticket> = Vec::new();
+    loop {
+        print!("zkp> ");
+        let _ = std::io::stdout().flush();
+        let mut line = String::new();
+        match stdin().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                error!(error = %e, "failed to read command");
+                break;
+            }
+        }
+
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (None, _) => continue,
+            (Some("register"), Some(user)) => {
+                let user = &usernames.canonical(user);
+                let password = match read_secret("Please enter password:") {
+                    Ok(input) => input,
+                    Err(e) => {
+                        error!(error = %e, "failed to fetch password");
+                        continue;
+                    }
+                };
+                match register_on_device(
+                    client,
+                    zkp,
+                    user,
+                    &password,
+                    salted_kdf(options),
+                    options.device,
+                )
+                .await
+                {
+                    Ok(response) => {
+                        info!(user, "registered");
+                        let response = response.into_inner();
+                        keep_receipt(receipts, server, &response, zkp, user, options.server_key);
+                    }
+                    Err(e) => rpc_failed("registration", &e),
+                }
+            }
+            (Some("login"), Some(user)) => {
+                let user = &usernames.canonical(user);
+                let password = match read_secret("Please enter password:") {
+                    Ok(input) => input,
+                    Err(e) => {
+                        error!(error = %e, "failed to fetch password");
+                        continue;
+                    }
+                };
+                let options = &LoginOptions {
+                    receipt: receipts.as_ref().and_then(|r| r.get(server, user)),
+                    ..*options
+                };
+                let result = match create_challenge(client, zkp, user, options).await {
+                    Ok(login) => {
+                        answer_challenge(client, zkp, login, user, &password, options).await
+                    }
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Ok((session_id, key)) => {
+                        info!(
+                            user,
+                            session_id = display_token(&session_id, redact_output),
+                            "logged in"
+                        );
+                        session = Some((user.to_string(), session_id, key));
+                    }
+                    Err(e) => login_failed("login", e),
+                }
+            }
+            (Some("migrate"), Some(user)) => {
+                let user = &usernames.canonical(user);
+                let Some((group, to)) = words
+                    .next()
+                    .and_then(|group| Some((group, groups::by_name(group)?)))
+                else {
+                    warn!(groups = ?groups::NAMES, "usage: migrate <user> <group>");
+                    continue;
+                };
+                let password = match read_secret("Please enter password:") {
+                    Ok(input) => input,
+                    Err(e) => {
+                        error!(error = %e, "failed to fetch password");
+                        continue;
+                    }
+                };
+                match migrate_group(client, zkp, &to, user, &password, options).await {
+                    Ok((session_id, key)) => {
+                        info!(
+                            user,
+                            group,
+                            session_id = display_token(&session_id, redact_output),
+                            "moved to the group, log in with --group from now on"
+                        );
+                        session = Some((user.to_string(), session_id, Some(key)));
+                    }
+                    Err(e) => rpc_failed("moving the user", &e),
+                }
+            }
+            (Some("redeem"), _) => match tickets.pop() {
+                None => warn!("no tickets left, use `tickets <n>` while logged in"),
+                Some(ticket) => match redeem_ticket(client, &ticket).await {
+                    Ok(()) => info!(left = tickets.len(), "ticket redeemed"),
+                    Err(e) => rpc_failed("redeeming the ticket", &e),
+                },
+            },
+            (
+                Some("validate" | "info" | "refresh" | "logout" | "devices" | "revoke" | "tickets"),
+                _,
+            ) if session.is_none() => {
+                warn!("not logged in, use `login <user>` first");
+            }
+            (Some("validate"), _) => {
+                let (_, session_id, key) = session.as_ref().unwrap();
+                let request = ValidateSessionRequest {
+                    session_id: session_id.clone(),
+                    mac: key
+                        .as_ref()
+                        .map(|key| key.mac(session_id.as_bytes()))
+                        .unwrap_or_default(),
+                };
+                match client.validate_session(request).await {
+                    Ok(resp) => {
+                        let resp = resp.into_inner();
+                        info!(
+                            user = resp.user,
+                            expires_in = seconds_until(resp.expires_at),
+                            "session valid"
+                        );
+                    }
+                    Err(e) => rpc_failed("session validation", &e),
+                }
+            }
+            (Some("info"), _) => {
+                let (_, session_id, key) = session.as_ref().unwrap();
+                let request = GetSessionInfoRequest {
+                    session_id: session_id.clone(),
+                    mac: key
+                        .as_ref()
+                        .map(|key| key.mac(session_id.as_bytes()))
+                        .unwrap_or_default(),
+                };
+                match client.get_session_info(request).await {
+                    Ok(resp) => {
+                        let resp = resp.into_inner();
+                        let client = resp.client.unwrap_or_default();
+                        info!(
+                            user = resp.user,
+                            realm = resp.realm,
+                            created_ago = seconds_since(resp.created_at),
+                            remote_addr = client.remote_addr,
+                            user_agent = client.user_agent,
+                            expires_in = seconds_until(resp.expires_at),
+                            "session info"
+                        );
+                    }
+                    Err(e) => rpc_failed("reading the session", &e),
+                }
+            }
+            (Some("refresh"), _) => {
+                let (user, session_id, key) = session.take().unwrap();
+                let request = RefreshSessionRequest {
+                    session_id: session_id.clone(),
+                };
+                match client.refresh_session(request).await {
+                    Ok(resp) => {
+                        let resp = resp.into_inner();
+                        info!(
+                            session_id = display_token(&resp.session_id, redact_output),
+                            expires_in = seconds_until(resp.expires_at),
+                            "session refreshed"
+                        );
+                        session = Some((user, resp.session_id, key));
+                    }
+                    Err(e) => {
+                        rpc_failed("refreshing the session", &e);
+                        session = Some((user, session_id, key));
+                    }
+                }
+            }
+            (Some("logout"), _) => {
+                let (user, session_id, _) = session.take().unwrap();
+                match client.logout(LogoutRequest { session_id }).await {
+                    Ok(_) => info!(user, "logged out"),
+                    Err(e) => rpc_failed("logout", &e),
+                }
+            }
+            (Some("devices"), _) => {
+                let (_, session_id, key) = session.as_ref().unwrap();
+                let request = ListDevicesRequest {
+                    session_id: session_id.clone(),
+                    mac: key
+                        .as_ref()
+                        .map(|key| key.mac(session_id.as_bytes()))
+                        .unwrap_or_default(),
+                    ..Default::default()
+                };
+                match client.list_devices(request).await {
+                    Ok(resp) => {
+                        let devices = resp.into_inner().devices;
+                        info!(devices = devices.len(), "devices listed");
+                        for device in devices {
+                            let info = device.info.unwrap_or_default();
+                            info!(
+                                device_id = info.device_id,
+                                name = info.name,
+                                platform = info.platform,
+                                logins = device.logins,
+                                last_seen_ago = seconds_since(device.last_seen),
+                                remote_addr = device.last_client.unwrap_or_default().remote_addr,
+                                registered = device.registered,
+                                "device"
+                            );
+                        }
+                    }
+                    Err(e) => rpc_failed("listing devices", &e),
+                }
+            }
+            (Some("revoke"), None) => warn!("usage: revoke <device>"),
+            (Some("tickets"), count) => {
+                let Some(count) = count.and_then(|count| count.parse().ok()) else {
+                    warn!("usage: tickets <n>");
+                    continue;
+                };
+                let (_, session_id, key) = session.as_ref().unwrap();
+                let Some(key) = key else {
+                    warn!("the session has no key to ask for tickets with");
+                    continue;
+                };
+                match issue_tickets(client, session_id, key, count, options).await {
+                    Ok((issued, left)) => {
+                        info!(issued = issued.len(), left, "tickets issued");
+                        tickets.extend(issued);
+                    }
+                    Err(e) => rpc_failed("issuing tickets", &e),
+                }
+            }
+            (Some("migrate"), None) => warn!("usage: migrate <user> <group>"),
+            (Some("revoke"), Some(device_id)) => {
+                let (_, session_id, key) = session.as_ref().unwrap();
+                let request = RevokeDeviceRequest {
+                    session_id: session_id.clone(),
+                    mac: key
+                        .as_ref()
+                        .map(|key| key.mac(session_id.as_bytes()))
+                        .unwrap_or_default(),
+                    device_id: device_id.to_string(),
+                    ..Default::default()
+                };
+                match client.revoke_device(request).await {
+                    Ok(resp) => info!(
+                        device_id,
+                        sessions_ended = resp.into_inner().sessions_ended,
+                        "device revoked"
+                    ),
+                    Err(e) => rpc_failed("revoking the device", &e),
+                }
+            }
+            (Some("help"), _) => println!("{}", SHELL_HELP),
+            (Some("quit" | "exit"), _) => break,
+            _ => warn!("unknown command, type `help` for the list of commands"),
+        }
+    }
+}
+
+async fn run_batch(
+    failover: &mut Failover,
+    zkp: &ZKP,
+    path: &Path,
+    deadline: Option<Instant>,
+    options: &LoginOptions<'_>,
+    usernames: &UsernamePolicy,
+    redact_output: bool,
+) {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => Zeroizing::new(contents),
+        Err(e) => {
+            error!(file = %path.display(), error = %e, "failed to read batch file");
+            std::process::exit(1);
+        }
+    };
+
+    let mut failures = 0;
+    for (line_no, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (username, password) = match line.split_once(char::is_whitespace) {
+            Some((user, password)) => (usernames.canonical(user), password.trim()),
+            None => {
+                error!(line = line_no + 1, "expected \"username password\"");
+                failures += 1;
+                continue;
+            }
+        };
+        let username = username.as_str();
+
+        // the events of an entry carry its line and user
+        let span = info_span!("entry", line = line_no + 1, user = username);
+        let entry = async {
+            // registered where the login goes, unless that server goes away
+            // between the two
+            let mut client = failover.client(failover.current());
+            let registration = register_on_device(
+                &mut client,
+                zkp,
+                username,
+                password,
+                salted_kdf(options),
+                options.device,
+            );
+            if let Err(e) = within_deadline(deadline, registration).await {
+                rpc_failed("registration", &e);
+                return false;
+            }
+            let login = async {
+                let login = failover.login(zkp, username, password, options).await;
+                login.map_err(AuthError::into_status)
+            };
+            match within_deadline(deadline, login).await {
+                Ok((session_id, _)) => {
+                    info!(
+                        session_id = display_token(&session_id, redact_output),
+                        "authenticated"
+                    );
+                    true
+                }
+                Err(e) => {
+                    login_failed("login", e);
+                    false
+                }
+            }
+        };
+        if !entry.instrument(span).await {
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        error!(failures, "batch entries failed");
+        std::process::exit(1);
+    }
+}
+
 #[tokio::main]
 async fn main() {
-    let (g, h, p, q) = ZKP::get_constants();
-    let zkp = ZKP {
-        p: p.clone(),
-        q: q.clone(),
-        g: g.clone(),
-        h: h.clone(),
+    let args = Args::parse();
+    init_logging(args.verbose, args.log_format);
+    if let Some(seed) = args.seed {
+        rng::seed_process(seed);
+        warn!(seed, "deterministic mode, for tests only");
+    }
+    let deadline = args
+        .deadline
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+
+    if let Some(Command::Prove {
+        challenge_file,
+        out,
+        valid_for,
+    }) = &args.command
+    {
+        run_prove(&args, challenge_file, out, *valid_for);
+        return;
+    }
+
+    let zkp = match groups::by_name(&args.group) {
+        Some(zkp) => zkp,
+        None => {
+            error!(group = args.group, groups = ?groups::NAMES, "unknown group");
+            std::process::exit(1);
+        }
     };
 
-    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
-        Ok(client) => client,
+    let pinned_params = args.params_fingerprint.as_ref().map(|fingerprint| {
+        match hex::decode(fingerprint)
+            .ok()
+            .and_then(|f| <[u8; 32]>::try_from(f).ok())
+        {
+            Some(fingerprint) => fingerprint,
+            None => {
+                error!("--params-fingerprint must be 64 hex digits");
+                std::process::exit(1);
+            }
+        }
+    });
+
+    let server_key = match &args.server_public_key {
+        Some(path) => match PublicKey::load(path) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                error!(file = %path.display(), error = %e, "failed to read server public key");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    // a single server is connected to right away, as before failover. of
+    // several, the first healthy one is used
+    let connection = async {
+        let lazy = args.server.len() > 1;
+        let mut servers = Vec::new();
+        for address in &args.server {
+            let server = connect(&args, address, lazy).await;
+            servers.push(server.map_err(|e| Status::unavailable(format!("{}: {}", address, e)))?);
+        }
+        let mut failover = Failover::new(servers);
+        failover.shared_state = args.servers_share_state;
+        if lazy {
+            failover.healthy().await?;
+        }
+        Ok(failover)
+    };
+    let mut failover = match within_deadline(deadline, connection).await {
+        Ok(failover) => failover,
         Err(e) => {
-            eprintln!("❌ Failed to connect to the server: {}", e);
+            error!(error = e.message(), "failed to connect to the server");
+            std::process::exit(1);
+        }
+    };
+    let server = failover.servers()[failover.current()].clone();
+    let mut client = failover.client(failover.current());
+    let channel_binding = server.channel_binding.clone();
+    info!(server = server.address, "connected");
+    if let Some(pinned) = &pinned_params {
+        let check = check_parameters(&mut client, &zkp, Some(pinned));
+        if let Err(e) = within_deadline(deadline, check).await {
+            error!(error = e.message(), "refusing the server");
             std::process::exit(1);
         }
+        info!("server parameters match the pinned fingerprint");
+    }
+    if let Some(path) = &args.known_params {
+        let mut known = match KnownParameters::load(path) {
+            Ok(known) => known,
+            Err(e) => {
+                error!(file = %path.display(), error = %e, "failed to read known parameters");
+                std::process::exit(1);
+            }
+        };
+        let on_change = match args.on_params_change {
+            ParamsChange::Refuse => OnChange::Refuse,
+            ParamsChange::Warn => OnChange::Warn,
+        };
+        let check =
+            check_known_parameters(&mut client, &zkp, &server.address, &mut known, on_change);
+        match within_deadline(deadline, check).await {
+            Ok(Trust::Known) => info!("server parameters match the recorded fingerprint"),
+            Ok(Trust::Learned) => info!(
+                file = %path.display(),
+                "server parameters recorded on first use"
+            ),
+            Ok(Trust::Changed { previous }) => warn!(
+                previous = hex::encode(previous),
+                "server parameters changed, now trusting the new ones"
+            ),
+            Err(e) => {
+                error!(error = e.message(), "refusing the server");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let device = args.device_id.as_ref().map(|device_id| DeviceInfo {
+        device_id: device_id.clone(),
+        name: args.device_name.clone(),
+        platform: std::env::consts::OS.to_string(),
+    });
+    let options = LoginOptions {
+        server_key: server_key.as_ref(),
+        channel_binding: &channel_binding,
+        salted: args.salted,
+        kdf: match args.kdf {
+            KdfKind::Pbkdf2 => verifier::KdfParams::default(),
+            KdfKind::Argon2id => verifier::KdfParams::argon2id(),
+        },
+        blinded: args.blinded,
+        commit_nonce: args.commit_nonce,
+        non_interactive: args.non_interactive,
+        application: args.app_id.as_bytes(),
+        second_factor: &args.second_factor,
+        device: device.as_ref(),
+        receipt: None,
     };
-    println!("✅ Client connected to server");
+    let mut receipts = match &args.receipts {
+        Some(path) => match Receipts::load(path) {
+            Ok(receipts) => Some(receipts),
+            Err(e) => {
+                error!(file = %path.display(), error = %e, "failed to read receipts");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    // the server stores and derives salted verifiers from canonical names
+    let usernames = UsernamePolicy {
+        fold_case: !args.username_case_sensitive,
+        ..UsernamePolicy::default()
+    };
+
+    match &args.command {
+        Some(Command::Shell) => {
+            run_shell(
+                &mut client,
+                &server.address,
+                &zkp,
+                &options,
+                &usernames,
+                &mut receipts,
+                args.redact,
+            )
+            .await;
+            return;
+        }
+        Some(Command::Export {
+            out,
+            admin_token_file,
+        }) => {
+            run_export(&mut client, out, admin_token_file).await;
+            return;
+        }
+        Some(Command::Import {
+            bundle,
+            admin_token_file,
+        }) => {
+            run_import(&mut client, bundle, admin_token_file).await;
+            return;
+        }
+        Some(Command::MarkDecoy {
+            user,
+            clear,
+            admin_token_file,
+        }) => {
+            run_mark_decoy(&mut client, user, !clear, admin_token_file).await;
+            return;
+        }
+        Some(Command::DeleteUser {
+            user,
+            restore,
+            admin_token_file,
+        }) => {
+            run_delete_user(&mut client, user, *restore, admin_token_file).await;
+            return;
+        }
+        Some(Command::DecoyHits { admin_token_file }) => {
+            run_decoy_hits(&mut client, admin_token_file).await;
+            return;
+        }
+        Some(Command::Recover { recovery_key_file }) => {
+            run_recover(&mut client, &zkp, recovery_key_file, &options, &usernames).await;
+            return;
+        }
+        Some(Command::AuditEvents { admin_token_file }) => {
+            run_audit_events(&mut client, admin_token_file).await;
+            return;
+        }
+        Some(Command::Drain {
+            resume,
+            admin_token_file,
+        }) => {
+            run_drain(&mut client, !resume, admin_token_file).await;
+            return;
+        }
+        Some(Command::ReloadSettings { admin_token_file }) => {
+            run_reload_settings(&mut client, admin_token_file).await;
+            return;
+        }
+        _ => {}
+    }
+
+    if let Some(path) = &args.batch {
+        run_batch(
+            &mut failover,
+            &zkp,
+            path,
+            deadline,
+            &options,
+            &usernames,
+            args.redact,
+        )
+        .await;
+        return;
+    }
 
     // Register
     let username = match read_input("Please enter username:") {
-        Ok(name) => name,
+        Ok(name) => usernames.canonical(&name),
         Err(e) => {
-            eprintln!("❌ Failed to fetch username: {}", e);
+            error!(error = %e, "failed to fetch username");
             std::process::exit(1);
         }
     };
 
-    let password_input = match read_input("Please enter password:") {
+    let password = match read_secret("Please enter password:") {
         Ok(input) => input,
         Err(e) => {
-            eprintln!("❌ Failed to fetch password: {}", e);
+            error!(error = %e, "failed to fetch password");
             std::process::exit(1);
         }
     };
-    let password = BigUint::from_bytes_be(password_input.as_bytes());
 
-    let y1 = ZKP::exponentiate(&zkp.g, &password, &zkp.p);
-    let y2 = ZKP::exponentiate(&zkp.h, &password, &zkp.p);
-
-    let request = RegisterRequest {
-        user: username.clone(),
-        y1: y1.to_bytes_be(),
-        y2: y2.to_bytes_be(),
-    };
-    let response = client.register(request).await;
-    match response {
-        Ok(resp) => {
-            println!("✅ User registered successfully: {:?}", resp);
+    // written before the registration, which is pointless without it
+    let recovery_key = args.recovery_key_file.as_ref().map(|path| {
+        if path.exists() {
+            error!(file = %path.display(), "not overwriting an existing recovery key");
+            std::process::exit(1);
+        }
+        let key = KeyPair::generate(&zkp);
+        if let Err(e) = key.save(path) {
+            error!(file = %path.display(), error = %e, "failed to write recovery key");
+            std::process::exit(1);
         }
+        info!(file = %path.display(), "recovery key written");
+        key
+    });
+    let registration = register_with_recovery(
+        &mut client,
+        &zkp,
+        &username,
+        &password,
+        salted_kdf(&options),
+        options.device,
+        recovery_key.as_ref().map(|key| &key.public),
+    );
+    let response = within_deadline(deadline, registration).await;
+    drop(password);
+    let response = match response {
+        Ok(response) => response.into_inner(),
         Err(e) => {
-            println!("❌ Error registering user: {:?}", e);
+            rpc_failed("registration", &e);
             std::process::exit(1);
         }
-    }
+    };
+    info!(user = username, "registered");
+    // presented with the challenge, in case it goes to a server that didn't
+    // get the registration
+    let receipt = keep_receipt(
+        &mut receipts,
+        &server.address,
+        &response,
+        &zkp,
+        &username,
+        server_key.as_ref(),
+    );
+    let options = LoginOptions {
+        receipt: receipt.as_ref(),
+        ..options
+    };
 
     // Create authentication challenge
-    let k = ZKP::generate_random_number_below(&zkp.q);
-    let r1 = ZKP::exponentiate(&zkp.g, &k, &zkp.p);
-    let r2 = ZKP::exponentiate(&zkp.h, &k, &zkp.p);
-
-    let request = AuthenticationChallengeRequest {
-        user: username,
-        r1: r1.to_bytes_be(),
-        r2: r2.to_bytes_be(),
-    };
-    let response = client.create_authentication_challenge(request).await;
-
-    let (auth_id, c) = match response {
-        Ok(resp) => {
-            let inner = resp.into_inner();
-            let auth_id = inner.auth_id.clone();
-            let c = inner.c.clone();
-            println!(
-                "✅ Authentication challenge created successfully: {:?}",
-                inner
+    // the answer goes to the server that issued the challenge
+    let challenge = async {
+        let login = failover.create_challenge(&zkp, &username, &options).await;
+        login.map_err(AuthError::into_status)
+    };
+    let response = within_deadline(deadline, challenge).await;
+
+    let login = match response {
+        Ok(login) => {
+            let challenge = &login.login.challenge;
+            info!(
+                auth_id = display_token(&challenge.auth_id, args.redact),
+                server = failover.servers()[login.server].address,
+                "challenge created"
             );
-            (auth_id, c)
+            // the challenge bytes only with redaction turned off
+            if !args.redact {
+                debug!(c = hex::encode(&challenge.c), "challenge");
+            }
+            login
         }
         Err(e) => {
-            println!("❌ Error creating authentication challenge: {:?}", e);
+            login_failed("creating the challenge", e);
             std::process::exit(1);
         }
     };
 
     // Verify authentication
-    println!("========== verify authentication ==========");
-    let password_input = match read_input("Please enter password to login:") {
+    let password = match read_secret("Please enter password to login:") {
         Ok(input) => input,
         Err(e) => {
-            eprintln!("❌ Failed to fetch password: {}", e);
+            error!(error = %e, "failed to fetch password");
             std::process::exit(1);
         }
     };
-    let password = BigUint::from_bytes_be(password_input.as_bytes());
-
-    let c_biguint = BigUint::from_bytes_be(&c);
-    let s = zkp.solve(&k, &c_biguint, &password);
 
-    let request = AuthenticationAnswerRequest {
-        auth_id,
-        s: s.to_bytes_be(),
+    let answer = async {
+        let answer = failover
+            .answer_challenge(&zkp, login, &username, &password, &options)
+            .await;
+        answer.map_err(AuthError::into_status)
     };
+    let response = within_deadline(deadline, answer).await;
+    drop(password);
 
-    let response = client.verify_authentication(request).await;
-
-    let session_id = match response {
-        Ok(resp) => {
-            let inner = resp.into_inner();
-            inner.session_id
-        }
+    let (session_id, session_key) = match response {
+        Ok(response) => response,
         Err(e) => {
-            println!("❌ Error verifying authentication: {:?}", e);
+            login_failed("verification", e);
             std::process::exit(1);
         }
     };
 
-    println!(
-        "✅ Authentication verified successfully. Session ID: {}",
-        session_id
+    info!(
+        user = username,
+        session_id = display_token(&session_id, args.redact),
+        session_key = session_key.is_some(),
+        "authenticated"
     );
 }
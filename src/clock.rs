@@ -0,0 +1,70 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+// where the server's expiry logic gets the time from: session TTLs, the
+// pruning of expired sessions, bundle imports and device records all ask the
+// server's clock rather than the system. tests fast-forward a ManualClock,
+// targets without a system clock (embedded, wasm) supply their own
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+// SystemTime::now, the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+// time that only moves when told to. clones share the time, so a test keeps
+// one to advance the clock it gave the server
+#[derive(Debug, Clone)]
+pub struct ManualClock(Arc<Mutex<SystemTime>>);
+
+impl ManualClock {
+    pub fn new(start: SystemTime) -> Self {
+        ManualClock(Arc::new(Mutex::new(start)))
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+
+    pub fn set(&self, to: SystemTime) {
+        *self.0.lock().unwrap() = to;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+impl Default for Box<dyn Clock> {
+    fn default() -> Self {
+        Box::new(SystemClock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn test_manual_clock_moves_when_told() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let clock = ManualClock::new(start);
+        let server: Box<dyn Clock> = Box::new(clock.clone());
+        assert_eq!(server.now(), start);
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(server.now(), start + Duration::from_secs(90));
+        clock.set(start);
+        assert_eq!(server.now(), start);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::encoding;
+use crate::groups;
+use crate::ZKP;
+use num_bigint::BigUint;
+use std::fmt::{Debug, Display};
+
+// how the integers of the wire (the `bytes` fields of the proto messages) are
+// written in each group: group elements, and the challenges and responses
+// below its order. the RPC handlers convert through for_group instead of
+// to_bytes_be and from_bytes_be, so a backend with its own encoding, e.g.
+// compressed curve points, is a codec here rather than a change to every
+// handler
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecError {
+    // a fixed-width field of another length
+    Length {
+        field: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    // bytes of the right length that aren't an element of the group
+    NotAnElement(&'static str),
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodecError::Length {
+                field,
+                expected,
+                actual,
+            } => write!(f, "{} is {} bytes, not {}", field, actual, expected),
+            CodecError::NotAnElement(field) => write!(f, "{} is not a group element", field),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+pub trait WireCodec: Debug + Send + Sync {
+    fn encode_element(&self, n: &BigUint) -> Vec<u8>;
+
+    fn decode_element(&self, field: &'static str, bytes: &[u8]) -> Result<BigUint, CodecError>;
+
+    fn encode_scalar(&self, n: &BigUint) -> Vec<u8>;
+
+    fn decode_scalar(&self, field: &'static str, bytes: &[u8]) -> Result<BigUint, CodecError>;
+}
+
+// the fewest big-endian bytes, leading zeros taken when decoding: the wire of
+// the MODP groups, and of every message written before there were codecs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinimalBigEndian;
+
+impl WireCodec for MinimalBigEndian {
+    fn encode_element(&self, n: &BigUint) -> Vec<u8> {
+        n.to_bytes_be()
+    }
+
+    fn decode_element(&self, _: &'static str, bytes: &[u8]) -> Result<BigUint, CodecError> {
+        Ok(BigUint::from_bytes_be(bytes))
+    }
+
+    fn encode_scalar(&self, n: &BigUint) -> Vec<u8> {
+        n.to_bytes_be()
+    }
+
+    fn decode_scalar(&self, _: &'static str, bytes: &[u8]) -> Result<BigUint, CodecError> {
+        Ok(BigUint::from_bytes_be(bytes))
+    }
+}
+
+// big-endian, zero-padded to the width of p for elements and of q for
+// scalars, and only that width taken, for peers that size their buffers by
+// the group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedWidth {
+    pub element_len: usize,
+    pub scalar_len: usize,
+}
+
+impl FixedWidth {
+    pub fn for_group(zkp: &ZKP) -> Self {
+        FixedWidth {
+            element_len: zkp.element_len(),
+            scalar_len: zkp.scalar_len(),
+        }
+    }
+}
+
+// n in len bytes, or in as many as it needs when that is more: an encoder
+// isn't where an out-of-range number is caught
+fn padded_be(n: &BigUint, len: usize) -> Vec<u8> {
+    let mut out = vec![0u8; len.max(encoding::be_len(n))];
+    encoding::write_be_into(n, &mut out).expect("sized to fit");
+    out
+}
+
+fn exact<'a>(field: &'static str, bytes: &'a [u8], len: usize) -> Result<&'a [u8], CodecError> {
+    match bytes.len() == len {
+        true => Ok(bytes),
+        false => Err(CodecError::Length {
+            field,
+            expected: len,
+            actual: bytes.len(),
+        }),
+    }
+}
+
+impl WireCodec for FixedWidth {
+    fn encode_element(&self, n: &BigUint) -> Vec<u8> {
+        padded_be(n, self.element_len)
+    }
+
+    fn decode_element(&self, field: &'static str, bytes: &[u8]) -> Result<BigUint, CodecError> {
+        exact(field, bytes, self.element_len).map(BigUint::from_bytes_be)
+    }
+
+    fn encode_scalar(&self, n: &BigUint) -> Vec<u8> {
+        padded_be(n, self.scalar_len)
+    }
+
+    fn decode_scalar(&self, field: &'static str, bytes: &[u8]) -> Result<BigUint, CodecError> {
+        exact(field, bytes, self.scalar_len).map(BigUint::from_bytes_be)
+    }
+}
+
+// elements as the curve's compressed points, checked to be one when decoded,
+// and scalars as the curve library's 32 little-endian bytes
+#[cfg(any(feature = "ristretto", feature = "bls12-381"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressedPoint {
+    // 32 bytes, the element is their little-endian number
+    #[cfg(feature = "ristretto")]
+    Ristretto,
+    // 48 bytes, the element is their big-endian number
+    #[cfg(feature = "bls12-381")]
+    Bls12G1,
+}
+
+#[cfg(any(feature = "ristretto", feature = "bls12-381"))]
+impl WireCodec for CompressedPoint {
+    fn encode_element(&self, n: &BigUint) -> Vec<u8> {
+        match self {
+            #[cfg(feature = "ristretto")]
+            CompressedPoint::Ristretto => {
+                let mut out = n.to_bytes_le();
+                out.resize(out.len().max(32), 0);
+                out
+            }
+            #[cfg(feature = "bls12-381")]
+            CompressedPoint::Bls12G1 => padded_be(n, 48),
+        }
+    }
+
+    fn decode_element(&self, field: &'static str, bytes: &[u8]) -> Result<BigUint, CodecError> {
+        let (n, is_point) = match self {
+            #[cfg(feature = "ristretto")]
+            CompressedPoint::Ristretto => {
+                let n = BigUint::from_bytes_le(exact(field, bytes, 32)?);
+                let is_point = crate::ristretto::RistrettoGroup::point(&n).is_some();
+                (n, is_point)
+            }
+            #[cfg(feature = "bls12-381")]
+            CompressedPoint::Bls12G1 => {
+                let n = BigUint::from_bytes_be(exact(field, bytes, 48)?);
+                let is_point = crate::bls::Bls12G1Group::point(&n).is_some();
+                (n, is_point)
+            }
+        };
+        match is_point {
+            true => Ok(n),
+            false => Err(CodecError::NotAnElement(field)),
+        }
+    }
+
+    fn encode_scalar(&self, n: &BigUint) -> Vec<u8> {
+        let mut out = n.to_bytes_le();
+        out.resize(out.len().max(32), 0);
+        out
+    }
+
+    fn decode_scalar(&self, field: &'static str, bytes: &[u8]) -> Result<BigUint, CodecError> {
+        exact(field, bytes, 32).map(BigUint::from_bytes_le)
+    }
+}
+
+// the codec of a named group (groups::NAMES, or an alias of one) or of a
+// curve backend by its name in capabilities ("ristretto", "bls12-381"), None
+// for a group this build doesn't have
+pub fn for_group(name: &str) -> Option<&'static dyn WireCodec> {
+    match groups::resolve(name) {
+        name if groups::NAMES.contains(&name) => Some(&MinimalBigEndian),
+        #[cfg(feature = "ristretto")]
+        "ristretto" => Some(&CompressedPoint::Ristretto),
+        #[cfg(feature = "bls12-381")]
+        "bls12-381" => Some(&CompressedPoint::Bls12G1),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codecs_round_trip_their_own_encoding() {
+        let zkp = groups::default_group();
+        let y = &zkp.g - 1u32;
+        let c = BigUint::from(0x0102u32);
+
+        // the MODP groups keep the wire they always had
+        let codec = for_group(groups::DEFAULT).unwrap();
+        assert_eq!(codec.encode_element(&y), y.to_bytes_be());
+        assert_eq!(codec.decode_scalar("c", &[0, 1, 2]), Ok(c.clone()));
+        assert!(for_group("p-256").is_none());
+
+        let fixed = FixedWidth::for_group(&zkp);
+        assert_eq!(fixed.encode_element(&c).len(), 128);
+        assert_eq!(fixed.encode_scalar(&c).len(), 20);
+        let element = fixed.encode_element(&y);
+        assert_eq!(fixed.decode_element("y1", &element), Ok(y));
+        assert_eq!(
+            fixed.decode_scalar("c", &c.to_bytes_be()),
+            Err(CodecError::Length {
+                field: "c",
+                expected: 20,
+                actual: 2
+            })
+        );
+    }
+
+    #[cfg(feature = "ristretto")]
+    #[test]
+    fn test_compressed_points_are_checked() {
+        use crate::group::ChaumPedersenGroup;
+        use crate::ristretto::RistrettoGroup;
+
+        let group = RistrettoGroup::default();
+        let (y1, _) = group.commit(&BigUint::from(7u32));
+        let codec = for_group("ristretto").unwrap();
+        let bytes = codec.encode_element(&y1);
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(codec.decode_element("y1", &bytes), Ok(y1));
+        assert_eq!(
+            codec.decode_element("y1", &[0xff; 32]),
+            Err(CodecError::NotAnElement("y1"))
+        );
+        assert_eq!(codec.encode_scalar(&BigUint::from(1u32))[0], 1);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::secret::wipe_biguint;
+use crate::{Proof, ZKP};
+use num_bigint::BigUint;
+
+// proof that one integer x is behind a verifier in two groups of different
+// orders, for moving a registration off a weaker group without the password:
+// y1 = g ** x, y2 = h ** x mod p in the first group and y1' = g' ** x,
+// y2' = h' ** x mod p' in the second. neither order can reduce the response,
+// so it is s = k - c * x over the integers, and hides x only because k is
+// drawn from a range 2 ** SECURITY_BITS times larger than any c * x
+// (statistical hiding, as in unknown_order). the verifier rejects any s
+// outside that range.
+//
+// the bound keeps x below about 2 ** (SECRET_BITS + CHALLENGE_BITS +
+// SECURITY_BITS), not below either order, so this shows knowledge of x in
+// the first group together with one consistent x in the second, not that x
+// is small. the server only takes it from a session of the user, and it is
+// what a stolen session lacks: without x no new verifier gets in
+
+// x < 2 ** SECRET_BITS once reduced by both orders, enough for two 256-bit q
+pub const SECRET_BITS: u64 = 512;
+// c < 2 ** CHALLENGE_BITS, below the order of every named group
+pub const CHALLENGE_BITS: u64 = 128;
+// statistical distance between s and uniform, about 2 ** -SECURITY_BITS
+pub const SECURITY_BITS: u64 = 128;
+
+fn power_of_two(bits: u64) -> BigUint {
+    BigUint::from(1u32) << bits
+}
+
+// k < 2 ** (SECRET_BITS + CHALLENGE_BITS + SECURITY_BITS), and so is s
+pub fn nonce_bound() -> BigUint {
+    power_of_two(SECRET_BITS + CHALLENGE_BITS + SECURITY_BITS)
+}
+
+// c = H(both groups, y1, y2, y1', y2', r1, r2, r1', r2', context) mod 2 ** 128
+fn challenge(
+    from: &ZKP,
+    (y1, y2): (&BigUint, &BigUint),
+    to: &ZKP,
+    (to_y1, to_y2): (&BigUint, &BigUint),
+    (r1, r2, to_r1, to_r2): (&BigUint, &BigUint, &BigUint, &BigUint),
+    context: &[u8],
+) -> BigUint {
+    let mut transcript = from.transcript(b"zkp-chaum-pedersen/cross-group");
+    transcript.append_biguint(b"to_g", &to.g);
+    transcript.append_biguint(b"to_h", &to.h);
+    transcript.append_biguint(b"to_p", &to.p);
+    transcript.append_biguint(b"to_q", &to.q);
+    transcript.append_biguint(b"y1", y1);
+    transcript.append_biguint(b"y2", y2);
+    transcript.append_biguint(b"to_y1", to_y1);
+    transcript.append_biguint(b"to_y2", to_y2);
+    transcript.append_biguint(b"r1", r1);
+    transcript.append_biguint(b"r2", r2);
+    transcript.append_biguint(b"to_r1", to_r1);
+    transcript.append_biguint(b"to_r2", to_r2);
+    transcript.append(b"context", context);
+    transcript.challenge(&power_of_two(CHALLENGE_BITS))
+}
+
+// non-interactive (Fiat–Shamir) proof for x, bound to context. x is the
+// secret before any reduction (verifier::unreduced_secret): g has order q
+// and g' order q', so only the integer both were reduced from is one x in
+// both groups. None when the orders are too long for SECRET_BITS
+pub fn prove(from: &ZKP, to: &ZKP, x: &BigUint, context: &[u8]) -> Option<Proof> {
+    let orders = &from.q * &to.q;
+    if orders.bits() > SECRET_BITS {
+        return None;
+    }
+    let mut x = x % orders;
+    let y = commit(from, &x);
+    let to_y = commit(to, &x);
+    let bound = nonce_bound();
+    let proof = loop {
+        let mut k = ZKP::generate_random_number_below(&bound);
+        let r = commit(from, &k);
+        let to_r = commit(to, &k);
+        let c = challenge(
+            from,
+            (&y.0, &y.1),
+            to,
+            (&to_y.0, &to_y.1),
+            (&r.0, &r.1, &to_r.0, &to_r.1),
+            context,
+        );
+        let cx = &c * &x;
+        let s = (k >= cx).then(|| &k - cx);
+        wipe_biguint(&mut k);
+        // k < c * x, about once in 2 ** SECURITY_BITS, would make s negative
+        if let Some(s) = s {
+            break Proof { c, s };
+        }
+    };
+    wipe_biguint(&mut x);
+    Some(proof)
+}
+
+// (g ** e mod p, h ** e mod p)
+fn commit(zkp: &ZKP, e: &BigUint) -> (BigUint, BigUint) {
+    (
+        ZKP::exponentiate(&zkp.g, e, &zkp.p),
+        ZKP::exponentiate(&zkp.h, e, &zkp.p),
+    )
+}
+
+// r1 = g ** s * y1 ** c mod p, and the same in the second group, and the
+// proof holds if hashing them gives back c
+pub fn verify(
+    from: &ZKP,
+    (y1, y2): (&BigUint, &BigUint),
+    to: &ZKP,
+    (to_y1, to_y2): (&BigUint, &BigUint),
+    proof: &Proof,
+    context: &[u8],
+) -> bool {
+    // y = 0 gives r = 0 for every s, which would let anyone forge a proof
+    let in_group = |zkp: &ZKP, y: &BigUint| *y > BigUint::from(0u32) && *y < zkp.p;
+    if !(in_group(from, y1) && in_group(from, y2) && in_group(to, to_y1) && in_group(to, to_y2))
+        || proof.c >= power_of_two(CHALLENGE_BITS)
+        || proof.s >= nonce_bound()
+    {
+        return false;
+    }
+    let (r1, r2) = from.proof_commitments(y1, y2, proof);
+    let (to_r1, to_r2) = to.proof_commitments(to_y1, to_y2, proof);
+    proof.c
+        == challenge(
+            from,
+            (y1, y2),
+            to,
+            (to_y1, to_y2),
+            (&r1, &r2, &to_r1, &to_r2),
+            context,
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groups;
+    use crate::verifier::{self, KdfParams};
+
+    #[test]
+    fn test_registration_moves_between_groups() {
+        let from = groups::by_name(groups::RFC5114_1024_160).unwrap();
+        let to = groups::by_name(groups::RFC5114_2048_256).unwrap();
+        let kdf = KdfParams::Pbkdf2 { iterations: 1000 };
+        let salt = b"saltsalt";
+        let x = verifier::unreduced_secret("alice", "password", salt, &kdf);
+        // the verifier alice registered, and the one she logs in with after
+        let old = verifier::derive_secret_with(&from, "alice", "password", salt, &kdf);
+        let new = verifier::derive_secret_with(&to, "alice", "password", salt, &kdf);
+        let (y1, y2) = commit(&from, old.expose());
+        let (to_y1, to_y2) = commit(&to, new.expose());
+
+        let honest = prove(&from, &to, x.expose(), b"alice").unwrap();
+        assert!(verify(
+            &from,
+            (&y1, &y2),
+            &to,
+            (&to_y1, &to_y2),
+            &honest,
+            b"alice"
+        ));
+        assert!(!verify(
+            &from,
+            (&y1, &y2),
+            &to,
+            (&to_y1, &to_y2),
+            &honest,
+            b"bob"
+        ));
+
+        // a verifier in the new group from another password doesn't verify
+        let other = verifier::derive_secret_with(&to, "alice", "guess", salt, &kdf);
+        let (other_y1, other_y2) = commit(&to, other.expose());
+        assert!(!verify(
+            &from,
+            (&y1, &y2),
+            &to,
+            (&other_y1, &other_y2),
+            &honest,
+            b"alice"
+        ));
+        let proof = prove(&from, &to, other.expose(), b"alice").unwrap();
+        assert!(!verify(
+            &from,
+            (&y1, &y2),
+            &to,
+            (&other_y1, &other_y2),
+            &proof,
+            b"alice"
+        ));
+
+        // nor does a response past the bound
+        let proof = Proof {
+            c: honest.c,
+            s: honest.s + nonce_bound(),
+        };
+        assert!(!verify(
+            &from,
+            (&y1, &y2),
+            &to,
+            (&to_y1, &to_y2),
+            &proof,
+            b"alice"
+        ));
+    }
+
+    #[test]
+    fn test_unsalted_password_moves_between_groups() {
+        let from = groups::by_name(groups::RFC5114_1024_160).unwrap();
+        let to = groups::by_name(groups::RFC5114_2048_256).unwrap();
+        // longer than SECRET_BITS, reduced by both orders first
+        let x =
+            verifier::unreduced_secret("alice", &"password".repeat(10), b"", &KdfParams::default());
+        let (y1, y2) = commit(&from, x.expose());
+        let (to_y1, to_y2) = commit(&to, x.expose());
+        let proof = prove(&from, &to, x.expose(), b"").unwrap();
+        assert!(verify(
+            &from,
+            (&y1, &y2),
+            &to,
+            (&to_y1, &to_y2),
+            &proof,
+            b""
+        ));
+    }
+}
@@ -0,0 +1,141 @@
+use daemonize::{Daemonize, Stdio};
+use listenfd::ListenFd;
+use sd_notify::NotifyState;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// running the server as a service: in the background with a pid file, or
+// under systemd with Type=notify, WatchdogSec= and socket activation. the
+// systemd calls do nothing when the server wasn't started by systemd
+
+// the server's process ID, in a file locked for as long as the server runs.
+// a second server given the same file refuses to start; a file left behind
+// by a server that crashed isn't locked and is taken over. removed on drop
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+    // holds the lock
+    _file: File,
+}
+
+impl PidFile {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    "another server is running with this pid file",
+                ));
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+        Ok(PidFile {
+            path: path.to_path_buf(),
+            _file: file,
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// forks into the background and returns in the daemon; the process that
+// called it exits. must run before the tokio runtime starts its threads.
+// the working directory is kept so relative paths in the flags still work.
+// stdout and stderr go to log, appended, or are dropped without one
+pub fn daemonize(log: Option<&Path>) -> Result<(), String> {
+    let output = |log: Option<&Path>| -> Result<Stdio, String> {
+        match log {
+            Some(path) => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map(Stdio::from)
+                .map_err(|e| format!("Failed to open {}: {}", path.display(), e)),
+            None => Ok(Stdio::devnull()),
+        }
+    };
+    let working_directory = std::env::current_dir().map_err(|e| e.to_string())?;
+    Daemonize::new()
+        .working_directory(working_directory)
+        .umask(0o027)
+        .stdout(output(log)?)
+        .stderr(output(log)?)
+        .start()
+        .map_err(|e| format!("Failed to daemonize: {}", e))
+}
+
+// the listening socket systemd passed (LISTEN_FDS, from a .socket unit), if
+// any. it is set non-blocking, for tokio
+pub fn activated_listener() -> std::io::Result<Option<TcpListener>> {
+    let listener = ListenFd::from_env().take_tcp_listener(0)?;
+    if let Some(listener) = &listener {
+        listener.set_nonblocking(true)?;
+    }
+    Ok(listener)
+}
+
+// READY=1, once the server accepts connections
+pub fn notify_ready() {
+    let _ = sd_notify::notify(false, &[NotifyState::Ready]);
+}
+
+// STOPPING=1, when a shutdown begins
+pub fn notify_stopping() {
+    let _ = sd_notify::notify(false, &[NotifyState::Stopping]);
+}
+
+// how often to send WATCHDOG=1: half of WatchdogSec=, None without a watchdog
+pub fn watchdog_interval() -> Option<Duration> {
+    let mut usec = 0;
+    sd_notify::watchdog_enabled(false, &mut usec).then(|| Duration::from_micros(usec / 2))
+}
+
+// sends WATCHDOG=1 every interval. run on the server's runtime, so a runtime
+// that stops making progress stops the pings and systemd restarts the server
+pub async fn keep_watchdog_fed(interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        let _ = sd_notify::notify(false, &[NotifyState::Watchdog]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_file_is_locked_while_held() {
+        let path = std::env::temp_dir().join(format!("zkp-server-{}.pid", std::process::id()));
+        let pid_file = PidFile::create(&path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.trim(), std::process::id().to_string());
+        let e = PidFile::create(&path).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::AlreadyExists);
+
+        drop(pid_file);
+        assert!(!path.exists());
+        // a stale file, from a server that didn't remove it, is taken over
+        std::fs::write(&path, "1\n").unwrap();
+        let pid_file = PidFile::create(&path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.trim(), std::process::id().to_string());
+        drop(pid_file);
+    }
+}
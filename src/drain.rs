@@ -0,0 +1,117 @@
+use tokio::sync::watch;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+
+// drain mode, for rolling deploys: a draining server turns away registrations
+// and new challenges with UNAVAILABLE so clients move to another instance, but
+// still takes the answers to challenges it already issued and keeps
+// validating, refreshing and ending sessions. the server's health service
+// reports NOT_SERVING while it drains
+#[derive(Debug)]
+pub struct Drain(watch::Sender<bool>);
+
+impl Default for Drain {
+    fn default() -> Self {
+        Drain(watch::Sender::new(false))
+    }
+}
+
+impl Drain {
+    pub fn is_draining(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    // returns whether the server was draining before
+    pub fn set(&self, draining: bool) -> bool {
+        self.0.send_replace(draining)
+    }
+
+    // sees every change, e.g. to update the health service
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.0.subscribe()
+    }
+
+    // keeps the health status of the services (and of the server as a whole,
+    // "") at SERVING, or NOT_SERVING while draining, until the drain is dropped
+    pub fn report_health(
+        &self,
+        reporter: HealthReporter,
+        services: &[&'static str],
+    ) -> impl Future<Output = ()> + Send + 'static {
+        let mut changes = self.subscribe();
+        let services = [""]
+            .into_iter()
+            .chain(services.iter().copied())
+            .collect::<Vec<_>>();
+        async move {
+            loop {
+                let status = match *changes.borrow_and_update() {
+                    true => ServingStatus::NotServing,
+                    false => ServingStatus::Serving,
+                };
+                for service in &services {
+                    reporter.set_service_status(*service, status).await;
+                }
+                if changes.changed().await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drain_is_switched_and_watched() {
+        let drain = Drain::default();
+        let mut changes = drain.subscribe();
+        assert!(!drain.is_draining());
+        assert!(!drain.set(true));
+        assert!(drain.is_draining());
+        assert!(changes.has_changed().unwrap());
+        assert!(*changes.borrow_and_update());
+        assert!(drain.set(false));
+        assert!(!*changes.borrow_and_update());
+    }
+
+    #[tokio::test]
+    async fn test_health_follows_the_drain() {
+        use tonic_health::pb::health_check_response::ServingStatus as Status;
+        use tonic_health::pb::health_server::Health;
+        use tonic_health::pb::HealthCheckRequest;
+        use tonic_health::server::HealthService;
+
+        let drain = Drain::default();
+        let reporter = HealthReporter::new();
+        let health = HealthService::from_health_reporter(reporter.clone());
+        let reporting = tokio::spawn(drain.report_health(reporter, &["zkp_auth.Auth"]));
+        // the reporter runs on its own task
+        let settled = async |service: &str, expected: Status| {
+            for _ in 0..100 {
+                let request = tonic::Request::new(HealthCheckRequest {
+                    service: service.to_string(),
+                });
+                let status = health
+                    .check(request)
+                    .await
+                    .map(|response| response.into_inner().status());
+                if status.ok() == Some(expected) {
+                    return true;
+                }
+                tokio::task::yield_now().await;
+            }
+            false
+        };
+        assert!(settled("zkp_auth.Auth", Status::Serving).await);
+        drain.set(true);
+        assert!(settled("", Status::NotServing).await);
+        assert!(settled("zkp_auth.Auth", Status::NotServing).await);
+        drain.set(false);
+        assert!(settled("", Status::Serving).await);
+        drop(drain);
+        reporting.await.unwrap();
+    }
+}
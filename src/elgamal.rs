@@ -0,0 +1,93 @@
+use crate::ZKP;
+use num_bigint::BigUint;
+
+// exponential ElGamal under the public key y = g ** x mod p:
+// (a, b) = (g ** r mod p, g ** m * y ** r mod p)
+// the message sits in the exponent, so ciphertexts add up under multiplication
+// and a tally only has to search a small range when decrypting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ciphertext {
+    pub a: BigUint,
+    pub b: BigUint,
+}
+
+pub fn public_key(zkp: &ZKP, x: &BigUint) -> BigUint {
+    ZKP::exponentiate(&zkp.g, x, &zkp.p)
+}
+
+pub fn encrypt(zkp: &ZKP, y: &BigUint, m: &BigUint, r: &BigUint) -> Ciphertext {
+    Ciphertext {
+        a: ZKP::exponentiate(&zkp.g, r, &zkp.p),
+        b: (ZKP::exponentiate(&zkp.g, m, &zkp.p) * ZKP::exponentiate(y, r, &zkp.p)) % &zkp.p,
+    }
+}
+
+// Chaum-Pedersen bases (g, y): proofs that log_g u = log_y v, which is what
+// ballot and re-encryption proofs are made of
+pub fn key_bases(zkp: &ZKP, y: &BigUint) -> ZKP {
+    ZKP {
+        p: zkp.p.clone(),
+        q: zkp.q.clone(),
+        g: zkp.g.clone(),
+        h: y.clone(),
+    }
+}
+
+// (a1 * a2 mod p, b1 * b2 mod p) encrypts m1 + m2
+pub fn add(zkp: &ZKP, left: &Ciphertext, right: &Ciphertext) -> Ciphertext {
+    Ciphertext {
+        a: (&left.a * &right.a) % &zkp.p,
+        b: (&left.b * &right.b) % &zkp.p,
+    }
+}
+
+// g ** m = b / a ** x mod p
+pub fn decrypt_to_element(zkp: &ZKP, x: &BigUint, ciphertext: &Ciphertext) -> Option<BigUint> {
+    let shared = ZKP::exponentiate(&ciphertext.a, x, &zkp.p);
+    let inverse = shared.modinv(&zkp.p)?;
+    Some((&ciphertext.b * inverse) % &zkp.p)
+}
+
+// m in 0..=max, found by trying every value
+pub fn decrypt(zkp: &ZKP, x: &BigUint, ciphertext: &Ciphertext, max: u64) -> Option<u64> {
+    let element = decrypt_to_element(zkp, x, ciphertext)?;
+    let mut candidate = BigUint::from(1u32);
+    for m in 0..=max {
+        if candidate == element {
+            return Some(m);
+        }
+        candidate = (candidate * &zkp.g) % &zkp.p;
+    }
+    None
+}
+
+// element of the order-q subgroup
+pub fn in_subgroup(zkp: &ZKP, n: &BigUint) -> bool {
+    let one = BigUint::from(1u32);
+    *n >= one && *n < zkp.p && ZKP::exponentiate(n, &zkp.q, &zkp.p) == one
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_tally() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let y = public_key(&zkp, &x);
+
+        let votes = [1u32, 0, 1, 1, 0];
+        let tally = votes
+            .iter()
+            .map(|vote| {
+                let r = ZKP::generate_random_number_below(&zkp.q);
+                encrypt(&zkp, &y, &BigUint::from(*vote), &r)
+            })
+            .reduce(|left, right| add(&zkp, &left, &right))
+            .unwrap();
+        assert_eq!(decrypt(&zkp, &x, &tally, votes.len() as u64), Some(3));
+        assert_eq!(decrypt(&zkp, &x, &tally, 2), None);
+    }
+}
@@ -0,0 +1,117 @@
+use num_bigint::BigUint;
+use std::fmt::Display;
+
+// big-endian conversions that write into a caller's buffer instead of
+// allocating a Vec per number, for the hot paths that only hash or copy the
+// bytes (transcripts hash every element of every login)
+
+// bytes of the largest group element of the named groups (2048-bit p), the
+// size of the stack buffers elements are written into
+pub const MAX_ELEMENT_LEN: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingError {
+    pub needed: usize,
+    pub available: usize,
+}
+
+impl Display for EncodingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} bytes needed, the buffer holds {}",
+            self.needed, self.available
+        )
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+// length of to_bytes_be: the fewest bytes that hold n, one for zero
+pub fn be_len(n: &BigUint) -> usize {
+    n.bits().div_ceil(8).max(1) as usize
+}
+
+// n as big-endian bytes filling all of `out`, zero-padded on the left
+pub fn write_be_into(n: &BigUint, out: &mut [u8]) -> Result<(), EncodingError> {
+    let needed = be_len(n);
+    if needed > out.len() {
+        return Err(EncodingError {
+            needed,
+            available: out.len(),
+        });
+    }
+    out.fill(0);
+    let mut end = out.len();
+    for digit in n.iter_u64_digits() {
+        // the top digit's high bytes are zero, and may not fit
+        let take = end.min(8);
+        out[end - take..end].copy_from_slice(&digit.to_be_bytes()[8 - take..]);
+        end -= take;
+    }
+    Ok(())
+}
+
+// BigUint::from_bytes_be for a fixed-size input (a digest), without its
+// temporary reversed copy: the digits are gathered on the stack and the
+// result's are the only allocation
+pub fn from_be_exact<const N: usize>(bytes: &[u8; N]) -> BigUint {
+    let mut digits = [0u32; MAX_ELEMENT_LEN / 4];
+    if N > MAX_ELEMENT_LEN {
+        return BigUint::from_bytes_be(bytes);
+    }
+    let mut count = 0;
+    for chunk in bytes.rchunks(4) {
+        let mut digit = [0u8; 4];
+        digit[4 - chunk.len()..].copy_from_slice(chunk);
+        digits[count] = u32::from_be_bytes(digit);
+        count += 1;
+    }
+    BigUint::from_slice(&digits[..count])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groups;
+
+    #[test]
+    fn test_matches_to_bytes_be() {
+        let zkp = groups::by_name(groups::RFC5114_2048_256).unwrap();
+        for n in [
+            BigUint::from(0u32),
+            BigUint::from(0x1234u32),
+            BigUint::from(u64::MAX),
+            &zkp.p - 1u32,
+            zkp.q.clone(),
+        ] {
+            let len = be_len(&n);
+            let mut buffer = [0xffu8; MAX_ELEMENT_LEN];
+            write_be_into(&n, &mut buffer[..len]).unwrap();
+            assert_eq!(buffer[..len], n.to_bytes_be()[..]);
+
+            // a wider buffer is zero-padded
+            write_be_into(&n, &mut buffer).unwrap();
+            assert_eq!(BigUint::from_bytes_be(&buffer), n);
+            assert!(buffer[..MAX_ELEMENT_LEN - len].iter().all(|&b| b == 0));
+        }
+
+        let mut short = [0u8; 2];
+        assert_eq!(
+            write_be_into(&BigUint::from(0x10000u32), &mut short),
+            Err(EncodingError {
+                needed: 3,
+                available: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_be_exact() {
+        let digest: [u8; 32] = std::array::from_fn(|i| i as u8 * 7 + 1);
+        assert_eq!(from_be_exact(&digest), BigUint::from_bytes_be(&digest));
+        let odd = [0u8, 0, 1, 2, 3];
+        assert_eq!(from_be_exact(&odd), BigUint::from(0x010203u32));
+        assert_eq!(from_be_exact(&[0u8; 8]), BigUint::from(0u32));
+    }
+}
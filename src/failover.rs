@@ -0,0 +1,179 @@
+use crate::login::{self, AuthError, ChannelBinding, LoginOptions, PendingLogin};
+use crate::session_key::SessionKey;
+use crate::zkp_auth::auth_client::AuthClient;
+use crate::ZKP;
+use tonic::transport::Channel;
+use tonic::{Code, Status};
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+
+// the servers of one deployment, tried in order. calls go to the current one,
+// and when it's unreachable or draining the next one that passes its health
+// check takes over. a challenge lives on the server that issued it unless the
+// servers share their state, so its answer goes back there
+
+// a server and the channel to it, connected lazily so one that is down
+// doesn't hold up the others
+#[derive(Debug, Clone)]
+pub struct Server {
+    pub address: String,
+    pub channel: Channel,
+    // the tls-server-end-point binding of this server's connection
+    pub channel_binding: ChannelBinding,
+}
+
+// a challenge and the index of the server that issued it
+#[derive(Debug)]
+pub struct PendingOn<'z> {
+    pub server: usize,
+    pub login: PendingLogin<'z>,
+}
+
+#[derive(Debug)]
+pub struct Failover {
+    servers: Vec<Server>,
+    current: usize,
+    // every server knows every challenge (a shared store behind them), so an
+    // answer may go to another server than the one that issued it
+    pub shared_state: bool,
+}
+
+impl Failover {
+    pub fn new(servers: Vec<Server>) -> Self {
+        assert!(!servers.is_empty(), "failover needs a server");
+        Failover {
+            servers,
+            current: 0,
+            shared_state: false,
+        }
+    }
+
+    pub fn servers(&self) -> &[Server] {
+        &self.servers
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn client(&self, server: usize) -> AuthClient<Channel> {
+        AuthClient::new(self.servers[server].channel.clone())
+    }
+
+    // whether the server reports SERVING on grpc.health.v1 for the Auth
+    // service. one that doesn't serve grpc.health counts as serving if it
+    // answers at all
+    pub async fn probe(&self, server: usize) -> bool {
+        let mut health = HealthClient::new(self.servers[server].channel.clone());
+        let request = HealthCheckRequest {
+            service: crate::zkp_auth::auth_server::SERVICE_NAME.to_string(),
+        };
+        match health.check(request).await {
+            Ok(response) => response.into_inner().status() == ServingStatus::Serving,
+            Err(status) => status.code() == Code::Unimplemented,
+        }
+    }
+
+    // the current server if it passes its health check, else the next one
+    // that does, which becomes current
+    pub async fn healthy(&mut self) -> Result<usize, Status> {
+        for offset in 0..self.servers.len() {
+            let server = (self.current + offset) % self.servers.len();
+            if self.probe(server).await {
+                self.current = server;
+                return Ok(server);
+            }
+        }
+        let addresses: Vec<_> = self.servers.iter().map(|s| s.address.as_str()).collect();
+        Err(Status::unavailable(format!(
+            "no server is serving: {}",
+            addresses.join(", ")
+        )))
+    }
+
+    // the options with the binding of the server's connection
+    fn options_for<'a>(&'a self, server: usize, options: &LoginOptions<'a>) -> LoginOptions<'a> {
+        LoginOptions {
+            channel_binding: &self.servers[server].channel_binding,
+            ..*options
+        }
+    }
+
+    // on the current healthy server, and on the next one while they turn
+    // out to be unavailable
+    pub async fn create_challenge<'z>(
+        &mut self,
+        zkp: &'z ZKP,
+        user: &str,
+        options: &LoginOptions<'_>,
+    ) -> Result<PendingOn<'z>, AuthError> {
+        let mut last = None;
+        for _ in 0..self.servers.len() {
+            let server = self.healthy().await?;
+            let options = self.options_for(server, options);
+            match login::create_challenge(&mut self.client(server), zkp, user, &options).await {
+                Ok(login) => return Ok(PendingOn { server, login }),
+                Err(status) => match AuthError::from(status) {
+                    AuthError::ServerUnavailable(status) => {
+                        last = Some(status);
+                        self.current = (server + 1) % self.servers.len();
+                    }
+                    error => return Err(error),
+                },
+            }
+        }
+        Err(AuthError::ServerUnavailable(
+            last.expect("a server was tried"),
+        ))
+    }
+
+    // to the server that issued the challenge, or with shared state to a
+    // healthy one when that one fails its health check
+    pub async fn answer_challenge(
+        &mut self,
+        zkp: &ZKP,
+        pending: PendingOn<'_>,
+        user: &str,
+        password: &str,
+        options: &LoginOptions<'_>,
+    ) -> Result<(String, Option<SessionKey>), AuthError> {
+        let mut server = pending.server;
+        if self.shared_state && !self.probe(server).await {
+            server = self.healthy().await?;
+        }
+        let options = self.options_for(server, options);
+        let mut client = self.client(server);
+        Ok(
+            login::answer_challenge(&mut client, zkp, pending.login, user, password, &options)
+                .await?,
+        )
+    }
+
+    // a whole login, started over on the next server when the one that
+    // issued the challenge goes away before the answer
+    pub async fn login(
+        &mut self,
+        zkp: &ZKP,
+        user: &str,
+        password: &str,
+        options: &LoginOptions<'_>,
+    ) -> Result<(String, Option<SessionKey>), AuthError> {
+        let mut result = None;
+        for _ in 0..self.servers.len() {
+            let pending = self.create_challenge(zkp, user, options).await?;
+            let server = pending.server;
+            match self
+                .answer_challenge(zkp, pending, user, password, options)
+                .await
+            {
+                Err(AuthError::ServerUnavailable(status)) => {
+                    result = Some(Err(AuthError::ServerUnavailable(status)));
+                    self.current = (server + 1) % self.servers.len();
+                }
+                done => return done,
+            }
+        }
+        result.expect("a server was tried")
+    }
+}
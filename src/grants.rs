@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// scopes and claims a session is issued with, reported by ValidateSession and
+// GetSessionInfo so the services behind the auth server can make coarse
+// authorization decisions from a session ID alone. they are fixed at login
+// and kept across refreshes
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Grant {
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub claims: BTreeMap<String, String>,
+}
+
+// what sessions are granted: every session of a realm gets the realm's grant,
+// a user's own grant is added on top (scopes joined, the user's claims
+// replacing the realm's). read from a JSON file like
+// {"realms": {"staff": {"scopes": ["read"]}},
+//  "users": {"alice": {"scopes": ["write"], "claims": {"team": "ops"}}}}
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GrantPolicy {
+    #[serde(default)]
+    pub realms: BTreeMap<String, Grant>,
+    #[serde(default)]
+    pub users: BTreeMap<String, Grant>,
+}
+
+impl GrantPolicy {
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let json = std::fs::read(path)?;
+        serde_json::from_slice(&json)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    // scopes sorted and without duplicates
+    pub fn grant(&self, realm: &str, user: &str) -> Grant {
+        let mut grant = self.realms.get(realm).cloned().unwrap_or_default();
+        if let Some(own) = self.users.get(user) {
+            grant.scopes.extend(own.scopes.iter().cloned());
+            grant.claims.extend(own.claims.clone());
+        }
+        grant.scopes.sort();
+        grant.scopes.dedup();
+        grant
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_grants_add_to_the_realm() {
+        let policy: GrantPolicy = serde_json::from_str(
+            r#"{
+                "realms": {"staff": {"scopes": ["read", "audit"], "claims": {"tier": "basic"}}},
+                "users": {"alice": {"scopes": ["write", "read"], "claims": {"tier": "gold"}}}
+            }"#,
+        )
+        .unwrap();
+        let alice = policy.grant("staff", "alice");
+        assert_eq!(alice.scopes, ["audit", "read", "write"]);
+        assert_eq!(alice.claims["tier"], "gold");
+        let bob = policy.grant("staff", "bob");
+        assert_eq!(bob.scopes, ["audit", "read"]);
+        assert_eq!(bob.claims["tier"], "basic");
+        assert_eq!(policy.grant("", "bob"), Grant::default());
+        assert_eq!(
+            GrantPolicy::default().grant("staff", "alice"),
+            Grant::default()
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use crate::ZKP;
+use num_bigint::BigUint;
+
+// one round of the protocol written against the response arithmetic, so the
+// same prover and verifier code runs in a group of known prime order q
+// (s = k - c * x mod q) and, with the unknown-order feature, in RSA or class
+// groups whose order nobody knows (s = k + c * x over the integers)
+pub trait ChaumPedersenGroup {
+    // (g ** e, h ** e): (r1, r2) for a nonce, (y1, y2) for the secret
+    fn commit(&self, e: &BigUint) -> (BigUint, BigUint);
+
+    fn respond(&self, k: &BigUint, c: &BigUint, x: &BigUint) -> BigUint;
+
+    fn check(
+        &self,
+        r1: &BigUint,
+        r2: &BigUint,
+        y1: &BigUint,
+        y2: &BigUint,
+        c: &BigUint,
+        s: &BigUint,
+    ) -> bool;
+
+    fn random_nonce(&self) -> BigUint;
+
+    fn random_challenge(&self) -> BigUint;
+}
+
+impl ChaumPedersenGroup for ZKP {
+    fn commit(&self, e: &BigUint) -> (BigUint, BigUint) {
+        (
+            ZKP::exponentiate(&self.g, e, &self.p),
+            ZKP::exponentiate(&self.h, e, &self.p),
+        )
+    }
+
+    // s = k - c * x mod q
+    fn respond(&self, k: &BigUint, c: &BigUint, x: &BigUint) -> BigUint {
+        self.solve(k, c, x)
+    }
+
+    fn check(
+        &self,
+        r1: &BigUint,
+        r2: &BigUint,
+        y1: &BigUint,
+        y2: &BigUint,
+        c: &BigUint,
+        s: &BigUint,
+    ) -> bool {
+        self.verify(r1, r2, y1, y2, c, s)
+    }
+
+    fn random_nonce(&self) -> BigUint {
+        ZKP::generate_random_number_below(&self.q)
+    }
+
+    fn random_challenge(&self) -> BigUint {
+        ZKP::generate_random_number_below(&self.q)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // commit, challenge, respond, check with the group's own arithmetic
+    pub(crate) fn run_round<G: ChaumPedersenGroup>(
+        group: &G,
+        x: &BigUint,
+        claimed: &BigUint,
+    ) -> bool {
+        let (y1, y2) = group.commit(x);
+        let k = group.random_nonce();
+        let (r1, r2) = group.commit(&k);
+        let c = group.random_challenge();
+        let s = group.respond(&k, &c, claimed);
+        group.check(&r1, &r2, &y1, &y2, &c, &s)
+    }
+
+    #[test]
+    fn test_known_order_round() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        assert!(run_round(&zkp, &x, &x));
+        assert!(!run_round(&zkp, &x, &(&x + 1u32)));
+    }
+}
@@ -0,0 +1,385 @@
+use crate::montgomery::{FixedBase, Montgomery};
+use crate::transcript::Transcript;
+use crate::ZKP;
+use num_bigint::BigUint;
+#[cfg(feature = "prover")]
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+// named groups users can register under. the name is kept with the user, so
+// the server proves and verifies every login in the group of its user and
+// users can move from the 1024-bit group to a stronger one one at a time
+
+// RFC 5114 2.1: 1024-bit MODP group with a 160-bit prime order subgroup, the
+// group of every user registered before groups had names (ZKP::get_constants)
+pub const RFC5114_1024_160: &str = "rfc5114-1024-160";
+
+// RFC 5114 2.3: 2048-bit MODP group with a 256-bit prime order subgroup
+pub const RFC5114_2048_256: &str = "rfc5114-2048-256";
+
+// for registrations and logins that don't name a group. the server key, sealed
+// identities of blinded logins and offline proofs stay in this group
+pub const DEFAULT: &str = RFC5114_1024_160;
+
+pub const NAMES: &[&str] = &[RFC5114_1024_160, RFC5114_2048_256];
+
+const RFC5114_2048_256_P: &str = "87A8E61DB4B6663CFFBBD19C651959998CEEF608660DD0F25D2CEED4435E3B00E00DF8F1D61957D4FAF7DF4561B2AA3016C3D91134096FAA3BF4296D830E9A7C209E0C6497517ABD5A8A9D306BCF67ED91F9E6725B4758C022E0B1EF4275BF7B6C5BFC11D45F9088B941F54EB1E59BB8BC39A0BF12307F5C4FDB70C581B23F76B63ACAE1CAA6B7902D52526735488A0EF13C6D9A51BFA4AB3AD8347796524D8EF6A167B5A41825D967E144E5140564251CCACB83E6B486F6B3CA3F7971506026C0B857F689962856DED4010ABD0BE621C3A3960A54E710C375F26375D7014103A4B54330C198AF126116D2276E11715F693877FAD7EF09CADB094AE91E1A1597";
+const RFC5114_2048_256_Q: &str = "8CF83642A709A097B447997640129DA299B1A47D1EB3750BA308B0FE64F5FBD3";
+const RFC5114_2048_256_G: &str = "3FB32C9B73134D0B2E77506660EDBD484CA7B18F21EF205407F4793A1A0BA12510DBC15077BE463FFF4FED4AAC0BB555BE3A6C1B0C6B47B1BC3773BF7E8C6F62901228F8C28CBB18A55AE31341000A650196F931C77A57F2DDF463E5E9EC144B777DE62AAAB8A8628AC376D282D6ED3864E67982428EBC831D14348F6F2F9193B5045AF2767164E1DFC967C1FB3F2E55A4BD1BFFE83B9C80D052B985D182EA0ADB2A3B7313D3FE14C8484B1E052588B9B7D2BBD2DF016199ECD06E1557CD0915B3353BBB64E0EC377FD028370DF92B52C7891428CDC67EB6184B523D1DB246C32F63078490F00EF8D647D148D47954515E2327CFEF98C582664B4C0F6CC41659";
+// h = (SHA-256(H_SEED || 0) || ... || SHA-256(H_SEED || 7) mod p) ** ((p - 1) / q) mod p
+// so nobody knows log_g h
+pub const RFC5114_2048_256_H_SEED: &[u8] = b"zkp-chaum-pedersen/rfc5114-2048-256/h";
+const RFC5114_2048_256_H: &str = "245BACCFDCF00B0FD1AEBAE8CEE4BDDFC0A7991C02FC251CE5C5CCADC390199E38F4BB08216143F259C370C4D856BF953ED76F0256314F2CB869B80E91F8873511C14AF24294389C20C9F3EA8A5A0052D0AB25DC35A9039CAFE79E06D8A1746BFA81DFFA045AAF303F5BDBD39CE5B2811C48B3FF05459E410069F14A7FC0725D157F9E426EC5D04AC3252324AAFDE3BEA0D2B89F6BD4F019F0F02344AA3EC3A07D4FB6C3A5CE79AFF8F95DDC8EAD25304AB68AEA7332A9A485659C407CA2BFB875B0C0A71123E833BD1D251F825664A0ABF1DBE27CC4C44303ED9F407E6C654A408430C79214C90A3D7C8D4269188AB76B8A0CB11A44BE28F0818D800D0A630A";
+
+fn from_hex(value: &str) -> BigUint {
+    BigUint::parse_bytes(value.as_bytes(), 16).expect("group constants are hex")
+}
+
+// an empty name is the default group
+pub fn resolve(name: &str) -> &str {
+    if name.is_empty() {
+        DEFAULT
+    } else {
+        name
+    }
+}
+
+pub fn by_name(name: &str) -> Option<ZKP> {
+    match resolve(name) {
+        RFC5114_1024_160 => Some(default_group()),
+        RFC5114_2048_256 => Some(ZKP {
+            p: from_hex(RFC5114_2048_256_P),
+            q: from_hex(RFC5114_2048_256_Q),
+            g: from_hex(RFC5114_2048_256_G),
+            h: from_hex(RFC5114_2048_256_H),
+        }),
+        _ => None,
+    }
+}
+
+pub fn default_group() -> ZKP {
+    let (g, h, p, q) = ZKP::get_constants();
+    ZKP { p, q, g, h }
+}
+
+// the structure every proof relies on: q divides p - 1, and g and h are
+// distinct generators of the order-q subgroup. primality is left to the
+// self-test, it is too slow to check on every start
+pub fn validate(zkp: &ZKP) -> Result<(), String> {
+    let one = BigUint::from(1u32);
+    if zkp.q <= one || (&zkp.p - &one) % &zkp.q != BigUint::from(0u32) {
+        return Err("q does not divide p - 1".to_string());
+    }
+    for (name, base) in [("g", &zkp.g), ("h", &zkp.h)] {
+        if *base <= one || *base >= zkp.p || ZKP::exponentiate(base, &zkp.q, &zkp.p) != one {
+            return Err(format!("{} does not generate the order-q subgroup", name));
+        }
+    }
+    if zkp.g == zkp.h {
+        return Err("g and h are equal".to_string());
+    }
+    Ok(())
+}
+
+// every named group, parsed and validated once and shared by the handlers
+// instead of being rebuilt from its constants on each request, with
+// precomputed powers of its g and h
+#[derive(Debug, Clone)]
+pub struct Groups {
+    named: Vec<(&'static str, Arc<ZKP>, Arc<Bases>)>,
+}
+
+// fixed-base tables of g and h, for exponents below q
+#[derive(Debug, Clone)]
+pub struct Bases {
+    pub g: FixedBase,
+    pub h: FixedBase,
+}
+
+impl Bases {
+    pub fn new(zkp: &ZKP) -> Option<Self> {
+        let montgomery = Montgomery::shared(&zkp.p)?;
+        Some(Bases {
+            g: FixedBase::new(montgomery.clone(), &zkp.g, zkp.q.bits()),
+            h: FixedBase::new(montgomery, &zkp.h, zkp.q.bits()),
+        })
+    }
+
+    // (g ** e, h ** e)
+    pub fn commit(&self, e: &BigUint) -> (BigUint, BigUint) {
+        (self.g.pow(e), self.h.pow(e))
+    }
+}
+
+impl Groups {
+    pub fn new() -> Result<Self, String> {
+        let named = NAMES
+            .iter()
+            .map(|name| {
+                let zkp = by_name(name).expect("named groups resolve");
+                validate(&zkp).map_err(|e| format!("group {}: {}", name, e))?;
+                let bases =
+                    Bases::new(&zkp).ok_or_else(|| format!("group {}: p is not odd", name))?;
+                Ok((*name, Arc::new(zkp), Arc::new(bases)))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Groups { named })
+    }
+
+    // an empty name is the default group
+    pub fn get(&self, name: &str) -> Option<Arc<ZKP>> {
+        let name = resolve(name);
+        self.named
+            .iter()
+            .find(|(named, _, _)| *named == name)
+            .map(|(_, zkp, _)| zkp.clone())
+    }
+
+    pub fn bases(&self, name: &str) -> Option<Arc<Bases>> {
+        let name = resolve(name);
+        self.named
+            .iter()
+            .find(|(named, _, _)| *named == name)
+            .map(|(_, _, bases)| bases.clone())
+    }
+
+    pub fn default_group(&self) -> Arc<ZKP> {
+        self.get(DEFAULT).expect("the default group is named")
+    }
+}
+
+impl Default for Groups {
+    fn default() -> Self {
+        Groups::new().expect("named groups are valid")
+    }
+}
+
+// name of a group with exactly these parameters
+pub fn name_of(zkp: &ZKP) -> Option<&'static str> {
+    NAMES.iter().copied().find(|name| {
+        by_name(name).is_some_and(|group| {
+            group.p == zkp.p && group.q == zkp.q && group.g == zkp.g && group.h == zkp.h
+        })
+    })
+}
+
+// SHA-256 of the group's canonical encoding: p, q, g and h as length-prefixed
+// minimal big-endian numbers in a transcript of their own. clients pin it to
+// notice a server that swapped its parameters
+pub fn fingerprint(zkp: &ZKP) -> [u8; 32] {
+    let mut transcript = Transcript::new(b"zkp-chaum-pedersen/group-fingerprint");
+    for (label, n) in [
+        (&b"p"[..], &zkp.p),
+        (b"q", &zkp.q),
+        (b"g", &zkp.g),
+        (b"h", &zkp.h),
+    ] {
+        transcript.append_biguint(label, n);
+    }
+    transcript.finalize()
+}
+
+// Miller-Rabin with random bases, wrong with probability at most 4 ** -rounds
+#[cfg(feature = "prover")]
+pub fn is_probable_prime(n: &BigUint, rounds: usize) -> bool {
+    let (one, two) = (BigUint::from(1u32), BigUint::from(2u32));
+    if *n < two {
+        return false;
+    }
+    for small in [2u32, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37] {
+        if *n == BigUint::from(small) {
+            return true;
+        }
+        if n % small == BigUint::from(0u32) {
+            return false;
+        }
+    }
+
+    // n - 1 = d * 2 ** r with d odd
+    let n_minus_one = n - &one;
+    let r = n_minus_one.trailing_zeros().unwrap_or(0);
+    let d = &n_minus_one >> r;
+    'witness: for _ in 0..rounds {
+        // a in [2, n - 2]
+        let a = ZKP::generate_random_number_below(&(n - 3u32)) + &two;
+        let mut x = ZKP::exponentiate(&a, &d, n);
+        if x == one || x == n_minus_one {
+            continue;
+        }
+        for _ in 1..r {
+            x = ZKP::exponentiate(&x, &two, n);
+            if x == n_minus_one {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+// a fresh group with a p_bits-bit p and a q_bits-bit q: q prime, p = m * q + 1
+// prime, g a random generator of the order-q subgroup and h hashed from the
+// others (like the 2048-bit group's), so nobody knows log_g h. slow for real
+// sizes, a 2048-bit p takes seconds to minutes
+#[cfg(feature = "prover")]
+pub fn generate(p_bits: u64, q_bits: u64) -> Result<ZKP, String> {
+    if q_bits < 16 || p_bits < q_bits + 16 {
+        return Err("p needs at least 16 bits more than q, q at least 16".to_string());
+    }
+    let one = BigUint::from(1u32);
+    let q = loop {
+        let q = random_bits(q_bits) | &one;
+        if is_probable_prime(&q, 32) {
+            break q;
+        }
+    };
+    let p = loop {
+        // an even m of p_bits - q_bits bits makes m * q + 1 odd and p_bits long
+        let m = random_bits(p_bits - q_bits) >> 1u32 << 1u32;
+        let p = &m * &q + &one;
+        if p.bits() == p_bits && is_probable_prime(&p, 32) {
+            break p;
+        }
+    };
+    let cofactor = (&p - &one) / &q;
+    let g = loop {
+        let base = ZKP::generate_random_number_below(&p);
+        let g = ZKP::exponentiate(&base, &cofactor, &p);
+        if g > one {
+            break g;
+        }
+    };
+    let mut transcript = Transcript::new(b"zkp-chaum-pedersen/generated-h");
+    for (label, n) in [(&b"p"[..], &p), (b"q", &q), (b"g", &g)] {
+        transcript.append_biguint(label, n);
+    }
+    let seed = transcript.finalize();
+    let h = (0u32..)
+        .map(|counter| {
+            let mut digest = Vec::new();
+            for i in 0..p_bits.div_ceil(256) + 1 {
+                digest.extend(
+                    Sha256::new()
+                        .chain_update(seed)
+                        .chain_update(counter.to_be_bytes())
+                        .chain_update(i.to_be_bytes())
+                        .finalize(),
+                );
+            }
+            ZKP::exponentiate(&(BigUint::from_bytes_be(&digest) % &p), &cofactor, &p)
+        })
+        .find(|h| *h > one && *h != g)
+        .expect("some counter gives a generator");
+    let zkp = ZKP { p, q, g, h };
+    validate(&zkp)?;
+    Ok(zkp)
+}
+
+// a random number of exactly `bits` bits
+#[cfg(feature = "prover")]
+fn random_bits(bits: u64) -> BigUint {
+    let top = BigUint::from(1u32) << (bits - 1);
+    ZKP::generate_random_number_below(&top) + top
+}
+
+#[cfg(all(test, feature = "prover"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_groups() {
+        let one = BigUint::from(1u32);
+        for name in NAMES {
+            let zkp = by_name(name).unwrap();
+            assert_eq!(name_of(&zkp), Some(*name));
+            assert_eq!((&zkp.p - &one) % &zkp.q, BigUint::from(0u32));
+            assert_eq!(ZKP::exponentiate(&zkp.g, &zkp.q, &zkp.p), one);
+            assert_eq!(ZKP::exponentiate(&zkp.h, &zkp.q, &zkp.p), one);
+            // the stack buffers elements are encoded into hold this group's
+            assert!(zkp.element_len() <= crate::encoding::MAX_ELEMENT_LEN);
+        }
+        assert_eq!(name_of(&by_name("").unwrap()), Some(DEFAULT));
+        assert!(by_name("toy-23-11").is_none());
+
+        // h of the 2048-bit group is what its seed hashes to
+        let zkp = by_name(RFC5114_2048_256).unwrap();
+        let seed: Vec<u8> = (0u8..8)
+            .flat_map(|i| {
+                Sha256::new()
+                    .chain_update(RFC5114_2048_256_H_SEED)
+                    .chain_update([i])
+                    .finalize()
+            })
+            .collect();
+        let base = BigUint::from_bytes_be(&seed) % &zkp.p;
+        let h = ZKP::exponentiate(&base, &((&zkp.p - &one) / &zkp.q), &zkp.p);
+        assert_eq!(h, zkp.h);
+    }
+
+    #[test]
+    fn test_shared_groups() {
+        let groups = Groups::new().unwrap();
+        assert!(Arc::ptr_eq(
+            &groups.get("").unwrap(),
+            &groups.default_group()
+        ));
+        assert_eq!(
+            name_of(&groups.get(RFC5114_2048_256).unwrap()),
+            Some(RFC5114_2048_256)
+        );
+        assert!(groups.get("toy-23-11").is_none());
+        let zkp = groups.default_group();
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        assert_eq!(
+            groups.bases("").unwrap().commit(&x),
+            (
+                ZKP::exponentiate(&zkp.g, &x, &zkp.p),
+                ZKP::exponentiate(&zkp.h, &x, &zkp.p)
+            )
+        );
+
+        let mut broken = default_group();
+        assert_eq!(validate(&broken), Ok(()));
+        broken.h = broken.g.clone();
+        assert!(validate(&broken).is_err());
+        broken.h = BigUint::from(1u32);
+        assert!(validate(&broken).is_err());
+        broken.q += 1u32;
+        assert!(validate(&broken).is_err());
+    }
+
+    #[test]
+    fn test_probable_primes() {
+        let (_, _, p, q) = ZKP::get_constants();
+        assert!(is_probable_prime(&p, 16));
+        assert!(is_probable_prime(&q, 16));
+        assert!(!is_probable_prime(&(&p * &q), 16));
+        // 41 * 61 * 101, a carmichael number: fools the fermat test, not this one
+        assert!(!is_probable_prime(&BigUint::from(252_601u32), 16));
+        assert!(is_probable_prime(&BigUint::from(23u32), 16));
+    }
+
+    #[test]
+    fn test_generated_group() {
+        let zkp = generate(256, 64).unwrap();
+        assert_eq!((zkp.p.bits(), zkp.q.bits()), (256, 64));
+        assert!(is_probable_prime(&zkp.p, 16));
+        assert!(is_probable_prime(&zkp.q, 16));
+        assert!(validate(&zkp).is_ok());
+        assert!(generate(64, 60).is_err());
+    }
+
+    #[test]
+    fn test_fingerprints() {
+        let default = default_group();
+        assert_eq!(fingerprint(&default), fingerprint(&by_name("").unwrap()));
+        assert_ne!(
+            fingerprint(&default),
+            fingerprint(&by_name(RFC5114_2048_256).unwrap())
+        );
+        // any one swapped parameter shows
+        let mut swapped = default.clone();
+        std::mem::swap(&mut swapped.g, &mut swapped.h);
+        assert_ne!(fingerprint(&default), fingerprint(&swapped));
+    }
+}
@@ -0,0 +1,199 @@
+use crate::ZKP;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::path::Path;
+
+// transcript of interactive rounds exchanged with another implementation of
+// the protocol. numbers are strings in the transcript's encoding:
+//   hex: big-endian, any length, odd lengths and leading zeros allowed
+//        (python's format(n, 'x'), go's Int.Text(16), our to_bytes_be)
+//   decimal: python's str(n), go's Int.String()
+// and every round must satisfy r1 = g ** s * y1 ** c, r2 = h ** s * y2 ** c (mod p)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transcript {
+    pub implementation: String,
+    pub encoding: Encoding,
+    pub group: TranscriptGroup,
+    pub rounds: Vec<Round>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Hex,
+    Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptGroup {
+    pub p: String,
+    pub q: String,
+    pub g: String,
+    pub h: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Round {
+    pub y1: String,
+    pub y2: String,
+    pub r1: String,
+    pub r2: String,
+    pub c: String,
+    pub s: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InteropError {
+    InvalidNumber(&'static str),
+    InvalidGroup,
+    Rejected { round: usize },
+}
+
+impl Display for InteropError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InteropError::InvalidNumber(field) => write!(f, "{} is not a valid number", field),
+            InteropError::InvalidGroup => write!(f, "p and q must be greater than 1"),
+            InteropError::Rejected { round } => write!(f, "round {} does not verify", round),
+        }
+    }
+}
+
+impl std::error::Error for InteropError {}
+
+impl Encoding {
+    pub fn encode(self, n: &BigUint) -> String {
+        match self {
+            Encoding::Hex => n.to_str_radix(16),
+            Encoding::Decimal => n.to_str_radix(10),
+        }
+    }
+
+    pub fn decode(self, field: &'static str, value: &str) -> Result<BigUint, InteropError> {
+        let radix = match self {
+            Encoding::Hex => 16,
+            Encoding::Decimal => 10,
+        };
+        if value.is_empty() {
+            return Err(InteropError::InvalidNumber(field));
+        }
+        BigUint::parse_bytes(value.as_bytes(), radix).ok_or(InteropError::InvalidNumber(field))
+    }
+}
+
+impl Transcript {
+    pub fn load(path: &Path) -> std::io::Result<Transcript> {
+        serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).expect("transcript serializes");
+        std::fs::write(path, json + "\n")
+    }
+
+    // count honest rounds of ours, x, k and c drawn below q
+    pub fn export(zkp: &ZKP, encoding: Encoding, count: usize) -> Transcript {
+        let rounds = (0..count)
+            .map(|_| {
+                let x = ZKP::generate_random_number_below(&zkp.q);
+                let k = ZKP::generate_random_number_below(&zkp.q);
+                let c = ZKP::generate_random_number_below(&zkp.q);
+                let encode = |n: &BigUint| encoding.encode(n);
+                Round {
+                    y1: encode(&ZKP::exponentiate(&zkp.g, &x, &zkp.p)),
+                    y2: encode(&ZKP::exponentiate(&zkp.h, &x, &zkp.p)),
+                    r1: encode(&ZKP::exponentiate(&zkp.g, &k, &zkp.p)),
+                    r2: encode(&ZKP::exponentiate(&zkp.h, &k, &zkp.p)),
+                    c: encode(&c),
+                    s: encode(&zkp.solve(&k, &c, &x)),
+                }
+            })
+            .collect();
+        Transcript {
+            implementation: "zkp-chaum-pedersen".to_string(),
+            encoding,
+            group: TranscriptGroup {
+                p: encoding.encode(&zkp.p),
+                q: encoding.encode(&zkp.q),
+                g: encoding.encode(&zkp.g),
+                h: encoding.encode(&zkp.h),
+            },
+            rounds,
+        }
+    }
+
+    pub fn zkp(&self) -> Result<ZKP, InteropError> {
+        let decode = |field, value: &str| self.encoding.decode(field, value);
+        let zkp = ZKP {
+            p: decode("p", &self.group.p)?,
+            q: decode("q", &self.group.q)?,
+            g: decode("g", &self.group.g)?,
+            h: decode("h", &self.group.h)?,
+        };
+        let one = BigUint::from(1u32);
+        if zkp.p <= one || zkp.q <= one {
+            return Err(InteropError::InvalidGroup);
+        }
+        Ok(zkp)
+    }
+
+    // every round goes through the wire encoding of the gRPC service
+    // (big-endian bytes) before it is verified, as if it had been received
+    pub fn verify(&self) -> Result<(), InteropError> {
+        let zkp = self.zkp()?;
+        let wire = |field, value: &str| -> Result<BigUint, InteropError> {
+            let n = self.encoding.decode(field, value)?;
+            Ok(BigUint::from_bytes_be(&n.to_bytes_be()))
+        };
+        for (i, round) in self.rounds.iter().enumerate() {
+            let verified = zkp.verify(
+                &wire("r1", &round.r1)?,
+                &wire("r2", &round.r2)?,
+                &wire("y1", &round.y1)?,
+                &wire("y2", &round.y2)?,
+                &wire("c", &round.c)?,
+                &wire("s", &round.s)?,
+            );
+            if !verified {
+                return Err(InteropError::Rejected { round: i });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encodings_of_other_implementations() {
+        // go's Text(16) and python's format(n, 'x') don't pad to whole bytes
+        assert_eq!(
+            Encoding::Hex.decode("s", "b").unwrap(),
+            BigUint::from(11u32)
+        );
+        assert_eq!(
+            Encoding::Hex.decode("s", "000b").unwrap(),
+            BigUint::from(11u32)
+        );
+        assert_eq!(
+            Encoding::Decimal.decode("s", "0").unwrap(),
+            BigUint::from(0u32)
+        );
+        assert_eq!(
+            Encoding::Decimal.decode("s", "-3"),
+            Err(InteropError::InvalidNumber("s"))
+        );
+        assert_eq!(
+            Encoding::Hex.decode("s", ""),
+            Err(InteropError::InvalidNumber("s"))
+        );
+
+        // python's n.to_bytes(...) and go's Bytes() are empty for zero, ours is [0]
+        assert_eq!(BigUint::from_bytes_be(&[]), BigUint::from(0u32));
+        assert_eq!(BigUint::from(0u32).to_bytes_be(), vec![0]);
+    }
+}
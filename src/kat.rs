@@ -0,0 +1,173 @@
+use crate::ZKP;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::path::Path;
+
+// known-answer test vectors, one JSON file per group, every number big-endian
+// hex. another implementation is compatible if, for every vector, it computes
+// the same y1, y2, r1, r2 and s from (x, k, c) and accepts the round
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KatFile {
+    pub group: KatGroup,
+    pub vectors: Vec<KnownAnswer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KatGroup {
+    pub name: String,
+    pub p: String,
+    pub q: String,
+    pub g: String,
+    pub h: String,
+}
+
+// y1 = g ** x ; y2 = h ** x ; r1 = g ** k ; r2 = h ** k (mod p)
+// s = k - c * x mod q
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownAnswer {
+    pub x: String,
+    pub k: String,
+    pub c: String,
+    pub y1: String,
+    pub y2: String,
+    pub r1: String,
+    pub r2: String,
+    pub s: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KatError {
+    InvalidHex(&'static str),
+    InvalidGroup,
+    Mismatch { vector: usize, field: &'static str },
+    Rejected { vector: usize },
+}
+
+impl Display for KatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KatError::InvalidHex(field) => write!(f, "{} is not valid hex", field),
+            KatError::InvalidGroup => write!(f, "p and q must be greater than 1"),
+            KatError::Mismatch { vector, field } => {
+                write!(
+                    f,
+                    "vector {}: {} differs from the expected value",
+                    vector, field
+                )
+            }
+            KatError::Rejected { vector } => write!(f, "vector {}: round does not verify", vector),
+        }
+    }
+}
+
+impl std::error::Error for KatError {}
+
+fn to_hex(n: &BigUint) -> String {
+    hex::encode(n.to_bytes_be())
+}
+
+fn from_hex(field: &'static str, value: &str) -> Result<BigUint, KatError> {
+    hex::decode(value)
+        .map(|bytes| BigUint::from_bytes_be(&bytes))
+        .map_err(|_| KatError::InvalidHex(field))
+}
+
+impl KnownAnswer {
+    pub fn compute(zkp: &ZKP, x: &BigUint, k: &BigUint, c: &BigUint) -> KnownAnswer {
+        KnownAnswer {
+            x: to_hex(x),
+            k: to_hex(k),
+            c: to_hex(c),
+            y1: to_hex(&ZKP::exponentiate(&zkp.g, x, &zkp.p)),
+            y2: to_hex(&ZKP::exponentiate(&zkp.h, x, &zkp.p)),
+            r1: to_hex(&ZKP::exponentiate(&zkp.g, k, &zkp.p)),
+            r2: to_hex(&ZKP::exponentiate(&zkp.h, k, &zkp.p)),
+            s: to_hex(&zkp.solve(k, c, x)),
+        }
+    }
+}
+
+impl KatFile {
+    pub fn load(path: &Path) -> std::io::Result<KatFile> {
+        serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).expect("KAT file serializes");
+        std::fs::write(path, json + "\n")
+    }
+
+    // count vectors with x, k, c drawn below q
+    pub fn generate(name: &str, zkp: &ZKP, count: usize) -> KatFile {
+        let vectors = (0..count)
+            .map(|_| {
+                let x = ZKP::generate_random_number_below(&zkp.q);
+                let k = ZKP::generate_random_number_below(&zkp.q);
+                let c = ZKP::generate_random_number_below(&zkp.q);
+                KnownAnswer::compute(zkp, &x, &k, &c)
+            })
+            .collect();
+        KatFile {
+            group: KatGroup {
+                name: name.to_string(),
+                p: to_hex(&zkp.p),
+                q: to_hex(&zkp.q),
+                g: to_hex(&zkp.g),
+                h: to_hex(&zkp.h),
+            },
+            vectors,
+        }
+    }
+
+    pub fn zkp(&self) -> Result<ZKP, KatError> {
+        let zkp = ZKP {
+            p: from_hex("p", &self.group.p)?,
+            q: from_hex("q", &self.group.q)?,
+            g: from_hex("g", &self.group.g)?,
+            h: from_hex("h", &self.group.h)?,
+        };
+        // a zero modulus panics in modpow, so don't hand one out
+        let one = BigUint::from(1u32);
+        if zkp.p <= one || zkp.q <= one {
+            return Err(KatError::InvalidGroup);
+        }
+        Ok(zkp)
+    }
+
+    // recomputes every vector from (x, k, c) and runs the verifier on it
+    pub fn check(&self) -> Result<(), KatError> {
+        let zkp = self.zkp()?;
+        for (i, vector) in self.vectors.iter().enumerate() {
+            let x = from_hex("x", &vector.x)?;
+            let k = from_hex("k", &vector.k)?;
+            let c = from_hex("c", &vector.c)?;
+            let computed = KnownAnswer::compute(&zkp, &x, &k, &c);
+            for (field, expected, actual) in [
+                ("y1", &vector.y1, &computed.y1),
+                ("y2", &vector.y2, &computed.y2),
+                ("r1", &vector.r1, &computed.r1),
+                ("r2", &vector.r2, &computed.r2),
+                ("s", &vector.s, &computed.s),
+            ] {
+                // compared as numbers, leading zero bytes don't matter
+                if from_hex(field, expected)? != from_hex(field, actual)? {
+                    return Err(KatError::Mismatch { vector: i, field });
+                }
+            }
+            let verified = zkp.verify(
+                &from_hex("r1", &vector.r1)?,
+                &from_hex("r2", &vector.r2)?,
+                &from_hex("y1", &vector.y1)?,
+                &from_hex("y2", &vector.y2)?,
+                &c,
+                &from_hex("s", &vector.s)?,
+            );
+            if !verified {
+                return Err(KatError::Rejected { vector: i });
+            }
+        }
+        Ok(())
+    }
+}
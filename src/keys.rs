@@ -0,0 +1,130 @@
+use crate::secret::SecretBigUint;
+use crate::ZKP;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+
+// long-term key pair: y1 = g ** x mod p ; y2 = h ** x mod p
+#[derive(Debug)]
+pub struct KeyPair {
+    pub x: SecretBigUint,
+    pub public: PublicKey,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    pub y1: BigUint,
+    pub y2: BigUint,
+}
+
+// on-disk formats, numbers are big-endian hex
+#[derive(Serialize, Deserialize)]
+struct KeyPairFile {
+    x: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PublicKeyFile {
+    y1: String,
+    y2: String,
+}
+
+fn invalid_data(e: impl ToString) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn from_hex(value: &str) -> std::io::Result<BigUint> {
+    hex::decode(value)
+        .map(|bytes| BigUint::from_bytes_be(&bytes))
+        .map_err(invalid_data)
+}
+
+impl KeyPair {
+    pub fn from_secret(zkp: &ZKP, x: SecretBigUint) -> Self {
+        let public = PublicKey {
+            y1: ZKP::exponentiate(&zkp.g, x.expose(), &zkp.p),
+            y2: ZKP::exponentiate(&zkp.h, x.expose(), &zkp.p),
+        };
+        KeyPair { x, public }
+    }
+
+    pub fn generate(zkp: &ZKP) -> Self {
+        // x = 0 would give y1 = y2 = 1
+        let mut x = ZKP::generate_random_number_below(&zkp.q);
+        while x == BigUint::from(0u32) {
+            x = ZKP::generate_random_number_below(&zkp.q);
+        }
+        KeyPair::from_secret(zkp, SecretBigUint::new(x))
+    }
+
+    pub fn load(zkp: &ZKP, path: &Path) -> std::io::Result<Self> {
+        let file: KeyPairFile =
+            serde_json::from_str(&std::fs::read_to_string(path)?).map_err(invalid_data)?;
+        Ok(KeyPair::from_secret(
+            zkp,
+            SecretBigUint::new(from_hex(&file.x)?),
+        ))
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let file = KeyPairFile {
+            x: hex::encode(self.x.expose().to_bytes_be()),
+        };
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // the private key is only readable by its owner
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(path)?
+            .write_all(serde_json::to_string_pretty(&file)?.as_bytes())
+    }
+}
+
+impl PublicKey {
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let file: PublicKeyFile =
+            serde_json::from_str(&std::fs::read_to_string(path)?).map_err(invalid_data)?;
+        Ok(PublicKey {
+            y1: from_hex(&file.y1)?,
+            y2: from_hex(&file.y2)?,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let file = PublicKeyFile {
+            y1: hex::encode(self.y1.to_bytes_be()),
+            y2: hex::encode(self.y2.to_bytes_be()),
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&file)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_files_round_trip() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+        let dir = std::env::temp_dir().join(format!("zkp-keys-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let key_pair = KeyPair::generate(&zkp);
+        key_pair.save(&dir.join("server_key.json")).unwrap();
+        key_pair
+            .public
+            .save(&dir.join("server_key.pub.json"))
+            .unwrap();
+
+        let loaded = KeyPair::load(&zkp, &dir.join("server_key.json")).unwrap();
+        let public = PublicKey::load(&dir.join("server_key.pub.json")).unwrap();
+        assert_eq!(loaded.x.expose(), key_pair.x.expose());
+        assert_eq!(loaded.public, key_pair.public);
+        assert_eq!(public, key_pair.public);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// the fingerprints of the group parameters each server had when the client
+// first talked to it, trusted on first use like ssh's known_hosts. a client
+// without a pinned fingerprint learns it on the first connection and from
+// then on notices a server whose parameters changed, which a long-lived
+// automation would otherwise go on logging in to
+
+// what to do when a server's parameters aren't the ones it had before
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnChange {
+    // refuse the server until the entry is forgotten
+    #[default]
+    Refuse,
+    // go on, and trust the new parameters from then on
+    Warn,
+}
+
+// how a fingerprint stood against the known ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trust {
+    // the one seen before
+    Known,
+    // the first seen for the server and group, now recorded
+    Learned,
+    // another than before, recorded in its place under OnChange::Warn
+    Changed { previous: [u8; 32] },
+}
+
+// a change refused under OnChange::Refuse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParametersChanged {
+    pub server: String,
+    pub group: String,
+    pub known: [u8; 32],
+    pub served: [u8; 32],
+}
+
+impl Display for ParametersChanged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "parameters of {} at {} changed from {} to {}",
+            self.group,
+            self.server,
+            hex::encode(self.known),
+            hex::encode(self.served)
+        )
+    }
+}
+
+impl std::error::Error for ParametersChanged {}
+
+// a JSON file of server address to group name to hex fingerprint:
+// {"https://auth.example:50051": {"rfc5114-1024-160": "ab12..."}}
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KnownParameters {
+    path: PathBuf,
+    servers: BTreeMap<String, BTreeMap<String, String>>,
+    changed: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct KnownFile(BTreeMap<String, BTreeMap<String, String>>);
+
+impl KnownParameters {
+    // the file is created by the first save, one that doesn't exist yet knows
+    // no server
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let servers = match std::fs::read_to_string(&path) {
+            Ok(json) => {
+                serde_json::from_str::<KnownFile>(&json)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?
+                    .0
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(KnownParameters {
+            path,
+            servers,
+            changed: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // None for a server and group not seen yet, or an entry that isn't 64 hex
+    // digits (which check then replaces as if it weren't there)
+    pub fn get(&self, server: &str, group: &str) -> Option<[u8; 32]> {
+        let fingerprint = self.servers.get(server)?.get(group)?;
+        hex::decode(fingerprint).ok()?.try_into().ok()
+    }
+
+    pub fn check(
+        &mut self,
+        server: &str,
+        group: &str,
+        served: [u8; 32],
+        on_change: OnChange,
+    ) -> Result<Trust, ParametersChanged> {
+        let trust = match self.get(server, group) {
+            Some(known) if known == served => return Ok(Trust::Known),
+            Some(known) if on_change == OnChange::Refuse => {
+                return Err(ParametersChanged {
+                    server: server.to_string(),
+                    group: group.to_string(),
+                    known,
+                    served,
+                });
+            }
+            Some(previous) => Trust::Changed { previous },
+            None => Trust::Learned,
+        };
+        self.servers
+            .entry(server.to_string())
+            .or_default()
+            .insert(group.to_string(), hex::encode(served));
+        self.changed = true;
+        Ok(trust)
+    }
+
+    // the entry of a server whose change was expected, so the next check
+    // learns the new parameters
+    pub fn forget(&mut self, server: &str, group: &str) {
+        if let Some(groups) = self.servers.get_mut(server)
+            && groups.remove(group).is_some()
+        {
+            if groups.is_empty() {
+                self.servers.remove(server);
+            }
+            self.changed = true;
+        }
+    }
+
+    // writes the file if check or forget changed it, next to itself and
+    // renamed over so it's never left half written
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        let json = serde_json::to_string_pretty(&KnownFile(self.servers.clone()))
+            .expect("fingerprints serialize");
+        let temporary = self.path.with_extension("tmp");
+        std::fs::File::create(&temporary)?.write_all(json.as_bytes())?;
+        std::fs::rename(&temporary, &self.path)?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprints_are_trusted_on_first_use() {
+        let path = std::env::temp_dir().join(format!("known-params-{}.json", std::process::id()));
+        let server = "http://127.0.0.1:50051";
+        let mut known = KnownParameters::load(&path).unwrap();
+        assert_eq!(
+            known.check(server, "g", [1; 32], OnChange::Refuse),
+            Ok(Trust::Learned)
+        );
+        known.save().unwrap();
+
+        let mut known = KnownParameters::load(&path).unwrap();
+        assert_eq!(known.get(server, "g"), Some([1; 32]));
+        assert_eq!(
+            known.check(server, "g", [1; 32], OnChange::Refuse),
+            Ok(Trust::Known)
+        );
+        let changed = known
+            .check(server, "g", [2; 32], OnChange::Refuse)
+            .unwrap_err();
+        assert_eq!(changed.known, [1; 32]);
+        // refused, so still the old one
+        assert_eq!(known.get(server, "g"), Some([1; 32]));
+        assert_eq!(
+            known.check(server, "g", [2; 32], OnChange::Warn),
+            Ok(Trust::Changed { previous: [1; 32] })
+        );
+        assert_eq!(known.get(server, "g"), Some([2; 32]));
+
+        known.forget(server, "g");
+        known.save().unwrap();
+        assert_eq!(KnownParameters::load(&path).unwrap().get(server, "g"), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
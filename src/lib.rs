@@ -1,6 +1,150 @@
-use num_bigint::{BigUint, RandBigInt};
+#[cfg(feature = "prover")]
+use base64::Engine;
+use montgomery::Montgomery;
+use num_bigint::BigUint;
+#[cfg(feature = "prover")]
+use num_bigint::RandBigInt;
+use proof_metadata::{FreshnessWindow, ProofMetadata, ProofMetadataError};
+#[cfg(feature = "prover")]
 use rand::{distributions::Alphanumeric, Rng};
+use secret::{Redacted, Reveal, Revealed};
 use std::fmt::{Debug, Display};
+use std::io::{self, Read};
+use transcript::Transcript;
+
+#[cfg(feature = "server")]
+pub mod access;
+#[cfg(feature = "server")]
+pub mod admission;
+#[cfg(feature = "prover")]
+pub mod artifacts;
+#[cfg(feature = "proto")]
+pub mod attestation;
+#[cfg(feature = "server")]
+pub mod audit;
+#[cfg(feature = "prover")]
+pub mod ballot;
+#[cfg(feature = "prover")]
+pub mod blinding;
+#[cfg(feature = "bls12-381")]
+pub mod bls;
+#[cfg(feature = "server")]
+pub mod bundle;
+pub mod capabilities;
+#[cfg(feature = "prover")]
+pub mod challenge;
+#[cfg(feature = "server")]
+pub mod challenge_pool;
+#[cfg(feature = "prover")]
+pub mod clock;
+pub mod codec;
+#[cfg(feature = "prover")]
+pub mod cross_group;
+#[cfg(all(unix, feature = "server"))]
+pub mod daemon;
+#[cfg(feature = "proto")]
+pub mod drain;
+#[cfg(feature = "prover")]
+pub mod elgamal;
+pub mod encoding;
+#[cfg(feature = "proto")]
+pub mod failover;
+#[cfg(feature = "prover")]
+pub mod grants;
+#[cfg(feature = "prover")]
+pub mod group;
+pub mod groups;
+#[cfg(feature = "prover")]
+pub mod interop;
+#[cfg(feature = "prover")]
+pub mod kat;
+#[cfg(feature = "prover")]
+pub mod keys;
+#[cfg(feature = "prover")]
+pub mod known_params;
+#[cfg(feature = "prover")]
+pub mod lockout;
+#[cfg(feature = "proto")]
+pub mod login;
+pub mod montgomery;
+#[cfg(feature = "prover")]
+pub mod offline;
+#[cfg(feature = "proto")]
+pub mod preflight;
+#[cfg(feature = "proto")]
+pub mod probes;
+#[cfg(all(feature = "profiling", unix))]
+pub mod profiling;
+pub mod proof_metadata;
+#[cfg(feature = "proto")]
+pub mod proto;
+#[cfg(feature = "server")]
+pub mod proxy;
+#[cfg(feature = "prover")]
+pub mod quota;
+#[cfg(feature = "prover")]
+pub mod receipt;
+#[cfg(feature = "prover")]
+pub mod recovery;
+#[cfg(feature = "prover")]
+pub mod reencryption;
+#[cfg(feature = "prover")]
+pub mod representation;
+#[cfg(feature = "server")]
+pub mod request_id;
+#[cfg(feature = "ristretto")]
+pub mod ristretto;
+#[cfg(feature = "prover")]
+pub mod rng;
+pub mod schnorr;
+#[cfg(feature = "server")]
+pub mod sealing;
+#[cfg(feature = "server")]
+pub mod second_factor;
+pub mod secret;
+#[cfg(feature = "proto")]
+pub mod self_test;
+#[cfg(feature = "proto")]
+pub mod service;
+#[cfg(feature = "proto")]
+pub mod service_v2;
+#[cfg(feature = "prover")]
+pub mod session_key;
+#[cfg(feature = "proto")]
+pub mod settings;
+#[cfg(feature = "server")]
+pub mod snapshot;
+#[cfg(feature = "server")]
+pub mod state;
+#[cfg(feature = "prover")]
+pub mod statement;
+#[cfg(feature = "prover")]
+pub mod tickets;
+#[cfg(feature = "server")]
+pub mod tls;
+#[cfg(feature = "server")]
+pub mod token;
+pub mod transcript;
+#[cfg(feature = "unknown-order")]
+pub mod unknown_order;
+#[cfg(feature = "prover")]
+pub mod username;
+pub mod verification_cache;
+#[cfg(feature = "prover")]
+pub mod verifier;
+#[cfg(feature = "server")]
+pub mod workers;
+
+// gRPC messages, client and server generated from proto/zkp_auth.proto and
+// proto/zkp_auth_v2.proto by build.rs
+#[cfg(feature = "proto")]
+pub mod zkp_auth {
+    tonic::include_proto!("zkp_auth");
+
+    pub mod v2 {
+        tonic::include_proto!("zkp_auth.v2");
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct ZKP {
@@ -17,12 +161,19 @@ impl ZKP {
         n.modpow(exponent, modulus)
     }
 
+    // a * b mod p, one multiplication and one reduction
+    pub fn mod_mul(a: &BigUint, b: &BigUint, modulus: &BigUint) -> BigUint {
+        (a * b) % modulus
+    }
+
     // s = k - c * x mod q
+    // c * x is computed once and reduced in place, k is only borrowed
     pub fn solve(&self, k: &BigUint, c: &BigUint, x: &BigUint) -> BigUint {
-        if *k >= c * x {
-            (k - c * x).modpow(&BigUint::from(1u32), &self.q)
+        let cx = c * x;
+        if *k >= cx {
+            (k - cx) % &self.q
         } else {
-            &self.q - (c * x - k).modpow(&BigUint::from(1u32), &self.q)
+            &self.q - (cx - k) % &self.q
         }
     }
 
@@ -31,10 +182,10 @@ impl ZKP {
         let cx = c * x;
         if *k >= cx {
             // in case of k >= c*x: k - c*x mod q
-            (k - &cx).modpow(&BigUint::from(1u32), &self.q)
+            (k - cx) % &self.q
         } else {
             // k < c*x: q - (c*x - k) mod q
-            &self.q - (cx - k).modpow(&BigUint::from(1u32), &self.q)
+            &self.q - (cx - k) % &self.q
         }
     }
 
@@ -49,27 +200,358 @@ impl ZKP {
         c: &BigUint,
         s: &BigUint,
     ) -> bool {
-        let cond1 = *r1
-            == (&self.g.modpow(s, &self.p) * y1.modpow(c, &self.p))
-                .modpow(&BigUint::from(1u32), &self.p);
-        let cond2 = *r2
-            == (&self.h.modpow(s, &self.p) * y2.modpow(c, &self.p))
-                .modpow(&BigUint::from(1u32), &self.p);
+        if !self.within_bounds(&[r1, r2, y1, y2], &[c, s]) {
+            return false;
+        }
+        // a group with an even p isn't one, nothing verifies in it
+        let Some(montgomery) = Montgomery::shared(&self.p) else {
+            return false;
+        };
+        // the four exponentiations share one reduction context, two at a time
+        // over one run of squarings
+        let cond1 = *r1 == montgomery.pow_product(&self.g, s, y1, c);
+        let cond2 = *r2 == montgomery.pow_product(&self.h, s, y2, c);
         cond1 && cond2
     }
 
+    // no group element longer than p and no exponent longer than twice p, so
+    // attacker-sized inputs can't make an exponentiation arbitrarily slow.
+    // honest exponents are below q, unreduced ones still fit
+    fn within_bounds(&self, elements: &[&BigUint], scalars: &[&BigUint]) -> bool {
+        elements.iter().all(|n| n.bits() <= self.p.bits())
+            && scalars.iter().all(|n| n.bits() <= 2 * self.p.bits())
+    }
+
+    // fixed sizes of the group's elements (below p) and scalars (below q) in
+    // bytes, for buffers that hold any of them
+    pub fn element_len(&self) -> usize {
+        encoding::be_len(&self.p)
+    }
+
+    pub fn scalar_len(&self) -> usize {
+        encoding::be_len(&self.q)
+    }
+
+    // bits of security against computing discrete logs in the group: the
+    // NIST SP 800-57 strength of a p this long (number field sieve), capped by
+    // half the bits of q (Pollard's rho). 0 for a p under 1024 bits, too
+    // small to rate
+    pub fn security_bits(&self) -> u64 {
+        let field = match self.p.bits() {
+            15360.. => 256,
+            7680.. => 192,
+            3072.. => 128,
+            2048.. => 112,
+            1024.. => 80,
+            _ => 0,
+        };
+        field.min(self.q.bits() / 2)
+    }
+
+    // transcript that starts with the group: domain, g, h, p, q
+    pub fn transcript(&self, domain: &[u8]) -> Transcript {
+        let mut transcript = Transcript::new(domain);
+        transcript.append_biguint(b"g", &self.g);
+        transcript.append_biguint(b"h", &self.h);
+        transcript.append_biguint(b"p", &self.p);
+        transcript.append_biguint(b"q", &self.q);
+        transcript
+    }
+
+    // transcript of a challenge derivation in one deployment: the group, then
+    // the application id. a proof made under one id never verifies under
+    // another, and an empty id isn't absorbed, so proofs made before ids
+    // existed still verify without one
+    pub fn application_transcript(&self, domain: &[u8], application: &[u8]) -> Transcript {
+        let mut transcript = self.transcript(domain);
+        if !application.is_empty() {
+            transcript.append(b"application", application);
+        }
+        transcript
+    }
+
+    // H(g, h, p, q, application, y1, y2, r1, r2, ...), the context goes last
+    fn fiat_shamir_transcript(
+        &self,
+        application: &[u8],
+        y1: &BigUint,
+        y2: &BigUint,
+        r1: &BigUint,
+        r2: &BigUint,
+    ) -> Transcript {
+        let mut transcript = self.application_transcript(b"zkp-chaum-pedersen", application);
+        transcript.append_biguint(b"y1", y1);
+        transcript.append_biguint(b"y2", y2);
+        transcript.append_biguint(b"r1", r1);
+        transcript.append_biguint(b"r2", r2);
+        transcript
+    }
+
+    // c = H(g, h, p, q, application, y1, y2, r1, r2, context) mod q
+    fn fiat_shamir_challenge(
+        &self,
+        application: &[u8],
+        y1: &BigUint,
+        y2: &BigUint,
+        r1: &BigUint,
+        r2: &BigUint,
+        context: &[u8],
+    ) -> BigUint {
+        let mut transcript = self.fiat_shamir_transcript(application, y1, y2, r1, r2);
+        transcript.append(b"context", context);
+        transcript.challenge(&self.q)
+    }
+
+    // non-interactive variant: the challenge is derived from the commitments
+    // (r1, r2) and the context instead of being sent by the verifier
+    #[cfg(feature = "prover")]
+    pub fn prove_non_interactive(&self, x: &BigUint, context: &[u8]) -> Proof {
+        self.prove_non_interactive_in(b"", x, context)
+    }
+
+    // same under a deployment's application id
+    #[cfg(feature = "prover")]
+    pub fn prove_non_interactive_in(
+        &self,
+        application: &[u8],
+        x: &BigUint,
+        context: &[u8],
+    ) -> Proof {
+        let mut k = ZKP::generate_random_number_below(&self.q);
+        let proof = self.prove_non_interactive_with_nonce(application, x, &k, context);
+        secret::wipe_biguint(&mut k);
+        proof
+    }
+
+    // same with the caller's nonce k, for a caller that goes on to use
+    // r1 = g ** k mod p (the login key agreement)
+    #[cfg(feature = "prover")]
+    pub fn prove_non_interactive_with_nonce(
+        &self,
+        application: &[u8],
+        x: &BigUint,
+        k: &BigUint,
+        context: &[u8],
+    ) -> Proof {
+        let y1 = ZKP::exponentiate(&self.g, x, &self.p);
+        let y2 = ZKP::exponentiate(&self.h, x, &self.p);
+        let r1 = ZKP::exponentiate(&self.g, k, &self.p);
+        let r2 = ZKP::exponentiate(&self.h, k, &self.p);
+
+        let c = self.fiat_shamir_challenge(application, &y1, &y2, &r1, &r2, context);
+        let s = self.solve(k, &c, x);
+        Proof { c, s }
+    }
+
+    // prove_non_interactive_in over the context stamped with a purpose, issue
+    // time and expiry (see proof_metadata), which only verifies with them
+    #[cfg(feature = "prover")]
+    pub fn prove_non_interactive_stamped(
+        &self,
+        application: &[u8],
+        x: &BigUint,
+        context: &[u8],
+        metadata: &ProofMetadata,
+    ) -> Proof {
+        self.prove_non_interactive_in(application, x, &metadata.stamp(context))
+    }
+
+    // prove_non_interactive over a context of `len` bytes read from `context`,
+    // for binding a proof to a payload too large to hold in memory. the proof
+    // is the one prove_non_interactive makes over the same bytes
+    #[cfg(feature = "prover")]
+    pub fn prove_non_interactive_reader(
+        &self,
+        application: &[u8],
+        x: &BigUint,
+        len: u64,
+        context: impl Read,
+    ) -> io::Result<Proof> {
+        let mut k = ZKP::generate_random_number_below(&self.q);
+        let y1 = ZKP::exponentiate(&self.g, x, &self.p);
+        let y2 = ZKP::exponentiate(&self.h, x, &self.p);
+        let r1 = ZKP::exponentiate(&self.g, &k, &self.p);
+        let r2 = ZKP::exponentiate(&self.h, &k, &self.p);
+
+        let mut transcript = self.fiat_shamir_transcript(application, &y1, &y2, &r1, &r2);
+        let absorbed = transcript.append_reader(b"context", len, context);
+        let proof = absorbed.map(|()| {
+            let c = transcript.challenge(&self.q);
+            let s = self.solve(&k, &c, x);
+            Proof { c, s }
+        });
+        secret::wipe_biguint(&mut k);
+        proof
+    }
+
+    // (r1, r2) a proof was made from: g ** s * y1 ** c mod p, h ** s * y2 ** c mod p
+    pub fn proof_commitments(
+        &self,
+        y1: &BigUint,
+        y2: &BigUint,
+        proof: &Proof,
+    ) -> (BigUint, BigUint) {
+        let r1 = Self::mod_mul(
+            &self.g.modpow(&proof.s, &self.p),
+            &y1.modpow(&proof.c, &self.p),
+            &self.p,
+        );
+        let r2 = Self::mod_mul(
+            &self.h.modpow(&proof.s, &self.p),
+            &y2.modpow(&proof.c, &self.p),
+            &self.p,
+        );
+        (r1, r2)
+    }
+
+    // context of a non-interactive login answer: H(auth_id, channel binding).
+    // the proof only verifies for the attempt it was made for (and, over TLS,
+    // the connection), so it can't be spliced into another login
+    pub fn answer_context(auth_id: &str, channel_binding: &[u8]) -> [u8; 32] {
+        let mut transcript = Transcript::new(b"zkp-chaum-pedersen/answer");
+        transcript.append(b"auth_id", auth_id.as_bytes());
+        transcript.append(b"channel_binding", channel_binding);
+        transcript.finalize()
+    }
+
+    // r1 = g ** s * y1 ** c mod p ; r2 = h ** s * y2 ** c mod p
+    // and the proof holds if hashing them gives back c
+    pub fn verify_non_interactive(
+        &self,
+        y1: &BigUint,
+        y2: &BigUint,
+        proof: &Proof,
+        context: &[u8],
+    ) -> bool {
+        self.verify_non_interactive_in(b"", y1, y2, proof, context)
+    }
+
+    // same under a deployment's application id
+    pub fn verify_non_interactive_in(
+        &self,
+        application: &[u8],
+        y1: &BigUint,
+        y2: &BigUint,
+        proof: &Proof,
+        context: &[u8],
+    ) -> bool {
+        match self.checked_proof_commitments(y1, y2, proof) {
+            Some((r1, r2)) => {
+                proof.c == self.fiat_shamir_challenge(application, y1, y2, &r1, &r2, context)
+            }
+            None => false,
+        }
+    }
+
+    // verify_non_interactive_in for a stamped proof, whose metadata also has
+    // to be within the verifier's window. the window is checked first, so a
+    // stale proof costs no exponentiation
+    pub fn verify_non_interactive_stamped(
+        &self,
+        application: &[u8],
+        y1: &BigUint,
+        y2: &BigUint,
+        proof: &Proof,
+        (context, metadata): (&[u8], &ProofMetadata),
+        window: &FreshnessWindow,
+    ) -> Result<(), ProofMetadataError> {
+        window.check(metadata)?;
+        match self.verify_non_interactive_in(application, y1, y2, proof, &metadata.stamp(context)) {
+            true => Ok(()),
+            false => Err(ProofMetadataError::InvalidProof),
+        }
+    }
+
+    // verify_non_interactive over a context of `len` bytes read from
+    // `context`. a proof that fails its checks is rejected without reading it
+    pub fn verify_non_interactive_reader(
+        &self,
+        application: &[u8],
+        y1: &BigUint,
+        y2: &BigUint,
+        proof: &Proof,
+        len: u64,
+        context: impl Read,
+    ) -> io::Result<bool> {
+        let Some((r1, r2)) = self.checked_proof_commitments(y1, y2, proof) else {
+            return Ok(false);
+        };
+        let mut transcript = self.fiat_shamir_transcript(application, y1, y2, &r1, &r2);
+        transcript.append_reader(b"context", len, context)?;
+        Ok(proof.c == transcript.challenge(&self.q))
+    }
+
+    // proof_commitments of a proof whose inputs are in range
+    fn checked_proof_commitments(
+        &self,
+        y1: &BigUint,
+        y2: &BigUint,
+        proof: &Proof,
+    ) -> Option<(BigUint, BigUint)> {
+        // y = 0 gives r = 0 for every s, which would let anyone forge a proof
+        let zero = BigUint::from(0u32);
+        if *y1 == zero
+            || *y2 == zero
+            || *y1 >= self.p
+            || *y2 >= self.p
+            || !self.within_bounds(&[], &[&proof.c, &proof.s])
+        {
+            return None;
+        }
+        Some(self.proof_commitments(y1, y2, proof))
+    }
+
+    // c' = H(g, h, p, q, application, r1, r2, c, channel binding) mod q
+    // both sides answer c' instead of c, so an answer relayed from one TLS
+    // connection to another does not verify
+    pub fn bind_challenge(
+        &self,
+        application: &[u8],
+        r1: &BigUint,
+        r2: &BigUint,
+        c: &BigUint,
+        channel_binding: &[u8],
+    ) -> BigUint {
+        let mut transcript =
+            self.application_transcript(b"zkp-chaum-pedersen/channel-binding", application);
+        transcript.append_biguint(b"r1", r1);
+        transcript.append_biguint(b"r2", r2);
+        transcript.append_biguint(b"c", c);
+        transcript.append(b"channel_binding", channel_binding);
+        transcript.challenge(&self.q)
+    }
+
+    // d = H(g, h, p, q, r1, r2)
+    // nonce-commitment variant: the prover sends d first and reveals (r1, r2)
+    // only with its answer, so the verifier has to pick c without seeing them
+    pub fn commit_nonce(&self, r1: &BigUint, r2: &BigUint) -> [u8; 32] {
+        let mut transcript = self.transcript(b"zkp-chaum-pedersen/nonce-commitment");
+        transcript.append_biguint(b"r1", r1);
+        transcript.append_biguint(b"r2", r2);
+        transcript.finalize()
+    }
+
+    #[cfg(feature = "prover")]
     pub fn generate_random_number_below(limit: &BigUint) -> BigUint {
-        let mut rng = rand::thread_rng();
+        rng::with_rng(|rng| rng.gen_biguint_below(limit))
+    }
 
-        rng.gen_biguint_below(limit)
+    // `bytes` random bytes as URL-safe base64 without padding, for bearer tokens
+    #[cfg(feature = "prover")]
+    pub fn generate_token(bytes: usize) -> String {
+        let mut token = vec![0u8; bytes];
+        rng::with_rng(|rng| rng.fill_bytes(&mut token));
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(token)
     }
 
+    #[cfg(feature = "prover")]
     pub fn generate_random_string(size: usize) -> String {
-        rand::thread_rng()
-            .sample_iter(Alphanumeric)
-            .take(size)
-            .map(char::from)
-            .collect()
+        rng::with_rng(|rng| {
+            rng.sample_iter(Alphanumeric)
+                .take(size)
+                .map(char::from)
+                .collect()
+        })
     }
 
     pub fn get_constants() -> (BigUint, BigUint, BigUint, BigUint) {
@@ -82,13 +564,48 @@ impl ZKP {
         let exp = BigUint::from_bytes_be(
             &hex::decode("8E73AFA32D779D5918D08BC8858F4DCEF97C2A24855E6EEB22B3B2E5").unwrap(),
         );
-        let h = g.modpow(&exp.clone(), &p.clone());
+        let h = g.modpow(&exp, &p);
         // let h = g.modpow(&ZKP::generate_random_number_below(&q), &p);
 
         (g, h, p, q)
     }
 }
 
+// non-interactive (Fiat–Shamir) proof. its Debug shows c and s as
+// secret::Redacted, reveal() in full
+#[derive(Clone, PartialEq, Eq)]
+pub struct Proof {
+    pub c: BigUint,
+    pub s: BigUint,
+}
+
+impl Debug for Proof {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Proof")
+            .field("c", &Redacted(&self.c))
+            .field("s", &Redacted(&self.s))
+            .finish()
+    }
+}
+
+impl Reveal for Proof {}
+
+impl Debug for Revealed<'_, Proof> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Proof")
+            .field("c", &self.0.c)
+            .field("s", &self.0.s)
+            .finish()
+    }
+}
+
+impl Proof {
+    // bytes of c and s as they go on the wire, big-endian without padding
+    pub fn size_bytes(&self) -> usize {
+        encoding::be_len(&self.c) + encoding::be_len(&self.s)
+    }
+}
+
 // trait: cryptographic operation
 pub trait CryptographicOperation {
     fn compute(&self, input: &BigUint) -> BigUint;
@@ -127,9 +644,10 @@ pub fn process_operation<T: CryptographicOperation + Display>(
     operation.compute(input)
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "prover"))]
 mod tests {
     use super::*;
+    use crate::groups;
 
     #[test]
     fn test_modulo_arithmetic() {
@@ -166,6 +684,22 @@ mod tests {
         assert_eq!(s2, manual_s2);
     }
 
+    #[test]
+    fn test_mod_mul() {
+        let p = BigUint::from(23u32);
+        let (a, b) = (BigUint::from(13u32), BigUint::from(9u32));
+        // 13 * 9 = 117 = 5 * 23 + 2
+        assert_eq!(ZKP::mod_mul(&a, &b, &p), BigUint::from(2u32));
+        assert_eq!(
+            ZKP::mod_mul(&a, &b, &p),
+            (&a * &b).modpow(&BigUint::from(1u32), &p)
+        );
+        assert_eq!(
+            ZKP::mod_mul(&a, &BigUint::from(0u32), &p),
+            BigUint::from(0u32)
+        );
+    }
+
     #[test]
     fn test_unified_formula() {
         let zkp = ZKP {
@@ -332,8 +866,8 @@ mod tests {
         println!("h^s mod p = {}", h_s);
         println!("y2^c mod p = {}", y2_c);
 
-        let cond1_calc = (g_s.clone() * y1_c.clone()).modpow(&BigUint::from(1u32), &zkp.p);
-        let cond2_calc = (h_s.clone() * y2_c.clone()).modpow(&BigUint::from(1u32), &zkp.p);
+        let cond1_calc = ZKP::mod_mul(&g_s, &y1_c, &zkp.p);
+        let cond2_calc = ZKP::mod_mul(&h_s, &y2_c, &zkp.p);
 
         println!("(g^s * y1^c) mod p = {}", cond1_calc);
         println!("(h^s * y2^c) mod p = {}", cond2_calc);
@@ -424,6 +958,203 @@ mod tests {
         assert!(result);
     }
 
+    #[test]
+    fn test_non_interactive_proof() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let y1 = ZKP::exponentiate(&zkp.g, &x, &zkp.p);
+        let y2 = ZKP::exponentiate(&zkp.h, &x, &zkp.p);
+
+        let proof = zkp.prove_non_interactive(&x, b"context");
+        assert!(zkp.verify_non_interactive(&y1, &y2, &proof, b"context"));
+
+        // bound to the context
+        assert!(!zkp.verify_non_interactive(&y1, &y2, &proof, b"other context"));
+
+        // fake secret
+        let x_fake = &x + 1u32;
+        let proof_fake = zkp.prove_non_interactive(&x_fake, b"context");
+        assert!(!zkp.verify_non_interactive(&y1, &y2, &proof_fake, b"context"));
+
+        // zero public values can't be used to forge a proof
+        let zero = BigUint::from(0u32);
+        assert!(!zkp.verify_non_interactive(&zero, &zero, &proof, b"context"));
+    }
+
+    #[test]
+    fn test_application_separates_proofs() {
+        let zkp = groups::default_group();
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let y1 = ZKP::exponentiate(&zkp.g, &x, &zkp.p);
+        let y2 = ZKP::exponentiate(&zkp.h, &x, &zkp.p);
+
+        let proof = zkp.prove_non_interactive_in(b"payments", &x, b"context");
+        assert!(zkp.verify_non_interactive_in(b"payments", &y1, &y2, &proof, b"context"));
+        assert!(!zkp.verify_non_interactive_in(b"staging", &y1, &y2, &proof, b"context"));
+        assert!(!zkp.verify_non_interactive(&y1, &y2, &proof, b"context"));
+
+        // no application is the transcript from before there were any
+        let proof = zkp.prove_non_interactive(&x, b"context");
+        assert!(zkp.verify_non_interactive_in(b"", &y1, &y2, &proof, b"context"));
+        assert!(!zkp.verify_non_interactive_in(b"payments", &y1, &y2, &proof, b"context"));
+    }
+
+    #[test]
+    fn test_non_interactive_proof_over_a_stream() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let y1 = ZKP::exponentiate(&zkp.g, &x, &zkp.p);
+        let y2 = ZKP::exponentiate(&zkp.h, &x, &zkp.p);
+        let document: Vec<u8> = (0..3_000_000u32).map(|i| (i % 253) as u8).collect();
+        let len = document.len() as u64;
+
+        // a streamed proof is the proof over the same bytes, either way round
+        let proof = zkp
+            .prove_non_interactive_reader(b"", &x, len, &document[..])
+            .unwrap();
+        assert!(zkp.verify_non_interactive(&y1, &y2, &proof, &document));
+        let proof = zkp.prove_non_interactive(&x, &document);
+        assert!(zkp
+            .verify_non_interactive_reader(b"", &y1, &y2, &proof, len, &document[..])
+            .unwrap());
+
+        let mut edited = document.clone();
+        edited[1_000_000] ^= 1;
+        assert!(!zkp
+            .verify_non_interactive_reader(b"", &y1, &y2, &proof, len, &edited[..])
+            .unwrap());
+        // a truncated document is an error, not a verdict
+        assert!(zkp
+            .verify_non_interactive_reader(b"", &y1, &y2, &proof, len, &document[..100])
+            .is_err());
+    }
+
+    #[test]
+    fn test_login_answer_is_bound_to_its_attempt() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        let (y1, y2) = (
+            ZKP::exponentiate(&zkp.g, &x, &zkp.p),
+            ZKP::exponentiate(&zkp.h, &x, &zkp.p),
+        );
+        let context = ZKP::answer_context("first", b"");
+        let proof = zkp.prove_non_interactive_with_nonce(b"", &x, &k, &context);
+        assert!(zkp.verify_non_interactive(&y1, &y2, &proof, &context));
+        // the verifier gets back the prover's r1, r2
+        assert_eq!(
+            zkp.proof_commitments(&y1, &y2, &proof),
+            (
+                ZKP::exponentiate(&zkp.g, &k, &zkp.p),
+                ZKP::exponentiate(&zkp.h, &k, &zkp.p)
+            )
+        );
+
+        // another auth_id, or another connection
+        let other_attempt = ZKP::answer_context("second", b"");
+        let other_channel = ZKP::answer_context("first", b"certificate hash");
+        assert!(!zkp.verify_non_interactive(&y1, &y2, &proof, &other_attempt));
+        assert!(!zkp.verify_non_interactive(&y1, &y2, &proof, &other_channel));
+    }
+
+    #[test]
+    fn test_channel_bound_challenge() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let y1 = ZKP::exponentiate(&zkp.g, &x, &zkp.p);
+        let y2 = ZKP::exponentiate(&zkp.h, &x, &zkp.p);
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        let r1 = ZKP::exponentiate(&zkp.g, &k, &zkp.p);
+        let r2 = ZKP::exponentiate(&zkp.h, &k, &zkp.p);
+        let c = ZKP::generate_random_number_below(&zkp.q);
+
+        // the prover answers on the connection it sees
+        let c_client = zkp.bind_challenge(b"", &r1, &r2, &c, b"client connection");
+        let s = zkp.solve(&k, &c_client, &x);
+        assert!(zkp.verify(&r1, &r2, &y1, &y2, &c_client, &s));
+
+        // relayed to the verifier over another connection
+        let c_server = zkp.bind_challenge(b"", &r1, &r2, &c, b"server connection");
+        assert!(!zkp.verify(&r1, &r2, &y1, &y2, &c_server, &s));
+    }
+
+    #[test]
+    fn test_nonce_commitment() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        let r1 = ZKP::exponentiate(&zkp.g, &k, &zkp.p);
+        let r2 = ZKP::exponentiate(&zkp.h, &k, &zkp.p);
+        let commitment = zkp.commit_nonce(&r1, &r2);
+        assert_eq!(commitment, zkp.commit_nonce(&r1, &r2));
+
+        // revealing other values after the challenge doesn't match
+        let other_r2 = (&r2 * &zkp.h) % &zkp.p;
+        assert_ne!(commitment, zkp.commit_nonce(&r1, &other_r2));
+        assert_ne!(commitment, zkp.commit_nonce(&r2, &r1));
+    }
+
+    #[test]
+    fn test_oversized_inputs_are_rejected() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let y1 = ZKP::exponentiate(&zkp.g, &x, &zkp.p);
+        let y2 = ZKP::exponentiate(&zkp.h, &x, &zkp.p);
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        let r1 = ZKP::exponentiate(&zkp.g, &k, &zkp.p);
+        let r2 = ZKP::exponentiate(&zkp.h, &k, &zkp.p);
+        let c = ZKP::generate_random_number_below(&zkp.q);
+        let s = zkp.solve(&k, &c, &x);
+        assert!(zkp.verify(&r1, &r2, &y1, &y2, &c, &s));
+
+        // s + q ** n still verifies in math, but costs an exponentiation of
+        // attacker-chosen length
+        let huge = &s + zkp.q.pow(200);
+        assert!(!zkp.verify(&r1, &r2, &y1, &y2, &c, &huge));
+        let r1_huge = &r1 + zkp.p.pow(2);
+        assert!(!zkp.verify(&r1_huge, &r2, &y1, &y2, &c, &s));
+
+        let proof = Proof {
+            c: zkp.prove_non_interactive(&x, b"context").c,
+            s: huge,
+        };
+        assert!(!zkp.verify_non_interactive(&y1, &y2, &proof, b"context"));
+    }
+
+    #[test]
+    fn test_security_bits_and_proof_size() {
+        let rfc5114_1024 = groups::default_group();
+        assert_eq!(rfc5114_1024.security_bits(), 80);
+        let rfc5114_2048 = groups::by_name(groups::RFC5114_2048_256).unwrap();
+        assert_eq!(rfc5114_2048.security_bits(), 112);
+        // a long p doesn't help a short q
+        let short_q = ZKP {
+            q: BigUint::from(1u32) << 127u32,
+            ..rfc5114_2048
+        };
+        assert_eq!(short_q.security_bits(), 64);
+
+        // c and s are below the 160-bit q
+        let proof = rfc5114_1024.prove_non_interactive(&BigUint::from(7u32), b"");
+        assert!(proof.size_bytes() <= 40);
+        let zero = Proof {
+            c: BigUint::from(0u32),
+            s: BigUint::from(0u32),
+        };
+        assert_eq!(zero.size_bytes(), 2);
+    }
+
     #[test]
     fn test_1024bit_constants() {
         let p = BigUint::from_bytes_be(&hex::decode("B10B8F96A080E01DDE92DE5EAE5D54EC52C99FBCFB06A3C69A6A9DCA52D23B616073E28675A23D189838EF1E2EE652C013ECB4AEA906112324975C3CD49B83BFACCBDD7D90C4BD7098488E9C219A73724EFFD6FAE5644738FAA31A4FF55BCCC0A151AF5F0DC8B4BD45BF37DF365C1A65E68CFDA76D4DA708DF1FB2BC2E4A4371").unwrap());
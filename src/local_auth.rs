@@ -0,0 +1,214 @@
+use clap::{Parser, ValueEnum};
+use num_bigint::BigUint;
+use zkp_chaum_pedersen::group::ChaumPedersenGroup;
+use zkp_chaum_pedersen::groups;
+use zkp_chaum_pedersen::secret::SecretBigUint;
+use zkp_chaum_pedersen::ZKP;
+
+#[derive(Parser, Debug)]
+#[command(about = "Run a Chaum-Pedersen login in one process, prover and verifier side by side")]
+struct Args {
+    /// group to run the protocol in
+    #[arg(long, value_enum, default_value_t = GroupKind::Toy)]
+    group: GroupKind,
+
+    /// password the prover registers with, x is the password as a number below q
+    #[arg(long, default_value = "hunter2")]
+    password: String,
+
+    /// password of the second, dishonest prover
+    #[arg(long, default_value = "hunter3")]
+    wrong_password: String,
+
+    /// print numbers in full instead of their first and last digits
+    #[arg(long)]
+    full: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum GroupKind {
+    /// p = 23, q = 11: small enough to check every step by hand
+    Toy,
+    /// the 1024-bit RFC 5114 group the server uses by default
+    Rfc5114,
+    /// the Ristretto group of Curve25519 (needs the ristretto feature)
+    Ristretto,
+    /// G1 of BLS12-381 (needs the bls12-381 feature)
+    #[value(name = "bls12-381")]
+    Bls12_381,
+}
+
+// what the walk-through needs to know about a group besides its arithmetic
+struct Backend {
+    group: Box<dyn ChaumPedersenGroup>,
+    description: String,
+    order: BigUint,
+}
+
+struct Printer {
+    full: bool,
+}
+
+impl Printer {
+    // small numbers in decimal, large ones as hex cut down to their ends
+    fn number(&self, n: &BigUint) -> String {
+        if n.bits() <= 64 {
+            return n.to_string();
+        }
+        let hex = n.to_str_radix(16);
+        if self.full || hex.len() <= 24 {
+            format!("0x{}", hex)
+        } else {
+            format!(
+                "0x{}…{} ({} bits)",
+                &hex[..10],
+                &hex[hex.len() - 10..],
+                n.bits()
+            )
+        }
+    }
+}
+
+fn modp_backend(zkp: ZKP, name: &str, printer: &Printer) -> Backend {
+    Backend {
+        description: format!(
+            "{}: p = {}, q = {}, g = {}, h = {}",
+            name,
+            printer.number(&zkp.p),
+            printer.number(&zkp.q),
+            printer.number(&zkp.g),
+            printer.number(&zkp.h)
+        ),
+        order: zkp.q.clone(),
+        group: Box::new(zkp),
+    }
+}
+
+#[cfg(feature = "ristretto")]
+fn ristretto_backend(printer: &Printer) -> Backend {
+    use zkp_chaum_pedersen::ristretto::RistrettoGroup;
+    let group = RistrettoGroup::default();
+    Backend {
+        description: format!(
+            "ristretto255: l = {}, g = the base point, h = {} (hashed to the group)",
+            printer.number(&RistrettoGroup::order()),
+            printer.number(&RistrettoGroup::element(&group.h))
+        ),
+        order: RistrettoGroup::order(),
+        group: Box::new(group),
+    }
+}
+
+#[cfg(not(feature = "ristretto"))]
+fn ristretto_backend(_printer: &Printer) -> Backend {
+    eprintln!("❌ Built without Ristretto, run with --features ristretto");
+    std::process::exit(1);
+}
+
+#[cfg(feature = "bls12-381")]
+fn bls12_381_backend(printer: &Printer) -> Backend {
+    use zkp_chaum_pedersen::bls::Bls12G1Group;
+    let group = Bls12G1Group::default();
+    Backend {
+        description: format!(
+            "BLS12-381 G1: r = {}, g = the generator, h = {} (hashed to the group)",
+            printer.number(&Bls12G1Group::order()),
+            printer.number(&Bls12G1Group::element(&group.h))
+        ),
+        order: Bls12G1Group::order(),
+        group: Box::new(group),
+    }
+}
+
+#[cfg(not(feature = "bls12-381"))]
+fn bls12_381_backend(_printer: &Printer) -> Backend {
+    eprintln!("❌ Built without BLS12-381, run with --features bls12-381");
+    std::process::exit(1);
+}
+
+// x = the password as a number, mapped into 1..q-1 (x = 0 would make y the
+// identity, which anyone can answer for)
+fn secret(backend: &Backend, password: &str) -> BigUint {
+    SecretBigUint::from_password(password).expose() % (&backend.order - 1u32) + 1u32
+}
+
+// one login, narrated. true when the verifier accepts
+fn login(
+    backend: &Backend,
+    printer: &Printer,
+    who: &str,
+    x: &BigUint,
+    y: &(BigUint, BigUint),
+) -> bool {
+    let group = &backend.group;
+    println!("\n👤 {}", who);
+    println!("   🎲 Prover draws a nonce k and commits to it");
+    let k = group.random_nonce();
+    let (r1, r2) = group.commit(&k);
+    println!("      r1 = g^k = {}", printer.number(&r1));
+    println!("      r2 = h^k = {}", printer.number(&r2));
+    println!("   ❓ Verifier answers with a random challenge");
+    let c = group.random_challenge();
+    println!("      c  = {}", printer.number(&c));
+    println!("   ✏️  Prover responds without revealing x");
+    let s = group.respond(&k, &c, x);
+    println!("      s  = k - c·x mod q = {}", printer.number(&s));
+    println!("   🔍 Verifier checks r1 = g^s · y1^c and r2 = h^s · y2^c");
+    let verified = group.check(&r1, &r2, &y.0, &y.1, &c, &s);
+    if verified {
+        println!("   ✅ Accepted");
+    } else {
+        println!("   ❌ Rejected");
+    }
+    verified
+}
+
+fn main() {
+    let args = Args::parse();
+    let printer = Printer { full: args.full };
+    let backend = match args.group {
+        GroupKind::Toy => modp_backend(
+            ZKP {
+                p: BigUint::from(23u32),
+                q: BigUint::from(11u32),
+                g: BigUint::from(4u32),
+                h: BigUint::from(9u32),
+            },
+            "toy",
+            &printer,
+        ),
+        GroupKind::Rfc5114 => {
+            modp_backend(groups::default_group(), groups::RFC5114_1024_160, &printer)
+        }
+        GroupKind::Ristretto => ristretto_backend(&printer),
+        GroupKind::Bls12_381 => bls12_381_backend(&printer),
+    };
+    println!("🔧 Group {}", backend.description);
+
+    // registration: the verifier keeps (y1, y2), never x
+    let x = secret(&backend, &args.password);
+    let y = backend.group.commit(&x);
+    println!("\n📝 Registration with password {:?}", args.password);
+    println!("   x  = {} (stays with the prover)", printer.number(&x));
+    println!("   y1 = g^x = {}", printer.number(&y.0));
+    println!("   y2 = h^x = {}", printer.number(&y.1));
+
+    let honest = login(&backend, &printer, "Honest prover", &x, &y);
+    let wrong_x = secret(&backend, &args.wrong_password);
+    let who = format!("Dishonest prover with password {:?}", args.wrong_password);
+    let dishonest = login(&backend, &printer, &who, &wrong_x, &y);
+
+    println!();
+    if wrong_x == x {
+        // only likely in the toy group, where there are 10 choices of x
+        println!("⚠️  Both passwords give the same x in this group, both log in");
+    } else if honest && !dishonest {
+        println!("🎉 The right password logs in, the wrong one doesn't");
+    } else {
+        eprintln!(
+            "❌ Unexpected outcome: honest {}, dishonest {}",
+            honest, dishonest
+        );
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,532 @@
+use crate::clock::Clock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+// accounts locked after too many failed logins in a row. the counts are kept
+// in a LockoutStore instead of the server's memory: with FileLockouts a
+// restart doesn't reset them, and replicas sharing the file count one user's
+// failures together, so an attacker can't spread guesses over them
+
+// max_failures failed answers in a row lock the account for duration, a
+// login that proves the password starts the count over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockoutPolicy {
+    pub max_failures: u32,
+    pub duration: Duration,
+}
+
+// locked_until in seconds since the Unix epoch, 0 for an account not locked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockoutRecord {
+    pub failures: u32,
+    #[serde(default)]
+    pub locked_until: u64,
+}
+
+impl LockoutRecord {
+    pub fn locked_at(&self, now: u64) -> bool {
+        now < self.locked_until
+    }
+}
+
+// update runs f on the user's record (the default one for a user without
+// one) as a single step, so failures answered at the same time, on one
+// replica or on several, are all counted. a record left at the default is
+// dropped
+pub trait LockoutStore: Debug + Send + Sync {
+    fn get(&self, user: &str) -> io::Result<LockoutRecord>;
+
+    fn update(
+        &self,
+        user: &str,
+        f: &mut dyn FnMut(&mut LockoutRecord),
+    ) -> io::Result<LockoutRecord>;
+
+    // how a store that can go away is doing, see Reconnecting. None for one
+    // that is always there
+    fn health(&self) -> Option<StoreHealth> {
+        None
+    }
+}
+
+impl Default for Box<dyn LockoutStore> {
+    fn default() -> Self {
+        Box::new(MemoryLockouts::default())
+    }
+}
+
+impl LockoutPolicy {
+    // counts a failed login at now, locking the account on the max_failures-th
+    pub fn record_failure(
+        &self,
+        store: &dyn LockoutStore,
+        user: &str,
+        now: u64,
+    ) -> io::Result<LockoutRecord> {
+        store.update(user, &mut |record| {
+            // a lock that has run out starts the count over
+            if record.locked_until != 0 && !record.locked_at(now) {
+                *record = LockoutRecord::default();
+            }
+            record.failures += 1;
+            if record.failures >= self.max_failures {
+                record.locked_until = now.saturating_add(self.duration.as_secs());
+            }
+        })
+    }
+
+    // after a login that proved the password. no write for a user with
+    // nothing counted, which is nearly every login
+    pub fn record_success(&self, store: &dyn LockoutStore, user: &str) -> io::Result<()> {
+        if store.get(user)? != LockoutRecord::default() {
+            store.update(user, &mut |record| *record = LockoutRecord::default())?;
+        }
+        Ok(())
+    }
+}
+
+// counts of this process only, lost on a restart
+#[derive(Debug, Default)]
+pub struct MemoryLockouts {
+    records: Mutex<HashMap<String, LockoutRecord>>,
+}
+
+impl LockoutStore for MemoryLockouts {
+    fn get(&self, user: &str) -> io::Result<LockoutRecord> {
+        let records = self.records.lock().unwrap();
+        Ok(records.get(user).copied().unwrap_or_default())
+    }
+
+    fn update(
+        &self,
+        user: &str,
+        f: &mut dyn FnMut(&mut LockoutRecord),
+    ) -> io::Result<LockoutRecord> {
+        let mut records = self.records.lock().unwrap();
+        let mut record = records.get(user).copied().unwrap_or_default();
+        f(&mut record);
+        if record == LockoutRecord::default() {
+            records.remove(user);
+        } else {
+            records.insert(user.to_string(), record);
+        }
+        Ok(record)
+    }
+}
+
+// counts in memory for at most max users. a new user past that evicts the
+// record that matters least: one not locked, else the lock that ends first.
+// for names nobody registered, see AuthImpl::conceal_unknown_users, which
+// must lock like a real account but can't be allowed to fill a store
+#[derive(Debug)]
+pub struct BoundedLockouts {
+    records: Mutex<HashMap<String, LockoutRecord>>,
+    max: usize,
+}
+
+pub const MAX_MADE_UP_LOCKOUTS: usize = 10_000;
+
+impl BoundedLockouts {
+    pub fn new(max: usize) -> Self {
+        BoundedLockouts {
+            records: Mutex::new(HashMap::new()),
+            max,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for BoundedLockouts {
+    fn default() -> Self {
+        BoundedLockouts::new(MAX_MADE_UP_LOCKOUTS)
+    }
+}
+
+impl LockoutStore for BoundedLockouts {
+    fn get(&self, user: &str) -> io::Result<LockoutRecord> {
+        let records = self.records.lock().unwrap();
+        Ok(records.get(user).copied().unwrap_or_default())
+    }
+
+    fn update(
+        &self,
+        user: &str,
+        f: &mut dyn FnMut(&mut LockoutRecord),
+    ) -> io::Result<LockoutRecord> {
+        let mut records = self.records.lock().unwrap();
+        let mut record = records.get(user).copied().unwrap_or_default();
+        f(&mut record);
+        if record == LockoutRecord::default() {
+            records.remove(user);
+            return Ok(record);
+        }
+        if !records.contains_key(user) && records.len() >= self.max {
+            let evicted = records
+                .iter()
+                .min_by_key(|(_, record)| record.locked_until)
+                .map(|(user, _)| user.clone());
+            if let Some(evicted) = evicted {
+                records.remove(&evicted);
+            }
+        }
+        records.insert(user.to_string(), record);
+        Ok(record)
+    }
+}
+
+// a JSON object of user name to record. every update reads the file again
+// under an exclusive lock of <path>.lock, so the processes sharing it (a
+// restarted server, replicas on one volume) take turns and see each other's
+// counts; the file is written next to itself and renamed over, never left
+// half written
+#[derive(Debug, Clone)]
+pub struct FileLockouts {
+    path: PathBuf,
+}
+
+impl FileLockouts {
+    // the file is created on the first failure
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileLockouts { path: path.into() }
+    }
+
+    fn read(&self) -> io::Result<BTreeMap<String, LockoutRecord>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl LockoutStore for FileLockouts {
+    fn get(&self, user: &str) -> io::Result<LockoutRecord> {
+        Ok(self.read()?.get(user).copied().unwrap_or_default())
+    }
+
+    fn update(
+        &self,
+        user: &str,
+        f: &mut dyn FnMut(&mut LockoutRecord),
+    ) -> io::Result<LockoutRecord> {
+        // released when the file is closed at the end
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.path.with_extension("lock"))?;
+        lock.lock()?;
+        let mut records = self.read()?;
+        let mut record = records.get(user).copied().unwrap_or_default();
+        f(&mut record);
+        if record == LockoutRecord::default() {
+            records.remove(user);
+        } else {
+            records.insert(user.to_string(), record);
+        }
+        let json = serde_json::to_string_pretty(&records).expect("records serialize");
+        let temporary = self.path.with_extension("tmp");
+        std::fs::File::create(&temporary)?.write_all(json.as_bytes())?;
+        std::fs::rename(&temporary, &self.path)?;
+        Ok(record)
+    }
+}
+
+// the counts of a store since it was wrapped in Reconnecting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoreHealth {
+    pub up: bool,
+    // calls the store failed
+    pub errors: u64,
+    // calls failed without trying the store while it was down
+    pub shed: u64,
+    // times it went down
+    pub outages: u64,
+}
+
+// waits between the tries to reach a store that is down: first after the
+// first failure, doubled after every failed try up to max
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub first: Duration,
+    pub max: Duration,
+}
+
+#[derive(Debug)]
+struct Outage {
+    retry_at: SystemTime,
+    wait: Duration,
+}
+
+#[derive(Debug)]
+struct Reachability {
+    health: StoreHealth,
+    outage: Option<Outage>,
+}
+
+// a store (a file on a network volume, or anything else that can blip) that
+// is marked down when a call fails. while it is down calls fail at once with
+// Unavailable instead of each waiting on it, and after the backoff one call
+// tries the store again: back up if that works, a longer wait if not. a
+// lockout store that is down still refuses logins (see
+// AuthImpl::check_lockout), but quickly, and the server comes back by itself
+// once the store does
+#[derive(Debug)]
+pub struct Reconnecting {
+    store: Box<dyn LockoutStore>,
+    backoff: Backoff,
+    clock: Box<dyn Clock>,
+    state: Mutex<Reachability>,
+}
+
+impl Reconnecting {
+    pub fn new(store: Box<dyn LockoutStore>, backoff: Backoff, clock: Box<dyn Clock>) -> Self {
+        Reconnecting {
+            store,
+            backoff,
+            clock,
+            state: Mutex::new(Reachability {
+                health: StoreHealth {
+                    up: true,
+                    errors: 0,
+                    shed: 0,
+                    outages: 0,
+                },
+                outage: None,
+            }),
+        }
+    }
+
+    fn call<T>(&self, f: impl FnOnce(&dyn LockoutStore) -> io::Result<T>) -> io::Result<T> {
+        let now = self.clock.now();
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(outage) = &state.outage
+                && now < outage.retry_at
+            {
+                let wait = outage.retry_at.duration_since(now).unwrap_or_default();
+                state.health.shed += 1;
+                return Err(io::Error::other(format!(
+                    "down, trying again in {}s",
+                    wait.as_secs_f64().ceil()
+                )));
+            }
+        }
+        let result = f(self.store.as_ref());
+        let mut state = self.state.lock().unwrap();
+        match &result {
+            Ok(_) if state.outage.is_some() => {
+                state.outage = None;
+                state.health.up = true;
+                println!("✅ Lockout store is back");
+            }
+            Ok(_) => {}
+            Err(e) => {
+                state.health.errors += 1;
+                let wait = match &state.outage {
+                    Some(outage) => (outage.wait * 2).min(self.backoff.max),
+                    None => {
+                        state.health.up = false;
+                        state.health.outages += 1;
+                        self.backoff.first.min(self.backoff.max)
+                    }
+                };
+                println!(
+                    "⚠️ Lockout store is down ({}), trying again in {}s",
+                    e,
+                    wait.as_secs_f64()
+                );
+                state.outage = Some(Outage {
+                    retry_at: now + wait,
+                    wait,
+                });
+            }
+        }
+        result
+    }
+}
+
+impl LockoutStore for Reconnecting {
+    fn get(&self, user: &str) -> io::Result<LockoutRecord> {
+        self.call(|store| store.get(user))
+    }
+
+    fn update(
+        &self,
+        user: &str,
+        f: &mut dyn FnMut(&mut LockoutRecord),
+    ) -> io::Result<LockoutRecord> {
+        self.call(|store| store.update(user, f))
+    }
+
+    fn health(&self) -> Option<StoreHealth> {
+        Some(self.state.lock().unwrap().health)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    const POLICY: LockoutPolicy = LockoutPolicy {
+        max_failures: 3,
+        duration: Duration::from_secs(60),
+    };
+
+    #[test]
+    fn test_failures_lock_until_the_duration_runs_out() {
+        let store = MemoryLockouts::default();
+        for now in [100, 101] {
+            let record = POLICY.record_failure(&store, "alice", now).unwrap();
+            assert!(!record.locked_at(now));
+        }
+        let record = POLICY.record_failure(&store, "alice", 102).unwrap();
+        assert_eq!(record.locked_until, 162);
+        assert!(store.get("alice").unwrap().locked_at(161));
+        assert!(!store.get("alice").unwrap().locked_at(162));
+        assert_eq!(store.get("bob").unwrap(), LockoutRecord::default());
+
+        // after the lock one failure is one failure again
+        let record = POLICY.record_failure(&store, "alice", 200).unwrap();
+        assert_eq!(record.failures, 1);
+        assert!(!record.locked_at(200));
+
+        POLICY.record_success(&store, "alice").unwrap();
+        assert_eq!(store.get("alice").unwrap(), LockoutRecord::default());
+    }
+
+    #[test]
+    fn test_file_counts_survive_a_restart_and_are_shared() {
+        let path = std::env::temp_dir().join(format!("lockouts-{}.json", std::process::id()));
+        let first = FileLockouts::new(&path);
+        POLICY.record_failure(&first, "alice", 100).unwrap();
+        POLICY.record_failure(&first, "alice", 101).unwrap();
+
+        // another replica, or the server after a restart, goes on counting
+        let second = FileLockouts::new(&path);
+        assert_eq!(second.get("alice").unwrap().failures, 2);
+        let record = POLICY.record_failure(&second, "alice", 102).unwrap();
+        assert!(record.locked_at(102));
+        assert!(first.get("alice").unwrap().locked_at(102));
+
+        POLICY.record_success(&first, "alice").unwrap();
+        assert_eq!(second.get("alice").unwrap(), LockoutRecord::default());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(path.with_extension("lock")).unwrap();
+    }
+
+    #[test]
+    fn test_bounded_store_evicts_what_matters_least() {
+        let store = BoundedLockouts::new(2);
+        for now in [100, 101, 102] {
+            POLICY.record_failure(&store, "alice", now).unwrap();
+        }
+        POLICY.record_failure(&store, "bob", 103).unwrap();
+        // carol takes bob's place, he isn't locked
+        POLICY.record_failure(&store, "carol", 104).unwrap();
+        assert_eq!(store.len(), 2);
+        assert!(store.get("alice").unwrap().locked_at(104));
+        assert_eq!(store.get("bob").unwrap(), LockoutRecord::default());
+        assert_eq!(store.get("carol").unwrap().failures, 1);
+
+        POLICY.record_success(&store, "carol").unwrap();
+        assert_eq!(store.len(), 1);
+    }
+
+    // fails every call while down is set
+    #[derive(Debug, Default)]
+    struct Flaky {
+        records: MemoryLockouts,
+        down: Arc<AtomicBool>,
+    }
+
+    impl LockoutStore for Flaky {
+        fn get(&self, user: &str) -> io::Result<LockoutRecord> {
+            match self.down.load(Ordering::SeqCst) {
+                true => Err(io::Error::other("connection reset")),
+                false => self.records.get(user),
+            }
+        }
+
+        fn update(
+            &self,
+            user: &str,
+            f: &mut dyn FnMut(&mut LockoutRecord),
+        ) -> io::Result<LockoutRecord> {
+            self.get(user)?;
+            self.records.update(user, f)
+        }
+    }
+
+    #[test]
+    fn test_a_store_that_is_down_is_tried_again_after_a_backoff() {
+        let down = Arc::new(AtomicBool::new(false));
+        let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+        let store = Reconnecting::new(
+            Box::new(Flaky {
+                down: down.clone(),
+                ..Flaky::default()
+            }),
+            Backoff {
+                first: Duration::from_secs(1),
+                max: Duration::from_secs(3),
+            },
+            Box::new(clock.clone()),
+        );
+        POLICY.record_failure(&store, "alice", 100).unwrap();
+
+        down.store(true, Ordering::SeqCst);
+        assert!(store.get("alice").is_err());
+        // not tried during the backoff
+        assert!(store.get("alice").is_err());
+        assert_eq!(
+            store.health(),
+            Some(StoreHealth {
+                up: false,
+                errors: 1,
+                shed: 1,
+                outages: 1,
+            })
+        );
+        // the waits double up to the max
+        for wait in [1, 2, 3, 3] {
+            clock.advance(Duration::from_secs(wait));
+            assert!(store.get("alice").is_err());
+        }
+        assert_eq!(store.health().unwrap().errors, 5);
+
+        down.store(false, Ordering::SeqCst);
+        clock.advance(Duration::from_secs(2));
+        assert!(store.get("alice").is_err());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(store.get("alice").unwrap().failures, 1);
+        let health = store.health().unwrap();
+        assert!(health.up);
+        assert_eq!((health.errors, health.shed, health.outages), (5, 2, 1));
+
+        // the next outage starts with the first wait again
+        down.store(true, Ordering::SeqCst);
+        assert!(store.get("alice").is_err());
+        down.store(false, Ordering::SeqCst);
+        clock.advance(Duration::from_secs(1));
+        assert!(store.get("alice").is_ok());
+        assert_eq!(store.health().unwrap().outages, 2);
+    }
+}
@@ -0,0 +1,756 @@
+use crate::blinding::{self, SealKey};
+use crate::cross_group;
+use crate::groups;
+use crate::keys::{KeyPair, PublicKey};
+use crate::known_params::{KnownParameters, OnChange, Trust};
+use crate::receipt::Receipt;
+use crate::recovery::{self, NewVerifier};
+use crate::secret::{wipe_biguint, SecretBigUint};
+use crate::session_key::{client_shared_secret, SessionKey};
+use crate::tickets::{BlindTicket, Ticket};
+use crate::verifier;
+use crate::zkp_auth::auth_client::AuthClient;
+use crate::zkp_auth::*;
+use crate::{Proof, ZKP};
+use num_bigint::BigUint;
+use prost::Message;
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tonic::transport::Channel;
+use tonic::{Code, Status};
+
+// how check_parameters' refusals of the server start
+const PARAMETERS_DIFFER: &str = "server parameters";
+
+// why a login failed, for an application to branch on instead of reading the
+// message. taken from the v2 error detail when the server sent one, else from
+// the v1 code and message; the Status is kept for the message and request ID
+#[derive(Debug, Clone)]
+pub enum AuthError {
+    // a wrong password, an unknown user or a proof for another channel
+    WrongCredentials(Status),
+    // the challenge is gone (expired, answered or unknown to this server) or
+    // has taken all its answers: start the login over
+    ChallengeExpired(Status),
+    // too many failed logins, retry_after as the server gave it
+    LockedOut {
+        retry_after: Option<Duration>,
+        status: Status,
+    },
+    // unreachable, draining or past the deadline: retry, or try another
+    // instance
+    ServerUnavailable(Status),
+    // the server's group parameters aren't the client's or the pinned ones,
+    // or it doesn't serve the group
+    ParameterMismatch(Status),
+    // the server lost or replaced the registration of a receipt the login
+    // presented: register again, and find out where it went
+    RegistrationLost(Status),
+    Other(Status),
+}
+
+impl AuthError {
+    pub fn status(&self) -> &Status {
+        match self {
+            AuthError::WrongCredentials(status)
+            | AuthError::ChallengeExpired(status)
+            | AuthError::LockedOut { status, .. }
+            | AuthError::ServerUnavailable(status)
+            | AuthError::ParameterMismatch(status)
+            | AuthError::RegistrationLost(status)
+            | AuthError::Other(status) => status,
+        }
+    }
+
+    pub fn into_status(self) -> Status {
+        match self {
+            AuthError::WrongCredentials(status)
+            | AuthError::ChallengeExpired(status)
+            | AuthError::LockedOut { status, .. }
+            | AuthError::ServerUnavailable(status)
+            | AuthError::ParameterMismatch(status)
+            | AuthError::RegistrationLost(status)
+            | AuthError::Other(status) => status,
+        }
+    }
+}
+
+impl From<Status> for AuthError {
+    fn from(status: Status) -> Self {
+        use v2::ErrorReason;
+        let reason = match status.details() {
+            [] => None,
+            details => v2::ErrorDetail::decode(details)
+                .ok()
+                .and_then(|detail| ErrorReason::try_from(detail.reason).ok()),
+        };
+        let message = status.message();
+        let locked = message.starts_with(crate::service::ACCOUNT_LOCKED);
+        match (reason, status.code()) {
+            (Some(ErrorReason::AccountLocked), _) | (_, Code::PermissionDenied) if locked => {
+                // "... for N more seconds"
+                let retry_after = message
+                    .split_whitespace()
+                    .rev()
+                    .nth(2)
+                    .and_then(|seconds| seconds.parse().ok())
+                    .map(Duration::from_secs);
+                AuthError::LockedOut {
+                    retry_after,
+                    status,
+                }
+            }
+            (Some(ErrorReason::AccountLocked), _) => AuthError::LockedOut {
+                retry_after: None,
+                status,
+            },
+            (Some(ErrorReason::NotVerified | ErrorReason::UserNotFound), _) => {
+                AuthError::WrongCredentials(status)
+            }
+            (Some(ErrorReason::AuthIdNotFound | ErrorReason::AnswersExhausted), _) => {
+                AuthError::ChallengeExpired(status)
+            }
+            (Some(ErrorReason::Draining | ErrorReason::ServerKeyUnavailable), _) => {
+                AuthError::ServerUnavailable(status)
+            }
+            (Some(ErrorReason::UnsupportedGroup), _) => AuthError::ParameterMismatch(status),
+            (Some(_), _) => AuthError::Other(status),
+            // v1, told apart by the message where codes are shared
+            (None, Code::Unavailable | Code::DeadlineExceeded) => {
+                AuthError::ServerUnavailable(status)
+            }
+            (None, Code::NotFound) if message.starts_with("AuthId:") => {
+                AuthError::ChallengeExpired(status)
+            }
+            (None, Code::NotFound) => AuthError::WrongCredentials(status),
+            (None, Code::PermissionDenied)
+                if message.starts_with(crate::service::ANSWERS_EXHAUSTED) =>
+            {
+                AuthError::ChallengeExpired(status)
+            }
+            (None, Code::PermissionDenied) if message.starts_with("AuthId:") => {
+                AuthError::WrongCredentials(status)
+            }
+            (None, Code::FailedPrecondition) if message.starts_with(PARAMETERS_DIFFER) => {
+                AuthError::ParameterMismatch(status)
+            }
+            (None, Code::FailedPrecondition)
+                if message.starts_with(crate::service::REGISTRATION_LOST) =>
+            {
+                AuthError::RegistrationLost(status)
+            }
+            (None, _) => AuthError::Other(status),
+        }
+    }
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.status().message())
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+// tls-server-end-point binding of the current connection, set on every (re)connect
+pub type ChannelBinding = Arc<Mutex<Option<Vec<u8>>>>;
+
+// settings shared by every login on the connection
+pub struct LoginOptions<'a> {
+    // pinned server key, the server must then prove its identity
+    pub server_key: Option<&'a PublicKey>,
+    pub channel_binding: &'a ChannelBinding,
+    pub salted: bool,
+    // what a salted registration derives x with
+    pub kdf: verifier::KdfParams,
+    pub blinded: bool,
+    pub commit_nonce: bool,
+    // answer with a Fiat–Shamir proof bound to the auth_id instead of s for c
+    pub non_interactive: bool,
+    // the server's application id, empty for a server without one
+    pub application: &'a [u8],
+    // input to the server's second factor (a TOTP code), empty for none
+    pub second_factor: &'a str,
+    // the device logging in, for the server's device list
+    pub device: Option<&'a DeviceInfo>,
+    // the receipt of the user's registration, for the server to confirm it
+    // still has it. not sent with a blinded login
+    pub receipt: Option<&'a Receipt>,
+}
+
+// client side of a login between the challenge and the answer
+#[derive(Debug)]
+pub struct PendingLogin<'z> {
+    k: SecretBigUint,
+    pub challenge: AuthenticationChallengeResponse,
+    // (g, h), borrowed from the group, or (g ** t, h ** t) for a blinded login
+    bases: Cow<'z, ZKP>,
+    // blinded login: key the user and the salt are sealed with
+    seal_key: Option<SealKey>,
+}
+
+// y1 = g ** x mod p ; y2 = h ** x mod p
+// with a salted verifier x comes from the password and a fresh salt
+pub async fn register(
+    client: &mut AuthClient<Channel>,
+    zkp: &ZKP,
+    user: &str,
+    password: &str,
+    salted: bool,
+) -> Result<tonic::Response<RegisterResponse>, Status> {
+    let kdf = salted.then(verifier::KdfParams::default);
+    register_with_kdf(client, zkp, user, password, kdf.as_ref()).await
+}
+
+// salted with the given KDF parameters, or the raw password without
+pub async fn register_with_kdf(
+    client: &mut AuthClient<Channel>,
+    zkp: &ZKP,
+    user: &str,
+    password: &str,
+    kdf: Option<&verifier::KdfParams>,
+) -> Result<tonic::Response<RegisterResponse>, Status> {
+    register_on_device(client, zkp, user, password, kdf, None).await
+}
+
+// as register_with_kdf, naming the device the registration comes from
+pub async fn register_on_device(
+    client: &mut AuthClient<Channel>,
+    zkp: &ZKP,
+    user: &str,
+    password: &str,
+    kdf: Option<&verifier::KdfParams>,
+    device: Option<&DeviceInfo>,
+) -> Result<tonic::Response<RegisterResponse>, Status> {
+    register_with_recovery(client, zkp, user, password, kdf, device, None).await
+}
+
+// as register_on_device, with the public half of a recovery key made in the
+// same group (see recovery)
+pub async fn register_with_recovery(
+    client: &mut AuthClient<Channel>,
+    zkp: &ZKP,
+    user: &str,
+    password: &str,
+    kdf: Option<&verifier::KdfParams>,
+    device: Option<&DeviceInfo>,
+    recovery: Option<&PublicKey>,
+) -> Result<tonic::Response<RegisterResponse>, Status> {
+    let group = group_name(zkp)?;
+    let (salt, y1, y2) = new_verifier(zkp, user, password, kdf);
+    let request = RegisterRequest {
+        user: user.to_string(),
+        y1: y1.to_bytes_be(),
+        y2: y2.to_bytes_be(),
+        salt,
+        group: group.clone(),
+        kdf: kdf.map(|kdf| (*kdf).into()),
+        device: device.cloned(),
+        recovery_key: recovery.map(|key| RecoveryKey {
+            y1: key.y1.to_bytes_be(),
+            y2: key.y2.to_bytes_be(),
+            group,
+        }),
+    };
+    client.register(request).await
+}
+
+// (salt, y1, y2) for the password, with a fresh salt when there is a KDF
+fn new_verifier(
+    zkp: &ZKP,
+    user: &str,
+    password: &str,
+    kdf: Option<&verifier::KdfParams>,
+) -> (Vec<u8>, BigUint, BigUint) {
+    let salt = match kdf {
+        Some(_) => verifier::generate_salt(),
+        None => Vec::new(),
+    };
+    let x = verifier::login_secret(
+        zkp,
+        user,
+        password,
+        &salt,
+        &kdf.copied().unwrap_or_default(),
+    );
+    let y1 = ZKP::exponentiate(&zkp.g, x.expose(), &zkp.p);
+    let y2 = ZKP::exponentiate(&zkp.h, x.expose(), &zkp.p);
+    (salt, y1, y2)
+}
+
+// the receipt of a registration, None when the server has no key to sign
+// one with. it must be for the user, group and parameters the client
+// registered with, and with a pinned server key signed by it
+pub fn receipt_of(
+    response: &RegisterResponse,
+    zkp: &ZKP,
+    user: &str,
+    server_key: Option<&PublicKey>,
+) -> Result<Option<Receipt>, Status> {
+    let Some(receipt) = &response.receipt else {
+        return Ok(None);
+    };
+    let receipt = Receipt::try_from(receipt).map_err(Status::invalid_argument)?;
+    if receipt.user != user
+        || receipt.group != group_name(zkp)?
+        || receipt.params_fingerprint != groups::fingerprint(zkp)
+    {
+        return Err(Status::invalid_argument(
+            "receipt is for another registration than the one sent",
+        ));
+    }
+    if let Some(key) = server_key
+        && !receipt.verify(&groups::default_group(), key)
+    {
+        return Err(Status::invalid_argument(
+            "receipt is not signed by the pinned server key",
+        ));
+    }
+    Ok(Some(receipt))
+}
+
+// the server only knows the groups in groups::NAMES
+fn group_name(zkp: &ZKP) -> Result<String, Status> {
+    groups::name_of(zkp)
+        .map(str::to_string)
+        .ok_or_else(|| Status::invalid_argument("not a named group"))
+}
+
+// the server's parameters for the group must be these, both the ones the
+// client computes in and a fingerprint pinned in its configuration. the
+// fingerprint is taken over what the server sent, not the one it claims
+pub async fn check_parameters(
+    client: &mut AuthClient<Channel>,
+    zkp: &ZKP,
+    pinned: Option<&[u8; 32]>,
+) -> Result<[u8; 32], Status> {
+    let request = GetParametersRequest {
+        group: group_name(zkp)?,
+    };
+    let response = client.get_parameters(request).await?.into_inner();
+    let served = ZKP {
+        p: BigUint::from_bytes_be(&response.p),
+        q: BigUint::from_bytes_be(&response.q),
+        g: BigUint::from_bytes_be(&response.g),
+        h: BigUint::from_bytes_be(&response.h),
+    };
+    let fingerprint = groups::fingerprint(&served);
+    if let Some(pinned) = pinned
+        && fingerprint != *pinned
+    {
+        return Err(Status::failed_precondition(format!(
+            "{} have fingerprint {}, {} is pinned",
+            PARAMETERS_DIFFER,
+            hex::encode(fingerprint),
+            hex::encode(pinned)
+        )));
+    }
+    if fingerprint != groups::fingerprint(zkp) {
+        return Err(Status::failed_precondition(format!(
+            "{} for {} differ from the client's",
+            PARAMETERS_DIFFER, response.group
+        )));
+    }
+    Ok(fingerprint)
+}
+
+// check_parameters, and the fingerprint trusted on first use: recorded for the
+// server the first time, and a change since handled by on_change. a refused
+// change fails like a pinned fingerprint that doesn't match
+pub async fn check_known_parameters(
+    client: &mut AuthClient<Channel>,
+    zkp: &ZKP,
+    server: &str,
+    known: &mut KnownParameters,
+    on_change: OnChange,
+) -> Result<Trust, Status> {
+    let group = group_name(zkp)?;
+    let fingerprint = check_parameters(client, zkp, None).await?;
+    let trust = known
+        .check(server, &group, fingerprint, on_change)
+        .map_err(|changed| {
+            Status::failed_precondition(format!(
+                "{} for {} changed from {} to {}, remove it from {} if that was expected",
+                PARAMETERS_DIFFER,
+                changed.group,
+                hex::encode(changed.known),
+                hex::encode(changed.served),
+                known.path().display()
+            ))
+        })?;
+    known.save().map_err(|e| {
+        Status::internal(format!("failed to save {}: {}", known.path().display(), e))
+    })?;
+    Ok(trust)
+}
+
+// r1 = g ** k mod p ; r2 = h ** k mod p
+// a blinded login uses g ** t, h ** t instead and seals the user and t to the
+// server (in the default group, where the server key is)
+pub async fn create_challenge<'z>(
+    client: &mut AuthClient<Channel>,
+    zkp: &'z ZKP,
+    user: &str,
+    options: &LoginOptions<'_>,
+) -> Result<PendingLogin<'z>, Status> {
+    let group = group_name(zkp)?;
+    let (bases, blinded, seal_key) = match options.server_key.filter(|_| options.blinded) {
+        Some(server_key) => {
+            let server_group = groups::default_group();
+            let mut t = ZKP::generate_random_number_below(&server_group.q);
+            while t == BigUint::from(0u32) {
+                t = ZKP::generate_random_number_below(&server_group.q);
+            }
+            let t = SecretBigUint::new(t);
+            let (u_public, key) = SealKey::to_server(&server_group, server_key);
+            let blinded = BlindedIdentity {
+                u_public: u_public.to_bytes_be(),
+                sealed_user: key.xor(b"user", user.as_bytes()),
+                sealed_t: key.seal_scalar(&server_group.q, t.expose()).to_bytes_be(),
+            };
+            (
+                Cow::Owned(blinding::blinded_bases(zkp, t.expose())),
+                Some(blinded),
+                Some(key),
+            )
+        }
+        None => (Cow::Borrowed(zkp), None, None),
+    };
+
+    let k = SecretBigUint::new(ZKP::generate_random_number_below(&zkp.q));
+    let r1 = ZKP::exponentiate(&bases.g, k.expose(), &zkp.p);
+    let r2 = ZKP::exponentiate(&bases.h, k.expose(), &zkp.p);
+    // nonce commitment: only H(r1, r2) now, r1 and r2 follow with the answer.
+    // non-interactive: nothing now, the proof carries them
+    let (r1, r2, nonce_commitment) = if options.non_interactive {
+        (Vec::new(), Vec::new(), Vec::new())
+    } else if options.commit_nonce {
+        (
+            Vec::new(),
+            Vec::new(),
+            bases.commit_nonce(&r1, &r2).to_vec(),
+        )
+    } else {
+        (r1.to_bytes_be(), r2.to_bytes_be(), Vec::new())
+    };
+
+    let request = AuthenticationChallengeRequest {
+        user: match blinded {
+            Some(_) => String::new(),
+            None => user.to_string(),
+        },
+        r1,
+        r2,
+        nonce_commitment,
+        group,
+        receipt: match blinded {
+            Some(_) => None,
+            None => options.receipt.map(Into::into),
+        },
+        blinded,
+    };
+    let response = client.create_authentication_challenge(request).await?;
+    Ok(PendingLogin {
+        k,
+        challenge: response.into_inner(),
+        bases,
+        seal_key,
+    })
+}
+
+// s = k - c * x mod q
+// with a pinned server key, the server must answer server_c for its own commitment
+pub async fn answer_challenge(
+    client: &mut AuthClient<Channel>,
+    zkp: &ZKP,
+    login: PendingLogin<'_>,
+    user: &str,
+    password: &str,
+    options: &LoginOptions<'_>,
+) -> Result<(String, Option<SessionKey>), Status> {
+    let PendingLogin {
+        k,
+        challenge,
+        bases,
+        seal_key,
+    } = login;
+    let r1 = ZKP::exponentiate(&bases.g, k.expose(), &zkp.p);
+    let r2 = ZKP::exponentiate(&bases.h, k.expose(), &zkp.p);
+    // over TLS both sides answer c' = H(r1, r2, c, channel binding) instead of c
+    let channel_binding = options.channel_binding.lock().unwrap().clone();
+    let bind = |zkp: &ZKP, r1: &BigUint, r2: &BigUint, c: BigUint| match &channel_binding {
+        Some(binding) => zkp.bind_challenge(options.application, r1, r2, &c, binding),
+        None => c,
+    };
+
+    let c = bind(&bases, &r1, &r2, BigUint::from_bytes_be(&challenge.c));
+    let (salt, kdf) = match &seal_key {
+        Some(key) => {
+            let kdf = key.xor(b"kdf", &challenge.sealed_kdf);
+            let kdf = match kdf.is_empty() {
+                true => None,
+                false => Some(KdfParams::decode(kdf.as_slice()).map_err(|_| {
+                    Status::invalid_argument("server sent sealed KDF parameters that don't open")
+                })?),
+            };
+            (key.xor(b"salt", &challenge.salt), kdf)
+        }
+        None => (challenge.salt.clone(), challenge.kdf),
+    };
+    // the server picks the parameters, but not unbounded ones
+    let kdf = crate::proto::kdf_from_wire(kdf.as_ref()).map_err(Status::invalid_argument)?;
+    verifier::KdfPolicy::permissive()
+        .check(&kdf)
+        .map_err(Status::invalid_argument)?;
+    let x = verifier::login_secret(zkp, user, password, &salt, &kdf);
+    // non-interactive: c = H(..., H(auth_id, channel binding)) instead of the server's
+    let (c, s, answered_binding) = if options.non_interactive {
+        let binding = channel_binding.clone().unwrap_or_default();
+        let context = ZKP::answer_context(&challenge.auth_id, &binding);
+        let proof = bases.prove_non_interactive_with_nonce(
+            options.application,
+            x.expose(),
+            k.expose(),
+            &context,
+        );
+        (proof.c.to_bytes_be(), proof.s, binding)
+    } else {
+        (
+            Vec::new(),
+            zkp.solve(k.expose(), &c, x.expose()),
+            Vec::new(),
+        )
+    };
+    drop(x);
+
+    // the server proves its identity in the default group, whatever the user's
+    let server_group = groups::default_group();
+    let server_key = options.server_key;
+    let server_c = server_key.map(|_| ZKP::generate_random_number_below(&server_group.q));
+    let request = AuthenticationAnswerRequest {
+        auth_id: challenge.auth_id.clone(),
+        s: s.to_bytes_be(),
+        server_c: server_c
+            .as_ref()
+            .map(|c| c.to_bytes_be())
+            .unwrap_or_default(),
+        r1: match options.commit_nonce {
+            true => r1.to_bytes_be(),
+            false => Vec::new(),
+        },
+        r2: match options.commit_nonce {
+            true => r2.to_bytes_be(),
+            false => Vec::new(),
+        },
+        c,
+        channel_binding: answered_binding,
+        second_factor: options.second_factor.to_string(),
+        device: options.device.cloned(),
+    };
+    let response = client.verify_authentication(request).await?.into_inner();
+
+    if let (Some(server_key), Some(server_c)) = (server_key, server_c) {
+        let server_r1 = BigUint::from_bytes_be(&challenge.server_r1);
+        let server_r2 = BigUint::from_bytes_be(&challenge.server_r2);
+        let server_s = BigUint::from_bytes_be(&response.server_s);
+        let server_c = bind(&server_group, &server_r1, &server_r2, server_c);
+        let verified = !challenge.server_r1.is_empty()
+            && !challenge.server_r2.is_empty()
+            && server_group.verify(
+                &server_r1,
+                &server_r2,
+                &server_key.y1,
+                &server_key.y2,
+                &server_c,
+                &server_s,
+            );
+        if !verified {
+            return Err(Status::unauthenticated(
+                "server failed to prove its identity",
+            ));
+        }
+    }
+
+    // shared = key_share ** k mod p, servers without key agreement send no key_share
+    let mut session_key = None;
+    if !response.key_share.is_empty() {
+        let key_share = BigUint::from_bytes_be(&response.key_share);
+        let mut shared = client_shared_secret(zkp, &key_share, k.expose())
+            .ok_or_else(|| Status::unauthenticated("server sent an invalid key share"))?;
+        let key = SessionKey::derive(&shared, &r1, &r2, &key_share, &challenge.auth_id);
+        wipe_biguint(&mut shared);
+        if !key.verify_confirmation(&response.key_confirmation) {
+            return Err(Status::unauthenticated("server key confirmation failed"));
+        }
+        session_key = Some(key);
+    }
+    Ok((response.session_id, session_key))
+}
+
+// create_challenge and answer_challenge, with the failure as an AuthError
+pub async fn login(
+    client: &mut AuthClient<Channel>,
+    zkp: &ZKP,
+    user: &str,
+    password: &str,
+    options: &LoginOptions<'_>,
+) -> Result<(String, Option<SessionKey>), AuthError> {
+    let pending = create_challenge(client, zkp, user, options).await?;
+    Ok(answer_challenge(client, zkp, pending, user, password, options).await?)
+}
+
+// moves the user's verifier from `from` to the stronger group `to` without
+// registering again: logs in under `from`, then sends the verifier in `to`
+// with a proof that the same x is behind both (see cross_group), authorized
+// by the new session. returns that session, which stays valid
+pub async fn migrate_group(
+    client: &mut AuthClient<Channel>,
+    from: &ZKP,
+    to: &ZKP,
+    user: &str,
+    password: &str,
+    options: &LoginOptions<'_>,
+) -> Result<(String, SessionKey), Status> {
+    // the salt has to come back in the clear, a blinded login would seal it
+    let options = LoginOptions {
+        blinded: false,
+        ..*options
+    };
+    let login = create_challenge(client, from, user, &options).await?;
+    let salt = login.challenge.salt.clone();
+    let kdf = crate::proto::kdf_from_wire(login.challenge.kdf.as_ref())
+        .map_err(Status::invalid_argument)?;
+    let (session_id, key) = answer_challenge(client, from, login, user, password, &options).await?;
+    let key = key.ok_or_else(|| {
+        Status::failed_precondition("server sent no session key to authorize the move with")
+    })?;
+
+    let x = verifier::unreduced_secret(user, password, &salt, &kdf);
+    let proof = cross_group::prove(from, to, x.expose(), user.as_bytes())
+        .ok_or_else(|| Status::invalid_argument("groups too large for a cross-group proof"))?;
+    drop(x);
+    let to_x = verifier::login_secret(to, user, password, &salt, &kdf);
+    let request = MigrateGroupRequest {
+        session_id: session_id.clone(),
+        mac: key.mac(session_id.as_bytes()),
+        group: group_name(to)?,
+        y1: ZKP::exponentiate(&to.g, to_x.expose(), &to.p).to_bytes_be(),
+        y2: ZKP::exponentiate(&to.h, to_x.expose(), &to.p).to_bytes_be(),
+        c: proof.c.to_bytes_be(),
+        s: proof.s.to_bytes_be(),
+    };
+    client.migrate_group(request).await?;
+    Ok((session_id, key))
+}
+
+// sets a verifier for the new password with the recovery key registered for
+// the user, both in zkp. the proof is dated by the client's clock, which has
+// to be within recovery::MAX_AGE of the server's
+pub async fn recover_account(
+    client: &mut AuthClient<Channel>,
+    zkp: &ZKP,
+    user: &str,
+    password: &str,
+    kdf: Option<&verifier::KdfParams>,
+    recovery_key: &KeyPair,
+    application: &[u8],
+) -> Result<tonic::Response<RecoverAccountResponse>, Status> {
+    let group = group_name(zkp)?;
+    let (salt, y1, y2) = new_verifier(zkp, user, password, kdf);
+    let issued_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    // the server holds an unsalted verifier to the default parameters
+    let bound_kdf = kdf.copied().unwrap_or_default();
+    let new = NewVerifier {
+        y1: &y1,
+        y2: &y2,
+        salt: &salt,
+        group: groups::resolve(&group),
+        kdf: &bound_kdf,
+    };
+    let proof = recovery::prove(zkp, application, recovery_key, user, &new, issued_at);
+    let request = RecoverAccountRequest {
+        user: user.to_string(),
+        y1: y1.to_bytes_be(),
+        y2: y2.to_bytes_be(),
+        salt,
+        group,
+        kdf: kdf.map(|kdf| (*kdf).into()),
+        issued_at,
+        c: proof.c.to_bytes_be(),
+        s: proof.s.to_bytes_be(),
+    };
+    client.recover_account(request).await
+}
+
+// has the server evaluate `count` anonymous tickets for the session (see
+// tickets), each checked against the pinned server key: without one, the
+// server could issue them under a key kept for this user and recognize them
+// when they are spent. returns the tickets and how many the session has left
+pub async fn issue_tickets(
+    client: &mut AuthClient<Channel>,
+    session_id: &str,
+    key: &SessionKey,
+    count: usize,
+    options: &LoginOptions<'_>,
+) -> Result<(Vec<Ticket>, u32), Status> {
+    let server_key = options
+        .server_key
+        .ok_or_else(|| Status::failed_precondition("tickets need the server's public key"))?;
+    // tickets are in the default group, where the server key is
+    let zkp = groups::default_group();
+    let blind: Vec<BlindTicket> = (0..count).map(|_| BlindTicket::new(&zkp)).collect();
+    let request = IssueTicketsRequest {
+        session_id: session_id.to_string(),
+        mac: key.mac(session_id.as_bytes()),
+        blinded: blind
+            .iter()
+            .map(|ticket| ticket.blinded.to_bytes_be())
+            .collect(),
+    };
+    let response = client.issue_tickets(request).await?.into_inner();
+    if response.tickets.len() != blind.len() {
+        return Err(Status::internal(
+            "server evaluated another number of tickets",
+        ));
+    }
+    let tickets = blind
+        .into_iter()
+        .zip(response.tickets)
+        .map(|(ticket, evaluated)| {
+            let proof = Proof {
+                c: BigUint::from_bytes_be(&evaluated.c),
+                s: BigUint::from_bytes_be(&evaluated.s),
+            };
+            ticket
+                .finish(
+                    &zkp,
+                    options.application,
+                    server_key,
+                    &BigUint::from_bytes_be(&evaluated.evaluated),
+                    &proof,
+                )
+                .ok_or_else(|| {
+                    Status::unauthenticated("server evaluated a ticket under another key")
+                })
+        })
+        .collect::<Result<Vec<_>, Status>>()?;
+    Ok((tickets, response.tickets_left))
+}
+
+// spends a ticket, on any connection and without a session
+pub async fn redeem_ticket(
+    client: &mut AuthClient<Channel>,
+    ticket: &Ticket,
+) -> Result<(), Status> {
+    let request = RedeemTicketRequest {
+        nonce: ticket.nonce.to_vec(),
+        w: ticket.w.to_bytes_be(),
+    };
+    client.redeem_ticket(request).await?;
+    Ok(())
+}
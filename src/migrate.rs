@@ -0,0 +1,191 @@
+use clap::Parser;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use zkp_chaum_pedersen::groups;
+use zkp_chaum_pedersen::sealing::StoreKey;
+use zkp_chaum_pedersen::snapshot::{UserRecord, UserSnapshot, VERSION};
+
+#[derive(Parser, Debug)]
+#[command(about = "Migrate Chaum-Pedersen ZKP registrations between stores and groups")]
+struct Args {
+    /// user snapshot to read (written by the server's --users-file)
+    #[arg(long)]
+    from: PathBuf,
+
+    /// user snapshot to write; registrations already in it are kept unless
+    /// the source has the same user
+    #[arg(long, required_unless_present = "check")]
+    to: Option<PathBuf>,
+
+    /// group the registrations should end up in (rfc5114-1024-160 or rfc5114-2048-256)
+    #[arg(long)]
+    to_group: Option<String>,
+
+    /// store key (64 hex digits) the source snapshot is sealed with
+    #[arg(long)]
+    from_store_key_file: Option<PathBuf>,
+
+    /// store key to seal the target snapshot with, and to read it with if it
+    /// is sealed already; without one the target is written plain
+    #[arg(long)]
+    to_store_key_file: Option<PathBuf>,
+
+    /// report what would be migrated without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// only check the source: its schema version, that every record would
+    /// restore, and which records aren't in the form this release writes.
+    /// exits with 3 when some aren't, 1 when the snapshot can't be used
+    #[arg(long, conflicts_with_all = ["to", "to_group", "dry_run"])]
+    check: bool,
+
+    /// print progress every this many users
+    #[arg(long, default_value_t = 1000)]
+    progress_every: usize,
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Some(group) = &args.to_group
+        && groups::by_name(group).is_none()
+    {
+        eprintln!(
+            "❌ Unknown group {}, expected one of {}",
+            group,
+            groups::NAMES.join(", ")
+        );
+        std::process::exit(1);
+    }
+
+    let store_key = |path: &Option<PathBuf>| {
+        path.as_ref().map(|path| {
+            StoreKey::load(path).unwrap_or_else(|e| {
+                eprintln!("❌ Failed to read store key {}: {}", path.display(), e);
+                std::process::exit(1);
+            })
+        })
+    };
+    let from_key = store_key(&args.from_store_key_file);
+    let to_key = store_key(&args.to_store_key_file);
+
+    if args.check {
+        check(&args.from, from_key.as_ref());
+        return;
+    }
+    let to = args.to.clone().expect("clap requires --to without --check");
+
+    let source = match UserSnapshot::load_with(&args.from, from_key.as_ref()) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("❌ Failed to read {}: {}", args.from.display(), e);
+            std::process::exit(1);
+        }
+    };
+    // every record is checked before anything is written
+    if let Err(e) = source.users() {
+        eprintln!("❌ {} is not a valid snapshot: {}", args.from.display(), e);
+        std::process::exit(1);
+    }
+    let mut target = if to.exists() {
+        match UserSnapshot::load_with(&to, to_key.as_ref()) {
+            Ok(target) => target,
+            Err(e) => {
+                eprintln!("❌ Failed to read {}: {}", to.display(), e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        UserSnapshot {
+            version: VERSION,
+            users: Vec::new(),
+        }
+    };
+
+    // by user name, as the server keeps them
+    let mut users: BTreeMap<String, UserRecord> = target
+        .users
+        .drain(..)
+        .map(|user| (user.user.clone(), user))
+        .collect();
+    let total = source.users.len();
+    let (mut copied, mut replaced, mut stranded) = (0, 0, Vec::new());
+    for (i, record) in source.users.iter().enumerate() {
+        // a verifier can't be moved to another group without x, or a proof
+        // that the same x is behind both, which only the user can make: those
+        // users keep their group until they move with MigrateGroup or
+        // register again
+        if let Some(group) = &args.to_group
+            && groups::resolve(&record.group) != groups::resolve(group)
+        {
+            stranded.push(record);
+        }
+        match users.insert(record.user.clone(), record.clone()) {
+            Some(_) => replaced += 1,
+            None => copied += 1,
+        }
+        if args.progress_every > 0 && (i + 1) % args.progress_every == 0 {
+            println!("⏳ {}/{} users", i + 1, total);
+        }
+    }
+    target.users = users.into_values().collect();
+
+    println!(
+        "📦 {} users: {} new, {} replacing a registration in {}",
+        total,
+        copied,
+        replaced,
+        to.display()
+    );
+    if let Some(group) = &args.to_group {
+        println!(
+            "🔁 {} already in {}, {} must move themselves (MigrateGroup) or register again",
+            total - stranded.len(),
+            groups::resolve(group),
+            stranded.len()
+        );
+        for record in &stranded {
+            println!("  ⚠️ {} stays in {}", record.user, record.group);
+        }
+    }
+
+    if args.dry_run {
+        println!("💡 Dry run, nothing was written");
+        return;
+    }
+    if let Err(e) = target.save_with(&to, to_key.as_ref()) {
+        eprintln!("❌ Failed to write {}: {}", to.display(), e);
+        std::process::exit(1);
+    }
+    println!("✅ Wrote {}", to.display());
+}
+
+// exits unless the snapshot is usable and every record in the current form
+fn check(path: &Path, key: Option<&StoreKey>) {
+    let (snapshot, drifted) = match UserSnapshot::check(path, key) {
+        Ok(checked) => checked,
+        Err(e) => {
+            eprintln!("❌ {} can't be used: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "🔎 {}: version {} (this release writes {}), {} users",
+        path.display(),
+        snapshot.version,
+        VERSION,
+        snapshot.users.len()
+    );
+    if drifted.is_empty() {
+        println!("✅ Every record is in the current form");
+        return;
+    }
+    for user in &drifted {
+        println!("  ⚠️ {} is in an older form", user);
+    }
+    println!(
+        "💡 {} records load as they are; migrate the file onto itself to rewrite them",
+        drifted.len()
+    );
+    std::process::exit(3);
+}
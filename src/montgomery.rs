@@ -0,0 +1,400 @@
+use num_bigint::BigUint;
+use std::sync::{Arc, Mutex};
+
+// reduction state for one odd modulus, computed once and shared by every
+// exponentiation and product of a verification (or a whole batch of them)
+// instead of being rebuilt by each modpow call.
+// numbers in Montgomery form are a * R mod p with R = 2 ** (64 * limbs), so a
+// product is reduced a limb at a time with no division by p
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Montgomery {
+    modulus: BigUint,
+    // little-endian 64-bit limbs of the modulus
+    limbs: Vec<u64>,
+    // -p ** -1 mod 2 ** 64
+    inverse: u64,
+    // R ** 2 mod p, to bring a number into Montgomery form
+    r_squared: Vec<u64>,
+    // R mod p, 1 in Montgomery form
+    one: Vec<u64>,
+}
+
+fn to_limbs(n: &BigUint, len: usize) -> Vec<u64> {
+    let mut limbs = n.to_u64_digits();
+    limbs.resize(len, 0);
+    limbs
+}
+
+fn from_limbs(limbs: &[u64]) -> BigUint {
+    BigUint::new(
+        limbs
+            .iter()
+            .flat_map(|limb| [*limb as u32, (*limb >> 32) as u32])
+            .collect(),
+    )
+}
+
+impl Montgomery {
+    // None for an even modulus (or 1), which has no Montgomery form
+    pub fn new(modulus: &BigUint) -> Option<Self> {
+        if *modulus == BigUint::from(1u32) || !modulus.bit(0) {
+            return None;
+        }
+        let limbs = modulus.to_u64_digits();
+        let len = limbs.len();
+
+        // p ** -1 mod 2 ** 64 by Newton's iteration, each step doubles the bits
+        // that are right (an odd p is its own inverse mod 2)
+        let mut inverse = 1u64;
+        for _ in 0..6 {
+            inverse = inverse.wrapping_mul(2u64.wrapping_sub(limbs[0].wrapping_mul(inverse)));
+        }
+
+        let r = BigUint::from(1u32) << (64 * len);
+        Some(Montgomery {
+            modulus: modulus.clone(),
+            inverse: inverse.wrapping_neg(),
+            r_squared: to_limbs(&((&r * &r) % modulus), len),
+            one: to_limbs(&(r % modulus), len),
+            limbs,
+        })
+    }
+
+    // the context of a modulus in use, set up on first use. the few most
+    // recent moduli are kept, a server only has a couple of groups
+    pub fn shared(modulus: &BigUint) -> Option<Arc<Self>> {
+        const KEPT: usize = 8;
+        static SHARED: Mutex<Vec<Arc<Montgomery>>> = Mutex::new(Vec::new());
+        let mut shared = SHARED.lock().unwrap();
+        if let Some(context) = shared.iter().find(|context| context.modulus == *modulus) {
+            return Some(context.clone());
+        }
+        let context = Arc::new(Montgomery::new(modulus)?);
+        if shared.len() == KEPT {
+            shared.remove(0);
+        }
+        shared.push(context.clone());
+        Some(context)
+    }
+
+    pub fn modulus(&self) -> &BigUint {
+        &self.modulus
+    }
+
+    // out = a * b * R ** -1 mod p for a, b < p (CIOS). scratch holds limbs + 2
+    fn mul_into(&self, a: &[u64], b: &[u64], out: &mut [u64], scratch: &mut [u64]) {
+        let (p, len) = (&self.limbs, self.limbs.len());
+        scratch.fill(0);
+        for &b_i in b {
+            let mut carry = 0u128;
+            for j in 0..len {
+                let sum = scratch[j] as u128 + a[j] as u128 * b_i as u128 + carry;
+                scratch[j] = sum as u64;
+                carry = sum >> 64;
+            }
+            let sum = scratch[len] as u128 + carry;
+            scratch[len] = sum as u64;
+            scratch[len + 1] = (sum >> 64) as u64;
+
+            // adding m * p clears the lowest limb, which is shifted out
+            let m = scratch[0].wrapping_mul(self.inverse);
+            let mut carry = (scratch[0] as u128 + m as u128 * p[0] as u128) >> 64;
+            for j in 1..len {
+                let sum = scratch[j] as u128 + m as u128 * p[j] as u128 + carry;
+                scratch[j - 1] = sum as u64;
+                carry = sum >> 64;
+            }
+            let sum = scratch[len] as u128 + carry;
+            scratch[len - 1] = sum as u64;
+            scratch[len] = scratch[len + 1] + (sum >> 64) as u64;
+        }
+
+        // the result is below 2p, one subtraction brings it below p
+        let at_least_p = scratch[len] != 0
+            || (0..len)
+                .rev()
+                .find(|&j| scratch[j] != p[j])
+                .is_none_or(|j| scratch[j] > p[j]);
+        if at_least_p {
+            let mut borrow = false;
+            for j in 0..len {
+                let (difference, under) = scratch[j].overflowing_sub(p[j]);
+                let (difference, under_again) = difference.overflowing_sub(borrow as u64);
+                out[j] = difference;
+                borrow = under || under_again;
+            }
+        } else {
+            out.copy_from_slice(&scratch[..len]);
+        }
+    }
+
+    fn mul(&self, a: &[u64], b: &[u64], scratch: &mut [u64]) -> Vec<u64> {
+        let mut out = vec![0; self.limbs.len()];
+        self.mul_into(a, b, &mut out, scratch);
+        out
+    }
+
+    fn scratch(&self) -> Vec<u64> {
+        vec![0; self.limbs.len() + 2]
+    }
+
+    // a * R mod p
+    fn to_montgomery(&self, a: &BigUint, scratch: &mut [u64]) -> Vec<u64> {
+        let a = if *a >= self.modulus {
+            to_limbs(&(a % &self.modulus), self.limbs.len())
+        } else {
+            to_limbs(a, self.limbs.len())
+        };
+        self.mul(&a, &self.r_squared, scratch)
+    }
+
+    fn out_of_montgomery(&self, a: &[u64], scratch: &mut [u64]) -> BigUint {
+        let mut one = vec![0; self.limbs.len()];
+        one[0] = 1;
+        from_limbs(&self.mul(a, &one, scratch))
+    }
+
+    // a * b mod p
+    pub fn mod_mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        let mut scratch = self.scratch();
+        let a = self.to_montgomery(a, &mut scratch);
+        let b = self.to_montgomery(b, &mut scratch);
+        let product = self.mul(&a, &b, &mut scratch);
+        self.out_of_montgomery(&product, &mut scratch)
+    }
+
+    // base ** exponent mod p, a window of bits of the exponent at a time
+    // (w-ary): w squarings, then one multiplication by a precomputed power
+    pub fn pow(&self, base: &BigUint, exponent: &BigUint) -> BigUint {
+        let mut scratch = self.scratch();
+        let bits = exponent.bits();
+        let width = window_width(bits);
+        let base = self.to_montgomery(base, &mut scratch);
+        let powers = self.powers(&base, width, &mut scratch);
+        let result = self.run_windows(bits, width, &mut scratch, |window| {
+            let digit = window_digit(exponent, window, width);
+            (digit != 0).then(|| self.power(&powers, digit))
+        });
+        self.out_of_montgomery(&result, &mut scratch)
+    }
+
+    // a ** x * b ** y mod p, both exponentiations sharing one run of squarings
+    // (Shamir's trick), as a verification equation needs them. two bits of
+    // each exponent are taken at a time against a table of a ** i * b ** j
+    pub fn pow_product(&self, a: &BigUint, x: &BigUint, b: &BigUint, y: &BigUint) -> BigUint {
+        const WIDTH: u64 = 2;
+        let mut scratch = self.scratch();
+        let a = self.to_montgomery(a, &mut scratch);
+        let b = self.to_montgomery(b, &mut scratch);
+        // entry i + j * 2 ** width is a ** i * b ** j
+        let a_powers = self.powers(&a, WIDTH, &mut scratch);
+        let len = self.limbs.len();
+        let mut table = vec![0; len << (2 * WIDTH)];
+        table[..len << WIDTH].copy_from_slice(&a_powers);
+        for entry in 1usize << WIDTH..1 << (2 * WIDTH) {
+            let (done, rest) = table.split_at_mut(entry * len);
+            let below = &done[(entry - (1 << WIDTH)) * len..];
+            self.mul_into(below, &b, &mut rest[..len], &mut scratch);
+        }
+        let bits = x.bits().max(y.bits());
+        let result = self.run_windows(bits, WIDTH, &mut scratch, |window| {
+            let digit = window_digit(x, window, WIDTH) | window_digit(y, window, WIDTH) << WIDTH;
+            (digit != 0).then(|| self.power(&table, digit))
+        });
+        self.out_of_montgomery(&result, &mut scratch)
+    }
+
+    // base ** 0 .. base ** (2 ** width - 1), in Montgomery form, one after
+    // the other in a single buffer
+    fn powers(&self, base: &[u64], width: u64, scratch: &mut [u64]) -> Vec<u64> {
+        let len = self.limbs.len();
+        let mut powers = vec![0; len << width];
+        powers[..len].copy_from_slice(&self.one);
+        for i in 1..1usize << width {
+            let (done, rest) = powers.split_at_mut(i * len);
+            self.mul_into(&done[(i - 1) * len..], base, &mut rest[..len], scratch);
+        }
+        powers
+    }
+
+    fn power<'t>(&self, powers: &'t [u64], index: usize) -> &'t [u64] {
+        let len = self.limbs.len();
+        &powers[index * len..(index + 1) * len]
+    }
+
+    // from the top window down: `width` squarings, then a multiplication by
+    // what the window picks, if anything
+    fn run_windows<'t>(
+        &self,
+        bits: u64,
+        width: u64,
+        scratch: &mut [u64],
+        pick: impl Fn(u64) -> Option<&'t [u64]>,
+    ) -> Vec<u64> {
+        let mut result = self.one.clone();
+        let mut next = vec![0; self.limbs.len()];
+        for window in (0..bits.div_ceil(width)).rev() {
+            for _ in 0..width {
+                self.mul_into(&result, &result, &mut next, scratch);
+                std::mem::swap(&mut result, &mut next);
+            }
+            if let Some(power) = pick(window) {
+                self.mul_into(&result, power, &mut next, scratch);
+                std::mem::swap(&mut result, &mut next);
+            }
+        }
+        result
+    }
+}
+
+// wider windows need fewer multiplications but a bigger table, which only
+// pays off for longer exponents
+fn window_width(bits: u64) -> u64 {
+    match bits {
+        0..24 => 1,
+        24..80 => 3,
+        80..240 => 4,
+        240..672 => 5,
+        _ => 6,
+    }
+}
+
+// bits [window * width, (window + 1) * width) of the exponent
+fn window_digit(exponent: &BigUint, window: u64, width: u64) -> usize {
+    (0..width)
+        .filter(|bit| exponent.bit(window * width + bit))
+        .fold(0, |digit, bit| digit | 1 << bit)
+}
+
+// precomputed powers of a base that is used over and over (g and h of a
+// group), so raising it to an exponent takes no squarings at all: for every
+// window i of the exponent the table holds base ** (d * 2 ** (width * i)),
+// and the result is the product of one entry per window
+#[derive(Debug, Clone)]
+pub struct FixedBase {
+    montgomery: Arc<Montgomery>,
+    base: BigUint,
+    width: u64,
+    // windows[i], the powers of window i one after the other, in Montgomery form
+    windows: Vec<Vec<u64>>,
+}
+
+impl FixedBase {
+    // a table for exponents of up to `bits` bits, longer ones are
+    // exponentiated the usual way
+    pub fn new(montgomery: Arc<Montgomery>, base: &BigUint, bits: u64) -> Self {
+        const WIDTH: u64 = 4;
+        let mut scratch = montgomery.scratch();
+        let mut power = montgomery.to_montgomery(base, &mut scratch);
+        let mut windows = Vec::new();
+        for _ in 0..bits.div_ceil(WIDTH) {
+            let powers = montgomery.powers(&power, WIDTH, &mut scratch);
+            // the next window's base: the last power times this window's base
+            let last = &powers[powers.len() - power.len()..];
+            power = montgomery.mul(last, &power, &mut scratch);
+            windows.push(powers);
+        }
+        FixedBase {
+            montgomery,
+            base: base.clone(),
+            width: WIDTH,
+            windows,
+        }
+    }
+
+    pub fn base(&self) -> &BigUint {
+        &self.base
+    }
+
+    // base ** exponent mod p
+    pub fn pow(&self, exponent: &BigUint) -> BigUint {
+        let montgomery = &self.montgomery;
+        if exponent.bits() > self.width * self.windows.len() as u64 {
+            return montgomery.pow(&self.base, exponent);
+        }
+        let len = montgomery.limbs.len();
+        let mut scratch = montgomery.scratch();
+        let mut result = montgomery.one.clone();
+        let mut next = vec![0; len];
+        for (window, powers) in self.windows.iter().enumerate() {
+            let digit = window_digit(exponent, window as u64, self.width);
+            if digit != 0 {
+                let power = montgomery.power(powers, digit);
+                montgomery.mul_into(&result, power, &mut next, &mut scratch);
+                std::mem::swap(&mut result, &mut next);
+            }
+        }
+        montgomery.out_of_montgomery(&result, &mut scratch)
+    }
+}
+
+#[cfg(all(test, feature = "prover"))]
+mod tests {
+    use super::*;
+    use crate::ZKP;
+
+    #[test]
+    fn test_agrees_with_modpow() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let context = Montgomery::new(&p).unwrap();
+        let x = ZKP::generate_random_number_below(&q);
+        let y = ZKP::generate_random_number_below(&q);
+        assert_eq!(context.pow(&g, &x), g.modpow(&x, &p));
+        assert_eq!(
+            context.pow_product(&g, &x, &h, &y),
+            (g.modpow(&x, &p) * h.modpow(&y, &p)) % &p
+        );
+        assert_eq!(context.mod_mul(&g, &h), (&g * &h) % &p);
+        // unreduced bases and zero exponents
+        assert_eq!(context.pow(&(&g + &p), &x), g.modpow(&x, &p));
+        assert_eq!(context.pow(&g, &BigUint::from(0u32)), BigUint::from(1u32));
+        assert_eq!(context.pow(&BigUint::from(0u32), &x), BigUint::from(0u32));
+
+        // moduli of a single limb, and one just past it
+        for modulus in [23u128, u64::MAX as u128, (1 << 64) + 1] {
+            let modulus = BigUint::from(modulus);
+            let context = Montgomery::new(&modulus).unwrap();
+            for base in [5u128, u64::MAX as u128 - 2, (1 << 64) + 7] {
+                let base = BigUint::from(base);
+                let exponent = BigUint::from(123_456_789u32);
+                assert_eq!(
+                    context.pow(&base, &exponent),
+                    base.modpow(&exponent, &modulus)
+                );
+            }
+        }
+        // every window width, against modpow
+        for bits in [1, 23, 24, 79, 80, 239, 240, 671, 672, 1024] {
+            let exponent = ZKP::generate_random_number_below(&(BigUint::from(1u32) << bits));
+            assert_eq!(context.pow(&h, &exponent), h.modpow(&exponent, &p));
+            assert_eq!(
+                context.pow_product(&g, &exponent, &h, &x),
+                (g.modpow(&exponent, &p) * h.modpow(&x, &p)) % &p
+            );
+        }
+
+        assert!(Arc::ptr_eq(
+            &Montgomery::shared(&p).unwrap(),
+            &Montgomery::shared(&p).unwrap()
+        ));
+        assert!(Montgomery::new(&BigUint::from(24u32)).is_none());
+        assert!(Montgomery::new(&BigUint::from(1u32)).is_none());
+    }
+
+    #[test]
+    fn test_fixed_base() {
+        let (g, _, p, q) = ZKP::get_constants();
+        let context = Arc::new(Montgomery::new(&p).unwrap());
+        let table = FixedBase::new(context, &g, q.bits());
+        for exponent in [
+            BigUint::from(0u32),
+            BigUint::from(1u32),
+            ZKP::generate_random_number_below(&q),
+            &q - 1u32,
+            // past the table
+            &q * &q,
+        ] {
+            assert_eq!(table.pow(&exponent), g.modpow(&exponent, &p));
+        }
+    }
+}
@@ -0,0 +1,450 @@
+use crate::groups;
+use crate::proof_metadata::{FreshnessWindow, ProofMetadata, ProofMetadataError};
+use crate::verification_cache::VerificationCache;
+use crate::verifier::KdfParams;
+use crate::{Proof, ZKP};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// challenge handed to an air-gapped prover; y1/y2 are the registered public
+// values the proof is checked against. the prover can go without them, the
+// verifier can't: a proof for a challenge without them is refused.
+// salt is set for users registered with a salted verifier, kdf when it was
+// derived with other than the default parameters. with max_lifetime_seconds
+// the proof has to be stamped (see proof_metadata) for purpose, or for ""
+// without one, and valid no longer than that
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChallengeFile {
+    pub user: String,
+    pub nonce: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y1: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y2: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kdf: Option<KdfParams>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_lifetime_seconds: Option<u64>,
+    // the group the user is registered in, see groups::NAMES; the default
+    // group without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+// Fiat–Shamir proof bound to the (user, nonce) of a challenge file,
+// all numbers are big-endian hex
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProofFile {
+    pub user: String,
+    pub nonce: String,
+    pub y1: String,
+    pub y2: String,
+    pub c: String,
+    pub s: String,
+    // absent for a proof without an expiry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ProofMetadata>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OfflineProofError {
+    InvalidHex(&'static str),
+    ChallengeMismatch(&'static str),
+    InvalidProof,
+    // the challenge asks for an expiry and the proof has none
+    Unstamped,
+    Metadata(ProofMetadataError),
+}
+
+impl Display for OfflineProofError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OfflineProofError::InvalidHex(field) => write!(f, "{} is not valid hex", field),
+            OfflineProofError::ChallengeMismatch(field) => {
+                write!(f, "{} does not match the challenge file", field)
+            }
+            OfflineProofError::InvalidProof => write!(f, "proof does not verify"),
+            OfflineProofError::Unstamped => write!(f, "proof has no expiry"),
+            OfflineProofError::Metadata(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for OfflineProofError {}
+
+fn to_hex(n: &BigUint) -> String {
+    hex::encode(n.to_bytes_be())
+}
+
+fn from_hex(field: &'static str, value: &str) -> Result<BigUint, OfflineProofError> {
+    hex::decode(value)
+        .map(|bytes| BigUint::from_bytes_be(&bytes))
+        .map_err(|_| OfflineProofError::InvalidHex(field))
+}
+
+impl ChallengeFile {
+    // bytes the proof is bound to: length-prefixed user and nonce
+    pub fn context(&self) -> Vec<u8> {
+        let mut context = Vec::new();
+        for field in [self.user.as_bytes(), self.nonce.as_bytes()] {
+            context.extend_from_slice(&(field.len() as u64).to_be_bytes());
+            context.extend_from_slice(field);
+        }
+        context
+    }
+
+    // the group the proof is made and verified in, None for an unknown name
+    pub fn zkp(&self) -> Option<ZKP> {
+        groups::by_name(self.group.as_deref().unwrap_or_default())
+    }
+
+    // the same challenge with the registered y1/y2 filled in
+    pub fn with_public_key(self, y1: &BigUint, y2: &BigUint) -> ChallengeFile {
+        ChallengeFile {
+            y1: Some(to_hex(y1)),
+            y2: Some(to_hex(y2)),
+            ..self
+        }
+    }
+
+    // the window a stamped proof is held to at now
+    pub fn window(&self, now: u64) -> FreshnessWindow<'_> {
+        FreshnessWindow {
+            purpose: self.purpose.as_deref().unwrap_or_default(),
+            now,
+            max_lifetime: self.max_lifetime_seconds.map(Duration::from_secs),
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// application is the deployment's application id (server --app-id), b"" for
+// none: a proof made under one id doesn't verify under another
+impl ProofFile {
+    // stamped to expire after the challenge's max_lifetime_seconds when it has one
+    pub fn prove(
+        zkp: &ZKP,
+        application: &[u8],
+        challenge: &ChallengeFile,
+        x: &BigUint,
+    ) -> ProofFile {
+        let metadata = challenge.max_lifetime_seconds.map(|seconds| {
+            ProofMetadata::new(
+                challenge.purpose.as_deref().unwrap_or_default(),
+                unix_now(),
+                Duration::from_secs(seconds),
+            )
+        });
+        ProofFile::prove_stamped(zkp, application, challenge, x, metadata)
+    }
+
+    pub fn prove_stamped(
+        zkp: &ZKP,
+        application: &[u8],
+        challenge: &ChallengeFile,
+        x: &BigUint,
+        metadata: Option<ProofMetadata>,
+    ) -> ProofFile {
+        let y1 = ZKP::exponentiate(&zkp.g, x, &zkp.p);
+        let y2 = ZKP::exponentiate(&zkp.h, x, &zkp.p);
+        let context = challenge.context();
+        let proof = match &metadata {
+            Some(metadata) => zkp.prove_non_interactive_stamped(application, x, &context, metadata),
+            None => zkp.prove_non_interactive_in(application, x, &context),
+        };
+
+        ProofFile {
+            user: challenge.user.clone(),
+            nonce: challenge.nonce.clone(),
+            y1: to_hex(&y1),
+            y2: to_hex(&y2),
+            c: to_hex(&proof.c),
+            s: to_hex(&proof.s),
+            metadata,
+        }
+    }
+
+    pub fn verify(
+        &self,
+        zkp: &ZKP,
+        application: &[u8],
+        challenge: &ChallengeFile,
+    ) -> Result<(), OfflineProofError> {
+        self.check(zkp, application, challenge, unix_now(), None)
+    }
+
+    // verify with the clock at now, in seconds since the Unix epoch
+    pub fn verify_at(
+        &self,
+        zkp: &ZKP,
+        application: &[u8],
+        challenge: &ChallengeFile,
+        now: u64,
+    ) -> Result<(), OfflineProofError> {
+        self.check(zkp, application, challenge, now, None)
+    }
+
+    // the same, through a cache of earlier verifications: replaying an archive
+    // of proof files checks each distinct proof once. the expiry of a stamped
+    // proof is checked every time
+    pub fn verify_cached(
+        &self,
+        zkp: &ZKP,
+        application: &[u8],
+        challenge: &ChallengeFile,
+        cache: &VerificationCache,
+    ) -> Result<(), OfflineProofError> {
+        self.check(zkp, application, challenge, unix_now(), Some(cache))
+    }
+
+    fn check(
+        &self,
+        zkp: &ZKP,
+        application: &[u8],
+        challenge: &ChallengeFile,
+        now: u64,
+        cache: Option<&VerificationCache>,
+    ) -> Result<(), OfflineProofError> {
+        if self.user != challenge.user {
+            return Err(OfflineProofError::ChallengeMismatch("user"));
+        }
+        if self.nonce != challenge.nonce {
+            return Err(OfflineProofError::ChallengeMismatch("nonce"));
+        }
+
+        // the proof carries its own y1/y2, but a proof is only worth anything
+        // against the registered ones: anybody can make a proof for a key of
+        // their own
+        let mut registered = Vec::with_capacity(2);
+        for (field, expected, actual) in [
+            ("y1", &challenge.y1, &self.y1),
+            ("y2", &challenge.y2, &self.y2),
+        ] {
+            let Some(expected) = expected else {
+                return Err(OfflineProofError::ChallengeMismatch(field));
+            };
+            let expected = from_hex(field, expected)?;
+            if from_hex(field, actual)? != expected {
+                return Err(OfflineProofError::ChallengeMismatch(field));
+            }
+            registered.push(expected);
+        }
+        let (y1, y2) = (&registered[0], &registered[1]);
+
+        let proof = Proof {
+            c: from_hex("c", &self.c)?,
+            s: from_hex("s", &self.s)?,
+        };
+        // a stamped proof is made over its stamped context, see proof_metadata
+        let context = match &self.metadata {
+            Some(metadata) => {
+                challenge
+                    .window(now)
+                    .check(metadata)
+                    .map_err(OfflineProofError::Metadata)?;
+                metadata.stamp(&challenge.context()).to_vec()
+            }
+            None if challenge.max_lifetime_seconds.is_some() => {
+                return Err(OfflineProofError::Unstamped);
+            }
+            None => challenge.context(),
+        };
+        let verified = match cache {
+            Some(cache) => {
+                cache.verify_non_interactive_in(zkp, application, y1, y2, &proof, &context)
+            }
+            None => zkp.verify_non_interactive_in(application, y1, y2, &proof, &context),
+        };
+        if verified {
+            Ok(())
+        } else {
+            Err(OfflineProofError::InvalidProof)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APP: &[u8] = b"audit.example.com";
+
+    fn toy_zkp() -> ZKP {
+        ZKP {
+            p: BigUint::from(23u32),
+            q: BigUint::from(11u32),
+            g: BigUint::from(4u32),
+            h: BigUint::from(9u32),
+        }
+    }
+
+    // the challenge with the y1/y2 registered for x
+    fn challenge_for(zkp: &ZKP, x: &BigUint, challenge: ChallengeFile) -> ChallengeFile {
+        challenge.with_public_key(
+            &ZKP::exponentiate(&zkp.g, x, &zkp.p),
+            &ZKP::exponentiate(&zkp.h, x, &zkp.p),
+        )
+    }
+
+    #[test]
+    fn test_proof_file_round_trip() {
+        let zkp = toy_zkp();
+        let challenge = ChallengeFile {
+            user: "alice".to_string(),
+            nonce: "0badc0de".to_string(),
+            y1: None,
+            y2: None,
+            salt: None,
+            kdf: None,
+            purpose: None,
+            max_lifetime_seconds: None,
+            group: None,
+        };
+        let x = BigUint::from(6u32);
+        let challenge = challenge_for(&zkp, &x, challenge);
+
+        let proof = ProofFile::prove(&zkp, APP, &challenge, &x);
+        let json = serde_json::to_string(&proof).unwrap();
+        let parsed: ProofFile = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.verify(&zkp, APP, &challenge), Ok(()));
+
+        let cache = VerificationCache::new(8);
+        for _ in 0..2 {
+            assert_eq!(parsed.verify_cached(&zkp, APP, &challenge, &cache), Ok(()));
+        }
+        assert_eq!(cache.hits(), 1);
+        // made for one deployment, it doesn't verify on another
+        assert_eq!(
+            parsed.verify(&zkp, b"", &challenge),
+            Err(OfflineProofError::InvalidProof)
+        );
+        assert_eq!(
+            parsed.verify_cached(&zkp, b"other", &challenge, &cache),
+            Err(OfflineProofError::InvalidProof)
+        );
+
+        // without the registered y1/y2 there is nothing to hold the proof to
+        let unregistered = ChallengeFile {
+            y2: None,
+            ..challenge.clone()
+        };
+        assert_eq!(
+            parsed.verify(&zkp, APP, &unregistered),
+            Err(OfflineProofError::ChallengeMismatch("y2"))
+        );
+        // and a proof for another key is refused even though it verifies
+        let other = ProofFile::prove(&zkp, APP, &unregistered, &BigUint::from(7u32));
+        assert_eq!(
+            other.verify(&zkp, APP, &challenge),
+            Err(OfflineProofError::ChallengeMismatch("y1"))
+        );
+
+        // the default group unless the challenge names another
+        assert_eq!(challenge.zkp().unwrap().p, groups::default_group().p);
+        let elsewhere = ChallengeFile {
+            group: Some("no-such-group".to_string()),
+            ..challenge
+        };
+        assert!(elsewhere.zkp().is_none());
+    }
+
+    #[test]
+    fn test_proof_file_rejects_other_challenge() {
+        let zkp = toy_zkp();
+        let challenge = ChallengeFile {
+            user: "alice".to_string(),
+            nonce: "0badc0de".to_string(),
+            y1: None,
+            y2: None,
+            salt: None,
+            kdf: None,
+            purpose: None,
+            max_lifetime_seconds: None,
+            group: None,
+        };
+        let x = BigUint::from(6u32);
+        let challenge = challenge_for(&zkp, &x, challenge);
+        let proof = ProofFile::prove(&zkp, APP, &challenge, &x);
+
+        let other_nonce = ChallengeFile {
+            nonce: "deadbeef".to_string(),
+            ..challenge.clone()
+        };
+        assert_eq!(
+            proof.verify(&zkp, APP, &other_nonce),
+            Err(OfflineProofError::ChallengeMismatch("nonce"))
+        );
+
+        // registered y1 belongs to a different secret
+        let other_key = ChallengeFile {
+            y1: Some(to_hex(&ZKP::exponentiate(
+                &zkp.g,
+                &BigUint::from(7u32),
+                &zkp.p,
+            ))),
+            ..challenge
+        };
+        assert_eq!(
+            proof.verify(&zkp, APP, &other_key),
+            Err(OfflineProofError::ChallengeMismatch("y1"))
+        );
+    }
+
+    #[test]
+    fn test_proof_file_expires() {
+        // a full-size q, in the toy group a moved expiry hashes to the same
+        // c one time in 11
+        let zkp = groups::default_group();
+        let challenge = ChallengeFile {
+            user: "alice".to_string(),
+            nonce: "0badc0de".to_string(),
+            y1: None,
+            y2: None,
+            salt: None,
+            kdf: None,
+            purpose: Some("quarterly-audit".to_string()),
+            max_lifetime_seconds: Some(3600),
+            group: None,
+        };
+        let x = BigUint::from(6u32);
+        let challenge = challenge_for(&zkp, &x, challenge);
+        let proof = ProofFile::prove(&zkp, APP, &challenge, &x);
+        assert_eq!(proof.verify(&zkp, APP, &challenge), Ok(()));
+        let metadata = proof.metadata.clone().unwrap();
+        assert_eq!(metadata.purpose, "quarterly-audit");
+        assert_eq!(
+            proof.verify_at(&zkp, APP, &challenge, metadata.expires_at),
+            Err(OfflineProofError::Metadata(ProofMetadataError::Expired))
+        );
+
+        // the challenge asks for an expiry, a proof without one is refused
+        let unstamped = ProofFile::prove_stamped(&zkp, APP, &challenge, &x, None);
+        assert_eq!(
+            unstamped.verify(&zkp, APP, &challenge),
+            Err(OfflineProofError::Unstamped)
+        );
+        // and one whose expiry was moved doesn't verify
+        let mut extended = proof.clone();
+        extended.metadata = Some(ProofMetadata {
+            expires_at: metadata.issued_at + 60,
+            ..metadata
+        });
+        assert_eq!(
+            extended.verify(&zkp, APP, &challenge),
+            Err(OfflineProofError::InvalidProof)
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::groups;
+use crate::self_test;
+use crate::service::AuthImpl;
+use std::fmt::Display;
+
+// the configuration checks every start runs, after the server is configured
+// and before the port is bound: the settings and durations, the groups, and
+// that the stores and audit sinks can be reached and written. every check
+// runs, so one start reports everything that is wrong rather than the first
+// thing, and a server that can't serve a login never starts. what is read
+// from files (TLS, keys, snapshots, settings) is refused where it is loaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    // what is wrong, e.g. "session TTL" or "audit sink file:/var/log/a"
+    pub setting: String,
+    pub problem: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.setting, self.problem)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// the empty name no registration can have, so checking the lockout store
+// leaves no record behind
+const PROBE_USER: &str = "";
+
+pub fn check(auth: &AuthImpl) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    let mut fail = |setting: &str, problem: String| {
+        errors.push(ConfigError {
+            setting: setting.to_string(),
+            problem,
+        })
+    };
+
+    let settings = auth.settings.current();
+    if let Err(problem) = settings.check() {
+        fail("settings", problem);
+    }
+    // a duration the clock can't be moved on by panics the call that adds it
+    let now = auth.clock.now();
+    let mut durations = vec![
+        ("session TTL", settings.session_ttl),
+        ("recovery cooldown", auth.recovery_cooldown.0),
+        ("deleted user retention", auth.deletion_retention.0),
+    ];
+    if let Some(lockout) = &auth.lockout {
+        durations.push(("lockout duration", lockout.duration));
+        if lockout.duration.is_zero() {
+            fail("lockout duration", "must be at least 1 second".to_string());
+        }
+        if lockout.max_failures == 0 {
+            fail(
+                "lockout threshold",
+                "must be at least 1 failure".to_string(),
+            );
+        }
+    }
+    for (setting, duration) in durations {
+        if now.checked_add(duration).is_none() {
+            fail(
+                setting,
+                format!("{}s is past the end of the clock", duration.as_secs()),
+            );
+        }
+    }
+    if auth.max_answers_per_challenge == Some(0) {
+        fail(
+            "answers per challenge",
+            "must allow at least 1 answer".to_string(),
+        );
+    }
+    if let Some(admission) = &auth.admission {
+        if auth.verifier_pool.is_none() {
+            fail(
+                "verification latency target",
+                "needs verification workers".to_string(),
+            );
+        }
+        if admission.target.is_zero() {
+            fail(
+                "verification latency target",
+                "must be at least 1 millisecond".to_string(),
+            );
+        }
+    }
+    let usernames = &auth.usernames;
+    if usernames.min_len == 0 || usernames.min_len > usernames.max_len {
+        fail(
+            "user name length",
+            format!(
+                "{} to {} characters admits no name",
+                usernames.min_len, usernames.max_len
+            ),
+        );
+    }
+
+    for name in groups::NAMES {
+        match auth.groups.get(name) {
+            Some(group) => {
+                if let Err(e) = self_test::check_group(&group) {
+                    fail(&format!("group {}", name), e.reason.to_string());
+                }
+            }
+            None => fail(&format!("group {}", name), "not loaded".to_string()),
+        }
+    }
+
+    if let Err(e) = auth.lockouts.update(PROBE_USER, &mut |_| {}) {
+        fail("lockout store", format!("can't be written: {}", e));
+    }
+    for sink in &auth.audit_sinks {
+        if let Err(e) = sink.check() {
+            fail(&format!("audit sink {}", sink.name()), e.to_string());
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admission::Admission;
+    use crate::audit::{self, Rotation};
+    use crate::lockout::{FileLockouts, LockoutPolicy};
+    use crate::state::Retention;
+    use std::time::Duration;
+
+    #[test]
+    fn test_every_problem_is_reported() {
+        assert_eq!(check(&AuthImpl::default()), Vec::new());
+
+        // nothing can be written in a directory that doesn't exist
+        let missing = std::env::temp_dir().join(format!("missing-{}", std::process::id()));
+        let auth = AuthImpl {
+            lockout: Some(LockoutPolicy {
+                max_failures: 0,
+                duration: Duration::from_secs(60),
+            }),
+            lockouts: Box::new(FileLockouts::new(missing.join("lockouts.json"))),
+            deletion_retention: Retention(Duration::MAX),
+            admission: Some(Admission::new(Duration::from_millis(100))),
+            audit_sinks: vec![audit::open(
+                &format!("file:{}", missing.join("audit.jsonl").display()),
+                Rotation::default(),
+            )
+            .unwrap()],
+            ..AuthImpl::default()
+        };
+        let settings: Vec<_> = check(&auth).into_iter().map(|e| e.setting).collect();
+        assert_eq!(
+            settings,
+            [
+                "lockout threshold".to_string(),
+                "deleted user retention".to_string(),
+                "verification latency target".to_string(),
+                "lockout store".to_string(),
+                format!("audit sink file:{}", missing.join("audit.jsonl").display()),
+            ]
+        );
+    }
+}
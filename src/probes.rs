@@ -0,0 +1,211 @@
+use crate::attestation::Attestation;
+use crate::service::AuthImpl;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+// plain HTTP probes for load balancers and orchestrators that can't speak
+// grpc.health.v1, e.g. Kubernetes httpGet probes behind some ingresses:
+// GET /healthz fails once the server's state is unusable, so it is
+// restarted; GET /readyz also fails while the server drains, so traffic
+// moves elsewhere but the server is left running. GET /metrics is the realm's
+// usage, see metrics. any other path is 404
+pub fn probe(auth: &AuthImpl, path: &str) -> (StatusCode, &'static str) {
+    let available =
+        auth.users.is_available() && auth.challenges.is_available() && auth.sessions.is_available();
+    match path {
+        "/healthz" | "/readyz" if !available => {
+            (StatusCode::SERVICE_UNAVAILABLE, "store unavailable\n")
+        }
+        "/healthz" => (StatusCode::OK, "ok\n"),
+        "/readyz" if auth.drain.is_draining() => (StatusCode::SERVICE_UNAVAILABLE, "draining\n"),
+        "/readyz" => (StatusCode::OK, "ready\n"),
+        _ => (StatusCode::NOT_FOUND, "not found\n"),
+    }
+}
+
+// the server realm's users, live sessions and logins this minute, the
+// refusals of every realm's quotas, the health of a lockout store that can be
+// down, and the crypto configuration (see attestation), in the Prometheus
+// text format
+pub fn metrics(auth: &AuthImpl) -> String {
+    let label = |realm: &str| realm.replace('\\', "\\\\").replace('"', "\\\"");
+    let realm = label(&auth.realm);
+    let now = auth.clock.now();
+    let mut out = String::new();
+    let gauges = [
+        ("zkp_realm_users", auth.users.len()),
+        (
+            "zkp_realm_sessions",
+            auth.sessions.count_in_realm(&auth.realm, now),
+        ),
+        (
+            "zkp_realm_logins_this_minute",
+            auth.login_rate
+                .count(&auth.realm, crate::service::unix_seconds(now)) as usize,
+        ),
+    ];
+    for (name, value) in gauges {
+        out += &format!(
+            "# TYPE {} gauge\n{}{{realm=\"{}\"}} {}\n",
+            name, name, realm, value
+        );
+    }
+    out += "# TYPE zkp_quota_refusals_total counter\n";
+    for (realm, kind, count) in auth.quota_metrics.refusals() {
+        out += &format!(
+            "zkp_quota_refusals_total{{realm=\"{}\",quota=\"{}\"}} {}\n",
+            label(&realm),
+            kind.as_str(),
+            count
+        );
+    }
+    if let Some(health) = auth.lockouts.health() {
+        let series = [
+            ("zkp_store_up", "gauge", health.up as u64),
+            ("zkp_store_errors_total", "counter", health.errors),
+            ("zkp_store_shed_total", "counter", health.shed),
+            ("zkp_store_outages_total", "counter", health.outages),
+        ];
+        for (name, kind, value) in series {
+            out += &format!(
+                "# TYPE {} {}\n{}{{store=\"lockouts\"}} {}\n",
+                name, kind, name, value
+            );
+        }
+    }
+    if let Some(pool) = &auth.verifier_pool {
+        let now = std::time::Instant::now();
+        out += &pool.metrics(now);
+        if let Some(admission) = &auth.admission {
+            out += &admission.metrics(pool, now);
+        }
+    }
+    if let Some(pool) = &auth.challenge_pool {
+        out += &pool.metrics();
+    }
+    out += &Attestation::of(auth).metrics();
+    out
+}
+
+// answers the probes on listener, HTTP/1.1 only, until the runtime stops
+pub async fn serve(listener: TcpListener, auth: Arc<AuthImpl>) {
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        let auth = auth.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request: Request<hyper::body::Incoming>| {
+                let auth = auth.clone();
+                async move {
+                    let (status, body) = match request.uri().path() {
+                        "/metrics" => (StatusCode::OK, Bytes::from(metrics(&auth))),
+                        #[cfg(all(feature = "profiling", unix))]
+                        path if path.starts_with("/debug/pprof/") => {
+                            let authorization = request
+                                .headers()
+                                .get(hyper::header::AUTHORIZATION)
+                                .and_then(|value| value.to_str().ok());
+                            crate::profiling::serve(
+                                &auth,
+                                path,
+                                request.uri().query(),
+                                authorization,
+                            )
+                            .await
+                        }
+                        path => {
+                            let (status, body) = probe(&auth, path);
+                            (status, Bytes::from_static(body.as_bytes()))
+                        }
+                    };
+                    let mut response = Response::new(Full::new(body));
+                    *response.status_mut() = status;
+                    Ok::<_, Infallible>(response)
+                }
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grants::Grant;
+    use crate::state::{ClientInfo, Session};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_readiness_follows_the_drain() {
+        let auth = AuthImpl::default();
+        assert_eq!(probe(&auth, "/healthz").0, StatusCode::OK);
+        assert_eq!(probe(&auth, "/readyz").0, StatusCode::OK);
+        assert_eq!(probe(&auth, "/").0, StatusCode::NOT_FOUND);
+        auth.drain.set(true);
+        assert_eq!(probe(&auth, "/healthz").0, StatusCode::OK);
+        assert_eq!(
+            probe(&auth, "/readyz"),
+            (StatusCode::SERVICE_UNAVAILABLE, "draining\n")
+        );
+    }
+
+    #[test]
+    fn test_metrics_of_the_realm() {
+        let auth = AuthImpl {
+            realm: "acme \"eu\"".to_string(),
+            ..AuthImpl::default()
+        };
+        auth.quota_metrics
+            .refused(&auth.realm, crate::quota::QuotaKind::Users);
+        auth.login_rate.admit(
+            &auth.realm,
+            None,
+            crate::service::unix_seconds(auth.clock.now()),
+        );
+        let metrics = metrics(&auth);
+        assert!(metrics.contains("zkp_realm_users{realm=\"acme \\\"eu\\\"\"} 0\n"));
+        assert!(metrics.contains("zkp_realm_logins_this_minute{realm=\"acme \\\"eu\\\"\"} 1\n"));
+        assert!(metrics
+            .contains("zkp_quota_refusals_total{realm=\"acme \\\"eu\\\"\",quota=\"users\"} 1\n"));
+        // only a store that can be down reports its health
+        assert!(!metrics.contains("zkp_store_up"));
+    }
+
+    #[test]
+    fn test_poisoned_store_fails_both_probes() {
+        let auth = Arc::new(AuthImpl::default());
+        auth.sessions.insert(
+            "session".to_string(),
+            Session {
+                user_name: "alice".to_string(),
+                created_at: SystemTime::now(),
+                expires_at: SystemTime::now() + Duration::from_secs(60),
+                key: None,
+                realm: String::new(),
+                client: ClientInfo::default(),
+                grant: Grant::default(),
+                tickets: 0,
+            },
+        );
+        // a handler that panics while holding the sessions lock
+        let poisoner = auth.clone();
+        let _ = std::thread::spawn(move || {
+            poisoner
+                .sessions
+                .export(SystemTime::now(), |_, _| panic!("handler bug"));
+        })
+        .join();
+        assert_eq!(probe(&auth, "/healthz").0, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(probe(&auth, "/readyz").0, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
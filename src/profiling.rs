@@ -0,0 +1,180 @@
+use crate::service::AuthImpl;
+use crate::ZKP;
+use hyper::body::Bytes;
+use hyper::StatusCode;
+use pprof::protos::Message;
+use std::ffi::CString;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+// CPU and heap profiles on the probe address, for finding where the modexps
+// and allocations go on a server under real traffic. both are in formats
+// `go tool pprof` reads: GET /debug/pprof/profile?seconds=N samples the
+// stacks of every thread N seconds (default 30) and answers with a pprof
+// protobuf, GET /debug/pprof/heap dumps jemalloc's sampled heap profile.
+// every request needs `Authorization: Bearer <admin token>`; without admin
+// tokens nobody gets a profile. heap sampling costs a little on every
+// allocation, so jemalloc only does it when the server was started with
+// MALLOC_CONF=prof:true. the heap profile covers what jemalloc allocates, the
+// server makes it the global allocator with this feature
+
+const DEFAULT_SECONDS: u64 = 30;
+const MAX_SECONDS: u64 = 300;
+// samples per second
+const FREQUENCY: i32 = 99;
+
+// answers a request for a path under /debug/pprof/
+pub async fn serve(
+    auth: &AuthImpl,
+    path: &str,
+    query: Option<&str>,
+    authorization: Option<&str>,
+) -> (StatusCode, Bytes) {
+    let admin = authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| auth.is_admin_token(token));
+    if !admin {
+        return text(StatusCode::UNAUTHORIZED, "an admin token is required");
+    }
+    match path {
+        "/debug/pprof/profile" => match seconds(query) {
+            Ok(seconds) => cpu(Duration::from_secs(seconds)).await,
+            Err(e) => text(StatusCode::BAD_REQUEST, &e),
+        },
+        "/debug/pprof/heap" => match tokio::task::spawn_blocking(heap).await {
+            Ok(response) => response,
+            Err(_) => text(StatusCode::INTERNAL_SERVER_ERROR, "heap dump failed"),
+        },
+        _ => text(StatusCode::NOT_FOUND, "not found"),
+    }
+}
+
+fn text(status: StatusCode, message: &str) -> (StatusCode, Bytes) {
+    (status, Bytes::from(format!("{}\n", message)))
+}
+
+// ?seconds=N
+fn seconds(query: Option<&str>) -> Result<u64, String> {
+    let Some(value) = query
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("seconds="))
+    else {
+        return Ok(DEFAULT_SECONDS);
+    };
+    match value.parse() {
+        Ok(seconds @ 1..=MAX_SECONDS) => Ok(seconds),
+        _ => Err(format!(
+            "seconds must be 1 to {}, not {:?}",
+            MAX_SECONDS, value
+        )),
+    }
+}
+
+// one profile runs at a time, the sampling is process-wide
+async fn cpu(duration: Duration) -> (StatusCode, Bytes) {
+    let profile = tokio::task::spawn_blocking(move || {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(FREQUENCY)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+        std::thread::sleep(duration);
+        let profile = guard
+            .report()
+            .build()
+            .and_then(|report| report.pprof())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        Ok(profile.encode_to_vec())
+    })
+    .await;
+    match profile {
+        Ok(Ok(profile)) => (StatusCode::OK, Bytes::from(profile)),
+        Ok(Err((status, e))) => text(status, &format!("CPU profile failed: {}", e)),
+        Err(_) => text(StatusCode::INTERNAL_SERVER_ERROR, "CPU profile failed"),
+    }
+}
+
+// jemalloc writes its dumps to a file by name: a directory only this process
+// can write, made on the first dump, so nobody can put a link in its place
+fn dump_path() -> Result<&'static [u8], String> {
+    static PATH: OnceLock<Result<&'static [u8], String>> = OnceLock::new();
+    PATH.get_or_init(|| {
+        let dir = std::env::temp_dir().join(format!(
+            "zkp-chaum-pedersen-{}-{}",
+            std::process::id(),
+            ZKP::generate_token(8)
+        ));
+        std::fs::DirBuilder::new()
+            .mode(0o700)
+            .create(&dir)
+            .map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let path = CString::new(dir.join("heap").into_os_string().into_encoded_bytes())
+            .map_err(|e| e.to_string())?;
+        Ok(&*Box::leak(path.into_bytes_with_nul().into_boxed_slice()))
+    })
+    .clone()
+}
+
+fn heap() -> (StatusCode, Bytes) {
+    // dumps go to the same file one at a time
+    static DUMPING: Mutex<()> = Mutex::new(());
+
+    if !tikv_jemalloc_ctl::profiling::prof::read().unwrap_or(false) {
+        return text(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "heap profiling is off, start the server with MALLOC_CONF=prof:true",
+        );
+    }
+    let path = match dump_path() {
+        Ok(path) => path,
+        Err(e) => return text(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    };
+    let file = PathBuf::from(String::from_utf8_lossy(&path[..path.len() - 1]).into_owned());
+    let _dumping = DUMPING.lock().unwrap();
+    let dump = tikv_jemalloc_ctl::raw::write_str(b"prof.dump\0", path)
+        .map_err(|e| e.to_string())
+        .and_then(|()| std::fs::read(&file).map_err(|e| e.to_string()));
+    let _ = std::fs::remove_file(&file);
+    match dump {
+        Ok(dump) => (StatusCode::OK, Bytes::from(dump)),
+        Err(e) => text(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("heap dump failed: {}", e),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_profiles_are_for_admins() {
+        let auth = AuthImpl {
+            admin_tokens: vec!["admin-token".to_string()],
+            ..AuthImpl::default()
+        };
+        let profile = "/debug/pprof/profile";
+        for authorization in [None, Some("Bearer wrong"), Some("admin-token")] {
+            let (status, _) = serve(&auth, profile, None, authorization).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+
+        let admin = Some("Bearer admin-token");
+        let (status, _) = serve(&auth, profile, Some("seconds=0"), admin).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = serve(&auth, "/debug/pprof/other", None, admin).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        // the tests don't run with MALLOC_CONF=prof:true
+        let (status, _) = serve(&auth, "/debug/pprof/heap", None, admin).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        let (status, body) = serve(&auth, profile, Some("seconds=1"), admin).await;
+        assert_eq!(status, StatusCode::OK);
+        let profile = pprof::protos::Profile::decode(body).unwrap();
+        assert!(!profile.sample_type.is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+use crate::transcript::Transcript;
+#[cfg(feature = "prover")]
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::time::Duration;
+
+// purpose, issue time and expiry of a non-interactive proof, signed with it:
+// the proof is made over stamp(context) instead of the context, so none of
+// them can be changed without breaking it, and a verifier that doesn't know
+// them can't verify the proof at all. FreshnessWindow holds them to the
+// verifier's purpose, its clock and the longest lifetime it takes, so a proof
+// made offline can't be hoarded and replayed months later
+
+// a proof issued up to this far ahead of the verifier's clock is still taken
+pub const CLOCK_SKEW: Duration = Duration::from_secs(300);
+
+// times in seconds since the Unix epoch
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "prover", derive(Serialize, Deserialize))]
+pub struct ProofMetadata {
+    pub purpose: String,
+    pub issued_at: u64,
+    pub expires_at: u64,
+}
+
+impl ProofMetadata {
+    pub fn new(purpose: &str, issued_at: u64, lifetime: Duration) -> Self {
+        ProofMetadata {
+            purpose: purpose.to_string(),
+            issued_at,
+            expires_at: issued_at.saturating_add(lifetime.as_secs()),
+        }
+    }
+
+    // H(context, purpose, issued_at, expires_at), what the proof is made over
+    pub fn stamp(&self, context: &[u8]) -> [u8; 32] {
+        let mut transcript = Transcript::new(b"zkp-chaum-pedersen/proof-metadata");
+        transcript.append(b"context", context);
+        transcript.append(b"purpose", self.purpose.as_bytes());
+        transcript.append(b"issued_at", &self.issued_at.to_be_bytes());
+        transcript.append(b"expires_at", &self.expires_at.to_be_bytes());
+        transcript.finalize()
+    }
+}
+
+// what a verifier takes: proofs for its purpose, valid at now (seconds since
+// the Unix epoch) and, when max_lifetime is set, issued to last no longer
+#[derive(Debug, Clone, Copy)]
+pub struct FreshnessWindow<'a> {
+    pub purpose: &'a str,
+    pub now: u64,
+    pub max_lifetime: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofMetadataError {
+    WrongPurpose,
+    NotYetValid,
+    Expired,
+    LifetimeTooLong,
+    InvalidProof,
+}
+
+impl Display for ProofMetadataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProofMetadataError::WrongPurpose => write!(f, "proof was made for another purpose"),
+            ProofMetadataError::NotYetValid => write!(f, "proof is issued in the future"),
+            ProofMetadataError::Expired => write!(f, "proof has expired"),
+            ProofMetadataError::LifetimeTooLong => {
+                write!(f, "proof is valid for longer than the verifier allows")
+            }
+            ProofMetadataError::InvalidProof => write!(f, "proof does not verify"),
+        }
+    }
+}
+
+impl std::error::Error for ProofMetadataError {}
+
+impl FreshnessWindow<'_> {
+    // the metadata only; the proof over it is checked by
+    // ZKP::verify_non_interactive_stamped
+    pub fn check(&self, metadata: &ProofMetadata) -> Result<(), ProofMetadataError> {
+        if metadata.purpose != self.purpose {
+            return Err(ProofMetadataError::WrongPurpose);
+        }
+        if metadata.issued_at > self.now.saturating_add(CLOCK_SKEW.as_secs()) {
+            return Err(ProofMetadataError::NotYetValid);
+        }
+        if self.now >= metadata.expires_at {
+            return Err(ProofMetadataError::Expired);
+        }
+        let lifetime = metadata.expires_at.saturating_sub(metadata.issued_at);
+        if self
+            .max_lifetime
+            .is_some_and(|max_lifetime| lifetime > max_lifetime.as_secs())
+        {
+            return Err(ProofMetadataError::LifetimeTooLong);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "prover"))]
+mod tests {
+    use super::*;
+    use crate::groups;
+    use num_bigint::BigUint;
+
+    #[test]
+    fn test_stamped_proof_is_held_to_its_window() {
+        let zkp = groups::default_group();
+        let x = BigUint::from(6u32);
+        let y1 = crate::ZKP::exponentiate(&zkp.g, &x, &zkp.p);
+        let y2 = crate::ZKP::exponentiate(&zkp.h, &x, &zkp.p);
+        let metadata = ProofMetadata::new("audit", 1000, Duration::from_secs(60));
+        let proof = zkp.prove_non_interactive_stamped(b"", &x, b"nonce", &metadata);
+        let window = FreshnessWindow {
+            purpose: "audit",
+            now: 1030,
+            max_lifetime: Some(Duration::from_secs(3600)),
+        };
+        let verify = |metadata: &ProofMetadata, window: &FreshnessWindow| {
+            zkp.verify_non_interactive_stamped(b"", &y1, &y2, &proof, (b"nonce", metadata), window)
+        };
+        assert_eq!(verify(&metadata, &window), Ok(()));
+
+        // the window is the verifier's
+        let later = FreshnessWindow {
+            now: 1060,
+            ..window
+        };
+        assert_eq!(verify(&metadata, &later), Err(ProofMetadataError::Expired));
+        let early = FreshnessWindow { now: 600, ..window };
+        assert_eq!(
+            verify(&metadata, &early),
+            Err(ProofMetadataError::NotYetValid)
+        );
+        let elsewhere = FreshnessWindow {
+            purpose: "login",
+            ..window
+        };
+        assert_eq!(
+            verify(&metadata, &elsewhere),
+            Err(ProofMetadataError::WrongPurpose)
+        );
+        let strict = FreshnessWindow {
+            max_lifetime: Some(Duration::from_secs(30)),
+            ..window
+        };
+        assert_eq!(
+            verify(&metadata, &strict),
+            Err(ProofMetadataError::LifetimeTooLong)
+        );
+
+        // and the metadata is the prover's, extending it breaks the proof
+        let extended = ProofMetadata {
+            expires_at: 2000,
+            ..metadata.clone()
+        };
+        assert_eq!(
+            verify(&extended, &window),
+            Err(ProofMetadataError::InvalidProof)
+        );
+        // nor does it verify without its metadata
+        assert!(!zkp.verify_non_interactive(&y1, &y2, &proof, b"nonce"));
+    }
+}
@@ -0,0 +1,201 @@
+// the wire types of proto/zkp_auth.proto, for services that talk to or stand in
+// for this server: build and parse the messages, or run the client and server
+// stubs, without a copy of the .proto file or the generated code
+pub use crate::zkp_auth::auth_client::AuthClient;
+pub use crate::zkp_auth::auth_server::{Auth, AuthServer};
+pub use crate::zkp_auth::{
+    AuditEvent, AuthenticationAnswerRequest, AuthenticationAnswerResponse,
+    AuthenticationChallengeRequest, AuthenticationChallengeResponse, BlindedIdentity,
+    ClientMetadata, DecoyHit, DeleteUserRequest, DeleteUserResponse, Device, DeviceInfo,
+    EvaluatedTicket, ExportStateRequest, ExportStateResponse, GetParametersRequest,
+    GetParametersResponse, GetSessionInfoRequest, GetSessionInfoResponse, ImportStateRequest,
+    ImportStateResponse, IssueTicketsRequest, IssueTicketsResponse, KdfAlgorithm, KdfParams,
+    ListAuditEventsRequest, ListAuditEventsResponse, ListDecoyHitsRequest, ListDecoyHitsResponse,
+    ListDevicesRequest, ListDevicesResponse, LogoutRequest, LogoutResponse, MarkDecoyRequest,
+    MarkDecoyResponse, MigrateGroupRequest, MigrateGroupResponse, ProofFlavor,
+    RecoverAccountRequest, RecoverAccountResponse, RecoveryKey, RedeemTicketRequest,
+    RedeemTicketResponse, RefreshSessionRequest, RefreshSessionResponse, RegisterRequest,
+    RegisterResponse, ReloadSettingsRequest, ReloadSettingsResponse, RevokeDeviceRequest,
+    RevokeDeviceResponse, SetDrainRequest, SetDrainResponse, ValidateSessionRequest,
+    ValidateSessionResponse, VerifyProofRequest, VerifyProofResponse,
+};
+
+// Debug for the messages build.rs leaves without one: the fields that carry a
+// credential or a proof print as their length and fingerprint (see
+// secret::Redacted), the rest as they are, so a request in the logs says
+// which session it was without handing the session to whoever reads them.
+// reveal() prints everything, for tests
+macro_rules! redacted_debug {
+    ($($message:ident { $($clear:ident),* ; $($secret:ident),* })*) => {$(
+        impl std::fmt::Debug for $message {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct(stringify!($message))
+                    $(.field(stringify!($clear), &self.$clear))*
+                    $(.field(stringify!($secret), &crate::secret::Redacted(&self.$secret)))*
+                    .finish()
+            }
+        }
+
+        impl crate::secret::Reveal for $message {}
+
+        impl std::fmt::Debug for crate::secret::Revealed<'_, $message> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct(stringify!($message))
+                    $(.field(stringify!($clear), &self.0.$clear))*
+                    $(.field(stringify!($secret), &self.0.$secret))*
+                    .finish()
+            }
+        }
+    )*};
+}
+
+redacted_debug! {
+    AuthenticationAnswerRequest { auth_id, server_c, r1, r2, channel_binding, device; s, c, second_factor }
+    AuthenticationAnswerResponse { key_share, second_factor; session_id, server_s, key_confirmation }
+    ValidateSessionRequest { ; session_id, mac }
+    RefreshSessionRequest { ; session_id }
+    RefreshSessionResponse { expires_at; session_id }
+    LogoutRequest { ; session_id }
+    GetSessionInfoRequest { ; session_id, mac }
+    ExportStateResponse { ; bundle }
+    ImportStateRequest { ; bundle }
+    DecoyHit { user, at, client, request_id; session_id }
+    RecoverAccountRequest { user, y1, y2, salt, group, kdf, issued_at; c, s }
+    ListDevicesRequest { user; session_id, mac }
+    RevokeDeviceRequest { user, device_id; session_id, mac }
+    MigrateGroupRequest { group, y1, y2; session_id, mac, c, s }
+    IssueTicketsRequest { ; session_id, mac, blinded }
+    EvaluatedTicket { ; evaluated, c, s }
+    RedeemTicketRequest { ; nonce, w }
+}
+
+// zkp_auth.v2, served next to v1 (see service_v2)
+pub mod v2 {
+    pub use crate::zkp_auth::v2::auth_client::AuthClient;
+    pub use crate::zkp_auth::v2::auth_server::{Auth, AuthServer};
+    pub use crate::zkp_auth::v2::{
+        AuthenticationAnswerRequest, AuthenticationAnswerResponse, AuthenticationChallengeRequest,
+        AuthenticationChallengeResponse, BlindedIdentity, ClientMetadata, DeviceInfo, ErrorDetail,
+        ErrorReason, Flavor, GetSessionInfoRequest, GetSessionInfoResponse, KdfAlgorithm,
+        KdfParams, LogoutRequest, LogoutResponse, RefreshSessionRequest, RefreshSessionResponse,
+        RegisterRequest, RegisterResponse, Session, ValidateSessionRequest,
+        ValidateSessionResponse,
+    };
+
+    redacted_debug! {
+        AuthenticationAnswerRequest { auth_id, server_c, r1, r2, channel_binding, device; s, c, second_factor }
+        AuthenticationAnswerResponse { session, key_share, second_factor; server_s, key_confirmation }
+        Session { user, expires_at, has_key, scopes, claims; session_id }
+        ValidateSessionRequest { ; session_id, mac }
+        RefreshSessionRequest { ; session_id }
+        LogoutRequest { ; session_id }
+        GetSessionInfoRequest { ; session_id, mac }
+    }
+}
+
+impl From<crate::verifier::KdfParams> for KdfParams {
+    fn from(kdf: crate::verifier::KdfParams) -> Self {
+        match kdf {
+            crate::verifier::KdfParams::Pbkdf2 { iterations } => KdfParams {
+                algorithm: KdfAlgorithm::Pbkdf2HmacSha256.into(),
+                iterations,
+                ..Default::default()
+            },
+            crate::verifier::KdfParams::Argon2id {
+                memory_kib,
+                passes,
+                parallelism,
+            } => KdfParams {
+                algorithm: KdfAlgorithm::Argon2id.into(),
+                memory_kib,
+                passes,
+                parallelism,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+// unset parameters are the default (PBKDF2, 100000 iterations)
+pub fn kdf_from_wire(kdf: Option<&KdfParams>) -> Result<crate::verifier::KdfParams, String> {
+    let Some(kdf) = kdf else {
+        return Ok(crate::verifier::KdfParams::default());
+    };
+    match KdfAlgorithm::try_from(kdf.algorithm) {
+        Ok(KdfAlgorithm::Unspecified) => Ok(crate::verifier::KdfParams::default()),
+        Ok(KdfAlgorithm::Pbkdf2HmacSha256) => Ok(crate::verifier::KdfParams::Pbkdf2 {
+            iterations: kdf.iterations,
+        }),
+        Ok(KdfAlgorithm::Argon2id) => Ok(crate::verifier::KdfParams::Argon2id {
+            memory_kib: kdf.memory_kib,
+            passes: kdf.passes,
+            parallelism: kdf.parallelism,
+        }),
+        Err(_) => Err(format!("KDF algorithm {} is not supported", kdf.algorithm)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    #[test]
+    fn test_messages_round_trip() {
+        let request = AuthenticationChallengeRequest {
+            user: "alice".to_string(),
+            r1: vec![1, 2, 3],
+            r2: vec![4, 5, 6],
+            ..Default::default()
+        };
+        let bytes = request.encode_to_vec();
+        assert_eq!(
+            AuthenticationChallengeRequest::decode(bytes.as_slice()).unwrap(),
+            request
+        );
+    }
+
+    #[test]
+    fn test_credentials_are_redacted_in_debug() {
+        use crate::secret::Reveal;
+        let request = ValidateSessionRequest {
+            session_id: "f00dfeedf00dfeed".to_string(),
+            ..Default::default()
+        };
+        let printed = format!("{:?}", request);
+        assert!(printed.starts_with("ValidateSessionRequest { session_id: <16 chars sha256:"));
+        assert!(!printed.contains("f00dfeed"));
+        assert!(format!("{:?}", request.reveal()).contains("f00dfeedf00dfeed"));
+
+        let answer = v2::AuthenticationAnswerResponse {
+            session: Some(v2::Session {
+                session_id: "f00dfeedf00dfeed".to_string(),
+                user: "alice".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let printed = format!("{:?}", answer);
+        assert!(printed.contains("user: \"alice\""));
+        assert!(!printed.contains("f00dfeed"));
+    }
+
+    #[test]
+    fn test_kdf_params_on_the_wire() {
+        use crate::verifier;
+        for kdf in [
+            verifier::KdfParams::default(),
+            verifier::KdfParams::argon2id(),
+        ] {
+            let wire = KdfParams::from(kdf);
+            assert_eq!(kdf_from_wire(Some(&wire)), Ok(kdf));
+        }
+        // verifiers registered before the parameters were sent
+        assert_eq!(kdf_from_wire(None), Ok(verifier::KdfParams::default()));
+        let unknown = KdfParams {
+            algorithm: 7,
+            ..Default::default()
+        };
+        assert!(kdf_from_wire(Some(&unknown)).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Mutex;
+
+// caps on what one realm (one tenant of a shared deployment) may use: the
+// users registered, the sessions held at once and the logins started each
+// minute. what goes past a cap is refused with RESOURCE_EXHAUSTED, and each
+// refusal is counted in QuotaMetrics for the operator to bill or alert on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quota {
+    pub max_users: Option<usize>,
+    pub max_sessions: Option<usize>,
+    pub max_logins_per_minute: Option<u32>,
+}
+
+// the quotas by realm, read from a JSON file like
+// {"realms": {"acme": {"max_users": 100, "max_logins_per_minute": 600}}}.
+// a realm not in it is unlimited
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaPolicy {
+    #[serde(default)]
+    pub realms: BTreeMap<String, Quota>,
+}
+
+impl QuotaPolicy {
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let json = std::fs::read(path)?;
+        serde_json::from_slice(&json)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn quota(&self, realm: &str) -> Quota {
+        self.realms.get(realm).copied().unwrap_or_default()
+    }
+}
+
+// the caps a refusal is counted under
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum QuotaKind {
+    Users,
+    Sessions,
+    LoginsPerMinute,
+}
+
+impl QuotaKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuotaKind::Users => "users",
+            QuotaKind::Sessions => "sessions",
+            QuotaKind::LoginsPerMinute => "logins_per_minute",
+        }
+    }
+}
+
+// logins started per realm in the current minute (of the Unix epoch), a
+// window that starts over on the minute rather than sliding
+#[derive(Debug, Default)]
+pub struct LoginRate(Mutex<HashMap<String, (u64, u32)>>);
+
+impl LoginRate {
+    // counts a login at now (seconds since the Unix epoch) unless the realm
+    // has started max this minute already
+    pub fn admit(&self, realm: &str, max: Option<u32>, now: u64) -> bool {
+        let minute = now / 60;
+        let windows = &mut self.0.lock().unwrap();
+        let (window, count) = windows.entry(realm.to_string()).or_default();
+        if *window != minute {
+            (*window, *count) = (minute, 0);
+        }
+        if max.is_some_and(|max| *count >= max) {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+
+    // logins the realm started in the minute of now
+    pub fn count(&self, realm: &str, now: u64) -> u32 {
+        match self.0.lock().unwrap().get(realm) {
+            Some((window, count)) if *window == now / 60 => *count,
+            _ => 0,
+        }
+    }
+}
+
+// refusals by realm and quota since the server started
+#[derive(Debug, Default)]
+pub struct QuotaMetrics(Mutex<BTreeMap<(String, QuotaKind), u64>>);
+
+impl QuotaMetrics {
+    pub fn refused(&self, realm: &str, kind: QuotaKind) {
+        *self
+            .0
+            .lock()
+            .unwrap()
+            .entry((realm.to_string(), kind))
+            .or_default() += 1;
+    }
+
+    pub fn refusals(&self) -> Vec<(String, QuotaKind, u64)> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((realm, kind), count)| (realm.clone(), *kind, *count))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logins_per_minute_start_over_each_minute() {
+        let policy: QuotaPolicy =
+            serde_json::from_str(r#"{"realms": {"acme": {"max_logins_per_minute": 2}}}"#).unwrap();
+        let acme = policy.quota("acme").max_logins_per_minute;
+        assert_eq!(policy.quota("other"), Quota::default());
+
+        let rate = LoginRate::default();
+        assert!(rate.admit("acme", acme, 600));
+        assert!(rate.admit("acme", acme, 630));
+        assert!(!rate.admit("acme", acme, 659));
+        assert_eq!(rate.count("acme", 659), 2);
+        // other realms and the next minute have their own count
+        assert!(rate.admit("other", None, 659));
+        assert!(rate.admit("acme", acme, 660));
+        assert_eq!(rate.count("acme", 660), 1);
+
+        assert!(
+            serde_json::from_str::<QuotaPolicy>(r#"{"realms": {"acme": {"users": 1}}}"#).is_err()
+        );
+    }
+}
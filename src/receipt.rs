@@ -0,0 +1,308 @@
+use crate::keys::{KeyPair, PublicKey};
+use crate::transcript::Transcript;
+use crate::{Proof, ZKP};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// a registration receipt: the server's signature over the user, the group and
+// its fingerprint, the verifier and when it was registered. the client keeps
+// it and presents it with later challenges; a server that has lost the
+// registration (a restore from an older backup, a replica that never got it)
+// or holds another verifier under the name then says so, where the login
+// alone would only fail as with a wrong password. signed like a bundle, with
+// a non-interactive proof of the server key over the digest, in the default
+// group
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    pub user: String,
+    pub group: String,
+    pub params_fingerprint: [u8; 32],
+    // H(y1, y2), see verifier_digest
+    pub verifier: [u8; 32],
+    // unix seconds
+    pub registered_at: u64,
+    // big-endian hex
+    pub c: String,
+    pub s: String,
+}
+
+// how a presented receipt stood against the registration the server has
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Standing {
+    Kept,
+    // no registration under the name
+    Dropped,
+    // another verifier, group or parameters than the receipted ones
+    Altered,
+}
+
+// H(y1, y2), so a receipt pins the verifier without carrying it
+pub fn verifier_digest(y1: &BigUint, y2: &BigUint) -> [u8; 32] {
+    let mut transcript = Transcript::new(b"zkp-chaum-pedersen/receipt-verifier");
+    transcript.append_biguint(b"y1", y1);
+    transcript.append_biguint(b"y2", y2);
+    transcript.finalize()
+}
+
+impl Receipt {
+    pub fn issue(
+        zkp: &ZKP,
+        key: &KeyPair,
+        user: &str,
+        group: &str,
+        params_fingerprint: [u8; 32],
+        verifier: [u8; 32],
+        registered_at: u64,
+    ) -> Self {
+        let mut receipt = Receipt {
+            user: user.to_string(),
+            group: group.to_string(),
+            params_fingerprint,
+            verifier,
+            registered_at,
+            c: String::new(),
+            s: String::new(),
+        };
+        let proof = zkp.prove_non_interactive(key.x.expose(), &receipt.digest());
+        receipt.c = hex::encode(proof.c.to_bytes_be());
+        receipt.s = hex::encode(proof.s.to_bytes_be());
+        receipt
+    }
+
+    // H(everything but the signature)
+    fn digest(&self) -> [u8; 32] {
+        let mut transcript = Transcript::new(b"zkp-chaum-pedersen/receipt");
+        transcript.append(b"user", self.user.as_bytes());
+        transcript.append(b"group", self.group.as_bytes());
+        transcript.append(b"params", &self.params_fingerprint);
+        transcript.append(b"verifier", &self.verifier);
+        transcript.append(b"registered_at", &self.registered_at.to_be_bytes());
+        transcript.finalize()
+    }
+
+    // signed by the key, in the default group
+    pub fn verify(&self, zkp: &ZKP, key: &PublicKey) -> bool {
+        let (Ok(c), Ok(s)) = (hex::decode(&self.c), hex::decode(&self.s)) else {
+            return false;
+        };
+        let proof = Proof {
+            c: BigUint::from_bytes_be(&c),
+            s: BigUint::from_bytes_be(&s),
+        };
+        zkp.verify_non_interactive(&key.y1, &key.y2, &proof, &self.digest())
+    }
+
+    // against the verifier and group registered under the receipt's user now,
+    // None when there is none, and the fingerprint of that group's parameters
+    pub fn standing(&self, registered: Option<([u8; 32], &str)>, params: [u8; 32]) -> Standing {
+        match registered {
+            None => Standing::Dropped,
+            Some((verifier, group))
+                if verifier == self.verifier
+                    && group == self.group
+                    && params == self.params_fingerprint =>
+            {
+                Standing::Kept
+            }
+            Some(_) => Standing::Altered,
+        }
+    }
+}
+
+#[cfg(feature = "proto")]
+impl From<&Receipt> for crate::zkp_auth::RegistrationReceipt {
+    fn from(receipt: &Receipt) -> Self {
+        crate::zkp_auth::RegistrationReceipt {
+            user: receipt.user.clone(),
+            group: receipt.group.clone(),
+            params_fingerprint: receipt.params_fingerprint.to_vec(),
+            verifier: receipt.verifier.to_vec(),
+            registered_at: receipt.registered_at,
+            c: hex::decode(&receipt.c).unwrap_or_default(),
+            s: hex::decode(&receipt.s).unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(feature = "proto")]
+impl TryFrom<&crate::zkp_auth::RegistrationReceipt> for Receipt {
+    type Error = &'static str;
+
+    fn try_from(receipt: &crate::zkp_auth::RegistrationReceipt) -> Result<Self, Self::Error> {
+        Ok(Receipt {
+            user: receipt.user.clone(),
+            group: receipt.group.clone(),
+            params_fingerprint: receipt
+                .params_fingerprint
+                .as_slice()
+                .try_into()
+                .map_err(|_| "params_fingerprint is not 32 bytes")?,
+            verifier: receipt
+                .verifier
+                .as_slice()
+                .try_into()
+                .map_err(|_| "verifier is not 32 bytes")?,
+            registered_at: receipt.registered_at,
+            c: hex::encode(&receipt.c),
+            s: hex::encode(&receipt.s),
+        })
+    }
+}
+
+// the receipts a client was given, in a JSON file of server address to user
+// to receipt. a registration replaces the user's receipt
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Receipts {
+    path: PathBuf,
+    servers: BTreeMap<String, BTreeMap<String, Receipt>>,
+    changed: bool,
+}
+
+// on-disk form, fingerprints hex
+#[derive(Serialize, Deserialize)]
+struct ReceiptFile {
+    group: String,
+    params_fingerprint: String,
+    verifier: String,
+    registered_at: u64,
+    c: String,
+    s: String,
+}
+
+fn digest_from_hex(value: &str) -> io::Result<[u8; 32]> {
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not 64 hex digits"))
+}
+
+impl Receipts {
+    // the file is created by the first save, one that doesn't exist yet has
+    // no receipts
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let files: BTreeMap<String, BTreeMap<String, ReceiptFile>> =
+            match std::fs::read_to_string(&path) {
+                Ok(json) => serde_json::from_str(&json)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(e) => return Err(e),
+            };
+        let mut servers = BTreeMap::new();
+        for (server, users) in files {
+            let mut receipts = BTreeMap::new();
+            for (user, file) in users {
+                let receipt = Receipt {
+                    user: user.clone(),
+                    group: file.group,
+                    params_fingerprint: digest_from_hex(&file.params_fingerprint)?,
+                    verifier: digest_from_hex(&file.verifier)?,
+                    registered_at: file.registered_at,
+                    c: file.c,
+                    s: file.s,
+                };
+                receipts.insert(user, receipt);
+            }
+            servers.insert(server, receipts);
+        }
+        Ok(Receipts {
+            path,
+            servers,
+            changed: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, server: &str, user: &str) -> Option<&Receipt> {
+        self.servers.get(server)?.get(user)
+    }
+
+    pub fn insert(&mut self, server: &str, receipt: Receipt) {
+        self.servers
+            .entry(server.to_string())
+            .or_default()
+            .insert(receipt.user.clone(), receipt);
+        self.changed = true;
+    }
+
+    // writes the file if insert changed it, next to itself and renamed over
+    // so it's never left half written
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        let files: BTreeMap<_, BTreeMap<_, _>> = self
+            .servers
+            .iter()
+            .map(|(server, users)| {
+                let users = users.iter().map(|(user, receipt)| {
+                    let file = ReceiptFile {
+                        group: receipt.group.clone(),
+                        params_fingerprint: hex::encode(receipt.params_fingerprint),
+                        verifier: hex::encode(receipt.verifier),
+                        registered_at: receipt.registered_at,
+                        c: receipt.c.clone(),
+                        s: receipt.s.clone(),
+                    };
+                    (user, file)
+                });
+                (server, users.collect())
+            })
+            .collect();
+        let json = serde_json::to_string_pretty(&files).expect("receipts serialize");
+        let temporary = self.path.with_extension("tmp");
+        std::fs::File::create(&temporary)?.write_all(json.as_bytes())?;
+        std::fs::rename(&temporary, &self.path)?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groups;
+
+    #[test]
+    fn test_receipts_are_signed_and_checked() {
+        let zkp = groups::default_group();
+        let key = KeyPair::generate(&zkp);
+        let params = groups::fingerprint(&zkp);
+        let verifier = verifier_digest(&BigUint::from(2u32), &BigUint::from(3u32));
+        let receipt = Receipt::issue(&zkp, &key, "alice", groups::DEFAULT, params, verifier, 7);
+        assert!(receipt.verify(&zkp, &key.public));
+        assert!(!receipt.verify(&zkp, &KeyPair::generate(&zkp).public));
+        let forged = Receipt {
+            registered_at: 8,
+            ..receipt.clone()
+        };
+        assert!(!forged.verify(&zkp, &key.public));
+
+        let registered = Some((verifier, groups::DEFAULT));
+        assert_eq!(receipt.standing(registered, params), Standing::Kept);
+        assert_eq!(receipt.standing(None, params), Standing::Dropped);
+        let other = verifier_digest(&BigUint::from(3u32), &BigUint::from(2u32));
+        assert_eq!(
+            receipt.standing(Some((other, groups::DEFAULT)), params),
+            Standing::Altered
+        );
+        assert_eq!(receipt.standing(registered, [0; 32]), Standing::Altered);
+
+        let path = std::env::temp_dir().join(format!("receipts-{}.json", std::process::id()));
+        let mut receipts = Receipts::load(&path).unwrap();
+        receipts.insert("http://127.0.0.1:50051", receipt.clone());
+        receipts.save().unwrap();
+        let receipts = Receipts::load(&path).unwrap();
+        assert_eq!(
+            receipts.get("http://127.0.0.1:50051", "alice"),
+            Some(&receipt)
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
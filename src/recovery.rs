@@ -0,0 +1,163 @@
+use crate::keys::{KeyPair, PublicKey};
+use crate::transcript::Transcript;
+use crate::verifier::KdfParams;
+use crate::{Proof, ZKP};
+use num_bigint::BigUint;
+use std::time::Duration;
+
+// account recovery: a second key pair, registered next to the verifier at
+// signup and kept offline, sets a new verifier once the password is lost.
+// the proof is a non-interactive Chaum-Pedersen proof for the recovery key,
+// bound to the user, the verifier it sets and when it was made, so it can't
+// be replayed for another verifier and goes stale after MAX_AGE. the server
+// waits out a cooldown between two recoveries of the same account
+
+// a proof made longer ago than this, or as far in the future, is refused
+pub const MAX_AGE: Duration = Duration::from_secs(300);
+
+// time an account waits between two recoveries, a day unless the server
+// picks another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cooldown(pub Duration);
+
+impl Default for Cooldown {
+    fn default() -> Self {
+        Cooldown(Duration::from_secs(24 * 60 * 60))
+    }
+}
+
+// the verifier a recovery sets
+#[derive(Debug, Clone, Copy)]
+pub struct NewVerifier<'a> {
+    pub y1: &'a BigUint,
+    pub y2: &'a BigUint,
+    pub salt: &'a [u8],
+    pub group: &'a str,
+    pub kdf: &'a KdfParams,
+}
+
+// what the proof is bound to. issued_at is in seconds since the Unix epoch
+pub fn context(user: &str, new: &NewVerifier, issued_at: u64) -> Vec<u8> {
+    let mut transcript = Transcript::new(b"zkp-chaum-pedersen/recovery");
+    transcript.append(b"user", user.as_bytes());
+    transcript.append_biguint(b"y1", new.y1);
+    transcript.append_biguint(b"y2", new.y2);
+    transcript.append(b"salt", new.salt);
+    transcript.append(b"group", new.group.as_bytes());
+    transcript.append(
+        b"kdf",
+        &serde_json::to_vec(new.kdf).expect("KDF parameters serialize"),
+    );
+    transcript.append(b"issued_at", &issued_at.to_be_bytes());
+    transcript.finalize().to_vec()
+}
+
+// zkp is the group the recovery key was made in
+pub fn prove(
+    zkp: &ZKP,
+    application: &[u8],
+    key: &KeyPair,
+    user: &str,
+    new: &NewVerifier,
+    issued_at: u64,
+) -> Proof {
+    zkp.prove_non_interactive_in(application, key.x.expose(), &context(user, new, issued_at))
+}
+
+pub fn verify(
+    zkp: &ZKP,
+    application: &[u8],
+    key: &PublicKey,
+    user: &str,
+    new: &NewVerifier,
+    issued_at: u64,
+    proof: &Proof,
+) -> bool {
+    zkp.verify_non_interactive_in(
+        application,
+        &key.y1,
+        &key.y2,
+        proof,
+        &context(user, new, issued_at),
+    )
+}
+
+// whether a proof made at issued_at is still taken at now, both in seconds
+// since the Unix epoch
+pub fn is_fresh(issued_at: u64, now: u64) -> bool {
+    issued_at.abs_diff(now) <= MAX_AGE.as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groups;
+
+    #[test]
+    fn test_recovery_proof_is_bound_to_the_new_verifier() {
+        let zkp = groups::default_group();
+        let key = KeyPair::generate(&zkp);
+        let kdf = KdfParams::default();
+        let (y1, y2) = (BigUint::from(2u32), BigUint::from(3u32));
+        let new = NewVerifier {
+            y1: &y1,
+            y2: &y2,
+            salt: b"salt",
+            group: groups::DEFAULT,
+            kdf: &kdf,
+        };
+        let proof = prove(&zkp, b"", &key, "alice", &new, 1000);
+        assert!(verify(&zkp, b"", &key.public, "alice", &new, 1000, &proof));
+        assert!(!verify(&zkp, b"", &key.public, "bob", &new, 1000, &proof));
+        assert!(!verify(&zkp, b"", &key.public, "alice", &new, 1001, &proof));
+
+        // the same proof for a verifier the attacker picked
+        let other_y1 = BigUint::from(4u32);
+        let other = NewVerifier {
+            y1: &other_y1,
+            ..new
+        };
+        assert!(!verify(
+            &zkp,
+            b"",
+            &key.public,
+            "alice",
+            &other,
+            1000,
+            &proof
+        ));
+        let argon2id = KdfParams::argon2id();
+        let other = NewVerifier {
+            kdf: &argon2id,
+            ..new
+        };
+        assert!(!verify(
+            &zkp,
+            b"",
+            &key.public,
+            "alice",
+            &other,
+            1000,
+            &proof
+        ));
+
+        // and under another recovery key
+        let stranger = KeyPair::generate(&zkp);
+        assert!(!verify(
+            &zkp,
+            b"",
+            &stranger.public,
+            "alice",
+            &new,
+            1000,
+            &proof
+        ));
+    }
+
+    #[test]
+    fn test_freshness() {
+        assert!(is_fresh(1000, 1000 + MAX_AGE.as_secs()));
+        assert!(is_fresh(1000 + MAX_AGE.as_secs(), 1000));
+        assert!(!is_fresh(1000, 1001 + MAX_AGE.as_secs()));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::elgamal::{self, Ciphertext};
+use crate::secret::wipe_biguint;
+use crate::{Proof, ZKP};
+use num_bigint::BigUint;
+
+// re-encryption under y with a fresh r: (a * g ** r mod p, b * y ** r mod p)
+// decrypts to the same message but can't be linked to the input
+pub fn reencrypt(zkp: &ZKP, y: &BigUint, ciphertext: &Ciphertext, r: &BigUint) -> Ciphertext {
+    let zero = elgamal::encrypt(zkp, y, &BigUint::from(0u32), r);
+    elgamal::add(zkp, ciphertext, &zero)
+}
+
+// the statement every weight and the proof are bound to:
+// H(g, h, p, q, y, inputs, outputs, context)
+fn statement(
+    zkp: &ZKP,
+    y: &BigUint,
+    inputs: &[Ciphertext],
+    outputs: &[Ciphertext],
+    context: &[u8],
+) -> [u8; 32] {
+    let mut transcript = zkp.transcript(b"zkp-chaum-pedersen/reencryption");
+    transcript.append_biguint(b"y", y);
+    transcript.append(b"count", &(inputs.len() as u64).to_be_bytes());
+    for (input, output) in inputs.iter().zip(outputs) {
+        transcript.append_biguint(b"a", &input.a);
+        transcript.append_biguint(b"b", &input.b);
+        transcript.append_biguint(b"a'", &output.a);
+        transcript.append_biguint(b"b'", &output.b);
+    }
+    transcript.append(b"context", context);
+    transcript.finalize()
+}
+
+// e_i = H(statement, i) mod q
+fn weights(zkp: &ZKP, statement: &[u8; 32], count: usize) -> Vec<BigUint> {
+    (0..count)
+        .map(|i| {
+            let mut transcript = zkp.transcript(b"zkp-chaum-pedersen/reencryption-weight");
+            transcript.append(b"statement", statement);
+            transcript.append(b"index", &(i as u64).to_be_bytes());
+            transcript.challenge(&zkp.q)
+        })
+        .collect()
+}
+
+// A = prod (a'_i / a_i) ** e_i mod p ; B = prod (b'_i / b_i) ** e_i mod p
+// every pair was re-encrypted with some r_i iff, except with probability
+// about 1/q, A = g ** R and B = y ** R for R = sum e_i * r_i mod q
+fn aggregate(
+    zkp: &ZKP,
+    inputs: &[Ciphertext],
+    outputs: &[Ciphertext],
+    weights: &[BigUint],
+) -> Option<(BigUint, BigUint)> {
+    let mut a = BigUint::from(1u32);
+    let mut b = BigUint::from(1u32);
+    for ((input, output), e) in inputs.iter().zip(outputs).zip(weights) {
+        let a_ratio = (&output.a * input.a.modinv(&zkp.p)?) % &zkp.p;
+        let b_ratio = (&output.b * input.b.modinv(&zkp.p)?) % &zkp.p;
+        a = (a * ZKP::exponentiate(&a_ratio, e, &zkp.p)) % &zkp.p;
+        b = (b * ZKP::exponentiate(&b_ratio, e, &zkp.p)) % &zkp.p;
+    }
+    Some((a, b))
+}
+
+// one proof for the whole list: outputs[i] = reencrypt(inputs[i], randomness[i]).
+// None if the lists differ in length or an input isn't invertible mod p
+pub fn prove(
+    zkp: &ZKP,
+    y: &BigUint,
+    inputs: &[Ciphertext],
+    outputs: &[Ciphertext],
+    randomness: &[BigUint],
+    context: &[u8],
+) -> Option<Proof> {
+    if inputs.len() != outputs.len() || inputs.len() != randomness.len() {
+        return None;
+    }
+    let statement = statement(zkp, y, inputs, outputs, context);
+    let weights = weights(zkp, &statement, inputs.len());
+
+    // R = sum e_i * r_i mod q
+    let mut combined = weights
+        .iter()
+        .zip(randomness)
+        .fold(BigUint::from(0u32), |sum, (e, r)| (sum + e * r) % &zkp.q);
+    let proof = elgamal::key_bases(zkp, y).prove_non_interactive(&combined, &statement);
+    wipe_biguint(&mut combined);
+    Some(proof)
+}
+
+// checks the whole list at the cost of one Chaum-Pedersen verification plus
+// two exponentiations per pair. every element must lie in the order-q
+// subgroup for the random combination to be sound, so that is checked first
+pub fn verify(
+    zkp: &ZKP,
+    y: &BigUint,
+    inputs: &[Ciphertext],
+    outputs: &[Ciphertext],
+    proof: &Proof,
+    context: &[u8],
+) -> bool {
+    if inputs.len() != outputs.len()
+        || !elgamal::in_subgroup(zkp, y)
+        || !inputs
+            .iter()
+            .chain(outputs)
+            .all(|c| elgamal::in_subgroup(zkp, &c.a) && elgamal::in_subgroup(zkp, &c.b))
+    {
+        return false;
+    }
+    let statement = statement(zkp, y, inputs, outputs, context);
+    let weights = weights(zkp, &statement, inputs.len());
+    match aggregate(zkp, inputs, outputs, &weights) {
+        Some((a, b)) => {
+            elgamal::key_bases(zkp, y).verify_non_interactive(&a, &b, proof, &statement)
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reencryption_proof() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let y = elgamal::public_key(&zkp, &x);
+
+        let inputs: Vec<Ciphertext> = (0..4u32)
+            .map(|m| {
+                let r = ZKP::generate_random_number_below(&zkp.q);
+                elgamal::encrypt(&zkp, &y, &BigUint::from(m), &r)
+            })
+            .collect();
+        let randomness: Vec<BigUint> = (0..4)
+            .map(|_| ZKP::generate_random_number_below(&zkp.q))
+            .collect();
+        let outputs: Vec<Ciphertext> = inputs
+            .iter()
+            .zip(&randomness)
+            .map(|(c, r)| reencrypt(&zkp, &y, c, r))
+            .collect();
+        assert_eq!(elgamal::decrypt(&zkp, &x, &outputs[2], 3), Some(2));
+
+        let proof = prove(&zkp, &y, &inputs, &outputs, &randomness, b"mix 1").unwrap();
+        assert!(verify(&zkp, &y, &inputs, &outputs, &proof, b"mix 1"));
+        assert!(!verify(&zkp, &y, &inputs, &outputs, &proof, b"mix 2"));
+        assert!(!verify(&zkp, &y, &inputs, &outputs[..3], &proof, b"mix 1"));
+
+        // swapping two outputs changes the messages at those positions
+        let mut swapped = outputs.clone();
+        swapped.swap(0, 1);
+        let proof = prove(&zkp, &y, &inputs, &swapped, &randomness, b"mix 1").unwrap();
+        assert!(!verify(&zkp, &y, &inputs, &swapped, &proof, b"mix 1"));
+
+        // an output that adds 1 to the message
+        let mut tampered = outputs.clone();
+        tampered[3].b = (&tampered[3].b * &zkp.g) % &zkp.p;
+        let proof = prove(&zkp, &y, &inputs, &tampered, &randomness, b"mix 1").unwrap();
+        assert!(!verify(&zkp, &y, &inputs, &tampered, &proof, b"mix 1"));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::secret::wipe_biguint;
+use crate::ZKP;
+use num_bigint::BigUint;
+
+// Okamoto proof of knowledge of a representation y = g ** a * h ** b mod p,
+// the building block of credential systems (Pedersen commitments, attributes).
+// it only binds the prover to (a, b) when nobody knows log_g(h): the h of
+// ZKP::get_constants is a published power of g, so use independent bases
+
+// y = g ** a * h ** b mod p
+pub fn represent(zkp: &ZKP, a: &BigUint, b: &BigUint) -> BigUint {
+    (ZKP::exponentiate(&zkp.g, a, &zkp.p) * ZKP::exponentiate(&zkp.h, b, &zkp.p)) % &zkp.p
+}
+
+// interactive protocol:
+// prover sends t = g ** k1 * h ** k2 mod p, verifier sends c,
+// prover answers s1 = k1 - c * a mod q ; s2 = k2 - c * b mod q
+pub fn solve(
+    zkp: &ZKP,
+    (k1, k2): (&BigUint, &BigUint),
+    c: &BigUint,
+    (a, b): (&BigUint, &BigUint),
+) -> (BigUint, BigUint) {
+    (zkp.solve(k1, c, a), zkp.solve(k2, c, b))
+}
+
+// t = g ** s1 * h ** s2 * y ** c mod p
+pub fn verify(
+    zkp: &ZKP,
+    t: &BigUint,
+    y: &BigUint,
+    c: &BigUint,
+    (s1, s2): (&BigUint, &BigUint),
+) -> bool {
+    // y = 0 gives 0 on the right for every answer
+    if *y == BigUint::from(0u32) || *y >= zkp.p {
+        return false;
+    }
+    *t == (represent(zkp, s1, s2) * ZKP::exponentiate(y, c, &zkp.p)) % &zkp.p
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepresentationProof {
+    pub c: BigUint,
+    pub s1: BigUint,
+    pub s2: BigUint,
+}
+
+// c = H(g, h, p, q, y, t, context) mod q
+fn challenge(zkp: &ZKP, y: &BigUint, t: &BigUint, context: &[u8]) -> BigUint {
+    let mut transcript = zkp.transcript(b"zkp-chaum-pedersen/representation");
+    transcript.append_biguint(b"y", y);
+    transcript.append_biguint(b"t", t);
+    transcript.append(b"context", context);
+    transcript.challenge(&zkp.q)
+}
+
+// non-interactive (Fiat–Shamir) form, bound to context
+pub fn prove(zkp: &ZKP, a: &BigUint, b: &BigUint, context: &[u8]) -> RepresentationProof {
+    let y = represent(zkp, a, b);
+    let mut k1 = ZKP::generate_random_number_below(&zkp.q);
+    let mut k2 = ZKP::generate_random_number_below(&zkp.q);
+    let t = represent(zkp, &k1, &k2);
+
+    let c = challenge(zkp, &y, &t, context);
+    let (s1, s2) = solve(zkp, (&k1, &k2), &c, (a, b));
+    wipe_biguint(&mut k1);
+    wipe_biguint(&mut k2);
+    RepresentationProof { c, s1, s2 }
+}
+
+pub fn verify_proof(zkp: &ZKP, y: &BigUint, proof: &RepresentationProof, context: &[u8]) -> bool {
+    if *y == BigUint::from(0u32) || *y >= zkp.p {
+        return false;
+    }
+    let t =
+        (represent(zkp, &proof.s1, &proof.s2) * ZKP::exponentiate(y, &proof.c, &zkp.p)) % &zkp.p;
+    proof.c == challenge(zkp, y, &t, context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toy_representation() {
+        let zkp = ZKP {
+            p: BigUint::from(23u32),
+            q: BigUint::from(11u32),
+            g: BigUint::from(4u32),
+            h: BigUint::from(9u32),
+        };
+        let (a, b) = (BigUint::from(3u32), BigUint::from(7u32));
+        // 4 ** 3 * 9 ** 7 mod 23 = 18 * 4 mod 23 = 3
+        let y = represent(&zkp, &a, &b);
+        assert_eq!(y, BigUint::from(3u32));
+
+        let (k1, k2) = (BigUint::from(5u32), BigUint::from(2u32));
+        let t = represent(&zkp, &k1, &k2);
+        let c = BigUint::from(4u32);
+        let (s1, s2) = solve(&zkp, (&k1, &k2), &c, (&a, &b));
+        assert!(verify(&zkp, &t, &y, &c, (&s1, &s2)));
+
+        // wrong second witness
+        let (s1, s2) = solve(&zkp, (&k1, &k2), &c, (&a, &BigUint::from(6u32)));
+        assert!(!verify(&zkp, &t, &y, &c, (&s1, &s2)));
+    }
+
+    #[test]
+    fn test_non_interactive_representation() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+        let a = ZKP::generate_random_number_below(&zkp.q);
+        let b = ZKP::generate_random_number_below(&zkp.q);
+        let y = represent(&zkp, &a, &b);
+
+        let proof = prove(&zkp, &a, &b, b"credential");
+        assert!(verify_proof(&zkp, &y, &proof, b"credential"));
+        assert!(!verify_proof(&zkp, &y, &proof, b"other context"));
+
+        let other = represent(&zkp, &a, &(&b + 1u32));
+        assert!(!verify_proof(&zkp, &other, &proof, b"credential"));
+        assert!(!verify_proof(
+            &zkp,
+            &BigUint::from(0u32),
+            &proof,
+            b"credential"
+        ));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::ZKP;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tonic::codegen::http;
+use tonic::metadata::MetadataMap;
+use tower::{Layer, Service};
+
+// every RPC runs under a request ID: the caller's `x-request-id` when it sends a
+// usable one, a fresh one otherwise. the server logs it, returns it in the
+// `x-request-id` response header (errors included) and puts it in v2 error
+// details, so what a client reports can be found in the server log
+pub const HEADER: &str = "x-request-id";
+
+const MAX_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: String;
+}
+
+// the ID of the RPC being handled, None outside of one
+pub fn current() -> Option<String> {
+    CURRENT.try_with(|id| id.clone()).ok()
+}
+
+// for log lines
+pub fn label() -> String {
+    current().unwrap_or_else(|| "-".to_string())
+}
+
+// the ID a response or an error came back with
+pub fn of(metadata: &MetadataMap) -> Option<&str> {
+    metadata.get(HEADER).and_then(|value| value.to_str().ok())
+}
+
+// a caller's ID is only kept if it can't smuggle anything into a log line
+fn usable(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b))
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestId<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestId(inner)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestId<S>(S);
+
+impl<S, B, R> Service<http::Request<B>> for RequestId<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<R>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        let id = request
+            .headers()
+            .get(HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| usable(id))
+            .map(str::to_string)
+            .unwrap_or_else(|| ZKP::generate_random_string(16));
+        let value = http::HeaderValue::from_str(&id).expect("request IDs are header values");
+        // the handlers see the ID in the metadata as well
+        request.headers_mut().insert(HEADER, value.clone());
+        let response = CURRENT.scope(id, self.0.call(request));
+        Box::pin(async move {
+            let mut response = response.await?;
+            response.headers_mut().insert(HEADER, value);
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usable_request_ids() {
+        assert!(usable("incident-42"));
+        assert!(usable("3f2a.b:c_d"));
+        assert!(!usable(""));
+        assert!(!usable("two words"));
+        assert!(!usable("line\nbreak"));
+        assert!(!usable(&"a".repeat(MAX_LEN + 1)));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::group::ChaumPedersenGroup;
+use crate::ZKP;
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use num_bigint::BigUint;
+use sha2::{Digest, Sha512};
+
+// Chaum-Pedersen in the Ristretto group of Curve25519: a prime order group
+// with 32-byte elements and scalars, where a 1024-bit MODP group needs 128
+// bytes for the same job. written multiplicatively like the rest of the
+// crate, g ** e is the point e * G. elements travel as the little-endian
+// number of their 32-byte encoding and scalars as numbers below l, so the
+// BigUint interface of ChaumPedersenGroup carries them unchanged
+
+// h = the point SHA-512(H_SEED) maps to, so nobody knows log_g h
+pub const H_SEED: &[u8] = b"zkp-chaum-pedersen/ristretto/h";
+
+#[derive(Debug, Clone)]
+pub struct RistrettoGroup {
+    pub g: RistrettoPoint,
+    pub h: RistrettoPoint,
+}
+
+impl Default for RistrettoGroup {
+    fn default() -> Self {
+        let hash: [u8; 64] = Sha512::digest(H_SEED).into();
+        RistrettoGroup {
+            g: RISTRETTO_BASEPOINT_POINT,
+            h: RistrettoPoint::from_uniform_bytes(&hash),
+        }
+    }
+}
+
+impl RistrettoGroup {
+    // l = 2 ** 252 + 27742317777372353535851937790883648493, the group order
+    pub fn order() -> BigUint {
+        (BigUint::from(1u32) << 252u32)
+            + BigUint::parse_bytes(b"27742317777372353535851937790883648493", 10)
+                .expect("the order is decimal")
+    }
+
+    pub fn element(point: &RistrettoPoint) -> BigUint {
+        BigUint::from_bytes_le(point.compress().as_bytes())
+    }
+
+    // None for a number that isn't the encoding of a point
+    pub fn point(element: &BigUint) -> Option<RistrettoPoint> {
+        let mut bytes = [0u8; 32];
+        let encoded = element.to_bytes_le();
+        bytes.get_mut(..encoded.len())?.copy_from_slice(&encoded);
+        CompressedRistretto(bytes).decompress()
+    }
+
+    // None at or above l, where two numbers would be one scalar
+    fn scalar(n: &BigUint) -> Option<Scalar> {
+        let mut bytes = [0u8; 32];
+        let encoded = n.to_bytes_le();
+        bytes.get_mut(..encoded.len())?.copy_from_slice(&encoded);
+        Scalar::from_canonical_bytes(bytes).into()
+    }
+
+    // any number, reduced mod l
+    fn reduced(n: &BigUint) -> Scalar {
+        let reduced = n % Self::order();
+        Self::scalar(&reduced).expect("reduced below l")
+    }
+}
+
+impl ChaumPedersenGroup for RistrettoGroup {
+    fn commit(&self, e: &BigUint) -> (BigUint, BigUint) {
+        let e = Self::reduced(e);
+        (Self::element(&(self.g * e)), Self::element(&(self.h * e)))
+    }
+
+    // s = k - c * x mod l
+    fn respond(&self, k: &BigUint, c: &BigUint, x: &BigUint) -> BigUint {
+        let s = Self::reduced(k) - Self::reduced(c) * Self::reduced(x);
+        BigUint::from_bytes_le(s.as_bytes())
+    }
+
+    // r1 = g ** s * y1 ** c ; r2 = h ** s * y2 ** c
+    fn check(
+        &self,
+        r1: &BigUint,
+        r2: &BigUint,
+        y1: &BigUint,
+        y2: &BigUint,
+        c: &BigUint,
+        s: &BigUint,
+    ) -> bool {
+        let points = (
+            Self::point(r1),
+            Self::point(r2),
+            Self::point(y1),
+            Self::point(y2),
+        );
+        let (Some(r1), Some(r2), Some(y1), Some(y2)) = points else {
+            return false;
+        };
+        let (Some(c), Some(s)) = (Self::scalar(c), Self::scalar(s)) else {
+            return false;
+        };
+        // the identity as y makes y ** c vanish, which would let anyone answer
+        let identity = RistrettoPoint::identity();
+        if y1 == identity || y2 == identity {
+            return false;
+        }
+        r1 == self.g * s + y1 * c && r2 == self.h * s + y2 * c
+    }
+
+    fn random_nonce(&self) -> BigUint {
+        ZKP::generate_random_number_below(&Self::order())
+    }
+
+    fn random_challenge(&self) -> BigUint {
+        ZKP::generate_random_number_below(&Self::order())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::tests::run_round;
+
+    #[test]
+    fn test_ristretto_round() {
+        let group = RistrettoGroup::default();
+        assert_ne!(group.g, group.h);
+        let x = group.random_nonce();
+        assert!(run_round(&group, &x, &x));
+        assert!(!run_round(&group, &x, &(&x + 1u32)));
+
+        // the identity as the public key can't be answered for
+        let zero = BigUint::from(0u32);
+        let (r1, r2) = group.commit(&zero);
+        let c = group.random_challenge();
+        assert!(!group.check(&r1, &r2, &r1, &r2, &c, &zero));
+
+        // nor a scalar at or above l, or a number that isn't a point
+        let (y1, y2) = group.commit(&x);
+        let k = group.random_nonce();
+        let (r1, r2) = group.commit(&k);
+        let s = group.respond(&k, &c, &x);
+        assert!(group.check(&r1, &r2, &y1, &y2, &c, &s));
+        let unreduced = &s + RistrettoGroup::order();
+        assert!(!group.check(&r1, &r2, &y1, &y2, &c, &unreduced));
+        assert!(RistrettoGroup::point(&(BigUint::from(1u32) << 256u32)).is_none());
+    }
+}
@@ -0,0 +1,151 @@
+use rand::rngs::OsRng;
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex, RwLock};
+
+// every random number the crate draws (nonces, challenges, salts, keys, auth
+// ids, session ids) comes from here, and through here from one
+// CryptoRngProvider: the operating system's generator (OsRng) unless another
+// provider is installed for the process. a seeded ChaCha20 provider can be
+// installed for the whole process (`--seed` on the server and client) or for
+// the current thread (tests), which makes full protocol runs reproducible. a
+// seeded run has no secrets: never use it outside of tests
+pub trait CryptoRngProvider: Send + Sync {
+    // fills dest with cryptographically secure random bytes
+    fn fill_bytes(&self, dest: &mut [u8]);
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsRngProvider;
+
+impl CryptoRngProvider for OsRngProvider {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        OsRng.fill_bytes(dest);
+    }
+}
+
+// a ChaCha20 stream from a 64-bit seed, for reproducible test runs only
+#[derive(Debug)]
+pub struct SeededProvider(Mutex<ChaCha20Rng>);
+
+impl SeededProvider {
+    pub fn new(seed: u64) -> Self {
+        SeededProvider(Mutex::new(ChaCha20Rng::seed_from_u64(seed)))
+    }
+}
+
+impl CryptoRngProvider for SeededProvider {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        self.0.lock().unwrap().fill_bytes(dest);
+    }
+}
+
+// None is OsRngProvider
+static PROCESS: RwLock<Option<Arc<dyn CryptoRngProvider>>> = RwLock::new(None);
+
+thread_local! {
+    static THREAD: RefCell<Option<ChaCha20Rng>> = const { RefCell::new(None) };
+}
+
+// replaces the provider for the whole process, the ones before it are dropped
+pub fn set_provider(provider: Arc<dyn CryptoRngProvider>) {
+    *PROCESS.write().unwrap() = Some(provider);
+}
+
+pub fn seed_process(seed: u64) {
+    set_provider(Arc::new(SeededProvider::new(seed)));
+}
+
+// takes precedence over the process provider, until clear_thread_seed
+pub fn seed_thread(seed: u64) {
+    THREAD.with(|rng| *rng.borrow_mut() = Some(ChaCha20Rng::seed_from_u64(seed)));
+}
+
+pub fn clear_thread_seed() {
+    THREAD.with(|rng| *rng.borrow_mut() = None);
+}
+
+// a provider as an RngCore, for the rand and num-bigint APIs
+struct ProviderRng<'a>(&'a dyn CryptoRngProvider);
+
+impl RngCore for ProviderRng<'_> {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.0.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.0.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.0.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for ProviderRng<'_> {}
+
+// runs f with the thread seed or the process provider, in that order
+pub fn with_rng<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+    THREAD.with(|thread| {
+        if let Some(rng) = thread.borrow_mut().as_mut() {
+            return f(rng);
+        }
+        // cloned out so f can draw without holding the lock
+        let provider = PROCESS.read().unwrap().clone();
+        match provider {
+            Some(provider) => f(&mut ProviderRng(provider.as_ref())),
+            None => f(&mut ProviderRng(&OsRngProvider)),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ZKP;
+    use num_bigint::BigUint;
+
+    #[test]
+    fn test_thread_seed_is_reproducible() {
+        let q = BigUint::from(1_000_003u32);
+        let draw = || {
+            (
+                ZKP::generate_random_number_below(&q),
+                ZKP::generate_random_string(12),
+            )
+        };
+
+        seed_thread(7);
+        let first = draw();
+        seed_thread(7);
+        assert_eq!(draw(), first);
+        seed_thread(8);
+        assert_ne!(draw(), first);
+        clear_thread_seed();
+    }
+
+    // set_provider is process-wide and would reach the other tests, so the
+    // providers are checked on their own
+    #[test]
+    fn test_providers() {
+        let draw = |provider: &dyn CryptoRngProvider| {
+            let mut bytes = [0u8; 32];
+            ProviderRng(provider).fill_bytes(&mut bytes);
+            bytes
+        };
+        let seeded = draw(&SeededProvider::new(7));
+        assert_eq!(draw(&SeededProvider::new(7)), seeded);
+        assert_ne!(draw(&SeededProvider::new(8)), seeded);
+        assert_ne!(draw(&OsRngProvider), draw(&OsRngProvider));
+    }
+}
@@ -0,0 +1,78 @@
+use crate::{Proof, ZKP};
+use num_bigint::BigUint;
+
+// Schnorr proof of knowledge of x with y = g ** x mod p, the one-base case of
+// Chaum-Pedersen: for a statement with a single public value, e.g. a key an
+// application checks without an h. proofs are crate::Proof (c, s) and use
+// their own domain, so a Schnorr proof never verifies as a Chaum-Pedersen
+// one or the other way round
+
+// c = H(g, h, p, q, application, y, r, context) mod q
+fn challenge(zkp: &ZKP, application: &[u8], y: &BigUint, r: &BigUint, context: &[u8]) -> BigUint {
+    let mut transcript = zkp.application_transcript(b"zkp-chaum-pedersen/schnorr", application);
+    transcript.append_biguint(b"y", y);
+    transcript.append_biguint(b"r", r);
+    transcript.append(b"context", context);
+    transcript.challenge(&zkp.q)
+}
+
+// r = g ** k mod p ; s = k - c * x mod q
+#[cfg(feature = "prover")]
+pub fn prove(zkp: &ZKP, application: &[u8], x: &BigUint, context: &[u8]) -> Proof {
+    let y = ZKP::exponentiate(&zkp.g, x, &zkp.p);
+    let mut k = ZKP::generate_random_number_below(&zkp.q);
+    let r = ZKP::exponentiate(&zkp.g, &k, &zkp.p);
+    let c = challenge(zkp, application, &y, &r, context);
+    let s = zkp.solve(&k, &c, x);
+    crate::secret::wipe_biguint(&mut k);
+    Proof { c, s }
+}
+
+// r = g ** s * y ** c mod p, and the proof holds if hashing it gives back c
+pub fn verify(zkp: &ZKP, application: &[u8], y: &BigUint, proof: &Proof, context: &[u8]) -> bool {
+    // y = 0 gives r = 0 for every s, which would let anyone forge a proof
+    if *y == BigUint::from(0u32) || *y >= zkp.p || !zkp.within_bounds(&[], &[&proof.c, &proof.s]) {
+        return false;
+    }
+    let r = ZKP::mod_mul(
+        &zkp.g.modpow(&proof.s, &zkp.p),
+        &y.modpow(&proof.c, &zkp.p),
+        &zkp.p,
+    );
+    proof.c == challenge(zkp, application, y, &r, context)
+}
+
+#[cfg(all(test, feature = "prover"))]
+mod tests {
+    use super::*;
+    use crate::groups;
+
+    #[test]
+    fn test_schnorr_proof() {
+        let zkp = groups::default_group();
+        let x = BigUint::from(0x5eedu32);
+        let y = ZKP::exponentiate(&zkp.g, &x, &zkp.p);
+        let proof = prove(&zkp, b"app", &x, b"nonce");
+        assert!(verify(&zkp, b"app", &y, &proof, b"nonce"));
+        assert!(!verify(&zkp, b"app", &y, &proof, b"other"));
+        assert!(!verify(&zkp, b"", &y, &proof, b"nonce"));
+        assert!(!verify(
+            &zkp,
+            b"app",
+            &(&y * 2u32 % &zkp.p),
+            &proof,
+            b"nonce"
+        ));
+        assert!(!verify(
+            &zkp,
+            b"app",
+            &BigUint::from(0u32),
+            &proof,
+            b"nonce"
+        ));
+
+        // nor as a Chaum-Pedersen proof with any y2
+        let y2 = ZKP::exponentiate(&zkp.h, &x, &zkp.p);
+        assert!(!zkp.verify_non_interactive_in(b"app", &y, &y2, &proof, b"nonce"));
+    }
+}
@@ -0,0 +1,138 @@
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+// state written to disk sealed with AES-256-GCM under a key of the
+// deployment, so a copy of the file (a backup, a stolen disk) gives up neither
+// the verifiers nor who registered. the key is a file of 64 hex digits, which
+// a KMS agent or a secrets mount can provide; it never goes into the sealed
+// file. every kind of file seals with its own label as associated data, so a
+// sealed file can't be passed off as another kind
+pub const CIPHER: &str = "aes-256-gcm";
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Clone)]
+pub struct StoreKey(Zeroizing<[u8; 32]>);
+
+impl std::fmt::Debug for StoreKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StoreKey(..)")
+    }
+}
+
+// a sealed file as it is on disk, its nonce and ciphertext hex
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub cipher: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SealError {
+    UnsupportedCipher(String),
+    // not a valid envelope, or one sealed under another key or label, or
+    // changed since
+    Unreadable,
+}
+
+impl Display for SealError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SealError::UnsupportedCipher(cipher) => {
+                write!(f, "sealed with {}, only {} is supported", cipher, CIPHER)
+            }
+            SealError::Unreadable => write!(f, "can't be opened with the store key"),
+        }
+    }
+}
+
+impl std::error::Error for SealError {}
+
+impl StoreKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        StoreKey(Zeroizing::new(key))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = Zeroizing::new(std::fs::read_to_string(path).map_err(|e| e.to_string())?);
+        let bytes = Zeroizing::new(
+            hex::decode(contents.trim()).map_err(|_| "the key is not hex".to_string())?,
+        );
+        let key: [u8; 32] = bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| format!("the key is {} bytes, not 32", bytes.len()))?;
+        Ok(StoreKey::new(key))
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.0[..]))
+    }
+
+    // a fresh random nonce for every seal
+    pub fn seal(&self, label: &[u8], plaintext: &[u8]) -> Envelope {
+        let mut nonce = [0u8; NONCE_LEN];
+        crate::rng::with_rng(|rng| rng.fill_bytes(&mut nonce));
+        let ciphertext = self
+            .cipher()
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: label,
+                },
+            )
+            .expect("AES-GCM seals any length a file has");
+        Envelope {
+            cipher: CIPHER.to_string(),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        }
+    }
+
+    pub fn open(&self, label: &[u8], envelope: &Envelope) -> Result<Zeroizing<Vec<u8>>, SealError> {
+        if envelope.cipher != CIPHER {
+            return Err(SealError::UnsupportedCipher(envelope.cipher.clone()));
+        }
+        let nonce = hex::decode(&envelope.nonce).map_err(|_| SealError::Unreadable)?;
+        let ciphertext = hex::decode(&envelope.ciphertext).map_err(|_| SealError::Unreadable)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(SealError::Unreadable);
+        }
+        self.cipher()
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: label,
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| SealError::Unreadable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sealed_bytes_open_only_under_their_key_and_label() {
+        let key = StoreKey::new([1; 32]);
+        let envelope = key.seal(b"users", b"alice");
+        assert!(!envelope.ciphertext.contains(&hex::encode(b"alice")));
+        assert_eq!(key.open(b"users", &envelope).unwrap().as_slice(), b"alice");
+        assert_eq!(key.open(b"sessions", &envelope), Err(SealError::Unreadable));
+        assert_eq!(
+            StoreKey::new([2; 32]).open(b"users", &envelope),
+            Err(SealError::Unreadable)
+        );
+        // the same plaintext seals differently every time
+        assert_ne!(key.seal(b"users", b"alice").nonce, envelope.nonce);
+    }
+}
@@ -0,0 +1,224 @@
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+// asked after the answer has proven the password and before a session is
+// issued: a TOTP code, a push approval, a hardware key. the proof only shows
+// that the client knows x, the factor decides whether this login goes ahead
+#[tonic::async_trait]
+pub trait SecondFactor: Debug + Send + Sync {
+    // reported in the answer response and in rejections, e.g. "totp"
+    fn name(&self) -> &str;
+
+    async fn check(&self, attempt: &SecondFactorAttempt<'_>) -> Result<(), SecondFactorError>;
+}
+
+// what the server knows about the login being checked
+#[derive(Debug, Clone, Copy)]
+pub struct SecondFactorAttempt<'a> {
+    pub user: &'a str,
+    // AuthenticationAnswerRequest.second_factor, empty when the client sent none
+    pub code: &'a str,
+    // empty when unknown
+    pub remote_addr: &'a str,
+    // the server's clock
+    pub now: SystemTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecondFactorError {
+    // the factor says no, the login fails
+    Rejected(String),
+    // the factor couldn't be asked, nothing was decided
+    Unavailable(String),
+}
+
+impl Display for SecondFactorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecondFactorError::Rejected(reason) => write!(f, "rejected: {}", reason),
+            SecondFactorError::Unavailable(reason) => {
+                write!(f, "second factor unavailable: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SecondFactorError {}
+
+// RFC 6238 time-based one-time passwords as authenticator apps make them:
+// HMAC-SHA1, 6 digits, 30-second steps, one step of clock skew either way.
+// a user without a secret can't log in, and a code is only taken once
+pub struct Totp {
+    secrets: HashMap<String, Zeroizing<Vec<u8>>>,
+    // the newest step each user has logged in with
+    used: Mutex<HashMap<String, u64>>,
+}
+
+const STEP_SECONDS: u64 = 30;
+const DIGITS: u32 = 6;
+const SKEW_STEPS: u64 = 1;
+
+impl Debug for Totp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Totp")
+            .field("users", &self.secrets.len())
+            .finish_non_exhaustive()
+    }
+}
+
+// RFC 4648 base32, the form authenticator apps are given secrets in. padding
+// and spaces are ignored, case doesn't matter
+pub fn decode_base32(text: &str) -> Option<Vec<u8>> {
+    let (mut bits, mut buffer, mut out) = (0u32, 0u64, Vec::new());
+    for c in text.chars().filter(|c| *c != '=' && !c.is_whitespace()) {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u64 - 'A' as u64,
+            c @ '2'..='7' => c as u64 - '2' as u64 + 26,
+            _ => return None,
+        };
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+impl Totp {
+    pub fn new(secrets: HashMap<String, Vec<u8>>) -> Self {
+        Totp {
+            secrets: secrets
+                .into_iter()
+                .map(|(user, secret)| (user, Zeroizing::new(secret)))
+                .collect(),
+            used: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // one "user base32-secret" pair per line, blank lines and # comments
+    // skipped. the names are as written, the caller canonicalizes them
+    pub fn load_secrets(path: &Path) -> Result<HashMap<String, Vec<u8>>, String> {
+        let contents = Zeroizing::new(std::fs::read_to_string(path).map_err(|e| e.to_string())?);
+        let mut secrets = HashMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let secret = line
+                .split_once(char::is_whitespace)
+                .and_then(|(user, secret)| Some((user, decode_base32(secret)?)));
+            match secret {
+                Some((user, secret)) if !secret.is_empty() => {
+                    secrets.insert(user.to_string(), secret);
+                }
+                _ => return Err(format!("line {}: expected \"user base32-secret\"", i + 1)),
+            }
+        }
+        Ok(secrets)
+    }
+
+    // the code for a time step
+    pub fn code(secret: &[u8], step: u64) -> u32 {
+        let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC takes any key length");
+        mac.update(&step.to_be_bytes());
+        let digest = mac.finalize().into_bytes();
+        // dynamic truncation
+        let offset = (digest[19] & 0x0f) as usize;
+        let value =
+            u32::from_be_bytes(digest[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+        value % 10u32.pow(DIGITS)
+    }
+
+    pub fn check_at(
+        &self,
+        user: &str,
+        code: &str,
+        unix_seconds: u64,
+    ) -> Result<(), SecondFactorError> {
+        let Some(secret) = self.secrets.get(user) else {
+            return Err(SecondFactorError::Rejected(
+                "no TOTP secret is enrolled".to_string(),
+            ));
+        };
+        let code = code.trim();
+        if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(SecondFactorError::Rejected(format!(
+                "expected a {}-digit code",
+                DIGITS
+            )));
+        }
+        let code: u32 = code.parse().expect("only digits");
+        let now = unix_seconds / STEP_SECONDS;
+        let step = (now.saturating_sub(SKEW_STEPS)..=now + SKEW_STEPS)
+            .find(|step| Totp::code(secret, *step) == code)
+            .ok_or_else(|| SecondFactorError::Rejected("wrong code".to_string()))?;
+        // a code seen once, or one older than the last used, is a replay
+        let mut used = self.used.lock().unwrap();
+        if used.get(user).is_some_and(|last| step <= *last) {
+            return Err(SecondFactorError::Rejected(
+                "code was already used".to_string(),
+            ));
+        }
+        used.insert(user.to_string(), step);
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl SecondFactor for Totp {
+    fn name(&self) -> &str {
+        "totp"
+    }
+
+    async fn check(&self, attempt: &SecondFactorAttempt<'_>) -> Result<(), SecondFactorError> {
+        let now = attempt
+            .now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.check_at(attempt.user, attempt.code, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totp() {
+        // RFC 6238 appendix B, SHA-1, cut to 6 digits
+        let secret = b"12345678901234567890";
+        assert_eq!(Totp::code(secret, 59 / 30), 287082);
+        assert_eq!(Totp::code(secret, 1111111109 / 30), 81804);
+        assert_eq!(
+            decode_base32("GEZDGNBV GY3TQOJQ GEZDGNBV GY3TQOJQ").unwrap(),
+            secret
+        );
+        assert!(decode_base32("not base32!").is_none());
+
+        let totp = Totp::new(HashMap::from([("alice".to_string(), secret.to_vec())]));
+        assert_eq!(totp.check_at("alice", "081804", 1111111109), Ok(()));
+        // once only, and no going back to an older step
+        assert!(totp.check_at("alice", "081804", 1111111109).is_err());
+        let earlier = format!("{:06}", Totp::code(secret, 1111111109 / 30 - 1));
+        assert!(totp.check_at("alice", &earlier, 1111111109).is_err());
+        // the next step's code is taken a step early
+        let next = format!("{:06}", Totp::code(secret, 1111111109 / 30 + 1));
+        assert_eq!(totp.check_at("alice", &next, 1111111109), Ok(()));
+
+        let far = format!("{:06}", Totp::code(secret, 1111111109 / 30 + 5));
+        assert!(totp.check_at("alice", &far, 1111111109).is_err());
+        assert!(totp.check_at("alice", "", 1111111109).is_err());
+        assert!(totp.check_at("bob", "081804", 1111111109).is_err());
+    }
+}
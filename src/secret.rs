@@ -0,0 +1,168 @@
+use num_bigint::BigUint;
+use sha2::{Digest, Sha256};
+use std::fmt::Debug;
+use std::hint::black_box;
+
+// overwrite the limbs of n in place. num-bigint has no zeroize support, and
+// writing plain zeros would make it truncate and reallocate the buffer before
+// the old digits are touched, so the top digit is set to 1 to keep the length
+pub fn wipe_biguint(n: &mut BigUint) {
+    let digits = n.iter_u32_digits().len();
+    if digits == 0 {
+        return;
+    }
+    let mut filler = vec![0u32; digits];
+    filler[digits - 1] = 1;
+    n.assign_from_slice(&filler);
+    black_box(&*n);
+}
+
+// secret number (the password-derived x or the nonce k), wiped on drop
+pub struct SecretBigUint(BigUint);
+
+impl SecretBigUint {
+    pub fn new(n: BigUint) -> Self {
+        SecretBigUint(n)
+    }
+
+    // x = password bytes read as a big-endian integer
+    pub fn from_password(password: &str) -> Self {
+        SecretBigUint(BigUint::from_bytes_be(password.as_bytes()))
+    }
+
+    pub fn expose(&self) -> &BigUint {
+        &self.0
+    }
+}
+
+impl Drop for SecretBigUint {
+    fn drop(&mut self) {
+        wipe_biguint(&mut self.0);
+    }
+}
+
+impl Debug for SecretBigUint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretBigUint(<redacted>)")
+    }
+}
+
+impl Reveal for SecretBigUint {}
+
+impl Debug for Revealed<'_, SecretBigUint> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SecretBigUint")
+            .field(self.0.expose())
+            .finish()
+    }
+}
+
+// Debug of a credential or proof value without the value: its length and
+// the first four bytes of its SHA-256, enough to tell two apart in a log or
+// to match one against a value at hand
+pub struct Redacted<'a, T: ?Sized>(pub &'a T);
+
+fn fmt_redacted(f: &mut std::fmt::Formatter<'_>, bytes: &[u8], unit: &str) -> std::fmt::Result {
+    if bytes.is_empty() {
+        return f.write_str("<empty>");
+    }
+    let digest = Sha256::digest(bytes);
+    write!(
+        f,
+        "<{} {} sha256:{}>",
+        bytes.len(),
+        unit,
+        hex::encode(&digest[..4])
+    )
+}
+
+impl Debug for Redacted<'_, [u8]> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_redacted(f, self.0, "bytes")
+    }
+}
+
+impl Debug for Redacted<'_, Vec<u8>> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_redacted(f, self.0, "bytes")
+    }
+}
+
+impl Debug for Redacted<'_, String> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_redacted(f, self.0.as_bytes(), "chars")
+    }
+}
+
+impl Debug for Redacted<'_, Vec<Vec<u8>>> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.0.iter().map(Redacted)).finish()
+    }
+}
+
+// big-endian bytes, so the fingerprint matches the wire form
+impl Debug for Redacted<'_, BigUint> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_redacted(f, &self.0.to_bytes_be(), "bytes")
+    }
+}
+
+// the full Debug of a type whose own Debug redacts, for tests and local
+// debugging: format!("{:?}", value.reveal()). never for logs
+pub struct Revealed<'a, T: ?Sized>(pub &'a T);
+
+pub trait Reveal {
+    fn reveal(&self) -> Revealed<'_, Self> {
+        Revealed(self)
+    }
+}
+
+// keeps a short prefix so log lines can still be correlated
+pub fn redact(value: &str) -> String {
+    let prefix: String = value.chars().take(4).collect();
+    format!("{}…({} chars)", prefix, value.chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wipe_overwrites_digits() {
+        let mut n = BigUint::from_bytes_be(&[0xab; 64]);
+        wipe_biguint(&mut n);
+        // only the marker digit is left
+        assert_eq!(n, BigUint::from(1u32) << (32 * 15));
+
+        let mut zero = BigUint::from(0u32);
+        wipe_biguint(&mut zero);
+        assert_eq!(zero, BigUint::from(0u32));
+    }
+
+    #[test]
+    fn test_secret_is_not_printed() {
+        let secret = SecretBigUint::from_password("hunter2");
+        assert_eq!(format!("{:?}", secret), "SecretBigUint(<redacted>)");
+        assert_eq!(secret.expose(), &BigUint::from_bytes_be(b"hunter2"));
+    }
+
+    #[test]
+    fn test_redacted_shows_length_and_fingerprint() {
+        let mac = vec![0xab_u8; 32];
+        let shown = format!("{:?}", Redacted(&mac));
+        assert!(shown.starts_with("<32 bytes sha256:"), "{}", shown);
+        assert!(!shown.contains("abab"));
+        // the same value, the same fingerprint
+        assert_eq!(shown, format!("{:?}", Redacted(mac.as_slice())));
+        assert_eq!(format!("{:?}", Redacted(&String::new())), "<empty>");
+
+        let secret = SecretBigUint::new(BigUint::from(1234u32));
+        assert_eq!(format!("{:?}", secret.reveal()), "SecretBigUint(1234)");
+    }
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact("k7UqwUlr8Ggj"), "k7Uq…(12 chars)");
+        assert_eq!(redact("ab"), "ab…(2 chars)");
+    }
+}
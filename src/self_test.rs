@@ -0,0 +1,240 @@
+use crate::blinding::{self, SealKey};
+use crate::group::ChaumPedersenGroup;
+use crate::groups;
+use crate::service::AuthImpl;
+use crate::state::Challenge;
+use crate::ZKP;
+use num_bigint::BigUint;
+use std::fmt::Display;
+
+// startup checks for `server --self-test`: the group parameters, one
+// prove/verify round trip per proof system the server is configured to run,
+// and the state stores. meant to fail before the port is bound, not on the
+// first login
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestError {
+    pub check: &'static str,
+    pub reason: &'static str,
+}
+
+impl Display for SelfTestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.check, self.reason)
+    }
+}
+
+impl std::error::Error for SelfTestError {}
+
+fn fail(check: &'static str, reason: &'static str) -> Result<(), SelfTestError> {
+    Err(SelfTestError { check, reason })
+}
+
+// p and q prime, q | p - 1, and g, h generators of the order-q subgroup
+pub fn check_group(zkp: &ZKP) -> Result<(), SelfTestError> {
+    const CHECK: &str = "group parameters";
+    let one = BigUint::from(1u32);
+    if !groups::is_probable_prime(&zkp.p, 32) {
+        return fail(CHECK, "p is not prime");
+    }
+    if !groups::is_probable_prime(&zkp.q, 32) {
+        return fail(CHECK, "q is not prime");
+    }
+    if (&zkp.p - &one) % &zkp.q != BigUint::from(0u32) {
+        return fail(CHECK, "q does not divide p - 1");
+    }
+    for (base, reason) in [
+        (&zkp.g, "g does not generate the order-q subgroup"),
+        (&zkp.h, "h does not generate the order-q subgroup"),
+    ] {
+        if *base <= one || *base >= zkp.p || ZKP::exponentiate(base, &zkp.q, &zkp.p) != one {
+            return fail(CHECK, reason);
+        }
+    }
+    if zkp.g == zkp.h {
+        return fail(CHECK, "g and h are equal");
+    }
+    Ok(())
+}
+
+// commit, challenge, respond, check, and a wrong secret must be rejected
+fn round_trip<G: ChaumPedersenGroup>(group: &G, check: &'static str) -> Result<(), SelfTestError> {
+    let x = group.random_nonce();
+    let (y1, y2) = group.commit(&x);
+    let k = group.random_nonce();
+    let (r1, r2) = group.commit(&k);
+    // c = 0 would accept any secret
+    let mut c = group.random_challenge();
+    while c == BigUint::from(0u32) {
+        c = group.random_challenge();
+    }
+    let s = group.respond(&k, &c, &x);
+    if !group.check(&r1, &r2, &y1, &y2, &c, &s) {
+        return fail(check, "an honest proof does not verify");
+    }
+    let s = group.respond(&k, &c, &(&x + 1u32));
+    if group.check(&r1, &r2, &y1, &y2, &c, &s) {
+        return fail(check, "a proof with the wrong secret verifies");
+    }
+    Ok(())
+}
+
+// names of the checks that ran, in order
+pub fn run(auth: &AuthImpl, zkp: &ZKP) -> Result<Vec<&'static str>, SelfTestError> {
+    let mut passed = Vec::new();
+
+    check_group(zkp)?;
+    passed.push("group parameters");
+
+    round_trip(zkp, "interactive proofs")?;
+    passed.push("interactive proofs");
+
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let proof = zkp.prove_non_interactive(&x, b"self-test");
+    let y1 = ZKP::exponentiate(&zkp.g, &x, &zkp.p);
+    let y2 = ZKP::exponentiate(&zkp.h, &x, &zkp.p);
+    if !zkp.verify_non_interactive(&y1, &y2, &proof, b"self-test") {
+        fail("offline proofs", "an honest proof does not verify")?;
+    }
+    passed.push("offline proofs");
+
+    // users can be registered under any of them
+    const GROUPS: &str = "named groups";
+    for name in groups::NAMES {
+        let group = groups::by_name(name).expect("named groups resolve");
+        check_group(&group).map_err(|e| SelfTestError {
+            check: GROUPS,
+            reason: e.reason,
+        })?;
+        round_trip(&group, GROUPS)?;
+    }
+    passed.push(GROUPS);
+
+    if let Some(binding) = &auth.channel_binding {
+        const CHECK: &str = "channel binding";
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        let (r1, r2) = zkp.commit(&k);
+        let c = zkp.bind_challenge(
+            &auth.application,
+            &r1,
+            &r2,
+            &zkp.random_challenge(),
+            binding,
+        );
+        let s = zkp.solve(&k, &c, &x);
+        if !zkp.verify(&r1, &r2, &y1, &y2, &c, &s) {
+            fail(CHECK, "an honest proof does not verify")?;
+        }
+        passed.push(CHECK);
+    }
+
+    if let Some(server_key) = &auth.server_key {
+        const CHECK: &str = "server key";
+        if zkp.commit(server_key.x.expose())
+            != (server_key.public.y1.clone(), server_key.public.y2.clone())
+        {
+            fail(CHECK, "the key pair does not belong to this group")?;
+        }
+        passed.push(CHECK);
+
+        // what a client seals for the server must open with the server key
+        const BLINDED: &str = "blinded logins";
+        let (u_public, client_key) = SealKey::to_server(zkp, &server_key.public);
+        let Some(server_seal) = SealKey::from_client(zkp, server_key.x.expose(), &u_public) else {
+            return Err(SelfTestError {
+                check: BLINDED,
+                reason: "the server can't open a sealed identity",
+            });
+        };
+        if server_seal.xor(b"user", &client_key.xor(b"user", b"self-test")) != b"self-test" {
+            fail(BLINDED, "the server can't open a sealed identity")?;
+        }
+        let t = ZKP::generate_random_number_below(&zkp.q) + 1u32;
+        round_trip(&blinding::blinded_bases(zkp, &t), BLINDED)?;
+        passed.push(BLINDED);
+    }
+
+    // the stores answer and give back what was put in
+    const STORES: &str = "state stores";
+    let auth_id = format!("self-test-{}", ZKP::generate_random_string(12));
+    let now = auth.clock.now();
+    let challenge = Challenge {
+        created_at: now,
+        ..Challenge::default()
+    };
+    if !auth.challenges.insert(auth_id.clone(), challenge, now)
+        || auth.challenges.take(&auth_id, now).is_none()
+    {
+        fail(STORES, "a pending challenge was lost")?;
+    }
+    let _ = auth.users.get(&auth_id);
+    let _ = auth.sessions.remove(&auth_id);
+    passed.push(STORES);
+
+    Ok(passed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::KeyPair;
+
+    fn group() -> ZKP {
+        let (g, h, p, q) = ZKP::get_constants();
+        ZKP { p, q, g, h }
+    }
+
+    #[test]
+    fn test_self_test() {
+        let zkp = group();
+        let auth = AuthImpl {
+            server_key: Some(KeyPair::generate(&zkp)),
+            channel_binding: Some(b"certificate hash".to_vec()),
+            ..AuthImpl::default()
+        };
+        let passed = run(&auth, &zkp).unwrap();
+        assert_eq!(passed.len(), 8);
+        assert!(auth.challenges.is_empty());
+
+        // toy group from the tests in lib.rs
+        let toy = ZKP {
+            p: BigUint::from(23u32),
+            q: BigUint::from(11u32),
+            g: BigUint::from(4u32),
+            h: BigUint::from(9u32),
+        };
+        assert_eq!(check_group(&toy), Ok(()));
+
+        // 5 generates all of Z_23*, not the order-11 subgroup
+        let wrong_generator = ZKP {
+            g: BigUint::from(5u32),
+            ..toy.clone()
+        };
+        assert_eq!(
+            check_group(&wrong_generator).unwrap_err().reason,
+            "g does not generate the order-q subgroup"
+        );
+        let wrong_order = ZKP {
+            q: BigUint::from(7u32),
+            ..toy.clone()
+        };
+        assert_eq!(
+            check_group(&wrong_order).unwrap_err().reason,
+            "q does not divide p - 1"
+        );
+
+        // a key from another group
+        let other = KeyPair::generate(&toy);
+        let auth = AuthImpl {
+            server_key: Some(other),
+            ..AuthImpl::default()
+        };
+        assert_eq!(
+            run(&auth, &zkp).unwrap_err(),
+            SelfTestError {
+                check: "server key",
+                reason: "the key pair does not belong to this group"
+            }
+        );
+    }
+}
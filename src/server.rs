@@ -1,154 +1,1165 @@
-use tonic::{transport::Server, Code, Request, Response, Status};
-include!("./zkp_auth.rs");
-use auth_server::{Auth, AuthServer};
-use num_bigint::BigUint;
-use std::collections::HashMap;
-use std::sync::Mutex;
-use zkp_chaum_pedersen::ZKP;
-
-#[derive(Debug, Default)]
-pub struct AuthImpl {
-    pub user_info: Mutex<HashMap<String, UserInfo>>,
-    pub auth_id_to_user: Mutex<HashMap<String, String>>,
-}
-
-#[derive(Debug, Default)]
-pub struct UserInfo {
-    // registration
-    pub user_name: String,
-    pub y1: BigUint,
-    pub y2: BigUint,
-
-    // authentication challenge
-    pub r1: BigUint,
-    pub r2: BigUint,
-
-    // verification
-    pub c: BigUint,
-    pub s: BigUint,
-    pub session_id: String,
-}
-
-#[tonic::async_trait]
-impl Auth for AuthImpl {
-    async fn register(
-        &self,
-        request: Request<RegisterRequest>,
-    ) -> Result<Response<RegisterResponse>, Status> {
-        println!("Processing register request: {:?}", request);
-
-        let request = request.into_inner();
-        let user_info = UserInfo {
-            user_name: request.user.clone(),
-            y1: BigUint::from_bytes_be(&request.y1),
-            y2: BigUint::from_bytes_be(&request.y2),
-            ..UserInfo::default()
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::server::NamedService;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{Certificate, Identity, Server, ServerTlsConfig};
+use zkp_chaum_pedersen::access::{AccessList, Cidr};
+use zkp_chaum_pedersen::admission::Admission;
+use zkp_chaum_pedersen::attestation::Attestation;
+use zkp_chaum_pedersen::audit::{self, AuditSink, Rotation};
+use zkp_chaum_pedersen::challenge::{ChallengeSource, HashBound, UniformRandom};
+use zkp_chaum_pedersen::challenge_pool::{ChallengePool, Pooled};
+#[cfg(unix)]
+use zkp_chaum_pedersen::daemon;
+use zkp_chaum_pedersen::grants::GrantPolicy;
+use zkp_chaum_pedersen::groups::{self, Groups};
+use zkp_chaum_pedersen::keys::{KeyPair, PublicKey};
+use zkp_chaum_pedersen::lockout::{
+    Backoff, FileLockouts, LockoutPolicy, LockoutStore, Reconnecting,
+};
+use zkp_chaum_pedersen::offline::{ChallengeFile, ProofFile};
+use zkp_chaum_pedersen::preflight;
+use zkp_chaum_pedersen::probes;
+use zkp_chaum_pedersen::proto::{v2, AuthServer};
+use zkp_chaum_pedersen::quota::QuotaPolicy;
+use zkp_chaum_pedersen::recovery;
+use zkp_chaum_pedersen::request_id::RequestIdLayer;
+use zkp_chaum_pedersen::rng;
+use zkp_chaum_pedersen::sealing::StoreKey;
+use zkp_chaum_pedersen::second_factor::{SecondFactor, Totp};
+use zkp_chaum_pedersen::self_test;
+use zkp_chaum_pedersen::service::AuthImpl;
+use zkp_chaum_pedersen::service_v2::AuthV2;
+use zkp_chaum_pedersen::settings::{Settings, SettingsFile, SettingsSource};
+use zkp_chaum_pedersen::snapshot::UserSnapshot;
+use zkp_chaum_pedersen::state::{
+    self, IdLength, LimitPolicy, Retention, SessionLimit, UserInfo, UserStore,
+};
+use zkp_chaum_pedersen::tls::{self, CertIdentity};
+use zkp_chaum_pedersen::username::{NameHasher, UsernamePolicy};
+use zkp_chaum_pedersen::verification_cache::VerificationCache;
+use zkp_chaum_pedersen::verifier;
+use zkp_chaum_pedersen::workers::WorkerPool;
+
+// heap profiles on the probe address, see profiling
+#[cfg(all(feature = "profiling", unix))]
+#[global_allocator]
+static ALLOCATOR: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[derive(Parser, Debug)]
+#[command(about = "Chaum-Pedersen ZKP authentication server")]
+struct Args {
+    /// key pair the server proves its identity with (mutual authentication),
+    /// created together with a `.pub.json` public key file if it doesn't exist
+    #[arg(long)]
+    key_file: Option<PathBuf>,
+
+    /// PEM certificate chain to serve TLS with, logins are then bound to the
+    /// TLS connection they are made on
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// PEM CA certificates client certificates are checked against; with it
+    /// every connection must present one (mutual TLS)
+    #[arg(long, requires = "tls_cert")]
+    tls_client_ca: Option<PathBuf>,
+
+    /// the user a registration, login or recovery names must be one of these
+    /// names of the connection's client certificate
+    #[arg(long, value_enum, requires = "tls_client_ca")]
+    client_cert_identity: Option<ClientCertIdentity>,
+
+    /// reject registrations that don't use a salted verifier (`client --salted`)
+    #[arg(long)]
+    require_salted_verifier: bool,
+
+    /// seed every random draw (nonces, challenges, ids) so runs are reproducible;
+    /// for tests only, a seeded run has no secrets. refused with TLS, a key
+    /// file or a store key, which only a real deployment has
+    #[arg(
+        long,
+        conflicts_with_all = ["tls_cert", "key_file", "store_key_file", "username_hmac_key_file"]
+    )]
+    seed: Option<u64>,
+
+    /// check the group parameters, run a prove/verify round trip for every
+    /// enabled proof system and probe the state stores before binding the port,
+    /// exiting on the first failure
+    #[arg(long)]
+    self_test: bool,
+
+    /// realm the sessions are issued in, reported by GetSessionInfo
+    #[arg(long, default_value = "default")]
+    realm: String,
+
+    /// JSON file of the scopes and claims sessions are issued with, per realm
+    /// and per user ({"realms": {..}, "users": {"alice": {"scopes": [..],
+    /// "claims": {..}}}}), reported by ValidateSession and GetSessionInfo
+    #[arg(long)]
+    grants_file: Option<PathBuf>,
+
+    /// JSON file of the users, sessions and logins a minute each realm may
+    /// have ({"realms": {"acme": {"max_users": 100, "max_sessions": 50,
+    /// "max_logins_per_minute": 600}}}); what goes past one is refused with
+    /// RESOURCE_EXHAUSTED and counted on the probe address's /metrics.
+    /// reread with --grants-file
+    #[arg(long)]
+    quotas_file: Option<PathBuf>,
+
+    /// random bytes in each auth ID and session ID (URL-safe base64), at least 16
+    #[arg(long, default_value_t = state::ID_BYTES)]
+    id_bytes: usize,
+
+    /// file with one admin token per line, a request carrying
+    /// `authorization: Bearer <token>` with one of them may read any session
+    /// with GetSessionInfo, export or import the server state and manage decoy
+    /// accounts
+    #[arg(long)]
+    admin_token_file: Option<PathBuf>,
+
+    /// network peers may connect from (e.g. 10.0.0.0/8), may be repeated;
+    /// without one every peer that isn't denied may connect
+    #[arg(long)]
+    allow_cidr: Vec<Cidr>,
+
+    /// network peers may not connect from, may be repeated; checked before
+    /// --allow-cidr
+    #[arg(long)]
+    deny_cidr: Vec<Cidr>,
+
+    /// file with one decoy user name per line: logins to them succeed as
+    /// usual and are reported by ListDecoyHits
+    #[arg(long)]
+    decoy_users_file: Option<PathBuf>,
+
+    /// answer a login for a user name nobody registered like one for a real
+    /// user with a wrong password: a challenge is issued (salted when
+    /// --require-salted-verifier is set) and the answer is verified against
+    /// fixed values before it is refused, so neither the reply nor its timing
+    /// tells which names exist
+    #[arg(long)]
+    conceal_unknown_users: bool,
+
+    /// weakest PBKDF2 a salted verifier may be registered with (iterations)
+    #[arg(long, default_value_t = verifier::KdfPolicy::default().min_pbkdf2_iterations)]
+    min_pbkdf2_iterations: u32,
+
+    /// weakest Argon2id a salted verifier may be registered with (memory in KiB)
+    #[arg(long, default_value_t = verifier::KdfPolicy::default().min_argon2_memory_kib)]
+    min_argon2_memory_kib: u32,
+
+    /// weakest Argon2id a salted verifier may be registered with (passes)
+    #[arg(long, default_value_t = verifier::KdfPolicy::default().min_argon2_passes)]
+    min_argon2_passes: u32,
+
+    /// shortest user name that may be registered (characters)
+    #[arg(long, default_value_t = UsernamePolicy::default().min_len)]
+    username_min_len: usize,
+
+    /// longest user name that may be registered (characters)
+    #[arg(long, default_value_t = UsernamePolicy::default().max_len)]
+    username_max_len: usize,
+
+    /// keep user names as typed instead of case folding them, so "Alice" and
+    /// "alice" are different accounts (clients need the same flag)
+    #[arg(long)]
+    username_case_sensitive: bool,
+
+    /// user name nobody may register, compared after normalization; may be
+    /// repeated or comma separated
+    #[arg(long, value_delimiter = ',')]
+    reserved_username: Vec<String>,
+
+    /// require a TOTP code with every login: file with one "user base32-secret"
+    /// pair per line (RFC 6238, SHA-1, 6 digits, 30 s); users not in it can't log in
+    #[arg(long)]
+    totp_secrets_file: Option<PathBuf>,
+
+    /// public key (`.pub.json`) of another instance whose bundles ImportState
+    /// accepts, may be repeated; the server's own bundles are always accepted
+    #[arg(long)]
+    trusted_bundle_key: Vec<PathBuf>,
+
+    /// how the server picks its challenges
+    #[arg(long, value_enum, default_value_t = ChallengeKind::Uniform)]
+    challenge_source: ChallengeKind,
+
+    /// user snapshot (see the migrate binary) whose registrations are loaded
+    /// at startup
+    #[arg(long)]
+    users_file: Option<PathBuf>,
+
+    /// file with a 32-byte AES-256-GCM key (64 hex digits) that sealed the
+    /// --users-file snapshot; a plain snapshot still loads with it
+    #[arg(long)]
+    store_key_file: Option<PathBuf>,
+
+    /// file with a key of at least 32 bytes to store user names as
+    /// HMAC-SHA256 hashes under, so snapshots, the users of exported bundles,
+    /// lockout records and audit files don't list the users; sessions and
+    /// devices (and the owners of sessions in bundles) keep names in the
+    /// clear. the same key must be given on every start
+    #[arg(long)]
+    username_hmac_key_file: Option<PathBuf>,
+
+    /// threads login answers are verified on, off the runtime accepting
+    /// connections; 0 verifies them inline
+    #[arg(long, default_value_t = 0)]
+    verify_workers: usize,
+
+    /// answers that may wait for a --verify-workers thread, a login arriving
+    /// when they are all taken is turned away with RESOURCE_EXHAUSTED
+    #[arg(long, default_value_t = 64)]
+    verify_queue: usize,
+
+    /// p99 verification latency, in milliseconds, to hold logins under: past
+    /// half of it registrations are turned away, past it new logins too.
+    /// needs --verify-workers
+    #[arg(long)]
+    verify_latency_target_ms: Option<u64>,
+
+    /// challenges per group and auth_ids drawn ahead of time by a background
+    /// task, for bursts of logins; 0 draws each one when it's asked for. with
+    /// --challenge-source hash-bound only the auth_ids are
+    #[arg(long, default_value_t = 0)]
+    challenge_pool_size: usize,
+
+    /// how long a session ID stays valid after login or refresh, in seconds
+    #[arg(long, default_value_t = 3600, value_parser = clap::value_parser!(u64).range(1..))]
+    session_ttl: u64,
+
+    /// JSON file overriding --session-ttl, --max-sessions-per-user and
+    /// --session-limit-policy ({"session_ttl_seconds": 900,
+    /// "max_sessions_per_user": 3, "session_limit_policy": "evict-oldest"}),
+    /// reread with --grants-file on SIGHUP or `client reload-settings`
+    #[arg(long)]
+    settings_file: Option<PathBuf>,
+
+    /// answers a login challenge takes before it is used up; the answer after
+    /// the last one allowed is refused with PERMISSION_DENIED
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    max_answers_per_challenge: u32,
+
+    /// failed logins in a row that lock an account for --lockout-seconds; a
+    /// locked account's challenges and answers are refused with
+    /// PERMISSION_DENIED. no lockout when not given
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    lockout_threshold: Option<u32>,
+
+    /// seconds an account stays locked after --lockout-threshold failures
+    #[arg(long, default_value_t = 900, requires = "lockout_threshold")]
+    lockout_seconds: u64,
+
+    /// JSON file the failed logins are counted in, so a restart doesn't
+    /// reset them and replicas sharing the file lock an account together;
+    /// counted in memory when not given
+    #[arg(long, requires = "lockout_threshold")]
+    lockout_file: Option<PathBuf>,
+
+    /// longest wait, in seconds, between the tries to reach a --lockout-file
+    /// that is down; logins are refused with UNAVAILABLE until it is back
+    #[arg(
+        long,
+        default_value_t = 30,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "lockout_file"
+    )]
+    lockout_store_max_backoff: u64,
+
+    /// anonymous tickets (IssueTickets) a session may be issued, evaluated
+    /// under the server key (needs --key-file); no tickets when not given
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    tickets_per_session: Option<u32>,
+
+    /// seconds an account waits after a RecoverAccount before it can be
+    /// recovered again
+    #[arg(long, default_value_t = 86400)]
+    recovery_cooldown: u64,
+
+    /// seconds a user deleted with DeleteUser can still be restored; after
+    /// that the registration, devices and audit events of the user are erased
+    #[arg(long, default_value_t = 30 * 86400)]
+    deleted_user_retention: u64,
+
+    /// where else audit events are written, may be repeated:
+    /// file:PATH (JSON lines), syslog:HOST:PORT (UDP), syslog:/dev/log or
+    /// nats://HOST:PORT/SUBJECT
+    #[arg(long)]
+    audit_sink: Vec<String>,
+
+    /// bytes a file: audit sink grows to before it is rotated
+    #[arg(long, default_value_t = 10 * 1024 * 1024, value_parser = clap::value_parser!(u64).range(1..))]
+    audit_file_max_bytes: u64,
+
+    /// seconds after which a file: audit sink is rotated at its next event,
+    /// however small; by size only when not given
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    audit_file_max_age: Option<u64>,
+
+    /// rotated files a file: audit sink keeps (PATH.1 is the newest)
+    #[arg(long, default_value_t = 5)]
+    audit_file_keep: usize,
+
+    /// gzip the files a file: audit sink rotates out (PATH.1.gz)
+    #[arg(long)]
+    audit_file_compress: bool,
+
+    /// sessions a user may hold at once, unlimited when not given
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_sessions_per_user: Option<u32>,
+
+    /// address to answer plain HTTP GET /healthz and /readyz on, for probes
+    /// that can't speak gRPC health (e.g. 0.0.0.0:8080); /readyz fails while
+    /// draining
+    #[arg(long)]
+    probe_addr: Option<std::net::SocketAddr>,
+
+    /// start in drain mode (no new registrations or logins, see SetDrain),
+    /// e.g. for a standby; lifted with `client drain --resume`
+    #[arg(long)]
+    drain: bool,
+
+    /// what a login beyond --max-sessions-per-user does
+    #[arg(long, value_enum, default_value_t = SessionLimitKind::RejectNew)]
+    session_limit_policy: SessionLimitKind,
+
+    /// application id of this deployment, mixed into every challenge
+    /// derivation so proofs made for it never verify on another deployment;
+    /// clients pass the same --app-id
+    #[arg(long, default_value = "")]
+    app_id: String,
+
+    /// fork into the background once started, e.g. for a Type=forking unit
+    #[cfg(unix)]
+    #[arg(long)]
+    daemonize: bool,
+
+    /// file the daemon's output is appended to, dropped without one
+    #[cfg(unix)]
+    #[arg(long, requires = "daemonize")]
+    daemon_log: Option<PathBuf>,
+
+    /// file to write the server's process ID to, locked while it runs and
+    /// removed when it stops
+    #[cfg(unix)]
+    #[arg(long)]
+    pid_file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ChallengeKind {
+    /// uniformly random below q
+    Uniform,
+    /// H(key, user, auth_id, r1, r2, nonce commitment) mod q, under a key
+    /// drawn at startup
+    HashBound,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ClientCertIdentity {
+    /// the CN of the certificate's subject
+    CommonName,
+    /// a DNS name of its subject alternative names
+    DnsName,
+    /// a URI of its subject alternative names, e.g. a SPIFFE ID
+    Uri,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SessionLimitKind {
+    /// refuse the login with RESOURCE_EXHAUSTED
+    RejectNew,
+    /// end the user's oldest sessions to make room
+    EvictOldest,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// verify a proof produced offline by `client prove`, without starting the server
+    VerifyFile {
+        /// challenge file the proof was made for
+        #[arg(long)]
+        challenge_file: PathBuf,
+
+        /// proof file to verify, may be given more than once
+        #[arg(long, required = true)]
+        proof: Vec<PathBuf>,
+
+        /// remember up to this many verification results, so a proof given
+        /// more than once (replaying an archive) is only checked once; 0 is off
+        #[arg(long, default_value_t = 0)]
+        cache_size: usize,
+    },
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&contents).map_err(|e| e.to_string())
+}
+
+// the user's registration in --users-file, read the way the server reads it
+fn registered_user(args: &Args, users_file: &Path, user_name: &str) -> Option<UserInfo> {
+    let users = match &args.username_hmac_key_file {
+        Some(path) => match NameHasher::load(path) {
+            Ok(names) => UserStore::with_hashed_names(names),
+            Err(e) => {
+                eprintln!("❌ Failed to read user name key {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => UserStore::default(),
+    };
+    let store_key = args.store_key_file.as_ref().map(|path| {
+        StoreKey::load(path).unwrap_or_else(|e| {
+            eprintln!("❌ Failed to read store key {}: {}", path.display(), e);
+            std::process::exit(1);
+        })
+    });
+    if let Err(e) = UserSnapshot::load_with(users_file, store_key.as_ref())
+        .and_then(|snapshot| snapshot.restore(&users))
+    {
+        eprintln!("❌ Failed to load users {}: {}", users_file.display(), e);
+        std::process::exit(1);
+    }
+    users.get(user_name)
+}
+
+fn run_verify_file(args: &Args, challenge_file: &Path, proof_files: &[PathBuf], cache_size: usize) {
+    let challenge: ChallengeFile = match read_json(challenge_file) {
+        Ok(challenge) => challenge,
+        Err(e) => {
+            eprintln!(
+                "❌ Failed to read challenge file {}: {}",
+                challenge_file.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    };
+    // a proof is checked against the registered y1/y2, in the user's group,
+    // under --app-id: y1/y2 and the group from the challenge file, or else
+    // from the user's registration in --users-file
+    let mut challenge = challenge;
+    if (challenge.y1.is_none() || challenge.y2.is_none())
+        && let Some(users_file) = &args.users_file
+    {
+        let Some(user) = registered_user(args, users_file, &challenge.user) else {
+            eprintln!(
+                "❌ User {} is not registered in {}",
+                challenge.user,
+                users_file.display()
+            );
+            std::process::exit(1);
         };
-        let user_info_hashmap = &mut self.user_info.lock().unwrap();
-        user_info_hashmap.insert(request.user.clone(), user_info);
+        if let Some(group) = &challenge.group
+            && groups::resolve(group) != groups::resolve(&user.group)
+        {
+            eprintln!(
+                "❌ Challenge is in group {}, user {} is registered in {}",
+                group, user.user_name, user.group
+            );
+            std::process::exit(1);
+        }
+        challenge = ChallengeFile {
+            group: Some(user.group.clone()),
+            ..challenge.with_public_key(&user.y1, &user.y2)
+        };
+    }
+    let Some(zkp) = challenge.zkp() else {
+        eprintln!(
+            "❌ Unknown group {} in {}",
+            challenge.group.as_deref().unwrap_or_default(),
+            challenge_file.display()
+        );
+        std::process::exit(1);
+    };
+    let application = args.app_id.as_bytes();
+    let cache = VerificationCache::new(cache_size);
+    let mut rejected = 0;
+    for proof_file in proof_files {
+        let proof: ProofFile = match read_json(proof_file) {
+            Ok(proof) => proof,
+            Err(e) => {
+                eprintln!(
+                    "❌ Failed to read proof file {}: {}",
+                    proof_file.display(),
+                    e
+                );
+                std::process::exit(1);
+            }
+        };
+        match proof.verify_cached(&zkp, application, &challenge, &cache) {
+            Ok(()) => println!("✅ Proof verified for user: {}", proof.user),
+            Err(e) => {
+                eprintln!("❌ Proof {} rejected: {}", proof_file.display(), e);
+                rejected += 1;
+            }
+        }
+    }
+    if cache.hits() > 0 {
+        println!("💾 {} verifications answered from the cache", cache.hits());
+    }
+    if rejected > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    // the runtime's threads don't survive a fork, so it starts afterwards
+    #[cfg(unix)]
+    if args.daemonize
+        && args.command.is_none()
+        && let Err(e) = daemon::daemonize(args.daemon_log.as_deref())
+    {
+        eprintln!("❌ {}", e);
+        std::process::exit(1);
+    }
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("❌ Failed to start the runtime: {}", e);
+            std::process::exit(1);
+        }
+    };
+    runtime.block_on(run(args));
+}
+
+async fn run(args: Args) {
+    if let Some(seed) = args.seed {
+        rng::seed_process(seed);
+        println!("⚠️ Deterministic mode (seed {}), for tests only", seed);
+    }
+    if let Some(Command::VerifyFile {
+        challenge_file,
+        proof,
+        cache_size,
+    }) = &args.command
+    {
+        run_verify_file(&args, challenge_file, proof, *cache_size);
+        return;
+    }
+    // held until the server stops
+    #[cfg(unix)]
+    let _pid_file = args
+        .pid_file
+        .as_deref()
+        .map(|path| match daemon::PidFile::create(path) {
+            Ok(pid_file) => pid_file,
+            Err(e) => {
+                eprintln!("❌ Failed to write pid file {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        });
 
-        Ok(Response::new(RegisterResponse {}))
+    let addr: String = "127.0.0.1:50051".to_string();
+
+    // every named group is parsed and checked here, once, and shared by the
+    // handlers from then on
+    let groups = match Groups::new() {
+        Ok(groups) => groups,
+        Err(e) => {
+            eprintln!("❌ Invalid group parameters: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // for clients to pin with --params-fingerprint
+    for name in groups::NAMES {
+        let fingerprint = groups::fingerprint(&groups.get(name).expect("named groups resolve"));
+        println!("🔏 Group {} fingerprint {}", name, hex::encode(fingerprint));
     }
+    let zkp = groups.default_group();
+    if args.tickets_per_session.is_some() && args.key_file.is_none() {
+        eprintln!("❌ --tickets-per-session needs a server key (--key-file)");
+        std::process::exit(1);
+    }
+    let server_key = match &args.key_file {
+        Some(path) if path.exists() => match KeyPair::load(&zkp, path) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                eprintln!("❌ Failed to load server key {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        Some(path) => {
+            let key = KeyPair::generate(&zkp);
+            let public_path = path.with_extension("pub.json");
+            if let Err(e) = key.save(path).and_then(|_| key.public.save(&public_path)) {
+                eprintln!("❌ Failed to save server key {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+            println!(
+                "🔑 Generated server key {} (public key: {})",
+                path.display(),
+                public_path.display()
+            );
+            Some(key)
+        }
+        // without a key file the server can't prove its identity
+        None => None,
+    };
 
-    async fn create_authentication_challenge(
-        &self,
-        request: Request<AuthenticationChallengeRequest>,
-    ) -> Result<Response<AuthenticationChallengeResponse>, Status> {
-        println!("Processing challenge request: {:?}", request);
+    let mut builder = Server::builder();
+    let mut channel_binding = None;
+    if let (Some(cert_path), Some(key_path)) = (&args.tls_cert, &args.tls_key) {
+        let identity = std::fs::read(cert_path)
+            .and_then(|cert| Ok(Identity::from_pem(cert, std::fs::read(key_path)?)));
+        let certificates = tls::load_certificates(cert_path);
+        let (identity, certificates) = match (identity, certificates) {
+            (Ok(identity), Ok(certificates)) => (identity, certificates),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("❌ Failed to load TLS certificate/key: {}", e);
+                std::process::exit(1);
+            }
+        };
+        let mut tls_config = ServerTlsConfig::new().identity(identity);
+        if let Some(ca_path) = &args.tls_client_ca {
+            match std::fs::read(ca_path) {
+                Ok(ca) => tls_config = tls_config.client_ca_root(Certificate::from_pem(ca)),
+                Err(e) => {
+                    eprintln!("❌ Failed to read client CA {}: {}", ca_path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        builder = match builder.tls_config(tls_config) {
+            Ok(builder) => builder,
+            Err(e) => {
+                eprintln!("❌ Invalid TLS configuration: {}", e);
+                std::process::exit(1);
+            }
+        };
+        channel_binding = Some(tls::server_end_point(&certificates[0]));
+        println!("🔒 TLS enabled, logins are bound to the TLS channel");
+        if args.tls_client_ca.is_some() {
+            println!("🔒 Client certificates required");
+        }
+    }
+    let client_cert_identity = args.client_cert_identity.map(|kind| match kind {
+        ClientCertIdentity::CommonName => CertIdentity::CommonName,
+        ClientCertIdentity::DnsName => CertIdentity::DnsName,
+        ClientCertIdentity::Uri => CertIdentity::Uri,
+    });
+    if let Some(kind) = client_cert_identity {
+        println!(
+            "🔒 User names must match the client certificate ({:?})",
+            kind
+        );
+    }
 
-        let request = request.into_inner();
-        let user_name = request.user.clone();
-        let user_info_hashmap = &mut self.user_info.lock().unwrap();
+    let admin_tokens = match &args.admin_token_file {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(contents) => contents
+                .lines()
+                .map(str::trim)
+                .filter(|token| !token.is_empty())
+                .map(str::to_string)
+                .collect(),
+            Err(e) => {
+                eprintln!("❌ Failed to read admin tokens {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => Vec::new(),
+    };
 
-        if let Some(user_info) = user_info_hashmap.get_mut(&user_name) {
-            user_info.r1 = BigUint::from_bytes_be(&request.r1);
-            user_info.r2 = BigUint::from_bytes_be(&request.r2);
+    let trusted_bundle_keys = args
+        .trusted_bundle_key
+        .iter()
+        .map(|path| match PublicKey::load(path) {
+            Ok(key) => key,
+            Err(e) => {
+                eprintln!("❌ Failed to read bundle key {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        })
+        .collect();
 
-            let (_, _, _, q) = ZKP::get_constants();
-            let c = ZKP::generate_random_number_below(&q);
-            let auth_id = ZKP::generate_random_string(12);
+    let id_length = match IdLength::new(args.id_bytes) {
+        Ok(id_length) => id_length,
+        Err(e) => {
+            eprintln!("❌ Invalid --id-bytes: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-            user_info.c = c.clone();
+    let challenge_pool = (args.challenge_pool_size > 0).then(|| {
+        println!(
+            "🎲 Drawing up to {} challenges per group and auth_ids ahead of time",
+            args.challenge_pool_size
+        );
+        let named: Vec<_> = groups::NAMES
+            .iter()
+            .filter_map(|name| Some((*name, groups.get(name)?)))
+            .collect();
+        Arc::new(ChallengePool::new(
+            args.challenge_pool_size,
+            id_length,
+            named.iter().map(|(name, zkp)| (*name, &**zkp)),
+        ))
+    });
+    let challenge_source: Box<dyn ChallengeSource> = match (args.challenge_source, &challenge_pool)
+    {
+        (ChallengeKind::Uniform, Some(pool)) => Box::new(Pooled(pool.clone())),
+        (ChallengeKind::Uniform, None) => Box::new(UniformRandom),
+        (ChallengeKind::HashBound, _) => Box::new(HashBound::generate()),
+    };
 
-            let auth_id_to_user = &mut self.auth_id_to_user.lock().unwrap();
-            auth_id_to_user.insert(auth_id.clone(), user_name);
+    let verifier_pool = (args.verify_workers > 0).then(|| {
+        println!(
+            "🧵 Verifying on {} threads, up to {} answers queued",
+            args.verify_workers, args.verify_queue
+        );
+        Arc::new(WorkerPool::new(args.verify_workers, args.verify_queue))
+    });
+    let admission = args.verify_latency_target_ms.map(|ms| {
+        println!(
+            "🚦 Turning registrations and then new logins away past a p99 verification latency of {}ms",
+            ms
+        );
+        Admission::new(Duration::from_millis(ms))
+    });
 
-            Ok(Response::new(AuthenticationChallengeResponse {
-                auth_id,
-                c: c.to_bytes_be(),
-            }))
-        } else {
-            Err(Status::new(
-                Code::NotFound,
-                format!("User: {} not found in the database", user_name),
+    let usernames = UsernamePolicy {
+        min_len: args.username_min_len,
+        max_len: args.username_max_len,
+        fold_case: !args.username_case_sensitive,
+        reserved: args.reserved_username,
+    };
+    let second_factor: Option<Box<dyn SecondFactor>> = match &args.totp_secrets_file {
+        Some(path) => match Totp::load_secrets(path) {
+            Ok(secrets) => {
+                println!("🔐 Requiring TOTP codes, {} users enrolled", secrets.len());
+                let secrets = secrets
+                    .into_iter()
+                    .map(|(user, secret)| (usernames.canonical(&user), secret))
+                    .collect();
+                Some(Box::new(Totp::new(secrets)))
+            }
+            Err(e) => {
+                eprintln!("❌ Failed to read TOTP secrets {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let settings_source = FileSettings {
+        session_ttl: Duration::from_secs(args.session_ttl),
+        session_limit: args.max_sessions_per_user.map(|max| SessionLimit {
+            max_per_user: max as usize,
+            policy: match args.session_limit_policy {
+                SessionLimitKind::RejectNew => LimitPolicy::RejectNew,
+                SessionLimitKind::EvictOldest => LimitPolicy::EvictOldest,
+            },
+        }),
+        settings_file: args.settings_file,
+        grants_file: args.grants_file,
+        quotas_file: args.quotas_file,
+        usernames: usernames.clone(),
+    };
+    let settings = match settings_source.load().and_then(|settings| {
+        settings.check()?;
+        Ok(settings)
+    }) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            std::process::exit(1);
+        }
+    };
+    print_settings(&settings);
+
+    let users = match &args.username_hmac_key_file {
+        Some(path) => match NameHasher::load(path) {
+            Ok(names) => {
+                println!("🙈 Storing user names as keyed hashes");
+                UserStore::with_hashed_names(names)
+            }
+            Err(e) => {
+                eprintln!("❌ Failed to read user name key {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => UserStore::default(),
+    };
+
+    let lockouts: Box<dyn LockoutStore> = match &args.lockout_file {
+        Some(path) => {
+            println!("🔒 Counting failed logins in {}", path.display());
+            let backoff = Backoff {
+                first: Duration::from_secs(1),
+                max: Duration::from_secs(args.lockout_store_max_backoff),
+            };
+            Box::new(Reconnecting::new(
+                Box::new(FileLockouts::new(path)),
+                backoff,
+                Box::default(),
             ))
         }
+        None => Box::default(),
+    };
+
+    let rotation = Rotation {
+        max_bytes: args.audit_file_max_bytes,
+        max_age: args.audit_file_max_age.map(Duration::from_secs),
+        keep: args.audit_file_keep,
+        compress: args.audit_file_compress,
+    };
+    let audit_sinks: Vec<Box<dyn AuditSink>> = args
+        .audit_sink
+        .iter()
+        .map(|spec| match audit::open(spec, rotation) {
+            Ok(sink) => {
+                println!("📝 Writing audit events to {}", sink.name());
+                sink
+            }
+            Err(e) => {
+                eprintln!("❌ Invalid --audit-sink: {}", e);
+                std::process::exit(1);
+            }
+        })
+        .collect();
+
+    let auth_impl = AuthImpl {
+        users,
+        server_key,
+        channel_binding,
+        client_cert_identity,
+        require_salted_verifier: args.require_salted_verifier,
+        realm: args.realm,
+        settings: settings.into(),
+        max_answers_per_challenge: Some(args.max_answers_per_challenge),
+        lockout: args.lockout_threshold.map(|max_failures| LockoutPolicy {
+            max_failures,
+            duration: Duration::from_secs(args.lockout_seconds),
+        }),
+        lockouts,
+        tickets_per_session: args.tickets_per_session,
+        recovery_cooldown: recovery::Cooldown(Duration::from_secs(args.recovery_cooldown)),
+        deletion_retention: Retention(Duration::from_secs(args.deleted_user_retention)),
+        audit_sinks,
+        settings_source: Some(Box::new(settings_source)),
+        admin_tokens,
+        trusted_bundle_keys,
+        kdf_policy: verifier::KdfPolicy {
+            min_pbkdf2_iterations: args.min_pbkdf2_iterations,
+            min_argon2_memory_kib: args.min_argon2_memory_kib,
+            min_argon2_passes: args.min_argon2_passes,
+        },
+        usernames,
+        second_factor,
+        challenge_source,
+        challenge_pool: challenge_pool.clone(),
+        id_length,
+        groups,
+        verifier_pool,
+        admission,
+        application: args.app_id.into_bytes(),
+        conceal_unknown_users: args.conceal_unknown_users,
+        ..AuthImpl::default()
+    };
+
+    println!("🧾 Attestation {}", Attestation::of(&auth_impl).line());
+
+    if let Some(path) = &args.decoy_users_file {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                let decoys: Vec<&str> = contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|user| !user.is_empty())
+                    .collect();
+                for user in &decoys {
+                    let user = auth_impl.usernames.canonical(user);
+                    auth_impl.decoys.mark(&user, true);
+                }
+                println!("🪤 Watching {} decoy accounts", decoys.len());
+            }
+            Err(e) => {
+                eprintln!("❌ Failed to read decoy users {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
     }
 
-    async fn verify_authentication(
-        &self,
-        request: Request<AuthenticationAnswerRequest>,
-    ) -> Result<Response<AuthenticationAnswerResponse>, Status> {
-        println!("Processing verification request: {:?}", request);
-
-        let request = request.into_inner();
-        let auth_id = request.auth_id.clone();
-        let user_info_hashmap = &mut self.auth_id_to_user.lock().unwrap();
-
-        if let Some(user_name) = user_info_hashmap.get(&auth_id) {
-            let user_info_hashmap = &mut self.user_info.lock().unwrap();
-            let user_info = user_info_hashmap.get_mut(user_name).unwrap();
-
-            // verification
-            let s = request.s.clone();
-            let (g, h, p, q) = ZKP::get_constants();
-            let zkp = ZKP { p, q, g, h };
-            let verification = zkp.verify(
-                &user_info.r1,
-                &user_info.r2,
-                &user_info.y1,
-                &user_info.y2,
-                &user_info.c,
-                &BigUint::from_bytes_be(&s),
-            );
-            println!("verification: {}", verification);
-
-            if verification {
-                let session_id = ZKP::generate_random_string(12);
-                user_info.session_id = session_id.clone();
-                Ok(Response::new(AuthenticationAnswerResponse { session_id }))
-            } else {
-                Err(Status::new(
-                    Code::PermissionDenied,
-                    format!("AuthId: {} is not verified", auth_id),
-                ))
-            }
-        } else {
-            Err(Status::new(
-                Code::NotFound,
-                format!("AuthId: {} not found in the database", auth_id),
-            ))
+    let store_key = args.store_key_file.as_ref().map(|path| {
+        StoreKey::load(path).unwrap_or_else(|e| {
+            eprintln!("❌ Failed to read store key {}: {}", path.display(), e);
+            std::process::exit(1);
+        })
+    });
+    if let Some(path) = &args.users_file {
+        match UserSnapshot::load_with(path, store_key.as_ref())
+            .and_then(|snapshot| snapshot.restore(&auth_impl.users))
+        {
+            Ok(count) => println!("👥 Loaded {} users from {}", count, path.display()),
+            Err(e) => {
+                eprintln!("❌ Failed to load users {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
         }
     }
-}
 
-#[tokio::main]
-async fn main() {
-    let addr: String = "127.0.0.1:50051".to_string();
-    let auth_impl = AuthImpl::default();
+    // every problem at once, before anything is served
+    let problems = preflight::check(&auth_impl);
+    if !problems.is_empty() {
+        for problem in &problems {
+            eprintln!("❌ Invalid configuration, {}", problem);
+        }
+        std::process::exit(1);
+    }
+
+    if args.self_test {
+        match self_test::run(&auth_impl, &zkp) {
+            Ok(passed) => {
+                for check in passed {
+                    println!("✅ Self-test: {}", check);
+                }
+            }
+            Err(e) => {
+                eprintln!("❌ Self-test failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let access = AccessList {
+        allow: args.allow_cidr,
+        deny: args.deny_cidr,
+    };
+    if !access.is_empty() {
+        println!(
+            "🧱 Access lists: {} allowed, {} denied networks",
+            access.allow.len(),
+            access.deny.len()
+        );
+    }
 
-    println!("🚀 Starting server on {}...", addr);
+    let incoming = match activated_listener() {
+        Some(listener) => {
+            println!("🧦 Serving on the socket systemd passed");
+            listener
+        }
+        None => {
+            println!("🚀 Starting server on {}...", addr);
+            TcpIncoming::bind(addr.parse().expect("Invalid address"))
+        }
+    };
+    let incoming = match incoming {
+        Ok(incoming) => incoming.with_nodelay(Some(true)),
+        Err(e) => {
+            eprintln!("❌ Failed to start server: {}", e);
+            eprintln!("💡 Try using a different port or check if the address is available");
+            std::process::exit(1);
+        }
+    };
+    // bound before the server says it is ready, so a probe port that is
+    // taken stops the start rather than a running server
+    let probe_listener = match args.probe_addr {
+        Some(probe_addr) => match tokio::net::TcpListener::bind(probe_addr).await {
+            Ok(listener) => Some((probe_addr, listener)),
+            Err(e) => {
+                eprintln!("❌ Failed to listen for probes on {}: {}", probe_addr, e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
     println!("📡 Server is ready to accept connections");
+    #[cfg(unix)]
+    {
+        daemon::notify_ready();
+        if let Some(interval) = daemon::watchdog_interval() {
+            println!("🐕 Notifying the systemd watchdog every {:?}", interval);
+            tokio::spawn(daemon::keep_watchdog_fed(interval));
+        }
+    }
 
-    match Server::builder()
-        .add_service(AuthServer::new(auth_impl))
-        .serve(addr.parse().expect("Invalid address"))
+    if args.drain {
+        auth_impl.drain.set(true);
+        println!("🚧 Draining: no new registrations or logins");
+    }
+    // grpc.health.v1, NOT_SERVING while draining
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    tokio::spawn(auth_impl.drain.report_health(
+        health_reporter,
+        &[
+            <AuthServer<AuthImpl> as NamedService>::NAME,
+            <v2::AuthServer<AuthV2> as NamedService>::NAME,
+        ],
+    ));
+
+    // v1 and v2 share one state
+    let auth_impl = Arc::new(auth_impl);
+    #[cfg(unix)]
+    tokio::spawn(reload_on_hangup(auth_impl.clone()));
+    tokio::spawn(housekeeping(auth_impl.clone()));
+    if let Some(pool) = challenge_pool {
+        tokio::spawn(pool.keep_filled());
+    }
+    if let Some((probe_addr, listener)) = probe_listener {
+        println!(
+            "🩺 HTTP probes on http://{}/healthz and /readyz",
+            probe_addr
+        );
+        tokio::spawn(probes::serve(listener, auth_impl.clone()));
+    }
+    match builder
+        .layer(RequestIdLayer)
+        .add_service(health_service)
+        .add_service(AuthServer::from_arc(auth_impl.clone()))
+        .add_service(v2::AuthServer::new(AuthV2(auth_impl)))
+        .serve_with_incoming_shutdown(access.filter_incoming(incoming), shutdown_signal())
         .await
     {
-        Ok(_) => println!("✅ Server stopped gracefully"), // never executed
+        Ok(_) => println!("✅ Server stopped gracefully"),
+        Err(e) => eprintln!("❌ Server failed: {}", e),
+    }
+}
+
+// the socket systemd passed, if there is one (unix only)
+fn activated_listener() -> Option<std::io::Result<TcpIncoming>> {
+    #[cfg(unix)]
+    match daemon::activated_listener() {
+        Ok(Some(listener)) => {
+            return Some(tokio::net::TcpListener::from_std(listener).map(TcpIncoming::from));
+        }
+        Ok(None) => {}
+        Err(e) => return Some(Err(e)),
+    }
+    None
+}
+
+// Ctrl+C, or SIGTERM as systemctl stop sends; in-flight calls finish first
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+        daemon::notify_stopping();
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+    println!("🛑 Shutting down, finishing the calls in flight");
+}
+
+// the server's flags, with the settings and grants files read over them on
+// every load
+#[derive(Debug)]
+struct FileSettings {
+    session_ttl: Duration,
+    session_limit: Option<SessionLimit>,
+    settings_file: Option<PathBuf>,
+    grants_file: Option<PathBuf>,
+    quotas_file: Option<PathBuf>,
+    // grants are keyed by canonical user name
+    usernames: UsernamePolicy,
+}
+
+impl SettingsSource for FileSettings {
+    fn load(&self) -> Result<Settings, String> {
+        let mut settings = Settings {
+            session_ttl: self.session_ttl,
+            session_limit: self.session_limit,
+            grants: GrantPolicy::default(),
+            quotas: QuotaPolicy::default(),
+        };
+        if let Some(path) = &self.settings_file {
+            SettingsFile::load(path)
+                .map_err(|e| format!("Failed to read settings {}: {}", path.display(), e))?
+                .apply(&mut settings);
+        }
+        if let Some(path) = &self.grants_file {
+            let policy = GrantPolicy::load(path)
+                .map_err(|e| format!("Failed to read grants {}: {}", path.display(), e))?;
+            settings.grants = GrantPolicy {
+                users: policy
+                    .users
+                    .into_iter()
+                    .map(|(user, grant)| (self.usernames.canonical(&user), grant))
+                    .collect(),
+                ..policy
+            };
+        }
+        if let Some(path) = &self.quotas_file {
+            settings.quotas = QuotaPolicy::load(path)
+                .map_err(|e| format!("Failed to read quotas {}: {}", path.display(), e))?;
+        }
+        Ok(settings)
+    }
+}
+
+fn print_settings(settings: &Settings) {
+    println!("⏳ Sessions last {}s", settings.session_ttl.as_secs());
+    if let Some(limit) = settings.session_limit {
+        println!(
+            "🎟️ At most {} sessions per user ({:?} beyond that)",
+            limit.max_per_user, limit.policy
+        );
+    }
+    if settings.grants != GrantPolicy::default() {
+        println!(
+            "🎫 Granting scopes and claims to {} realms and {} users",
+            settings.grants.realms.len(),
+            settings.grants.users.len()
+        );
+    }
+    for (realm, quota) in &settings.quotas.realms {
+        println!("🧾 Quota of realm {}: {:?}", realm, quota);
+    }
+}
+
+// the users deleted longer than the retention ago and the challenges left
+// unanswered past their TTL, checked every minute
+async fn housekeeping(auth_impl: Arc<AuthImpl>) {
+    let mut minutes = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        minutes.tick().await;
+        auth_impl.erase_deleted_users();
+        auth_impl.sweep_challenges();
+    }
+}
+
+// SIGHUP rereads the settings, like the ReloadSettings RPC
+
+#[cfg(unix)]
+async fn reload_on_hangup(auth_impl: Arc<AuthImpl>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
         Err(e) => {
-            eprintln!("❌ Failed to start server: {}", e);
-            eprintln!("💡 Try using a different port or check if the address is available");
+            eprintln!("❌ Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        match auth_impl.reload_settings() {
+            Ok(settings) => {
+                println!("🔄 Settings reloaded on SIGHUP");
+                print_settings(&settings);
+            }
+            Err(e) => eprintln!("❌ Settings not reloaded, keeping the old ones: {}", e),
         }
     }
 }
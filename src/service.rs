@@ -0,0 +1,2195 @@
+use crate::admission::{Admission, Call};
+use crate::audit::AuditSink;
+use crate::blinding::{self, SealKey};
+use crate::bundle::{Bundle, BundleError};
+use crate::challenge::{ChallengeRequest, ChallengeSource};
+use crate::challenge_pool::ChallengePool;
+use crate::clock::Clock;
+use crate::codec::{self, WireCodec};
+use crate::cross_group;
+use crate::drain::Drain;
+use crate::groups;
+use crate::keys::{KeyPair, PublicKey};
+use crate::lockout::{BoundedLockouts, LockoutPolicy, LockoutStore};
+use crate::quota::{LoginRate, QuotaKind, QuotaMetrics};
+use crate::receipt::{self, Receipt, Standing};
+use crate::recovery::{self, NewVerifier};
+use crate::request_id;
+use crate::schnorr;
+use crate::second_factor::{SecondFactor, SecondFactorAttempt, SecondFactorError};
+use crate::secret::{wipe_biguint, SecretBigUint};
+use crate::session_key::{server_key_share, SessionKey};
+use crate::settings::{LiveSettings, Settings, SettingsSource};
+use crate::state::{
+    self, AuditKind, AuditLog, Challenge, ChallengeStore, ClientInfo, DecoyStore, DeviceStore,
+    IdLength, LimitPolicy, Retention, Session, SessionRefusal, SessionStore, TicketStore, UserInfo,
+    UserRefusal, UserStore,
+};
+use crate::tickets::{self, Ticket};
+use crate::tls::{self, CertIdentity};
+use crate::username::UsernamePolicy;
+use crate::verifier::KdfPolicy;
+use crate::workers::{PoolError, WorkerPool};
+use crate::zkp_auth::auth_server::Auth;
+use crate::zkp_auth::*;
+use crate::{Proof, ZKP};
+use num_bigint::BigUint;
+use prost::Message;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Request, Response, Status};
+
+// longest device ID, name or platform a client may send
+const MAX_DEVICE_FIELD: usize = 128;
+
+// the v1 message of a registration or login from a revoked device, which v2
+// tells apart from other PERMISSION_DENIED failures by it
+pub(crate) const DEVICE_REVOKED: &str = "Device has been revoked for this user";
+
+// the v1 message of a login refused under LimitPolicy::RejectNew
+pub(crate) const SESSION_LIMIT_REACHED: &str = "User already has the most sessions allowed";
+
+// the v1 message of an answer to a challenge that has taken all the answers
+// it allows
+pub(crate) const ANSWERS_EXHAUSTED: &str = "Challenge has taken all the answers it allows";
+
+// the v1 message of a request for a user the client certificate doesn't name
+pub(crate) const CLIENT_CERT_MISMATCH: &str = "User name doesn't match the client certificate";
+
+// the v1 message of a challenge or answer for an account locked by failed
+// logins, followed by the seconds left
+pub(crate) const ACCOUNT_LOCKED: &str = "Account is locked after too many failed logins";
+
+// the v1 message of a registration, challenge or login past a quota of the
+// realm, followed by which one
+pub(crate) const QUOTA_EXCEEDED: &str = "Realm is over its quota";
+
+// the v1 message of a challenge whose registration receipt the server no
+// longer stands by, followed by whether the registration is gone or changed
+pub(crate) const REGISTRATION_LOST: &str = "Server no longer has the receipted registration";
+
+// the v1 message of a registration or challenge refused while draining
+pub(crate) const SERVER_DRAINING: &str = "Server is draining, try another instance";
+
+// the v1 message of a registration or challenge turned away by admission
+// control, followed by the load
+pub(crate) const SERVER_OVERLOADED: &str = "Server is behind on verifications, try again later";
+
+// the v1 message of a registration under the name of a user deleted but not
+// erased yet
+pub(crate) const USER_DELETED: &str = "User is deleted until restored or erased";
+
+#[derive(Debug, Default)]
+pub struct AuthImpl {
+    pub users: UserStore,
+    // pending challenges by auth_id
+    pub challenges: ChallengeStore,
+    pub sessions: SessionStore,
+    // session TTL, session limit and grants, swapped by reload_settings
+    pub settings: LiveSettings,
+    // where reload_settings reads them from, no reloads without one
+    pub settings_source: Option<Box<dyn SettingsSource>>,
+    pub server_key: Option<KeyPair>,
+    // tls-server-end-point binding of the certificate the server presents
+    pub channel_binding: Option<Vec<u8>>,
+    // the names of the client certificate (server --tls-client-ca) the user
+    // of a registration, login or recovery has to be one of, see
+    // check_client_cert. no binding when None
+    pub client_cert_identity: Option<CertIdentity>,
+    pub require_salted_verifier: bool,
+    // uniform random unless the deployment picks another source
+    pub challenge_source: Box<dyn ChallengeSource>,
+    // realm sessions are issued in, reported by GetSessionInfo
+    pub realm: String,
+    // bearer tokens that may read any session with GetSessionInfo and
+    // export or import the server state
+    pub admin_tokens: Vec<String>,
+    // keys of other instances whose bundles ImportState takes, besides the
+    // server's own
+    pub trusted_bundle_keys: Vec<PublicKey>,
+    // weakest KDF parameters a salted verifier may be registered with
+    pub kdf_policy: KdfPolicy,
+    // what a user name may be, and the canonical form it is stored under
+    pub usernames: UsernamePolicy,
+    // asked once the password is proven, before the session is issued
+    pub second_factor: Option<Box<dyn SecondFactor>>,
+    // decoy accounts and the logins to them, reported by ListDecoyHits
+    pub decoys: DecoyStore,
+    // the devices users registered and logged in from, see ListDevices
+    pub devices: DeviceStore,
+    // failed logins in a row that lock an account, and for how long. no
+    // lockout when None
+    pub lockout: Option<LockoutPolicy>,
+    // where the failures are counted, in this process's memory unless the
+    // deployment keeps them where restarts and other replicas see them
+    pub lockouts: Box<dyn LockoutStore>,
+    // the failures of the users conceal_unknown_users makes up, so they lock
+    // like real ones without a record in lockouts for every name tried
+    pub made_up_lockouts: BoundedLockouts,
+    // answers a challenge takes before it is used up, one when None. a
+    // used-up challenge is kept to refuse the next answer to it
+    pub max_answers_per_challenge: Option<u32>,
+    // anonymous tickets a session may be issued, none when None. needs the
+    // server key
+    pub tickets_per_session: Option<u32>,
+    // nonces of the tickets redeemed
+    pub spent_tickets: TicketStore,
+    // between two recoveries of the same account
+    pub recovery_cooldown: recovery::Cooldown,
+    // recovery key registrations, recoveries and deletions, see
+    // ListAuditEvents
+    pub audit: AuditLog,
+    // where else the audit events are written, see audit::AuditSink
+    pub audit_sinks: Vec<Box<dyn AuditSink>>,
+    // how long a deleted user is kept before erase_deleted_users erases them
+    pub deletion_retention: Retention,
+    // the time sessions are issued, refreshed and expired by
+    pub clock: Box<dyn Clock>,
+    // set by SetDrain, see drain
+    pub drain: Drain,
+    // random bytes in each auth_id and session ID
+    pub id_length: IdLength,
+    // the named groups, built once at startup
+    pub groups: groups::Groups,
+    // threads the answers are verified on, inline on the runtime without one
+    pub verifier_pool: Option<Arc<WorkerPool>>,
+    // turns registrations and new logins away when the verifier_pool falls
+    // behind, see admission. needs the pool
+    pub admission: Option<Admission>,
+    // auth_ids drawn ahead of time, and the challenges when the source is
+    // Pooled, see challenge_pool. drawn per call without one
+    pub challenge_pool: Option<Arc<ChallengePool>>,
+    // mixed into every challenge derivation, so a proof made for this
+    // deployment never verifies on another. empty for none
+    pub application: Vec<u8>,
+    // a challenge for a user that doesn't exist is issued as for one that
+    // does, and its answer verified against the stand-in, see unknown_user
+    pub conceal_unknown_users: bool,
+    // logins started this minute, held to the realm's quota in settings
+    pub login_rate: LoginRate,
+    // what the quotas refused, served on the probe address's /metrics
+    pub quota_metrics: QuotaMetrics,
+}
+
+// verified in place of an unknown auth_id or user, so a miss costs what a
+// wrong answer does. its x is dropped, nothing can answer it
+struct StandIn {
+    user: UserInfo,
+    r1: BigUint,
+    r2: BigUint,
+    c: BigUint,
+    // the salts of unknown users are derived under
+    salt_key: [u8; 32],
+}
+
+fn stand_in() -> &'static StandIn {
+    static STAND_IN: OnceLock<StandIn> = OnceLock::new();
+    STAND_IN.get_or_init(|| {
+        let zkp = groups::default_group();
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        StandIn {
+            user: UserInfo {
+                user_name: String::new(),
+                y1: ZKP::exponentiate(&zkp.g, &x, &zkp.p),
+                y2: ZKP::exponentiate(&zkp.h, &x, &zkp.p),
+                group: groups::DEFAULT.to_string(),
+                ..UserInfo::default()
+            },
+            r1: ZKP::exponentiate(&zkp.g, &k, &zkp.p),
+            r2: ZKP::exponentiate(&zkp.h, &k, &zkp.p),
+            c: ZKP::generate_random_number_below(&zkp.q),
+            salt_key: {
+                let mut key = [0u8; 32];
+                crate::rng::with_rng(|rng| rng.fill_bytes(&mut key));
+                key
+            },
+        }
+    })
+}
+
+// the wire integers of a group, see codec. groups the server doesn't have
+// are refused before anything in them is decoded, so the MODP default
+// covers only requests that are refused anyway
+#[derive(Clone, Copy)]
+struct Wire(&'static dyn WireCodec);
+
+fn wire(group: &str) -> Wire {
+    Wire(codec::for_group(group).unwrap_or(&codec::MinimalBigEndian))
+}
+
+impl Wire {
+    fn element(&self, field: &'static str, bytes: &[u8]) -> Result<BigUint, Status> {
+        self.0
+            .decode_element(field, bytes)
+            .map_err(|e| Status::new(Code::InvalidArgument, e.to_string()))
+    }
+
+    fn scalar(&self, field: &'static str, bytes: &[u8]) -> Result<BigUint, Status> {
+        self.0
+            .decode_scalar(field, bytes)
+            .map_err(|e| Status::new(Code::InvalidArgument, e.to_string()))
+    }
+}
+
+// the decoded number, or zero with the failure kept as the rejection
+fn or_reject(decoded: Result<BigUint, Status>, rejection: &mut Option<Status>) -> BigUint {
+    decoded.unwrap_or_else(|status| {
+        rejection.get_or_insert(status);
+        BigUint::default()
+    })
+}
+
+// a login the pool had no room for is turned away before it is verified
+fn pool_rejection(error: PoolError) -> Status {
+    match error {
+        PoolError::Saturated => Status::new(
+            Code::ResourceExhausted,
+            "Too many logins are being verified, try again later",
+        ),
+        PoolError::Failed => Status::new(Code::Internal, "Verification failed to run"),
+    }
+}
+
+fn client_metadata(client: &ClientInfo) -> ClientMetadata {
+    ClientMetadata {
+        remote_addr: client
+            .remote_addr
+            .map(|addr| addr.to_string())
+            .unwrap_or_default(),
+        user_agent: client.user_agent.clone(),
+        device_id: client.device_id.clone(),
+    }
+}
+
+// the peer address and user agent of the call, and the device the client named
+fn client_info<T>(request: &Request<T>, device: Option<&DeviceInfo>) -> ClientInfo {
+    ClientInfo {
+        remote_addr: request.remote_addr(),
+        user_agent: request
+            .metadata()
+            .get("user-agent")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string(),
+        device_id: device
+            .map(|device| device.device_id.clone())
+            .unwrap_or_default(),
+    }
+}
+
+fn check_device(device: Option<&DeviceInfo>) -> Result<(), Status> {
+    let Some(device) = device else {
+        return Ok(());
+    };
+    for (field, value) in [
+        ("ID", &device.device_id),
+        ("name", &device.name),
+        ("platform", &device.platform),
+    ] {
+        if value.chars().count() > MAX_DEVICE_FIELD {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                format!(
+                    "Device {} takes at most {} characters",
+                    field, MAX_DEVICE_FIELD
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// a registration or login from the device, None when the client named none
+fn device_seen(
+    device: Option<&DeviceInfo>,
+    client: &ClientInfo,
+    registered: bool,
+    now: SystemTime,
+) -> Option<state::Device> {
+    let device = device.filter(|device| !device.device_id.is_empty())?;
+    Some(state::Device {
+        device_id: device.device_id.clone(),
+        name: device.name.clone(),
+        platform: device.platform.clone(),
+        first_seen: now,
+        last_seen: now,
+        client: client.clone(),
+        logins: u32::from(!registered),
+        registered,
+    })
+}
+
+// whether the session's key made mac over its ID
+fn check_owner(session: &Session, session_id: &str, mac: &[u8]) -> Result<(), Status> {
+    let owner = !mac.is_empty()
+        && session
+            .key
+            .as_ref()
+            .is_some_and(|key| key.verify_mac(session_id.as_bytes(), mac));
+    match owner {
+        true => Ok(()),
+        false => Err(Status::new(
+            Code::PermissionDenied,
+            "Session key MAC does not match",
+        )),
+    }
+}
+
+pub(crate) fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl AuthImpl {
+    // logged and kept whatever else the server is configured to report
+    fn audit(&self, kind: AuditKind, user_name: &str, client: ClientInfo) {
+        println!(
+            "[{}] 📝 Audit: {} for {} from {}",
+            request_id::label(),
+            kind.as_str(),
+            user_name,
+            client_metadata(&client).remote_addr
+        );
+        let event = state::AuditEvent {
+            kind,
+            user_name: user_name.to_string(),
+            request_id: request_id::current().unwrap_or_default(),
+            at: self.clock.now(),
+            client,
+        };
+        // what leaves the server names the user as the user store does, by
+        // the keyed hash with --username-hmac-key-file
+        let written = state::AuditEvent {
+            user_name: self.users.stored_name(user_name),
+            ..event.clone()
+        };
+        for sink in &self.audit_sinks {
+            if let Err(e) = sink.write(&written) {
+                eprintln!(
+                    "[{}] ⚠️ Audit sink {} missed {}: {}",
+                    request_id::label(),
+                    sink.name(),
+                    kind.as_str(),
+                    e
+                );
+            }
+        }
+        self.audit.record(event);
+    }
+
+    // drops the challenges nobody answered in time, the server runs it every
+    // minute. returns how many were dropped
+    pub fn sweep_challenges(&self) -> usize {
+        self.challenges.sweep(self.clock.now())
+    }
+
+    // erases the users deleted longer than the retention ago, and what is
+    // kept of them elsewhere: their devices, decoy marks and logins, and
+    // audit events. the server runs it every minute. returns how many users
+    // were erased
+    pub fn erase_deleted_users(&self) -> usize {
+        let Some(before) = self.clock.now().checked_sub(self.deletion_retention.0) else {
+            return 0;
+        };
+        let erased: HashSet<String> = self.users.erase_deleted(before).into_iter().collect();
+        if erased.is_empty() {
+            return 0;
+        }
+        // the other stores keep names in the clear, the user store perhaps
+        // only their hashes
+        let is_erased = |user_name: &str| erased.contains(&self.users.stored_name(user_name));
+        self.devices.erase_users(&is_erased);
+        self.decoys.erase_users(&is_erased);
+        let events = self.audit.erase_users(&is_erased);
+        println!(
+            "🗑️ Erased {} deleted users, with {} audit events",
+            erased.len(),
+            events
+        );
+        erased.len()
+    }
+
+    // rereads the settings from settings_source and swaps them in, keeping
+    // the old ones when they can't be read or fail Settings::check
+    pub fn reload_settings(&self) -> Result<Arc<Settings>, String> {
+        let Some(source) = &self.settings_source else {
+            return Err("Server has no settings to reload".to_string());
+        };
+        let settings = source.load()?;
+        settings.check()?;
+        self.settings.replace(settings);
+        Ok(self.settings.current())
+    }
+
+    // the challenge both sides answer: bound to the TLS channel when there is one,
+    // c itself (borrowed) otherwise
+    fn bound_challenge<'c>(
+        &self,
+        zkp: &ZKP,
+        r1: &BigUint,
+        r2: &BigUint,
+        c: &'c BigUint,
+    ) -> Cow<'c, BigUint> {
+        match &self.channel_binding {
+            Some(binding) => Cow::Owned(zkp.bind_challenge(&self.application, r1, r2, c, binding)),
+            None => Cow::Borrowed(c),
+        }
+    }
+
+    fn create_session(
+        &self,
+        user_name: &str,
+        key: Option<SessionKey>,
+        client: ClientInfo,
+    ) -> Result<(String, SystemTime), Status> {
+        let settings = self.settings.current();
+        let session_id = self.id_length.generate();
+        let created_at = self.clock.now();
+        let expires_at = created_at + settings.session_ttl;
+        let quota = settings.quotas.quota(&self.realm);
+        let evicted = self.sessions.insert_within(
+            session_id.clone(),
+            Session {
+                user_name: user_name.to_string(),
+                created_at,
+                expires_at,
+                key,
+                realm: self.realm.clone(),
+                client,
+                grant: settings.grants.grant(&self.realm, user_name),
+                tickets: 0,
+            },
+            settings.session_limit,
+            quota.max_sessions,
+            created_at,
+        );
+        match evicted {
+            Err(SessionRefusal::UserLimit) => {
+                Err(Status::new(Code::ResourceExhausted, SESSION_LIMIT_REACHED))
+            }
+            Err(SessionRefusal::RealmQuota) => Err(self.over_quota(QuotaKind::Sessions)),
+            Ok(0) => Ok((session_id, expires_at)),
+            Ok(evicted) => {
+                println!(
+                    "[{}] ⏏️ Ended the {} oldest sessions of {} to stay within the limit",
+                    request_id::label(),
+                    evicted,
+                    user_name
+                );
+                Ok((session_id, expires_at))
+            }
+        }
+    }
+
+    // "authorization: Bearer <token>" with one of the admin tokens
+    fn is_admin(&self, metadata: &MetadataMap) -> bool {
+        metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| self.is_admin_token(token))
+    }
+
+    // tokens are compared by their SHA-256, so the time taken doesn't depend on
+    // how much of a token matches
+    pub fn is_admin_token(&self, token: &str) -> bool {
+        let digest = Sha256::digest(token.as_bytes());
+        self.admin_tokens
+            .iter()
+            .any(|admin| Sha256::digest(admin.as_bytes()) == digest)
+    }
+
+    // the names the client certificate of the request's connection gives its
+    // holder, in canonical form. None without client_cert_identity, empty
+    // for a connection without a certificate
+    fn client_cert_names<T>(&self, request: &Request<T>) -> Option<Vec<String>> {
+        let kind = self.client_cert_identity?;
+        let certificates = request.peer_certs();
+        let names = match certificates
+            .as_deref()
+            .and_then(|certificates| certificates.first())
+        {
+            Some(certificate) => tls::client_identities(certificate, kind)
+                .iter()
+                .map(|name| self.usernames.canonical(name))
+                .collect(),
+            None => Vec::new(),
+        };
+        Some(names)
+    }
+
+    // the password adds to the machine identity instead of replacing it: a
+    // user is only registered, logged in or recovered over a connection whose
+    // certificate names it
+    fn check_client_cert(
+        &self,
+        names: &Option<Vec<String>>,
+        user_name: &str,
+    ) -> Result<(), Status> {
+        match names {
+            Some(names) if !names.iter().any(|name| name == user_name) => {
+                Err(Status::new(Code::PermissionDenied, CLIENT_CERT_MISMATCH))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // counted for /metrics, and told to the client as RESOURCE_EXHAUSTED
+    fn over_quota(&self, kind: QuotaKind) -> Status {
+        println!(
+            "[{}] 🧾 Realm {:?} is over its quota of {}",
+            request_id::label(),
+            self.realm,
+            kind.as_str()
+        );
+        self.quota_metrics.refused(&self.realm, kind);
+        Status::new(
+            Code::ResourceExhausted,
+            format!("{} of {}", QUOTA_EXCEEDED, kind.as_str()),
+        )
+    }
+
+    // where the user's failures are counted and under what name. a name
+    // nobody registered only gets that far with conceal_unknown_users
+    fn lockouts_of(&self, user_name: &str) -> (&dyn LockoutStore, String) {
+        let lockouts: &dyn LockoutStore = match self.users.get(user_name) {
+            Some(_) => self.lockouts.as_ref(),
+            None => &self.made_up_lockouts,
+        };
+        (lockouts, self.users.stored_name(user_name))
+    }
+
+    // a lock that can't be read isn't skipped, the login is refused as
+    // Unavailable
+    fn check_lockout(&self, user_name: &str) -> Result<(), Status> {
+        if self.lockout.is_none() {
+            return Ok(());
+        }
+        let (lockouts, stored_name) = self.lockouts_of(user_name);
+        let record = lockouts
+            .get(&stored_name)
+            .map_err(|e| Status::new(Code::Unavailable, format!("Lockout store: {}", e)))?;
+        let now = unix_seconds(self.clock.now());
+        match record.locked_at(now) {
+            true => Err(Status::new(
+                Code::PermissionDenied,
+                format!(
+                    "{} for {} more seconds",
+                    ACCOUNT_LOCKED,
+                    record.locked_until - now
+                ),
+            )),
+            false => Ok(()),
+        }
+    }
+
+    // the answer is refused either way, a failure that can't be counted is
+    // only logged
+    fn record_login_failure(&self, user_name: &str) {
+        let Some(policy) = &self.lockout else {
+            return;
+        };
+        let now = unix_seconds(self.clock.now());
+        let (lockouts, stored_name) = self.lockouts_of(user_name);
+        match policy.record_failure(lockouts, &stored_name, now) {
+            Ok(record) if record.locked_at(now) => println!(
+                "[{}] 🔒 {} locked after {} failed logins",
+                request_id::label(),
+                user_name,
+                record.failures
+            ),
+            Ok(_) => {}
+            Err(e) => println!(
+                "[{}] ❌ Failed to count a failed login of {}: {}",
+                request_id::label(),
+                user_name,
+                e
+            ),
+        }
+    }
+
+    fn record_login_success(&self, user_name: &str) {
+        let Some(policy) = &self.lockout else {
+            return;
+        };
+        let (lockouts, stored_name) = self.lockouts_of(user_name);
+        if let Err(e) = policy.record_success(lockouts, &stored_name) {
+            println!(
+                "[{}] ❌ Failed to clear the failed logins of {}: {}",
+                request_id::label(),
+                user_name,
+                e
+            );
+        }
+    }
+
+    // the receipt of a registration, signed with the server key. None without
+    // one
+    fn receipt_for(&self, user: &UserInfo) -> Option<Receipt> {
+        let key = self.server_key.as_ref()?;
+        let group = self.groups.get(&user.group)?;
+        Some(Receipt::issue(
+            &self.groups.default_group(),
+            key,
+            &user.user_name,
+            &user.group,
+            groups::fingerprint(&group),
+            receipt::verifier_digest(&user.y1, &user.y2),
+            unix_seconds(self.clock.now()),
+        ))
+    }
+
+    // a receipt this server signed for the user, and the registration it has
+    // for them still the receipted one
+    fn check_receipt(&self, receipt: &RegistrationReceipt, user_name: &str) -> Result<(), Status> {
+        let receipt = Receipt::try_from(receipt)
+            .map_err(|e| Status::new(Code::InvalidArgument, format!("Receipt {}", e)))?;
+        let signed = match &self.server_key {
+            Some(key) => receipt.verify(&self.groups.default_group(), &key.public),
+            None => false,
+        };
+        if !signed || receipt.user != user_name {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                format!("Receipt is not one this server signed for {}", user_name),
+            ));
+        }
+        let registered = self.users.get(user_name);
+        let params = match registered
+            .as_ref()
+            .and_then(|user| self.groups.get(&user.group))
+        {
+            Some(group) => groups::fingerprint(&group),
+            None => [0; 32],
+        };
+        let verifier = registered.as_ref().map(|user| {
+            (
+                receipt::verifier_digest(&user.y1, &user.y2),
+                user.group.as_str(),
+            )
+        });
+        let what = match receipt.standing(verifier, params) {
+            Standing::Kept => return Ok(()),
+            Standing::Dropped => "is gone",
+            Standing::Altered => "has changed",
+        };
+        println!(
+            "[{}] 🧾 registration of {} receipted at {} {}",
+            request_id::label(),
+            user_name,
+            receipt.registered_at,
+            what
+        );
+        Err(Status::new(
+            Code::FailedPrecondition,
+            format!(
+                "{} of {} (registered at {}), it {}",
+                REGISTRATION_LOST, user_name, receipt.registered_at, what
+            ),
+        ))
+    }
+
+    // the registered user, or with conceal_unknown_users one made up for a
+    // name nobody registered: in the default group, with the stand-in's y1
+    // and y2 so its answers cost a real verification and never pass, salted
+    // when every real user is, with the same salt on every challenge. the
+    // salt key is per process, a restart changes the salts
+    fn lookup_user(&self, user_name: &str) -> Option<UserInfo> {
+        if let Some(user) = self.users.get(user_name) {
+            return Some(user);
+        }
+        if !self.conceal_unknown_users {
+            return None;
+        }
+        let stand_in = stand_in();
+        let salt = match self.require_salted_verifier {
+            true => Sha256::new()
+                .chain_update(stand_in.salt_key)
+                .chain_update(user_name.as_bytes())
+                .finalize()[..crate::verifier::SALT_LEN]
+                .to_vec(),
+            false => Vec::new(),
+        };
+        Some(UserInfo {
+            user_name: user_name.to_string(),
+            salt,
+            ..stand_in.user.clone()
+        })
+    }
+
+    fn check_not_draining(&self) -> Result<(), Status> {
+        match self.drain.is_draining() {
+            true => Err(Status::new(Code::Unavailable, SERVER_DRAINING)),
+            false => Ok(()),
+        }
+    }
+
+    fn admit(&self, call: Call) -> Result<(), Status> {
+        let (Some(admission), Some(pool)) = (&self.admission, &self.verifier_pool) else {
+            return Ok(());
+        };
+        admission
+            .admit(pool, call, std::time::Instant::now())
+            .map_err(|load| {
+                println!(
+                    "[{}] 🚦 Turning a {} away, the server is {}",
+                    request_id::label(),
+                    call.as_str(),
+                    load
+                );
+                Status::new(
+                    Code::ResourceExhausted,
+                    format!("{} ({})", SERVER_OVERLOADED, load),
+                )
+            })
+    }
+
+    // a recovery key in a group the server knows, neither half of it outside
+    // the order-q subgroup
+    fn recovery_key(&self, recovery: &RecoveryKey) -> Result<state::RecoveryKey, Status> {
+        let Some(zkp) = self.groups.get(&recovery.group) else {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                format!("Group {} is not supported", recovery.group),
+            ));
+        };
+        let wire = wire(&recovery.group);
+        let key = PublicKey {
+            y1: wire.element("y1", &recovery.y1)?,
+            y2: wire.element("y2", &recovery.y2)?,
+        };
+        if !tickets::in_subgroup(&zkp, &key.y1) || !tickets::in_subgroup(&zkp, &key.y2) {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                "Recovery key is not in the group",
+            ));
+        }
+        Ok(state::RecoveryKey {
+            key,
+            group: groups::resolve(&recovery.group).to_string(),
+        })
+    }
+
+    // the user whose devices are asked about: the owner of the session, who
+    // proves the session key, or any user for an admin
+    fn device_owner(
+        &self,
+        metadata: &MetadataMap,
+        user: &str,
+        session_id: &str,
+        mac: &[u8],
+    ) -> Result<String, Status> {
+        if !session_id.is_empty() {
+            return self.session_owner(session_id, mac);
+        }
+        if !self.is_admin(metadata) {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Only the user or an admin may manage the user's devices",
+            ));
+        }
+        if user.is_empty() {
+            return Err(Status::new(Code::InvalidArgument, "No user given"));
+        }
+        Ok(self.usernames.canonical(user))
+    }
+
+    // the user of a session whose key made mac over its ID
+    fn session_owner(&self, session_id: &str, mac: &[u8]) -> Result<String, Status> {
+        self.sessions
+            .with_session(session_id, self.clock.now(), |session| {
+                check_owner(session, session_id, mac)?;
+                Ok(session.user_name.clone())
+            })
+    }
+
+    // (user, t, seal key) of a blinded login
+    fn open_blinded(
+        &self,
+        zkp: &ZKP,
+        blinded: &BlindedIdentity,
+    ) -> Result<(String, SecretBigUint, SealKey), Status> {
+        let server_key = self.server_key.as_ref().ok_or_else(|| {
+            Status::new(
+                Code::FailedPrecondition,
+                "Blinded logins need a server key (--key-file)",
+            )
+        })?;
+        let u_public = BigUint::from_bytes_be(&blinded.u_public);
+        let key = SealKey::from_client(zkp, server_key.x.expose(), &u_public)
+            .ok_or_else(|| Status::new(Code::InvalidArgument, "Invalid blinded identity"))?;
+        let user = String::from_utf8(key.xor(b"user", &blinded.sealed_user))
+            .map_err(|_| Status::new(Code::InvalidArgument, "Invalid blinded identity"))?;
+        let t =
+            SecretBigUint::new(key.open_scalar(&zkp.q, &BigUint::from_bytes_be(&blinded.sealed_t)));
+        // t = 0 turns every base into 1, which any answer would verify against
+        if *t.expose() == BigUint::from(0u32) {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                "Invalid blinded identity",
+            ));
+        }
+        Ok((user, t, key))
+    }
+}
+
+#[tonic::async_trait]
+impl Auth for AuthImpl {
+    async fn register(
+        &self,
+        request: Request<RegisterRequest>,
+    ) -> Result<Response<RegisterResponse>, Status> {
+        println!(
+            "[{}] Processing register request: {:?}",
+            request_id::label(),
+            request.get_ref()
+        );
+
+        self.check_not_draining()?;
+        self.admit(Call::Register)?;
+        let client = client_info(&request, request.get_ref().device.as_ref());
+        let cert_names = self.client_cert_names(&request);
+        let request = request.into_inner();
+        check_device(request.device.as_ref())?;
+        let user_name = self.usernames.normalize(&request.user).map_err(|e| {
+            Status::new(
+                Code::InvalidArgument,
+                format!("User name {:?} {}", request.user, e),
+            )
+        })?;
+        self.check_client_cert(&cert_names, &user_name)?;
+        if self.require_salted_verifier && request.salt.is_empty() {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                "Server only accepts salted verifiers",
+            ));
+        }
+        if self.groups.get(&request.group).is_none() {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                format!("Group {} is not supported", request.group),
+            ));
+        }
+        // only a salted verifier is derived with a KDF
+        let kdf = crate::proto::kdf_from_wire(request.kdf.as_ref())
+            .map_err(|e| Status::new(Code::InvalidArgument, e))?;
+        if !request.salt.is_empty() {
+            self.kdf_policy
+                .check(&kdf)
+                .map_err(|e| Status::new(Code::InvalidArgument, e))?;
+        }
+        let salted = !request.salt.is_empty();
+        let recovery = match &request.recovery_key {
+            Some(recovery) => Some(self.recovery_key(recovery)?),
+            None => None,
+        };
+        if let Some(device) = device_seen(request.device.as_ref(), &client, true, self.clock.now())
+        {
+            if !self.devices.record(&user_name, device) {
+                return Err(Status::new(Code::PermissionDenied, DEVICE_REVOKED));
+            }
+            println!(
+                "[{}] 📱 {} registered from device {}",
+                request_id::label(),
+                user_name,
+                client.device_id
+            );
+        }
+        let wire = wire(&request.group);
+        let user_info = UserInfo {
+            user_name,
+            y1: wire.element("y1", &request.y1)?,
+            y2: wire.element("y2", &request.y2)?,
+            salt: request.salt,
+            group: groups::resolve(&request.group).to_string(),
+            kdf,
+            recovery,
+            recovered_at: None,
+            deleted_at: None,
+        };
+        let has_recovery = user_info.recovery.is_some();
+        let user_name = user_info.user_name.clone();
+        let receipt = self.receipt_for(&user_info);
+        let quota = self.settings.current().quotas.quota(&self.realm);
+        match self.users.insert_within(user_info, quota.max_users) {
+            Ok(()) => {}
+            Err(UserRefusal::Quota) => return Err(self.over_quota(QuotaKind::Users)),
+            Err(UserRefusal::Deleted) => {
+                return Err(Status::new(Code::FailedPrecondition, USER_DELETED));
+            }
+        }
+        if has_recovery {
+            self.audit(AuditKind::RecoveryKeyRegistered, &user_name, client);
+        }
+
+        Ok(Response::new(RegisterResponse {
+            kdf: salted.then(|| kdf.into()),
+            receipt: receipt.as_ref().map(RegistrationReceipt::from),
+        }))
+    }
+
+    async fn create_authentication_challenge(
+        &self,
+        request: Request<AuthenticationChallengeRequest>,
+    ) -> Result<Response<AuthenticationChallengeResponse>, Status> {
+        println!(
+            "[{}] Processing challenge request: {:?}",
+            request_id::label(),
+            request.get_ref()
+        );
+        self.check_not_draining()?;
+        self.admit(Call::Login)?;
+
+        let cert_names = self.client_cert_names(&request);
+        let request = request.into_inner();
+        // sealed to the server key, in the default group whatever the user's
+        let blinded = match &request.blinded {
+            Some(blinded) => Some(self.open_blinded(&self.groups.default_group(), blinded)?),
+            None => None,
+        };
+        let user_name = match &blinded {
+            Some((user, _, _)) => self.usernames.canonical(user),
+            None => self.usernames.canonical(&request.user),
+        };
+        self.check_client_cert(&cert_names, &user_name)?;
+        if let Some(receipt) = &request.receipt {
+            self.check_receipt(receipt, &user_name)?;
+        }
+        let Some(user_info) = self.lookup_user(&user_name) else {
+            return Err(Status::new(
+                Code::NotFound,
+                match &blinded {
+                    Some(_) => "User not found in the database".to_string(),
+                    None => format!("User: {} not found in the database", user_name),
+                },
+            ));
+        };
+        self.check_lockout(&user_name)?;
+        // every login started counts, whether or not it is answered
+        let quota = self.settings.current().quotas.quota(&self.realm);
+        let now = unix_seconds(self.clock.now());
+        if !self
+            .login_rate
+            .admit(&self.realm, quota.max_logins_per_minute, now)
+        {
+            return Err(self.over_quota(QuotaKind::LoginsPerMinute));
+        }
+
+        // r1/r2 are in the user's group, and so is the answer
+        if groups::resolve(&request.group) != user_info.group {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                format!(
+                    "User is registered under group {}, not {}",
+                    user_info.group,
+                    groups::resolve(&request.group)
+                ),
+            ));
+        }
+        let group = self
+            .groups
+            .get(&user_info.group)
+            .expect("registered groups are supported");
+
+        let auth_id = match &self.challenge_pool {
+            Some(pool) => pool.auth_id(),
+            None => self.id_length.generate(),
+        };
+        let c = self.challenge_source.challenge(
+            &group,
+            &ChallengeRequest {
+                application: &self.application,
+                user: &user_name,
+                auth_id: &auth_id,
+                r1: &request.r1,
+                r2: &request.r2,
+                nonce_commitment: &request.nonce_commitment,
+            },
+        );
+
+        // server_r1 = g ** k_server mod p ; server_r2 = h ** k_server mod p
+        let (mut server_r1, mut server_r2, mut server_k) = (Vec::new(), Vec::new(), None);
+        if self.server_key.is_some() {
+            let zkp = self.groups.default_group();
+            let k = SecretBigUint::new(ZKP::generate_random_number_below(&zkp.q));
+            let bases = self
+                .groups
+                .bases(groups::DEFAULT)
+                .expect("the default group is named");
+            let (r1, r2) = bases.commit(k.expose());
+            let wire = wire(groups::DEFAULT).0;
+            (server_r1, server_r2) = (wire.encode_element(&r1), wire.encode_element(&r2));
+            server_k = Some(k);
+        }
+
+        // the salt and KDF parameters would link blinded logins of the same user
+        let kdf = (!user_info.salt.is_empty()).then(|| KdfParams::from(user_info.kdf));
+        let (salt, kdf, sealed_kdf) = match &blinded {
+            Some((_, _, key)) => (
+                key.xor(b"salt", &user_info.salt),
+                None,
+                kdf.map(|kdf| key.xor(b"kdf", &kdf.encode_to_vec()))
+                    .unwrap_or_default(),
+            ),
+            None => (user_info.salt, kdf, Vec::new()),
+        };
+        let non_interactive =
+            request.r1.is_empty() && request.r2.is_empty() && request.nonce_commitment.is_empty();
+        // r1/r2 arrive with the answer in the other variants
+        let wire = wire(&user_info.group);
+        let (r1, r2) = match request.r1.is_empty() && request.r2.is_empty() {
+            true => (BigUint::default(), BigUint::default()),
+            false => (
+                wire.element("r1", &request.r1)?,
+                wire.element("r2", &request.r2)?,
+            ),
+        };
+        let issued = Challenge {
+            user_name,
+            r1,
+            r2,
+            nonce_commitment: request.nonce_commitment,
+            non_interactive,
+            c: c.clone(),
+            server_k,
+            blinding: blinded.map(|(_, t, _)| t),
+            answers: 0,
+            created_at: self.clock.now(),
+        };
+        if !self
+            .challenges
+            .insert(auth_id.clone(), issued, self.clock.now())
+        {
+            return Err(Status::new(
+                Code::ResourceExhausted,
+                "Too many challenges are waiting for an answer, try again later",
+            ));
+        }
+
+        Ok(Response::new(AuthenticationChallengeResponse {
+            auth_id,
+            c: wire.0.encode_scalar(&c),
+            server_r1,
+            server_r2,
+            salt,
+            kdf,
+            sealed_kdf,
+        }))
+    }
+
+    async fn verify_authentication(
+        &self,
+        request: Request<AuthenticationAnswerRequest>,
+    ) -> Result<Response<AuthenticationAnswerResponse>, Status> {
+        println!(
+            "[{}] Processing verification request: {:?}",
+            request_id::label(),
+            request.get_ref()
+        );
+
+        let client = client_info(&request, request.get_ref().device.as_ref());
+        let cert_names = self.client_cert_names(&request);
+        let request = request.into_inner();
+        check_device(request.device.as_ref())?;
+        let auth_id = request.auth_id.clone();
+        // taken out here, atomically, before anything is checked: a concurrent
+        // answer to the same challenge finds nothing. a rejected answer counts
+        // against it, and once it has taken max_answers_per_challenge another
+        // s against the same c is refused. a failure that decided nothing (a
+        // full verifier queue, a second factor that can't be reached) doesn't
+        // count
+        let found = self.challenges.take(&auth_id, self.clock.now());
+        let user_info = found
+            .as_ref()
+            .and_then(|challenge| self.lookup_user(&challenge.user_name));
+        // every step runs whichever fails, an unknown auth_id or user against the
+        // stand-in, so the time taken doesn't tell which one did. the first
+        // failure is the one reported
+        let mut rejection = match (&found, &user_info) {
+            // used up by the answer before, which dropped it
+            (None, _) if self.challenges.take_used_up(&auth_id, self.clock.now()) => {
+                Some(Status::new(Code::PermissionDenied, ANSWERS_EXHAUSTED))
+            }
+            (None, _) => Some(Status::new(
+                Code::NotFound,
+                format!("AuthId: {} not found in the database", auth_id),
+            )),
+            (Some(challenge), None) => Some(Status::new(
+                Code::NotFound,
+                format!("User: {} not found in the database", challenge.user_name),
+            )),
+            // issued to a connection with another certificate
+            (Some(challenge), _) => self
+                .check_client_cert(&cert_names, &challenge.user_name)
+                .err(),
+        };
+        let (mut challenge, user_info) = match (found, user_info) {
+            (Some(challenge), Some(user_info)) => (challenge, user_info),
+            // a challenge whose user is gone is dropped
+            _ => {
+                let stand_in = stand_in();
+                (
+                    Challenge {
+                        user_name: stand_in.user.user_name.clone(),
+                        r1: stand_in.r1.clone(),
+                        r2: stand_in.r2.clone(),
+                        c: stand_in.c.clone(),
+                        ..Challenge::default()
+                    },
+                    stand_in.user.clone(),
+                )
+            }
+        };
+        let missing = rejection.is_some();
+        // a lock taken while the challenge was out refuses its answer, after
+        // it is verified like any other. the refused answer isn't counted, or
+        // the lock would never run out
+        let mut locked = false;
+        if !missing {
+            match self.check_lockout(&challenge.user_name) {
+                Ok(()) => {}
+                Err(status) if status.code() == Code::Unavailable => {
+                    self.challenges.put_back(auth_id.clone(), challenge);
+                    return Err(status);
+                }
+                Err(status) => {
+                    locked = true;
+                    rejection = Some(status);
+                }
+            }
+        }
+
+        // verification
+        // an answer the group's codec can't read is refused like a wrong one,
+        // after the same work
+        let wire = wire(&user_info.group);
+        let s = or_reject(wire.scalar("s", &request.s), &mut rejection);
+        // the user's proof is in the user's group, the server's in the default one
+        let group = self
+            .groups
+            .get(&user_info.group)
+            .expect("registered groups are supported");
+        let zkp = self.groups.default_group();
+        // a blinded login is verified on g ** t, h ** t against y1 ** t, y2 ** t
+        let (bases, y1, y2) = match &challenge.blinding {
+            Some(t) => {
+                let (y1, y2) =
+                    blinding::blinded_key(&group, &user_info.y1, &user_info.y2, t.expose());
+                (
+                    Arc::new(blinding::blinded_bases(&group, t.expose())),
+                    y1,
+                    y2,
+                )
+            }
+            None => (group, user_info.y1, user_info.y2),
+        };
+        let (r1, r2, verification) = if challenge.non_interactive {
+            // non-interactive variant: a proof over H(auth_id, channel binding),
+            // r1/r2 are what it was made from
+            let channel_binding = self.channel_binding.as_deref().unwrap_or_default();
+            if request.channel_binding != channel_binding {
+                rejection.get_or_insert(Status::new(
+                    Code::PermissionDenied,
+                    format!("AuthId: {} is answered for another channel", auth_id),
+                ));
+            }
+            let proof = Proof {
+                c: or_reject(wire.scalar("c", &request.c), &mut rejection),
+                s,
+            };
+            let context = ZKP::answer_context(&auth_id, channel_binding);
+            // a rejected proof isn't exponentiated again, its inputs may be oversized
+            let application = self.application.clone();
+            let commitments = move |bases: &ZKP, y1: &BigUint, y2: &BigUint| {
+                bases
+                    .verify_non_interactive_in(&application, y1, y2, &proof, &context)
+                    .then(|| bases.proof_commitments(y1, y2, &proof))
+            };
+            let commitments = match &self.verifier_pool {
+                None => Ok(commitments(&bases, &y1, &y2)),
+                Some(pool) => {
+                    let (bases, y1, y2) = (bases.clone(), y1.clone(), y2.clone());
+                    pool.run(move || commitments(&bases, &y1, &y2)).await
+                }
+            };
+            match commitments {
+                Ok(Some((r1, r2))) => (Cow::Owned(r1), Cow::Owned(r2), Ok(true)),
+                Ok(None) => (Cow::default(), Cow::default(), Ok(false)),
+                Err(error) => (Cow::default(), Cow::default(), Err(error)),
+            }
+        } else {
+            // nonce-commitment variant: r1/r2 only arrive now and must match
+            let (r1, r2) = if challenge.nonce_commitment.is_empty() {
+                // only borrowed, the challenge may be put back when nothing
+                // is decided
+                (Cow::Borrowed(&challenge.r1), Cow::Borrowed(&challenge.r2))
+            } else {
+                let r1 = or_reject(wire.element("r1", &request.r1), &mut rejection);
+                let r2 = or_reject(wire.element("r2", &request.r2), &mut rejection);
+                if bases.commit_nonce(&r1, &r2)[..] != challenge.nonce_commitment[..] {
+                    rejection.get_or_insert(Status::new(
+                        Code::PermissionDenied,
+                        format!(
+                            "AuthId: {} revealed a nonce that doesn't match its commitment",
+                            auth_id
+                        ),
+                    ));
+                }
+                (Cow::Owned(r1), Cow::Owned(r2))
+            };
+            let c = self.bound_challenge(&bases, &r1, &r2, &challenge.c);
+            let verification = match &self.verifier_pool {
+                None => Ok(bases.verify(&r1, &r2, &y1, &y2, &c, &s)),
+                Some(pool) => {
+                    let (bases, y1, y2, s) = (bases.clone(), y1.clone(), y2.clone(), s.clone());
+                    let (r1, r2, c) = (
+                        r1.clone().into_owned(),
+                        r2.clone().into_owned(),
+                        c.into_owned(),
+                    );
+                    pool.run(move || bases.verify(&r1, &r2, &y1, &y2, &c, &s))
+                        .await
+                }
+            };
+            (r1, r2, verification)
+        };
+        // nothing was verified, the challenge stays for the client to retry
+        let verification = match verification {
+            Ok(verification) => verification,
+            Err(error) => {
+                if !missing {
+                    self.challenges.put_back(auth_id.clone(), challenge);
+                }
+                return Err(pool_rejection(error));
+            }
+        };
+        println!("[{}] verification: {}", request_id::label(), verification);
+
+        if !verification {
+            rejection.get_or_insert(Status::new(
+                Code::PermissionDenied,
+                format!("AuthId: {} is not verified", auth_id),
+            ));
+            if !missing && !locked {
+                self.record_login_failure(&challenge.user_name);
+            }
+        }
+        // counted against the challenge, which goes back for another answer
+        // or, used up, is dropped. its auth_id is kept for a while, so the next
+        // answer is told why it is refused
+        if let Some(rejection) = rejection {
+            if !missing {
+                challenge.answers += 1;
+                if challenge.answers < self.max_answers_per_challenge.unwrap_or(1) {
+                    self.challenges.put_back(auth_id.clone(), challenge);
+                } else {
+                    self.challenges
+                        .use_up(auth_id.clone(), challenge, self.clock.now());
+                }
+            }
+            return Err(rejection);
+        }
+        let second_factor = match &self.second_factor {
+            None => String::new(),
+            Some(factor) => {
+                let remote_addr = client_metadata(&client).remote_addr;
+                let attempt = SecondFactorAttempt {
+                    user: &challenge.user_name,
+                    code: &request.second_factor,
+                    remote_addr: &remote_addr,
+                    now: self.clock.now(),
+                };
+                match factor.check(&attempt).await {
+                    Ok(()) => factor.name().to_string(),
+                    // the challenge is used up, another code means proving
+                    // again. a wrong code is a failed login like a wrong
+                    // password, or knowing the password would buy unlimited
+                    // guesses at the code
+                    Err(SecondFactorError::Rejected(reason)) => {
+                        self.record_login_failure(&challenge.user_name);
+                        return Err(Status::new(
+                            Code::Unauthenticated,
+                            format!("Second factor {} rejected: {}", factor.name(), reason),
+                        ));
+                    }
+                    // nothing was decided, the challenge stays for the client to retry
+                    Err(error) => {
+                        self.challenges.put_back(auth_id.clone(), challenge);
+                        return Err(Status::new(Code::Unavailable, error.to_string()));
+                    }
+                }
+            }
+        };
+        // both factors are proven
+        self.record_login_success(&challenge.user_name);
+
+        // server_s = k_server - server_c * x_server mod q. k_server stays with
+        // the challenge in case it is put back, but server_s only reaches the
+        // answer that gets a session, which uses the challenge up: no two
+        // server_c ever learn an s under one k_server
+        let mut server_s = Vec::new();
+        if !request.server_c.is_empty() {
+            match (&self.server_key, &challenge.server_k) {
+                (Some(server_key), Some(server_k)) => {
+                    let (server_r1, server_r2) = self
+                        .groups
+                        .bases(groups::DEFAULT)
+                        .expect("the default group is named")
+                        .commit(server_k.expose());
+                    let server_wire = self::wire(groups::DEFAULT);
+                    let server_c = server_wire.scalar("server_c", &request.server_c)?;
+                    let server_c = self.bound_challenge(&zkp, &server_r1, &server_r2, &server_c);
+                    server_s = server_wire.0.encode_scalar(&zkp.solve(
+                        server_k.expose(),
+                        &server_c,
+                        server_key.x.expose(),
+                    ));
+                }
+                _ => {
+                    return Err(Status::new(
+                        Code::FailedPrecondition,
+                        "Server has no pending commitment to prove its identity",
+                    ));
+                }
+            }
+        }
+
+        // key_share = g ** b mod p ; shared = r1 ** b mod p (on the blinded bases
+        // for a blinded login)
+        let (key_share, mut shared) = server_key_share(&bases, &r1);
+        let key = SessionKey::derive(&shared, &r1, &r2, &key_share, &auth_id);
+        wipe_biguint(&mut shared);
+        let key_confirmation = key.confirmation();
+
+        // one session per challenge, a replayed answer finds no auth_id. a
+        // session that can't be created undoes taking the challenge, as a
+        // transaction would, so no login is left proven without a session:
+        // the same answer can be sent again once there is room. not after a
+        // second factor, which can't be undone (a TOTP code is spent once
+        // checked), so the challenge is used up and the login starts over
+        let (session_id, _) =
+            match self.create_session(&challenge.user_name, Some(key), client.clone()) {
+                Ok(session) => session,
+                Err(status) if self.second_factor.is_some() => {
+                    self.challenges
+                        .use_up(auth_id.clone(), challenge, self.clock.now());
+                    return Err(status);
+                }
+                Err(status) => {
+                    self.challenges.put_back(auth_id.clone(), challenge);
+                    return Err(status);
+                }
+            };
+        // checked after the session is in place, so a concurrent DeleteUser
+        // either ends the session or makes this fail
+        if self.users.get(&challenge.user_name).is_none() {
+            self.sessions.remove(&session_id);
+            return Err(Status::new(
+                Code::NotFound,
+                format!("User: {} not found in the database", challenge.user_name),
+            ));
+        }
+        // recorded after the session is in place, so a concurrent RevokeDevice
+        // either ends the session or makes this fail
+        if let Some(device) = device_seen(request.device.as_ref(), &client, false, self.clock.now())
+        {
+            if !self.devices.record(&challenge.user_name, device) {
+                self.sessions.remove(&session_id);
+                return Err(Status::new(Code::PermissionDenied, DEVICE_REVOKED));
+            }
+            println!(
+                "[{}] 📱 {} logged in from device {}",
+                request_id::label(),
+                challenge.user_name,
+                client.device_id
+            );
+        }
+        // a decoy login looks like any other to the client, only admins hear of it
+        let remote_addr = client_metadata(&client).remote_addr;
+        let hit = state::DecoyHit {
+            user_name: challenge.user_name.clone(),
+            session_id: session_id.clone(),
+            request_id: request_id::current().unwrap_or_default(),
+            at: self.clock.now(),
+            client,
+        };
+        if self.decoys.record(hit) {
+            println!(
+                "[{}] 🚨 Decoy account {} logged in from {}",
+                request_id::label(),
+                challenge.user_name,
+                if remote_addr.is_empty() {
+                    "an unknown address"
+                } else {
+                    &remote_addr
+                }
+            );
+        }
+        Ok(Response::new(AuthenticationAnswerResponse {
+            session_id,
+            server_s,
+            key_share: wire.0.encode_element(&key_share),
+            key_confirmation,
+            second_factor,
+        }))
+    }
+
+    async fn validate_session(
+        &self,
+        request: Request<ValidateSessionRequest>,
+    ) -> Result<Response<ValidateSessionResponse>, Status> {
+        let request = request.into_inner();
+        self.sessions
+            .with_session(&request.session_id, self.clock.now(), |session| {
+                // proof of possession of the session key
+                if !request.mac.is_empty() {
+                    let valid = session.key.as_ref().is_some_and(|key| {
+                        key.verify_mac(request.session_id.as_bytes(), &request.mac)
+                    });
+                    if !valid {
+                        return Err(Status::new(
+                            Code::Unauthenticated,
+                            "Session key MAC does not match",
+                        ));
+                    }
+                }
+                Ok(Response::new(ValidateSessionResponse {
+                    user: session.user_name.clone(),
+                    expires_at: unix_seconds(session.expires_at),
+                    scopes: session.grant.scopes.clone(),
+                    claims: session.grant.claims.clone().into_iter().collect(),
+                }))
+            })
+    }
+
+    async fn refresh_session(
+        &self,
+        request: Request<RefreshSessionRequest>,
+    ) -> Result<Response<RefreshSessionResponse>, Status> {
+        let request = request.into_inner();
+        let session_id = self.id_length.generate();
+        let now = self.clock.now();
+        let expires_at = now + self.settings.current().session_ttl;
+        self.sessions
+            .refresh(&request.session_id, session_id.clone(), expires_at, now)?;
+        Ok(Response::new(RefreshSessionResponse {
+            session_id,
+            expires_at: unix_seconds(expires_at),
+        }))
+    }
+
+    async fn logout(
+        &self,
+        request: Request<LogoutRequest>,
+    ) -> Result<Response<LogoutResponse>, Status> {
+        let request = request.into_inner();
+        match self.sessions.remove(&request.session_id) {
+            Some(_) => Ok(Response::new(LogoutResponse {})),
+            None => Err(Status::new(Code::Unauthenticated, "Session not found")),
+        }
+    }
+
+    async fn get_session_info(
+        &self,
+        request: Request<GetSessionInfoRequest>,
+    ) -> Result<Response<GetSessionInfoResponse>, Status> {
+        let admin = self.is_admin(request.metadata());
+        let request = request.into_inner();
+        self.sessions
+            .with_session(&request.session_id, self.clock.now(), |session| {
+                // the session ID alone is what the services asking hold, the owner
+                // proves possession of the session key
+                let owner = !request.mac.is_empty()
+                    && session.key.as_ref().is_some_and(|key| {
+                        key.verify_mac(request.session_id.as_bytes(), &request.mac)
+                    });
+                if !owner && !admin {
+                    return Err(Status::new(
+                        Code::PermissionDenied,
+                        "Only the session owner or an admin may read the session",
+                    ));
+                }
+                Ok(Response::new(GetSessionInfoResponse {
+                    user: session.user_name.clone(),
+                    created_at: unix_seconds(session.created_at),
+                    expires_at: unix_seconds(session.expires_at),
+                    realm: session.realm.clone(),
+                    client: Some(client_metadata(&session.client)),
+                    scopes: session.grant.scopes.clone(),
+                    claims: session.grant.claims.clone().into_iter().collect(),
+                }))
+            })
+    }
+
+    async fn export_state(
+        &self,
+        request: Request<ExportStateRequest>,
+    ) -> Result<Response<ExportStateResponse>, Status> {
+        if !self.is_admin(request.metadata()) {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Only an admin may export the server state",
+            ));
+        }
+        let Some(server_key) = &self.server_key else {
+            return Err(Status::new(
+                Code::FailedPrecondition,
+                "Bundles are signed with the server key (--key-file)",
+            ));
+        };
+        let mut bundle = Bundle::export(&self.users, &self.sessions, self.clock.now());
+        bundle.sign(&self.groups.default_group(), server_key);
+        println!(
+            "[{}] 📦 Exported {} users and {} sessions",
+            request_id::label(),
+            bundle.users.len(),
+            bundle.sessions.len()
+        );
+        Ok(Response::new(ExportStateResponse {
+            bundle: serde_json::to_string(&bundle).expect("bundle serializes"),
+        }))
+    }
+
+    async fn import_state(
+        &self,
+        request: Request<ImportStateRequest>,
+    ) -> Result<Response<ImportStateResponse>, Status> {
+        if !self.is_admin(request.metadata()) {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Only an admin may import a server state",
+            ));
+        }
+        let bundle: Bundle = serde_json::from_str(&request.into_inner().bundle)
+            .map_err(|e| Status::new(Code::InvalidArgument, format!("Invalid bundle: {}", e)))?;
+        let trusted: Vec<&PublicKey> = self
+            .server_key
+            .iter()
+            .map(|key| &key.public)
+            .chain(&self.trusted_bundle_keys)
+            .collect();
+        let imported = bundle
+            .verify(&self.groups.default_group(), &trusted)
+            .and_then(|()| bundle.import(&self.users, &self.sessions, self.clock.now()));
+        let (users, sessions) = imported.map_err(|e| {
+            let code = match e {
+                BundleError::UntrustedSigner | BundleError::BadSignature => Code::PermissionDenied,
+                _ => Code::InvalidArgument,
+            };
+            Status::new(code, format!("Bundle rejected: {}", e))
+        })?;
+        println!(
+            "[{}] 📥 Imported {} users and {} sessions",
+            request_id::label(),
+            users,
+            sessions
+        );
+        Ok(Response::new(ImportStateResponse {
+            users: users as u32,
+            sessions: sessions as u32,
+        }))
+    }
+
+    async fn mark_decoy(
+        &self,
+        request: Request<MarkDecoyRequest>,
+    ) -> Result<Response<MarkDecoyResponse>, Status> {
+        if !self.is_admin(request.metadata()) {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Only an admin may mark decoy accounts",
+            ));
+        }
+        let request = request.into_inner();
+        if request.user.is_empty() {
+            return Err(Status::new(Code::InvalidArgument, "No user to mark"));
+        }
+        // the user doesn't have to be registered yet, nor be a name that could
+        let user_name = self.usernames.canonical(&request.user);
+        self.decoys.mark(&user_name, request.decoy);
+        Ok(Response::new(MarkDecoyResponse {}))
+    }
+
+    async fn list_decoy_hits(
+        &self,
+        request: Request<ListDecoyHitsRequest>,
+    ) -> Result<Response<ListDecoyHitsResponse>, Status> {
+        if !self.is_admin(request.metadata()) {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Only an admin may list decoy logins",
+            ));
+        }
+        let hits = self
+            .decoys
+            .hits()
+            .iter()
+            .map(|hit| DecoyHit {
+                user: hit.user_name.clone(),
+                session_id: hit.session_id.clone(),
+                request_id: hit.request_id.clone(),
+                at: unix_seconds(hit.at),
+                client: Some(client_metadata(&hit.client)),
+            })
+            .collect();
+        Ok(Response::new(ListDecoyHitsResponse { hits }))
+    }
+
+    async fn get_parameters(
+        &self,
+        request: Request<GetParametersRequest>,
+    ) -> Result<Response<GetParametersResponse>, Status> {
+        let request = request.into_inner();
+        let Some(zkp) = self.groups.get(&request.group) else {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                format!("Group {} is not supported", request.group),
+            ));
+        };
+        Ok(Response::new(GetParametersResponse {
+            group: groups::resolve(&request.group).to_string(),
+            p: zkp.p.to_bytes_be(),
+            q: zkp.q.to_bytes_be(),
+            g: zkp.g.to_bytes_be(),
+            h: zkp.h.to_bytes_be(),
+            fingerprint: groups::fingerprint(&zkp).to_vec(),
+        }))
+    }
+
+    async fn list_devices(
+        &self,
+        request: Request<ListDevicesRequest>,
+    ) -> Result<Response<ListDevicesResponse>, Status> {
+        let user_name = {
+            let (metadata, request) = (request.metadata(), request.get_ref());
+            self.device_owner(metadata, &request.user, &request.session_id, &request.mac)?
+        };
+        let devices = self
+            .devices
+            .list(&user_name)
+            .into_iter()
+            .map(|device| Device {
+                info: Some(DeviceInfo {
+                    device_id: device.device_id,
+                    name: device.name,
+                    platform: device.platform,
+                }),
+                first_seen: unix_seconds(device.first_seen),
+                last_seen: unix_seconds(device.last_seen),
+                last_client: Some(client_metadata(&device.client)),
+                logins: device.logins,
+                registered: device.registered,
+            })
+            .collect();
+        Ok(Response::new(ListDevicesResponse { devices }))
+    }
+
+    async fn revoke_device(
+        &self,
+        request: Request<RevokeDeviceRequest>,
+    ) -> Result<Response<RevokeDeviceResponse>, Status> {
+        let user_name = {
+            let (metadata, request) = (request.metadata(), request.get_ref());
+            self.device_owner(metadata, &request.user, &request.session_id, &request.mac)?
+        };
+        let request = request.into_inner();
+        // marked first: a login racing this either has its session ended
+        // below or is refused when it records the device
+        if !self.devices.revoke(&user_name, &request.device_id) {
+            return Err(Status::new(
+                Code::NotFound,
+                format!("Device {} not found", request.device_id),
+            ));
+        }
+        let ended = self.sessions.remove_device(&user_name, &request.device_id);
+        println!(
+            "[{}] 📵 Device {} of {} revoked, {} sessions ended",
+            request_id::label(),
+            request.device_id,
+            user_name,
+            ended
+        );
+        Ok(Response::new(RevokeDeviceResponse {
+            sessions_ended: ended as u32,
+        }))
+    }
+
+    async fn set_drain(
+        &self,
+        request: Request<SetDrainRequest>,
+    ) -> Result<Response<SetDrainResponse>, Status> {
+        if !self.is_admin(request.metadata()) {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Only an admin may drain the server",
+            ));
+        }
+        let draining = request.into_inner().draining;
+        let was_draining = self.drain.set(draining);
+        if draining != was_draining {
+            println!(
+                "[{}] {}",
+                request_id::label(),
+                match draining {
+                    true => "🚧 Draining: no new registrations or logins",
+                    false => "✅ Drain lifted, accepting registrations and logins",
+                }
+            );
+        }
+        Ok(Response::new(SetDrainResponse { was_draining }))
+    }
+
+    async fn reload_settings(
+        &self,
+        request: Request<ReloadSettingsRequest>,
+    ) -> Result<Response<ReloadSettingsResponse>, Status> {
+        if !self.is_admin(request.metadata()) {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Only an admin may reload the settings",
+            ));
+        }
+        let settings = AuthImpl::reload_settings(self).map_err(|e| {
+            eprintln!("[{}] ❌ Settings not reloaded: {}", request_id::label(), e);
+            Status::new(Code::FailedPrecondition, e)
+        })?;
+        println!(
+            "[{}] 🔄 Settings reloaded, session TTL {}s",
+            request_id::label(),
+            settings.session_ttl.as_secs()
+        );
+        Ok(Response::new(ReloadSettingsResponse {
+            session_ttl_seconds: settings.session_ttl.as_secs(),
+            max_sessions_per_user: settings
+                .session_limit
+                .map_or(0, |limit| limit.max_per_user.try_into().unwrap_or(u32::MAX)),
+            session_limit_policy: match settings.session_limit.map(|limit| limit.policy) {
+                None => String::new(),
+                Some(LimitPolicy::RejectNew) => "reject-new".to_string(),
+                Some(LimitPolicy::EvictOldest) => "evict-oldest".to_string(),
+            },
+            grant_realms: settings.grants.realms.len() as u32,
+            grant_users: settings.grants.users.len() as u32,
+        }))
+    }
+
+    async fn migrate_group(
+        &self,
+        request: Request<MigrateGroupRequest>,
+    ) -> Result<Response<MigrateGroupResponse>, Status> {
+        let request = request.into_inner();
+        // an admin can't make the proof, only the user
+        if request.session_id.is_empty() {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Only the user may move their verifier",
+            ));
+        }
+        let user_name = self.session_owner(&request.session_id, &request.mac)?;
+        let user = self
+            .users
+            .get(&user_name)
+            .ok_or_else(|| Status::new(Code::NotFound, format!("User {} not found", user_name)))?;
+        let (Some(from), Some(to)) = (
+            self.groups.get(&user.group),
+            self.groups.get(&request.group),
+        ) else {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                format!("Group {} is not supported", request.group),
+            ));
+        };
+        if to.p.bits() <= from.p.bits() {
+            return Err(Status::new(
+                Code::FailedPrecondition,
+                format!(
+                    "Group {} is not stronger than {}",
+                    groups::resolve(&request.group),
+                    user.group
+                ),
+            ));
+        }
+        let wire = wire(&request.group);
+        let (y1, y2) = (
+            wire.element("y1", &request.y1)?,
+            wire.element("y2", &request.y2)?,
+        );
+        let proof = Proof {
+            c: wire.scalar("c", &request.c)?,
+            s: wire.scalar("s", &request.s)?,
+        };
+        if !cross_group::verify(
+            &from,
+            (&user.y1, &user.y2),
+            &to,
+            (&y1, &y2),
+            &proof,
+            user_name.as_bytes(),
+        ) {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Cross-group proof does not verify",
+            ));
+        }
+        let moved = UserInfo {
+            y1,
+            y2,
+            group: groups::resolve(&request.group).to_string(),
+            ..user.clone()
+        };
+        if !self.users.replace(&user, moved) {
+            return Err(Status::new(
+                Code::Aborted,
+                "Verifier changed during the migration",
+            ));
+        }
+        println!(
+            "[{}] 🔀 {} moved from {} to {}",
+            request_id::label(),
+            user_name,
+            user.group,
+            groups::resolve(&request.group)
+        );
+        Ok(Response::new(MigrateGroupResponse {
+            previous_group: user.group,
+        }))
+    }
+
+    async fn recover_account(
+        &self,
+        request: Request<RecoverAccountRequest>,
+    ) -> Result<Response<RecoverAccountResponse>, Status> {
+        println!(
+            "[{}] Processing recover account request: {:?}",
+            request_id::label(),
+            request.get_ref()
+        );
+        self.check_not_draining()?;
+        let client = client_info(&request, None);
+        let cert_names = self.client_cert_names(&request);
+        let request = request.into_inner();
+        let user_name = self.usernames.canonical(&request.user);
+        self.check_client_cert(&cert_names, &user_name)?;
+        let user = self
+            .users
+            .get(&user_name)
+            .ok_or_else(|| Status::new(Code::NotFound, format!("User {} not found", user_name)))?;
+        let Some(recovery_key) = user.recovery.clone() else {
+            return Err(Status::new(
+                Code::FailedPrecondition,
+                format!("User {} has no recovery key", user_name),
+            ));
+        };
+        // the new verifier is held to what Register holds one to
+        if self.require_salted_verifier && request.salt.is_empty() {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                "Server only accepts salted verifiers",
+            ));
+        }
+        if self.groups.get(&request.group).is_none() {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                format!("Group {} is not supported", request.group),
+            ));
+        }
+        let kdf = crate::proto::kdf_from_wire(request.kdf.as_ref())
+            .map_err(|e| Status::new(Code::InvalidArgument, e))?;
+        if !request.salt.is_empty() {
+            self.kdf_policy
+                .check(&kdf)
+                .map_err(|e| Status::new(Code::InvalidArgument, e))?;
+        }
+
+        let now = self.clock.now();
+        let cooldown = self.recovery_cooldown.0;
+        if user.recovered_at.is_some_and(|at| now < at + cooldown) {
+            self.audit(AuditKind::RecoveryRefused, &user_name, client);
+            return Err(Status::new(
+                Code::FailedPrecondition,
+                format!(
+                    "Account was recovered less than {} seconds ago",
+                    cooldown.as_secs()
+                ),
+            ));
+        }
+        let zkp = self
+            .groups
+            .get(&recovery_key.group)
+            .expect("registered groups are supported");
+        let wire = wire(&request.group);
+        let (y1, y2) = (
+            wire.element("y1", &request.y1)?,
+            wire.element("y2", &request.y2)?,
+        );
+        let group = groups::resolve(&request.group).to_string();
+        let new = NewVerifier {
+            y1: &y1,
+            y2: &y2,
+            salt: &request.salt,
+            group: &group,
+            kdf: &kdf,
+        };
+        let proof = Proof {
+            c: wire.scalar("c", &request.c)?,
+            s: wire.scalar("s", &request.s)?,
+        };
+        if !recovery::is_fresh(request.issued_at, unix_seconds(now))
+            || !recovery::verify(
+                &zkp,
+                &self.application,
+                &recovery_key.key,
+                &user_name,
+                &new,
+                request.issued_at,
+                &proof,
+            )
+        {
+            self.audit(AuditKind::RecoveryRejected, &user_name, client);
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Recovery proof does not verify",
+            ));
+        }
+        let recovered = UserInfo {
+            y1,
+            y2,
+            salt: request.salt,
+            group,
+            kdf,
+            recovered_at: Some(now),
+            ..user.clone()
+        };
+        if !self.users.replace(&user, recovered) {
+            return Err(Status::new(
+                Code::Aborted,
+                "Verifier changed during the recovery",
+            ));
+        }
+        // whoever held the password may still hold a session
+        let sessions_ended = self.sessions.remove_user(&user_name);
+        self.audit(AuditKind::AccountRecovered, &user_name, client);
+        Ok(Response::new(RecoverAccountResponse {
+            sessions_ended: sessions_ended as u32,
+        }))
+    }
+
+    async fn list_audit_events(
+        &self,
+        request: Request<ListAuditEventsRequest>,
+    ) -> Result<Response<ListAuditEventsResponse>, Status> {
+        if !self.is_admin(request.metadata()) {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Only an admin may list audit events",
+            ));
+        }
+        let events = self
+            .audit
+            .events()
+            .iter()
+            .map(|event| AuditEvent {
+                kind: event.kind.as_str().to_string(),
+                user: event.user_name.clone(),
+                at: unix_seconds(event.at),
+                client: Some(client_metadata(&event.client)),
+                request_id: event.request_id.clone(),
+            })
+            .collect();
+        Ok(Response::new(ListAuditEventsResponse { events }))
+    }
+
+    async fn delete_user(
+        &self,
+        request: Request<DeleteUserRequest>,
+    ) -> Result<Response<DeleteUserResponse>, Status> {
+        if !self.is_admin(request.metadata()) {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Only an admin may delete users",
+            ));
+        }
+        let client = client_info(&request, None);
+        let request = request.into_inner();
+        let user_name = self.usernames.canonical(&request.user);
+        if request.restore {
+            if self.users.set_deleted(&user_name, None).is_none() {
+                return Err(Status::new(
+                    Code::NotFound,
+                    format!("User {} is not deleted", user_name),
+                ));
+            }
+            self.audit(AuditKind::UserRestored, &user_name, client);
+            return Ok(Response::new(DeleteUserResponse {
+                sessions_ended: 0,
+                erase_at: 0,
+            }));
+        }
+        let Some(deleted_at) = self.users.set_deleted(&user_name, Some(self.clock.now())) else {
+            return Err(Status::new(
+                Code::NotFound,
+                format!("User {} not found", user_name),
+            ));
+        };
+        let sessions_ended = self.sessions.remove_user(&user_name);
+        // nor are the failed logins counted under the name kept
+        self.record_login_success(&user_name);
+        self.audit(AuditKind::UserDeleted, &user_name, client);
+        Ok(Response::new(DeleteUserResponse {
+            sessions_ended: sessions_ended as u32,
+            erase_at: unix_seconds(deleted_at + self.deletion_retention.0),
+        }))
+    }
+
+    async fn verify_proof(
+        &self,
+        request: Request<VerifyProofRequest>,
+    ) -> Result<Response<VerifyProofResponse>, Status> {
+        let request = request.into_inner();
+        let Some(zkp) = self.groups.get(&request.group) else {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                format!("Group {} is not supported", request.group),
+            ));
+        };
+        let Ok(flavor) = ProofFlavor::try_from(request.flavor) else {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                format!("Proof flavor {} is not supported", request.flavor),
+            ));
+        };
+        let wire = wire(&request.group);
+        let y1 = wire.element("y1", &request.y1)?;
+        let y2 = wire.element("y2", &request.y2)?;
+        let proof = Proof {
+            c: wire.scalar("c", &request.c)?,
+            s: wire.scalar("s", &request.s)?,
+        };
+        let verify = move || match flavor {
+            ProofFlavor::Unspecified | ProofFlavor::ChaumPedersen => zkp.verify_non_interactive_in(
+                &request.application,
+                &y1,
+                &y2,
+                &proof,
+                &request.context,
+            ),
+            // a y2 would be a statement the proof says nothing about
+            ProofFlavor::Schnorr => {
+                request.y2.is_empty()
+                    && schnorr::verify(&zkp, &request.application, &y1, &proof, &request.context)
+            }
+        };
+        // on the verification workers like a login's answer, when there are
+        // any, so a burst of proofs doesn't hold up the runtime
+        let valid = match &self.verifier_pool {
+            None => verify(),
+            Some(pool) => pool.run(verify).await.map_err(pool_rejection)?,
+        };
+        Ok(Response::new(VerifyProofResponse { valid }))
+    }
+
+    async fn issue_tickets(
+        &self,
+        request: Request<IssueTicketsRequest>,
+    ) -> Result<Response<IssueTicketsResponse>, Status> {
+        let request = request.into_inner();
+        let (Some(limit), Some(server_key)) = (self.tickets_per_session, &self.server_key) else {
+            return Err(Status::new(
+                Code::FailedPrecondition,
+                "Server issues no tickets (--tickets-per-session and --key-file)",
+            ));
+        };
+        // the server key is in the default group
+        let zkp = self.groups.default_group();
+        let blinded: Vec<BigUint> = request
+            .blinded
+            .iter()
+            .map(|blinded| BigUint::from_bytes_be(blinded))
+            .collect();
+        if !blinded
+            .iter()
+            .all(|blinded| tickets::in_subgroup(&zkp, blinded))
+        {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                "Blinded ticket is not in the group",
+            ));
+        }
+        let wanted = u32::try_from(blinded.len()).unwrap_or(u32::MAX);
+        // counted before anything is evaluated, so the session can't ask for
+        // more than its share in parallel
+        let left =
+            self.sessions
+                .with_session_mut(&request.session_id, self.clock.now(), |session| {
+                    check_owner(session, &request.session_id, &request.mac)?;
+                    let left = limit.saturating_sub(session.tickets);
+                    if wanted > left {
+                        return Err(Status::new(
+                            Code::ResourceExhausted,
+                            format!("Session has {} tickets left", left),
+                        ));
+                    }
+                    session.tickets += wanted;
+                    Ok(left - wanted)
+                })?;
+        let tickets: Vec<EvaluatedTicket> = blinded
+            .iter()
+            .filter_map(|blinded| tickets::evaluate(&zkp, &self.application, server_key, blinded))
+            .map(|(evaluated, proof)| EvaluatedTicket {
+                evaluated: evaluated.to_bytes_be(),
+                c: proof.c.to_bytes_be(),
+                s: proof.s.to_bytes_be(),
+            })
+            .collect();
+        println!(
+            "[{}] 🎟️ {} tickets issued, {} left on the session",
+            request_id::label(),
+            tickets.len(),
+            left
+        );
+        Ok(Response::new(IssueTicketsResponse {
+            tickets,
+            tickets_left: left,
+        }))
+    }
+
+    async fn redeem_ticket(
+        &self,
+        request: Request<RedeemTicketRequest>,
+    ) -> Result<Response<RedeemTicketResponse>, Status> {
+        let request = request.into_inner();
+        let (Some(_), Some(server_key)) = (self.tickets_per_session, &self.server_key) else {
+            return Err(Status::new(
+                Code::FailedPrecondition,
+                "Server issues no tickets (--tickets-per-session and --key-file)",
+            ));
+        };
+        let ticket = Ticket {
+            nonce: request
+                .nonce
+                .try_into()
+                .map_err(|_| Status::new(Code::InvalidArgument, "Ticket nonce is not 32 bytes"))?,
+            w: BigUint::from_bytes_be(&request.w),
+        };
+        if !tickets::check(&self.groups.default_group(), server_key, &ticket) {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Ticket was not issued by this server",
+            ));
+        }
+        if !self.spent_tickets.spend(ticket.nonce) {
+            return Err(Status::new(
+                Code::AlreadyExists,
+                "Ticket was already redeemed",
+            ));
+        }
+        println!("[{}] 🎟️ Ticket redeemed", request_id::label());
+        Ok(Response::new(RedeemTicketResponse {}))
+    }
+}
@@ -0,0 +1,441 @@
+use crate::groups;
+use crate::request_id;
+use crate::service::AuthImpl;
+use crate::zkp_auth as v1;
+use crate::zkp_auth::auth_server::Auth as _;
+use crate::zkp_auth::v2::auth_server::Auth;
+use crate::zkp_auth::v2::*;
+use prost::Message;
+use std::sync::Arc;
+use tonic::{Code, Request, Response, Status};
+
+// zkp_auth.v2.Auth on top of the v1 handlers: every call is converted to its
+// v1 request, answered by the same AuthImpl (and so the same users, challenges
+// and sessions), and the answer converted back
+#[derive(Debug, Clone)]
+pub struct AuthV2(pub Arc<AuthImpl>);
+
+fn error(code: Code, reason: ErrorReason, message: impl Into<String>) -> Status {
+    let message = message.into();
+    let detail = ErrorDetail {
+        reason: reason.into(),
+        message: message.clone(),
+        request_id: request_id::current().unwrap_or_default(),
+    };
+    Status::with_details(code, message, detail.encode_to_vec().into())
+}
+
+// v1 failures that share their code with others in the same call, told apart
+// by the message, or by how it starts when it goes on with details
+const MESSAGE_REASONS: &[(&str, ErrorReason)] = &[
+    (crate::service::DEVICE_REVOKED, ErrorReason::DeviceRevoked),
+    (
+        crate::service::SESSION_LIMIT_REACHED,
+        ErrorReason::SessionLimitReached,
+    ),
+    (crate::service::SERVER_DRAINING, ErrorReason::Draining),
+    (
+        crate::service::ANSWERS_EXHAUSTED,
+        ErrorReason::AnswersExhausted,
+    ),
+    (crate::service::ACCOUNT_LOCKED, ErrorReason::AccountLocked),
+    (crate::service::QUOTA_EXCEEDED, ErrorReason::QuotaExceeded),
+    (crate::service::USER_DELETED, ErrorReason::UserDeleted),
+    (crate::service::SERVER_OVERLOADED, ErrorReason::Overloaded),
+];
+
+// a v1 error with the reason its code (or message) stands for in this call
+fn with_reason(status: Status, reasons: &[(Code, ErrorReason)]) -> Status {
+    let by_message = MESSAGE_REASONS
+        .iter()
+        .find(|(message, _)| status.message().starts_with(message))
+        .map(|(_, reason)| *reason);
+    let reason = by_message
+        .or_else(|| {
+            reasons
+                .iter()
+                .find(|(code, _)| *code == status.code())
+                .map(|(_, reason)| *reason)
+        })
+        .unwrap_or(ErrorReason::Unspecified);
+    error(status.code(), reason, status.message())
+}
+
+// the group the request is for, by the name in groups::NAMES
+fn check_group(group_id: &str) -> Result<&str, Status> {
+    let name = groups::resolve(group_id);
+    match groups::NAMES.contains(&name) {
+        true => Ok(name),
+        false => Err(error(
+            Code::InvalidArgument,
+            ErrorReason::UnsupportedGroup,
+            format!(
+                "Group {} is not supported, only {}",
+                group_id,
+                groups::NAMES.join(", ")
+            ),
+        )),
+    }
+}
+
+// the flavor the fields describe, it must agree with a flavor that is given
+fn resolve_flavor(request: &AuthenticationChallengeRequest) -> Result<Flavor, Status> {
+    let inferred = if !request.nonce_commitment.is_empty() {
+        Flavor::NonceCommitment
+    } else if request.r1.is_empty() && request.r2.is_empty() {
+        Flavor::NonInteractive
+    } else {
+        Flavor::Interactive
+    };
+    match request.flavor() {
+        Flavor::Unspecified => Ok(inferred),
+        flavor if flavor == inferred => Ok(flavor),
+        flavor => Err(error(
+            Code::InvalidArgument,
+            ErrorReason::FlavorMismatch,
+            format!(
+                "Flavor {} doesn't match the request fields ({})",
+                flavor.as_str_name(),
+                inferred.as_str_name()
+            ),
+        )),
+    }
+}
+
+impl From<RegisterRequest> for v1::RegisterRequest {
+    fn from(request: RegisterRequest) -> Self {
+        v1::RegisterRequest {
+            user: request.user,
+            y1: request.y1,
+            y2: request.y2,
+            salt: request.salt,
+            group: request.group_id,
+            kdf: request.kdf.map(Into::into),
+            device: request.device.map(Into::into),
+            recovery_key: None,
+        }
+    }
+}
+
+impl From<DeviceInfo> for v1::DeviceInfo {
+    fn from(device: DeviceInfo) -> Self {
+        v1::DeviceInfo {
+            device_id: device.device_id,
+            name: device.name,
+            platform: device.platform,
+        }
+    }
+}
+
+// the same fields in both versions
+impl From<KdfParams> for v1::KdfParams {
+    fn from(kdf: KdfParams) -> Self {
+        v1::KdfParams {
+            algorithm: kdf.algorithm,
+            iterations: kdf.iterations,
+            memory_kib: kdf.memory_kib,
+            passes: kdf.passes,
+            parallelism: kdf.parallelism,
+        }
+    }
+}
+
+impl From<v1::KdfParams> for KdfParams {
+    fn from(kdf: v1::KdfParams) -> Self {
+        KdfParams {
+            algorithm: kdf.algorithm,
+            iterations: kdf.iterations,
+            memory_kib: kdf.memory_kib,
+            passes: kdf.passes,
+            parallelism: kdf.parallelism,
+        }
+    }
+}
+
+impl From<BlindedIdentity> for v1::BlindedIdentity {
+    fn from(blinded: BlindedIdentity) -> Self {
+        v1::BlindedIdentity {
+            u_public: blinded.u_public,
+            sealed_user: blinded.sealed_user,
+            sealed_t: blinded.sealed_t,
+        }
+    }
+}
+
+impl From<AuthenticationChallengeRequest> for v1::AuthenticationChallengeRequest {
+    fn from(request: AuthenticationChallengeRequest) -> Self {
+        v1::AuthenticationChallengeRequest {
+            user: request.user,
+            r1: request.r1,
+            r2: request.r2,
+            blinded: request.blinded.map(Into::into),
+            nonce_commitment: request.nonce_commitment,
+            group: request.group_id,
+            // v2 registrations aren't receipted
+            receipt: None,
+        }
+    }
+}
+
+impl From<AuthenticationAnswerRequest> for v1::AuthenticationAnswerRequest {
+    fn from(request: AuthenticationAnswerRequest) -> Self {
+        v1::AuthenticationAnswerRequest {
+            auth_id: request.auth_id,
+            s: request.s,
+            server_c: request.server_c,
+            r1: request.r1,
+            r2: request.r2,
+            c: request.c,
+            channel_binding: request.channel_binding,
+            second_factor: request.second_factor,
+            device: request.device.map(Into::into),
+        }
+    }
+}
+
+impl From<v1::ClientMetadata> for ClientMetadata {
+    fn from(client: v1::ClientMetadata) -> Self {
+        ClientMetadata {
+            remote_addr: client.remote_addr,
+            user_agent: client.user_agent,
+            device_id: client.device_id,
+        }
+    }
+}
+
+impl AuthV2 {
+    fn session(&self, session_id: String) -> Result<Session, Status> {
+        self.0
+            .sessions
+            .with_session(&session_id, self.0.clock.now(), |session| {
+                Ok(Session {
+                    session_id: session_id.clone(),
+                    user: session.user_name.clone(),
+                    expires_at: crate::service::unix_seconds(session.expires_at),
+                    has_key: session.key.is_some(),
+                    scopes: session.grant.scopes.clone(),
+                    claims: session.grant.claims.clone().into_iter().collect(),
+                })
+            })
+    }
+}
+
+const SESSION_REASONS: &[(Code, ErrorReason)] =
+    &[(Code::Unauthenticated, ErrorReason::SessionInvalid)];
+
+#[tonic::async_trait]
+impl Auth for AuthV2 {
+    async fn register(
+        &self,
+        request: Request<RegisterRequest>,
+    ) -> Result<Response<RegisterResponse>, Status> {
+        let group_id = check_group(&request.get_ref().group_id)?.to_string();
+        let response = self
+            .0
+            // with the metadata and the peer address, kept with the device
+            .register(request.map(Into::into))
+            .await
+            .map_err(|e| with_reason(e, &[(Code::InvalidArgument, ErrorReason::InvalidArgument)]))?
+            .into_inner();
+        Ok(Response::new(RegisterResponse {
+            group_id,
+            kdf: response.kdf.map(Into::into),
+        }))
+    }
+
+    async fn create_authentication_challenge(
+        &self,
+        request: Request<AuthenticationChallengeRequest>,
+    ) -> Result<Response<AuthenticationChallengeResponse>, Status> {
+        // mapped rather than rebuilt, so the v1 call sees the connection's
+        // client certificate
+        let group_id = check_group(&request.get_ref().group_id)?.to_string();
+        let flavor = resolve_flavor(request.get_ref())?;
+        let response = self
+            .0
+            .create_authentication_challenge(request.map(Into::into))
+            .await
+            .map_err(|e| {
+                with_reason(
+                    e,
+                    &[
+                        (Code::NotFound, ErrorReason::UserNotFound),
+                        (Code::InvalidArgument, ErrorReason::InvalidArgument),
+                        (Code::FailedPrecondition, ErrorReason::ServerKeyUnavailable),
+                    ],
+                )
+            })?
+            .into_inner();
+        Ok(Response::new(AuthenticationChallengeResponse {
+            auth_id: response.auth_id,
+            group_id,
+            flavor: flavor.into(),
+            c: response.c,
+            server_r1: response.server_r1,
+            server_r2: response.server_r2,
+            salt: response.salt,
+            kdf: response.kdf.map(Into::into),
+            sealed_kdf: response.sealed_kdf,
+        }))
+    }
+
+    async fn verify_authentication(
+        &self,
+        request: Request<AuthenticationAnswerRequest>,
+    ) -> Result<Response<AuthenticationAnswerResponse>, Status> {
+        let response = self
+            .0
+            // with the metadata and the peer address, kept with the session
+            .verify_authentication(request.map(Into::into))
+            .await
+            .map_err(|e| {
+                with_reason(
+                    e,
+                    &[
+                        (Code::NotFound, ErrorReason::AuthIdNotFound),
+                        (Code::PermissionDenied, ErrorReason::NotVerified),
+                        (Code::FailedPrecondition, ErrorReason::ServerKeyUnavailable),
+                        (Code::Unauthenticated, ErrorReason::SecondFactorRejected),
+                    ],
+                )
+            })?
+            .into_inner();
+        Ok(Response::new(AuthenticationAnswerResponse {
+            session: Some(self.session(response.session_id)?),
+            server_s: response.server_s,
+            key_share: response.key_share,
+            key_confirmation: response.key_confirmation,
+            second_factor: response.second_factor,
+        }))
+    }
+
+    async fn validate_session(
+        &self,
+        request: Request<ValidateSessionRequest>,
+    ) -> Result<Response<ValidateSessionResponse>, Status> {
+        let request = request.into_inner();
+        let session_id = request.session_id.clone();
+        self.0
+            .validate_session(Request::new(v1::ValidateSessionRequest {
+                session_id: request.session_id,
+                mac: request.mac,
+            }))
+            .await
+            .map_err(|e| with_reason(e, SESSION_REASONS))?;
+        Ok(Response::new(ValidateSessionResponse {
+            session: Some(
+                self.session(session_id)
+                    .map_err(|e| with_reason(e, SESSION_REASONS))?,
+            ),
+        }))
+    }
+
+    async fn refresh_session(
+        &self,
+        request: Request<RefreshSessionRequest>,
+    ) -> Result<Response<RefreshSessionResponse>, Status> {
+        let response = self
+            .0
+            .refresh_session(Request::new(v1::RefreshSessionRequest {
+                session_id: request.into_inner().session_id,
+            }))
+            .await
+            .map_err(|e| with_reason(e, SESSION_REASONS))?
+            .into_inner();
+        Ok(Response::new(RefreshSessionResponse {
+            session: Some(
+                self.session(response.session_id)
+                    .map_err(|e| with_reason(e, SESSION_REASONS))?,
+            ),
+        }))
+    }
+
+    async fn logout(
+        &self,
+        request: Request<LogoutRequest>,
+    ) -> Result<Response<LogoutResponse>, Status> {
+        self.0
+            .logout(Request::new(v1::LogoutRequest {
+                session_id: request.into_inner().session_id,
+            }))
+            .await
+            .map_err(|e| with_reason(e, SESSION_REASONS))?;
+        Ok(Response::new(LogoutResponse {}))
+    }
+
+    async fn get_session_info(
+        &self,
+        request: Request<GetSessionInfoRequest>,
+    ) -> Result<Response<GetSessionInfoResponse>, Status> {
+        let session_id = request.get_ref().session_id.clone();
+        // the admin token travels in the metadata
+        let request = request.map(|request| v1::GetSessionInfoRequest {
+            session_id: request.session_id,
+            mac: request.mac,
+        });
+        let response = self
+            .0
+            .get_session_info(request)
+            .await
+            .map_err(|e| {
+                with_reason(
+                    e,
+                    &[
+                        (Code::Unauthenticated, ErrorReason::SessionInvalid),
+                        (Code::PermissionDenied, ErrorReason::NotAuthorized),
+                    ],
+                )
+            })?
+            .into_inner();
+        Ok(Response::new(GetSessionInfoResponse {
+            session: Some(
+                self.session(session_id)
+                    .map_err(|e| with_reason(e, SESSION_REASONS))?,
+            ),
+            created_at: response.created_at,
+            realm: response.realm,
+            client: response.client.map(Into::into),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flavor_is_checked_against_the_fields() {
+        let interactive = AuthenticationChallengeRequest {
+            r1: vec![1],
+            r2: vec![2],
+            ..Default::default()
+        };
+        assert_eq!(resolve_flavor(&interactive).unwrap(), Flavor::Interactive);
+
+        let committed = AuthenticationChallengeRequest {
+            nonce_commitment: vec![0; 32],
+            flavor: Flavor::Interactive.into(),
+            ..Default::default()
+        };
+        let status = resolve_flavor(&committed).unwrap_err();
+        let detail = ErrorDetail::decode(status.details()).unwrap();
+        assert_eq!(detail.reason(), ErrorReason::FlavorMismatch);
+
+        let non_interactive = AuthenticationChallengeRequest::default();
+        assert_eq!(
+            resolve_flavor(&non_interactive).unwrap(),
+            Flavor::NonInteractive
+        );
+
+        assert!(check_group("").is_ok());
+        assert_eq!(check_group("").unwrap(), groups::DEFAULT);
+        assert_eq!(
+            check_group(groups::RFC5114_2048_256).unwrap(),
+            groups::RFC5114_2048_256
+        );
+        let status = check_group("toy-23-11").unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        let detail = ErrorDetail::decode(status.details()).unwrap();
+        assert_eq!(detail.reason(), ErrorReason::UnsupportedGroup);
+    }
+}
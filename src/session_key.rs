@@ -0,0 +1,146 @@
+use crate::secret::SecretBigUint;
+use crate::transcript::Transcript;
+use crate::ZKP;
+use hmac::{Hmac, Mac};
+use num_bigint::BigUint;
+use sha2::Sha256;
+use std::fmt::Debug;
+use zeroize::Zeroize;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// key shared by client and server after a successful login. the client's
+// commitment r1 = g ** k mod p doubles as its Diffie-Hellman share, so it is
+// authenticated by the proof itself: shared = r1 ** b = key_share ** k mod p
+pub struct SessionKey([u8; 32]);
+
+impl SessionKey {
+    // K = H(shared, r1, r2, key_share, auth_id)
+    pub fn derive(
+        shared: &BigUint,
+        r1: &BigUint,
+        r2: &BigUint,
+        key_share: &BigUint,
+        auth_id: &str,
+    ) -> SessionKey {
+        let mut transcript = Transcript::new(b"zkp-chaum-pedersen/session-key");
+        transcript.append_biguint(b"shared", shared);
+        transcript.append_biguint(b"r1", r1);
+        transcript.append_biguint(b"r2", r2);
+        transcript.append_biguint(b"key_share", key_share);
+        transcript.append(b"auth_id", auth_id.as_bytes());
+        SessionKey(transcript.finalize())
+    }
+
+    // for moving sessions between servers (bundle.rs), never sent to a client
+    #[cfg(feature = "server")]
+    pub(crate) fn from_bytes(key: [u8; 32]) -> SessionKey {
+        SessionKey(key)
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    pub fn mac(&self, message: &[u8]) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC takes any key length");
+        mac.update(message);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    // constant-time comparison
+    pub fn verify_mac(&self, message: &[u8], tag: &[u8]) -> bool {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC takes any key length");
+        mac.update(message);
+        mac.verify_slice(tag).is_ok()
+    }
+
+    // sent by the server so the client knows both sides derived the same key
+    pub fn confirmation(&self) -> Vec<u8> {
+        self.mac(b"server key confirmation")
+    }
+
+    pub fn verify_confirmation(&self, tag: &[u8]) -> bool {
+        self.verify_mac(b"server key confirmation", tag)
+    }
+}
+
+impl Drop for SessionKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Debug for SessionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SessionKey(<redacted>)")
+    }
+}
+
+// server side: b random, key_share = g ** b mod p, shared = r1 ** b mod p
+pub fn server_key_share(zkp: &ZKP, r1: &BigUint) -> (BigUint, BigUint) {
+    let b = SecretBigUint::new(ZKP::generate_random_number_below(&zkp.q));
+    let key_share = ZKP::exponentiate(&zkp.g, b.expose(), &zkp.p);
+    let shared = ZKP::exponentiate(r1, b.expose(), &zkp.p);
+    (key_share, shared)
+}
+
+// client side: shared = key_share ** k mod p, once key_share is checked to
+// be a non-trivial element of the order-q subgroup
+pub fn client_shared_secret(zkp: &ZKP, key_share: &BigUint, k: &BigUint) -> Option<BigUint> {
+    let one = BigUint::from(1u32);
+    if *key_share <= one
+        || *key_share >= zkp.p
+        || ZKP::exponentiate(key_share, &zkp.q, &zkp.p) != one
+    {
+        return None;
+    }
+    Some(ZKP::exponentiate(key_share, k, &zkp.p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_and_server_derive_same_key() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        let r1 = ZKP::exponentiate(&zkp.g, &k, &zkp.p);
+        let r2 = ZKP::exponentiate(&zkp.h, &k, &zkp.p);
+
+        let (key_share, server_shared) = server_key_share(&zkp, &r1);
+        let server_key = SessionKey::derive(&server_shared, &r1, &r2, &key_share, "auth");
+
+        let client_shared = client_shared_secret(&zkp, &key_share, &k).unwrap();
+        let client_key = SessionKey::derive(&client_shared, &r1, &r2, &key_share, "auth");
+
+        assert!(client_key.verify_confirmation(&server_key.confirmation()));
+        let tag = client_key.mac(b"request");
+        assert!(server_key.verify_mac(b"request", &tag));
+        assert!(!server_key.verify_mac(b"other request", &tag));
+
+        // bound to the login
+        let other_key = SessionKey::derive(&client_shared, &r1, &r2, &key_share, "other");
+        assert!(!other_key.verify_confirmation(&server_key.confirmation()));
+    }
+
+    #[test]
+    fn test_rejects_trivial_key_shares() {
+        let zkp = ZKP {
+            p: BigUint::from(23u32),
+            q: BigUint::from(11u32),
+            g: BigUint::from(4u32),
+            h: BigUint::from(9u32),
+        };
+        let k = BigUint::from(3u32);
+        for key_share in [0u32, 1, 22, 23, 5] {
+            // 22 = -1 and 5 are outside the order-11 subgroup
+            assert!(client_shared_secret(&zkp, &BigUint::from(key_share), &k).is_none());
+        }
+        assert!(client_shared_secret(&zkp, &BigUint::from(4u32), &k).is_some());
+    }
+}
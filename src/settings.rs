@@ -0,0 +1,168 @@
+use crate::grants::GrantPolicy;
+use crate::quota::QuotaPolicy;
+use crate::state::{LimitPolicy, SessionLimit};
+use serde::Deserialize;
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// what a running server can change without a restart, on SIGHUP or the
+// ReloadSettings RPC. users, challenges and sessions are untouched by a
+// reload; a login reads the settings once and uses them throughout, and a
+// reload swaps them whole, so no login sees half of one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    // how long a session ID stays valid after login or refresh
+    pub session_ttl: Duration,
+    // sessions a user may hold at once, unlimited when None
+    pub session_limit: Option<SessionLimit>,
+    // scopes and claims sessions are issued with
+    pub grants: GrantPolicy,
+    // users, sessions and logins a minute each realm may have
+    pub quotas: QuotaPolicy,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            session_ttl: Duration::from_secs(60 * 60),
+            session_limit: None,
+            grants: GrantPolicy::default(),
+            quotas: QuotaPolicy::default(),
+        }
+    }
+}
+
+impl Settings {
+    // settings a server can't run with, refused before they are swapped in
+    pub fn check(&self) -> Result<(), String> {
+        if self.session_ttl.is_zero() {
+            return Err("session TTL must be at least 1 second".to_string());
+        }
+        if self
+            .session_limit
+            .is_some_and(|limit| limit.max_per_user == 0)
+        {
+            return Err("session limit must allow at least 1 session".to_string());
+        }
+        Ok(())
+    }
+}
+
+// the settings file, JSON with any of these fields; a field left out keeps
+// the value from the command line
+// {"session_ttl_seconds": 900, "max_sessions_per_user": 3,
+//  "session_limit_policy": "evict-oldest"}
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SettingsFile {
+    pub session_ttl_seconds: Option<u64>,
+    pub max_sessions_per_user: Option<usize>,
+    pub session_limit_policy: Option<LimitPolicy>,
+}
+
+impl SettingsFile {
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let json = std::fs::read(path)?;
+        serde_json::from_slice(&json)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(seconds) = self.session_ttl_seconds {
+            settings.session_ttl = Duration::from_secs(seconds);
+        }
+        let policy = self
+            .session_limit_policy
+            .or(settings.session_limit.map(|limit| limit.policy))
+            .unwrap_or(LimitPolicy::RejectNew);
+        if let Some(max_per_user) = self.max_sessions_per_user {
+            settings.session_limit = Some(SessionLimit {
+                max_per_user,
+                policy,
+            });
+        }
+        if let Some(limit) = &mut settings.session_limit {
+            limit.policy = policy;
+        }
+    }
+}
+
+// where a reload reads the settings from, e.g. the server's flags and files
+pub trait SettingsSource: Debug + Send + Sync {
+    fn load(&self) -> Result<Settings, String>;
+}
+
+// the settings in force
+#[derive(Debug, Default)]
+pub struct LiveSettings(RwLock<Arc<Settings>>);
+
+impl From<Settings> for LiveSettings {
+    fn from(settings: Settings) -> Self {
+        LiveSettings(RwLock::new(Arc::new(settings)))
+    }
+}
+
+impl LiveSettings {
+    pub fn current(&self) -> Arc<Settings> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn replace(&self, settings: Settings) {
+        *self.0.write().unwrap() = Arc::new(settings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_overrides_what_it_names() {
+        let file: SettingsFile = serde_json::from_str(
+            r#"{"max_sessions_per_user": 3, "session_limit_policy": "evict-oldest"}"#,
+        )
+        .unwrap();
+        let mut settings = Settings::default();
+        file.apply(&mut settings);
+        assert_eq!(settings.session_ttl, Duration::from_secs(3600));
+        assert_eq!(
+            settings.session_limit,
+            Some(SessionLimit {
+                max_per_user: 3,
+                policy: LimitPolicy::EvictOldest,
+            })
+        );
+
+        // the policy alone changes a limit from the command line
+        let file: SettingsFile = serde_json::from_str(
+            r#"{"session_ttl_seconds": 60, "session_limit_policy": "reject-new"}"#,
+        )
+        .unwrap();
+        file.apply(&mut settings);
+        assert_eq!(settings.session_ttl, Duration::from_secs(60));
+        assert_eq!(
+            settings.session_limit.unwrap().policy,
+            LimitPolicy::RejectNew
+        );
+        assert!(settings.check().is_ok());
+
+        assert!(serde_json::from_str::<SettingsFile>(r#"{"session_ttl": 60}"#).is_err());
+        let file: SettingsFile = serde_json::from_str(r#"{"session_ttl_seconds": 0}"#).unwrap();
+        file.apply(&mut settings);
+        assert!(settings.check().is_err());
+    }
+
+    #[test]
+    fn test_replaced_whole() {
+        let live = LiveSettings::from(Settings::default());
+        let before = live.current();
+        live.replace(Settings {
+            session_ttl: Duration::from_secs(60),
+            ..Settings::default()
+        });
+        assert_eq!(before.session_ttl, Duration::from_secs(3600));
+        assert_eq!(live.current().session_ttl, Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,349 @@
+use crate::groups;
+use crate::keys::PublicKey;
+use crate::sealing::{Envelope, StoreKey};
+use crate::state::{RecoveryKey, UserInfo, UserStore};
+use crate::verifier::KdfParams;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+// registrations written out of a UserStore, so they survive a restart and can
+// be moved to another store. only the verifier is kept (y1, y2, salt, group),
+// never anything a login could be made from
+pub const VERSION: u32 = 1;
+
+// associated data of a sealed snapshot, so no other sealed file opens as one
+const SEAL_LABEL: &[u8] = b"zkp-chaum-pedersen/user-snapshot";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserSnapshot {
+    pub version: u32,
+    pub users: Vec<UserRecord>,
+}
+
+// on-disk form of a UserInfo, numbers are big-endian hex
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserRecord {
+    pub user: String,
+    pub y1: String,
+    pub y2: String,
+    pub salt: String,
+    pub group: String,
+    // absent from snapshots written before KDF parameters were kept
+    #[serde(default)]
+    pub kdf: KdfParams,
+    // absent for users registered without a recovery key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovery: Option<RecoveryRecord>,
+    // seconds since the Unix epoch, absent for accounts never recovered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovered_at: Option<u64>,
+    // seconds since the Unix epoch, absent for users not deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<u64>,
+}
+
+// on-disk form of a RecoveryKey
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveryRecord {
+    pub y1: String,
+    pub y2: String,
+    pub group: String,
+}
+
+fn invalid_data(e: impl ToString) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn unix_seconds(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl UserRecord {
+    pub fn from_user(user: &UserInfo) -> Self {
+        UserRecord {
+            user: user.user_name.clone(),
+            y1: hex::encode(user.y1.to_bytes_be()),
+            y2: hex::encode(user.y2.to_bytes_be()),
+            salt: hex::encode(&user.salt),
+            group: groups::resolve(&user.group).to_string(),
+            kdf: user.kdf,
+            recovery: user.recovery.as_ref().map(|recovery| RecoveryRecord {
+                y1: hex::encode(recovery.key.y1.to_bytes_be()),
+                y2: hex::encode(recovery.key.y2.to_bytes_be()),
+                group: groups::resolve(&recovery.group).to_string(),
+            }),
+            recovered_at: user.recovered_at.map(unix_seconds),
+            deleted_at: user.deleted_at.map(unix_seconds),
+        }
+    }
+
+    // a record for a group the server doesn't know can't be logged in to
+    pub fn to_user(&self) -> std::io::Result<UserInfo> {
+        if groups::by_name(&self.group).is_none() {
+            return Err(invalid_data(format!(
+                "user {} is in unknown group {}",
+                self.user, self.group
+            )));
+        }
+        let decode = |value: &str| hex::decode(value).map_err(invalid_data);
+        let recovery = match &self.recovery {
+            Some(recovery) if groups::by_name(&recovery.group).is_none() => {
+                return Err(invalid_data(format!(
+                    "recovery key of user {} is in unknown group {}",
+                    self.user, recovery.group
+                )));
+            }
+            Some(recovery) => Some(RecoveryKey {
+                key: PublicKey {
+                    y1: BigUint::from_bytes_be(&decode(&recovery.y1)?),
+                    y2: BigUint::from_bytes_be(&decode(&recovery.y2)?),
+                },
+                group: groups::resolve(&recovery.group).to_string(),
+            }),
+            None => None,
+        };
+        Ok(UserInfo {
+            user_name: self.user.clone(),
+            y1: BigUint::from_bytes_be(&decode(&self.y1)?),
+            y2: BigUint::from_bytes_be(&decode(&self.y2)?),
+            salt: decode(&self.salt)?,
+            group: groups::resolve(&self.group).to_string(),
+            kdf: self.kdf,
+            recovery,
+            recovered_at: self
+                .recovered_at
+                .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds)),
+            deleted_at: self
+                .deleted_at
+                .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds)),
+        })
+    }
+}
+
+impl UserSnapshot {
+    pub fn of(store: &UserStore) -> Self {
+        UserSnapshot {
+            version: VERSION,
+            users: store.all().iter().map(UserRecord::from_user).collect(),
+        }
+    }
+
+    // checks every record before the store sees any of them
+    pub fn users(&self) -> std::io::Result<Vec<UserInfo>> {
+        if self.version != VERSION {
+            return Err(invalid_data(format!(
+                "snapshot version {} is not supported, only {}",
+                self.version, VERSION
+            )));
+        }
+        self.users.iter().map(UserRecord::to_user).collect()
+    }
+
+    pub fn restore(&self, store: &UserStore) -> std::io::Result<usize> {
+        let users = self.users()?;
+        let count = users.len();
+        for user in users {
+            store.restore(user);
+        }
+        Ok(count)
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Self::load_with(path, None)
+    }
+
+    // a snapshot sealed with a store key (see sealing) needs the key; a plain
+    // one loads with or without it, so a store can be moved to sealed
+    // snapshots by writing it out again
+    pub fn load_with(path: &Path, key: Option<&StoreKey>) -> std::io::Result<Self> {
+        serde_json::from_slice(&Self::read(path, key)?).map_err(invalid_data)
+    }
+
+    // the JSON of the snapshot, opened if it is sealed
+    fn read(path: &Path, key: Option<&StoreKey>) -> std::io::Result<Zeroizing<Vec<u8>>> {
+        let contents = Zeroizing::new(std::fs::read(path)?);
+        let Ok(envelope) = serde_json::from_slice::<Envelope>(&contents) else {
+            return Ok(contents);
+        };
+        let key =
+            key.ok_or_else(|| invalid_data("the snapshot is sealed, it needs a store key"))?;
+        key.open(SEAL_LABEL, &envelope).map_err(invalid_data)
+    }
+
+    // the snapshot, checked like a restore, and the users whose records
+    // aren't written as this release writes them, e.g. without a field added
+    // since. they load all the same, but a snapshot written again (migrate
+    // with the file as both --from and --to) is brought up to date
+    pub fn check(path: &Path, key: Option<&StoreKey>) -> std::io::Result<(Self, Vec<String>)> {
+        let json = Self::read(path, key)?;
+        let snapshot: Self = serde_json::from_slice(&json).map_err(invalid_data)?;
+        snapshot.users()?;
+        let raw: serde_json::Value = serde_json::from_slice(&json).map_err(invalid_data)?;
+        let records = raw["users"].as_array().cloned().unwrap_or_default();
+        let drifted = snapshot
+            .users
+            .iter()
+            .zip(records)
+            .filter(|(record, raw)| {
+                serde_json::to_value(record).expect("records serialize") != *raw
+            })
+            .map(|(record, _)| record.user.clone())
+            .collect();
+        Ok((snapshot, drifted))
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        self.save_with(path, None)
+    }
+
+    // sealed when given a key. written next to the target and renamed over
+    // it, so a crash never leaves half a snapshot behind
+    pub fn save_with(&self, path: &Path, key: Option<&StoreKey>) -> std::io::Result<()> {
+        let json = Zeroizing::new(serde_json::to_string_pretty(self).expect("snapshot serializes"));
+        let contents = match key {
+            Some(key) => serde_json::to_string_pretty(&key.seal(SEAL_LABEL, json.as_bytes()))
+                .expect("envelope serializes"),
+            None => json.to_string(),
+        };
+        let temporary = path.with_extension("tmp");
+        std::fs::File::create(&temporary)?.write_all(contents.as_bytes())?;
+        std::fs::rename(&temporary, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let store = UserStore::default();
+        store.insert(UserInfo {
+            user_name: "bob".to_string(),
+            y1: BigUint::from(2u32),
+            y2: BigUint::from(3u32),
+            salt: vec![1, 2],
+            group: groups::RFC5114_2048_256.to_string(),
+            kdf: KdfParams::argon2id(),
+            recovery: Some(RecoveryKey {
+                key: PublicKey {
+                    y1: BigUint::from(6u32),
+                    y2: BigUint::from(7u32),
+                },
+                group: groups::DEFAULT.to_string(),
+            }),
+            recovered_at: Some(UNIX_EPOCH + Duration::from_secs(1000)),
+            deleted_at: None,
+        });
+        store.insert(UserInfo {
+            user_name: "alice".to_string(),
+            y1: BigUint::from(4u32),
+            y2: BigUint::from(5u32),
+            salt: Vec::new(),
+            group: String::new(),
+            kdf: KdfParams::default(),
+            ..UserInfo::default()
+        });
+
+        let snapshot = UserSnapshot::of(&store);
+        assert_eq!(snapshot.users[0].user, "alice");
+        assert_eq!(snapshot.users[0].group, groups::DEFAULT);
+
+        let path = std::env::temp_dir().join(format!("users-{}.json", std::process::id()));
+        snapshot.save(&path).unwrap();
+        let loaded = UserSnapshot::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, snapshot);
+
+        let restored = UserStore::default();
+        assert_eq!(loaded.restore(&restored).unwrap(), 2);
+        let bob = restored.get("bob").unwrap();
+        assert_eq!((bob.y1, bob.salt), (BigUint::from(2u32), vec![1, 2]));
+        assert_eq!(bob.kdf, KdfParams::argon2id());
+        assert_eq!(bob.recovery.unwrap().key.y2, BigUint::from(7u32));
+        assert_eq!(
+            bob.recovered_at,
+            Some(UNIX_EPOCH + Duration::from_secs(1000))
+        );
+        assert!(restored.get("alice").unwrap().recovery.is_none());
+
+        // nothing is restored from a snapshot with a bad record
+        let mut bad = loaded.clone();
+        bad.users[1].group = "toy-23-11".to_string();
+        let empty = UserStore::default();
+        assert!(bad.restore(&empty).is_err());
+        assert!(empty.get("alice").is_none());
+        bad.version = 2;
+        assert!(bad.users().is_err());
+    }
+
+    #[test]
+    fn test_sealed_snapshots_need_the_store_key() {
+        let snapshot = UserSnapshot {
+            version: VERSION,
+            users: vec![UserRecord::from_user(&UserInfo {
+                user_name: "alice".to_string(),
+                y1: BigUint::from(4u32),
+                y2: BigUint::from(5u32),
+                ..UserInfo::default()
+            })],
+        };
+        let key = StoreKey::new([7; 32]);
+        let path = std::env::temp_dir().join(format!("sealed-users-{}.json", std::process::id()));
+        snapshot.save_with(&path, Some(&key)).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("alice"));
+        assert!(UserSnapshot::load(&path).is_err());
+        assert!(UserSnapshot::load_with(&path, Some(&StoreKey::new([8; 32]))).is_err());
+        assert_eq!(
+            UserSnapshot::load_with(&path, Some(&key)).unwrap(),
+            snapshot
+        );
+
+        // a plain snapshot still loads where a key is configured
+        snapshot.save(&path).unwrap();
+        assert_eq!(
+            UserSnapshot::load_with(&path, Some(&key)).unwrap(),
+            snapshot
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_check_finds_records_not_in_the_current_form() {
+        let path = std::env::temp_dir().join(format!("drift-users-{}.json", std::process::id()));
+        let record = UserRecord::from_user(&UserInfo {
+            user_name: "bob".to_string(),
+            y1: BigUint::from(2u32),
+            y2: BigUint::from(3u32),
+            ..UserInfo::default()
+        });
+        let snapshot = UserSnapshot {
+            version: VERSION,
+            users: vec![record],
+        };
+        snapshot.save(&path).unwrap();
+        assert_eq!(
+            UserSnapshot::check(&path, None).unwrap(),
+            (snapshot.clone(), Vec::new())
+        );
+
+        // written before KDF parameters were kept
+        let mut old = serde_json::to_value(&snapshot).unwrap();
+        old["users"][0].as_object_mut().unwrap().remove("kdf");
+        std::fs::write(&path, old.to_string()).unwrap();
+        let (loaded, drifted) = UserSnapshot::check(&path, None).unwrap();
+        assert_eq!((loaded, drifted), (snapshot, vec!["bob".to_string()]));
+
+        old["version"] = (VERSION + 1).into();
+        std::fs::write(&path, old.to_string()).unwrap();
+        assert!(UserSnapshot::check(&path, None).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
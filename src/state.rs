@@ -0,0 +1,1469 @@
+use crate::grants::Grant;
+use crate::keys::PublicKey;
+use crate::secret::{Redacted, SecretBigUint};
+use crate::session_key::SessionKey;
+use crate::username::NameHasher;
+use crate::verifier::KdfParams;
+use crate::ZKP;
+use num_bigint::BigUint;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+use subtle::ConstantTimeEq;
+use tonic::{Code, Status};
+
+#[cfg(zkp_loom)]
+use loom::sync::Mutex;
+#[cfg(not(zkp_loom))]
+use std::sync::Mutex;
+
+// state shared by the RPC handlers: registered users, pending challenges and
+// sessions, each behind its own lock. every operation takes exactly one lock
+// and does its whole read-modify-write under it, so there is no lock order to
+// get wrong and nothing changes between a check and the update it guards.
+// RUSTFLAGS="--cfg zkp_loom" swaps in loom's Mutex for the tests at the bottom
+
+#[derive(Debug, Clone, Default)]
+pub struct UserInfo {
+    pub user_name: String,
+    pub y1: BigUint,
+    pub y2: BigUint,
+    // empty for verifiers computed from the raw password
+    pub salt: Vec<u8>,
+    // name of the group y1/y2 are in, see groups
+    pub group: String,
+    // how x was derived from the password, for salted verifiers
+    pub kdf: KdfParams,
+    // registered at signup to set a new verifier with, see recovery
+    pub recovery: Option<RecoveryKey>,
+    // when a recovery last set the verifier, the next one waits out a cooldown
+    pub recovered_at: Option<SystemTime>,
+    // when an admin deleted the user: no login from then on, and the
+    // registration is erased once the retention has passed, see
+    // AuthImpl::erase_deleted_users
+    pub deleted_at: Option<SystemTime>,
+}
+
+// public half of a recovery key pair, in the group it was made in: a
+// migration moves the verifier, not this
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveryKey {
+    pub key: PublicKey,
+    pub group: String,
+}
+
+// one outstanding challenge, keyed by its auth_id so two logins of the same
+// user don't overwrite each other
+#[derive(Debug)]
+pub struct Challenge {
+    pub user_name: String,
+    pub r1: BigUint,
+    pub r2: BigUint,
+    // nonce-commitment variant: H(r1, r2), r1/r2 are revealed with the answer
+    pub nonce_commitment: Vec<u8>,
+    // non-interactive variant: no r1/r2 yet, the answer is a proof bound to the auth_id
+    pub non_interactive: bool,
+    pub c: BigUint,
+    // mutual authentication: nonce behind server_r1/server_r2
+    pub server_k: Option<SecretBigUint>,
+    // blinded login: t of the challenge
+    pub blinding: Option<SecretBigUint>,
+    // rejected answers so far, see AuthImpl::max_answers_per_challenge
+    pub answers: u32,
+    // when it was issued, it expires CHALLENGE_TTL later
+    pub created_at: SystemTime,
+}
+
+impl Default for Challenge {
+    fn default() -> Self {
+        Challenge {
+            user_name: String::new(),
+            r1: BigUint::default(),
+            r2: BigUint::default(),
+            nonce_commitment: Vec::new(),
+            non_interactive: false,
+            c: BigUint::default(),
+            server_k: None,
+            blinding: None,
+            answers: 0,
+            created_at: SystemTime::UNIX_EPOCH,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Session {
+    pub user_name: String,
+    // login time, kept across refreshes like the key and the client
+    pub created_at: SystemTime,
+    pub expires_at: SystemTime,
+    // agreed at login, kept across refreshes
+    pub key: Option<SessionKey>,
+    pub realm: String,
+    pub client: ClientInfo,
+    // scopes and claims issued at login, kept across refreshes
+    pub grant: Grant,
+    // anonymous tickets issued to the session, see tickets
+    pub tickets: u32,
+}
+
+// what the server saw of the client that logged in
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub remote_addr: Option<SocketAddr>,
+    pub user_agent: String,
+    // as the client named its device, empty for none
+    pub device_id: String,
+}
+
+// a device a user registered or logged in from, see DeviceStore
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    pub device_id: String,
+    pub name: String,
+    pub platform: String,
+    pub first_seen: SystemTime,
+    pub last_seen: SystemTime,
+    // of the latest registration or login
+    pub client: ClientInfo,
+    pub logins: u32,
+    // the user's current verifier was registered from it
+    pub registered: bool,
+}
+
+// how many sessions a user may hold at once, and what a login beyond that
+// does. max_per_user is at least 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionLimit {
+    pub max_per_user: usize,
+    pub policy: LimitPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LimitPolicy {
+    // the new login fails, the sessions already issued stay
+    RejectNew,
+    // the sessions logged in to longest ago end to make room
+    EvictOldest,
+}
+
+// how long a deleted user is kept, and can be restored, before they are
+// erased: 30 days unless the server picks another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention(pub Duration);
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention(Duration::from_secs(30 * 24 * 60 * 60))
+    }
+}
+
+// why UserStore::insert_within refused a registration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserRefusal {
+    // the store holds max_users and the user is new
+    Quota,
+    // the name is of a deleted user, until it is restored or erased
+    Deleted,
+}
+
+// why SessionStore::insert_within refused a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionRefusal {
+    // the user holds SessionLimit::max_per_user under LimitPolicy::RejectNew
+    UserLimit,
+    // the realm holds its quota of sessions
+    RealmQuota,
+}
+
+// a login to a decoy account, see DecoyStore
+#[derive(Clone, PartialEq, Eq)]
+pub struct DecoyHit {
+    pub user_name: String,
+    pub session_id: String,
+    // of the VerifyAuthentication call, see request_id
+    pub request_id: String,
+    pub at: SystemTime,
+    pub client: ClientInfo,
+}
+
+// the session id is live until it expires, so only its fingerprint is shown
+impl std::fmt::Debug for DecoyHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecoyHit")
+            .field("user_name", &self.user_name)
+            .field("session_id", &Redacted(&self.session_id))
+            .field("request_id", &self.request_id)
+            .field("at", &self.at)
+            .field("client", &self.client)
+            .finish()
+    }
+}
+
+// an account event that is always recorded, see AuditLog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditKind {
+    RecoveryKeyRegistered,
+    // a recovery whose proof didn't verify or was stale
+    RecoveryRejected,
+    // a recovery within the cooldown of the last one
+    RecoveryRefused,
+    AccountRecovered,
+    // by an admin, see UserInfo::deleted_at
+    UserDeleted,
+    UserRestored,
+}
+
+impl AuditKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditKind::RecoveryKeyRegistered => "recovery-key-registered",
+            AuditKind::RecoveryRejected => "recovery-rejected",
+            AuditKind::RecoveryRefused => "recovery-refused",
+            AuditKind::AccountRecovered => "account-recovered",
+            AuditKind::UserDeleted => "user-deleted",
+            AuditKind::UserRestored => "user-restored",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    pub kind: AuditKind,
+    pub user_name: String,
+    // of the call that caused it, see request_id
+    pub request_id: String,
+    pub at: SystemTime,
+    pub client: ClientInfo,
+}
+
+#[derive(Debug, Default)]
+struct Devices {
+    // oldest first
+    known: Vec<Device>,
+    revoked: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+struct Decoys {
+    users: HashSet<String>,
+    hits: Vec<DecoyHit>,
+}
+
+// registrations by user name, or by its keyed hash when the store has a
+// NameHasher
+#[derive(Debug)]
+pub struct UserStore {
+    users: Mutex<HashMap<String, UserInfo>>,
+    names: Option<NameHasher>,
+}
+
+// auth_ids and session IDs are bearer tokens: 256 random bits by default, never
+// fewer than 128
+pub const ID_BYTES: usize = 32;
+pub const MIN_ID_BYTES: usize = 16;
+const MAX_ID_BYTES: usize = 512;
+
+// how many random bytes go into a new auth_id or session ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdLength(usize);
+
+// an auth_id or session ID as a map key, compared in constant time so a lookup
+// doesn't reveal how much of a guessed ID was right
+#[derive(Debug, Clone)]
+struct Id(String);
+
+impl Hash for Id {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl PartialEq for Id {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_bytes().ct_eq(other.0.as_bytes()).into()
+    }
+}
+
+impl Eq for Id {}
+
+impl From<&str> for Id {
+    fn from(id: &str) -> Self {
+        Id(id.to_string())
+    }
+}
+
+// challenges waiting for their answer. one that isn't answered within the
+// ttl is dropped, and past max_pending new ones are refused, so challenges
+// asked for and never answered can't fill the memory
+#[derive(Debug)]
+pub struct ChallengeStore {
+    pending: Mutex<Pending>,
+    ttl: Duration,
+    max_pending: usize,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    challenges: HashMap<Id, Challenge>,
+    // the auth_ids of challenges that took all their answers, until they
+    // would have expired, so the next answer is told why it's refused
+    used_up: HashMap<Id, SystemTime>,
+}
+
+pub const CHALLENGE_TTL: Duration = Duration::from_secs(300);
+pub const MAX_PENDING_CHALLENGES: usize = 100_000;
+
+#[derive(Debug)]
+pub struct SessionStore(Mutex<HashMap<Id, Session>>);
+
+// user names marked as decoys and the logins to them. a decoy is an ordinary
+// registration, only the server knows it's one
+#[derive(Debug)]
+pub struct DecoyStore(Mutex<Decoys>);
+
+// events of the account recovery, kept for ListAuditEvents. there is no
+// setting that turns them off
+#[derive(Debug)]
+pub struct AuditLog(Mutex<Vec<AuditEvent>>);
+
+impl Default for UserStore {
+    fn default() -> Self {
+        UserStore {
+            users: Mutex::new(HashMap::new()),
+            names: None,
+        }
+    }
+}
+
+impl Default for ChallengeStore {
+    fn default() -> Self {
+        ChallengeStore::new(CHALLENGE_TTL, MAX_PENDING_CHALLENGES)
+    }
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        SessionStore(Mutex::new(HashMap::new()))
+    }
+}
+
+impl Default for IdLength {
+    fn default() -> Self {
+        IdLength(ID_BYTES)
+    }
+}
+
+impl IdLength {
+    pub fn new(bytes: usize) -> Result<Self, String> {
+        if !(MIN_ID_BYTES..=MAX_ID_BYTES).contains(&bytes) {
+            return Err(format!(
+                "IDs must have {} to {} random bytes, not {}",
+                MIN_ID_BYTES, MAX_ID_BYTES, bytes
+            ));
+        }
+        Ok(IdLength(bytes))
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.0
+    }
+
+    // URL-safe base64, 43 characters for the default 32 bytes
+    pub fn generate(&self) -> String {
+        ZKP::generate_token(self.0)
+    }
+}
+
+// the devices of each user, by user name. a device is known by the ID its
+// client picked; registrations and logins without one aren't tracked
+#[derive(Debug)]
+pub struct DeviceStore(Mutex<HashMap<String, Devices>>);
+
+// nonces of the tickets redeemed so far. a ticket carries nothing about who
+// it was issued to, so only its nonce can keep it from being spent twice
+#[derive(Debug)]
+pub struct TicketStore(Mutex<HashSet<[u8; 32]>>);
+
+impl Default for DeviceStore {
+    fn default() -> Self {
+        DeviceStore(Mutex::new(HashMap::new()))
+    }
+}
+
+impl Default for DecoyStore {
+    fn default() -> Self {
+        DecoyStore(Mutex::new(Decoys::default()))
+    }
+}
+
+impl Default for TicketStore {
+    fn default() -> Self {
+        TicketStore(Mutex::new(HashSet::new()))
+    }
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        AuditLog(Mutex::new(Vec::new()))
+    }
+}
+
+impl UserStore {
+    // a store that keeps user names only as their hashes: get, insert and
+    // replace take the name in the clear, all returns the registrations under
+    // the hashed names and restore takes them back
+    pub fn with_hashed_names(names: NameHasher) -> Self {
+        UserStore {
+            users: Mutex::new(HashMap::new()),
+            names: Some(names),
+        }
+    }
+
+    // the name a user is stored under
+    fn key(&self, user_name: &str) -> String {
+        match &self.names {
+            Some(names) => names.hash(user_name),
+            None => user_name.to_string(),
+        }
+    }
+
+    // false once a handler panicked holding the lock: every later call on the
+    // store would panic too
+    pub fn is_available(&self) -> bool {
+        self.users.lock().is_ok()
+    }
+
+    // registering again replaces the verifier
+    pub fn insert(&self, mut user: UserInfo) {
+        user.user_name = self.key(&user.user_name);
+        let users = &mut self.users.lock().unwrap();
+        users.insert(user.user_name.clone(), user);
+    }
+
+    // a registration as all returned it, from a snapshot or a bundle: a
+    // hashed name goes in as it is, one in the clear is hashed. hashed names
+    // only ever come from here, a user registering one has it hashed again
+    pub fn restore(&self, mut user: UserInfo) {
+        if !NameHasher::is_hashed(&user.user_name) {
+            user.user_name = self.key(&user.user_name);
+        }
+        let users = &mut self.users.lock().unwrap();
+        users.insert(user.user_name.clone(), user);
+    }
+
+    // inserts the user unless it is new and the store holds max_users
+    // already, or it is deleted, checked and applied under the one lock.
+    // registering again replaces the verifier whatever the count
+    pub fn insert_within(
+        &self,
+        mut user: UserInfo,
+        max_users: Option<usize>,
+    ) -> Result<(), UserRefusal> {
+        user.user_name = self.key(&user.user_name);
+        let users = &mut self.users.lock().unwrap();
+        match users.get(&user.user_name) {
+            Some(current) if current.deleted_at.is_some() => return Err(UserRefusal::Deleted),
+            Some(_) => {}
+            None if max_users.is_some_and(|max| users.len() >= max) => {
+                return Err(UserRefusal::Quota);
+            }
+            None => {}
+        }
+        users.insert(user.user_name.clone(), user);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.users.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // under the name it was asked for, hashed names or not. a deleted user
+    // isn't there
+    pub fn get(&self, user_name: &str) -> Option<UserInfo> {
+        let mut user = self
+            .users
+            .lock()
+            .unwrap()
+            .get(&self.key(user_name))
+            .filter(|user| user.deleted_at.is_none())
+            .cloned()?;
+        user.user_name = user_name.to_string();
+        Some(user)
+    }
+
+    // marks the user deleted at `at`, or with None restores them. deleting
+    // again keeps the first time. the time the user was deleted at, None for
+    // a user that isn't registered, or for a restore of one not deleted
+    pub fn set_deleted(&self, user_name: &str, at: Option<SystemTime>) -> Option<SystemTime> {
+        let users = &mut self.users.lock().unwrap();
+        let user = users.get_mut(&self.key(user_name))?;
+        match at {
+            Some(at) => Some(*user.deleted_at.get_or_insert(at)),
+            None => user.deleted_at.take(),
+        }
+    }
+
+    // removes the users deleted at or before `before`, returns the names
+    // they were stored under (see stored_name)
+    pub fn erase_deleted(&self, before: SystemTime) -> Vec<String> {
+        let users = &mut self.users.lock().unwrap();
+        let erased: Vec<String> = users
+            .values()
+            .filter(|user| user.deleted_at.is_some_and(|at| at <= before))
+            .map(|user| user.user_name.clone())
+            .collect();
+        for user_name in &erased {
+            users.remove(user_name);
+        }
+        erased
+    }
+
+    // the name a user is stored under: the name itself, or its keyed hash
+    // in a store with hashed names
+    pub fn stored_name(&self, user_name: &str) -> String {
+        self.key(user_name)
+    }
+
+    // swaps the user's verifier for `new` only while it is still `old`, so a
+    // registration that lands in between isn't overwritten. false if it did
+    pub fn replace(&self, old: &UserInfo, mut new: UserInfo) -> bool {
+        new.user_name = self.key(&new.user_name);
+        let users = &mut self.users.lock().unwrap();
+        match users.get_mut(&new.user_name) {
+            Some(current)
+                if current.y1 == old.y1
+                    && current.y2 == old.y2
+                    && current.group == old.group
+                    && current.deleted_at.is_none() =>
+            {
+                *current = new;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // every registration, deleted ones too, by user name (by the hashed one
+    // in a store with hashed names)
+    pub fn all(&self) -> Vec<UserInfo> {
+        let mut users: Vec<UserInfo> = self.users.lock().unwrap().values().cloned().collect();
+        users.sort_by(|a, b| a.user_name.cmp(&b.user_name));
+        users
+    }
+}
+
+impl ChallengeStore {
+    pub fn new(ttl: Duration, max_pending: usize) -> Self {
+        ChallengeStore {
+            pending: Mutex::new(Pending::default()),
+            ttl,
+            max_pending,
+        }
+    }
+
+    // see UserStore::is_available
+    pub fn is_available(&self) -> bool {
+        self.pending.lock().is_ok()
+    }
+
+    fn expires_at(&self, challenge: &Challenge) -> SystemTime {
+        challenge.created_at + self.ttl
+    }
+
+    fn drop_expired(&self, pending: &mut Pending, now: SystemTime) {
+        pending
+            .challenges
+            .retain(|_, challenge| self.expires_at(challenge) > now);
+        pending.used_up.retain(|_, expires_at| *expires_at > now);
+    }
+
+    // false, and nothing stored, when max_pending challenges are waiting
+    // even after the expired ones are dropped
+    pub fn insert(&self, auth_id: String, challenge: Challenge, now: SystemTime) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.challenges.len() >= self.max_pending {
+            self.drop_expired(&mut pending, now);
+            if pending.challenges.len() >= self.max_pending {
+                return false;
+            }
+        }
+        pending.challenges.insert(Id(auth_id), challenge);
+        true
+    }
+
+    // a challenge taken out and given back, whatever the count: it was in the
+    // store a moment ago
+    pub fn put_back(&self, auth_id: String, challenge: Challenge) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.challenges.insert(Id(auth_id), challenge);
+    }
+
+    // a challenge taken out that took all its answers is dropped, only its
+    // auth_id is kept until it would have expired. at most max_pending are
+    // kept, past that the next answer finds nothing at all
+    pub fn use_up(&self, auth_id: String, challenge: Challenge, now: SystemTime) {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.used_up.len() >= self.max_pending {
+            self.drop_expired(&mut pending, now);
+            if pending.used_up.len() >= self.max_pending {
+                return;
+            }
+        }
+        let expires_at = self.expires_at(&challenge);
+        pending.used_up.insert(Id(auth_id), expires_at);
+    }
+
+    // removes the challenge, so of concurrent answers to it only one gets it.
+    // an expired one is dropped and not handed out
+    pub fn take(&self, auth_id: &str, now: SystemTime) -> Option<Challenge> {
+        self.pending
+            .lock()
+            .unwrap()
+            .challenges
+            .remove(&Id::from(auth_id))
+            .filter(|challenge| self.expires_at(challenge) > now)
+    }
+
+    // whether auth_id was used up and hasn't expired, answered once
+    pub fn take_used_up(&self, auth_id: &str, now: SystemTime) -> bool {
+        self.pending
+            .lock()
+            .unwrap()
+            .used_up
+            .remove(&Id::from(auth_id))
+            .is_some_and(|expires_at| expires_at > now)
+    }
+
+    // drops the challenges expired by now, returns how many
+    pub fn sweep(&self, now: SystemTime) -> usize {
+        let mut pending = self.pending.lock().unwrap();
+        let before = pending.challenges.len();
+        self.drop_expired(&mut pending, now);
+        before - pending.challenges.len()
+    }
+
+    // challenges waiting for an answer
+    pub fn len(&self) -> usize {
+        self.pending.lock().unwrap().challenges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SessionStore {
+    // see UserStore::is_available
+    pub fn is_available(&self) -> bool {
+        self.0.lock().is_ok()
+    }
+
+    pub fn insert(&self, session_id: String, session: Session) {
+        self.0.lock().unwrap().insert(Id(session_id), session);
+    }
+
+    // inserts the session unless the user is at the limit, checked and applied
+    // under the one lock so concurrent logins can't both squeeze in. returns
+    // how many of the user's sessions were evicted, None if the session was
+    // refused. the user's sessions expired by now are dropped along the way
+    pub fn insert_limited(
+        &self,
+        session_id: String,
+        session: Session,
+        limit: Option<SessionLimit>,
+        now: SystemTime,
+    ) -> Option<usize> {
+        self.insert_within(session_id, session, limit, None, now)
+            .ok()
+    }
+
+    // insert_limited, also refused when the session's realm holds
+    // max_in_realm live sessions. the user's sessions evicted to make room
+    // make room in the realm too, nothing is evicted for a refused session
+    pub fn insert_within(
+        &self,
+        session_id: String,
+        session: Session,
+        limit: Option<SessionLimit>,
+        max_in_realm: Option<usize>,
+        now: SystemTime,
+    ) -> Result<usize, SessionRefusal> {
+        let sessions = &mut self.0.lock().unwrap();
+        let mut evicted = Vec::new();
+        if let Some(limit) = limit {
+            sessions
+                .retain(|_, other| other.user_name != session.user_name || other.expires_at > now);
+            let mut live: Vec<(SystemTime, Id)> = sessions
+                .iter()
+                .filter(|(_, other)| other.user_name == session.user_name)
+                .map(|(id, other)| (other.created_at, id.clone()))
+                .collect();
+            let excess = (live.len() + 1).saturating_sub(limit.max_per_user.max(1));
+            if excess > 0 {
+                if limit.policy == LimitPolicy::RejectNew {
+                    return Err(SessionRefusal::UserLimit);
+                }
+                live.sort_by_key(|(created_at, _)| *created_at);
+                evicted = live.into_iter().take(excess).map(|(_, id)| id).collect();
+            }
+        }
+        if let Some(max) = max_in_realm {
+            let in_realm = sessions
+                .iter()
+                .filter(|(id, other)| {
+                    other.realm == session.realm && other.expires_at > now && !evicted.contains(id)
+                })
+                .count();
+            if in_realm >= max {
+                return Err(SessionRefusal::RealmQuota);
+            }
+        }
+        for id in &evicted {
+            sessions.remove(id);
+        }
+        sessions.insert(Id(session_id), session);
+        Ok(evicted.len())
+    }
+
+    // f sees the session if it exists and hasn't expired by now, expired ones
+    // are removed
+    pub fn with_session<T>(
+        &self,
+        session_id: &str,
+        now: SystemTime,
+        f: impl FnOnce(&Session) -> Result<T, Status>,
+    ) -> Result<T, Status> {
+        let sessions = &mut self.0.lock().unwrap();
+        let session_id = Id::from(session_id);
+        Self::check(sessions, &session_id, now)?;
+        f(&sessions[&session_id])
+    }
+
+    // with_session for an update to the session
+    pub fn with_session_mut<T>(
+        &self,
+        session_id: &str,
+        now: SystemTime,
+        f: impl FnOnce(&mut Session) -> Result<T, Status>,
+    ) -> Result<T, Status> {
+        let sessions = &mut self.0.lock().unwrap();
+        let session_id = Id::from(session_id);
+        Self::check(sessions, &session_id, now)?;
+        f(sessions.get_mut(&session_id).unwrap())
+    }
+
+    // sessions of the realm that haven't expired by now
+    pub fn count_in_realm(&self, realm: &str, now: SystemTime) -> usize {
+        self.0
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|session| session.realm == realm && session.expires_at > now)
+            .count()
+    }
+
+    // replaces old_id with new_id in one step, the user and key carry over
+    pub fn refresh(
+        &self,
+        old_id: &str,
+        new_id: String,
+        expires_at: SystemTime,
+        now: SystemTime,
+    ) -> Result<(), Status> {
+        let sessions = &mut self.0.lock().unwrap();
+        let old_id = Id::from(old_id);
+        Self::check(sessions, &old_id, now)?;
+        let session = sessions.remove(&old_id).unwrap();
+        sessions.insert(
+            Id(new_id),
+            Session {
+                expires_at,
+                ..session
+            },
+        );
+        Ok(())
+    }
+
+    // f over every session that hasn't expired by now, by session ID
+    pub fn export<T>(&self, now: SystemTime, f: impl Fn(&str, &Session) -> T) -> Vec<T> {
+        let sessions = self.0.lock().unwrap();
+        let mut live: Vec<_> = sessions
+            .iter()
+            .filter(|(_, session)| session.expires_at > now)
+            .collect();
+        live.sort_by(|(a, _), (b, _)| a.0.cmp(&b.0));
+        live.into_iter()
+            .map(|(id, session)| f(&id.0, session))
+            .collect()
+    }
+
+    pub fn remove(&self, session_id: &str) -> Option<Session> {
+        self.0.lock().unwrap().remove(&Id::from(session_id))
+    }
+
+    // ends the sessions a user logged in to from a device, returns how many
+    pub fn remove_device(&self, user_name: &str, device_id: &str) -> usize {
+        let sessions = &mut self.0.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, session| {
+            session.user_name != user_name || session.client.device_id != device_id
+        });
+        before - sessions.len()
+    }
+
+    // ends every session of a user, returns how many
+    pub fn remove_user(&self, user_name: &str) -> usize {
+        let sessions = &mut self.0.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, session| session.user_name != user_name);
+        before - sessions.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn check(
+        sessions: &mut HashMap<Id, Session>,
+        session_id: &Id,
+        now: SystemTime,
+    ) -> Result<(), Status> {
+        match sessions.get(session_id) {
+            Some(session) if session.expires_at > now => Ok(()),
+            Some(_) => {
+                sessions.remove(session_id);
+                Err(Status::new(Code::Unauthenticated, "Session expired"))
+            }
+            None => Err(Status::new(Code::Unauthenticated, "Session not found")),
+        }
+    }
+}
+
+impl DeviceStore {
+    // adds a registration or login (seen.logins of them) to the device's
+    // record, or starts one. false for a device revoked for the user, nothing
+    // is recorded then
+    pub fn record(&self, user_name: &str, seen: Device) -> bool {
+        let users = &mut self.0.lock().unwrap();
+        let devices = users.entry(user_name.to_string()).or_default();
+        if devices.revoked.contains(&seen.device_id) {
+            return false;
+        }
+        // registering again replaces the verifier, only the newest is current
+        if seen.registered {
+            for device in &mut devices.known {
+                device.registered = false;
+            }
+        }
+        match devices
+            .known
+            .iter_mut()
+            .find(|device| device.device_id == seen.device_id)
+        {
+            Some(device) => {
+                if !seen.name.is_empty() {
+                    device.name = seen.name;
+                }
+                if !seen.platform.is_empty() {
+                    device.platform = seen.platform;
+                }
+                device.last_seen = seen.last_seen;
+                device.client = seen.client;
+                device.logins += seen.logins;
+                device.registered |= seen.registered;
+            }
+            None => devices.known.push(seen),
+        }
+        true
+    }
+
+    // oldest first
+    pub fn list(&self, user_name: &str) -> Vec<Device> {
+        let users = self.0.lock().unwrap();
+        users
+            .get(user_name)
+            .map(|devices| devices.known.clone())
+            .unwrap_or_default()
+    }
+
+    // forgets a known device and refuses it from now on, false for a device
+    // the user hasn't been seen on
+    pub fn revoke(&self, user_name: &str, device_id: &str) -> bool {
+        let users = &mut self.0.lock().unwrap();
+        let Some(devices) = users.get_mut(user_name) else {
+            return false;
+        };
+        let before = devices.known.len();
+        devices.known.retain(|device| device.device_id != device_id);
+        if devices.known.len() == before {
+            return false;
+        }
+        devices.revoked.insert(device_id.to_string());
+        true
+    }
+
+    // drops everything kept of the users `erased` picks
+    pub fn erase_users(&self, erased: &dyn Fn(&str) -> bool) {
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|user_name, _| !erased(user_name));
+    }
+}
+
+impl TicketStore {
+    // false if the nonce was already spent
+    pub fn spend(&self, nonce: [u8; 32]) -> bool {
+        self.0.lock().unwrap().insert(nonce)
+    }
+}
+
+impl DecoyStore {
+    // false clears the mark, the hits already recorded stay
+    pub fn mark(&self, user_name: &str, decoy: bool) {
+        let decoys = &mut self.0.lock().unwrap();
+        if decoy {
+            decoys.users.insert(user_name.to_string());
+        } else {
+            decoys.users.remove(user_name);
+        }
+    }
+
+    pub fn is_decoy(&self, user_name: &str) -> bool {
+        self.0.lock().unwrap().users.contains(user_name)
+    }
+
+    // records the hit if the user is a decoy, in one step so a mark cleared
+    // concurrently either sees the hit recorded or not at all
+    pub fn record(&self, hit: DecoyHit) -> bool {
+        let decoys = &mut self.0.lock().unwrap();
+        if !decoys.users.contains(&hit.user_name) {
+            return false;
+        }
+        decoys.hits.push(hit);
+        true
+    }
+
+    // oldest first
+    pub fn hits(&self) -> Vec<DecoyHit> {
+        self.0.lock().unwrap().hits.clone()
+    }
+
+    // drops the marks of and logins to the users `erased` picks
+    pub fn erase_users(&self, erased: &dyn Fn(&str) -> bool) {
+        let decoys = &mut self.0.lock().unwrap();
+        decoys.users.retain(|user_name| !erased(user_name));
+        decoys.hits.retain(|hit| !erased(&hit.user_name));
+    }
+}
+
+impl AuditLog {
+    pub fn record(&self, event: AuditEvent) {
+        self.0.lock().unwrap().push(event);
+    }
+
+    // oldest first
+    pub fn events(&self) -> Vec<AuditEvent> {
+        self.0.lock().unwrap().clone()
+    }
+
+    // drops the events of the users `erased` picks, returns how many
+    pub fn erase_users(&self, erased: &dyn Fn(&str) -> bool) -> usize {
+        let events = &mut self.0.lock().unwrap();
+        let before = events.len();
+        events.retain(|event| !erased(&event.user_name));
+        before - events.len()
+    }
+}
+
+#[cfg(all(test, not(zkp_loom)))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn session(user_name: &str, expires_at: SystemTime) -> Session {
+        Session {
+            user_name: user_name.to_string(),
+            created_at: SystemTime::now(),
+            expires_at,
+            key: None,
+            realm: String::new(),
+            client: ClientInfo::default(),
+            grant: Grant::default(),
+            tickets: 0,
+        }
+    }
+
+    #[test]
+    fn test_replace_keeps_a_newer_registration() {
+        let users = UserStore::default();
+        let old = UserInfo {
+            user_name: "alice".to_string(),
+            y1: BigUint::from(2u32),
+            y2: BigUint::from(3u32),
+            ..UserInfo::default()
+        };
+        users.insert(old.clone());
+        let moved = UserInfo {
+            y1: BigUint::from(4u32),
+            y2: BigUint::from(9u32),
+            group: "rfc5114-2048-256".to_string(),
+            ..old.clone()
+        };
+        assert!(users.replace(&old, moved.clone()));
+        assert_eq!(users.get("alice").unwrap().y1, moved.y1);
+        // the verifier changed since old was read
+        assert!(!users.replace(&old, moved));
+    }
+
+    #[test]
+    fn test_hashed_names_are_found_by_the_name() {
+        let users = UserStore::with_hashed_names(NameHasher::new(vec![7; 32]).unwrap());
+        users.insert(UserInfo {
+            user_name: "alice".to_string(),
+            y1: BigUint::from(2u32),
+            ..UserInfo::default()
+        });
+        assert_eq!(users.get("alice").unwrap().user_name, "alice");
+        let stored = users.all();
+        assert!(NameHasher::is_hashed(&stored[0].user_name));
+        // the hashed name isn't a name to log in with
+        assert!(users.get(&stored[0].user_name).is_none());
+
+        // and goes back in as it is, the way a snapshot restores it
+        let restored = UserStore::with_hashed_names(NameHasher::new(vec![7; 32]).unwrap());
+        restored.restore(stored[0].clone());
+        assert_eq!(restored.get("alice").unwrap().y1, BigUint::from(2u32));
+    }
+    #[test]
+    fn test_challenges_of_one_user_are_kept_apart() {
+        let challenges = ChallengeStore::default();
+        let now = SystemTime::now();
+        for (auth_id, c) in [("first", 1u32), ("second", 2u32)] {
+            challenges.insert(
+                auth_id.to_string(),
+                Challenge {
+                    user_name: "alice".to_string(),
+                    c: c.into(),
+                    created_at: now,
+                    ..Challenge::default()
+                },
+                now,
+            );
+        }
+        assert_eq!(challenges.take("first", now).unwrap().c, 1u32.into());
+        assert!(challenges.take("first", now).is_none());
+        assert_eq!(challenges.take("second", now).unwrap().c, 2u32.into());
+    }
+
+    #[test]
+    fn test_unanswered_challenges_expire() {
+        let challenges = ChallengeStore::new(Duration::from_secs(60), 2);
+        let now = SystemTime::now();
+        let issued = |created_at| Challenge {
+            created_at,
+            ..Challenge::default()
+        };
+        assert!(challenges.insert("old".to_string(), issued(now), now));
+        assert!(challenges.insert(
+            "new".to_string(),
+            issued(now + Duration::from_secs(30)),
+            now
+        ));
+        // full until the first one expires
+        assert!(!challenges.insert("more".to_string(), issued(now), now));
+        let later = now + Duration::from_secs(60);
+        assert!(challenges.insert("more".to_string(), issued(later), later));
+        assert!(challenges.take("old", later).is_none());
+
+        // taken too late, it's gone all the same
+        assert!(challenges
+            .take("new", now + Duration::from_secs(90))
+            .is_none());
+        assert_eq!(challenges.len(), 1);
+        assert_eq!(challenges.sweep(later + Duration::from_secs(60)), 1);
+        assert!(challenges.is_empty());
+
+        // a used-up challenge is told apart once, until it would have expired
+        challenges.use_up("used".to_string(), issued(now), now);
+        challenges.use_up("late".to_string(), issued(now), now);
+        assert!(challenges.take("used", now).is_none());
+        assert!(challenges.take_used_up("used", now));
+        assert!(!challenges.take_used_up("used", now));
+        assert!(!challenges.take_used_up("late", later));
+    }
+
+    #[test]
+    fn test_expired_session_is_removed() {
+        let sessions = SessionStore::default();
+        let now = SystemTime::now();
+        sessions.insert(
+            "old".to_string(),
+            session("alice", now - Duration::from_secs(1)),
+        );
+        sessions.insert(
+            "new".to_string(),
+            session("alice", now + Duration::from_secs(60)),
+        );
+
+        let status = sessions.with_session("old", now, |_| Ok(())).unwrap_err();
+        assert_eq!(status.message(), "Session expired");
+        assert_eq!(sessions.len(), 1);
+
+        sessions
+            .refresh(
+                "new",
+                "newer".to_string(),
+                now + Duration::from_secs(120),
+                now,
+            )
+            .unwrap();
+        let user = sessions
+            .with_session("newer", now, |session| Ok(session.user_name.clone()))
+            .unwrap();
+        assert_eq!(user, "alice");
+        assert!(sessions.with_session("new", now, |_| Ok(())).is_err());
+    }
+
+    #[test]
+    fn test_ids() {
+        let id = IdLength::default().generate();
+        assert_eq!(id.len(), 43);
+        assert!(id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'));
+        assert_eq!(IdLength::new(MIN_ID_BYTES).unwrap().generate().len(), 22);
+        assert!(IdLength::new(12).is_err());
+        assert!(IdLength::new(MAX_ID_BYTES + 1).is_err());
+
+        // a guess sharing a prefix with the ID finds nothing
+        let sessions = SessionStore::default();
+        sessions.insert(
+            id.clone(),
+            session("alice", SystemTime::now() + Duration::from_secs(60)),
+        );
+        assert!(sessions
+            .with_session(&id[..42], SystemTime::now(), |_| Ok(()))
+            .is_err());
+        assert!(sessions
+            .with_session(&id, SystemTime::now(), |_| Ok(()))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_only_decoy_logins_are_recorded() {
+        let decoys = DecoyStore::default();
+        let hit = |user_name: &str| DecoyHit {
+            user_name: user_name.to_string(),
+            session_id: "session".to_string(),
+            request_id: String::new(),
+            at: SystemTime::now(),
+            client: ClientInfo::default(),
+        };
+        decoys.mark("admin", true);
+        assert!(decoys.record(hit("admin")));
+        assert!(!decoys.record(hit("alice")));
+
+        decoys.mark("admin", false);
+        assert!(!decoys.is_decoy("admin"));
+        assert!(!decoys.record(hit("admin")));
+        let hits = decoys.hits();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].user_name, "admin");
+    }
+
+    #[test]
+    fn test_session_limit() {
+        let sessions = SessionStore::default();
+        let now = SystemTime::now();
+        let login = |user_name: &str, minutes_ago: u64| Session {
+            created_at: now - Duration::from_secs(60 * minutes_ago),
+            ..session(user_name, now + Duration::from_secs(60))
+        };
+        let reject = Some(SessionLimit {
+            max_per_user: 2,
+            policy: LimitPolicy::RejectNew,
+        });
+        assert_eq!(
+            sessions.insert_limited("a".to_string(), login("alice", 3), reject, now),
+            Some(0)
+        );
+        assert_eq!(
+            sessions.insert_limited("b".to_string(), login("alice", 2), reject, now),
+            Some(0)
+        );
+        assert_eq!(
+            sessions.insert_limited("c".to_string(), login("alice", 1), reject, now),
+            None
+        );
+        // other users and expired sessions don't count
+        assert_eq!(
+            sessions.insert_limited("d".to_string(), login("bob", 1), reject, now),
+            Some(0)
+        );
+        sessions.insert(
+            "old".to_string(),
+            session("bob", now - Duration::from_secs(1)),
+        );
+        assert_eq!(
+            sessions.insert_limited("e".to_string(), login("bob", 0), reject, now),
+            Some(0)
+        );
+        assert!(sessions.with_session("old", now, |_| Ok(())).is_err());
+
+        let evict = Some(SessionLimit {
+            max_per_user: 2,
+            policy: LimitPolicy::EvictOldest,
+        });
+        assert_eq!(
+            sessions.insert_limited("f".to_string(), login("alice", 0), evict, now),
+            Some(1)
+        );
+        assert!(sessions.with_session("a", now, |_| Ok(())).is_err());
+        assert!(sessions.with_session("b", now, |_| Ok(())).is_ok());
+        assert!(sessions.with_session("f", now, |_| Ok(())).is_ok());
+        assert_eq!(
+            sessions.insert_limited("g".to_string(), login("alice", 0), None, now),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_realm_quotas() {
+        let sessions = SessionStore::default();
+        let now = SystemTime::now();
+        let login = |user_name: &str, realm: &str, minutes_ago: u64| Session {
+            created_at: now - Duration::from_secs(60 * minutes_ago),
+            realm: realm.to_string(),
+            ..session(user_name, now + Duration::from_secs(60))
+        };
+        let insert = |id: &str, session: Session, limit: Option<SessionLimit>| {
+            sessions.insert_within(id.to_string(), session, limit, Some(2), now)
+        };
+        assert_eq!(insert("a", login("alice", "acme", 2), None), Ok(0));
+        assert_eq!(insert("b", login("bob", "acme", 1), None), Ok(0));
+        assert_eq!(
+            insert("c", login("carol", "acme", 0), None),
+            Err(SessionRefusal::RealmQuota)
+        );
+        assert_eq!(insert("d", login("carol", "other", 0), None), Ok(0));
+        assert_eq!(sessions.count_in_realm("acme", now), 2);
+
+        // a session evicted under the user's own limit makes room
+        let evict = Some(SessionLimit {
+            max_per_user: 1,
+            policy: LimitPolicy::EvictOldest,
+        });
+        assert_eq!(insert("e", login("alice", "acme", 0), evict), Ok(1));
+        assert!(sessions.with_session("a", now, |_| Ok(())).is_err());
+        assert_eq!(sessions.count_in_realm("acme", now), 2);
+
+        let users = UserStore::default();
+        let user = |user_name: &str| UserInfo {
+            user_name: user_name.to_string(),
+            ..UserInfo::default()
+        };
+        assert_eq!(users.insert_within(user("alice"), Some(1)), Ok(()));
+        assert_eq!(
+            users.insert_within(user("bob"), Some(1)),
+            Err(UserRefusal::Quota)
+        );
+        // registering again isn't a new user
+        assert_eq!(users.insert_within(user("alice"), Some(1)), Ok(()));
+        assert_eq!(users.len(), 1);
+    }
+
+    #[test]
+    fn test_revoked_devices_stay_revoked() {
+        let devices = DeviceStore::default();
+        let seen = |device_id: &str, name: &str, registered: bool| Device {
+            device_id: device_id.to_string(),
+            name: name.to_string(),
+            platform: String::new(),
+            first_seen: SystemTime::now(),
+            last_seen: SystemTime::now(),
+            client: ClientInfo::default(),
+            logins: u32::from(!registered),
+            registered,
+        };
+        assert!(devices.record("alice", seen("laptop", "Laptop", true)));
+        assert!(devices.record("alice", seen("laptop", "", false)));
+        assert!(devices.record("alice", seen("phone", "Phone", true)));
+        let known = devices.list("alice");
+        assert_eq!(known.len(), 2);
+        assert_eq!((known[0].name.as_str(), known[0].logins), ("Laptop", 1));
+        // only the newest registration is current
+        assert!(!known[0].registered && known[1].registered);
+
+        assert!(devices.revoke("alice", "laptop"));
+        assert!(!devices.revoke("alice", "laptop"));
+        assert!(!devices.revoke("bob", "phone"));
+        assert!(!devices.record("alice", seen("laptop", "Laptop", false)));
+        assert_eq!(devices.list("alice").len(), 1);
+        // IDs are per user
+        assert!(devices.record("bob", seen("laptop", "Laptop", false)));
+
+        let sessions = SessionStore::default();
+        let expires_at = SystemTime::now() + Duration::from_secs(60);
+        for (id, user_name, device_id) in [
+            ("a", "alice", "laptop"),
+            ("b", "alice", "phone"),
+            ("c", "bob", "laptop"),
+        ] {
+            let mut session = session(user_name, expires_at);
+            session.client.device_id = device_id.to_string();
+            sessions.insert(id.to_string(), session);
+        }
+        assert_eq!(sessions.remove_device("alice", "laptop"), 1);
+        assert_eq!(sessions.len(), 2);
+    }
+}
+
+// RUSTFLAGS="--cfg zkp_loom" cargo test --release --lib state::loom_tests
+#[cfg(all(test, zkp_loom))]
+mod loom_tests {
+    use super::*;
+    use loom::sync::Arc;
+    use loom::thread;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn session(user_name: &str) -> Session {
+        Session {
+            user_name: user_name.to_string(),
+            created_at: SystemTime::now(),
+            expires_at: SystemTime::now() + Duration::from_secs(60),
+            key: None,
+            realm: String::new(),
+            client: ClientInfo::default(),
+            grant: Grant::default(),
+            tickets: 0,
+        }
+    }
+
+    // a replayed answer racing the original: exactly one of them gets the challenge
+    #[test]
+    fn test_challenge_is_consumed_once() {
+        loom::model(|| {
+            let challenges = Arc::new(ChallengeStore::default());
+            challenges.insert("auth".to_string(), Challenge::default(), UNIX_EPOCH);
+
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    let challenges = challenges.clone();
+                    thread::spawn(move || challenges.take("auth", UNIX_EPOCH).is_some())
+                })
+                .collect();
+            let taken = handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .filter(|taken| *taken)
+                .count();
+            assert_eq!(taken, 1);
+            assert!(challenges.is_empty());
+        });
+    }
+
+    // two users asking for a challenge while a third answers: nothing is lost
+    #[test]
+    fn test_concurrent_challenges_are_not_lost() {
+        loom::model(|| {
+            let challenges = Arc::new(ChallengeStore::default());
+            challenges.insert("carol".to_string(), Challenge::default(), UNIX_EPOCH);
+
+            let handles: Vec<_> = ["alice", "bob"]
+                .into_iter()
+                .map(|user| {
+                    let challenges = challenges.clone();
+                    thread::spawn(move || {
+                        challenges.insert(
+                            user.to_string(),
+                            Challenge {
+                                user_name: user.to_string(),
+                                ..Challenge::default()
+                            },
+                            UNIX_EPOCH,
+                        )
+                    })
+                })
+                .collect();
+            assert!(challenges.take("carol", UNIX_EPOCH).is_some());
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            assert_eq!(
+                challenges.take("alice", UNIX_EPOCH).unwrap().user_name,
+                "alice"
+            );
+            assert_eq!(challenges.take("bob", UNIX_EPOCH).unwrap().user_name, "bob");
+        });
+    }
+
+    // a refresh racing a logout of the same session: either the logout wins and
+    // the refresh fails, or the refresh wins and the logout finds nothing.
+    // never both, never a session left under the old ID
+    #[test]
+    fn test_refresh_and_logout_race() {
+        loom::model(|| {
+            let sessions = Arc::new(SessionStore::default());
+            sessions.insert("old".to_string(), session("alice"));
+
+            let refresher = {
+                let sessions = sessions.clone();
+                thread::spawn(move || {
+                    let expires_at = SystemTime::now() + Duration::from_secs(60);
+                    sessions
+                        .refresh("old", "new".to_string(), expires_at, SystemTime::now())
+                        .is_ok()
+                })
+            };
+            let logged_out = sessions.remove("old").is_some();
+            let refreshed = refresher.join().unwrap();
+
+            assert!(logged_out != refreshed);
+            assert!(sessions
+                .with_session("old", SystemTime::now(), |_| Ok(()))
+                .is_err());
+            assert_eq!(sessions.len(), usize::from(refreshed));
+        });
+    }
+
+    // two refreshes of the same session: one new session, not two
+    #[test]
+    fn test_double_refresh() {
+        loom::model(|| {
+            let sessions = Arc::new(SessionStore::default());
+            sessions.insert("old".to_string(), session("alice"));
+
+            let handles: Vec<_> = ["a", "b"]
+                .into_iter()
+                .map(|new_id| {
+                    let sessions = sessions.clone();
+                    thread::spawn(move || {
+                        let expires_at = SystemTime::now() + Duration::from_secs(60);
+                        sessions
+                            .refresh("old", new_id.to_string(), expires_at, SystemTime::now())
+                            .is_ok()
+                    })
+                })
+                .collect();
+            let refreshed = handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .filter(|refreshed| *refreshed)
+                .count();
+            assert_eq!(refreshed, 1);
+            assert_eq!(sessions.len(), 1);
+        });
+    }
+}
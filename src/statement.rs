@@ -0,0 +1,318 @@
+use crate::keys::PublicKey;
+use crate::{Proof, ZKP};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+// what a non-interactive proof is about, put together once: the group (p, q
+// and the generators g, h), the public values y1 = g ** x, y2 = h ** x mod p
+// and what the challenge is bound to (the application id and the context).
+// the same Statement then proves, verifies, verifies a batch and goes to JSON
+// and back, instead of each of them taking the loose values
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "StatementFile", try_from = "StatementFile")]
+pub struct Statement {
+    zkp: ZKP,
+    y1: BigUint,
+    y2: BigUint,
+    application: Vec<u8>,
+    context: Vec<u8>,
+}
+
+// a Statement in progress, see Statement::builder. the group and the public
+// values are required, the application id and the context default to empty
+#[derive(Debug, Default)]
+pub struct StatementBuilder {
+    zkp: Option<ZKP>,
+    public: Option<(BigUint, BigUint)>,
+    application: Vec<u8>,
+    context: Vec<u8>,
+}
+
+// on-disk form of a Statement, numbers and bytes are big-endian hex
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatementFile {
+    pub p: String,
+    pub q: String,
+    pub g: String,
+    pub h: String,
+    pub y1: String,
+    pub y2: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub application: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub context: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatementError {
+    MissingGroup,
+    MissingPublicValues,
+    // p or q below 2, or a public value outside 1..p
+    OutOfRange(&'static str),
+    InvalidHex(&'static str),
+    // x is not the secret behind y1 and y2
+    WrongSecret,
+}
+
+impl Display for StatementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatementError::MissingGroup => write!(f, "statement has no group"),
+            StatementError::MissingPublicValues => write!(f, "statement has no y1 and y2"),
+            StatementError::OutOfRange(field) => write!(f, "{} is out of range", field),
+            StatementError::InvalidHex(field) => write!(f, "{} is not valid hex", field),
+            StatementError::WrongSecret => write!(f, "secret does not match y1 and y2"),
+        }
+    }
+}
+
+impl std::error::Error for StatementError {}
+
+impl StatementBuilder {
+    pub fn group(mut self, zkp: ZKP) -> Self {
+        self.zkp = Some(zkp);
+        self
+    }
+
+    pub fn public(mut self, y1: BigUint, y2: BigUint) -> Self {
+        self.public = Some((y1, y2));
+        self
+    }
+
+    pub fn public_key(self, key: &PublicKey) -> Self {
+        self.public(key.y1.clone(), key.y2.clone())
+    }
+
+    pub fn application(mut self, application: &[u8]) -> Self {
+        self.application = application.to_vec();
+        self
+    }
+
+    pub fn context(mut self, context: &[u8]) -> Self {
+        self.context = context.to_vec();
+        self
+    }
+
+    // y1 = 0 or y2 = 0 would let anyone make a proof, so neither is taken
+    pub fn build(self) -> Result<Statement, StatementError> {
+        let zkp = self.zkp.ok_or(StatementError::MissingGroup)?;
+        let (y1, y2) = self.public.ok_or(StatementError::MissingPublicValues)?;
+        let two = BigUint::from(2u32);
+        if zkp.p < two {
+            return Err(StatementError::OutOfRange("p"));
+        }
+        if zkp.q < two {
+            return Err(StatementError::OutOfRange("q"));
+        }
+        let zero = BigUint::from(0u32);
+        for (field, y) in [("y1", &y1), ("y2", &y2)] {
+            if *y == zero || *y >= zkp.p {
+                return Err(StatementError::OutOfRange(field));
+            }
+        }
+        Ok(Statement {
+            zkp,
+            y1,
+            y2,
+            application: self.application,
+            context: self.context,
+        })
+    }
+}
+
+impl Statement {
+    pub fn builder() -> StatementBuilder {
+        StatementBuilder::default()
+    }
+
+    pub fn group(&self) -> &ZKP {
+        &self.zkp
+    }
+
+    pub fn public(&self) -> (&BigUint, &BigUint) {
+        (&self.y1, &self.y2)
+    }
+
+    pub fn application(&self) -> &[u8] {
+        &self.application
+    }
+
+    pub fn context(&self) -> &[u8] {
+        &self.context
+    }
+
+    // ZKP::prove_non_interactive_in for the statement, once x is checked to
+    // be the secret behind it
+    pub fn prove(&self, x: &BigUint) -> Result<Proof, StatementError> {
+        let zkp = &self.zkp;
+        if ZKP::exponentiate(&zkp.g, x, &zkp.p) != self.y1
+            || ZKP::exponentiate(&zkp.h, x, &zkp.p) != self.y2
+        {
+            return Err(StatementError::WrongSecret);
+        }
+        Ok(zkp.prove_non_interactive_in(&self.application, x, &self.context))
+    }
+
+    pub fn verify(&self, proof: &Proof) -> bool {
+        self.zkp.verify_non_interactive_in(
+            &self.application,
+            &self.y1,
+            &self.y2,
+            proof,
+            &self.context,
+        )
+    }
+
+    // every proof of the batch against its statement. a (c, s) proof carries
+    // no commitments to combine with the others, so each is verified on its
+    // own; Err is the index of the first that doesn't verify
+    pub fn verify_batch(batch: &[(&Statement, &Proof)]) -> Result<(), usize> {
+        match batch
+            .iter()
+            .position(|(statement, proof)| !statement.verify(proof))
+        {
+            Some(index) => Err(index),
+            None => Ok(()),
+        }
+    }
+}
+
+fn to_hex(n: &BigUint) -> String {
+    hex::encode(n.to_bytes_be())
+}
+
+fn from_hex(field: &'static str, value: &str) -> Result<BigUint, StatementError> {
+    hex::decode(value)
+        .map(|bytes| BigUint::from_bytes_be(&bytes))
+        .map_err(|_| StatementError::InvalidHex(field))
+}
+
+impl From<Statement> for StatementFile {
+    fn from(statement: Statement) -> Self {
+        let zkp = &statement.zkp;
+        StatementFile {
+            p: to_hex(&zkp.p),
+            q: to_hex(&zkp.q),
+            g: to_hex(&zkp.g),
+            h: to_hex(&zkp.h),
+            y1: to_hex(&statement.y1),
+            y2: to_hex(&statement.y2),
+            application: hex::encode(&statement.application),
+            context: hex::encode(&statement.context),
+        }
+    }
+}
+
+// through the builder, so a statement read from a file is checked as one
+// built in code
+impl TryFrom<StatementFile> for Statement {
+    type Error = StatementError;
+
+    fn try_from(file: StatementFile) -> Result<Self, Self::Error> {
+        let zkp = ZKP {
+            p: from_hex("p", &file.p)?,
+            q: from_hex("q", &file.q)?,
+            g: from_hex("g", &file.g)?,
+            h: from_hex("h", &file.h)?,
+        };
+        let application = hex::decode(&file.application)
+            .map_err(|_| StatementError::InvalidHex("application"))?;
+        let context =
+            hex::decode(&file.context).map_err(|_| StatementError::InvalidHex("context"))?;
+        Statement::builder()
+            .group(zkp)
+            .public(from_hex("y1", &file.y1)?, from_hex("y2", &file.y2)?)
+            .application(&application)
+            .context(&context)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groups;
+    use crate::keys::KeyPair;
+
+    #[test]
+    fn test_statement_proves_verifies_and_round_trips() {
+        let zkp = groups::default_group();
+        let key = KeyPair::generate(&zkp);
+        let statement = Statement::builder()
+            .group(zkp.clone())
+            .public_key(&key.public)
+            .application(b"app")
+            .context(b"nonce")
+            .build()
+            .unwrap();
+        let proof = statement.prove(key.x.expose()).unwrap();
+        assert!(statement.verify(&proof));
+        // the same proof as the loose-argument API makes
+        assert!(zkp.verify_non_interactive_in(
+            b"app",
+            &key.public.y1,
+            &key.public.y2,
+            &proof,
+            b"nonce"
+        ));
+
+        let json = serde_json::to_string(&statement).unwrap();
+        let parsed: Statement = serde_json::from_str(&json).unwrap();
+        assert!(parsed.verify(&proof));
+        assert_eq!(parsed.context(), b"nonce");
+
+        let other = KeyPair::generate(&zkp);
+        assert_eq!(
+            statement.prove(other.x.expose()),
+            Err(StatementError::WrongSecret)
+        );
+        let elsewhere = Statement::builder()
+            .group(zkp)
+            .public_key(&key.public)
+            .context(b"nonce")
+            .build()
+            .unwrap();
+        assert!(!elsewhere.verify(&proof));
+        assert_eq!(
+            Statement::verify_batch(&[(&statement, &proof), (&parsed, &proof)]),
+            Ok(())
+        );
+        assert_eq!(
+            Statement::verify_batch(&[(&statement, &proof), (&elsewhere, &proof)]),
+            Err(1)
+        );
+    }
+
+    #[test]
+    fn test_builder_refuses_incomplete_statements() {
+        let zkp = groups::default_group();
+        assert_eq!(
+            Statement::builder().build().unwrap_err(),
+            StatementError::MissingGroup
+        );
+        assert_eq!(
+            Statement::builder().group(zkp.clone()).build().unwrap_err(),
+            StatementError::MissingPublicValues
+        );
+        let zero = Statement::builder()
+            .group(zkp.clone())
+            .public(BigUint::from(0u32), BigUint::from(0u32))
+            .build();
+        assert_eq!(zero.unwrap_err(), StatementError::OutOfRange("y1"));
+
+        // and a file with a bad value is refused the same way
+        let mut file = StatementFile::from(
+            Statement::builder()
+                .group(zkp.clone())
+                .public(BigUint::from(2u32), BigUint::from(3u32))
+                .build()
+                .unwrap(),
+        );
+        file.y2 = hex::encode(zkp.p.to_bytes_be());
+        assert_eq!(
+            Statement::try_from(file).unwrap_err(),
+            StatementError::OutOfRange("y2")
+        );
+    }
+}
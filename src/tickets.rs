@@ -0,0 +1,206 @@
+use crate::keys::{KeyPair, PublicKey};
+use crate::secret::{wipe_biguint, SecretBigUint};
+use crate::{Proof, ZKP};
+use num_bigint::BigUint;
+use sha2::{Digest, Sha256};
+
+// anonymous tickets, Privacy Pass style: a logged-in user has the server
+// evaluate its key x on blinded random points, and later spends each result
+// once without the server being able to tell whose it was.
+//
+// client: T = H(nonce) in the order-q subgroup, sends B = T ** r mod p
+// server: Z = B ** x mod p, with a Chaum-Pedersen proof on the bases (g, B)
+//         that log_g y1 = log_B Z, i.e. that Z was made with the server key
+//         and not one kept for this user (which would tag the ticket)
+// client: W = Z ** (1 / r) = T ** x mod p
+// redeem: the server checks W = H(nonce) ** x mod p and that the nonce
+//         wasn't spent, having never seen T or W before
+
+// what a ticket is bound to besides the server key
+pub const CONTEXT: &[u8] = b"zkp-chaum-pedersen/ticket";
+
+// H(nonce) = (SHA-256(domain || nonce || 0) || ... mod p) ** ((p - 1) / q) mod p
+// nobody knows its log to any base; the counter moves on in the (negligible)
+// case that lands on 1
+pub fn hash_to_group(zkp: &ZKP, nonce: &[u8]) -> BigUint {
+    let one = BigUint::from(1u32);
+    let cofactor = (&zkp.p - &one) / &zkp.q;
+    // 64 bits more than p, so the reduction is close to uniform
+    let blocks = (zkp.p.bits() + 64).div_ceil(256) as u32;
+    let mut counter = 0u32;
+    loop {
+        let digest: Vec<u8> = (counter..counter + blocks)
+            .flat_map(|i| {
+                Sha256::new()
+                    .chain_update(CONTEXT)
+                    .chain_update((nonce.len() as u64).to_be_bytes())
+                    .chain_update(nonce)
+                    .chain_update(i.to_be_bytes())
+                    .finalize()
+            })
+            .collect();
+        let point = ZKP::exponentiate(
+            &(BigUint::from_bytes_be(&digest) % &zkp.p),
+            &cofactor,
+            &zkp.p,
+        );
+        if point > one {
+            return point;
+        }
+        counter += blocks;
+    }
+}
+
+// a non-trivial element of the order-q subgroup, the only blinded points
+// the server evaluates
+pub fn in_subgroup(zkp: &ZKP, n: &BigUint) -> bool {
+    let one = BigUint::from(1u32);
+    *n > one && *n < zkp.p && ZKP::exponentiate(n, &zkp.q, &zkp.p) == one
+}
+
+// the bases of the proof: g and the blinded point
+fn evaluation_bases(zkp: &ZKP, blinded: &BigUint) -> ZKP {
+    ZKP {
+        p: zkp.p.clone(),
+        q: zkp.q.clone(),
+        g: zkp.g.clone(),
+        h: blinded.clone(),
+    }
+}
+
+// a ticket before the server evaluated it. r is wiped on drop
+#[derive(Debug)]
+pub struct BlindTicket {
+    pub nonce: [u8; 32],
+    r: SecretBigUint,
+    // B = H(nonce) ** r mod p, sent to the server
+    pub blinded: BigUint,
+}
+
+impl BlindTicket {
+    pub fn new(zkp: &ZKP) -> Self {
+        let mut nonce = [0u8; 32];
+        crate::rng::with_rng(|rng| rng.fill_bytes(&mut nonce));
+        // r = 0 would send 1 and lose the ticket
+        let mut r = ZKP::generate_random_number_below(&zkp.q);
+        while r == BigUint::from(0u32) {
+            r = ZKP::generate_random_number_below(&zkp.q);
+        }
+        let blinded = ZKP::exponentiate(&hash_to_group(zkp, &nonce), &r, &zkp.p);
+        BlindTicket {
+            nonce,
+            r: SecretBigUint::new(r),
+            blinded,
+        }
+    }
+
+    // W = Z ** (1 / r) mod p, once the proof shows Z = B ** x for the
+    // server's key. None for an evaluation that isn't
+    pub fn finish(
+        self,
+        zkp: &ZKP,
+        application: &[u8],
+        server: &PublicKey,
+        evaluated: &BigUint,
+        proof: &Proof,
+    ) -> Option<Ticket> {
+        if !in_subgroup(zkp, evaluated) {
+            return None;
+        }
+        let bases = evaluation_bases(zkp, &self.blinded);
+        if !bases.verify_non_interactive_in(application, &server.y1, evaluated, proof, CONTEXT) {
+            return None;
+        }
+        let mut inverse = self.r.expose().modinv(&zkp.q)?;
+        let w = ZKP::exponentiate(evaluated, &inverse, &zkp.p);
+        wipe_biguint(&mut inverse);
+        Some(Ticket {
+            nonce: self.nonce,
+            w,
+        })
+    }
+}
+
+// spent once, by sending both halves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ticket {
+    pub nonce: [u8; 32],
+    // H(nonce) ** x mod p
+    pub w: BigUint,
+}
+
+// server side: Z = B ** x mod p and the proof for it. None unless B is a
+// non-trivial element of the order-q subgroup, so x can't be probed in a
+// small one
+pub fn evaluate(
+    zkp: &ZKP,
+    application: &[u8],
+    key: &KeyPair,
+    blinded: &BigUint,
+) -> Option<(BigUint, Proof)> {
+    if !in_subgroup(zkp, blinded) {
+        return None;
+    }
+    let evaluated = ZKP::exponentiate(blinded, key.x.expose(), &zkp.p);
+    let proof = evaluation_bases(zkp, blinded).prove_non_interactive_in(
+        application,
+        key.x.expose(),
+        CONTEXT,
+    );
+    Some((evaluated, proof))
+}
+
+// server side: whether the ticket was made with the server key. whether it
+// was already spent is up to the caller
+pub fn check(zkp: &ZKP, key: &KeyPair, ticket: &Ticket) -> bool {
+    in_subgroup(zkp, &ticket.w)
+        && ticket.w == ZKP::exponentiate(&hash_to_group(zkp, &ticket.nonce), key.x.expose(), &zkp.p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groups;
+
+    #[test]
+    fn test_ticket_round() {
+        let zkp = groups::default_group();
+        let key = KeyPair::generate(&zkp);
+        let blind = BlindTicket::new(&zkp);
+        let (evaluated, proof) = evaluate(&zkp, b"", &key, &blind.blinded).unwrap();
+        // the server never sees T, so the ticket doesn't match what it evaluated
+        let blinded = blind.blinded.clone();
+        let ticket = blind
+            .finish(&zkp, b"", &key.public, &evaluated, &proof)
+            .unwrap();
+        assert!(check(&zkp, &key, &ticket));
+        assert_ne!(ticket.w, evaluated);
+        assert_ne!(hash_to_group(&zkp, &ticket.nonce), blinded);
+
+        // a ticket from another key, or with another nonce, isn't one
+        let other = KeyPair::generate(&zkp);
+        assert!(!check(&zkp, &other, &ticket));
+        let forged = Ticket {
+            nonce: [7; 32],
+            ..ticket
+        };
+        assert!(!check(&zkp, &key, &forged));
+    }
+
+    #[test]
+    fn test_evaluation_under_another_key_is_refused() {
+        let zkp = groups::default_group();
+        let key = KeyPair::generate(&zkp);
+        // a key kept for one user, to recognize their tickets by
+        let tagging = KeyPair::generate(&zkp);
+        let blind = BlindTicket::new(&zkp);
+        let (evaluated, proof) = evaluate(&zkp, b"", &tagging, &blind.blinded).unwrap();
+        assert!(blind
+            .finish(&zkp, b"", &key.public, &evaluated, &proof)
+            .is_none());
+
+        // and outside the subgroup nothing is evaluated
+        assert!(evaluate(&zkp, b"", &key, &BigUint::from(1u32)).is_none());
+        assert!(evaluate(&zkp, b"", &key, &(&zkp.p - 1u32)).is_none());
+    }
+}
@@ -0,0 +1,185 @@
+use sha2::{Digest, Sha256};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+// tls-server-end-point channel binding (RFC 5929): hash of the certificate the
+// server presents. SHA-256 is used for every certificate, which is what the RFC
+// asks for unless the certificate is signed with a stronger hash
+pub fn server_end_point(cert_der: &[u8]) -> Vec<u8> {
+    Sha256::digest(cert_der).to_vec()
+}
+
+// every certificate in a PEM file, the server's own certificate comes first
+pub fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let certificates = CertificateDer::pem_file_iter(path)
+        .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+    if certificates.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("no certificate found in {}", path.display()),
+        ));
+    }
+    Ok(certificates)
+}
+
+// trusts only the certificates in ca_file, speaks HTTP/2 for gRPC. with
+// identity (a PEM certificate chain and its key) it presents a client
+// certificate, for a server that requires one (server --tls-client-ca)
+pub fn client_config(
+    ca_file: &Path,
+    identity: Option<(&Path, &Path)>,
+) -> Result<Arc<ClientConfig>, Error> {
+    let mut roots = RootCertStore::empty();
+    for certificate in load_certificates(ca_file)? {
+        roots
+            .add(certificate)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+    }
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+    let mut config = match identity {
+        Some((cert_file, key_file)) => {
+            let key = PrivateKeyDer::from_pem_file(key_file)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+            builder
+                .with_client_auth_cert(load_certificates(cert_file)?, key)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?
+        }
+        None => builder.with_no_client_auth(),
+    };
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    Ok(Arc::new(config))
+}
+
+// the part of a client certificate that names its holder, see
+// client_identities. server --client-cert-identity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertIdentity {
+    // the CN of the subject
+    CommonName,
+    // the DNS names of the subject alternative names
+    DnsName,
+    // the URIs of the subject alternative names, e.g. SPIFFE IDs
+    Uri,
+}
+
+// the names a client certificate gives its holder, empty for a certificate
+// that can't be parsed or has none of the kind
+pub fn client_identities(cert_der: &CertificateDer<'_>, kind: CertIdentity) -> Vec<String> {
+    let Ok(cert) = webpki::EndEntityCert::try_from(cert_der) else {
+        return Vec::new();
+    };
+    match kind {
+        CertIdentity::CommonName => common_names(cert.subject()),
+        CertIdentity::DnsName => cert.valid_dns_names().map(str::to_string).collect(),
+        CertIdentity::Uri => cert.valid_uri_names().map(str::to_string).collect(),
+    }
+}
+
+// one DER element off the front of input: (tag, contents, rest). None when
+// it is cut short, or longer than a certificate field can be
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = match first {
+        0..=0x7f => (first as usize, rest),
+        0x81..=0x84 => {
+            let (len, rest) = rest.split_at_checked((first & 0x7f) as usize)?;
+            let len = len.iter().fold(0, |len, &byte| len << 8 | byte as usize);
+            (len, rest)
+        }
+        _ => return None,
+    };
+    let (contents, rest) = rest.split_at_checked(len)?;
+    Some((tag, contents, rest))
+}
+
+// id-at-commonName, 2.5.4.3
+const COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+// the commonName attributes of a subject, the contents of its Name: a
+// SEQUENCE of SETs of SEQUENCE { type, value }. values that aren't
+// UTF8String, PrintableString or IA5String are skipped
+fn common_names(subject: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rdns = subject;
+    while let Some((0x31, mut attributes, rest)) = der_element(rdns) {
+        while let Some((0x30, attribute, next)) = der_element(attributes) {
+            if let Some((0x06, COMMON_NAME, value)) = der_element(attribute)
+                && let Some((0x0c | 0x13 | 0x16, value, _)) = der_element(value)
+                && let Ok(name) = std::str::from_utf8(value)
+            {
+                names.push(name.to_string());
+            }
+            attributes = next;
+        }
+        rdns = rest;
+    }
+    names
+}
+
+// TLS handshake over an established stream, returns the stream together with
+// the channel binding of the certificate the peer actually presented
+pub async fn connect<S>(
+    stream: S,
+    host: &str,
+    config: Arc<ClientConfig>,
+) -> Result<(TlsStream<S>, Vec<u8>), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let server_name = ServerName::try_from(host.to_string())
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))?;
+    let stream = TlsConnector::from(config)
+        .connect(server_name, stream)
+        .await?;
+    let binding = match stream.get_ref().1.peer_certificates() {
+        Some([certificate, ..]) => server_end_point(certificate),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "server sent no certificate",
+            ));
+        }
+    };
+    Ok((stream, binding))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_identities() {
+        let cert = CertificateDer::from_pem_slice(include_bytes!("../tests/vectors/tls/alice.pem"))
+            .unwrap();
+        assert_eq!(
+            client_identities(&cert, CertIdentity::CommonName),
+            ["alice"]
+        );
+        assert_eq!(
+            client_identities(&cert, CertIdentity::DnsName),
+            ["alice.example.com"]
+        );
+        assert_eq!(
+            client_identities(&cert, CertIdentity::Uri),
+            ["spiffe://example.com/alice"]
+        );
+        let garbage = CertificateDer::from(vec![0x30, 0x03, 0x02, 0x01, 0x00]);
+        assert!(client_identities(&garbage, CertIdentity::CommonName).is_empty());
+
+        // a length past the end of the input
+        assert_eq!(der_element(&[0x30, 0x05, 0x00]), None);
+        assert_eq!(
+            der_element(&[0x30, 0x81, 0x01, 0x07]),
+            Some((0x30, &[0x07][..], &[][..]))
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use crate::encoding::{self, MAX_ELEMENT_LEN};
+use num_bigint::BigUint;
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+use zeroize::Zeroize;
+
+// Fiat–Shamir transcript: every absorbed item is labelled and length-prefixed
+// so that two different sequences of items can never hash to the same input
+#[derive(Clone)]
+pub struct Transcript {
+    hasher: Sha256,
+}
+
+impl Transcript {
+    pub fn new(domain: &[u8]) -> Self {
+        let mut transcript = Transcript {
+            hasher: Sha256::new(),
+        };
+        transcript.append(b"domain", domain);
+        transcript
+    }
+
+    pub fn append(&mut self, label: &[u8], bytes: &[u8]) {
+        self.hasher.update((label.len() as u64).to_be_bytes());
+        self.hasher.update(label);
+        self.hasher.update((bytes.len() as u64).to_be_bytes());
+        self.hasher.update(bytes);
+    }
+
+    // `len` bytes read from `reader` in chunks, hashed as append would hash
+    // them all at once, so a large payload is never held in memory. a reader
+    // that ends early is an error, and leaves the item half absorbed: the
+    // transcript must then be dropped
+    pub fn append_reader(&mut self, label: &[u8], len: u64, reader: impl Read) -> io::Result<()> {
+        self.hasher.update((label.len() as u64).to_be_bytes());
+        self.hasher.update(label);
+        self.hasher.update(len.to_be_bytes());
+        let read = io::copy(&mut reader.take(len), &mut self.hasher)?;
+        if read < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} bytes read of {}", read, len),
+            ));
+        }
+        Ok(())
+    }
+
+    // the bytes of to_bytes_be, written on the stack (unreduced scalars are up
+    // to twice an element). they may be a secret, the buffer is wiped after
+    pub fn append_biguint(&mut self, label: &[u8], n: &BigUint) {
+        let mut buffer = [0u8; 2 * MAX_ELEMENT_LEN];
+        let len = encoding::be_len(n);
+        match buffer.get_mut(..len) {
+            Some(bytes) => {
+                encoding::write_be_into(n, bytes).expect("the buffer is as long as n");
+                self.append(label, bytes);
+                bytes.zeroize();
+            }
+            None => self.append(label, &n.to_bytes_be()),
+        }
+    }
+
+    // c = H(transcript) mod q
+    pub fn challenge(self, q: &BigUint) -> BigUint {
+        encoding::from_be_exact(&self.finalize()) % q
+    }
+
+    pub fn finalize(self) -> [u8; 32] {
+        self.hasher.finalize().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length_prefix_separates_items() {
+        let q = BigUint::from(1_000_003u32);
+
+        let mut t1 = Transcript::new(b"test");
+        t1.append(b"a", b"xy");
+        t1.append(b"b", b"z");
+
+        let mut t2 = Transcript::new(b"test");
+        t2.append(b"a", b"x");
+        t2.append(b"b", b"yz");
+
+        assert_ne!(t1.challenge(&q), t2.challenge(&q));
+    }
+
+    #[test]
+    fn test_domain_changes_challenge() {
+        let q = BigUint::from(1_000_003u32);
+        let n = BigUint::from(42u32);
+
+        let mut t1 = Transcript::new(b"app-1");
+        t1.append_biguint(b"n", &n);
+        let mut t2 = Transcript::new(b"app-2");
+        t2.append_biguint(b"n", &n);
+
+        assert_ne!(t1.challenge(&q), t2.challenge(&q));
+    }
+
+    #[test]
+    fn test_streamed_item_hashes_like_append() {
+        let payload: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let mut t1 = Transcript::new(b"test");
+        t1.append(b"payload", &payload);
+        let mut t2 = Transcript::new(b"test");
+        t2.append_reader(b"payload", payload.len() as u64, &payload[..])
+            .unwrap();
+        assert_eq!(t1.finalize(), t2.finalize());
+
+        // a longer reader is cut at len, a shorter one is an error
+        let mut t3 = Transcript::new(b"test");
+        t3.append_reader(b"payload", 10, &payload[..]).unwrap();
+        let mut t4 = Transcript::new(b"test");
+        t4.append(b"payload", &payload[..10]);
+        assert_eq!(t3.finalize(), t4.finalize());
+        let error = Transcript::new(b"test")
+            .append_reader(b"payload", 10, &payload[..4])
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_biguint_is_absorbed_as_its_bytes() {
+        let small = BigUint::from(0x0102u32);
+        let large = BigUint::from(1u32) << (8 * 2 * MAX_ELEMENT_LEN + 3);
+        for n in [BigUint::from(0u32), small, large] {
+            let mut t1 = Transcript::new(b"test");
+            t1.append_biguint(b"n", &n);
+            let mut t2 = Transcript::new(b"test");
+            t2.append(b"n", &n.to_bytes_be());
+            assert_eq!(t1.finalize(), t2.finalize());
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::group::ChaumPedersenGroup;
+use crate::ZKP;
+use num_bigint::BigUint;
+
+// Chaum-Pedersen in a group of unknown order, e.g. the quadratic residues mod
+// an RSA modulus n whose factors nobody kept, as used by RSA accumulators.
+// there is no q to reduce by, so the response is s = k + c * x over the
+// integers and hides x only because k is drawn from a range
+// 2 ** security_bits times larger than any c * x (statistical hiding)
+//
+// g and h should be squares so that -1 and other small-order elements can't
+// be mixed in, and the verifier must reject responses above the bound
+#[derive(Debug, Clone)]
+pub struct UnknownOrderGroup {
+    pub n: BigUint,
+    pub g: BigUint,
+    pub h: BigUint,
+    // x < 2 ** secret_bits
+    pub secret_bits: u64,
+    // c < 2 ** challenge_bits
+    pub challenge_bits: u64,
+    // statistical distance between s and uniform, about 2 ** -security_bits
+    pub security_bits: u64,
+}
+
+fn power_of_two(bits: u64) -> BigUint {
+    BigUint::from(1u32) << bits
+}
+
+impl UnknownOrderGroup {
+    // k < 2 ** (secret_bits + challenge_bits + security_bits)
+    pub fn nonce_bound(&self) -> BigUint {
+        power_of_two(self.secret_bits + self.challenge_bits + self.security_bits)
+    }
+
+    // s = k + c * x < nonce_bound + 2 ** (secret_bits + challenge_bits)
+    pub fn response_bound(&self) -> BigUint {
+        self.nonce_bound() + power_of_two(self.secret_bits + self.challenge_bits)
+    }
+
+    fn in_group(&self, n: &BigUint) -> bool {
+        *n > BigUint::from(0u32) && *n < self.n
+    }
+}
+
+impl ChaumPedersenGroup for UnknownOrderGroup {
+    fn commit(&self, e: &BigUint) -> (BigUint, BigUint) {
+        (
+            ZKP::exponentiate(&self.g, e, &self.n),
+            ZKP::exponentiate(&self.h, e, &self.n),
+        )
+    }
+
+    // s = k + c * x, not reduced
+    fn respond(&self, k: &BigUint, c: &BigUint, x: &BigUint) -> BigUint {
+        k + c * x
+    }
+
+    // g ** s = r1 * y1 ** c mod n ; h ** s = r2 * y2 ** c mod n
+    fn check(
+        &self,
+        r1: &BigUint,
+        r2: &BigUint,
+        y1: &BigUint,
+        y2: &BigUint,
+        c: &BigUint,
+        s: &BigUint,
+    ) -> bool {
+        if !(self.in_group(r1) && self.in_group(r2) && self.in_group(y1) && self.in_group(y2))
+            || *c >= power_of_two(self.challenge_bits)
+            || *s >= self.response_bound()
+        {
+            return false;
+        }
+        let cond1 = ZKP::exponentiate(&self.g, s, &self.n)
+            == (r1 * ZKP::exponentiate(y1, c, &self.n)) % &self.n;
+        let cond2 = ZKP::exponentiate(&self.h, s, &self.n)
+            == (r2 * ZKP::exponentiate(y2, c, &self.n)) % &self.n;
+        cond1 && cond2
+    }
+
+    fn random_nonce(&self) -> BigUint {
+        ZKP::generate_random_number_below(&self.nonce_bound())
+    }
+
+    fn random_challenge(&self) -> BigUint {
+        ZKP::generate_random_number_below(&power_of_two(self.challenge_bits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::tests::run_round;
+
+    #[test]
+    fn test_unknown_order_round() {
+        // toy modulus (2 ** 61 - 1) * (2 ** 89 - 1); a real deployment uses an
+        // RSA modulus from a ceremony that discarded the factors
+        let p = (BigUint::from(1u32) << 61u32) - 1u32;
+        let q = (BigUint::from(1u32) << 89u32) - 1u32;
+        let group = UnknownOrderGroup {
+            n: p * q,
+            g: BigUint::from(4u32),
+            h: BigUint::from(9u32),
+            secret_bits: 256,
+            challenge_bits: 128,
+            security_bits: 128,
+        };
+        let x = ZKP::generate_random_number_below(&power_of_two(group.secret_bits));
+        assert!(run_round(&group, &x, &x));
+        assert!(!run_round(&group, &x, &(&x + 1u32)));
+
+        // a response above the bound is rejected even if the equations hold
+        let (y1, y2) = group.commit(&x);
+        let k = group.response_bound();
+        let (r1, r2) = group.commit(&k);
+        let c = BigUint::from(0u32);
+        assert!(!group.check(&r1, &r2, &y1, &y2, &c, &k));
+    }
+}
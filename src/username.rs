@@ -0,0 +1,198 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt::Display;
+use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
+use zeroize::Zeroizing;
+
+// user names are compared in a canonical form, NFC and (by default) case
+// folded, so "Alice", "alice" and an "Alice" typed with a combining accent
+// elsewhere are one account rather than look-alikes registered side by side.
+// the canonical name is what the server stores and looks up, and what a
+// salted verifier is derived from, so clients must canonicalize the same way
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsernamePolicy {
+    // in characters of the canonical name
+    pub min_len: usize,
+    pub max_len: usize,
+    pub fold_case: bool,
+    // canonical names nobody may register
+    pub reserved: Vec<String>,
+}
+
+impl Default for UsernamePolicy {
+    fn default() -> Self {
+        UsernamePolicy {
+            min_len: 1,
+            max_len: 64,
+            fold_case: true,
+            reserved: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UsernameError {
+    TooShort(usize),
+    TooLong(usize),
+    ControlCharacter,
+    Reserved(String),
+}
+
+impl Display for UsernameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsernameError::TooShort(min) => write!(f, "needs at least {} characters", min),
+            UsernameError::TooLong(max) => write!(f, "takes at most {} characters", max),
+            UsernameError::ControlCharacter => write!(f, "contains a control character"),
+            UsernameError::Reserved(name) => write!(f, "{} is reserved", name),
+        }
+    }
+}
+
+impl std::error::Error for UsernameError {}
+
+impl UsernamePolicy {
+    // the name as it is stored and looked up. lowercasing can undo NFC
+    // (a precomposed capital may lower to a decomposed pair), hence the
+    // second pass
+    pub fn canonical(&self, name: &str) -> String {
+        let nfc: String = name.nfc().collect();
+        if self.fold_case {
+            nfc.to_lowercase().nfc().collect()
+        } else {
+            nfc
+        }
+    }
+
+    // canonical form of a name being registered, or why it can't be
+    pub fn normalize(&self, name: &str) -> Result<String, UsernameError> {
+        let canonical = self.canonical(name);
+        let len = canonical.chars().count();
+        if len < self.min_len {
+            return Err(UsernameError::TooShort(self.min_len));
+        }
+        if len > self.max_len {
+            return Err(UsernameError::TooLong(self.max_len));
+        }
+        if canonical.chars().any(char::is_control) {
+            return Err(UsernameError::ControlCharacter);
+        }
+        if self
+            .reserved
+            .iter()
+            .any(|reserved| self.canonical(reserved) == canonical)
+        {
+            return Err(UsernameError::Reserved(canonical));
+        }
+        Ok(canonical)
+    }
+}
+
+// the fewest bytes a name key may have
+pub const MIN_NAME_KEY_LEN: usize = 32;
+
+// user names stored as HMAC-SHA256 under a key of the deployment instead of
+// in the clear, so a leaked store or export doesn't list the users while a
+// lookup still finds one by hashing the name it is given. a hashed name is
+// HASHED_PREFIX and the hex of the MAC
+#[derive(Clone)]
+pub struct NameHasher {
+    key: Zeroizing<Vec<u8>>,
+}
+
+pub const HASHED_PREFIX: &str = "hmac-sha256:";
+
+impl std::fmt::Debug for NameHasher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("NameHasher(..)")
+    }
+}
+
+impl NameHasher {
+    pub fn new(key: impl Into<Vec<u8>>) -> Result<Self, String> {
+        let key = Zeroizing::new(key.into());
+        if key.len() < MIN_NAME_KEY_LEN {
+            return Err(format!(
+                "a name key needs at least {} bytes, not {}",
+                MIN_NAME_KEY_LEN,
+                key.len()
+            ));
+        }
+        Ok(NameHasher { key })
+    }
+
+    // the key is the file's contents with the surrounding whitespace trimmed
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = Zeroizing::new(std::fs::read_to_string(path).map_err(|e| e.to_string())?);
+        NameHasher::new(contents.trim().as_bytes())
+    }
+
+    pub fn is_hashed(name: &str) -> bool {
+        name.starts_with(HASHED_PREFIX)
+    }
+
+    // the name as the store keeps it
+    pub fn hash(&self, name: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC takes any key length");
+        mac.update(name.as_bytes());
+        format!(
+            "{}{}",
+            HASHED_PREFIX,
+            hex::encode(mac.finalize().into_bytes())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_are_canonical() {
+        let policy = UsernamePolicy {
+            reserved: vec!["Admin".to_string()],
+            ..UsernamePolicy::default()
+        };
+        assert_eq!(policy.normalize("Alice"), Ok("alice".to_string()));
+        // é precomposed and as e + combining acute
+        assert_eq!(
+            policy.normalize("Ren\u{e9}e"),
+            policy.normalize("RENE\u{301}E")
+        );
+        assert_eq!(policy.normalize(""), Err(UsernameError::TooShort(1)));
+        assert_eq!(
+            policy.normalize(&"a".repeat(65)),
+            Err(UsernameError::TooLong(64))
+        );
+        assert_eq!(
+            policy.normalize("bob\n"),
+            Err(UsernameError::ControlCharacter)
+        );
+        assert_eq!(
+            policy.normalize("ADMIN"),
+            Err(UsernameError::Reserved("admin".to_string()))
+        );
+
+        let sensitive = UsernamePolicy {
+            fold_case: false,
+            ..UsernamePolicy::default()
+        };
+        assert_eq!(sensitive.normalize("Alice"), Ok("Alice".to_string()));
+        assert_eq!(sensitive.canonical("Ren\u{e9}e"), "Ren\u{e9}e");
+        assert_eq!(sensitive.canonical("Rene\u{301}e"), "Ren\u{e9}e");
+    }
+
+    #[test]
+    fn test_names_hash_under_the_key() {
+        assert!(NameHasher::new(vec![1; 31]).is_err());
+        let names = NameHasher::new(vec![1; 32]).unwrap();
+        let alice = names.hash("alice");
+        assert!(NameHasher::is_hashed(&alice));
+        assert!(!alice.contains("alice"));
+        // a name that looks hashed is hashed all the same
+        assert_ne!(names.hash(&alice), alice);
+        assert_ne!(names.hash("bob"), alice);
+        assert_ne!(NameHasher::new(vec![2; 32]).unwrap().hash("alice"), alice);
+    }
+}
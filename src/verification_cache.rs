@@ -0,0 +1,152 @@
+use crate::{Proof, ZKP};
+use num_bigint::BigUint;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+// results of non-interactive verifications, by a digest of everything the
+// result depends on (group, statement, proof and context), so verifying the
+// same artifact again (an audit replaying a log of proofs) is a lookup.
+// opt-in and only for artifacts that may be seen more than once: a login
+// answer must never be checked through it, a replayed answer would pass
+// without reaching the replay checks. the oldest entry makes room once it is full
+#[derive(Debug)]
+pub struct VerificationCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    results: HashMap<[u8; 32], bool>,
+    order: VecDeque<[u8; 32]>,
+    hits: u64,
+}
+
+impl VerificationCache {
+    pub fn new(capacity: usize) -> Self {
+        VerificationCache {
+            capacity,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    // ZKP::verify_non_interactive, from the cache when this exact proof was
+    // verified before
+    pub fn verify_non_interactive(
+        &self,
+        zkp: &ZKP,
+        y1: &BigUint,
+        y2: &BigUint,
+        proof: &Proof,
+        context: &[u8],
+    ) -> bool {
+        self.verify_non_interactive_in(zkp, b"", y1, y2, proof, context)
+    }
+
+    // same under a deployment's application id, which is part of the key
+    pub fn verify_non_interactive_in(
+        &self,
+        zkp: &ZKP,
+        application: &[u8],
+        y1: &BigUint,
+        y2: &BigUint,
+        proof: &Proof,
+        context: &[u8],
+    ) -> bool {
+        let mut transcript =
+            zkp.application_transcript(b"zkp-chaum-pedersen/verification-cache", application);
+        transcript.append_biguint(b"y1", y1);
+        transcript.append_biguint(b"y2", y2);
+        transcript.append_biguint(b"c", &proof.c);
+        transcript.append_biguint(b"s", &proof.s);
+        transcript.append(b"context", context);
+        let key = transcript.finalize();
+
+        if let Some(result) = self.lookup(&key) {
+            return result;
+        }
+        // verified outside the lock, a concurrent miss on the same key just
+        // verifies it twice
+        let result = zkp.verify_non_interactive_in(application, y1, y2, proof, context);
+        self.store(key, result);
+        result
+    }
+
+    fn lookup(&self, key: &[u8; 32]) -> Option<bool> {
+        let mut entries = self.entries.lock().unwrap();
+        let result = entries.results.get(key).copied();
+        if result.is_some() {
+            entries.hits += 1;
+        }
+        result
+    }
+
+    fn store(&self, key: [u8; 32], result: bool) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.results.insert(key, result).is_some() {
+            return;
+        }
+        entries.order.push_back(key);
+        if entries.order.len() > self.capacity
+            && let Some(oldest) = entries.order.pop_front()
+        {
+            entries.results.remove(&oldest);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // verifications answered from the cache
+    pub fn hits(&self) -> u64 {
+        self.entries.lock().unwrap().hits
+    }
+}
+
+#[cfg(all(test, feature = "prover"))]
+mod tests {
+    use super::*;
+    use crate::groups;
+
+    #[test]
+    fn test_cached_verifications() {
+        let zkp = groups::default_group();
+        let cache = VerificationCache::new(2);
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let (y1, y2) = (
+            ZKP::exponentiate(&zkp.g, &x, &zkp.p),
+            ZKP::exponentiate(&zkp.h, &x, &zkp.p),
+        );
+        let proof = zkp.prove_non_interactive(&x, b"audit");
+
+        assert!(cache.verify_non_interactive(&zkp, &y1, &y2, &proof, b"audit"));
+        assert!(cache.verify_non_interactive(&zkp, &y1, &y2, &proof, b"audit"));
+        assert_eq!((cache.len(), cache.hits()), (1, 1));
+        // another context is another entry, and fails like it does uncached
+        assert!(!cache.verify_non_interactive(&zkp, &y1, &y2, &proof, b"other"));
+        assert!(!cache.verify_non_interactive(&zkp, &y1, &y2, &proof, b"other"));
+        assert_eq!((cache.len(), cache.hits()), (2, 2));
+
+        // a third entry pushes out the first
+        let tampered = Proof {
+            c: proof.c.clone(),
+            s: &proof.s + 1u32,
+        };
+        assert!(!cache.verify_non_interactive(&zkp, &y1, &y2, &tampered, b"audit"));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.verify_non_interactive(&zkp, &y1, &y2, &proof, b"audit"));
+        assert_eq!(cache.hits(), 2);
+
+        let disabled = VerificationCache::new(0);
+        assert!(disabled.verify_non_interactive(&zkp, &y1, &y2, &proof, b"audit"));
+        assert!(disabled.is_empty());
+    }
+}
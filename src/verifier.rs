@@ -0,0 +1,338 @@
+use crate::secret::SecretBigUint;
+use crate::ZKP;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt::Display;
+use zeroize::Zeroizing;
+
+pub const SALT_LEN: usize = 16;
+pub const PBKDF2_ITERATIONS: u32 = 100_000;
+
+// RFC 9106 second recommended option (64 MiB, 3 passes, 4 lanes)
+pub const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+pub const ARGON2_PASSES: u32 = 3;
+pub const ARGON2_PARALLELISM: u32 = 4;
+
+// how x is derived from the password and salt. registered with the verifier
+// and handed back with every challenge, so a client on a new device derives
+// the same x. verifiers salted before the parameters were sent use the default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+pub enum KdfParams {
+    Pbkdf2 {
+        iterations: u32,
+    },
+    Argon2id {
+        memory_kib: u32,
+        passes: u32,
+        parallelism: u32,
+    },
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams::Pbkdf2 {
+            iterations: PBKDF2_ITERATIONS,
+        }
+    }
+}
+
+impl KdfParams {
+    pub fn argon2id() -> Self {
+        KdfParams::Argon2id {
+            memory_kib: ARGON2_MEMORY_KIB,
+            passes: ARGON2_PASSES,
+            parallelism: ARGON2_PARALLELISM,
+        }
+    }
+}
+
+impl Display for KdfParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KdfParams::Pbkdf2 { iterations } => {
+                write!(f, "PBKDF2-HMAC-SHA256 ({} iterations)", iterations)
+            }
+            KdfParams::Argon2id {
+                memory_kib,
+                passes,
+                parallelism,
+            } => write!(
+                f,
+                "Argon2id (m={} KiB, t={}, p={})",
+                memory_kib, passes, parallelism
+            ),
+        }
+    }
+}
+
+// the weakest parameters a server registers. also caps them, so a server
+// can't make clients on new devices spend unbounded memory or time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfPolicy {
+    pub min_pbkdf2_iterations: u32,
+    pub min_argon2_memory_kib: u32,
+    pub min_argon2_passes: u32,
+}
+
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+const MAX_ARGON2_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+const MAX_ARGON2_PASSES: u32 = 64;
+const MAX_ARGON2_PARALLELISM: u32 = 64;
+
+impl Default for KdfPolicy {
+    fn default() -> Self {
+        KdfPolicy {
+            min_pbkdf2_iterations: PBKDF2_ITERATIONS,
+            // OWASP minimum: 19 MiB, 2 passes
+            min_argon2_memory_kib: 19 * 1024,
+            min_argon2_passes: 2,
+        }
+    }
+}
+
+impl KdfPolicy {
+    // only the caps, for a client deriving x with parameters the server sent
+    pub fn permissive() -> Self {
+        KdfPolicy {
+            min_pbkdf2_iterations: 1,
+            min_argon2_memory_kib: 8,
+            min_argon2_passes: 1,
+        }
+    }
+
+    pub fn check(&self, kdf: &KdfParams) -> Result<(), String> {
+        match *kdf {
+            KdfParams::Pbkdf2 { iterations } => {
+                if iterations < self.min_pbkdf2_iterations {
+                    return Err(format!(
+                        "PBKDF2 needs at least {} iterations",
+                        self.min_pbkdf2_iterations
+                    ));
+                }
+                if iterations > MAX_PBKDF2_ITERATIONS {
+                    return Err(format!(
+                        "PBKDF2 takes at most {} iterations",
+                        MAX_PBKDF2_ITERATIONS
+                    ));
+                }
+            }
+            KdfParams::Argon2id {
+                memory_kib,
+                passes,
+                parallelism,
+            } => {
+                if memory_kib < self.min_argon2_memory_kib || passes < self.min_argon2_passes {
+                    return Err(format!(
+                        "Argon2id needs at least {} KiB and {} passes",
+                        self.min_argon2_memory_kib, self.min_argon2_passes
+                    ));
+                }
+                if memory_kib > MAX_ARGON2_MEMORY_KIB
+                    || passes > MAX_ARGON2_PASSES
+                    || parallelism == 0
+                    || parallelism > MAX_ARGON2_PARALLELISM
+                    // argon2 needs 8 KiB per lane
+                    || memory_kib < 8 * parallelism
+                {
+                    return Err("Argon2id parameters are out of range".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn generate_salt() -> Vec<u8> {
+    let mut salt = vec![0u8; SALT_LEN];
+    crate::rng::with_rng(|rng| rng.fill_bytes(&mut salt));
+    salt
+}
+
+// salted secret, SRP style: x = PBKDF2-HMAC-SHA256(len || user || password, salt) mod q
+// the stored (y1, y2) can then only be attacked one user and one slow guess at
+// a time, instead of being matched against a precomputed dictionary
+pub fn derive_secret(
+    zkp: &ZKP,
+    user: &str,
+    password: &str,
+    salt: &[u8],
+    iterations: u32,
+) -> SecretBigUint {
+    SecretBigUint::new(pbkdf2_output(user, password, salt, iterations).expose() % &zkp.q)
+}
+
+fn pbkdf2_output(user: &str, password: &str, salt: &[u8], iterations: u32) -> SecretBigUint {
+    let mut input = Zeroizing::new(Vec::new());
+    input.extend_from_slice(&(user.len() as u64).to_be_bytes());
+    input.extend_from_slice(user.as_bytes());
+    input.extend_from_slice(password.as_bytes());
+
+    let mut output = Zeroizing::new([0u8; 32]);
+    pbkdf2::pbkdf2_hmac::<Sha256>(&input, salt, iterations, output.as_mut());
+    SecretBigUint::new(BigUint::from_bytes_be(output.as_ref()))
+}
+
+// x = Argon2id(len || user || password, salt) mod q, the memory-hard variant
+pub fn derive_secret_argon2id(
+    zkp: &ZKP,
+    user: &str,
+    password: &str,
+    salt: &[u8],
+    memory_kib: u32,
+    passes: u32,
+    parallelism: u32,
+) -> SecretBigUint {
+    let output = argon2id_output(user, password, salt, memory_kib, passes, parallelism);
+    SecretBigUint::new(output.expose() % &zkp.q)
+}
+
+fn argon2id_output(
+    user: &str,
+    password: &str,
+    salt: &[u8],
+    memory_kib: u32,
+    passes: u32,
+    parallelism: u32,
+) -> SecretBigUint {
+    let mut input = Zeroizing::new(Vec::new());
+    input.extend_from_slice(&(user.len() as u64).to_be_bytes());
+    input.extend_from_slice(user.as_bytes());
+    input.extend_from_slice(password.as_bytes());
+
+    let params = argon2::Params::new(memory_kib, passes, parallelism, Some(32))
+        .expect("argon2 parameters are checked by KdfPolicy");
+    let argon2 = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+    let mut output = Zeroizing::new([0u8; 32]);
+    argon2
+        .hash_password_into(&input, salt, output.as_mut())
+        .expect("salts are at least 8 bytes");
+    SecretBigUint::new(BigUint::from_bytes_be(output.as_ref()))
+}
+
+pub fn derive_secret_with(
+    zkp: &ZKP,
+    user: &str,
+    password: &str,
+    salt: &[u8],
+    kdf: &KdfParams,
+) -> SecretBigUint {
+    match *kdf {
+        KdfParams::Pbkdf2 { iterations } => derive_secret(zkp, user, password, salt, iterations),
+        KdfParams::Argon2id {
+            memory_kib,
+            passes,
+            parallelism,
+        } => derive_secret_argon2id(zkp, user, password, salt, memory_kib, passes, parallelism),
+    }
+}
+
+// x before it is reduced into a group: the KDF output, or the password
+// itself without a salt. every group's x is this integer mod its q, so it is
+// the one secret a verifier in either of two groups was computed from (see
+// cross_group)
+pub fn unreduced_secret(user: &str, password: &str, salt: &[u8], kdf: &KdfParams) -> SecretBigUint {
+    if salt.is_empty() {
+        return SecretBigUint::from_password(password);
+    }
+    match *kdf {
+        KdfParams::Pbkdf2 { iterations } => pbkdf2_output(user, password, salt, iterations),
+        KdfParams::Argon2id {
+            memory_kib,
+            passes,
+            parallelism,
+        } => argon2id_output(user, password, salt, memory_kib, passes, parallelism),
+    }
+}
+
+// x for a login: salted when the server returned a salt for the user,
+// otherwise the password itself as before
+pub fn login_secret(
+    zkp: &ZKP,
+    user: &str,
+    password: &str,
+    salt: &[u8],
+    kdf: &KdfParams,
+) -> SecretBigUint {
+    if salt.is_empty() {
+        SecretBigUint::from_password(password)
+    } else {
+        derive_secret_with(zkp, user, password, salt, kdf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_salt_and_user_change_the_secret() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+        let salt = generate_salt();
+        assert_eq!(salt.len(), SALT_LEN);
+
+        let x = derive_secret(&zkp, "alice", "hunter2", &salt, 1_000);
+        assert_eq!(
+            x.expose(),
+            derive_secret(&zkp, "alice", "hunter2", &salt, 1_000).expose()
+        );
+        assert!(x.expose() < &zkp.q);
+
+        let other_salt = generate_salt();
+        assert_ne!(
+            x.expose(),
+            derive_secret(&zkp, "alice", "hunter2", &other_salt, 1_000).expose()
+        );
+        assert_ne!(
+            x.expose(),
+            derive_secret(&zkp, "bob", "hunter2", &salt, 1_000).expose()
+        );
+        // "al" + "icehunter2" must not collide with "alice" + "hunter2"
+        assert_ne!(
+            x.expose(),
+            derive_secret(&zkp, "al", "icehunter2", &salt, 1_000).expose()
+        );
+    }
+
+    #[test]
+    fn test_kdf_params() {
+        let (g, h, p, q) = ZKP::get_constants();
+        let zkp = ZKP { p, q, g, h };
+        let salt = generate_salt();
+        // small enough for a test, checked against a lowered policy
+        let argon2 = KdfParams::Argon2id {
+            memory_kib: 64,
+            passes: 1,
+            parallelism: 1,
+        };
+        let policy = KdfPolicy {
+            min_argon2_memory_kib: 64,
+            min_argon2_passes: 1,
+            ..KdfPolicy::default()
+        };
+        assert_eq!(policy.check(&argon2), Ok(()));
+        assert!(KdfPolicy::default().check(&argon2).is_err());
+        assert_eq!(KdfPolicy::default().check(&KdfParams::argon2id()), Ok(()));
+        assert_eq!(KdfPolicy::default().check(&KdfParams::default()), Ok(()));
+        assert!(KdfPolicy::default()
+            .check(&KdfParams::Pbkdf2 { iterations: 1_000 })
+            .is_err());
+
+        let x = login_secret(&zkp, "alice", "hunter2", &salt, &argon2);
+        assert_eq!(
+            x.expose(),
+            login_secret(&zkp, "alice", "hunter2", &salt, &argon2).expose()
+        );
+        let pbkdf2 = KdfParams::Pbkdf2 { iterations: 1_000 };
+        assert_ne!(
+            x.expose(),
+            login_secret(&zkp, "alice", "hunter2", &salt, &pbkdf2).expose()
+        );
+        assert_eq!(
+            login_secret(&zkp, "alice", "hunter2", &salt, &pbkdf2).expose(),
+            derive_secret(&zkp, "alice", "hunter2", &salt, 1_000).expose()
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+// a fixed number of threads doing the verification math, fed from a bounded
+// queue. the async handlers hand their exponentiations over and wait, so a
+// burst of logins queues here instead of stalling the runtime that accepts
+// connections, and once the queue is full a login is turned away at once
+// rather than waiting longer and longer
+type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug)]
+pub struct WorkerPool {
+    queue: SyncSender<Job>,
+    workers: usize,
+    capacity: usize,
+    // jobs waiting for a worker
+    queued: Arc<AtomicUsize>,
+    latencies: LatencyWindow,
+}
+
+// how long recent jobs took from being queued to their result, over the last
+// SPAN. a sample ages out even when no new ones arrive, so a server that has
+// stopped taking work because it was slow sees itself as idle again
+#[derive(Debug, Default)]
+pub struct LatencyWindow(Mutex<VecDeque<(Instant, Duration)>>);
+
+const SPAN: Duration = Duration::from_secs(10);
+// at most this many samples are kept, the latest ones
+const MAX_SAMPLES: usize = 4096;
+
+impl LatencyWindow {
+    pub fn record(&self, at: Instant, latency: Duration) {
+        let mut samples = self.0.lock().unwrap();
+        samples.push_back((at, latency));
+        if samples.len() > MAX_SAMPLES {
+            samples.pop_front();
+        }
+    }
+
+    // the 99th percentile of the samples within SPAN of now, None without any
+    pub fn p99(&self, now: Instant) -> Option<Duration> {
+        let mut samples = self.0.lock().unwrap();
+        while samples
+            .front()
+            .is_some_and(|(at, _)| now.saturating_duration_since(*at) > SPAN)
+        {
+            samples.pop_front();
+        }
+        let mut latencies: Vec<Duration> = samples.iter().map(|(_, latency)| *latency).collect();
+        latencies.sort();
+        let rank = (latencies.len() * 99).div_ceil(100);
+        latencies.get(rank.checked_sub(1)?).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolError {
+    // every worker is busy and the queue is full
+    Saturated,
+    // the job panicked
+    Failed,
+}
+
+impl Display for PoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PoolError::Saturated => write!(f, "verification queue is full"),
+            PoolError::Failed => write!(f, "verification failed to run"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+fn work(jobs: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        // the lock is only held while waiting, not while the job runs
+        let job = jobs.lock().unwrap().recv();
+        match job {
+            // a panicking job drops its result sender, which its caller sees
+            Ok(job) => drop(std::panic::catch_unwind(AssertUnwindSafe(job))),
+            // the pool is gone
+            Err(_) => return,
+        }
+    }
+}
+
+impl WorkerPool {
+    // `workers` threads and room for `capacity` jobs waiting on them
+    pub fn new(workers: usize, capacity: usize) -> Self {
+        let (queue, jobs) = mpsc::sync_channel(capacity);
+        let jobs = Arc::new(Mutex::new(jobs));
+        for i in 0..workers.max(1) {
+            let jobs = jobs.clone();
+            std::thread::Builder::new()
+                .name(format!("verifier-{}", i))
+                .spawn(move || work(jobs))
+                .expect("verifier threads start");
+        }
+        WorkerPool {
+            queue,
+            workers: workers.max(1),
+            capacity,
+            queued: Arc::new(AtomicUsize::new(0)),
+            latencies: LatencyWindow::default(),
+        }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    pub fn latencies(&self) -> &LatencyWindow {
+        &self.latencies
+    }
+
+    // the queue and recent latency, in the Prometheus text format
+    pub fn metrics(&self, now: Instant) -> String {
+        let gauges = [
+            ("zkp_verification_queue_depth", self.queued() as f64),
+            (
+                "zkp_verification_latency_p99_seconds",
+                self.latencies.p99(now).unwrap_or_default().as_secs_f64(),
+            ),
+        ];
+        let mut out = String::new();
+        for (name, value) in gauges {
+            out += &format!("# TYPE {} gauge\n{} {}\n", name, name, value);
+        }
+        out
+    }
+
+    // runs the job on a worker and waits for its result, or fails at once
+    // when the queue is full
+    pub async fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce() -> T + Send + 'static,
+    ) -> Result<T, PoolError> {
+        let (result, receiver) = oneshot::channel();
+        let queued = self.queued.clone();
+        let job: Job = Box::new(move || {
+            queued.fetch_sub(1, Ordering::Relaxed);
+            // the caller may have gone away, nobody is left to tell
+            let _ = result.send(job());
+        });
+        let start = Instant::now();
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        self.queue.try_send(job).map_err(|e| {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+            match e {
+                TrySendError::Full(_) => PoolError::Saturated,
+                TrySendError::Disconnected(_) => PoolError::Failed,
+            }
+        })?;
+        let output = receiver.await.map_err(|_| PoolError::Failed)?;
+        let now = Instant::now();
+        self.latencies.record(now, now - start);
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_full_queue_is_rejected() {
+        let pool = Arc::new(WorkerPool::new(1, 1));
+        assert_eq!(pool.run(|| 6 * 7).await, Ok(42));
+        assert_eq!(
+            pool.run(|| panic!("bad job")).await,
+            Err::<(), _>(PoolError::Failed)
+        );
+
+        assert_eq!(pool.queued(), 0);
+
+        // the worker waits on this job
+        let (started, running) = channel();
+        let (release, released) = channel::<()>();
+        let busy = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                pool.run(move || {
+                    started.send(()).unwrap();
+                    released.recv().unwrap();
+                })
+                .await
+            }
+        });
+        tokio::task::spawn_blocking(move || running.recv().unwrap())
+            .await
+            .unwrap();
+        // queued behind it, its caller gives up waiting but the job stays
+        let queued = tokio::time::timeout(Duration::from_millis(50), pool.run(|| 1)).await;
+        assert!(queued.is_err());
+        assert_eq!(pool.run(|| 2).await, Err(PoolError::Saturated));
+        assert_eq!(pool.queued(), 1);
+
+        release.send(()).unwrap();
+        assert_eq!(busy.await.unwrap(), Ok(()));
+        // there is room again once the worker has taken the queued job
+        loop {
+            match pool.run(|| 3).await {
+                Err(PoolError::Saturated) => tokio::task::yield_now().await,
+                result => break assert_eq!(result, Ok(3)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_latency_window() {
+        let window = LatencyWindow::default();
+        let start = Instant::now();
+        assert_eq!(window.p99(start), None);
+        for ms in 1..=200 {
+            window.record(start, Duration::from_millis(ms));
+        }
+        assert_eq!(window.p99(start), Some(Duration::from_millis(198)));
+        window.record(start + SPAN, Duration::from_millis(5));
+        // the first 200 age out
+        assert_eq!(
+            window.p99(start + SPAN + Duration::from_secs(1)),
+            Some(Duration::from_millis(5))
+        );
+        assert_eq!(window.p99(start + SPAN * 3), None);
+    }
+}
@@ -0,0 +1,366 @@
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
+use num_bigint::BigUint;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use zkp_chaum_pedersen::artifacts::{Artifact, ContextProof, Format, GroupParams, SecretKey};
+use zkp_chaum_pedersen::capabilities;
+use zkp_chaum_pedersen::groups;
+use zkp_chaum_pedersen::ZKP;
+
+#[derive(Parser, Debug)]
+#[command(about = "Chaum-Pedersen group parameters, keys and proofs as files, without a server")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// write group parameters, a named group or freshly generated ones
+    GenParams {
+        /// named group to write (rfc5114-1024-160 or rfc5114-2048-256)
+        #[arg(long, conflicts_with = "bits")]
+        group: Option<String>,
+
+        /// generate a group with a p of this many bits instead (slow above 1024)
+        #[arg(long)]
+        bits: Option<u64>,
+
+        /// bits of the generated group's order q
+        #[arg(long, default_value_t = 256, requires = "bits")]
+        order_bits: u64,
+
+        #[command(flatten)]
+        output: Output,
+    },
+    /// draw a secret x and write it with its public values y1 = g^x, y2 = h^x
+    Keygen {
+        /// group parameters file
+        #[arg(long)]
+        params: PathBuf,
+
+        /// also write the public values alone, to hand to verifiers
+        #[arg(long)]
+        public_out: Option<PathBuf>,
+
+        #[command(flatten)]
+        output: Output,
+    },
+    /// prove knowledge of a key's x, bound to a context
+    Prove {
+        /// group parameters file
+        #[arg(long)]
+        params: PathBuf,
+
+        /// secret key file
+        #[arg(long)]
+        key: PathBuf,
+
+        #[command(flatten)]
+        context: Context,
+
+        #[command(flatten)]
+        output: Output,
+    },
+    /// check a proof against public values and a context, exits 1 when it fails
+    Verify {
+        /// group parameters file
+        #[arg(long)]
+        params: PathBuf,
+
+        /// public key file (a secret key file works too)
+        #[arg(long)]
+        public: PathBuf,
+
+        /// proof file
+        #[arg(long)]
+        proof: PathBuf,
+
+        #[command(flatten)]
+        context: Context,
+    },
+    /// describe any file this tool writes, and check group parameters
+    Inspect {
+        /// the file to describe
+        file: PathBuf,
+    },
+    /// list the features, backends and named groups of this build
+    Capabilities,
+}
+
+#[derive(ClapArgs, Debug)]
+struct Output {
+    /// file to write
+    #[arg(long)]
+    out: PathBuf,
+
+    /// encoding of the written file, reading takes either
+    #[arg(long, value_enum, default_value_t = FileFormat::Json)]
+    format: FileFormat,
+}
+
+#[derive(ClapArgs, Debug)]
+struct Context {
+    /// context the proof is bound to
+    #[arg(long, conflicts_with = "context_file")]
+    context: Option<String>,
+
+    /// file whose contents are the context, streamed rather than loaded
+    #[arg(long)]
+    context_file: Option<PathBuf>,
+
+    /// application id mixed into the challenge, as the server's --app-id
+    #[arg(long, default_value = "")]
+    app_id: String,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FileFormat {
+    Json,
+    Cbor,
+}
+
+impl From<FileFormat> for Format {
+    fn from(format: FileFormat) -> Self {
+        match format {
+            FileFormat::Json => Format::Json,
+            FileFormat::Cbor => Format::Cbor,
+        }
+    }
+}
+
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("❌ {}", message);
+    std::process::exit(1);
+}
+
+fn read(path: &Path) -> Artifact {
+    Artifact::read(path)
+        .unwrap_or_else(|e| fail(format!("Failed to read {}: {}", path.display(), e)))
+}
+
+fn read_params(path: &Path) -> ZKP {
+    let zkp = read(path)
+        .into_params()
+        .and_then(|params| params.zkp())
+        .unwrap_or_else(|e| fail(format!("{}: {}", path.display(), e)));
+    if let Err(e) = groups::validate(&zkp) {
+        fail(format!("{} is not a usable group: {}", path.display(), e));
+    }
+    zkp
+}
+
+fn write(artifact: &Artifact, output: &Output) {
+    if let Err(e) = artifact.write(&output.out, output.format.into()) {
+        fail(format!("Failed to write {}: {}", output.out.display(), e));
+    }
+    println!("✅ Wrote {} to {}", artifact.kind(), output.out.display());
+}
+
+// passes the context through while hashing it, for the proof's context_sha256
+struct Hashing<R> {
+    inner: R,
+    hash: Sha256,
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hash.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+// runs `f` over the context with its length, and returns its result with the
+// context's SHA-256
+fn with_context<T>(
+    context: &Context,
+    f: impl FnOnce(u64, &mut dyn Read) -> std::io::Result<T>,
+) -> (T, [u8; 32]) {
+    let (len, inner): (u64, Box<dyn Read>) = match (&context.context, &context.context_file) {
+        (_, Some(path)) => {
+            let file = std::fs::File::open(path)
+                .unwrap_or_else(|e| fail(format!("Failed to open {}: {}", path.display(), e)));
+            let len = file
+                .metadata()
+                .unwrap_or_else(|e| fail(format!("Failed to read {}: {}", path.display(), e)))
+                .len();
+            (len, Box::new(file))
+        }
+        (Some(text), None) => (
+            text.len() as u64,
+            Box::new(std::io::Cursor::new(text.clone().into_bytes())),
+        ),
+        (None, None) => (0, Box::new(std::io::empty())),
+    };
+    let mut reader = Hashing {
+        inner,
+        hash: Sha256::new(),
+    };
+    let result =
+        f(len, &mut reader).unwrap_or_else(|e| fail(format!("Failed to read the context: {}", e)));
+    (result, reader.hash.finalize().into())
+}
+
+fn gen_params(group: Option<String>, bits: Option<u64>, order_bits: u64, output: Output) {
+    let (name, zkp) = match bits {
+        Some(bits) => {
+            println!(
+                "⏳ Generating a {}-bit group with a {}-bit order",
+                bits, order_bits
+            );
+            let zkp = groups::generate(bits, order_bits).unwrap_or_else(|e| fail(e));
+            (None, zkp)
+        }
+        None => {
+            let name = groups::resolve(group.as_deref().unwrap_or(groups::DEFAULT));
+            let zkp = groups::by_name(name).unwrap_or_else(|| {
+                fail(format!(
+                    "Unknown group {}, expected one of {}",
+                    name,
+                    groups::NAMES.join(", ")
+                ))
+            });
+            (Some(name), zkp)
+        }
+    };
+    write(&Artifact::Params(GroupParams::new(name, &zkp)), &output);
+}
+
+fn keygen(params: PathBuf, public_out: Option<PathBuf>, output: Output) {
+    let zkp = read_params(&params);
+    // x = 0 would make y1 = y2 = 1, which anyone can prove for
+    let x = ZKP::generate_random_number_below(&(&zkp.q - 1u32)) + 1u32;
+    let key = SecretKey::new(&zkp, &x);
+    write(&Artifact::SecretKey(key.clone()), &output);
+    if let Some(out) = public_out {
+        let public = Output {
+            out,
+            format: output.format,
+        };
+        write(&Artifact::PublicKey(key.public()), &public);
+    }
+}
+
+fn prove(params: PathBuf, key: PathBuf, context: Context, output: Output) {
+    let zkp = read_params(&params);
+    let key = read(&key)
+        .into_secret_key()
+        .unwrap_or_else(|e| fail(format!("{}: {}", key.display(), e)));
+    let x = key.x().unwrap_or_else(|e| fail(e));
+    let (proof, context_sha256) = with_context(&context, |len, reader| {
+        zkp.prove_non_interactive_reader(context.app_id.as_bytes(), x.expose(), len, reader)
+    });
+    write(
+        &Artifact::Proof(ContextProof::new(&proof, context_sha256)),
+        &output,
+    );
+}
+
+fn verify(params: PathBuf, public: PathBuf, proof: PathBuf, context: Context) {
+    let zkp = read_params(&params);
+    let (y1, y2) = read(&public)
+        .into_public_values()
+        .and_then(|public| public.values())
+        .unwrap_or_else(|e| fail(format!("{}: {}", public.display(), e)));
+    let file = read(&proof)
+        .into_proof()
+        .unwrap_or_else(|e| fail(format!("{}: {}", proof.display(), e)));
+    let parsed = file.proof().unwrap_or_else(|e| fail(e));
+    let (verified, context_sha256) = with_context(&context, |len, reader| {
+        zkp.verify_non_interactive_reader(context.app_id.as_bytes(), &y1, &y2, &parsed, len, reader)
+    });
+    if verified {
+        println!("✅ Proof verified");
+        return;
+    }
+    if hex::encode(context_sha256) != file.context_sha256 {
+        fail("Proof rejected: the context differs from the one it was made for");
+    }
+    fail("Proof rejected");
+}
+
+fn bits(hex: &str) -> String {
+    match hex::decode(hex) {
+        Ok(bytes) => format!("{} bits", BigUint::from_bytes_be(&bytes).bits()),
+        Err(_) => "not hex".to_string(),
+    }
+}
+
+fn inspect(file: PathBuf) {
+    let artifact = read(&file);
+    println!("📄 {}: {}", file.display(), artifact.kind());
+    match artifact {
+        Artifact::Params(params) => {
+            if let Some(name) = &params.name {
+                println!("   name: {}", name);
+            }
+            println!("   p: {}, q: {}", bits(&params.p), bits(&params.q));
+            let zkp = params.zkp().unwrap_or_else(|e| fail(e));
+            println!("   fingerprint: {}", hex::encode(groups::fingerprint(&zkp)));
+            println!("   security: {} bits", zkp.security_bits());
+            if let Err(e) = groups::validate(&zkp) {
+                fail(format!("Not a usable group: {}", e));
+            }
+            if !groups::is_probable_prime(&zkp.p, 32) || !groups::is_probable_prime(&zkp.q, 32) {
+                fail("Not a usable group: p or q is not prime");
+            }
+            println!("✅ q divides p - 1, both are prime, g and h generate the order-q subgroup");
+        }
+        Artifact::SecretKey(key) => {
+            println!("   x: {} (not shown)", bits(&key.x));
+            println!("   y1: {}\n   y2: {}", key.y1, key.y2);
+        }
+        Artifact::PublicKey(public) => {
+            println!("   y1: {}\n   y2: {}", public.y1, public.y2);
+        }
+        Artifact::Proof(proof) => {
+            println!("   c: {}\n   s: {}", proof.c, proof.s);
+            println!("   context sha256: {}", proof.context_sha256);
+        }
+    }
+}
+
+fn print_capabilities() {
+    let capabilities = capabilities::capabilities();
+    println!("🧩 Features: {}", capabilities.features.join(", "));
+    println!("   backends: {}", capabilities.backends.join(", "));
+    println!("   proving: {}", capabilities.proving);
+    for group in &capabilities.groups {
+        println!(
+            "   {}: p {} bits, q {} bits, {} bits of security, proofs up to {} bytes",
+            group.name, group.p_bits, group.q_bits, group.security_bits, group.max_proof_bytes
+        );
+    }
+}
+
+fn main() {
+    match Args::parse().command {
+        Command::GenParams {
+            group,
+            bits,
+            order_bits,
+            output,
+        } => gen_params(group, bits, order_bits, output),
+        Command::Keygen {
+            params,
+            public_out,
+            output,
+        } => keygen(params, public_out, output),
+        Command::Prove {
+            params,
+            key,
+            context,
+            output,
+        } => prove(params, key, context, output),
+        Command::Verify {
+            params,
+            public,
+            proof,
+            context,
+        } => verify(params, public, proof, context),
+        Command::Inspect { file } => inspect(file),
+        Command::Capabilities => print_capabilities(),
+    }
+}
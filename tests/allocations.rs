@@ -0,0 +1,119 @@
+use num_bigint::BigUint;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use zkp_chaum_pedersen::encoding;
+use zkp_chaum_pedersen::groups;
+use zkp_chaum_pedersen::transcript::Transcript;
+use zkp_chaum_pedersen::ZKP;
+
+// what the hot path allocates today, in the default group. a change that
+// makes one of them go up should be a deliberate one
+const SOLVE: usize = 7;
+const MOD_MUL: usize = 2;
+const VERIFY: usize = 40;
+
+// counts the allocations of the thread making them, so tests running in
+// parallel don't see each other's
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn allocations<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    let after = ALLOCATIONS.with(Cell::get);
+    drop(result);
+    after - before
+}
+
+fn at_most(count: usize, limit: usize) {
+    assert!(
+        count <= limit,
+        "{} allocations, expected at most {}",
+        count,
+        limit
+    );
+}
+
+// fixed inputs, so the counts don't depend on the size of random numbers
+fn inputs(zkp: &ZKP) -> (BigUint, BigUint, BigUint) {
+    let x = &zkp.q - 3u32;
+    let k = &zkp.q - 5u32;
+    let c = &zkp.q - 7u32;
+    (x, k, c)
+}
+
+#[test]
+fn test_solve_allocations() {
+    let zkp = groups::default_group();
+    let (x, k, c) = inputs(&zkp);
+    // c * x, and what reducing it takes. neither k nor q is cloned
+    at_most(allocations(|| zkp.solve(&k, &c, &x)), SOLVE);
+    // k < c * x takes the other branch
+    let one = BigUint::from(1u32);
+    at_most(allocations(|| zkp.solve(&one, &c, &x)), SOLVE);
+}
+
+#[test]
+fn test_mod_mul_allocations() {
+    let zkp = groups::default_group();
+    let (x, k, _) = inputs(&zkp);
+    at_most(allocations(|| ZKP::mod_mul(&x, &k, &zkp.p)), MOD_MUL);
+}
+
+#[test]
+fn test_verify_allocations() {
+    let zkp = groups::default_group();
+    let (x, k, c) = inputs(&zkp);
+    let (y1, y2) = (
+        ZKP::exponentiate(&zkp.g, &x, &zkp.p),
+        ZKP::exponentiate(&zkp.h, &x, &zkp.p),
+    );
+    let (r1, r2) = (
+        ZKP::exponentiate(&zkp.g, &k, &zkp.p),
+        ZKP::exponentiate(&zkp.h, &k, &zkp.p),
+    );
+    let s = zkp.solve(&k, &c, &x);
+    let mut verified = false;
+    let count = allocations(|| verified = zkp.verify(&r1, &r2, &y1, &y2, &c, &s));
+    assert!(verified);
+    // the exponentiations run on reused limb buffers, only their setup allocates
+    at_most(count, VERIFY);
+}
+
+#[test]
+fn test_encoding_allocations() {
+    let zkp = groups::default_group();
+    let (x, _, _) = inputs(&zkp);
+    let mut buffer = [0u8; encoding::MAX_ELEMENT_LEN];
+    at_most(
+        allocations(|| encoding::write_be_into(&x, &mut buffer).unwrap()),
+        0,
+    );
+    // hashing a number doesn't copy it into a Vec first
+    let mut transcript = Transcript::new(b"test");
+    at_most(allocations(|| transcript.append_biguint(b"p", &zkp.p)), 0);
+    // the digits of the result, nothing else
+    at_most(allocations(|| encoding::from_be_exact(&[7u8; 32])), 1);
+}
@@ -0,0 +1,3157 @@
+use num_bigint::BigUint;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{Channel, Endpoint, Server};
+use tonic::Code;
+use zkp_chaum_pedersen::access::AccessList;
+use zkp_chaum_pedersen::admission::Admission;
+use zkp_chaum_pedersen::audit::{self, Rotation};
+use zkp_chaum_pedersen::challenge::{ChallengeSource, Fixed, HashBound};
+use zkp_chaum_pedersen::challenge_pool::{ChallengePool, Pooled};
+use zkp_chaum_pedersen::clock::ManualClock;
+use zkp_chaum_pedersen::cross_group;
+use zkp_chaum_pedersen::failover::{self, Failover};
+use zkp_chaum_pedersen::grants::GrantPolicy;
+use zkp_chaum_pedersen::groups;
+use zkp_chaum_pedersen::keys::KeyPair;
+use zkp_chaum_pedersen::known_params::{KnownParameters, OnChange, Trust};
+use zkp_chaum_pedersen::lockout::{FileLockouts, LockoutPolicy};
+use zkp_chaum_pedersen::login::{
+    answer_challenge, check_known_parameters, check_parameters, create_challenge, issue_tickets,
+    login, migrate_group, receipt_of, recover_account, redeem_ticket, register, register_on_device,
+    register_with_kdf, register_with_recovery, AuthError, ChannelBinding, LoginOptions,
+};
+use zkp_chaum_pedersen::proto::*;
+use zkp_chaum_pedersen::quota::{Quota, QuotaPolicy};
+use zkp_chaum_pedersen::request_id::{self, RequestIdLayer};
+use zkp_chaum_pedersen::rng;
+use zkp_chaum_pedersen::second_factor::{SecondFactor, SecondFactorAttempt, SecondFactorError};
+use zkp_chaum_pedersen::secret::SecretBigUint;
+use zkp_chaum_pedersen::service::AuthImpl;
+use zkp_chaum_pedersen::service_v2::AuthV2;
+use zkp_chaum_pedersen::settings::{Settings, SettingsSource};
+use zkp_chaum_pedersen::state::{
+    IdLength, LimitPolicy, Retention, SessionLimit, UserStore, CHALLENGE_TTL,
+};
+use zkp_chaum_pedersen::tickets::Ticket;
+use zkp_chaum_pedersen::username::{NameHasher, UsernamePolicy};
+use zkp_chaum_pedersen::verifier::{KdfParams, KdfPolicy};
+use zkp_chaum_pedersen::workers::WorkerPool;
+use zkp_chaum_pedersen::ZKP;
+
+fn group() -> ZKP {
+    let (g, h, p, q) = ZKP::get_constants();
+    ZKP { p, q, g, h }
+}
+
+// serves the real service, v1 and v2 on one state, on an ephemeral port
+fn serve(service: AuthImpl) -> String {
+    serve_shared(Arc::new(service))
+}
+
+// for a test that also calls the service directly
+fn serve_shared(service: Arc<AuthImpl>) -> String {
+    let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = incoming.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .layer(RequestIdLayer)
+            .add_service(AuthServer::from_arc(service.clone()))
+            .add_service(v2::AuthServer::new(AuthV2(service)))
+            .serve_with_incoming(incoming),
+    );
+    format!("http://{}", addr)
+}
+
+// and connects a v1 client to it
+async fn start(service: AuthImpl) -> AuthClient<Channel> {
+    AuthClient::connect(serve(service)).await.unwrap()
+}
+
+fn options(channel_binding: &ChannelBinding) -> LoginOptions<'_> {
+    LoginOptions {
+        server_key: None,
+        channel_binding,
+        salted: false,
+        kdf: Default::default(),
+        blinded: false,
+        commit_nonce: false,
+        non_interactive: false,
+        application: b"",
+        second_factor: "",
+        device: None,
+        receipt: None,
+    }
+}
+
+#[tokio::test]
+async fn test_register_and_login() {
+    let zkp = group();
+    let mut client = start(AuthImpl::default()).await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let (session_id, key) =
+        answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+            .await
+            .unwrap();
+    let key = key.expect("server agrees a session key");
+    // 256 random bits, URL-safe base64
+    assert_eq!(session_id.len(), 43);
+
+    let session = client
+        .validate_session(ValidateSessionRequest {
+            session_id: session_id.clone(),
+            mac: key.mac(session_id.as_bytes()),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(session.user, "alice");
+
+    // the session ID alone is not enough once a MAC is checked
+    let status = client
+        .validate_session(ValidateSessionRequest {
+            session_id: session_id.clone(),
+            mac: vec![0; 32],
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    client
+        .logout(LogoutRequest {
+            session_id: session_id.clone(),
+        })
+        .await
+        .unwrap();
+    let status = client
+        .validate_session(ValidateSessionRequest {
+            session_id,
+            mac: Vec::new(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+}
+
+#[tokio::test]
+async fn test_failed_login() {
+    let zkp = group();
+    let mut client = start(AuthImpl::default()).await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let status = answer_challenge(&mut client, &zkp, login, "alice", "hunter3", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let status = create_challenge(&mut client, &zkp, "mallory", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+// a login for a name nobody registered is refused like a wrong password, after
+// as much work: the median answer times of the two are about the same
+#[tokio::test]
+async fn test_unknown_users_look_like_wrong_passwords() {
+    let zkp = group();
+    let mut client = start(AuthImpl {
+        conceal_unknown_users: true,
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+
+    let mut answer_times = async |user: &str| {
+        let mut times = Vec::new();
+        for _ in 0..20 {
+            let login = create_challenge(&mut client, &zkp, user, &options)
+                .await
+                .unwrap();
+            let auth_id = login.challenge.auth_id.clone();
+            let start = std::time::Instant::now();
+            let status = answer_challenge(&mut client, &zkp, login, user, "wrong", &options)
+                .await
+                .unwrap_err();
+            times.push(start.elapsed());
+            assert_eq!(status.code(), Code::PermissionDenied);
+            assert_eq!(
+                status.message(),
+                format!("AuthId: {} is not verified", auth_id)
+            );
+        }
+        times
+    };
+    let median = |mut times: Vec<Duration>| {
+        times.sort();
+        times[times.len() / 2]
+    };
+    let known = median(answer_times("alice").await);
+    let unknown = median(answer_times("mallory").await);
+    assert!(
+        known < unknown * 2 && unknown < known * 2,
+        "{:?} vs {:?}",
+        known,
+        unknown
+    );
+
+    // where every user is salted an unknown one is too, the same salt on
+    // every challenge
+    let mut client = start(AuthImpl {
+        conceal_unknown_users: true,
+        require_salted_verifier: true,
+        ..AuthImpl::default()
+    })
+    .await;
+    let mut salts = Vec::new();
+    for _ in 0..2 {
+        let login = create_challenge(&mut client, &zkp, "mallory", &options)
+            .await
+            .unwrap();
+        salts.push(login.challenge.salt);
+    }
+    assert_eq!(salts[0].len(), 16);
+    assert_eq!(salts[0], salts[1]);
+
+    // without the mode the name is told apart at once
+    let mut client = start(AuthImpl::default()).await;
+    let status = create_challenge(&mut client, &zkp, "mallory", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn test_replayed_answer_is_rejected() {
+    let zkp = group();
+    let mut client = start(AuthImpl::default()).await;
+
+    let x = SecretBigUint::from_password("hunter2");
+    client
+        .register(RegisterRequest {
+            user: "alice".to_string(),
+            y1: ZKP::exponentiate(&zkp.g, x.expose(), &zkp.p).to_bytes_be(),
+            y2: ZKP::exponentiate(&zkp.h, x.expose(), &zkp.p).to_bytes_be(),
+            salt: Vec::new(),
+            group: String::new(),
+            kdf: None,
+            device: None,
+            recovery_key: None,
+        })
+        .await
+        .unwrap();
+
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let challenge = client
+        .create_authentication_challenge(AuthenticationChallengeRequest {
+            user: "alice".to_string(),
+            r1: ZKP::exponentiate(&zkp.g, &k, &zkp.p).to_bytes_be(),
+            r2: ZKP::exponentiate(&zkp.h, &k, &zkp.p).to_bytes_be(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let s = zkp.solve(&k, &BigUint::from_bytes_be(&challenge.c), x.expose());
+    let answer = AuthenticationAnswerRequest {
+        auth_id: challenge.auth_id,
+        s: s.to_bytes_be(),
+        ..Default::default()
+    };
+
+    client.verify_authentication(answer.clone()).await.unwrap();
+    // an eavesdropper sending the same answer again gets no session
+    let status = client
+        .verify_authentication(answer.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    // a guessed auth_id is verified against the stand-in, and still not found
+    let status = client
+        .verify_authentication(AuthenticationAnswerRequest {
+            auth_id: "guessed".to_string(),
+            ..answer
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert!(status.message().starts_with("AuthId: guessed not found"));
+}
+
+// an answer refused for want of a session keeps its challenge, so the same
+// request goes through once there is room
+#[tokio::test]
+async fn test_answer_without_a_session_can_be_sent_again() {
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    let mut client = start(AuthImpl {
+        settings: Settings {
+            session_limit: Some(SessionLimit {
+                max_per_user: 1,
+                policy: LimitPolicy::RejectNew,
+            }),
+            ..Settings::default()
+        }
+        .into(),
+        ..AuthImpl::default()
+    })
+    .await;
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let (first, _) = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+
+    let x = SecretBigUint::from_password("hunter2");
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let challenge = client
+        .create_authentication_challenge(AuthenticationChallengeRequest {
+            user: "alice".to_string(),
+            r1: ZKP::exponentiate(&zkp.g, &k, &zkp.p).to_bytes_be(),
+            r2: ZKP::exponentiate(&zkp.h, &k, &zkp.p).to_bytes_be(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let s = zkp.solve(&k, &BigUint::from_bytes_be(&challenge.c), x.expose());
+    let answer = AuthenticationAnswerRequest {
+        auth_id: challenge.auth_id,
+        s: s.to_bytes_be(),
+        ..Default::default()
+    };
+    let status = client
+        .verify_authentication(answer.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+
+    client
+        .logout(LogoutRequest { session_id: first })
+        .await
+        .unwrap();
+    client.verify_authentication(answer.clone()).await.unwrap();
+    // and once it has a session, it is used up
+    let status = client.verify_authentication(answer).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+// each wrong answer counts against the challenge, so guesses at s cost
+// challenges
+#[tokio::test]
+async fn test_answers_per_challenge_are_limited() {
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    for max_answers in [None, Some(3)] {
+        let mut client = start(AuthImpl {
+            max_answers_per_challenge: max_answers,
+            ..AuthImpl::default()
+        })
+        .await;
+        register(&mut client, &zkp, "alice", "hunter2", false)
+            .await
+            .unwrap();
+
+        let login = create_challenge(&mut client, &zkp, "alice", &options)
+            .await
+            .unwrap();
+        let auth_id = login.challenge.auth_id.clone();
+        let status = answer_challenge(&mut client, &zkp, login, "alice", "wrong", &options)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+        let guess = async |client: &mut AuthClient<Channel>| {
+            client
+                .verify_authentication(AuthenticationAnswerRequest {
+                    auth_id: auth_id.clone(),
+                    s: vec![1],
+                    ..Default::default()
+                })
+                .await
+                .unwrap_err()
+        };
+        for _ in 1..max_answers.unwrap_or(1) {
+            let status = guess(&mut client).await;
+            assert_eq!(
+                status.message(),
+                format!("AuthId: {} is not verified", auth_id)
+            );
+        }
+        let status = guess(&mut client).await;
+        assert_eq!(status.code(), Code::PermissionDenied);
+        assert_eq!(
+            status.message(),
+            "Challenge has taken all the answers it allows"
+        );
+        // and is gone
+        assert_eq!(guess(&mut client).await.code(), Code::NotFound);
+    }
+}
+
+// two replicas counting in one file lock an account together, and a new
+// instance still finds it locked
+#[tokio::test]
+async fn test_failed_logins_lock_the_account_across_replicas() {
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    let path = std::env::temp_dir().join(format!("lockouts-it-{}.json", std::process::id()));
+    let clock = ManualClock::new(std::time::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    let replica = || AuthImpl {
+        lockout: Some(LockoutPolicy {
+            max_failures: 3,
+            duration: Duration::from_secs(900),
+        }),
+        lockouts: Box::new(FileLockouts::new(&path)),
+        clock: Box::new(clock.clone()),
+        ..AuthImpl::default()
+    };
+    let mut first = start(replica()).await;
+    let mut second = start(replica()).await;
+    for client in [&mut first, &mut second] {
+        register(client, &zkp, "alice", "hunter2", false)
+            .await
+            .unwrap();
+    }
+    let login = async |client: &mut AuthClient<Channel>, password: &str| {
+        let login = create_challenge(client, &zkp, "alice", &options).await?;
+        answer_challenge(client, &zkp, login, "alice", password, &options).await
+    };
+
+    for client in [&mut first, &mut second] {
+        let status = login(client, "wrong").await.unwrap_err();
+        assert!(status.message().ends_with("is not verified"));
+    }
+    login(&mut first, "wrong").await.unwrap_err();
+    let mut restarted = start(replica()).await;
+    register(&mut restarted, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    for client in [&mut first, &mut second, &mut restarted] {
+        let status = login(client, "hunter2").await.unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+        assert_eq!(
+            status.message(),
+            "Account is locked after too many failed logins for 900 more seconds"
+        );
+    }
+
+    // the lock runs out, and the login clears the count for every replica
+    clock.advance(Duration::from_secs(900));
+    login(&mut second, "hunter2").await.unwrap();
+    login(&mut first, "wrong").await.unwrap_err();
+    login(&mut first, "wrong").await.unwrap_err();
+    login(&mut restarted, "hunter2").await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(path.with_extension("lock")).unwrap();
+}
+
+// a name nobody registered locks like a real account, but its failures are
+// counted in memory, not in the lockout file
+#[tokio::test]
+async fn test_made_up_users_lock_without_a_lockout_record() {
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    let path = std::env::temp_dir().join(format!("lockouts-made-up-{}.json", std::process::id()));
+    let mut client = start(AuthImpl {
+        conceal_unknown_users: true,
+        lockout: Some(LockoutPolicy {
+            max_failures: 2,
+            duration: Duration::from_secs(900),
+        }),
+        lockouts: Box::new(FileLockouts::new(&path)),
+        ..AuthImpl::default()
+    })
+    .await;
+    for _ in 0..2 {
+        let login = create_challenge(&mut client, &zkp, "mallory", &options)
+            .await
+            .unwrap();
+        answer_challenge(&mut client, &zkp, login, "mallory", "wrong", &options)
+            .await
+            .unwrap_err();
+    }
+    let status = create_challenge(&mut client, &zkp, "mallory", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert!(status.message().starts_with("Account is locked"));
+    assert!(!path.exists());
+}
+
+// a challenge left unanswered past its TTL is gone
+#[tokio::test]
+async fn test_unanswered_challenge_expires() {
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    let clock = ManualClock::new(std::time::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    let mut client = start(AuthImpl {
+        clock: Box::new(clock.clone()),
+        ..AuthImpl::default()
+    })
+    .await;
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    clock.advance(CHALLENGE_TTL);
+    let status = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    clock.advance(CHALLENGE_TTL - Duration::from_secs(1));
+    answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+}
+
+// the client library tells the failures apart without the application
+// reading messages
+#[tokio::test]
+async fn test_login_failures_are_classified() {
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    // the seconds left of the lock don't depend on when the test runs
+    let clock = ManualClock::new(std::time::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    let mut client = start(AuthImpl {
+        lockout: Some(LockoutPolicy {
+            max_failures: 2,
+            duration: Duration::from_secs(60),
+        }),
+        clock: Box::new(clock),
+        ..AuthImpl::default()
+    })
+    .await;
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+
+    let error = login(&mut client, &zkp, "alice", "wrong", &options)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, AuthError::WrongCredentials(_)));
+    let error = login(&mut client, &zkp, "mallory", "hunter2", &options)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, AuthError::WrongCredentials(_)));
+
+    // an answer to a challenge already answered
+    let pending = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let request = AuthenticationAnswerRequest {
+        auth_id: pending.challenge.auth_id.clone(),
+        ..Default::default()
+    };
+    answer_challenge(&mut client, &zkp, pending, "alice", "wrong", &options)
+        .await
+        .unwrap_err();
+    let status = client.verify_authentication(request).await.unwrap_err();
+    assert!(matches!(
+        AuthError::from(status),
+        AuthError::ChallengeExpired(_)
+    ));
+
+    let error = login(&mut client, &zkp, "alice", "hunter2", &options)
+        .await
+        .unwrap_err();
+    match error {
+        AuthError::LockedOut { retry_after, .. } => {
+            assert_eq!(retry_after, Some(Duration::from_secs(60)))
+        }
+        error => panic!("not locked out: {:?}", error),
+    }
+
+    let strong = groups::by_name(groups::RFC5114_2048_256).unwrap();
+    let status = check_parameters(&mut client, &strong, Some(&[0; 32]))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        AuthError::from(status),
+        AuthError::ParameterMismatch(_)
+    ));
+    let status = tonic::Status::unavailable("connection refused");
+    assert!(matches!(
+        AuthError::from(status),
+        AuthError::ServerUnavailable(_)
+    ));
+}
+
+// a tenant past its quota is refused, whichever quota it is
+#[tokio::test]
+async fn test_realm_quotas_are_enforced() {
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    let clock = ManualClock::new(std::time::UNIX_EPOCH + Duration::from_secs(1_700_000_030));
+    let quota = Quota {
+        max_users: Some(2),
+        max_sessions: Some(1),
+        max_logins_per_minute: Some(2),
+    };
+    let mut client = start(AuthImpl {
+        realm: "acme".to_string(),
+        settings: Settings {
+            quotas: QuotaPolicy {
+                realms: [("acme".to_string(), quota)].into(),
+            },
+            ..Settings::default()
+        }
+        .into(),
+        clock: Box::new(clock.clone()),
+        ..AuthImpl::default()
+    })
+    .await;
+    let over_quota = |status: tonic::Status, quota: &str| {
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(
+            status.message(),
+            format!("Realm is over its quota of {}", quota)
+        );
+    };
+
+    for user in ["alice", "bob"] {
+        register(&mut client, &zkp, user, "hunter2", false)
+            .await
+            .unwrap();
+    }
+    let status = register(&mut client, &zkp, "carol", "hunter2", false)
+        .await
+        .unwrap_err();
+    over_quota(status, "users");
+    // a new verifier for a user already counted goes through
+    register(&mut client, &zkp, "alice", "hunter3", false)
+        .await
+        .unwrap();
+
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    answer_challenge(&mut client, &zkp, login, "alice", "hunter3", &options)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "bob", &options)
+        .await
+        .unwrap();
+    let status = answer_challenge(&mut client, &zkp, login, "bob", "hunter2", &options)
+        .await
+        .unwrap_err();
+    over_quota(status, "sessions");
+
+    // both logins of the minute are used, the next waits for the one after
+    let status = create_challenge(&mut client, &zkp, "bob", &options)
+        .await
+        .unwrap_err();
+    over_quota(status, "logins_per_minute");
+    clock.advance(Duration::from_secs(20));
+    create_challenge(&mut client, &zkp, "bob", &options)
+        .await
+        .unwrap();
+}
+
+// tests can inject the challenge, a deployment can bind it to the attempt
+#[tokio::test]
+async fn test_challenge_sources() {
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    let sources: [Box<dyn ChallengeSource>; 2] = [
+        Box::new(Fixed(BigUint::from(12_345u32))),
+        Box::new(HashBound::new([7; 32])),
+    ];
+    for (i, source) in sources.into_iter().enumerate() {
+        let mut client = start(AuthImpl {
+            challenge_source: source,
+            ..AuthImpl::default()
+        })
+        .await;
+        register(&mut client, &zkp, "alice", "hunter2", false)
+            .await
+            .unwrap();
+        let login = create_challenge(&mut client, &zkp, "alice", &options)
+            .await
+            .unwrap();
+        if i == 0 {
+            assert_eq!(login.challenge.c, BigUint::from(12_345u32).to_bytes_be());
+        }
+        answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+            .await
+            .unwrap();
+    }
+}
+
+// a login takes its challenge and auth_id off the pool
+#[tokio::test]
+async fn test_challenges_come_from_the_pool() {
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    let pool = Arc::new(ChallengePool::new(
+        2,
+        IdLength::default(),
+        [(groups::DEFAULT, &zkp)],
+    ));
+    pool.fill();
+    let mut client = start(AuthImpl {
+        challenge_source: Box::new(Pooled(pool.clone())),
+        challenge_pool: Some(pool.clone()),
+        ..AuthImpl::default()
+    })
+    .await;
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    assert_eq!(pool.challenges(), [(groups::DEFAULT, 1)]);
+    assert_eq!(pool.auth_ids(), 1);
+    answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+}
+
+// services holding only the session ID are turned away, the owner (session
+// key MAC) and admins (bearer token) get the session's metadata
+#[tokio::test]
+async fn test_session_info_is_for_the_owner_or_an_admin() {
+    let zkp = group();
+    let mut client = start(AuthImpl {
+        realm: "staff".to_string(),
+        admin_tokens: vec!["admin-token".to_string()],
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let (session_id, key) =
+        answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+            .await
+            .unwrap();
+    let bearer = GetSessionInfoRequest {
+        session_id: session_id.clone(),
+        ..Default::default()
+    };
+
+    let status = client.get_session_info(bearer.clone()).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let info = client
+        .get_session_info(GetSessionInfoRequest {
+            mac: key.unwrap().mac(session_id.as_bytes()),
+            ..bearer.clone()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(info.user, "alice");
+    assert_eq!(info.realm, "staff");
+    assert!(info.created_at < info.expires_at);
+    let metadata = info.client.unwrap();
+    assert!(metadata.remote_addr.starts_with("127.0.0.1:"));
+    assert!(metadata.user_agent.contains("tonic"));
+
+    for (token, allowed) in [("admin-token", true), ("guess", false)] {
+        let mut request = tonic::Request::new(bearer.clone());
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        let result = client.get_session_info(request).await;
+        assert_eq!(result.is_ok(), allowed, "{}", token);
+    }
+}
+
+fn admin<T>(message: T) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    request
+        .metadata_mut()
+        .insert("authorization", "Bearer admin-token".parse().unwrap());
+    request
+}
+
+// a signed bundle moves users and live sessions to another instance
+#[tokio::test]
+async fn test_state_moves_to_another_instance() {
+    let zkp = group();
+    let blue_key = KeyPair::generate(&zkp);
+    let blue_public = blue_key.public.clone();
+    let mut blue = start(AuthImpl {
+        server_key: Some(blue_key),
+        admin_tokens: vec!["admin-token".to_string()],
+        ..AuthImpl::default()
+    })
+    .await;
+    let mut green = start(AuthImpl {
+        server_key: Some(KeyPair::generate(&zkp)),
+        admin_tokens: vec!["admin-token".to_string()],
+        trusted_bundle_keys: vec![blue_public],
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+
+    register(&mut blue, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut blue, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let (session_id, key) = answer_challenge(&mut blue, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+
+    let status = blue.export_state(ExportStateRequest {}).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let bundle = blue
+        .export_state(admin(ExportStateRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .bundle;
+
+    let imported = green
+        .import_state(admin(ImportStateRequest {
+            bundle: bundle.clone(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!((imported.users, imported.sessions), (1, 1));
+
+    // the session and its key carry over, and so does the registration
+    let session = green
+        .validate_session(ValidateSessionRequest {
+            session_id: session_id.clone(),
+            mac: key.unwrap().mac(session_id.as_bytes()),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(session.user, "alice");
+    let login = create_challenge(&mut green, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    answer_challenge(&mut green, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+
+    // blue doesn't trust green, and a changed bundle doesn't verify
+    let green_bundle = green
+        .export_state(admin(ExportStateRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .bundle;
+    let status = blue
+        .import_state(admin(ImportStateRequest {
+            bundle: green_bundle,
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let status = green
+        .import_state(admin(ImportStateRequest {
+            bundle: bundle.replace("alice", "mallory"),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+}
+
+// every response names the RPC's request ID, the caller's if it sent one, and
+// v2 errors carry it in their details
+#[tokio::test]
+async fn test_request_ids() {
+    let zkp = group();
+    let addr = serve(AuthImpl::default());
+    let mut client = AuthClient::connect(addr.clone()).await.unwrap();
+
+    let response = register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let generated = request_id::of(response.metadata()).unwrap();
+    assert_eq!(generated.len(), 16);
+
+    let mut request = tonic::Request::new(AuthenticationChallengeRequest {
+        user: "mallory".to_string(),
+        ..Default::default()
+    });
+    request
+        .metadata_mut()
+        .insert(request_id::HEADER, "incident-42".parse().unwrap());
+    let status = client
+        .create_authentication_challenge(request)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(request_id::of(status.metadata()), Some("incident-42"));
+
+    // one that could forge log lines is replaced
+    let mut request = tonic::Request::new(v2::AuthenticationChallengeRequest {
+        user: "mallory".to_string(),
+        ..Default::default()
+    });
+    request
+        .metadata_mut()
+        .insert(request_id::HEADER, "a b".parse().unwrap());
+    let mut v2_client = v2::AuthClient::connect(addr).await.unwrap();
+    let status = v2_client
+        .create_authentication_challenge(request)
+        .await
+        .unwrap_err();
+    let request_id = request_id::of(status.metadata()).unwrap();
+    assert_ne!(request_id, "a b");
+    let detail = v2::ErrorDetail::decode(status.details()).unwrap();
+    assert_eq!(detail.request_id, request_id);
+}
+
+// peers outside the access lists are disconnected before any request is read
+#[tokio::test]
+async fn test_access_lists() {
+    let zkp = group();
+    let serve_with = |access: AccessList| {
+        let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = incoming.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(AuthServer::new(AuthImpl::default()))
+                .serve_with_incoming(access.filter_incoming(incoming)),
+        );
+        format!("http://{}", addr)
+    };
+
+    let denied = serve_with(AccessList {
+        allow: vec!["127.0.0.0/8".parse().unwrap()],
+        deny: vec!["127.0.0.1".parse().unwrap()],
+    });
+    let registered = match AuthClient::connect(denied).await {
+        Ok(mut client) => register(&mut client, &zkp, "alice", "hunter2", false)
+            .await
+            .is_ok(),
+        // the connection is closed as soon as it's accepted
+        Err(_) => false,
+    };
+    assert!(!registered);
+
+    let allowed = serve_with(AccessList {
+        allow: vec!["127.0.0.0/8".parse().unwrap()],
+        deny: vec!["10.0.0.0/8".parse().unwrap()],
+    });
+    let mut client = AuthClient::connect(allowed).await.unwrap();
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+}
+
+// a login to a decoy account succeeds like any other and is reported to admins
+#[tokio::test]
+async fn test_decoy_login_is_reported() {
+    let zkp = group();
+    let mut client = start(AuthImpl {
+        admin_tokens: vec!["admin-token".to_string()],
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+
+    let mark = MarkDecoyRequest {
+        user: "backup-admin".to_string(),
+        decoy: true,
+    };
+    let status = client.mark_decoy(mark.clone()).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    client.mark_decoy(admin(mark)).await.unwrap();
+
+    for user in ["alice", "backup-admin"] {
+        register(&mut client, &zkp, user, "hunter2", false)
+            .await
+            .unwrap();
+        let login = create_challenge(&mut client, &zkp, user, &options)
+            .await
+            .unwrap();
+        answer_challenge(&mut client, &zkp, login, user, "hunter2", &options)
+            .await
+            .unwrap();
+    }
+
+    let status = client
+        .list_decoy_hits(ListDecoyHitsRequest {})
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let hits = client
+        .list_decoy_hits(admin(ListDecoyHitsRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .hits;
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].user, "backup-admin");
+    assert!(!hits[0].request_id.is_empty());
+    assert!(!hits[0].client.as_ref().unwrap().remote_addr.is_empty());
+
+    // the session of the decoy login is an ordinary one
+    let session = client
+        .validate_session(ValidateSessionRequest {
+            session_id: hits[0].session_id.clone(),
+            mac: Vec::new(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(session.user, "backup-admin");
+}
+
+// a non-interactive answer is a proof over H(auth_id, channel binding): it
+// can't be spliced into another attempt or sent for another connection
+#[tokio::test]
+async fn test_non_interactive_answer_is_bound_to_its_attempt() {
+    let zkp = group();
+    let mut client = start(AuthImpl {
+        channel_binding: Some(b"certificate hash".to_vec()),
+        ..AuthImpl::default()
+    })
+    .await;
+
+    let x = SecretBigUint::from_password("hunter2");
+    client
+        .register(RegisterRequest {
+            user: "alice".to_string(),
+            y1: ZKP::exponentiate(&zkp.g, x.expose(), &zkp.p).to_bytes_be(),
+            y2: ZKP::exponentiate(&zkp.h, x.expose(), &zkp.p).to_bytes_be(),
+            salt: Vec::new(),
+            group: String::new(),
+            kdf: None,
+            device: None,
+            recovery_key: None,
+        })
+        .await
+        .unwrap();
+    let mut auth_ids = Vec::new();
+    for _ in 0..3 {
+        let challenge = client
+            .create_authentication_challenge(AuthenticationChallengeRequest {
+                user: "alice".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        auth_ids.push(challenge.auth_id);
+    }
+
+    let context = ZKP::answer_context(&auth_ids[0], b"certificate hash");
+    let proof = zkp.prove_non_interactive(x.expose(), &context);
+    let answer = AuthenticationAnswerRequest {
+        auth_id: auth_ids[0].clone(),
+        s: proof.s.to_bytes_be(),
+        c: proof.c.to_bytes_be(),
+        channel_binding: b"certificate hash".to_vec(),
+        ..Default::default()
+    };
+
+    // spliced into the second attempt
+    let spliced = AuthenticationAnswerRequest {
+        auth_id: auth_ids[1].clone(),
+        ..answer.clone()
+    };
+    let status = client.verify_authentication(spliced).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    // claimed for the channel of another connection
+    let relayed = AuthenticationAnswerRequest {
+        channel_binding: b"other certificate hash".to_vec(),
+        ..answer.clone()
+    };
+    let status = client.verify_authentication(relayed).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    // which used up the attempt
+    let status = client.verify_authentication(answer).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert_eq!(
+        status.message(),
+        "Challenge has taken all the answers it allows"
+    );
+
+    let context = ZKP::answer_context(&auth_ids[2], b"certificate hash");
+    let proof = zkp.prove_non_interactive(x.expose(), &context);
+    client
+        .verify_authentication(AuthenticationAnswerRequest {
+            auth_id: auth_ids[2].clone(),
+            s: proof.s.to_bytes_be(),
+            c: proof.c.to_bytes_be(),
+            channel_binding: b"certificate hash".to_vec(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // the login flow, with the key agreed from the proof's r1
+    let binding = ChannelBinding::new(Mutex::new(Some(b"certificate hash".to_vec())));
+    let options = LoginOptions {
+        non_interactive: true,
+        ..options(&binding)
+    };
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let (_, key) = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+    assert!(key.is_some());
+}
+
+// a proof made for one deployment's application id doesn't verify on another
+#[tokio::test]
+async fn test_non_interactive_answer_is_bound_to_its_application() {
+    let zkp = group();
+    let mut client = start(AuthImpl {
+        application: b"payments".to_vec(),
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    for (application, verified) in [(&b"payments"[..], true), (b"", false), (b"staging", false)] {
+        let options = LoginOptions {
+            non_interactive: true,
+            application,
+            ..options(&binding)
+        };
+        let login = create_challenge(&mut client, &zkp, "alice", &options)
+            .await
+            .unwrap();
+        let answer = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options).await;
+        match verified {
+            true => assert!(answer.is_ok()),
+            false => assert_eq!(answer.unwrap_err().code(), Code::PermissionDenied),
+        }
+    }
+}
+
+// a v2 login carries the group and flavor and gets the session back, and the
+// user it registered logs in over v1 as well
+#[tokio::test]
+async fn test_v2_login_alongside_v1() {
+    let zkp = group();
+    let addr = serve(AuthImpl::default());
+    let mut client = v2::AuthClient::connect(addr.clone()).await.unwrap();
+
+    let x = SecretBigUint::from_password("hunter2");
+    let registered = client
+        .register(v2::RegisterRequest {
+            user: "alice".to_string(),
+            group_id: groups::DEFAULT.to_string(),
+            y1: ZKP::exponentiate(&zkp.g, x.expose(), &zkp.p).to_bytes_be(),
+            y2: ZKP::exponentiate(&zkp.h, x.expose(), &zkp.p).to_bytes_be(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(registered.group_id, groups::DEFAULT);
+
+    // a group the server doesn't serve
+    let status = client
+        .create_authentication_challenge(v2::AuthenticationChallengeRequest {
+            user: "alice".to_string(),
+            group_id: "toy-23-11".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let detail = v2::ErrorDetail::decode(status.details()).unwrap();
+    assert_eq!(detail.reason(), v2::ErrorReason::UnsupportedGroup);
+
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let challenge = client
+        .create_authentication_challenge(v2::AuthenticationChallengeRequest {
+            user: "alice".to_string(),
+            r1: ZKP::exponentiate(&zkp.g, &k, &zkp.p).to_bytes_be(),
+            r2: ZKP::exponentiate(&zkp.h, &k, &zkp.p).to_bytes_be(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(challenge.flavor(), v2::Flavor::Interactive);
+    assert_eq!(challenge.group_id, groups::DEFAULT);
+
+    let s = zkp.solve(&k, &BigUint::from_bytes_be(&challenge.c), x.expose());
+    let answer = v2::AuthenticationAnswerRequest {
+        auth_id: challenge.auth_id,
+        s: s.to_bytes_be(),
+        ..Default::default()
+    };
+    let session = client
+        .verify_authentication(answer.clone())
+        .await
+        .unwrap()
+        .into_inner()
+        .session
+        .unwrap();
+    assert_eq!(session.user, "alice");
+    assert!(session.has_key);
+    assert!(session.expires_at > 0);
+
+    let status = client.verify_authentication(answer).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let detail = v2::ErrorDetail::decode(status.details()).unwrap();
+    assert_eq!(detail.reason(), v2::ErrorReason::AuthIdNotFound);
+
+    let validated = client
+        .validate_session(v2::ValidateSessionRequest {
+            session_id: session.session_id.clone(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .session
+        .unwrap();
+    assert_eq!(validated, session);
+
+    // same users over v1
+    let mut v1_client = AuthClient::connect(addr).await.unwrap();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    let login = create_challenge(&mut v1_client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    answer_challenge(&mut v1_client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+}
+
+// each challenge is kept under its own auth_id: a second login started before
+// the first is answered doesn't overwrite the first one's nonce and challenge
+#[tokio::test]
+async fn test_interleaved_logins_of_one_user() {
+    let zkp = group();
+    let mut client = start(AuthImpl::default()).await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let first = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let second = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    for login in [first, second] {
+        answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+            .await
+            .unwrap();
+    }
+}
+
+// answers verified on the pool log in as they do inline, and an answer that
+// finds every worker busy and no room in the queue is turned away at once
+#[tokio::test]
+async fn test_logins_on_a_saturated_pool() {
+    let zkp = group();
+    let pool = Arc::new(WorkerPool::new(1, 1));
+    let mut client = start(AuthImpl {
+        verifier_pool: Some(pool.clone()),
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let non_interactive = LoginOptions {
+        non_interactive: true,
+        ..options(&binding)
+    };
+    let options = options(&binding);
+
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    for options in [&options, &non_interactive] {
+        let login = create_challenge(&mut client, &zkp, "alice", options)
+            .await
+            .unwrap();
+        answer_challenge(&mut client, &zkp, login, "alice", "hunter2", options)
+            .await
+            .unwrap();
+    }
+
+    // the only worker is held until released, and a job waits behind it
+    let (started, running) = std::sync::mpsc::channel();
+    let (release, released) = std::sync::mpsc::channel::<()>();
+    let busy = tokio::spawn({
+        let pool = pool.clone();
+        async move {
+            pool.run(move || {
+                started.send(()).unwrap();
+                released.recv().unwrap();
+            })
+            .await
+        }
+    });
+    tokio::task::spawn_blocking(move || running.recv().unwrap())
+        .await
+        .unwrap();
+    let queued = tokio::time::timeout(Duration::from_millis(50), pool.run(|| ())).await;
+    assert!(queued.is_err());
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let status = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+
+    release.send(()).unwrap();
+    busy.await.unwrap().unwrap();
+    // turned away until the worker has taken the queued job
+    loop {
+        let login = create_challenge(&mut client, &zkp, "alice", &options)
+            .await
+            .unwrap();
+        match answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options).await {
+            Err(status) if status.code() == Code::ResourceExhausted => {
+                tokio::task::yield_now().await
+            }
+            answer => break assert!(answer.is_ok()),
+        }
+    }
+}
+
+// registrations are turned away first and new logins next as verification
+// falls behind, while logins under way and session checks carry on
+#[tokio::test]
+async fn test_admission_sheds_registrations_then_logins() {
+    let zkp = group();
+    let pool = Arc::new(WorkerPool::new(1, 64));
+    let mut client = start(AuthImpl {
+        verifier_pool: Some(pool.clone()),
+        admission: Some(Admission::new(Duration::from_millis(100))),
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let (session_id, _) = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+
+    // busy
+    pool.latencies()
+        .record(Instant::now(), Duration::from_millis(60));
+    let status = register(&mut client, &zkp, "bob", "hunter2", false)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    assert!(status
+        .message()
+        .starts_with("Server is behind on verifications"));
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+
+    // overloaded
+    pool.latencies()
+        .record(Instant::now(), Duration::from_millis(500));
+    let status = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+    client
+        .validate_session(ValidateSessionRequest {
+            session_id,
+            mac: Vec::new(),
+        })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_login_with_every_option() {
+    let zkp = group();
+    let server_key = KeyPair::generate(&zkp);
+    let public = server_key.public.clone();
+    let mut client = start(AuthImpl {
+        server_key: Some(server_key),
+        require_salted_verifier: true,
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = LoginOptions {
+        server_key: Some(&public),
+        channel_binding: &binding,
+        salted: true,
+        kdf: Default::default(),
+        blinded: true,
+        commit_nonce: true,
+        non_interactive: false,
+        application: b"",
+        second_factor: "",
+        device: None,
+        receipt: None,
+    };
+
+    // the server only takes salted verifiers
+    let status = register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    register(&mut client, &zkp, "alice", "hunter2", true)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let (session_id, key) =
+        answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+            .await
+            .unwrap();
+    assert!(key.is_some());
+    let session = client
+        .validate_session(ValidateSessionRequest {
+            session_id,
+            mac: Vec::new(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(session.user, "alice");
+
+    // a different pinned key: the server can't prove it holds it
+    let impostor = KeyPair::generate(&zkp).public;
+    let options = LoginOptions {
+        server_key: Some(&impostor),
+        blinded: false,
+        ..options
+    };
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let status = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+}
+
+#[tokio::test]
+async fn test_verifier_moves_to_a_stronger_group() {
+    let legacy = group();
+    let strong = groups::by_name(groups::RFC5114_2048_256).unwrap();
+    let mut client = start(AuthImpl::default()).await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+
+    register(&mut client, &legacy, "alice", "hunter2", true)
+        .await
+        .unwrap();
+    register(&mut client, &legacy, "bob", "hunter2", false)
+        .await
+        .unwrap();
+    for user in ["alice", "bob"] {
+        let (session_id, key) =
+            migrate_group(&mut client, &legacy, &strong, user, "hunter2", &options)
+                .await
+                .unwrap();
+        // the session that authorized the move stays valid
+        client
+            .validate_session(ValidateSessionRequest {
+                session_id: session_id.clone(),
+                mac: key.mac(session_id.as_bytes()),
+            })
+            .await
+            .unwrap();
+
+        // the same password now logs in under the new group only
+        let login = create_challenge(&mut client, &strong, user, &options)
+            .await
+            .unwrap();
+        answer_challenge(&mut client, &strong, login, user, "hunter2", &options)
+            .await
+            .unwrap();
+        let status = create_challenge(&mut client, &legacy, user, &options)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    // a proof for another password doesn't verify: log in as carol, then
+    // send a verifier of another password with its own proof
+    register(&mut client, &legacy, "carol", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &legacy, "carol", &options)
+        .await
+        .unwrap();
+    let (session_id, key) =
+        answer_challenge(&mut client, &legacy, login, "carol", "hunter2", &options)
+            .await
+            .unwrap();
+    let key = key.unwrap();
+    let x = SecretBigUint::from_password("stolen");
+    let proof = cross_group::prove(&legacy, &strong, x.expose(), b"carol").unwrap();
+    let request = MigrateGroupRequest {
+        session_id: session_id.clone(),
+        mac: key.mac(session_id.as_bytes()),
+        group: groups::RFC5114_2048_256.to_string(),
+        y1: ZKP::exponentiate(&strong.g, x.expose(), &strong.p).to_bytes_be(),
+        y2: ZKP::exponentiate(&strong.h, x.expose(), &strong.p).to_bytes_be(),
+        c: proof.c.to_bytes_be(),
+        s: proof.s.to_bytes_be(),
+    };
+    let status = client.migrate_group(request.clone()).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    // nor without the session key
+    let status = client
+        .migrate_group(MigrateGroupRequest {
+            mac: vec![0; 32],
+            ..request.clone()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    // and there is no moving back to a weaker group
+    let status = client
+        .migrate_group(MigrateGroupRequest {
+            group: groups::RFC5114_1024_160.to_string(),
+            ..request
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+}
+
+#[tokio::test]
+async fn test_tickets_are_spent_once_without_a_session() {
+    let zkp = group();
+    let server_key = KeyPair::generate(&zkp);
+    let public = server_key.public.clone();
+    let addr = serve(AuthImpl {
+        server_key: Some(server_key),
+        tickets_per_session: Some(2),
+        ..AuthImpl::default()
+    });
+    let mut client = AuthClient::connect(addr.clone()).await.unwrap();
+    let binding = ChannelBinding::default();
+    let options = LoginOptions {
+        server_key: Some(&public),
+        ..options(&binding)
+    };
+
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let (session_id, key) =
+        answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+            .await
+            .unwrap();
+    let key = key.unwrap();
+    let (tickets, left) = issue_tickets(&mut client, &session_id, &key, 2, &options)
+        .await
+        .unwrap();
+    assert_eq!((tickets.len(), left), (2, 0));
+    let status = issue_tickets(&mut client, &session_id, &key, 1, &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+
+    // spent from another connection, after the session is gone
+    client.logout(LogoutRequest { session_id }).await.unwrap();
+    let mut anonymous = AuthClient::connect(addr).await.unwrap();
+    redeem_ticket(&mut anonymous, &tickets[0]).await.unwrap();
+    let status = redeem_ticket(&mut anonymous, &tickets[0])
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::AlreadyExists);
+    let forged = Ticket {
+        nonce: [7; 32],
+        ..tickets[1].clone()
+    };
+    let status = redeem_ticket(&mut anonymous, &forged).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    redeem_ticket(&mut anonymous, &tickets[1]).await.unwrap();
+}
+
+// the recovery key sets a new password once, ending the sessions of the old
+// one, and every attempt is audited
+#[tokio::test]
+async fn test_account_is_recovered_with_its_recovery_key() {
+    let zkp = group();
+    let mut client = start(AuthImpl {
+        admin_tokens: vec!["admin-token".to_string()],
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    let recovery_key = KeyPair::generate(&zkp);
+    register_with_recovery(
+        &mut client,
+        &zkp,
+        "alice",
+        "hunter2",
+        None,
+        None,
+        Some(&recovery_key.public),
+    )
+    .await
+    .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let (session_id, key) =
+        answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+            .await
+            .unwrap();
+
+    // a key that isn't the registered one proves nothing
+    let stranger = KeyPair::generate(&zkp);
+    let status = recover_account(&mut client, &zkp, "alice", "stolen", None, &stranger, b"")
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let kdf = KdfParams::default();
+    let response = recover_account(
+        &mut client,
+        &zkp,
+        "alice",
+        "correct horse",
+        Some(&kdf),
+        &recovery_key,
+        b"",
+    )
+    .await
+    .unwrap()
+    .into_inner();
+    assert_eq!(response.sessions_ended, 1);
+    let status = client
+        .validate_session(ValidateSessionRequest {
+            mac: key.unwrap().mac(session_id.as_bytes()),
+            session_id,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    assert!(
+        answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+            .await
+            .is_err()
+    );
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    answer_challenge(&mut client, &zkp, login, "alice", "correct horse", &options)
+        .await
+        .unwrap();
+
+    // not again within the cooldown, nor for a user without a recovery key
+    let status = recover_account(
+        &mut client,
+        &zkp,
+        "alice",
+        "again",
+        None,
+        &recovery_key,
+        b"",
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    register(&mut client, &zkp, "bob", "hunter2", false)
+        .await
+        .unwrap();
+    let status = recover_account(&mut client, &zkp, "bob", "mine", None, &recovery_key, b"")
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    let status = client
+        .list_audit_events(ListAuditEventsRequest {})
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let events = client
+        .list_audit_events(admin(ListAuditEventsRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .events;
+    let kinds: Vec<(&str, &str)> = events
+        .iter()
+        .map(|event| (event.kind.as_str(), event.user.as_str()))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            ("recovery-key-registered", "alice"),
+            ("recovery-rejected", "alice"),
+            ("account-recovered", "alice"),
+            ("recovery-refused", "alice"),
+        ]
+    );
+}
+
+// a deleted user can't log in and can be restored until the retention has
+// passed, then nothing of them is left
+#[tokio::test]
+async fn test_deleted_users_are_erased_after_the_retention() {
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    let clock = ManualClock::new(std::time::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    let auth = Arc::new(AuthImpl {
+        admin_tokens: vec!["admin-token".to_string()],
+        clock: Box::new(clock.clone()),
+        deletion_retention: Retention(Duration::from_secs(3600)),
+        ..AuthImpl::default()
+    });
+    let mut client = AuthClient::connect(serve_shared(auth.clone()))
+        .await
+        .unwrap();
+    for user in ["alice", "bob"] {
+        register(&mut client, &zkp, user, "hunter2", false)
+            .await
+            .unwrap();
+    }
+    let login = async |client: &mut AuthClient<Channel>, user: &str| {
+        let challenge = create_challenge(client, &zkp, user, &options).await?;
+        answer_challenge(client, &zkp, challenge, user, "hunter2", &options).await
+    };
+    let delete = |user: &str, restore: bool| {
+        admin(DeleteUserRequest {
+            user: user.to_string(),
+            restore,
+        })
+    };
+    let (session_id, _) = login(&mut client, "alice").await.unwrap();
+
+    let status = client
+        .delete_user(DeleteUserRequest {
+            user: "alice".to_string(),
+            restore: false,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let deleted = client
+        .delete_user(delete("alice", false))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(
+        (deleted.sessions_ended, deleted.erase_at),
+        (1, 1_700_003_600)
+    );
+    let status = client
+        .validate_session(ValidateSessionRequest {
+            session_id,
+            mac: Vec::new(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    let status = login(&mut client, "alice").await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    // nor can the name be taken over before it is erased
+    let status = register(&mut client, &zkp, "alice", "mine", false)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    client.delete_user(delete("alice", true)).await.unwrap();
+    login(&mut client, "alice").await.unwrap();
+    let status = client.delete_user(delete("alice", true)).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    client.delete_user(delete("alice", false)).await.unwrap();
+    clock.advance(Duration::from_secs(3599));
+    assert_eq!(auth.erase_deleted_users(), 0);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(auth.erase_deleted_users(), 1);
+    let status = client.delete_user(delete("alice", true)).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let events = client
+        .list_audit_events(admin(ListAuditEventsRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .events;
+    assert!(events.is_empty());
+    login(&mut client, "bob").await.unwrap();
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_login_under_a_named_group() {
+    let strong = groups::by_name(groups::RFC5114_2048_256).unwrap();
+    // the server key stays in the default group
+    let server_key = KeyPair::generate(&group());
+    let public = server_key.public.clone();
+    let mut client = start(AuthImpl {
+        server_key: Some(server_key),
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = LoginOptions {
+        server_key: Some(&public),
+        channel_binding: &binding,
+        salted: true,
+        kdf: Default::default(),
+        blinded: true,
+        commit_nonce: true,
+        non_interactive: false,
+        application: b"",
+        second_factor: "",
+        device: None,
+        receipt: None,
+    };
+
+    register(&mut client, &strong, "alice", "hunter2", true)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &strong, "alice", &options)
+        .await
+        .unwrap();
+    let (_, key) = answer_challenge(&mut client, &strong, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+    assert!(key.is_some());
+
+    // a user only logs in under the group it registered with
+    let status = create_challenge(&mut client, &group(), "alice", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    // and the server only takes groups it knows
+    let status = client
+        .register(RegisterRequest {
+            user: "bob".to_string(),
+            y1: vec![1],
+            y2: vec![1],
+            salt: Vec::new(),
+            group: "toy-23-11".to_string(),
+            kdf: None,
+            device: None,
+            recovery_key: None,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+// approves "ok", can't be reached for "down", rejects anything else
+#[derive(Debug)]
+struct CodeFactor;
+
+#[tonic::async_trait]
+impl SecondFactor for CodeFactor {
+    fn name(&self) -> &str {
+        "code"
+    }
+
+    async fn check(&self, attempt: &SecondFactorAttempt<'_>) -> Result<(), SecondFactorError> {
+        assert_eq!(attempt.user, "alice");
+        match attempt.code {
+            "ok" => Ok(()),
+            "down" => Err(SecondFactorError::Unavailable("no answer".to_string())),
+            _ => Err(SecondFactorError::Rejected("wrong code".to_string())),
+        }
+    }
+}
+
+// a wrong code counts like a wrong password: the password alone doesn't buy
+// unlimited guesses at the code
+#[tokio::test]
+async fn test_wrong_second_factor_codes_lock_the_account() {
+    let zkp = group();
+    let mut client = start(AuthImpl {
+        second_factor: Some(Box::new(CodeFactor)),
+        lockout: Some(LockoutPolicy {
+            max_failures: 3,
+            duration: Duration::from_secs(60),
+        }),
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+
+    for _ in 0..3 {
+        let options = LoginOptions {
+            second_factor: "123456",
+            ..options(&binding)
+        };
+        let login = create_challenge(&mut client, &zkp, "alice", &options)
+            .await
+            .unwrap();
+        let status = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+    }
+    let options = LoginOptions {
+        second_factor: "ok",
+        ..options(&binding)
+    };
+    let status = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert!(status.message().starts_with("Account is locked"));
+}
+
+#[tokio::test]
+async fn test_second_factor_is_asked_before_the_session() {
+    let zkp = group();
+    let mut client = start(AuthImpl {
+        second_factor: Some(Box::new(CodeFactor)),
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+
+    for (code, expected) in [
+        ("ok", None),
+        ("123456", Some(Code::Unauthenticated)),
+        ("down", Some(Code::Unavailable)),
+    ] {
+        let options = LoginOptions {
+            second_factor: code,
+            ..options(&binding)
+        };
+        let login = create_challenge(&mut client, &zkp, "alice", &options)
+            .await
+            .unwrap();
+        let result = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options).await;
+        assert_eq!(result.err().map(|status| status.code()), expected);
+    }
+}
+
+// the code was spent on the refused answer, so it can't be sent again: the
+// login starts over
+#[tokio::test]
+async fn test_answer_without_a_session_after_a_second_factor_is_used_up() {
+    let zkp = group();
+    let mut client = start(AuthImpl {
+        second_factor: Some(Box::new(CodeFactor)),
+        settings: Settings {
+            session_limit: Some(SessionLimit {
+                max_per_user: 1,
+                policy: LimitPolicy::RejectNew,
+            }),
+            ..Settings::default()
+        }
+        .into(),
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = LoginOptions {
+        second_factor: "ok",
+        ..options(&binding)
+    };
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let auth_id = login.challenge.auth_id.clone();
+    let status = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    let status = client
+        .verify_authentication(AuthenticationAnswerRequest {
+            auth_id,
+            second_factor: "ok".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert_eq!(
+        status.message(),
+        "Challenge has taken all the answers it allows"
+    );
+}
+
+#[tokio::test]
+async fn test_sessions_carry_their_grants() {
+    let zkp = group();
+    let grants: GrantPolicy = serde_json::from_str(
+        r#"{
+            "realms": {"staff": {"scopes": ["read"]}},
+            "users": {"alice": {"scopes": ["write"], "claims": {"team": "ops"}}}
+        }"#,
+    )
+    .unwrap();
+    let mut client = start(AuthImpl {
+        realm: "staff".to_string(),
+        settings: Settings {
+            grants,
+            ..Settings::default()
+        }
+        .into(),
+        admin_tokens: vec!["admin-token".to_string()],
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+
+    for user in ["alice", "bob"] {
+        register(&mut client, &zkp, user, "hunter2", false)
+            .await
+            .unwrap();
+    }
+    let mut login = async |user| {
+        let challenge = create_challenge(&mut client, &zkp, user, &options)
+            .await
+            .unwrap();
+        answer_challenge(&mut client, &zkp, challenge, user, "hunter2", &options)
+            .await
+            .unwrap()
+            .0
+    };
+    let (alice, bob) = (login("alice").await, login("bob").await);
+
+    let session = client
+        .validate_session(ValidateSessionRequest {
+            session_id: alice.clone(),
+            mac: Vec::new(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(session.scopes, ["read", "write"]);
+    assert_eq!(session.claims["team"], "ops");
+    let info = client
+        .get_session_info(admin(GetSessionInfoRequest {
+            session_id: bob,
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(info.scopes, ["read"]);
+    assert!(info.claims.is_empty());
+
+    // kept across a refresh
+    let refreshed = client
+        .refresh_session(RefreshSessionRequest { session_id: alice })
+        .await
+        .unwrap()
+        .into_inner();
+    let info = client
+        .get_session_info(admin(GetSessionInfoRequest {
+            session_id: refreshed.session_id,
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(info.scopes, ["read", "write"]);
+}
+
+#[tokio::test]
+async fn test_devices_are_listed_and_revoked() {
+    let zkp = group();
+    let mut client = start(AuthImpl {
+        admin_tokens: vec!["admin-token".to_string()],
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let device = |device_id: &str| DeviceInfo {
+        device_id: device_id.to_string(),
+        name: format!("alice's {}", device_id),
+        platform: "linux".to_string(),
+    };
+    let (laptop, phone) = (device("laptop"), device("phone"));
+
+    register_on_device(&mut client, &zkp, "alice", "hunter2", None, Some(&laptop))
+        .await
+        .unwrap();
+    let mut sessions = Vec::new();
+    for device in [&laptop, &laptop, &phone] {
+        let options = LoginOptions {
+            device: Some(device),
+            ..options(&binding)
+        };
+        let login = create_challenge(&mut client, &zkp, "alice", &options)
+            .await
+            .unwrap();
+        let (session_id, key) =
+            answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+                .await
+                .unwrap();
+        sessions.push((session_id, key.unwrap()));
+    }
+    let (phone_session, phone_key) = sessions.pop().unwrap();
+
+    // by the user, proving a session key
+    let status = client
+        .list_devices(ListDevicesRequest {
+            session_id: phone_session.clone(),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let devices = client
+        .list_devices(ListDevicesRequest {
+            session_id: phone_session.clone(),
+            mac: phone_key.mac(phone_session.as_bytes()),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .devices;
+    assert_eq!(devices.len(), 2);
+    assert_eq!(devices[0].info, Some(laptop.clone()));
+    assert_eq!((devices[0].logins, devices[0].registered), (2, true));
+    assert_eq!((devices[1].logins, devices[1].registered), (1, false));
+    assert!(devices[1]
+        .last_client
+        .as_ref()
+        .unwrap()
+        .remote_addr
+        .starts_with("127.0.0.1:"));
+
+    let revoked = client
+        .revoke_device(RevokeDeviceRequest {
+            session_id: phone_session.clone(),
+            mac: phone_key.mac(phone_session.as_bytes()),
+            device_id: "laptop".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(revoked.sessions_ended, 2);
+    for (session_id, _) in sessions {
+        let status = client
+            .validate_session(ValidateSessionRequest {
+                session_id,
+                mac: Vec::new(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+    }
+
+    // the laptop can't come back, the phone is unaffected
+    let options = LoginOptions {
+        device: Some(&laptop),
+        ..options(&binding)
+    };
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let status = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let status = register_on_device(&mut client, &zkp, "alice", "hunter2", None, Some(&laptop))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    // or by an admin, naming the user
+    let devices = client
+        .list_devices(admin(ListDevicesRequest {
+            user: "alice".to_string(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .devices;
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].info, Some(phone));
+    let status = client
+        .revoke_device(admin(RevokeDeviceRequest {
+            user: "alice".to_string(),
+            device_id: "laptop".to_string(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn test_sessions_per_user_are_limited() {
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    for policy in [LimitPolicy::RejectNew, LimitPolicy::EvictOldest] {
+        let mut client = start(AuthImpl {
+            settings: Settings {
+                session_limit: Some(SessionLimit {
+                    max_per_user: 2,
+                    policy,
+                }),
+                ..Settings::default()
+            }
+            .into(),
+            ..AuthImpl::default()
+        })
+        .await;
+        register(&mut client, &zkp, "alice", "hunter2", false)
+            .await
+            .unwrap();
+        let mut results = Vec::new();
+        for _ in 0..3 {
+            let login = create_challenge(&mut client, &zkp, "alice", &options)
+                .await
+                .unwrap();
+            let result =
+                answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options).await;
+            results.push(result.map(|(session_id, _)| session_id));
+        }
+        let mut valid = Vec::new();
+        for result in &results {
+            let Ok(session_id) = result else {
+                valid.push(false);
+                continue;
+            };
+            let request = ValidateSessionRequest {
+                session_id: session_id.clone(),
+                mac: Vec::new(),
+            };
+            valid.push(client.validate_session(request).await.is_ok());
+        }
+        match policy {
+            LimitPolicy::RejectNew => {
+                let status = results[2].as_ref().unwrap_err();
+                assert_eq!(status.code(), Code::ResourceExhausted);
+                assert_eq!(valid, [true, true, false]);
+            }
+            LimitPolicy::EvictOldest => assert_eq!(valid, [false, true, true]),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_draining_server_keeps_its_sessions() {
+    let zkp = group();
+    let mut client = start(AuthImpl {
+        admin_tokens: vec!["admin-token".to_string()],
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+
+    let status = client
+        .set_drain(SetDrainRequest { draining: true })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let response = client
+        .set_drain(admin(SetDrainRequest { draining: true }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!response.was_draining);
+
+    let status = register(&mut client, &zkp, "bob", "hunter2", false)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+    let status = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+    // a login already under way finishes, and its session keeps working
+    let (session_id, _) = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+    let request = ValidateSessionRequest {
+        session_id,
+        mac: Vec::new(),
+    };
+    client.validate_session(request).await.unwrap();
+
+    let response = client
+        .set_drain(admin(SetDrainRequest { draining: false }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(response.was_draining);
+    create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+}
+
+// a client given several servers passes over one that is down or draining,
+// and answers each challenge on the server that issued it
+#[tokio::test]
+async fn test_client_fails_over_between_servers() {
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    // a port nobody listens on any more
+    let down = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let first = serve(AuthImpl {
+        admin_tokens: vec!["admin-token".to_string()],
+        ..AuthImpl::default()
+    });
+    let second = serve(AuthImpl::default());
+    let server = |address: &String| failover::Server {
+        address: address.clone(),
+        channel: Endpoint::from_shared(address.clone())
+            .unwrap()
+            .connect_lazy(),
+        channel_binding: ChannelBinding::default(),
+    };
+    let mut failover = Failover::new(vec![server(&down), server(&first), server(&second)]);
+    assert_eq!(failover.healthy().await.unwrap(), 1);
+
+    // the two don't share their state, alice registers with both
+    for address in [&first, &second] {
+        let mut client = AuthClient::connect(address.clone()).await.unwrap();
+        register(&mut client, &zkp, "alice", "hunter2", false)
+            .await
+            .unwrap();
+    }
+    let pending = failover
+        .create_challenge(&zkp, "alice", &options)
+        .await
+        .unwrap();
+    assert_eq!(pending.server, 1);
+
+    // logins move to the second once the first drains, the answer to the
+    // first's challenge still goes to the first
+    let mut client = failover.client(1);
+    client
+        .set_drain(admin(SetDrainRequest { draining: true }))
+        .await
+        .unwrap();
+    let (session_id, _) = failover
+        .login(&zkp, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+    assert_eq!(failover.current(), 2);
+    let request = ValidateSessionRequest {
+        session_id,
+        mac: Vec::new(),
+    };
+    failover.client(2).validate_session(request).await.unwrap();
+    failover
+        .answer_challenge(&zkp, pending, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_user_names_are_normalized() {
+    let zkp = group();
+    let mut client = start(AuthImpl {
+        usernames: UsernamePolicy {
+            reserved: vec!["admin".to_string()],
+            ..UsernamePolicy::default()
+        },
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+
+    register(&mut client, &zkp, "Alice", "hunter2", false)
+        .await
+        .unwrap();
+    // the same account, not a second one
+    let status = create_challenge(&mut client, &zkp, "ALICE", &options).await;
+    assert!(status.is_ok());
+    let user = UsernamePolicy::default().canonical("ALICE");
+    let login = create_challenge(&mut client, &zkp, &user, &options)
+        .await
+        .unwrap();
+    let (session_id, _) = answer_challenge(&mut client, &zkp, login, &user, "hunter2", &options)
+        .await
+        .unwrap();
+    let session = client
+        .validate_session(ValidateSessionRequest {
+            session_id,
+            mac: Vec::new(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(session.user, "alice");
+
+    for name in ["Admin", "", "bob\u{7}"] {
+        let status = register(&mut client, &zkp, name, "hunter2", false)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+}
+
+#[tokio::test]
+async fn test_parameters_are_pinned() {
+    let mut client = start(AuthImpl::default()).await;
+    let strong = groups::by_name(groups::RFC5114_2048_256).unwrap();
+    let response = client
+        .get_parameters(GetParametersRequest {
+            group: groups::RFC5114_2048_256.to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(BigUint::from_bytes_be(&response.h), strong.h);
+    assert_eq!(response.fingerprint, groups::fingerprint(&strong));
+
+    let pinned = groups::fingerprint(&group());
+    let fingerprint = check_parameters(&mut client, &group(), Some(&pinned))
+        .await
+        .unwrap();
+    assert_eq!(fingerprint, pinned);
+    // pinned to other parameters than the server's
+    let status = check_parameters(&mut client, &strong, Some(&pinned))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    let status = client
+        .get_parameters(GetParametersRequest {
+            group: "toy-23-11".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+// without a pinned fingerprint the first connection records one, and a later
+// server with other parameters is noticed
+#[tokio::test]
+async fn test_parameters_are_trusted_on_first_use() {
+    let mut client = start(AuthImpl::default()).await;
+    let path = std::env::temp_dir().join(format!("known-params-it-{}.json", std::process::id()));
+    let server = "http://auth.example:50051";
+    let mut known = KnownParameters::load(&path).unwrap();
+    let trust = check_known_parameters(&mut client, &group(), server, &mut known, OnChange::Refuse)
+        .await
+        .unwrap();
+    assert_eq!(trust, Trust::Learned);
+    let mut known = KnownParameters::load(&path).unwrap();
+    let trust = check_known_parameters(&mut client, &group(), server, &mut known, OnChange::Refuse)
+        .await
+        .unwrap();
+    assert_eq!(trust, Trust::Known);
+
+    // the recorded fingerprint isn't what the server has now
+    known.forget(server, groups::DEFAULT);
+    known
+        .check(server, groups::DEFAULT, [7; 32], OnChange::Refuse)
+        .unwrap();
+    let status =
+        check_known_parameters(&mut client, &group(), server, &mut known, OnChange::Refuse)
+            .await
+            .unwrap_err();
+    assert!(matches!(
+        AuthError::from(status),
+        AuthError::ParameterMismatch(_)
+    ));
+    let trust = check_known_parameters(&mut client, &group(), server, &mut known, OnChange::Warn)
+        .await
+        .unwrap();
+    assert_eq!(trust, Trust::Changed { previous: [7; 32] });
+    assert_eq!(
+        KnownParameters::load(&path)
+            .unwrap()
+            .get(server, groups::DEFAULT),
+        Some(groups::fingerprint(&group()))
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+// a login presenting its registration's receipt tells a server that lost or
+// replaced the registration from a wrong password
+#[tokio::test]
+async fn test_registration_receipts_catch_lost_registrations() {
+    let zkp = group();
+    let key = KeyPair::generate(&zkp);
+    let public = key.public.clone();
+    let same_key = || KeyPair::from_secret(&zkp, SecretBigUint::new(key.x.expose().clone()));
+    let mut client = start(AuthImpl {
+        server_key: Some(same_key()),
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+
+    let response = register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap()
+        .into_inner();
+    let receipt = receipt_of(&response, &zkp, "alice", Some(&public))
+        .unwrap()
+        .unwrap();
+    assert_eq!(receipt.user, "alice");
+    let options = LoginOptions {
+        receipt: Some(&receipt),
+        ..options(&binding)
+    };
+    login(&mut client, &zkp, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+
+    // registered over since, with another password
+    register(&mut client, &zkp, "alice", "swordfish", false)
+        .await
+        .unwrap();
+    let error = login(&mut client, &zkp, "alice", "hunter2", &options)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, AuthError::RegistrationLost(_)));
+    assert!(error.status().message().ends_with("it has changed"));
+
+    // an instance restored without it, under the same key
+    let mut restored = start(AuthImpl {
+        server_key: Some(same_key()),
+        ..AuthImpl::default()
+    })
+    .await;
+    let error = login(&mut restored, &zkp, "alice", "hunter2", &options)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, AuthError::RegistrationLost(_)));
+    assert!(error.status().message().ends_with("it is gone"));
+
+    // another deployment doesn't take this one's receipts
+    let mut other = start(AuthImpl {
+        server_key: Some(KeyPair::generate(&zkp)),
+        ..AuthImpl::default()
+    })
+    .await;
+    let status = create_challenge(&mut other, &zkp, "alice", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    // and a server without a key gives none
+    let mut unkeyed = start(AuthImpl::default()).await;
+    let response = register(&mut unkeyed, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(response.receipt.is_none());
+}
+
+// a new device logs in with the KDF parameters the server hands back
+#[tokio::test]
+async fn test_kdf_parameters_travel_with_the_verifier() {
+    let zkp = group();
+    let server_key = KeyPair::generate(&zkp);
+    let public = server_key.public.clone();
+    let mut client = start(AuthImpl {
+        server_key: Some(server_key),
+        kdf_policy: KdfPolicy {
+            min_argon2_memory_kib: 64,
+            min_argon2_passes: 1,
+            ..KdfPolicy::default()
+        },
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let argon2 = KdfParams::Argon2id {
+        memory_kib: 64,
+        passes: 1,
+        parallelism: 1,
+    };
+
+    let registered = register_with_kdf(&mut client, &zkp, "alice", "hunter2", Some(&argon2))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(registered.kdf, Some(argon2.into()));
+    // the new device only knows the password, the options default to PBKDF2
+    for blinded in [false, true] {
+        let options = LoginOptions {
+            server_key: Some(&public),
+            blinded,
+            ..options(&binding)
+        };
+        let login = create_challenge(&mut client, &zkp, "alice", &options)
+            .await
+            .unwrap();
+        assert_eq!(login.challenge.kdf.is_some(), !blinded);
+        answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+            .await
+            .unwrap();
+    }
+
+    // weaker than the server's minimum
+    let weak = KdfParams::Pbkdf2 { iterations: 1_000 };
+    let status = register_with_kdf(&mut client, &zkp, "bob", "hunter2", Some(&weak))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+// what the client sees of a seeded login, checked in as tests/vectors/seeded_login.json
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct SeededLogin {
+    seed: u64,
+    auth_id: String,
+    c: String,
+    session_id: String,
+}
+
+// client and server share the test thread (current-thread runtime), so one
+// thread seed drives both sides
+async fn seeded_login(seed: u64) -> SeededLogin {
+    rng::seed_thread(seed);
+    let zkp = group();
+    let mut client = start(AuthImpl::default()).await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let (auth_id, c) = (
+        login.challenge.auth_id.clone(),
+        hex::encode(&login.challenge.c),
+    );
+    let (session_id, _) = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+    rng::clear_thread_seed();
+    SeededLogin {
+        seed,
+        auth_id,
+        c,
+        session_id,
+    }
+}
+
+// UPDATE_VECTORS=1 cargo test --test integration rewrites the vector after an
+// intended change to the order of random draws
+#[tokio::test]
+async fn test_seeded_login_is_reproducible() {
+    let first = seeded_login(42).await;
+    assert_eq!(seeded_login(42).await, first);
+    assert_ne!(seeded_login(43).await, first);
+
+    let path =
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vectors/seeded_login.json");
+    if std::env::var_os("UPDATE_VECTORS").is_some() {
+        std::fs::write(&path, serde_json::to_string_pretty(&first).unwrap() + "\n").unwrap();
+    }
+    let expected: SeededLogin =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(first, expected);
+}
+
+// settings a test edits between reloads
+#[derive(Debug, Clone, Default)]
+struct EditedSettings(Arc<Mutex<Settings>>);
+
+impl SettingsSource for EditedSettings {
+    fn load(&self) -> Result<Settings, String> {
+        Ok(self.0.lock().unwrap().clone())
+    }
+}
+
+#[tokio::test]
+async fn test_settings_are_reloaded_in_place() {
+    let zkp = group();
+    let limited = Settings {
+        session_limit: Some(SessionLimit {
+            max_per_user: 1,
+            policy: LimitPolicy::RejectNew,
+        }),
+        ..Settings::default()
+    };
+    let source = EditedSettings::default();
+    *source.0.lock().unwrap() = limited.clone();
+    let mut client = start(AuthImpl {
+        settings: limited.into(),
+        settings_source: Some(Box::new(source.clone())),
+        admin_tokens: vec!["admin-token".to_string()],
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = async |client: &mut AuthClient<Channel>| {
+        let challenge = create_challenge(client, &zkp, "alice", &options)
+            .await
+            .unwrap();
+        answer_challenge(client, &zkp, challenge, "alice", "hunter2", &options).await
+    };
+    let (first, _) = login(&mut client).await.unwrap();
+    let status = login(&mut client).await.unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+
+    let status = client
+        .reload_settings(ReloadSettingsRequest {})
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    *source.0.lock().unwrap() = Settings {
+        session_ttl: Duration::from_secs(60),
+        ..Settings::default()
+    };
+    let response = client
+        .reload_settings(admin(ReloadSettingsRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.session_ttl_seconds, 60);
+    assert_eq!(response.max_sessions_per_user, 0);
+    assert!(response.session_limit_policy.is_empty());
+
+    // the users and sessions are kept, the new settings apply to new sessions
+    let (second, _) = login(&mut client).await.unwrap();
+    let expiry = async |client: &mut AuthClient<Channel>, session_id: String| {
+        client
+            .validate_session(ValidateSessionRequest {
+                session_id,
+                mac: Vec::new(),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .expires_at
+    };
+    assert!(expiry(&mut client, first).await > expiry(&mut client, second).await + 3000);
+
+    // invalid settings are refused and the ones in force stay
+    source.0.lock().unwrap().session_ttl = Duration::ZERO;
+    let status = client
+        .reload_settings(admin(ReloadSettingsRequest {}))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert!(login(&mut client).await.is_ok());
+}
+
+// sessions expire by the server's clock, which a test can fast-forward
+#[tokio::test]
+async fn test_sessions_expire_by_the_servers_clock() {
+    let zkp = group();
+    let clock = ManualClock::new(std::time::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    let mut client = start(AuthImpl {
+        clock: Box::new(clock.clone()),
+        ..AuthImpl::default()
+    })
+    .await;
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    let (session_id, _) = answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+    let validate = async |client: &mut AuthClient<Channel>, session_id: &str| {
+        client
+            .validate_session(ValidateSessionRequest {
+                session_id: session_id.to_string(),
+                mac: Vec::new(),
+            })
+            .await
+    };
+    let session = validate(&mut client, &session_id)
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(session.expires_at, 1_700_000_000 + 3600);
+
+    // a refresh just before expiry moves it on from the clock's time
+    clock.advance(Duration::from_secs(3599));
+    let refreshed = client
+        .refresh_session(RefreshSessionRequest { session_id })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(refreshed.expires_at, 1_700_000_000 + 3599 + 3600);
+    clock.advance(Duration::from_secs(3600));
+    let status = validate(&mut client, &refreshed.session_id)
+        .await
+        .unwrap_err();
+    assert_eq!(status.message(), "Session expired");
+}
+
+#[tokio::test]
+async fn test_proofs_of_other_applications_are_verified() {
+    let mut client = start(AuthImpl::default()).await;
+    let strong = groups::by_name(groups::RFC5114_2048_256).unwrap();
+    let x = BigUint::from(0x5eedu32);
+    let y1 = ZKP::exponentiate(&strong.g, &x, &strong.p);
+    let y2 = ZKP::exponentiate(&strong.h, &x, &strong.p);
+    let proof = strong.prove_non_interactive_in(b"payments", &x, b"invoice 7");
+    let request = VerifyProofRequest {
+        group: groups::RFC5114_2048_256.to_string(),
+        flavor: ProofFlavor::ChaumPedersen.into(),
+        y1: y1.to_bytes_be(),
+        y2: y2.to_bytes_be(),
+        application: b"payments".to_vec(),
+        context: b"invoice 7".to_vec(),
+        c: proof.c.to_bytes_be(),
+        s: proof.s.to_bytes_be(),
+    };
+    let verify = async |client: &mut AuthClient<Channel>, request: &VerifyProofRequest| {
+        client.verify_proof(request.clone()).await
+    };
+    assert!(
+        verify(&mut client, &request)
+            .await
+            .unwrap()
+            .into_inner()
+            .valid
+    );
+
+    // the statement is the caller's, another context is another statement
+    let elsewhere = VerifyProofRequest {
+        context: b"invoice 8".to_vec(),
+        ..request.clone()
+    };
+    assert!(
+        !verify(&mut client, &elsewhere)
+            .await
+            .unwrap()
+            .into_inner()
+            .valid
+    );
+
+    // a Schnorr proof over y1 alone, not over y1 and a y2
+    let schnorr = zkp_chaum_pedersen::schnorr::prove(&strong, b"payments", &x, b"invoice 7");
+    let request = VerifyProofRequest {
+        flavor: ProofFlavor::Schnorr.into(),
+        y2: Vec::new(),
+        c: schnorr.c.to_bytes_be(),
+        s: schnorr.s.to_bytes_be(),
+        ..request
+    };
+    assert!(
+        verify(&mut client, &request)
+            .await
+            .unwrap()
+            .into_inner()
+            .valid
+    );
+    let with_y2 = VerifyProofRequest {
+        y2: y2.to_bytes_be(),
+        ..request.clone()
+    };
+    assert!(
+        !verify(&mut client, &with_y2)
+            .await
+            .unwrap()
+            .into_inner()
+            .valid
+    );
+
+    for invalid in [
+        VerifyProofRequest {
+            group: "rfc5114-768".to_string(),
+            ..request.clone()
+        },
+        VerifyProofRequest {
+            flavor: 9,
+            ..request.clone()
+        },
+    ] {
+        let status = verify(&mut client, &invalid).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+}
+
+#[tokio::test]
+async fn test_user_names_are_bound_to_client_certificates() {
+    use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
+    use zkp_chaum_pedersen::tls::CertIdentity;
+
+    let vector = |name: &str| std::fs::read(format!("tests/vectors/tls/{}", name)).unwrap();
+    let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let port = incoming.local_addr().unwrap().port();
+    let service = AuthImpl {
+        client_cert_identity: Some(CertIdentity::CommonName),
+        ..AuthImpl::default()
+    };
+    let tls = ServerTlsConfig::new()
+        .identity(Identity::from_pem(
+            vector("server.pem"),
+            vector("server.key"),
+        ))
+        .client_ca_root(Certificate::from_pem(vector("ca.pem")));
+    tokio::spawn(
+        Server::builder()
+            .tls_config(tls)
+            .unwrap()
+            .add_service(AuthServer::new(service))
+            .serve_with_incoming(incoming),
+    );
+    // alice.pem: CN=alice, signed by ca.pem
+    let channel = Channel::from_shared(format!("https://localhost:{}", port))
+        .unwrap()
+        .tls_config(
+            ClientTlsConfig::new()
+                .ca_certificate(Certificate::from_pem(vector("ca.pem")))
+                .identity(Identity::from_pem(vector("alice.pem"), vector("alice.key")))
+                .domain_name("localhost"),
+        )
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut client = AuthClient::new(channel);
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    let login = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap();
+    answer_challenge(&mut client, &zkp, login, "alice", "hunter2", &options)
+        .await
+        .unwrap();
+
+    // alice's certificate doesn't make its holder bob, password or not
+    let status = client
+        .register(RegisterRequest {
+            user: "bob".to_string(),
+            y1: vec![1],
+            y2: vec![1],
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert_eq!(
+        status.message(),
+        "User name doesn't match the client certificate"
+    );
+    let status = client
+        .create_authentication_challenge(AuthenticationChallengeRequest {
+            user: "bob".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+}
+
+#[tokio::test]
+async fn test_audit_events_reach_every_sink_that_is_up() {
+    let zkp = group();
+    let path = std::env::temp_dir().join(format!("audit-sink-{}.jsonl", std::process::id()));
+    // nothing listens on a port just released
+    let closed = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let sinks = [
+        format!("nats://{}/zkp.audit", closed),
+        format!("file:{}", path.display()),
+    ];
+    let auth = AuthImpl {
+        admin_tokens: vec!["admin-token".to_string()],
+        audit_sinks: sinks
+            .iter()
+            .map(|spec| audit::open(spec, Rotation::default()).unwrap())
+            .collect(),
+        ..AuthImpl::default()
+    };
+    let mut client = AuthClient::connect(serve(auth)).await.unwrap();
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    client
+        .delete_user(admin(DeleteUserRequest {
+            user: "alice".to_string(),
+            restore: false,
+        }))
+        .await
+        .unwrap();
+
+    // the sink that is down costs neither the file nor the kept copy
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let record: serde_json::Value = serde_json::from_str(written.trim()).unwrap();
+    assert_eq!(record["kind"], "user-deleted");
+    assert_eq!(record["user"], "alice");
+    let events = client
+        .list_audit_events(admin(ListAuditEventsRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .events;
+    assert_eq!(events.len(), 1);
+}
+
+// with hashed names, the lockout file and the audit files don't name users
+#[tokio::test]
+async fn test_hashed_names_stay_out_of_lockout_and_audit_files() {
+    let zkp = group();
+    let binding = ChannelBinding::default();
+    let options = options(&binding);
+    let dir = std::env::temp_dir();
+    let lockouts = dir.join(format!("lockouts-hashed-{}.json", std::process::id()));
+    let audit = dir.join(format!("audit-hashed-{}.jsonl", std::process::id()));
+    let mut client = start(AuthImpl {
+        admin_tokens: vec!["admin-token".to_string()],
+        users: UserStore::with_hashed_names(NameHasher::new(vec![7; 32]).unwrap()),
+        lockout: Some(LockoutPolicy {
+            max_failures: 2,
+            duration: Duration::from_secs(900),
+        }),
+        lockouts: Box::new(FileLockouts::new(&lockouts)),
+        audit_sinks: vec![
+            audit::open(&format!("file:{}", audit.display()), Rotation::default()).unwrap(),
+        ],
+        ..AuthImpl::default()
+    })
+    .await;
+    register(&mut client, &zkp, "alice", "hunter2", false)
+        .await
+        .unwrap();
+    for _ in 0..2 {
+        let login = create_challenge(&mut client, &zkp, "alice", &options)
+            .await
+            .unwrap();
+        answer_challenge(&mut client, &zkp, login, "alice", "wrong", &options)
+            .await
+            .unwrap_err();
+    }
+    // counted under the hash all the same
+    let status = create_challenge(&mut client, &zkp, "alice", &options)
+        .await
+        .unwrap_err();
+    assert!(status.message().starts_with("Account is locked"));
+    let locked = std::fs::read_to_string(&lockouts).unwrap();
+    client
+        .delete_user(admin(DeleteUserRequest {
+            user: "alice".to_string(),
+            restore: false,
+        }))
+        .await
+        .unwrap();
+
+    let audited = std::fs::read_to_string(&audit).unwrap();
+    for written in [&locked, &audited] {
+        assert!(written.contains("hmac-sha256:"), "{}", written);
+        assert!(!written.contains("alice"), "{}", written);
+    }
+    std::fs::remove_file(&audit).unwrap();
+    std::fs::remove_file(&lockouts).unwrap();
+    std::fs::remove_file(lockouts.with_extension("lock")).unwrap();
+}
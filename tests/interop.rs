@@ -0,0 +1,95 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use zkp_chaum_pedersen::interop::{Encoding, InteropError, Transcript};
+use zkp_chaum_pedersen::rng;
+use zkp_chaum_pedersen::ZKP;
+
+fn interop_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vectors/interop")
+}
+
+// transcripts recorded from other implementations, one file each
+fn recorded() -> Vec<PathBuf> {
+    let mut paths: Vec<_> = std::fs::read_dir(interop_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+// the python reference in tests/interop/, None when there is no python3
+fn python_verify(path: &Path) -> Option<bool> {
+    let script = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/interop/chaum_pedersen.py");
+    let status = Command::new("python3")
+        .arg(script)
+        .arg("verify")
+        .arg(path)
+        .status()
+        .ok()?;
+    Some(status.success())
+}
+
+#[test]
+fn test_transcripts_of_other_implementations() {
+    let paths = recorded();
+    assert!(!paths.is_empty());
+    for path in paths {
+        let transcript = Transcript::load(&path).unwrap();
+        assert_ne!(transcript.implementation, "zkp-chaum-pedersen");
+        if let Err(e) = transcript.verify() {
+            panic!("{}: {}", path.display(), e);
+        }
+    }
+}
+
+// UPDATE_VECTORS=1 cargo test --test interop rewrites export/ from a fixed seed
+#[test]
+fn test_exported_transcripts() {
+    let (g, h, p, q) = ZKP::get_constants();
+    let zkp = ZKP { p, q, g, h };
+    for (i, encoding) in [Encoding::Hex, Encoding::Decimal].into_iter().enumerate() {
+        let path = interop_dir()
+            .join("export")
+            .join(format!("zkp-chaum-pedersen-{}.json", name(encoding)));
+        if std::env::var_os("UPDATE_VECTORS").is_some() {
+            rng::seed_thread(i as u64);
+            Transcript::export(&zkp, encoding, 8).save(&path).unwrap();
+            rng::clear_thread_seed();
+        }
+
+        let transcript = Transcript::load(&path).unwrap();
+        assert_eq!(transcript.encoding, encoding);
+        transcript.verify().unwrap();
+        match python_verify(&path) {
+            Some(verified) => assert!(verified, "python rejects {}", path.display()),
+            None => eprintln!("python3 not found, {} checked by us only", path.display()),
+        }
+    }
+}
+
+fn name(encoding: Encoding) -> &'static str {
+    match encoding {
+        Encoding::Hex => "hex",
+        Encoding::Decimal => "decimal",
+    }
+}
+
+#[test]
+fn test_tampered_transcript_is_rejected() {
+    let path = interop_dir().join("python-toy-hex.json");
+    let mut transcript = Transcript::load(&path).unwrap();
+    transcript.verify().unwrap();
+
+    // s of a toy round is below 11, so s + 1 is a valid number but a wrong answer
+    let s = u32::from_str_radix(&transcript.rounds[2].s, 16).unwrap();
+    transcript.rounds[2].s = format!("{:x}", (s + 1) % 11);
+    assert_eq!(
+        transcript.verify(),
+        Err(InteropError::Rejected { round: 2 })
+    );
+
+    transcript.rounds[2].s = "0x3".to_string();
+    assert_eq!(transcript.verify(), Err(InteropError::InvalidNumber("s")));
+}
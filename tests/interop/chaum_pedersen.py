@@ -0,0 +1,115 @@
+#!/usr/bin/env python3
+"""Chaum-Pedersen the way the common Python tutorials write it: plain ints,
+pow(base, exp, p) and s = (k - c * x) % q.
+
+    chaum_pedersen.py generate [--encoding hex|decimal] [--group rfc5114|toy] [--rounds N]
+    chaum_pedersen.py verify TRANSCRIPT.json
+
+Transcripts use the format of src/interop.rs. Standard library only.
+"""
+
+import argparse
+import json
+import secrets
+import sys
+
+RFC5114 = {
+    "p": int(
+        "B10B8F96A080E01DDE92DE5EAE5D54EC52C99FBCFB06A3C69A6A9DCA52D23B61"
+        "6073E28675A23D189838EF1E2EE652C013ECB4AEA906112324975C3CD49B83BF"
+        "ACCBDD7D90C4BD7098488E9C219A73724EFFD6FAE5644738FAA31A4FF55BCCC0"
+        "A151AF5F0DC8B4BD45BF37DF365C1A65E68CFDA76D4DA708DF1FB2BC2E4A4371",
+        16,
+    ),
+    "q": int("F518AA8781A8DF278ABA4E7D64B7CB9D49462353", 16),
+    "g": int(
+        "A4D1CBD5C3FD34126765A442EFB99905F8104DD258AC507FD6406CFF14266D31"
+        "266FEA1E5C41564B777E690F5504F213160217B4B01B886A5E91547F9E2749F4"
+        "D7FBD7D3B9A92EE1909D0D2263F80A76A6A24C087A091F531DBF0A0169B6A28A"
+        "D662A4D18E73AFA32D779D5918D08BC8858F4DCEF97C2A24855E6EEB22B3B2E5",
+        16,
+    ),
+}
+RFC5114["h"] = pow(
+    RFC5114["g"],
+    int("8E73AFA32D779D5918D08BC8858F4DCEF97C2A24855E6EEB22B3B2E5", 16),
+    RFC5114["p"],
+)
+
+TOY = {"p": 23, "q": 11, "g": 4, "h": 9}
+
+
+def encode(n, encoding):
+    return format(n, "x") if encoding == "hex" else str(n)
+
+
+def decode(value, encoding):
+    return int(value, 16 if encoding == "hex" else 10)
+
+
+def generate(group, encoding, rounds):
+    p, q, g, h = group["p"], group["q"], group["g"], group["h"]
+    out = []
+    for _ in range(rounds):
+        x = secrets.randbelow(q)
+        k = secrets.randbelow(q)
+        c = secrets.randbelow(q)
+        s = (k - c * x) % q
+        values = {
+            "y1": pow(g, x, p),
+            "y2": pow(h, x, p),
+            "r1": pow(g, k, p),
+            "r2": pow(h, k, p),
+            "c": c,
+            "s": s,
+        }
+        out.append({name: encode(v, encoding) for name, v in values.items()})
+    return {
+        "implementation": "python-tutorial",
+        "encoding": encoding,
+        "group": {name: encode(group[name], encoding) for name in "pqgh"},
+        "rounds": out,
+    }
+
+
+def verify(transcript):
+    encoding = transcript["encoding"]
+    group = {name: decode(v, encoding) for name, v in transcript["group"].items()}
+    p, g, h = group["p"], group["g"], group["h"]
+    for i, round_ in enumerate(transcript["rounds"]):
+        n = {name: decode(v, encoding) for name, v in round_.items()}
+        # the bytes a gRPC peer would send: big-endian, empty for zero
+        n = {
+            name: int.from_bytes(v.to_bytes((v.bit_length() + 7) // 8, "big"), "big")
+            for name, v in n.items()
+        }
+        ok1 = n["r1"] == pow(g, n["s"], p) * pow(n["y1"], n["c"], p) % p
+        ok2 = n["r2"] == pow(h, n["s"], p) * pow(n["y2"], n["c"], p) % p
+        if not (ok1 and ok2):
+            print("round {} does not verify".format(i), file=sys.stderr)
+            return False
+    return True
+
+
+def main():
+    parser = argparse.ArgumentParser()
+    commands = parser.add_subparsers(dest="command", required=True)
+    gen = commands.add_parser("generate")
+    gen.add_argument("--encoding", choices=["hex", "decimal"], default="decimal")
+    gen.add_argument("--group", choices=["rfc5114", "toy"], default="rfc5114")
+    gen.add_argument("--rounds", type=int, default=8)
+    check = commands.add_parser("verify")
+    check.add_argument("transcript")
+    args = parser.parse_args()
+
+    if args.command == "generate":
+        group = RFC5114 if args.group == "rfc5114" else TOY
+        json.dump(generate(group, args.encoding, args.rounds), sys.stdout, indent=2)
+        print()
+        return 0
+    with open(args.transcript) as f:
+        return 0 if verify(json.load(f)) else 1
+
+
+if __name__ == "__main__":
+    sys.exit(main())
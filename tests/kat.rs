@@ -0,0 +1,79 @@
+use num_bigint::BigUint;
+use std::path::{Path, PathBuf};
+use zkp_chaum_pedersen::groups;
+use zkp_chaum_pedersen::kat::{KatError, KatFile};
+use zkp_chaum_pedersen::rng;
+use zkp_chaum_pedersen::ZKP;
+
+fn kat_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vectors/kat")
+}
+
+// every group the crate supports, with the name of its vector file
+fn supported_groups() -> Vec<(&'static str, ZKP)> {
+    let (g, h, p, q) = ZKP::get_constants();
+    vec![
+        ("rfc5114-1024-160", ZKP { p, q, g, h }),
+        (
+            "toy-23-11",
+            ZKP {
+                p: BigUint::from(23u32),
+                q: BigUint::from(11u32),
+                g: BigUint::from(4u32),
+                h: BigUint::from(9u32),
+            },
+        ),
+        (
+            groups::RFC5114_2048_256,
+            groups::by_name(groups::RFC5114_2048_256).unwrap(),
+        ),
+    ]
+}
+
+// UPDATE_VECTORS=1 cargo test --test kat regenerates the files from a fixed seed
+#[test]
+fn test_known_answers() {
+    for (i, (name, zkp)) in supported_groups().into_iter().enumerate() {
+        let path = kat_dir().join(format!("{}.json", name));
+        if std::env::var_os("UPDATE_VECTORS").is_some() {
+            rng::seed_thread(i as u64);
+            KatFile::generate(name, &zkp, 8).save(&path).unwrap();
+            rng::clear_thread_seed();
+        }
+
+        let file = KatFile::load(&path).unwrap();
+        assert_eq!(file.group.name, name);
+        let group = file.zkp().unwrap();
+        assert_eq!(
+            (group.p, group.q, group.g, group.h),
+            (zkp.p, zkp.q, zkp.g, zkp.h),
+            "{} describes another group",
+            name
+        );
+        assert_eq!(file.vectors.len(), 8);
+        file.check().unwrap();
+    }
+}
+
+#[test]
+fn test_wrong_answer_is_reported() {
+    let path = kat_dir().join("toy-23-11.json");
+    let mut file = KatFile::load(&path).unwrap();
+    file.vectors[3].s = "ff".to_string();
+    assert_eq!(
+        file.check(),
+        Err(KatError::Mismatch {
+            vector: 3,
+            field: "s"
+        })
+    );
+}
+
+#[test]
+fn test_degenerate_group_is_rejected() {
+    let path = kat_dir().join("toy-23-11.json");
+    let mut file = KatFile::load(&path).unwrap();
+    file.group.p = "00".to_string();
+    assert!(matches!(file.zkp(), Err(KatError::InvalidGroup)));
+    assert_eq!(file.check(), Err(KatError::InvalidGroup));
+}
@@ -0,0 +1,88 @@
+use num_bigint::BigUint;
+use proptest::prelude::*;
+use zkp_chaum_pedersen::ZKP;
+
+// q such that p = 2q + 1 is prime too, so the squares mod p are a subgroup of
+// prime order q
+const SOPHIE_GERMAIN_PRIMES: &[u32] = &[
+    5, 11, 23, 29, 41, 53, 83, 89, 113, 131, 173, 179, 191, 233, 239, 251, 281, 293, 419, 431, 443,
+    491, 509, 593, 641, 653, 659, 683, 719, 743, 761, 809, 911, 953, 1013, 1019, 1031, 1049, 1103,
+    1223, 1229, 1289, 1409, 1439, 1451, 1481, 1499, 1511, 1559,
+];
+
+// g = a ** 2 mod p generates the order-q subgroup unless it is 1, h = g ** w
+fn small_group() -> impl Strategy<Value = ZKP> {
+    (
+        prop::sample::select(SOPHIE_GERMAIN_PRIMES),
+        any::<u32>(),
+        any::<u32>(),
+    )
+        .prop_filter_map("generator or h is 1", |(q, a, w)| {
+            let p = 2 * q + 1;
+            let a = a % (p - 3) + 2;
+            let g = BigUint::from(a * a % p);
+            let w = BigUint::from(w % (q - 1) + 1);
+            let p = BigUint::from(p);
+            let h = ZKP::exponentiate(&g, &w, &p);
+            let one = BigUint::from(1u32);
+            (g != one && h != one).then(|| ZKP {
+                p,
+                q: BigUint::from(q),
+                g,
+                h,
+            })
+        })
+}
+
+// a group and (x, k, c), each drawn below q
+fn group_and_round() -> impl Strategy<Value = (ZKP, BigUint, BigUint, BigUint)> {
+    small_group().prop_flat_map(|zkp| {
+        let q = u32::try_from(&zkp.q).unwrap();
+        (Just(zkp), 0..q, 0..q, 0..q).prop_map(|(zkp, x, k, c)| (zkp, x.into(), k.into(), c.into()))
+    })
+}
+
+fn public_values(zkp: &ZKP, x: &BigUint) -> (BigUint, BigUint) {
+    (
+        ZKP::exponentiate(&zkp.g, x, &zkp.p),
+        ZKP::exponentiate(&zkp.h, x, &zkp.p),
+    )
+}
+
+proptest! {
+    // an honest prover always convinces the verifier
+    #[test]
+    fn test_completeness((zkp, x, k, c) in group_and_round()) {
+        let (y1, y2) = public_values(&zkp, &x);
+        let (r1, r2) = public_values(&zkp, &k);
+        let s = zkp.solve(&k, &c, &x);
+        prop_assert!(zkp.verify(&r1, &r2, &y1, &y2, &c, &s));
+    }
+
+    #[test]
+    fn test_non_interactive_completeness((zkp, x, _, _) in group_and_round(), context in any::<Vec<u8>>()) {
+        let (y1, y2) = public_values(&zkp, &x);
+        let proof = zkp.prove_non_interactive(&x, &context);
+        prop_assert!(zkp.verify_non_interactive(&y1, &y2, &proof, &context));
+    }
+
+    // answering with x' != x mod q gives g ** s * y1 ** c = r1 * g ** (c * (x - x')),
+    // which only equals r1 for c = 0, so with the challenge in 1..q it always fails
+    #[test]
+    fn test_wrong_secret_fails((zkp, x, k, c) in group_and_round(), offset in any::<u32>()) {
+        prop_assume!(c != BigUint::from(0u32));
+        let (y1, y2) = public_values(&zkp, &x);
+        let (r1, r2) = public_values(&zkp, &k);
+        let q = u32::try_from(&zkp.q).unwrap();
+        let wrong = &x + (offset % (q - 1) + 1);
+        let s = zkp.solve(&k, &c, &wrong);
+        prop_assert!(!zkp.verify(&r1, &r2, &y1, &y2, &c, &s));
+    }
+
+    // no restriction to values below q here: both formulas reduce on their own
+    #[test]
+    fn test_solve_matches_solve_unified(zkp in small_group(), k in any::<u64>(), c in any::<u64>(), x in any::<u64>()) {
+        let (k, c, x) = (k.into(), c.into(), x.into());
+        prop_assert_eq!(zkp.solve(&k, &c, &x), zkp.solve_unified(&k, &c, &x));
+    }
+}
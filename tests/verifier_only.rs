@@ -0,0 +1,32 @@
+use num_bigint::BigUint;
+use zkp_chaum_pedersen::groups;
+use zkp_chaum_pedersen::{Proof, ZKP};
+
+// runs in every build, including --no-default-features --features
+// verifier-only, which has no way to make the proof: it was made once with
+// prove_non_interactive_with_nonce(b"", 0x5eed, 0x12345678, b"verifier-only")
+// in the default group
+fn proof() -> Proof {
+    let hex = |s: &str| BigUint::parse_bytes(s.as_bytes(), 16).unwrap();
+    Proof {
+        c: hex("6e4f22cd9a3f22f7ce8911c9fe2734de39335e5c"),
+        s: hex("f1db6abeee04423cd2cff64ea53c3390ffc17b9a"),
+    }
+}
+
+#[test]
+fn test_verifies_a_proof_made_elsewhere() {
+    let zkp = groups::default_group();
+    let x = BigUint::from(0x5eed_u32);
+    let y1 = ZKP::exponentiate(&zkp.g, &x, &zkp.p);
+    let y2 = ZKP::exponentiate(&zkp.h, &x, &zkp.p);
+    assert!(zkp.verify_non_interactive(&y1, &y2, &proof(), b"verifier-only"));
+    assert!(!zkp.verify_non_interactive(&y1, &y2, &proof(), b"another context"));
+    assert!(!zkp.verify_non_interactive_in(b"app", &y1, &y2, &proof(), b"verifier-only"));
+
+    let tampered = Proof {
+        s: proof().s + 1u32,
+        ..proof()
+    };
+    assert!(!zkp.verify_non_interactive(&y1, &y2, &tampered, b"verifier-only"));
+}